@@ -7,14 +7,9 @@ pub static PRODUCER_HISTORY: Mutex<Vec<Vec<f32>>> = Mutex::new(Vec::new());
 pub static LAYER1_HISTORY: Mutex<Vec<Vec<f32>>> = Mutex::new(Vec::new());
 pub static LAYER2_HISTORY: Mutex<Vec<Vec<f32>>> = Mutex::new(Vec::new());
 
+#[derive(Default)]
 pub struct MyApp {}
 
-impl Default for MyApp {
-    fn default() -> Self {
-        Self {}
-    }
-}
-
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
@@ -4,11 +4,11 @@ use crate::BATCH_SIZE;
 pub async fn processor_1(
     id: usize,
     input: Receiver<u64>,
-    output: flume::Sender<Box<Vec<u64>>>,
+    output: flume::Sender<Vec<u64>>,
     report: flume::Sender<crate::reporter::Report>,
 ) {
     let batch_size = BATCH_SIZE.load(std::sync::atomic::Ordering::Relaxed);
-    let mut batch = Box::new(Vec::with_capacity(batch_size));
+    let mut batch = Vec::with_capacity(batch_size);
     let mut start = std::time::Instant::now();
     let mut count = 0;
     while let Ok(data) = input.recv_async().await {
@@ -20,7 +20,7 @@ pub async fn processor_1(
             if r.is_err() {
                 break;
             }
-            batch = Box::new(Vec::with_capacity(batch_size));
+            batch = Vec::with_capacity(batch_size);
         }
         let elapsed_seconds = start.elapsed().as_secs_f32();
         if elapsed_seconds >= 0.25 {
@@ -2,7 +2,7 @@ use std::time::Duration;
 use crate::PROCESSING_DELAY_10TH_SECONDS;
 
 pub async fn processor_layer2(
-    from_layer1: flume::Receiver<Box<Vec<u64>>>,
+    from_layer1: flume::Receiver<Vec<u64>>,
     report: flume::Sender<crate::reporter::Report>,
 ) {
     let mut count = 0;
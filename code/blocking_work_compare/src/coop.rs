@@ -0,0 +1,94 @@
+//! A task that never awaits, doing async work in name only: a loop of small pieces of
+//! CPU work with nothing in it that could return `Pending`.
+//!
+//! Tokio only switches tasks when one returns from `poll`, so a loop like that keeps its
+//! thread until it's done, and on a current-thread runtime nothing else runs at all, not
+//! even a timer that's long overdue. Moving the work elsewhere fixes that; so does
+//! giving the thread back now and then, with `tokio::task::yield_now().await` between
+//! pieces, which goes to the back of the queue every time whether anything else wants
+//! to run or not.
+//!
+//! `tokio::task::coop::consume_budget().await` is the cheaper kind of yield point. Every
+//! task gets a budget of operations each time it's polled (128 of them), which tokio's
+//! own sockets, channels and timers spend as they go, and which makes them return
+//! `Pending` once it's gone even if they're ready, so a busy task can't hog a worker by
+//! always finding its next message waiting. `consume_budget` spends one unit of it and
+//! yields only when it's gone: a piece of work between each costs a yield every 128
+//! pieces, and the heartbeat waits at least as long as 128 pieces take. Two or three
+//! times that, as it turns out: its timer only fires when the runtime turns its driver,
+//! at a yield, and it only runs at the one after.
+
+use crate::cpu;
+use std::time::Duration;
+
+/// About how long a piece of work takes: short enough that a heartbeat ticking between
+/// any two is on time, long enough that a budget's worth of them is not.
+pub const PIECE: Duration = Duration::from_micros(250);
+
+/// What goes between the pieces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spin {
+    /// Nothing: the loop keeps its thread until it's done.
+    Tight,
+    /// `yield_now().await`, every piece.
+    YieldNow,
+    /// `consume_budget().await`, every piece.
+    ConsumeBudget,
+}
+
+impl Spin {
+    /// Each of them, worst first.
+    pub const ALL: [Self; 3] = [Self::Tight, Self::ConsumeBudget, Self::YieldNow];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tight => "a tight loop",
+            Self::YieldNow => "yield_now",
+            Self::ConsumeBudget => "consume_budget",
+        }
+    }
+}
+
+/// `pieces` rounds of [`cpu::crunch`] of `rounds` each, right there in the task, with
+/// `how` between them.
+pub async fn spin(how: Spin, pieces: usize, rounds: u64) -> u64 {
+    let mut answer = 0u64;
+    for _ in 0..pieces {
+        answer = answer.wrapping_add(cpu::crunch(rounds));
+        match how {
+            Spin::Tight => {}
+            Spin::YieldNow => tokio::task::yield_now().await,
+            Spin::ConsumeBudget => tokio::task::coop::consume_budget().await,
+        }
+    }
+    answer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parallel::with_heartbeat;
+
+    /// About 300ms of pieces.
+    async fn worst_beat(how: Spin) -> Duration {
+        with_heartbeat(spin(how, 1200, cpu::rounds_for(PIECE))).await.worst_beat
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_a_tight_loop_stops_the_heartbeat_and_yielding_does_not() {
+        let tight = worst_beat(Spin::Tight).await;
+        let yielding = worst_beat(Spin::YieldNow).await;
+        // The whole 300ms without a tick, against a chance to every piece.
+        assert!(tight > Duration::from_millis(200), "{tight:?}");
+        assert!(yielding < Duration::from_millis(60), "{yielding:?}");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_consume_budget_yields_only_when_the_budget_is_spent() {
+        let tight = worst_beat(Spin::Tight).await;
+        let budget = worst_beat(Spin::ConsumeBudget).await;
+        // A yield every 128 pieces, 32ms or so: held up for a while, and then let go.
+        assert!(budget > Duration::from_millis(20), "{budget:?}");
+        assert!(budget < tight / 2, "{budget:?}, against {tight:?} without yielding");
+    }
+}
@@ -0,0 +1,62 @@
+//! Work that keeps a core busy, for the runs to block with instead of sleeping.
+//!
+//! A sleeping thread blocks its tasks but leaves its core free, so a sleep moved off
+//! the runtime costs nothing wherever it goes: ten of them on ten blocking threads take
+//! as long as one. Real work competes for the cores, with the runtime's workers as well
+//! as with itself, and that's when where it runs starts to matter. The work here is
+//! hashing a buffer over and over, a fixed number of rounds; [`rounds_for`] finds how
+//! many take about as long as a given sleep on this machine, on an idle core, so the two
+//! workloads can be compared.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Bytes hashed per round.
+const BUFFER: usize = 4096;
+
+/// Hashes a buffer `rounds` times over, each round seeded with the last.
+pub fn crunch(rounds: u64) -> u64 {
+    let buffer: Vec<u8> = (0..BUFFER).map(|i| i as u8).collect();
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for _ in 0..rounds {
+        for &byte in black_box(&buffer) {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    black_box(hash)
+}
+
+/// How many rounds of [`crunch`] take about `target` here, measured on this thread.
+pub fn rounds_for(target: Duration) -> u64 {
+    // Long enough to measure, short enough not to hold up the demo.
+    let mut probe = 1;
+    loop {
+        let start = Instant::now();
+        crunch(probe);
+        let took = start.elapsed();
+        if took >= Duration::from_millis(20) || probe >= 1 << 40 {
+            let per_round = took.as_secs_f64() / probe as f64;
+            return ((target.as_secs_f64() / per_round).round() as u64).max(1);
+        }
+        probe *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounds_scale_with_the_target() {
+        let short = rounds_for(Duration::from_millis(10));
+        let long = rounds_for(Duration::from_millis(40));
+        // Measured twice on a machine doing other things, so only roughly four times.
+        assert!((2 * short..8 * short).contains(&long), "{short} rounds for 10ms, {long} for 40ms");
+    }
+
+    #[test]
+    fn test_crunch_depends_on_the_rounds() {
+        assert_eq!(crunch(3), crunch(3));
+        assert_ne!(crunch(3), crunch(4));
+    }
+}
@@ -0,0 +1,133 @@
+//! The same runs on executors other than tokio's, to tell what's tokio's from what's
+//! blocking's. Only built with the `smol` or `futures-executor` feature:
+//!
+//! ```text
+//! cargo run -p blocking_work_compare --features smol,futures-executor
+//! ```
+//!
+//! Blocking inside async code serializes the tasks on any executor: `join_all` polls
+//! them from one future, and a future that doesn't return can't be polled past, whose
+//! ever it is. What's tokio's is everything that waits: `tokio::time::sleep` needs
+//! tokio's timer and `spawn_blocking` its blocking pool, so on another executor both
+//! panic, and smol has its own of each instead, `smol::Timer` and `smol::unblock`.
+//! `block_in_place` has no runtime to tell, and blocks where it is. Rayon doesn't care,
+//! and nor does the `oneshot` it answers on: `tokio::sync` is plain futures, and works
+//! under anyone's executor.
+//!
+//! `futures::executor::block_on` is the degenerate case: it polls one future on the
+//! calling thread, parking it in between, with no timer or IO of its own. A future
+//! that waits on nothing but other futures runs fine; one that waits on time needs a
+//! timer that brings its own way of waking it. Tokio's doesn't, and smol's does: when
+//! nobody's `smol::block_on` is driving its reactor, it drives it from a thread of its
+//! own.
+
+use crate::{Strategy, Trace, Workload, run};
+use interleaving::Timeline;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "smol")]
+use {
+    crate::workers::Place,
+    futures::future::join_all,
+    std::time::Instant,
+    tracing::{Instrument, info, info_span},
+};
+
+/// An executor that isn't tokio's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Executor {
+    /// `smol::block_on`, which drives smol's reactor, and its timers, while it waits.
+    #[cfg(feature = "smol")]
+    Smol,
+    /// `futures::executor::block_on`.
+    #[cfg(feature = "futures-executor")]
+    BlockOn,
+}
+
+impl Executor {
+    /// Each of them the build has.
+    pub const ALL: &[Self] = &[
+        #[cfg(feature = "smol")]
+        Self::Smol,
+        #[cfg(feature = "futures-executor")]
+        Self::BlockOn,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "smol")]
+            Self::Smol => "smol",
+            #[cfg(feature = "futures-executor")]
+            Self::BlockOn => "futures::executor::block_on",
+        }
+    }
+
+    /// Runs `strategy`'s run, tokio's version, to the end on this executor, from the
+    /// calling thread, and returns what its tasks recorded, or why it panicked.
+    pub fn run(self, strategy: Strategy, workload: Workload) -> Result<Timeline, String> {
+        self.block_on(move |trace| run(strategy, self.name(), workload, trace))
+    }
+
+    /// The same for `strategy`'s run written with smol's timer or blocking pool, for the
+    /// ones that need tokio's runtime otherwise. `None` for the rest.
+    #[cfg(feature = "smol")]
+    pub fn run_smols(self, strategy: Strategy, workload: Workload) -> Option<Result<Timeline, String>> {
+        match strategy {
+            Strategy::AsyncSleep => Some(self.block_on(move |trace| run_smol_timer(self.name(), workload, trace))),
+            Strategy::SpawnBlocking => Some(self.block_on(move |trace| run_unblock(self.name(), workload, trace))),
+            _ => None,
+        }
+    }
+
+    fn block_on<F: Future<Output = ()>>(self, run: impl FnOnce(Trace) -> F) -> Result<Timeline, String> {
+        let trace = Trace::default();
+        let run = run(trace.clone());
+        panic::catch_unwind(AssertUnwindSafe(|| match self {
+            #[cfg(feature = "smol")]
+            Self::Smol => smol::block_on(run),
+            #[cfg(feature = "futures-executor")]
+            Self::BlockOn => futures::executor::block_on(run),
+        }))
+        .map_err(|panic| panic.downcast_ref::<&str>().map(|message| message.to_string()).or_else(|| panic.downcast_ref::<String>().cloned()).unwrap_or_default())?;
+        Ok(trace.recorder.timeline())
+    }
+}
+
+/// Each task sleeping with `smol::Timer`, the async sleep for smol's reactor.
+#[cfg(feature = "smol")]
+pub async fn run_smol_timer(label: &'static str, workload: Workload, trace: Trace) {
+    let start = Instant::now();
+    let run = info_span!("run", runtime = label);
+    let tasks: Vec<_> = (0..workload.tasks).map(|n| timer_looper(n, start, workload, trace.clone()).instrument(info_span!(parent: &run, "task", n))).collect();
+    join_all(tasks).await;
+}
+
+/// The blocking moved onto smol's blocking pool with `smol::unblock`.
+#[cfg(feature = "smol")]
+pub async fn run_unblock(label: &'static str, workload: Workload, trace: Trace) {
+    let start = Instant::now();
+    let run = info_span!("run", runtime = label);
+    let tasks: Vec<_> = (0..workload.tasks).map(|n| unblock_looper(n, start, workload, trace.clone()).instrument(info_span!(parent: &run, "task", n))).collect();
+    join_all(tasks).await;
+}
+
+#[cfg(feature = "smol")]
+async fn timer_looper(n: usize, start: Instant, workload: Workload, trace: Trace) {
+    for i in 0..workload.iterations {
+        trace.recorder.record(n, i);
+        trace.activity.polled(n, i);
+        info!("+{:>4}ms iteration {i} (before smol::Timer)", start.elapsed().as_millis());
+        smol::Timer::after(workload.work).await;
+    }
+}
+
+#[cfg(feature = "smol")]
+async fn unblock_looper(n: usize, start: Instant, workload: Workload, trace: Trace) {
+    for i in 0..workload.iterations {
+        trace.recorder.record(n, i);
+        trace.activity.polled(n, i);
+        info!("+{:>4}ms iteration {i} (before smol::unblock)", start.elapsed().as_millis());
+
+        let activity = trace.activity.clone();
+        smol::unblock(move || activity.busy(Place::BlockingPool, n, i, || workload.block())).await;
+    }
+}
@@ -0,0 +1,172 @@
+//! Writing a big file and reading it back, three ways: `std::fs` right in the task,
+//! `tokio::fs`, and `std::fs` inside `spawn_blocking`.
+//!
+//! There is no async file IO underneath any of them: operating systems don't offer one
+//! tokio can use everywhere, so `tokio::fs` is `std::fs` on the blocking pool, a
+//! `spawn_blocking` per call. That makes it the convenient way to do the third, and the
+//! slow one when the calls are small and many, since each is a trip to another thread
+//! and back. `std::fs` in the task is the fastest and blocks the worker for as long as
+//! the disk takes, which [`write_then_read`] shows as the heartbeat's worst gap; one
+//! `spawn_blocking` around the whole job is as fast without blocking anything.
+
+use crate::workers::{spawn_heartbeat, worst_gap};
+use interleaving::Recorder;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Bytes per write and per read.
+const CHUNK: usize = 1 << 20;
+
+/// How the file is written and read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileIo {
+    /// `std::fs`, right in the task.
+    Inline,
+    /// `tokio::fs`.
+    TokioFs,
+    /// `std::fs`, the whole job in one `spawn_blocking`.
+    SpawnBlocking,
+}
+
+impl FileIo {
+    pub const ALL: [Self; 3] = [Self::Inline, Self::TokioFs, Self::SpawnBlocking];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Inline => "std::fs inline",
+            Self::TokioFs => "tokio::fs",
+            Self::SpawnBlocking => "spawn_blocking + std::fs",
+        }
+    }
+}
+
+/// How a file went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoReport {
+    pub bytes: u64,
+    /// Writing, including the `sync_all` that gets it to the disk.
+    pub write: Duration,
+    pub read: Duration,
+    /// The longest the heartbeat went between two ticks, against [`BEAT`](crate::workers::BEAT).
+    pub worst_beat: Duration,
+}
+
+impl IoReport {
+    /// Megabytes a second, writing and reading.
+    pub fn throughput(&self) -> (f64, f64) {
+        let mb = self.bytes as f64 / 1e6;
+        (mb / self.write.as_secs_f64(), mb / self.read.as_secs_f64())
+    }
+}
+
+/// A file in the temporary directory for `how`, not there yet.
+pub fn temp_path(how: FileIo) -> PathBuf {
+    let name = format!("blocking_work_compare-{}-{how:?}.bin", std::process::id());
+    std::env::temp_dir().join(name)
+}
+
+/// Writes `size` bytes to `path`, reads them back and removes it, `how` says how, with a
+/// heartbeat on the runtime meanwhile.
+pub async fn write_then_read(how: FileIo, path: &Path, size: usize) -> io::Result<IoReport> {
+    let beats = Recorder::new();
+    let heartbeat = spawn_heartbeat(beats.clone());
+    let done = timed(how, path.to_path_buf(), size).await;
+    heartbeat.abort();
+    let worst_beat = worst_gap(&beats);
+    // Gone whichever way it went, to not fill the disk with files from failed runs.
+    let _ = std::fs::remove_file(path);
+    let (write, read, bytes) = done?;
+    Ok(IoReport { bytes, write, read, worst_beat })
+}
+
+async fn timed(how: FileIo, path: PathBuf, size: usize) -> io::Result<(Duration, Duration, u64)> {
+    let chunk = vec![0xa5u8; CHUNK];
+    match how {
+        FileIo::Inline => {
+            let write = time(|| write_std(&path, &chunk, size))?;
+            let (read, bytes) = time_with(|| read_std(&path))?;
+            Ok((write, read, bytes))
+        }
+        FileIo::SpawnBlocking => tokio::task::spawn_blocking(move || {
+            let write = time(|| write_std(&path, &chunk, size))?;
+            let (read, bytes) = time_with(|| read_std(&path))?;
+            Ok((write, read, bytes))
+        })
+        .await
+        .map_err(io::Error::other)?,
+        FileIo::TokioFs => {
+            let start = Instant::now();
+            let mut file = tokio::fs::File::create(&path).await?;
+            let mut left = size;
+            while left > 0 {
+                let n = left.min(CHUNK);
+                file.write_all(&chunk[..n]).await?;
+                left -= n;
+            }
+            file.sync_all().await?;
+            let write = start.elapsed();
+
+            let start = Instant::now();
+            let mut file = tokio::fs::File::open(&path).await?;
+            let mut buffer = vec![0; CHUNK];
+            let mut bytes = 0;
+            loop {
+                match file.read(&mut buffer).await? {
+                    0 => break,
+                    n => bytes += n as u64,
+                }
+            }
+            Ok((write, start.elapsed(), bytes))
+        }
+    }
+}
+
+fn time(work: impl FnOnce() -> io::Result<()>) -> io::Result<Duration> {
+    time_with(work).map(|(took, ())| took)
+}
+
+fn time_with<T>(work: impl FnOnce() -> io::Result<T>) -> io::Result<(Duration, T)> {
+    let start = Instant::now();
+    let result = work()?;
+    Ok((start.elapsed(), result))
+}
+
+fn write_std(path: &Path, chunk: &[u8], size: usize) -> io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    let mut left = size;
+    while left > 0 {
+        let n = left.min(chunk.len());
+        file.write_all(&chunk[..n])?;
+        left -= n;
+    }
+    file.sync_all()
+}
+
+fn read_std(path: &Path) -> io::Result<u64> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0; CHUNK];
+    let mut bytes = 0;
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(bytes),
+            n => bytes += n as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_every_way_reads_back_what_it_wrote_and_cleans_up() {
+        for how in FileIo::ALL {
+            let path = temp_path(how);
+            let report = write_then_read(how, &path, 3 * CHUNK + 17).await.unwrap();
+            assert_eq!(report.bytes, 3 * CHUNK as u64 + 17, "{}", how.name());
+            assert!(!path.exists(), "{}", how.name());
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! The runs the demo compares: a few tasks, each blocking a few times, inline, moved to
+//! `spawn_blocking` or to rayon, or inside `block_in_place`, next to tasks that sleep
+//! asynchronously instead. How many, for how long, and whether blocking is a sleep or
+//! [`cpu`] work, is a [`Workload`].
+//!
+//! Each task records the start of every iteration in the [`Trace`] it's handed, so
+//! what the log shows by eye can be checked by a test too: blocking inline serializes
+//! the tasks, on either flavour of runtime and inside `block_in_place` as well, since
+//! `join_all` polls them all from one task; async sleeps, `spawn_blocking` and rayon
+//! keep them in step, as far as there are cores for them. [`timing`] turns what was
+//! recorded into rows to plot, [`workers`] into a chart of what each thread did, and
+//! [`matrix`] does every run on runtimes of each flavour and size and tabulates them;
+//! [`parallel`] does one job across the cores instead, [`pool`] more blocking at once
+//! than the blocking pool has threads for, [`file_io`] blocking on the disk, and
+//! [`coop`] an async loop that never gives its thread back. With the `smol` or
+//! `futures-executor` feature, `executors` does the runs on other executors than
+//! tokio's.
+
+pub mod coop;
+pub mod cpu;
+#[cfg(any(feature = "smol", feature = "futures-executor"))]
+pub mod executors;
+pub mod file_io;
+pub mod matrix;
+pub mod parallel;
+pub mod pool;
+pub mod timing;
+pub mod workers;
+
+use futures::future::join_all;
+use interleaving::Recorder;
+use workers::{Activity, Place};
+use serde::Serialize;
+use tokio::sync::oneshot;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, info, info_span};
+
+/// What each run does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workload {
+    /// Tasks per run.
+    pub tasks: usize,
+    /// Times each task blocks.
+    pub iterations: usize,
+    /// How long each time takes, or for `Job::Cpu` about how long on an idle core.
+    pub work: Duration,
+    pub job: Job,
+}
+
+/// What blocking is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Job {
+    /// `std::thread::sleep`: the thread is held up, its core isn't.
+    #[default]
+    Sleep,
+    /// [`cpu::crunch`] for `rounds`: the thread and its core both.
+    Cpu { rounds: u64 },
+}
+
+impl Default for Workload {
+    /// Three tasks, three 60ms sleeps each: short enough to finish in a couple of seconds,
+    /// long enough that the log shows the difference.
+    fn default() -> Self {
+        Self { tasks: 3, iterations: 3, work: Duration::from_millis(60), job: Job::Sleep }
+    }
+}
+
+impl Workload {
+    /// The same, with CPU work taking about as long as the sleeps would, calibrated on
+    /// the calling thread.
+    pub fn cpu(self) -> Self {
+        Self { job: Job::Cpu { rounds: cpu::rounds_for(self.work) }, ..self }
+    }
+
+    /// Blocks for one iteration.
+    pub fn block(&self) {
+        match self.job {
+            Job::Sleep => thread::sleep(self.work),
+            Job::Cpu { rounds } => {
+                cpu::crunch(rounds);
+            }
+        }
+    }
+
+    /// What blocking is, for the log.
+    pub fn blocking(&self) -> &'static str {
+        match self.job {
+            Job::Sleep => "std::thread::sleep",
+            Job::Cpu { .. } => "hashing",
+        }
+    }
+}
+
+/// Where a run's tasks block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Right there in the task: [`run_inline`].
+    Inline,
+    /// Nowhere, with `tokio::time::sleep` instead: [`run_async_sleep`].
+    AsyncSleep,
+    /// On the blocking pool: [`run_spawn_blocking`].
+    SpawnBlocking,
+    /// Inside `block_in_place`: [`run_block_in_place`].
+    BlockInPlace,
+    /// On rayon's pool: [`run_rayon`].
+    Rayon,
+}
+
+impl Strategy {
+    /// As the CSV and JSON have it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Inline => "inline",
+            Self::AsyncSleep => "async_sleep",
+            Self::SpawnBlocking => "spawn_blocking",
+            Self::BlockInPlace => "block_in_place",
+            Self::Rayon => "rayon",
+        }
+    }
+}
+
+/// `spawn_blocking`, with a name for the console.
+fn spawn_blocking_named(name: &str, work: impl FnOnce() + Send + 'static) -> tokio::task::JoinHandle<()> {
+    #[cfg(all(tokio_unstable, feature = "console"))]
+    return tokio::task::Builder::new().name(name).spawn_blocking(work).expect("spawning inside a runtime");
+    #[cfg(not(all(tokio_unstable, feature = "console")))]
+    {
+        let _ = name;
+        tokio::task::spawn_blocking(work)
+    }
+}
+
+/// What a run's tasks note as they go: when each iteration started, for checking how
+/// they interleaved, and which thread did what, for [`workers::chart`].
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub recorder: Recorder,
+    pub activity: Activity,
+}
+
+/// Runs `strategy`'s run.
+pub async fn run(strategy: Strategy, label: &'static str, workload: Workload, trace: Trace) {
+    match strategy {
+        Strategy::Inline => run_inline(label, workload, trace).await,
+        Strategy::AsyncSleep => run_async_sleep(label, workload, trace).await,
+        Strategy::SpawnBlocking => run_spawn_blocking(label, workload, trace).await,
+        Strategy::BlockInPlace => run_block_in_place(label, workload, trace).await,
+        Strategy::Rayon => run_rayon(label, workload, trace).await,
+    }
+}
+
+/// Each task blocking where it is: that blocks the thread, and the tasks run one after
+/// another.
+pub async fn run_inline(label: &'static str, workload: Workload, trace: Trace) {
+    let start = Instant::now();
+    let run = info_span!("run", runtime = label);
+    let tasks: Vec<_> = (0..workload.tasks).map(|n| inline_looper(n, start, workload, trace.clone()).instrument(info_span!(parent: &run, "task", n))).collect();
+    join_all(tasks).await;
+}
+
+/// Each task sleeping with `tokio::time::sleep`: the tasks take turns while they wait.
+/// There's no async way to do CPU work, only places other than the runtime to do it,
+/// so with `Job::Cpu` this sleeps for `work` all the same.
+pub async fn run_async_sleep(label: &'static str, workload: Workload, trace: Trace) {
+    let start = Instant::now();
+    let run = info_span!("run", runtime = label);
+    let tasks: Vec<_> = (0..workload.tasks).map(|n| async_looper(n, start, workload, trace.clone()).instrument(info_span!(parent: &run, "task", n))).collect();
+    join_all(tasks).await;
+}
+
+/// The blocking moved onto the blocking pool, where it runs in parallel: as far as
+/// there are cores for it, for CPU work, which then competes with the runtime's workers
+/// for them.
+pub async fn run_spawn_blocking(label: &'static str, workload: Workload, trace: Trace) {
+    let start = Instant::now();
+    let run = info_span!("run", runtime = label);
+    let tasks: Vec<_> =
+        (0..workload.tasks).map(|n| looper_with_spawn_blocking(n, start, label, workload, trace.clone()).instrument(info_span!(parent: &run, "task", n))).collect();
+    join_all(tasks).await;
+}
+
+/// The blocking inside `block_in_place`, which tells the runtime this thread is
+/// about to block: it hands the worker's other tasks to a new thread and carries on
+/// with them there. It's for a task that has to block where it is, without the move
+/// to another thread `spawn_blocking` needs, and it buys less than that does: the
+/// runtime's other tasks keep going, but the futures joined in this one wait with it,
+/// so these tasks still run one after another. And as it needs somewhere to hand the
+/// other tasks to, it panics on a current-thread runtime.
+pub async fn run_block_in_place(label: &'static str, workload: Workload, trace: Trace) {
+    let start = Instant::now();
+    let run = info_span!("run", runtime = label);
+    let tasks: Vec<_> =
+        (0..workload.tasks).map(|n| block_in_place_looper(n, start, workload, trace.clone()).instrument(info_span!(parent: &run, "task", n))).collect();
+    join_all(tasks).await;
+}
+
+/// The blocking handed to rayon's pool, with a `oneshot` to await the answer on. Rayon
+/// keeps a thread per core, so unlike the blocking pool it never has more CPU work
+/// running than there are cores to run it: the rest queues, rather than everything
+/// slowing down together, runtime workers included. It's the place for CPU work, and
+/// the wrong one for sleeps, which tie up its few threads doing nothing.
+pub async fn run_rayon(label: &'static str, workload: Workload, trace: Trace) {
+    let start = Instant::now();
+    let run = info_span!("run", runtime = label);
+    let tasks: Vec<_> = (0..workload.tasks).map(|n| rayon_looper(n, start, workload, trace.clone()).instrument(info_span!(parent: &run, "task", n))).collect();
+    join_all(tasks).await;
+}
+
+async fn inline_looper(n: usize, start: Instant, workload: Workload, trace: Trace) {
+    for i in 0..workload.iterations {
+        trace.recorder.record(n, i);
+        info!("+{:>4}ms iteration {i} (before {})", start.elapsed().as_millis(), workload.blocking());
+        trace.activity.busy(Place::Worker, n, i, || workload.block());
+    }
+}
+
+async fn async_looper(n: usize, start: Instant, workload: Workload, trace: Trace) {
+    for i in 0..workload.iterations {
+        trace.recorder.record(n, i);
+        trace.activity.polled(n, i);
+        info!("+{:>4}ms iteration {i} (before tokio::time::sleep)", start.elapsed().as_millis());
+        tokio::time::sleep(workload.work).await;
+    }
+}
+
+async fn looper_with_spawn_blocking(n: usize, start: Instant, label: &'static str, workload: Workload, trace: Trace) {
+    for i in 0..workload.iterations {
+        trace.recorder.record(n, i);
+        trace.activity.polled(n, i);
+        info!("+{:>4}ms iteration {i} (before spawn_blocking)", start.elapsed().as_millis());
+
+        let activity = trace.activity.clone();
+        spawn_blocking_named(&format!("[{label}] task {n} iteration {i}: {} on the blocking pool", workload.blocking()), move || {
+            activity.busy(Place::BlockingPool, n, i, || workload.block());
+        })
+        .await
+        .expect("spawn_blocking task panicked");
+    }
+}
+
+async fn block_in_place_looper(n: usize, start: Instant, workload: Workload, trace: Trace) {
+    for i in 0..workload.iterations {
+        trace.recorder.record(n, i);
+        info!("+{:>4}ms iteration {i} (before block_in_place)", start.elapsed().as_millis());
+        tokio::task::block_in_place(|| trace.activity.busy(Place::Worker, n, i, || workload.block()));
+    }
+}
+
+async fn rayon_looper(n: usize, start: Instant, workload: Workload, trace: Trace) {
+    for i in 0..workload.iterations {
+        trace.recorder.record(n, i);
+        trace.activity.polled(n, i);
+        info!("+{:>4}ms iteration {i} (before rayon::spawn)", start.elapsed().as_millis());
+
+        let (done, finished) = oneshot::channel();
+        let activity = trace.activity.clone();
+        rayon::spawn(move || {
+            activity.busy(Place::Rayon, n, i, || workload.block());
+            let _ = done.send(());
+        });
+        finished.await.expect("rayon drops a job only by aborting");
+    }
+}
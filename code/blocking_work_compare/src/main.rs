@@ -1,99 +1,374 @@
-use futures::future::join_all;
-use std::thread;
+use blocking_work_compare::coop::{PIECE, Spin, spin};
+use blocking_work_compare::cpu;
+#[cfg(any(feature = "smol", feature = "futures-executor"))]
+use blocking_work_compare::executors::Executor;
+use blocking_work_compare::file_io::{FileIo, temp_path, write_then_read};
+use blocking_work_compare::matrix::{Cell, Flavor, Outcome, Plan, table};
+use blocking_work_compare::parallel::{on_blocking_pool, on_rayon, with_heartbeat};
+use blocking_work_compare::pool::flood;
+use blocking_work_compare::timing::{Output, TimingEvent, write_csv, write_json};
+use blocking_work_compare::workers::{Activity, BEAT, chart, spawn_heartbeat};
+use blocking_work_compare::{Job, Strategy, Trace, Workload, run};
+use clap::Parser;
+use interleaving::{Recorder, Timeline};
+use runtime_metrics::Sampler;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::process::ExitCode;
 use std::time::{Duration, Instant};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::prelude::*;
 
-fn main() {
-    run_multithread_runtime();
-    run_current_thread_runtime();
+// Without it the console would connect and see nothing: tokio only reports its tasks
+// to `tracing` in an unstable build.
+#[cfg(all(feature = "console", not(tokio_unstable)))]
+compile_error!("the console feature needs RUSTFLAGS=\"--cfg tokio_unstable\"");
+
+/// How often each runtime's metrics are logged while it runs.
+const SAMPLE_PERIOD: Duration = Duration::from_millis(100);
+
+/// Tasks blocking in async code, and each way around it, compared run by run.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Tasks in each run.
+    #[arg(long, default_value_t = 3)]
+    tasks: usize,
+    /// Times each task blocks.
+    #[arg(long, default_value_t = 3)]
+    iters: usize,
+    /// Milliseconds each time blocks for.
+    #[arg(long, value_name = "MS", default_value_t = 60)]
+    sleep_ms: u64,
+    /// What blocking is.
+    #[arg(long, value_enum, default_value_t)]
+    workload: Kind,
+    /// Every run on every runtime, then the small pool and the loop that never awaits.
+    #[arg(long)]
+    matrix: bool,
+    /// Worker threads for each multi-thread runtime.
+    #[arg(long, value_name = "N,...", value_delimiter = ',', default_value = "1,2,4,8", requires = "matrix")]
+    workers: Vec<NonZeroUsize>,
+    /// The blocking pool's size for the small-pool run.
+    #[arg(long, value_name = "THREADS", default_value = "2", requires = "matrix")]
+    blocking_threads: NonZeroUsize,
+    /// Write a file, sync it and read it back, each of the ways there are.
+    #[arg(long)]
+    file_io: bool,
+    /// Megabytes to write and read back with `--file-io`.
+    #[arg(long, value_name = "MB", default_value_t = 64, requires = "file_io")]
+    file_mb: usize,
+    /// How to print the timelines once the runs are done.
+    #[arg(long, value_enum, default_value_t)]
+    output: Output,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+enum Kind {
+    /// `std::thread::sleep`.
+    #[default]
+    Sleep,
+    /// Hashing, calibrated against `--sleep-ms` before the runs start.
+    Cpu,
 }
 
-async fn run_blocking_sleep(label: &str) {
-    let start = Instant::now();
-    let tasks: Vec<_> = (0..3).map(|n| blocking_looper(n, start, label)).collect();
-    join_all(tasks).await;
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_tracing(cli.output);
+    // The runs are over in a second or two: wait for the console to connect first, and
+    // keep the finished tasks on screen afterwards.
+    #[cfg(feature = "console")]
+    wait_for_enter("connect tokio-console, then press Enter to start");
+    let workload = Workload { tasks: cli.tasks, iterations: cli.iters, work: Duration::from_millis(cli.sleep_ms), job: Job::Sleep };
+    let workload = match cli.workload {
+        Kind::Sleep => workload,
+        Kind::Cpu => workload.cpu(),
+    };
+    // Without `--matrix`, the three runs the demo started with, on the two runtimes it
+    // started with.
+    let (runs, flavors) = if cli.matrix { (&RUNS[..], Flavor::all(&cli.workers)) } else { (&RUNS[..3], Flavor::baseline()) };
+    info!(workload.tasks, workload.iterations, ?workload.work, ?workload.job, ?flavors, "every run");
+    let mut events = Vec::new();
+    let cells: Vec<Cell> = flavors.into_iter().flat_map(|flavor| run_on(flavor, runs, workload, &mut events)).collect();
+    info!("how long each run took, on each runtime:\n{}", table(&cells));
+    #[cfg(any(feature = "smol", feature = "futures-executor"))]
+    run_other_executors(runs, workload);
+    if cli.matrix {
+        run_small_pool_runtime(workload, cli.workers.iter().copied().max().unwrap_or(NonZeroUsize::MIN), cli.blocking_threads);
+        run_starvation_runtime(workload);
+    }
+    if cli.file_io && let Err(e) = run_file_io_runtime(cli.file_mb) {
+        error!(error = %e, "the file IO runs failed");
+        return ExitCode::FAILURE;
+    }
+    let written = match cli.output {
+        Output::Text => Ok(()),
+        Output::Csv => write_csv(&events, std::io::stdout().lock()),
+        Output::Json => write_json(&events, std::io::stdout().lock()),
+    };
+    if let Err(e) = written {
+        error!(error = %e, "can't write the timelines");
+        return ExitCode::FAILURE;
+    }
+    #[cfg(feature = "console")]
+    wait_for_enter("press Enter to exit");
+    ExitCode::SUCCESS
 }
 
-async fn run_async_sleep(label: &str) {
-    let start = Instant::now();
-    let tasks: Vec<_> = (0..3).map(|n| async_looper(n, start, label)).collect();
-    join_all(tasks).await;
+/// Logs through `tracing` at the levels `RUST_LOG` asks for (`info` when it isn't set),
+/// with the thread each line came from: that's where blocking shows. The log goes to
+/// stdout unless the timelines are going there. With the `console` feature the same
+/// subscriber serves tokio-console on 127.0.0.1:6669, from a thread of its own, so it
+/// outlives both runtimes.
+fn init_tracing(output: Output) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let to_stderr = output != Output::Text;
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_thread_names(true)
+        .with_target(false)
+        .without_time()
+        .with_writer(move || -> Box<dyn std::io::Write> { if to_stderr { Box::new(std::io::stderr()) } else { Box::new(std::io::stdout()) } });
+    let registry = tracing_subscriber::registry().with(fmt.with_filter(filter));
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
+    registry.init();
 }
 
-async fn run_spawn_blocking(label: &str) {
-    let start = Instant::now();
-    let tasks: Vec<_> = (0..3).map(|n| looper_with_spawn_blocking(n, start, label)).collect();
-    join_all(tasks).await;
+#[cfg(feature = "console")]
+fn wait_for_enter(prompt: &str) {
+    info!("{prompt}");
+    let _ = std::io::stdin().read_line(&mut String::new());
 }
 
-async fn blocking_looper(n: u8, start: Instant, label: &str) {
-    for i in 0..3 {
-        println!(
-            "[{label}] +{:>4}ms task {n} iteration {i} (before std::thread::sleep)",
-            start.elapsed().as_millis()
-        );
-        thread::sleep(Duration::from_millis(60));
+/// Runs `run` as a task called `name`, with a heartbeat next to it, waits for it, logs
+/// how its tasks interleaved and what each thread did, and returns what they recorded.
+async fn run_as_task<F>(name: &str, run: impl FnOnce(Trace) -> F) -> Timeline
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let trace = Trace::default();
+    let beats = Recorder::new();
+    let heartbeat = spawn_heartbeat(beats.clone());
+    spawn_named(name, run(trace.clone())).await.expect("run task panicked");
+    heartbeat.abort();
+    let timeline = trace.recorder.timeline();
+    report(&timeline);
+    info!("what each thread did, the tasks by number:\n{}", chart(&trace.activity.spans(), &beats.timeline()));
+    timeline
+}
+
+/// Runs `run` as a task called `name`, as `run_as_task` does, for a run that panics
+/// where it is, and logs why it did. The panic message itself is on stderr already.
+/// Returns whether it panicked.
+async fn run_expecting_panic<F>(name: &str, run: impl FnOnce(Trace) -> F) -> bool
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    match spawn_named(name, run(Trace::default())).await {
+        Ok(()) => {
+            info!("it didn't panic after all");
+            false
+        }
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let message = panic.downcast_ref::<&str>().copied().or_else(|| panic.downcast_ref::<String>().map(String::as_str)).unwrap_or("?");
+            info!("it panicked, as it should: {message}");
+            true
+        }
+        Err(e) => {
+            info!("it was cancelled: {e}");
+            false
+        }
     }
 }
 
-async fn async_looper(n: u8, start: Instant, label: &str) {
-    for i in 0..3 {
-        println!(
-            "[{label}] +{:>4}ms task {n} iteration {i} (before tokio::time::sleep)",
-            start.elapsed().as_millis()
-        );
-        tokio::time::sleep(Duration::from_millis(60)).await;
+/// `tokio::spawn`, with a name for the console. The console only lists tasks, and the
+/// future `block_on` runs isn't one; the name says whether it blocks.
+fn spawn_named(name: &str, run: impl Future<Output = ()> + Send + 'static) -> tokio::task::JoinHandle<()> {
+    #[cfg(all(tokio_unstable, feature = "console"))]
+    return tokio::task::Builder::new().name(name).spawn(run).expect("spawning inside a runtime");
+    #[cfg(not(all(tokio_unstable, feature = "console")))]
+    {
+        let _ = name;
+        tokio::spawn(run)
     }
 }
 
-async fn looper_with_spawn_blocking(n: u8, start: Instant, label: &str) {
-    for i in 0..3 {
-        println!(
-            "[{label}] +{:>4}ms task {n} iteration {i} (before spawn_blocking)",
-            start.elapsed().as_millis()
-        );
+/// What the log above shows by eye: whether the tasks ran one after another, and how
+/// far apart the tasks reached the same iteration at worst.
+fn report(timeline: &Timeline) {
+    let widest = timeline.max_step_spread().map(|(_, spread)| spread.as_millis());
+    info!(
+        elapsed_ms = timeline.elapsed().as_millis(),
+        serialized = timeline.serialized().is_ok(),
+        widest_spread_ms = widest,
+        "how the tasks interleaved"
+    );
+}
+
+/// Logs `runtime`'s metrics every `SAMPLE_PERIOD` from a thread of its own, which keeps
+/// reporting however blocked the runtime is.
+fn sample(runtime: &tokio::runtime::Runtime, flavor: Flavor) -> Sampler {
+    Sampler::spawn(runtime.handle().clone(), SAMPLE_PERIOD, move |sample| info!(runtime = %flavor, "{sample}"))
+        .expect("spawning the sampler thread")
+}
+
+/// The runs, in order, with how the log introduces each.
+const RUNS: [(Strategy, &str); 5] = [
+    (Strategy::Inline, "BAD - blocking in async code"),
+    (Strategy::AsyncSleep, "GOOD - tokio::time::sleep().await"),
+    (Strategy::SpawnBlocking, "GOOD - move blocking work to spawn_blocking"),
+    (Strategy::BlockInPlace, "SO-SO - tell the runtime with block_in_place"),
+    (Strategy::Rayon, "GOOD for CPU work - hand it to rayon"),
+];
 
-        tokio::task::spawn_blocking(|| {
-            thread::sleep(Duration::from_millis(60));
-        })
-        .await
-        .expect("spawn_blocking task panicked");
+/// Every one of `runs` that `flavor` can do, on a runtime of its own, adding what their
+/// tasks recorded to `events`, and with CPU work the one big job as well; and how long
+/// each took.
+fn run_on(flavor: Flavor, runs: &[(Strategy, &str)], workload: Workload, events: &mut Vec<TimingEvent>) -> Vec<Cell> {
+    let runtime = flavor.build().expect("Failed to build the runtime");
+    let sampler = sample(&runtime, flavor);
+    let label = flavor.name();
+
+    let cells = runtime.block_on(async {
+        let mut cells = Vec::new();
+        for (number, &(strategy, heading)) in (1..).zip(runs) {
+            let name = format!("[{flavor}] run {number}: {}", strategy.name());
+            let start = Instant::now();
+            let outcome = match flavor.plan(strategy, workload) {
+                Plan::Skip => {
+                    info!("=== [{flavor}] RUN {number}: skipped, there's no async version of CPU work ===");
+                    Outcome::Skipped
+                }
+                Plan::Panics => {
+                    info!("=== [{flavor}] RUN {number}: {heading}: panics, there's no other thread to hand the tasks to ===");
+                    let panicked = run_expecting_panic(&format!("{name}, which panics"), |trace| run(strategy, label, workload, trace)).await;
+                    if panicked { Outcome::Panicked } else { Outcome::Took(start.elapsed()) }
+                }
+                Plan::Run => {
+                    info!("=== [{flavor}] RUN {number}: {heading} ({}) ===", workload.blocking());
+                    let timeline = run_as_task(&name, |trace| run(strategy, label, workload, trace)).await;
+                    events.extend(TimingEvent::from_timeline(flavor, strategy, &timeline));
+                    Outcome::Took(start.elapsed())
+                }
+            };
+            cells.push(Cell { flavor, strategy, outcome });
+        }
+        if let (Flavor::MultiThread { .. }, Job::Cpu { rounds }) = (flavor, workload.job) {
+            run_parallel(flavor, workload.tasks * workload.iterations, rounds).await;
+        }
+        cells
+    });
+    info!(runtime = %flavor, "over all the runs: {}", sampler.stop());
+    cells
+}
+
+/// Every one of `runs` on each executor other than tokio's that the build has, from the
+/// main thread, with how its tasks interleaved or why it panicked.
+#[cfg(any(feature = "smol", feature = "futures-executor"))]
+fn run_other_executors(runs: &[(Strategy, &str)], workload: Workload) {
+    let outcome = |ran: Result<Timeline, String>| match ran {
+        Ok(timeline) => report(&timeline),
+        Err(message) => info!("it panicked: {message}"),
+    };
+    for &executor in Executor::ALL {
+        for (number, &(strategy, heading)) in (1..).zip(runs) {
+            if strategy == Strategy::AsyncSleep && workload.job != Job::Sleep {
+                continue;
+            }
+            info!("=== [{}] RUN {number}: {heading} ({}) ===", executor.name(), workload.blocking());
+            outcome(executor.run(strategy, workload));
+            #[cfg(feature = "smol")]
+            if let Some(ran) = executor.run_smols(strategy, workload) {
+                info!("=== [{}] RUN {number}, with smol::Timer and smol::unblock instead ===", executor.name());
+                outcome(ran);
+            }
+        }
     }
 }
 
-fn run_multithread_runtime() {
+/// Every task's every iteration as a blocking job of its own, all at once, on a runtime
+/// whose blocking pool has only `threads`: how long they queued, and which thread did
+/// each.
+fn run_small_pool_runtime(workload: Workload, workers: NonZeroUsize, threads: NonZeroUsize) {
     let runtime = tokio::runtime::Builder::new_multi_thread()
-        .worker_threads(2)
+        .worker_threads(workers.get())
+        .max_blocking_threads(threads.get())
         .enable_all()
         .build()
-        .expect("Failed to build multi-thread runtime");
+        .expect("Failed to build the small-pool runtime");
+    let jobs = workload.tasks * workload.iterations;
 
     runtime.block_on(async {
-        println!("=== RUN 1: BAD - std::thread::sleep in async code ===");
-        run_blocking_sleep("multithread").await;
+        info!("=== [small pool] {jobs} spawn_blocking jobs at once, for {threads} blocking threads ({}) ===", workload.blocking());
+        let activity = Activity::new();
+        let beats = Recorder::new();
+        let heartbeat = spawn_heartbeat(beats.clone());
+        let queueing = flood(jobs, workload, &activity).await;
+        heartbeat.abort();
+        info!(
+            queued = queueing.queued(),
+            longest_wait = ?queueing.longest(),
+            mean_wait = ?queueing.mean(),
+            "jobs that waited for a thread, of {jobs}"
+        );
+        info!("what each thread did, the jobs by number:\n{}", chart(&activity.spans(), &beats.timeline()));
+    });
+}
 
-        println!("\n=== RUN 2: GOOD - tokio::time::sleep().await ===");
-        run_async_sleep("multithread").await;
+/// `workload`'s blocking for one task, as hashing in pieces of about `PIECE` in an async
+/// loop, on a current-thread runtime, with nothing between the pieces and then each
+/// way of yielding, and how late the heartbeat got meanwhile.
+fn run_starvation_runtime(workload: Workload) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build current_thread runtime");
+    let total = workload.work * u32::try_from(workload.iterations).unwrap_or(u32::MAX);
+    let pieces = usize::try_from(total.as_micros() / PIECE.as_micros()).unwrap_or(usize::MAX);
+    let rounds = cpu::rounds_for(PIECE);
 
-        println!("\n=== RUN 3: GOOD - move blocking work to spawn_blocking ===");
-        run_spawn_blocking("multithread").await;
+    runtime.block_on(async {
+        info!("=== [current_thread] {total:?} of hashing in {pieces} pieces, in an async loop ===");
+        for how in Spin::ALL {
+            let report = with_heartbeat(spin(how, pieces, rounds)).await;
+            info!(took = ?report.took, worst_beat = ?report.worst_beat, beat = ?BEAT, "{}", how.name());
+        }
     });
 }
 
-fn run_current_thread_runtime() {
+/// Writes a `mb` megabyte file and reads it back, each of the ways there are, on a
+/// current-thread runtime, where anything blocking stops the heartbeat.
+fn run_file_io_runtime(mb: usize) -> std::io::Result<()> {
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .expect("Failed to build current_thread runtime");
 
     runtime.block_on(async {
-        println!("\n=== RUN 4: current_thread runtime comparison ===");
-        println!("-- current_thread + std::thread::sleep (bad) --");
-        run_blocking_sleep("current_thread").await;
-
-        println!("\n-- current_thread + tokio::time::sleep (good) --");
-        run_async_sleep("current_thread").await;
+        info!("=== [current_thread] file IO: {mb}MB written, synced and read back ===");
+        for how in FileIo::ALL {
+            let report = write_then_read(how, &temp_path(how), mb * 1_000_000).await?;
+            let (write, read) = report.throughput();
+            info!(
+                write_mb_per_sec = format!("{write:.0}"),
+                read_mb_per_sec = format!("{read:.0}"),
+                worst_beat = ?report.worst_beat,
+                beat = ?BEAT,
+                "{}",
+                how.name()
+            );
+        }
+        Ok(())
+    })
+}
 
-        println!("\n-- current_thread + spawn_blocking (good) --");
-        run_spawn_blocking("current_thread").await;
-    });
+/// All the runs' work as one job, on the blocking pool and then on rayon, with how long
+/// each took and how late the runtime's heartbeat got meanwhile.
+async fn run_parallel(flavor: Flavor, pieces: usize, rounds: u64) {
+    info!("=== [{flavor}] one job in {pieces} pieces: a spawn_blocking each, then a rayon par_iter ===");
+    let pool = with_heartbeat(on_blocking_pool(pieces, rounds)).await;
+    info!(took = ?pool.took, worst_beat = ?pool.worst_beat, beat = ?BEAT, "spawn_blocking");
+    let rayon = with_heartbeat(on_rayon(pieces, rounds)).await;
+    info!(took = ?rayon.took, worst_beat = ?rayon.worst_beat, beat = ?BEAT, threads = rayon::current_num_threads(), "rayon");
 }
@@ -0,0 +1,186 @@
+//! Every run on every runtime: a current-thread one, and multi-thread ones with each of
+//! a few worker counts, and a table of how long each run took on each.
+//!
+//! A multi-thread runtime with one worker is the current-thread one with a thread to
+//! spare for `block_in_place` to hand the others to. More workers show how much of the
+//! blocking inline is hidden by having workers to spread it over, which is none, since
+//! `join_all` polls every task from one, and how much of `spawn_blocking` and rayon's
+//! CPU work is, which is as far as there are cores.
+
+use crate::{Job, Strategy, Workload};
+use std::fmt::{self, Write};
+use std::io;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+/// A kind of runtime to do the runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Flavor {
+    CurrentThread,
+    MultiThread { workers: NonZeroUsize },
+}
+
+impl Flavor {
+    /// The quick demo's two: a multi-thread runtime with two workers, then the
+    /// current-thread one.
+    pub fn baseline() -> Vec<Self> {
+        let workers = NonZeroUsize::new(2).expect("two is not zero");
+        vec![Self::MultiThread { workers }, Self::CurrentThread]
+    }
+
+    /// The current-thread runtime, then a multi-thread one for each of `workers`.
+    pub fn all(workers: &[NonZeroUsize]) -> Vec<Self> {
+        [Self::CurrentThread].into_iter().chain(workers.iter().map(|&workers| Self::MultiThread { workers })).collect()
+    }
+
+    /// As the CSV and JSON have it, without the workers.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CurrentThread => "current_thread",
+            Self::MultiThread { .. } => "multithread",
+        }
+    }
+
+    /// The threads polling tasks.
+    pub fn workers(self) -> usize {
+        match self {
+            Self::CurrentThread => 1,
+            Self::MultiThread { workers } => workers.get(),
+        }
+    }
+
+    pub fn build(self) -> io::Result<Runtime> {
+        match self {
+            Self::CurrentThread => Builder::new_current_thread().enable_all().build(),
+            Self::MultiThread { workers } => Builder::new_multi_thread().worker_threads(workers.get()).enable_all().build(),
+        }
+    }
+
+    /// What `strategy`'s run with `workload` does on this runtime.
+    pub fn plan(self, strategy: Strategy, workload: Workload) -> Plan {
+        match (self, strategy) {
+            (_, Strategy::AsyncSleep) if workload.job != Job::Sleep => Plan::Skip,
+            (Self::CurrentThread, Strategy::BlockInPlace) => Plan::Panics,
+            _ => Plan::Run,
+        }
+    }
+}
+
+impl fmt::Display for Flavor {
+    /// `current_thread`, or `multithread x4` for four workers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CurrentThread => f.write_str(self.name()),
+            Self::MultiThread { workers } => write!(f, "{} x{workers}", self.name()),
+        }
+    }
+}
+
+/// Whether a run is worth doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plan {
+    Run,
+    /// `block_in_place` with no other thread to hand the tasks to: run it to show it.
+    Panics,
+    /// `tokio::time::sleep` for CPU work, which there's no async version of.
+    Skip,
+}
+
+/// How a run went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Wall-clock, from spawning it to its last task finishing.
+    Took(Duration),
+    Panicked,
+    Skipped,
+}
+
+/// One run, on one runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub flavor: Flavor,
+    pub strategy: Strategy,
+    pub outcome: Outcome,
+}
+
+/// A row per strategy and a column per runtime, in the order `cells` first has them.
+pub fn table(cells: &[Cell]) -> String {
+    let mut flavors: Vec<Flavor> = Vec::new();
+    let mut strategies: Vec<Strategy> = Vec::new();
+    for cell in cells {
+        if !flavors.contains(&cell.flavor) {
+            flavors.push(cell.flavor);
+        }
+        if !strategies.contains(&cell.strategy) {
+            strategies.push(cell.strategy);
+        }
+    }
+    let headings: Vec<String> = flavors.iter().map(Flavor::to_string).collect();
+    let first = strategies.iter().map(|strategy| strategy.name().len()).max().unwrap_or(0);
+    let mut table = format!("{:first$}", "");
+    for heading in &headings {
+        write!(table, "  {heading:>14}").expect("writing to a String");
+    }
+    for strategy in strategies {
+        write!(table, "\n{:first$}", strategy.name()).expect("writing to a String");
+        for &flavor in &flavors {
+            let outcome = match cells.iter().find(|cell| cell.strategy == strategy && cell.flavor == flavor).map(|cell| cell.outcome) {
+                Some(Outcome::Took(took)) => format!("{}ms", took.as_millis()),
+                Some(Outcome::Panicked) => "panics".to_string(),
+                Some(Outcome::Skipped) | None => "-".to_string(),
+            };
+            write!(table, "  {outcome:>14}").expect("writing to a String");
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workers(n: usize) -> Flavor {
+        Flavor::MultiThread { workers: NonZeroUsize::new(n).unwrap() }
+    }
+
+    #[test]
+    fn test_the_table_has_a_column_per_runtime() {
+        let ms = |ms| Outcome::Took(Duration::from_millis(ms));
+        let cells = [
+            Cell { flavor: Flavor::CurrentThread, strategy: Strategy::Inline, outcome: ms(541) },
+            Cell { flavor: workers(4), strategy: Strategy::Inline, outcome: ms(540) },
+            Cell { flavor: Flavor::CurrentThread, strategy: Strategy::BlockInPlace, outcome: Outcome::Panicked },
+            Cell { flavor: workers(4), strategy: Strategy::BlockInPlace, outcome: ms(1200) },
+            Cell { flavor: workers(4), strategy: Strategy::AsyncSleep, outcome: Outcome::Skipped },
+        ];
+        assert_eq!(
+            table(&cells),
+            [
+                "                current_thread  multithread x4",
+                "inline                   541ms           540ms",
+                "block_in_place          panics          1200ms",
+                "async_sleep                  -               -",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_a_runtime_has_the_workers_it_says() {
+        for flavor in Flavor::all(&[NonZeroUsize::MIN, NonZeroUsize::new(3).unwrap()]) {
+            let runtime = flavor.build().unwrap();
+            assert_eq!(runtime.metrics().num_workers(), flavor.workers(), "{flavor}");
+        }
+    }
+
+    #[test]
+    fn test_only_what_can_run_is_run() {
+        let sleep = Workload::default();
+        let cpu = Workload { job: Job::Cpu { rounds: 1 }, ..sleep };
+        assert_eq!(Flavor::CurrentThread.plan(Strategy::BlockInPlace, sleep), Plan::Panics);
+        assert_eq!(workers(1).plan(Strategy::BlockInPlace, sleep), Plan::Run);
+        assert_eq!(workers(1).plan(Strategy::AsyncSleep, cpu), Plan::Skip);
+        assert_eq!(Flavor::CurrentThread.plan(Strategy::AsyncSleep, sleep), Plan::Run);
+    }
+}
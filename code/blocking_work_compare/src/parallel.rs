@@ -0,0 +1,85 @@
+//! One big CPU job from async code, split into pieces: on the blocking pool, a
+//! `spawn_blocking` a piece, or on rayon, a `par_iter` over them handed over whole, with
+//! a `oneshot` to await the answer on.
+//!
+//! Both keep the runtime's workers free to poll, and both finish in about the time the
+//! cores take to get through the pieces. The difference is what the workers are up
+//! against meanwhile: the blocking pool starts a thread for every piece, and the
+//! operating system shares the cores out among all of them and the workers alike, so a
+//! worker waits its turn behind every piece; rayon runs a thread per core whatever the
+//! pieces, and the workers only wait behind those. [`with_heartbeat`] shows it as a
+//! timer on the runtime that wants to tick every few milliseconds, and how late it got.
+
+use crate::cpu;
+use crate::workers::{spawn_heartbeat, worst_gap};
+use interleaving::Recorder;
+use rayon::prelude::*;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// How a job went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub answer: u64,
+    pub took: Duration,
+    /// The longest the heartbeat went between two ticks, against [`BEAT`](crate::workers::BEAT).
+    pub worst_beat: Duration,
+}
+
+/// `pieces` pieces of `rounds` each, each on a blocking thread of its own.
+pub async fn on_blocking_pool(pieces: usize, rounds: u64) -> u64 {
+    let handles: Vec<_> = (0..pieces).map(|_| tokio::task::spawn_blocking(move || cpu::crunch(rounds))).collect();
+    let mut answer = 0u64;
+    for handle in handles {
+        answer = answer.wrapping_add(handle.await.expect("crunching doesn't panic"));
+    }
+    answer
+}
+
+/// The same pieces, run by rayon across its pool, and the answer sent back.
+pub async fn on_rayon(pieces: usize, rounds: u64) -> u64 {
+    let (done, answer) = oneshot::channel();
+    rayon::spawn(move || {
+        let answer = (0..pieces).into_par_iter().map(|_| cpu::crunch(rounds)).reduce(|| 0, u64::wrapping_add);
+        // Nobody to tell if the caller's gone.
+        let _ = done.send(answer);
+    });
+    answer.await.expect("rayon drops a job only by aborting")
+}
+
+/// Runs `job` with a heartbeat ticking on the same runtime, and reports.
+pub async fn with_heartbeat(job: impl Future<Output = u64>) -> Report {
+    let recorder = Recorder::new();
+    let heartbeat = spawn_heartbeat(recorder.clone());
+    let start = Instant::now();
+    let answer = job.await;
+    let took = start.elapsed();
+    heartbeat.abort();
+    Report { answer, took, worst_beat: worst_gap(&recorder) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_both_get_the_same_answer() {
+        let pool = with_heartbeat(on_blocking_pool(6, 20)).await;
+        let rayon = with_heartbeat(on_rayon(6, 20)).await;
+        assert_eq!(pool.answer, rayon.answer);
+        assert_eq!(pool.answer, cpu::crunch(20).wrapping_mul(6));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_the_heartbeat_ticks_through_a_job_off_the_runtime() {
+        let report = with_heartbeat(async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            1
+        })
+        .await;
+        assert!(report.took >= Duration::from_millis(50));
+        // A few beats' slack for a busy machine, far less than the job.
+        assert!(report.worst_beat < Duration::from_millis(40), "{report:?}");
+    }
+}
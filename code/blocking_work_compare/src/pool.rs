@@ -0,0 +1,53 @@
+//! The blocking pool is a pool: `spawn_blocking` hands work to one of its threads, and
+//! when they're all busy and there are `max_blocking_threads` of them already, the work
+//! queues for one to come free.
+//!
+//! Tokio's default is 512 threads, so a little blocking never notices; a lot of it, or
+//! a runtime built with a small pool to keep the thread count down, does. [`flood`]
+//! sends more jobs than there are threads and measures how long each waited for one.
+
+use crate::Workload;
+use crate::workers::{Activity, Place};
+use std::time::{Duration, Instant};
+
+/// How long each job waited before a thread picked it up, in the order they were sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Queueing {
+    pub waits: Vec<Duration>,
+}
+
+impl Queueing {
+    pub fn longest(&self) -> Duration {
+        self.waits.iter().copied().max().unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.waits.iter().sum::<Duration>().checked_div(self.waits.len() as u32).unwrap_or_default()
+    }
+
+    /// The jobs that found no thread free, give or take a millisecond to start one.
+    pub fn queued(&self) -> usize {
+        self.waits.iter().filter(|&&wait| wait > Duration::from_millis(1)).count()
+    }
+}
+
+/// Sends `jobs` of `workload`'s blocking to the blocking pool all at once, noting in
+/// `activity` which thread did each, and waits for them all.
+pub async fn flood(jobs: usize, workload: Workload, activity: &Activity) -> Queueing {
+    let handles: Vec<_> = (0..jobs)
+        .map(|job| {
+            let activity = activity.clone();
+            let sent = Instant::now();
+            tokio::task::spawn_blocking(move || {
+                let waited = sent.elapsed();
+                activity.busy(Place::BlockingPool, job, 0, || workload.block());
+                waited
+            })
+        })
+        .collect();
+    let mut waits = Vec::with_capacity(jobs);
+    for handle in handles {
+        waits.push(handle.await.expect("blocking doesn't panic"));
+    }
+    Queueing { waits }
+}
@@ -0,0 +1,98 @@
+//! The runs' timelines as data, for plotting afterwards: one [`TimingEvent`] per
+//! iteration a task started, written out as CSV or JSON.
+
+use crate::Strategy;
+use crate::matrix::Flavor;
+use interleaving::Timeline;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// A task starting an iteration, in one strategy's run on one runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TimingEvent {
+    /// `multithread` or `current_thread`.
+    pub runtime: &'static str,
+    /// The runtime's worker threads.
+    pub workers: usize,
+    pub strategy: Strategy,
+    pub task: usize,
+    pub iteration: usize,
+    /// Microseconds since the run started.
+    pub at_us: u64,
+}
+
+/// How to print the timelines once every run is done.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// Only the log, as the runs go.
+    #[default]
+    Text,
+    /// A header and a row per event.
+    Csv,
+    /// An array of events.
+    Json,
+}
+
+impl TimingEvent {
+    /// Every event in `timeline`, from `strategy`'s run on a `flavor` runtime.
+    pub fn from_timeline(flavor: Flavor, strategy: Strategy, timeline: &Timeline) -> Vec<Self> {
+        timeline
+            .events()
+            .iter()
+            .map(|event| Self {
+                runtime: flavor.name(),
+                workers: flavor.workers(),
+                strategy,
+                task: event.task,
+                iteration: event.step,
+                at_us: u64::try_from(event.at.as_micros()).unwrap_or(u64::MAX),
+            })
+            .collect()
+    }
+}
+
+pub fn write_csv(events: &[TimingEvent], mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "runtime,workers,strategy,task,iteration,at_us")?;
+    for event in events {
+        writeln!(out, "{},{},{},{},{},{}", event.runtime, event.workers, event.strategy.name(), event.task, event.iteration, event.at_us)?;
+    }
+    Ok(())
+}
+
+pub fn write_json(events: &[TimingEvent], mut out: impl Write) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut out, events).map_err(io::Error::other)?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interleaving::Event;
+    use std::time::Duration;
+
+    fn events() -> Vec<TimingEvent> {
+        let timeline = Timeline::from_events(vec![
+            Event { task: 1, step: 0, at: Duration::from_micros(1500) },
+            Event { task: 0, step: 0, at: Duration::from_micros(20) },
+        ]);
+        TimingEvent::from_timeline(Flavor::CurrentThread, Strategy::SpawnBlocking, &timeline)
+    }
+
+    #[test]
+    fn test_csv_has_a_row_per_event_in_time_order() {
+        let mut csv = Vec::new();
+        write_csv(&events(), &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "runtime,workers,strategy,task,iteration,at_us\ncurrent_thread,1,spawn_blocking,0,0,20\ncurrent_thread,1,spawn_blocking,1,0,1500\n"
+        );
+    }
+
+    #[test]
+    fn test_json_names_the_strategy_as_csv_does() {
+        let mut json = Vec::new();
+        write_json(&events(), &mut json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed[1], serde_json::json!({"runtime": "current_thread", "workers": 1, "strategy": "spawn_blocking", "task": 1, "iteration": 0, "at_us": 1500}));
+    }
+}
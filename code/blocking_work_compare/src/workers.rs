@@ -0,0 +1,242 @@
+//! Which thread did each task's blocking, and when, drawn as a line per thread, with a
+//! timer on the runtime underneath to show whether it kept time meanwhile.
+//!
+//! The log's timestamps say when each iteration started; that a worker was blocked has
+//! to be worked out from the gaps. The chart shows it: a row per thread that a task
+//! ran or blocked on, a column per slice of time, and in each column the task that was
+//! busy on that thread then, so a blocked worker is a row full of one task's number
+//! while the others wait their turn, and `*` is several tasks polled at once. The
+//! `timer` row is a heartbeat spawned next to the tasks, `+` where it ticked on time
+//! and `!` where it ticked late, `.` while it couldn't tick at all:
+//!
+//! ```text
+//! worker 0   000000111111222222
+//! timer      +++++++++++++++++!
+//! ```
+//!
+//! Tokio names its workers and its blocking threads alike, so rows are named by what
+//! the thread was doing, and numbered as they turn up.
+
+use interleaving::{Recorder, Timeline};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// How often the heartbeat wants to tick.
+pub const BEAT: Duration = Duration::from_millis(5);
+
+/// The kind of thread something ran on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Place {
+    /// One of the runtime's, polling tasks (or, in `block_in_place`, one that was).
+    Worker,
+    /// A `spawn_blocking` thread.
+    BlockingPool,
+    /// One of rayon's.
+    Rayon,
+}
+
+impl Place {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Worker => "worker",
+            Self::BlockingPool => "blocking",
+            Self::Rayon => "rayon",
+        }
+    }
+}
+
+/// A thread busy with a task's iteration from `from` to `to`; the two are the same for
+/// a worker that only polled the task on its way to waiting somewhere else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Busy {
+    pub thread: ThreadId,
+    pub place: Place,
+    pub task: usize,
+    pub iteration: usize,
+    pub from: Duration,
+    pub to: Duration,
+}
+
+/// Where tasks note what their threads did. Cheap to clone, like a [`Recorder`].
+#[derive(Debug, Clone)]
+pub struct Activity {
+    start: Instant,
+    busy: Arc<Mutex<Vec<Busy>>>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Activity {
+    /// Starts the clock.
+    pub fn new() -> Self {
+        Self { start: Instant::now(), busy: Arc::default() }
+    }
+
+    /// Runs `work` as `task`'s `iteration`, noting which thread it ran on and for how long.
+    pub fn busy<R>(&self, place: Place, task: usize, iteration: usize, work: impl FnOnce() -> R) -> R {
+        let from = self.start.elapsed();
+        let result = work();
+        let to = self.start.elapsed();
+        let busy = Busy { thread: thread::current().id(), place, task, iteration, from, to };
+        self.busy.lock().expect("noting doesn't panic").push(busy);
+        result
+    }
+
+    /// Notes that a worker polled `task`'s `iteration` just now.
+    pub fn polled(&self, task: usize, iteration: usize) {
+        self.busy(Place::Worker, task, iteration, || ());
+    }
+
+    /// What's been noted so far, in the order it finished.
+    pub fn spans(&self) -> Vec<Busy> {
+        self.busy.lock().expect("noting doesn't panic").clone()
+    }
+}
+
+/// A task ticking every [`BEAT`] on the current runtime and recording each tick as
+/// task 0's next step, until it's aborted.
+pub fn spawn_heartbeat(recorder: Recorder) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(BEAT);
+        // A late tick is the measurement: no catching up after it.
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        for beat in 0.. {
+            ticks.tick().await;
+            recorder.record(0, beat);
+        }
+    })
+}
+
+/// The longest `beats` went without an event, counting from when it was made to now: a
+/// heartbeat that never got to tick at all went the whole time.
+pub fn worst_gap(beats: &Recorder) -> Duration {
+    let ats: Vec<Duration> = [Duration::ZERO].into_iter().chain(beats.timeline().events().iter().map(|event| event.at)).chain([beats.elapsed()]).collect();
+    ats.windows(2).map(|pair| pair[1].saturating_sub(pair[0])).max().unwrap_or_default()
+}
+
+/// A task's number as one character.
+fn mark(task: usize) -> char {
+    char::from_digit((task % 36) as u32, 36).expect("less than 36")
+}
+
+/// At most this many columns, however long the run.
+const WIDTH: u128 = 72;
+
+/// The chart of `busy`, with `beats` from [`spawn_heartbeat`] as the timer row.
+pub fn chart(busy: &[Busy], beats: &Timeline) -> String {
+    let end = busy.iter().map(|busy| busy.to).chain([beats.elapsed()]).max().unwrap_or_default();
+    // Ten milliseconds a column, or as many more as fit the run in `WIDTH`.
+    let column = Duration::from_millis(10).max(Duration::from_millis(u64::try_from(end.as_millis().div_ceil(WIDTH)).unwrap_or(u64::MAX)));
+    let columns = usize::try_from(end.as_nanos() / column.as_nanos()).unwrap_or(0) + 1;
+    let slot = |at: Duration| usize::try_from(at.as_nanos() / column.as_nanos()).unwrap_or(usize::MAX).min(columns - 1);
+
+    // Threads in the order they turned up, numbered apart for each kind.
+    let mut rows: Vec<(Place, ThreadId)> = Vec::new();
+    let mut by_start: Vec<&Busy> = busy.iter().collect();
+    by_start.sort_by_key(|busy| busy.from);
+    for busy in &by_start {
+        if !rows.contains(&(busy.place, busy.thread)) {
+            rows.push((busy.place, busy.thread));
+        }
+    }
+    rows.sort_by_key(|&(place, _)| place);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{:<11}{column:?} a column", "");
+    let mut numbering: BTreeMap<Place, usize> = BTreeMap::new();
+    for &(place, thread) in &rows {
+        let number = numbering.entry(place).or_default();
+        let label = format!("{} {number}", place.name());
+        *number += 1;
+        let mut line = vec!['.'; columns];
+        let on_thread = || busy.iter().filter(|busy| busy.place == place && busy.thread == thread);
+        for busy in on_thread().filter(|busy| busy.to > busy.from) {
+            line[slot(busy.from)..=slot(busy.to)].fill(mark(busy.task));
+        }
+        // Polls only where nothing was busy, and `*` where several tasks were polled.
+        let mut polled: Vec<Option<usize>> = vec![None; columns];
+        for busy in on_thread().filter(|busy| busy.to == busy.from) {
+            let at = slot(busy.from);
+            if line[at] == '.' || polled[at].is_some() {
+                line[at] = if polled[at].is_some_and(|task| task != busy.task) { '*' } else { mark(busy.task) };
+                polled[at] = Some(busy.task);
+            }
+        }
+        let _ = writeln!(out, "{label:<11}{}", line.into_iter().collect::<String>());
+    }
+    let mut timer = vec!['.'; columns];
+    let mut last = None;
+    for event in beats.events() {
+        let late = last.is_some_and(|last| event.at - last > 2 * BEAT);
+        let cell = &mut timer[slot(event.at)];
+        if late || *cell != '!' {
+            *cell = if late { '!' } else { '+' };
+        }
+        last = Some(event.at);
+    }
+    let _ = writeln!(out, "{:<11}{}", "timer", timer.into_iter().collect::<String>());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interleaving::Event;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_a_blocked_worker_is_a_row_of_one_task_and_a_late_timer() {
+        let worker = thread::current().id();
+        let pool = thread::spawn(|| thread::current().id()).join().unwrap();
+        let busy = [
+            Busy { thread: worker, place: Place::Worker, task: 0, iteration: 0, from: ms(0), to: ms(30) },
+            Busy { thread: worker, place: Place::Worker, task: 1, iteration: 0, from: ms(30), to: ms(30) },
+            Busy { thread: pool, place: Place::BlockingPool, task: 1, iteration: 0, from: ms(30), to: ms(50) },
+        ];
+        let beats = Timeline::from_events([0, 30, 35, 40, 45, 50].map(|at| Event { task: 0, step: 0, at: ms(at) }).to_vec());
+        let chart = chart(&busy, &beats);
+        let rows: Vec<&str> = chart.lines().collect();
+        // Task 1's poll is hidden behind task 0's blocking, which ends in the same column.
+        assert_eq!(rows[1], "worker 0   0000..", "{chart}");
+        assert_eq!(rows[2], "blocking 0 ...111", "{chart}");
+        assert_eq!(rows[3], "timer      +..!++", "{chart}");
+    }
+
+    #[test]
+    fn test_polls_of_several_tasks_at_once_are_a_star() {
+        let worker = thread::current().id();
+        let busy = [0, 1, 2].map(|task| Busy { thread: worker, place: Place::Worker, task, iteration: 0, from: ms(1), to: ms(1) });
+        let chart = chart(&busy, &Timeline::default());
+        assert_eq!(chart.lines().nth(1).unwrap(), "worker 0   *", "{chart}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_a_heartbeat_that_never_ticked_went_the_whole_time() {
+        let beats = Recorder::new();
+        tokio::time::sleep(ms(30)).await;
+        assert_eq!(worst_gap(&beats), ms(30));
+        beats.record(0, 0);
+        tokio::time::sleep(ms(10)).await;
+        assert_eq!(worst_gap(&beats), ms(30));
+    }
+
+    #[test]
+    fn test_long_runs_get_wider_columns() {
+        let busy = [Busy { thread: thread::current().id(), place: Place::Worker, task: 2, iteration: 0, from: ms(0), to: ms(7200) }];
+        let chart = chart(&busy, &Timeline::default());
+        assert!(chart.lines().next().unwrap().ends_with("100ms a column"), "{chart}");
+        assert_eq!(chart.lines().nth(1).unwrap(), format!("worker 0   {}", "2".repeat(73)));
+    }
+}
@@ -0,0 +1,63 @@
+//! The runs on executors other than tokio's, graded as `interleavings.rs` grades them
+//! on tokio's. Only built with the `smol` or `futures-executor` feature:
+//!
+//! ```text
+//! cargo test -p blocking_work_compare --features smol,futures-executor --test executors
+//! ```
+#![cfg(any(feature = "smol", feature = "futures-executor"))]
+
+use blocking_work_compare::executors::Executor;
+use blocking_work_compare::{Strategy, Workload};
+#[cfg(feature = "smol")]
+use std::time::Duration;
+
+/// As on tokio: far less than the 120ms a task waits for the one before it when
+/// they're serialized.
+#[cfg(feature = "smol")]
+const WINDOW: Duration = Duration::from_millis(40);
+
+#[test]
+fn test_blocking_inline_serializes_the_tasks_on_any_executor() {
+    for &executor in Executor::ALL {
+        let timeline = executor.run(Strategy::Inline, Workload::default()).unwrap();
+        timeline.serialized().unwrap_or_else(|e| panic!("{}: {e}", executor.name()));
+    }
+}
+
+#[test]
+fn test_tokios_sleep_and_blocking_pool_need_tokios_runtime() {
+    for &executor in Executor::ALL {
+        for strategy in [Strategy::AsyncSleep, Strategy::SpawnBlocking] {
+            let e = executor.run(strategy, Workload::default()).unwrap_err();
+            assert!(e.contains("Tokio 1.x runtime"), "{} {}: {e}", executor.name(), strategy.name());
+        }
+    }
+}
+
+#[test]
+fn test_block_in_place_without_a_runtime_blocks_where_it_is() {
+    for &executor in Executor::ALL {
+        let timeline = executor.run(Strategy::BlockInPlace, Workload::default()).unwrap();
+        timeline.serialized().unwrap_or_else(|e| panic!("{}: {e}", executor.name()));
+    }
+}
+
+#[test]
+fn test_rayon_and_its_oneshot_work_under_any_executor() {
+    for &executor in Executor::ALL {
+        let timeline = executor.run(Strategy::Rayon, Workload::default().cpu()).unwrap();
+        assert!(timeline.serialized().is_err(), "{}:\n{timeline}", executor.name());
+    }
+}
+
+#[test]
+#[cfg(feature = "smol")]
+fn test_smols_own_sleep_and_blocking_pool_keep_the_tasks_in_step_on_any_executor() {
+    for &executor in Executor::ALL {
+        for strategy in [Strategy::AsyncSleep, Strategy::SpawnBlocking] {
+            let timeline = executor.run_smols(strategy, Workload::default()).expect("smol has its own").unwrap();
+            timeline.in_step(WINDOW).unwrap_or_else(|e| panic!("{} {}: {e}", executor.name(), strategy.name()));
+            assert!(timeline.serialized().is_err(), "{} {}:\n{timeline}", executor.name(), strategy.name());
+        }
+    }
+}
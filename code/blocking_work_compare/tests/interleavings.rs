@@ -0,0 +1,160 @@
+//! The demo's runs, graded by how their tasks interleaved rather than by reading
+//! the log. These run on the real clock, since the point is what a blocked thread
+//! does, so the window is generous next to an iteration's 60ms.
+
+use blocking_work_compare::matrix::Flavor;
+use blocking_work_compare::timing::{TimingEvent, write_csv};
+use blocking_work_compare::{Strategy, Trace, Workload, run_async_sleep, run_block_in_place, run_inline, run_rayon, run_spawn_blocking};
+use blocking_work_compare::pool::flood;
+use blocking_work_compare::workers::{Activity, Place};
+use interleaving::Timeline;
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+/// Far less than the 120ms a task waits for the one before it when they're serialized.
+const WINDOW: Duration = Duration::from_millis(40);
+
+fn multithread() -> Runtime {
+    Builder::new_multi_thread().worker_threads(2).enable_all().build().unwrap()
+}
+
+fn current_thread() -> Runtime {
+    Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+/// Runs `run` on `workload` as a task, as the demo does, and returns what its tasks
+/// noted.
+fn traced<F>(runtime: Runtime, workload: Workload, run: impl FnOnce(Workload, Trace) -> F) -> Trace
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let trace = Trace::default();
+    let run = run(workload, trace.clone());
+    runtime.block_on(async { tokio::spawn(run).await }).unwrap();
+    let timeline = trace.recorder.timeline();
+    assert_eq!(timeline.events().len(), workload.tasks * workload.iterations, "{timeline}");
+    trace
+}
+
+/// The same, for just when each iteration started.
+fn timeline<F>(runtime: Runtime, workload: Workload, run: impl FnOnce(Workload, Trace) -> F) -> Timeline
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    traced(runtime, workload, run).recorder.timeline()
+}
+
+#[test]
+fn test_blocking_inline_serializes_the_tasks_on_either_runtime() {
+    for (label, runtime) in [("multithread", multithread()), ("current_thread", current_thread())] {
+        let timeline = timeline(runtime, Workload::default(), |workload, trace| run_inline(label, workload, trace));
+        timeline.serialized().unwrap_or_else(|e| panic!("{label}: {e}"));
+        assert!(timeline.in_step(WINDOW).is_err(), "{label}:\n{timeline}");
+        // The last task only started after the other two had done all their sleeping.
+        let Workload { tasks, iterations, work, .. } = Workload::default();
+        assert!(timeline.elapsed() >= work * (iterations * tasks - 1) as u32, "{label}:\n{timeline}");
+    }
+}
+
+#[test]
+fn test_async_sleep_keeps_the_tasks_in_step() {
+    for (label, runtime) in [("multithread", multithread()), ("current_thread", current_thread())] {
+        let timeline = timeline(runtime, Workload::default(), |workload, trace| run_async_sleep(label, workload, trace));
+        timeline.in_step(WINDOW).unwrap_or_else(|e| panic!("{label}: {e}"));
+        assert!(timeline.serialized().is_err(), "{label}:\n{timeline}");
+    }
+}
+
+#[test]
+fn test_spawn_blocking_keeps_the_tasks_in_step() {
+    for (label, runtime) in [("multithread", multithread()), ("current_thread", current_thread())] {
+        let timeline = timeline(runtime, Workload::default(), |workload, trace| run_spawn_blocking(label, workload, trace));
+        timeline.in_step(WINDOW).unwrap_or_else(|e| panic!("{label}: {e}"));
+        assert!(timeline.serialized().is_err(), "{label}:\n{timeline}");
+    }
+}
+
+#[test]
+fn test_block_in_place_still_serializes_the_tasks_joined_with_it() {
+    let timeline = timeline(multithread(), Workload::default(), |workload, trace| run_block_in_place("multithread", workload, trace));
+    timeline.serialized().unwrap_or_else(|e| panic!("{e}"));
+}
+
+#[test]
+fn test_block_in_place_panics_on_current_thread() {
+    let run = run_block_in_place("current_thread", Workload::default(), Trace::default());
+    let e = current_thread().block_on(async { tokio::spawn(run).await }).unwrap_err();
+    assert!(e.is_panic(), "{e}");
+}
+
+#[test]
+fn test_cpu_work_inline_serializes_the_tasks_too() {
+    let timeline = timeline(current_thread(), Workload::default().cpu(), |workload, trace| run_inline("current_thread", workload, trace));
+    timeline.serialized().unwrap_or_else(|e| panic!("{e}"));
+}
+
+#[test]
+fn test_rayon_takes_the_blocking_off_the_runtime() {
+    // However few cores rayon has to queue the work for, every task gets to hand its
+    // first piece over straight away.
+    let timeline = timeline(current_thread(), Workload::default().cpu(), |workload, trace| run_rayon("current_thread", workload, trace));
+    assert!(timeline.serialized().is_err(), "{timeline}");
+}
+
+#[test]
+fn test_a_run_exports_a_row_per_iteration() {
+    let timeline = timeline(current_thread(), Workload::default(), |workload, trace| run_inline("current_thread", workload, trace));
+    let events = TimingEvent::from_timeline(Flavor::CurrentThread, Strategy::Inline, &timeline);
+    let mut csv = Vec::new();
+    write_csv(&events, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let rows: Vec<&str> = csv.lines().skip(1).collect();
+    let Workload { tasks, iterations, .. } = Workload::default();
+    assert_eq!(rows.len(), tasks * iterations, "{csv}");
+    // Serialized: task 0's iterations first, straight away.
+    assert!(rows[0].starts_with("current_thread,1,inline,0,0,"), "{csv}");
+    assert!(rows[iterations - 1].starts_with("current_thread,1,inline,0,2,"), "{csv}");
+}
+
+#[test]
+fn test_more_tasks_than_workers_still_keep_in_step_with_async_sleep() {
+    let workload = Workload { tasks: 16, iterations: 2, work: Duration::from_millis(30), ..Workload::default() };
+    let timeline = timeline(multithread(), workload, |workload, trace| run_async_sleep("multithread", workload, trace));
+    timeline.in_step(WINDOW).unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(timeline.tasks().len(), 16);
+}
+
+#[test]
+fn test_inline_blocking_is_all_on_the_one_worker() {
+    let trace = traced(current_thread(), Workload::default(), |workload, trace| run_inline("current_thread", workload, trace));
+    let spans = trace.activity.spans();
+    assert_eq!(spans.len(), 9);
+    assert!(spans.iter().all(|busy| busy.place == Place::Worker && busy.thread == spans[0].thread), "{spans:?}");
+}
+
+#[test]
+fn test_spawn_blocking_blocks_on_threads_other_than_the_worker() {
+    let trace = traced(current_thread(), Workload::default(), |workload, trace| run_spawn_blocking("current_thread", workload, trace));
+    let spans = trace.activity.spans();
+    let workers: HashSet<_> = spans.iter().filter(|busy| busy.place == Place::Worker).map(|busy| busy.thread).collect();
+    let pool: HashSet<_> = spans.iter().filter(|busy| busy.place == Place::BlockingPool).map(|busy| busy.thread).collect();
+    assert_eq!(workers.len(), 1, "{spans:?}");
+    assert!(workers.is_disjoint(&pool), "{spans:?}");
+    // The three tasks block at once, so on three threads.
+    assert!(pool.len() >= 3, "{spans:?}");
+}
+
+#[test]
+fn test_a_full_blocking_pool_queues_the_rest() {
+    let runtime = Builder::new_multi_thread().worker_threads(2).max_blocking_threads(2).enable_all().build().unwrap();
+    let activity = Activity::new();
+    let workload = Workload { work: Duration::from_millis(30), ..Workload::default() };
+    let queueing = runtime.block_on(flood(6, workload, &activity));
+    // Two at a time: the last pair waits for two pairs before it.
+    assert_eq!(queueing.queued(), 4, "{queueing:?}");
+    assert!(queueing.longest() >= Duration::from_millis(60), "{queueing:?}");
+    let threads: HashSet<_> = activity.spans().iter().map(|busy| busy.thread).collect();
+    assert_eq!(threads.len(), 2);
+}
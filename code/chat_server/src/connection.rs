@@ -0,0 +1,180 @@
+//! One chat client: read its lines, forward its room's messages, and react to
+//! shutdown, all from a single `select!` loop.
+
+use crate::rooms::{Message, Rooms};
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// The room every client starts in.
+pub(crate) const LOBBY: &str = "lobby";
+
+/// What a line from the client asks for.
+#[derive(Debug, PartialEq, Eq)]
+enum Command<'a> {
+    Say(&'a str),
+    Nick(&'a str),
+    Join(&'a str),
+    Quit,
+    Unknown(&'a str),
+}
+
+impl<'a> Command<'a> {
+    fn parse(line: &'a str) -> Self {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix('/') else {
+            return Command::Say(line);
+        };
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("nick"), Some(nick), None) => Command::Nick(nick),
+            (Some("join"), Some(room), None) => Command::Join(room.trim_start_matches('#')),
+            (Some("quit"), None, None) => Command::Quit,
+            _ => Command::Unknown(line),
+        }
+    }
+}
+
+/// The room a client is currently in.
+struct Membership {
+    room: String,
+    tx: broadcast::Sender<Message>,
+    rx: broadcast::Receiver<Message>,
+}
+
+impl Membership {
+    fn join(rooms: &Rooms, room: &str) -> Self {
+        let (tx, rx) = rooms.join(room);
+        Self {
+            room: room.to_string(),
+            tx,
+            rx,
+        }
+    }
+
+    fn leave(self, rooms: &Rooms) {
+        rooms.leave(&self.room, self.rx);
+    }
+
+    fn announce(&self, from: u64, text: String) {
+        // Only fails if nobody is subscribed, and we are.
+        let _ = self.tx.send(Message { from, text: text.into() });
+    }
+}
+
+/// Runs one client until it quits, hangs up, or the server shuts down.
+pub(crate) async fn handle_client(
+    socket: TcpStream,
+    id: u64,
+    peer: SocketAddr,
+    rooms: &Rooms,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let mut nick = format!("guest{id}");
+    let mut member = Membership::join(rooms, LOBBY);
+    member.announce(id, format!("*** {nick} joined #{LOBBY}"));
+    println!("[server] conn={id} peer={peer} joined #{LOBBY} as {nick}");
+
+    // The chat loop returns early on I/O errors; leaving the room has to happen either
+    // way, so it's done out here rather than before each `return`.
+    let result = chat(socket, id, &mut nick, &mut member, rooms, &mut shutdown_rx).await;
+    member.announce(id, format!("*** {nick} left"));
+    member.leave(rooms);
+    result
+}
+
+async fn chat(
+    socket: TcpStream,
+    id: u64,
+    nick: &mut String,
+    member: &mut Membership,
+    rooms: &Rooms,
+    shutdown_rx: &mut broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    send_line(&mut writer, &format!("*** welcome {nick}, you're in #{LOBBY} (/nick NAME, /join ROOM, /quit)")).await?;
+
+    loop {
+        tokio::select! {
+            // Shutdown first: once it's fired, a goodbye from someone else's task
+            // shouldn't be written ahead of our own.
+            biased;
+            _ = shutdown_rx.recv() => {
+                send_line(&mut writer, "*** server shutting down").await?;
+                return writer.shutdown().await;
+            }
+            // Both `broadcast::Receiver::recv` and `Lines::next_line` are cancel safe,
+            // so whichever branch loses the race hasn't consumed anything.
+            received = member.rx.recv() => match received {
+                Ok(message) if message.from == id => {}
+                Ok(message) => send_line(&mut writer, &message.text).await?,
+                // This client's task fell more than a room buffer behind (usually
+                // because the client isn't reading and our writes are blocked), and the
+                // oldest messages were overwritten. The receiver has already skipped
+                // ahead to the oldest one that's left; all we can do is say so.
+                Err(RecvError::Lagged(missed)) => {
+                    println!("[server] conn={id} lagged, skipped {missed} message(s) in #{}", member.room);
+                    send_line(&mut writer, &format!("*** you fell behind and missed {missed} message(s)")).await?;
+                }
+                // We hold a sender for the room ourselves, so it can't close under us.
+                Err(RecvError::Closed) => unreachable!("room closed while a member holds its sender"),
+            },
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+                match Command::parse(&line) {
+                    Command::Say("") => {}
+                    Command::Say(text) => member.announce(id, format!("{nick}: {text}")),
+                    Command::Nick(new) => {
+                        member.announce(id, format!("*** {nick} is now {new}"));
+                        send_line(&mut writer, &format!("*** you are now {new}")).await?;
+                        *nick = new.to_string();
+                    }
+                    Command::Join(room) if room == member.room => {
+                        send_line(&mut writer, &format!("*** already in #{room}")).await?;
+                    }
+                    Command::Join(room) => {
+                        member.announce(id, format!("*** {nick} left for #{room}"));
+                        let old = std::mem::replace(member, Membership::join(rooms, room));
+                        old.leave(rooms);
+                        member.announce(id, format!("*** {nick} joined #{room}"));
+                        send_line(&mut writer, &format!("*** you're in #{room}")).await?;
+                        println!("[server] conn={id} moved to #{room}");
+                    }
+                    Command::Quit => {
+                        send_line(&mut writer, "*** bye").await?;
+                        return writer.shutdown().await;
+                    }
+                    Command::Unknown(line) => {
+                        send_line(&mut writer, &format!("*** unknown command: {line}")).await?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send_line(writer: &mut OwnedWriteHalf, line: &str) -> io::Result<()> {
+    writer.write_all(format!("{line}\n").as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("hello there\r"), Command::Say("hello there"));
+        assert_eq!(Command::parse("/nick alice"), Command::Nick("alice"));
+        assert_eq!(Command::parse("/join #rust"), Command::Join("rust"));
+        assert_eq!(Command::parse("/join rust"), Command::Join("rust"));
+        assert_eq!(Command::parse(" /quit "), Command::Quit);
+        assert_eq!(Command::parse("/nick two words"), Command::Unknown("/nick two words"));
+        assert_eq!(Command::parse("/dance"), Command::Unknown("/dance"));
+    }
+}
@@ -0,0 +1,119 @@
+//! A line-based chat server with rooms.
+//!
+//! Every room is a `tokio::sync::broadcast` channel. A client's task holds a sender
+//! and a receiver for its current room, and its `select!` loop races three things: a
+//! line from the client (say it to the room), a message from the room (write it to the
+//! client), and the shutdown signal. Fan-out is the channel's job; no task ever loops
+//! over the other members.
+//!
+//! The interesting failure is a slow reader. `broadcast` keeps a fixed number of
+//! messages per room and never waits for the slowest receiver: once a receiver falls
+//! further behind than that, the oldest messages are overwritten and its next `recv`
+//! returns `Lagged(n)`. That's the right trade for chat (one stalled client can't stall
+//! the room, and it can't make the server buffer without limit either) as long as the
+//! client is told what it missed, which is what the connection task does.
+//!
+//! Shutdown is the usual scaffolding: every connection subscribes to a
+//! `ShutdownController`, says goodbye when it fires, and the server waits up to
+//! `drain_timeout` for them to finish.
+
+mod connection;
+mod rooms;
+
+use rooms::Rooms;
+use shutdown_util::ShutdownController;
+use shutdown_util::backoff::AcceptBackoff;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct ChatConfig {
+    /// Messages each room buffers before its slowest members start missing them.
+    pub room_capacity: usize,
+    /// How long connections get to say goodbye after shutdown.
+    pub drain_timeout: Duration,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            room_capacity: 64,
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Serves chat clients accepted on `listener` until shutdown is signalled, then drains.
+pub async fn run_server(
+    listener: TcpListener,
+    config: ChatConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let rooms = Arc::new(Rooms::new(config.room_capacity));
+    let mut next_conn_id = 0_u64;
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[server] shutdown requested, no longer accepting");
+                break;
+            }
+            accepted = listener.accept() => {
+                let (socket, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let delay = backoff.on_error();
+                        eprintln!("[server] accept failed: {e}, retrying in {delay:?}");
+                        sleep(delay).await;
+                        continue;
+                    }
+                };
+                if let Some(failures) = backoff.on_success() {
+                    println!("[server] accepting again after {failures} failure(s)");
+                }
+                next_conn_id += 1;
+                let id = next_conn_id;
+                let rooms = rooms.clone();
+                let conn_shutdown = controller.subscribe();
+                controller.spawn(async move {
+                    match connection::handle_client(socket, id, peer, &rooms, conn_shutdown).await {
+                        Ok(()) => println!("[server] conn={id} peer={peer} closed ({} room(s) open)", rooms.len()),
+                        Err(e) => eprintln!("[server] conn={id} peer={peer} failed: {e}"),
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every client that ever left piling up, and leaves the drain only the ones
+            // still open.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[server] connection task join error: {e}");
+                }
+            }
+        }
+    }
+    drop(listener);
+
+    println!(
+        "[server] waiting up to {:?} for {} connection(s) to finish",
+        config.drain_timeout,
+        controller.active_tasks()
+    );
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[server] connection task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[server] drain deadline hit, aborted {} connection(s)", report.aborted);
+    } else {
+        println!("[server] all connections finished");
+    }
+    Ok(())
+}
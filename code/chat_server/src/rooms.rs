@@ -0,0 +1,99 @@
+//! The room registry: one `broadcast` channel per room, created on first join and
+//! dropped when the last member leaves.
+//!
+//! The map sits behind a `std::sync::Mutex`, not a Tokio one: it's only ever held for a
+//! lookup or an insert, never across an `.await`, so there's nothing for an async lock
+//! to buy us.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// One line of chat, as every member of the room will see it.
+#[derive(Debug, Clone)]
+pub(crate) struct Message {
+    /// The connection that sent it, so it isn't echoed back to its author.
+    pub(crate) from: u64,
+    /// `Arc<str>` because the channel clones the message once per receiver.
+    pub(crate) text: Arc<str>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Rooms {
+    capacity: usize,
+    rooms: Mutex<HashMap<String, broadcast::Sender<Message>>>,
+}
+
+impl Rooms {
+    /// `capacity` is how many messages each room buffers for its slowest member before
+    /// that member starts missing them.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            rooms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Joins `name`, creating it if this is its first member.
+    pub(crate) fn join(&self, name: &str) -> (broadcast::Sender<Message>, broadcast::Receiver<Message>) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let tx = rooms
+            .entry(name.to_string())
+            .or_insert_with(|| broadcast::channel(self.capacity).0);
+        (tx.clone(), tx.subscribe())
+    }
+
+    /// Leaves `name`, removing the room if `rx` was its last member.
+    ///
+    /// Taking the receiver by value means it's dropped while we hold the lock, so a
+    /// concurrent `join` either finds the room before we count its members or creates a
+    /// fresh one after we've removed it; it can't subscribe to a room we're deleting.
+    pub(crate) fn leave(&self, name: &str, rx: broadcast::Receiver<Message>) {
+        let mut rooms = self.rooms.lock().unwrap();
+        drop(rx);
+        if rooms.get(name).is_some_and(|tx| tx.receiver_count() == 0) {
+            rooms.remove(name);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.rooms.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> Message {
+        Message {
+            from: 1,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn test_members_of_a_room_share_one_channel() {
+        let rooms = Rooms::new(4);
+        let (tx, _rx_a) = rooms.join("lobby");
+        let (_, mut rx_b) = rooms.join("lobby");
+        let (_, mut rx_other) = rooms.join("other");
+        assert_eq!(rooms.len(), 2);
+
+        tx.send(message("hi")).unwrap();
+        assert_eq!(&*rx_b.try_recv().unwrap().text, "hi");
+        assert!(rx_other.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_room_is_removed_with_its_last_member() {
+        let rooms = Rooms::new(4);
+        let (_, rx_a) = rooms.join("lobby");
+        let (_, rx_b) = rooms.join("lobby");
+
+        rooms.leave("lobby", rx_a);
+        assert_eq!(rooms.len(), 1);
+        rooms.leave("lobby", rx_b);
+        assert_eq!(rooms.len(), 0);
+    }
+}
@@ -0,0 +1,128 @@
+use chat_server::{ChatConfig, run_server};
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+async fn start_server(config: ChatConfig) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    spawn_server(|listener, controller, shutdown_rx| run_server(listener, config, controller, shutdown_rx)).await
+}
+
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Client {
+    /// Connects and waits for the welcome, after which the client is in the lobby.
+    async fn connect(addr: SocketAddr) -> Self {
+        Self::from_stream(TcpStream::connect(addr).await.unwrap()).await
+    }
+
+    async fn from_stream(socket: TcpStream) -> Self {
+        let (reader, writer) = socket.into_split();
+        let mut client = Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        };
+        assert!(client.line().await.unwrap().starts_with("*** welcome"));
+        client
+    }
+
+    async fn say(&mut self, line: &str) {
+        self.writer.write_all(format!("{line}\n").as_bytes()).await.unwrap();
+    }
+
+    async fn line(&mut self) -> Option<String> {
+        timeout(Duration::from_secs(5), self.lines.next_line())
+            .await
+            .expect("timed out waiting for a line")
+            .unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_lines_reach_everyone_else_in_the_room() {
+    let (addr, trigger, task) = start_server(ChatConfig::default()).await;
+    let mut a = Client::connect(addr).await;
+    let mut b = Client::connect(addr).await;
+    assert_eq!(a.line().await.unwrap(), "*** guest2 joined #lobby");
+    let mut c = Client::connect(addr).await;
+    assert_eq!(a.line().await.unwrap(), "*** guest3 joined #lobby");
+    assert_eq!(b.line().await.unwrap(), "*** guest3 joined #lobby");
+    c.say("/join #other").await;
+    assert_eq!(c.line().await.unwrap(), "*** you're in #other");
+    assert_eq!(a.line().await.unwrap(), "*** guest3 left for #other");
+    assert_eq!(b.line().await.unwrap(), "*** guest3 left for #other");
+
+    a.say("hi").await;
+    assert_eq!(b.line().await.unwrap(), "guest1: hi");
+    // Nothing is echoed to its author: the next thing `a` sees is `b`'s reply.
+    b.say("yo").await;
+    assert_eq!(a.line().await.unwrap(), "guest2: yo");
+    // And nothing leaks into other rooms.
+    c.say("/nick carol").await;
+    assert_eq!(c.line().await.unwrap(), "*** you are now carol");
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_slow_client_is_told_what_it_missed() {
+    let config = ChatConfig {
+        room_capacity: 4,
+        ..ChatConfig::default()
+    };
+    let (addr, trigger, task) = start_server(config).await;
+
+    // A small receive buffer so the server's writes to this client block sooner.
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.set_recv_buffer_size(4096).unwrap();
+    let mut slow = Client::from_stream(socket.connect(addr).await.unwrap()).await;
+    let mut chatty = Client::connect(addr).await;
+
+    // Far more than the socket buffers hold, so the slow client's task stalls on a
+    // write while the room moves on without it.
+    let line = "x".repeat(32 * 1024);
+    for _ in 0..300 {
+        chatty.say(&line).await;
+    }
+    // Once this is answered the server has broadcast every line above.
+    chatty.say("/nick done").await;
+    assert_eq!(chatty.line().await.unwrap(), "*** you are now done");
+
+    let mut received = 0;
+    loop {
+        let line = slow.line().await.unwrap();
+        if line.starts_with("*** you fell behind and missed ") {
+            break;
+        }
+        received += 1;
+    }
+    assert!(received < 300, "got all {received} lines, expected to lag");
+
+    drop(slow);
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_says_goodbye_and_closes() {
+    let (addr, trigger, task) = start_server(ChatConfig::default()).await;
+    let mut a = Client::connect(addr).await;
+    let mut b = Client::connect(addr).await;
+    assert_eq!(a.line().await.unwrap(), "*** guest2 joined #lobby");
+
+    trigger.trigger();
+    for client in [&mut a, &mut b] {
+        assert_eq!(client.line().await.unwrap(), "*** server shutting down");
+        assert_eq!(client.line().await, None);
+    }
+    timeout(Duration::from_secs(2), task).await.unwrap().unwrap().unwrap();
+}
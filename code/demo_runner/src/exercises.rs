@@ -0,0 +1,50 @@
+//! The exercises in `exercises/`, and checking them in order.
+//!
+//! They're a workspace of their own, next to this one rather than in it: their tests
+//! fail until someone has done the exercises, and the main workspace's tests have to
+//! pass. `info.toml` there says which order to take them in and holds a hint for each,
+//! shown when its tests fail.
+
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// One exercise: a crate in the exercises workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Exercise {
+    /// The package name, and its directory.
+    pub name: String,
+    pub hint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Info {
+    exercises: Vec<Exercise>,
+}
+
+/// Where the exercises live in the workspace at `root`.
+pub fn dir(root: &Path) -> PathBuf {
+    root.join("exercises")
+}
+
+/// The exercises in `dir`, in the order to do them.
+pub fn load(dir: &Path) -> io::Result<Vec<Exercise>> {
+    let path = dir.join("info.toml");
+    let text = std::fs::read_to_string(&path).map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let info: Info = toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", path.display())))?;
+    Ok(info.exercises)
+}
+
+/// `cargo test` for one exercise, with its output captured to show only on failure.
+pub fn cargo_test(dir: &Path, exercise: &Exercise) -> Command {
+    let mut command = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    command
+        .current_dir(dir)
+        .args(["test", "--quiet", "-p", &exercise.name])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
@@ -0,0 +1,39 @@
+//! The workshop's examples from one place: what there is, and a way to run any of them
+//! without `cd`-ing between crates or remembering which binary is which.
+//!
+//! [`workspace`] finds the demos, [`pair`] runs a server and its client together, and
+//! [`exercises`] finds the exercises that `verify` checks.
+//! Everything else is `cargo run`, run from the workspace root, so the runner builds
+//! whatever it's asked to run and the demos see the paths they'd see if run by hand.
+
+pub mod exercises;
+pub mod pair;
+pub mod workspace;
+
+pub use pair::{Ended, PairOutcome, Teardown, run_pair};
+pub use workspace::{Demo, Pair, default_root, discover, find};
+
+use std::path::Path;
+use tokio::process::Command;
+
+/// `cargo run` for one binary of `package`, from `root`, with `args` for the binary.
+pub fn cargo_run(root: &Path, package: &str, bin: &str, release: bool, args: &[String]) -> Command {
+    let mut command = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    command.current_dir(root).args(["run", "--quiet", "-p", package, "--bin", bin]);
+    if release {
+        command.arg("--release");
+    }
+    command.arg("--").args(args);
+    command
+}
+
+/// `cargo build` for every binary of `package`, so a pair's two `cargo run`s don't
+/// build at the same time.
+pub fn cargo_build(root: &Path, package: &str, release: bool) -> Command {
+    let mut command = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    command.current_dir(root).args(["build", "-p", package, "--bins"]);
+    if release {
+        command.arg("--release");
+    }
+    command
+}
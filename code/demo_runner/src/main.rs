@@ -0,0 +1,244 @@
+use clap::{Parser, Subcommand};
+use demo_runner::{Demo, Ended, Teardown, cargo_build, cargo_run, default_root, discover, exercises, find, run_pair};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Lists the workshop's examples and runs them from anywhere in the workspace.
+///
+/// `demo_runner list` shows what there is; `demo_runner run tcp_server_graceful_shutdown
+/// -- --bind 127.0.0.1:4000` runs one, with everything after `--` for the demo; and
+/// `demo_runner run tcp_server_graceful_shutdown --pair` starts its server, runs its
+/// client against it, and shuts the server down gracefully when the client is done.
+/// `demo_runner verify` checks the exercises, in order, as far as they pass.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// The workspace to find the demos in; the one the runner was built in by default.
+    #[arg(long, value_name = "DIR", global = true)]
+    workspace: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Cmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// List the demos, with what each is for.
+    List,
+    /// Run a demo, or with `--pair` its server and client together.
+    Run {
+        /// The demo's package name, as `list` shows it.
+        demo: String,
+        /// Which of its binaries, if it has more than one.
+        #[arg(long, conflicts_with = "pair")]
+        bin: Option<String>,
+        /// Run its server and then its client, and stop the server once the client is
+        /// done.
+        #[arg(long)]
+        pair: bool,
+        /// With --pair, an argument for the client; repeat it for more.
+        #[arg(long = "client-arg", value_name = "ARG", allow_hyphen_values = true, requires = "pair")]
+        client_args: Vec<String>,
+        /// With --pair, where to wait for the server instead of the address its manifest
+        /// names, for when the arguments move it.
+        #[arg(long, value_name = "ADDR", requires = "pair")]
+        ready: Option<String>,
+        /// With --pair, seconds each process gets to exit after Ctrl-C before it's
+        /// killed.
+        #[arg(long, value_name = "SECS", default_value = "10", value_parser = parse_secs)]
+        grace: Duration,
+        /// Build and run with optimisations.
+        #[arg(long)]
+        release: bool,
+        /// Arguments for the demo, or with --pair for its server.
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Run the exercises' tests in order, stopping at the first that fails.
+    Verify {
+        /// Carry on past failures and report on every exercise.
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let root = cli.workspace.unwrap_or_else(default_root);
+    if let Cmd::Verify { all } = cli.command {
+        return verify(&root, all).await;
+    }
+    let demos = match discover(&root) {
+        Ok(demos) => demos,
+        Err(e) => {
+            eprintln!("[main] can't read the workspace: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match cli.command {
+        Cmd::List => {
+            list(&demos);
+            ExitCode::SUCCESS
+        }
+        Cmd::Run { demo, bin, pair, client_args, ready, grace, release, args } => {
+            let demo = match find(&demos, &demo) {
+                Ok(demo) => demo,
+                Err(e) => {
+                    eprintln!("[main] {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            if pair {
+                run_both(&root, demo, &args, &client_args, ready, grace, release).await
+            } else {
+                run_one(&root, demo, bin, &args, release).await
+            }
+        }
+        Cmd::Verify { .. } => unreachable!("handled before looking for demos"),
+    }
+}
+
+fn list(demos: &[Demo]) {
+    let width = demos.iter().map(|demo| demo.name.len()).max().unwrap_or(0);
+    for demo in demos {
+        let kind = match (&demo.pair, demo.is_library()) {
+            (Some(_), _) => "pair",
+            (None, true) => "lib",
+            (None, false) => "",
+        };
+        println!("{:width$}  {kind:4}  {}", demo.name, demo.description.as_deref().unwrap_or("-"));
+    }
+    println!("\n`pair`: `run --pair` starts its server and client together; `lib`: no binary, `cargo test -p` it instead");
+}
+
+async fn run_one(root: &Path, demo: &Demo, bin: Option<String>, args: &[String], release: bool) -> ExitCode {
+    if demo.is_library() {
+        eprintln!("[main] {} is a library: its tests are the demo, `cargo test -p {}`", demo.name, demo.name);
+        return ExitCode::FAILURE;
+    }
+    let Some(bin) = bin.or_else(|| demo.default_bin.clone()) else {
+        eprintln!("[main] {} has more than one binary, pick one with --bin: {}", demo.name, demo.bins.join(", "));
+        return ExitCode::FAILURE;
+    };
+    if !demo.bins.contains(&bin) {
+        eprintln!("[main] {} has no binary called {bin}: {}", demo.name, demo.bins.join(", "));
+        return ExitCode::FAILURE;
+    }
+    println!("[main] running {} ({bin})", demo.name);
+    // The demo shares our terminal, so a Ctrl-C reaches it too and it gets to shut down
+    // by itself; we only wait to pass its exit status on.
+    let mut child = match cargo_run(root, &demo.name, &bin, release, args).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("[main] can't run cargo: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    loop {
+        tokio::select! {
+            status = child.wait() => return match status {
+                Ok(status) if status.success() => ExitCode::SUCCESS,
+                Ok(status) => {
+                    eprintln!("[main] {} exited with {status}", demo.name);
+                    ExitCode::FAILURE
+                }
+                Err(e) => {
+                    eprintln!("[main] lost track of {}: {e}", demo.name);
+                    ExitCode::FAILURE
+                }
+            },
+            Ok(()) = tokio::signal::ctrl_c() => println!("[main] waiting for {} to shut down", demo.name),
+        }
+    }
+}
+
+async fn run_both(root: &Path, demo: &Demo, server_args: &[String], client_args: &[String], ready: Option<String>, grace: Duration, release: bool) -> ExitCode {
+    let Some(pair) = &demo.pair else {
+        eprintln!("[main] {} has no client to pair with; `list` marks the ones that do", demo.name);
+        return ExitCode::FAILURE;
+    };
+    let ready = ready.unwrap_or_else(|| pair.ready.clone());
+    // Both at once, now, rather than the two `cargo run`s building side by side.
+    match cargo_build(root, &demo.name, release).status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("[main] building {} failed ({status})", demo.name);
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("[main] can't run cargo: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    println!("[main] starting {}, then {} once {ready} is listening; Ctrl-C stops both", pair.server, pair.client);
+    let server = cargo_run(root, &demo.name, &pair.server, release, server_args);
+    let client = cargo_run(root, &demo.name, &pair.client, release, client_args);
+    let teardown = Teardown { grace, ..Teardown::default() };
+    let interrupt = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        println!("[main] stopping the client, then the server");
+    };
+    match run_pair(server, client, &ready, teardown, interrupt).await {
+        Ok(outcome) => {
+            println!("[main] client: {}, server: {}", describe(outcome.client), describe(outcome.server));
+            let clean = |ended| matches!(ended, Ended::Exited(status) if status.success());
+            if clean(outcome.client) && clean(outcome.server) { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+        }
+        Err(e) => {
+            eprintln!("[main] {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn describe(ended: Ended) -> String {
+    match ended {
+        Ended::Exited(status) => status.to_string(),
+        Ended::Killed => "killed".to_string(),
+        Ended::NotStarted => "never started".to_string(),
+    }
+}
+
+async fn verify(root: &Path, all: bool) -> ExitCode {
+    let dir = exercises::dir(root);
+    let exercises = match exercises::load(&dir) {
+        Ok(exercises) => exercises,
+        Err(e) => {
+            eprintln!("[main] can't read the exercises: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut done = 0;
+    for exercise in &exercises {
+        let output = match exercises::cargo_test(&dir, exercise).output().await {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("[main] can't run cargo: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        if output.status.success() {
+            done += 1;
+            println!("[main] done      {}", exercise.name);
+            continue;
+        }
+        println!("[main] not yet   {}", exercise.name);
+        if !all {
+            print!("{}", String::from_utf8_lossy(&output.stdout));
+            eprint!("{}", String::from_utf8_lossy(&output.stderr));
+            println!("[main] hint: {}", exercise.hint);
+            println!("[main] edit {}, then run verify again", dir.join(&exercise.name).join("src/lib.rs").display());
+            break;
+        }
+    }
+    let total = exercises.len();
+    println!("[main] [{}{}] {done}/{total} exercises done", "#".repeat(done), "-".repeat(total - done));
+    if done == total { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
@@ -0,0 +1,155 @@
+//! Running a server and its client together, and taking both down in the right order.
+//!
+//! The server starts first, and the client only once a connection to the server's
+//! `ready` address succeeds, so it doesn't race the bind (or a first build); the server
+//! sees that probe as a connection that closes straight away. When the client is done
+//! the server is asked to stop the way a person would ask it, with `SIGINT`, and given `grace` to drain before it's killed; that's the graceful shutdown
+//! the workshop is about, so it should get to run. A server that dies early takes the
+//! client with it, since there's nothing left for it to talk to.
+//!
+//! Both run in process groups of their own. A Ctrl-C at the terminal would otherwise
+//! reach all three processes at once, and the client and server would race each other
+//! to shut down; instead only the runner gets it, and stops the client and then the
+//! server, in that order, as if the client had finished. The price is that neither can
+//! read the terminal, so both get an empty stdin.
+
+use std::future::Future;
+use std::io;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::time::{Instant, sleep, timeout};
+
+/// How long each step may take.
+#[derive(Debug, Clone, Copy)]
+pub struct Teardown {
+    /// For the server to start listening, builds included.
+    pub ready_timeout: Duration,
+    /// For each process to exit after `SIGINT`, before it's killed.
+    pub grace: Duration,
+}
+
+impl Default for Teardown {
+    fn default() -> Self {
+        Self { ready_timeout: Duration::from_secs(120), grace: Duration::from_secs(10) }
+    }
+}
+
+/// How one of the two processes ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ended {
+    /// By itself, or after `SIGINT`.
+    Exited(ExitStatus),
+    /// Still running after `grace`.
+    Killed,
+    /// Never started: the server didn't come up.
+    NotStarted,
+}
+
+/// How a pair ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairOutcome {
+    pub server: Ended,
+    pub client: Ended,
+}
+
+/// Runs `server`, then `client` once `ready` accepts connections, and stops the server
+/// when the client is done or when `interrupt` fires. An error means the server
+/// couldn't be started or didn't come up; it's been stopped by then.
+pub async fn run_pair(mut server: Command, mut client: Command, ready: &str, teardown: Teardown, interrupt: impl Future<Output = ()>) -> io::Result<PairOutcome> {
+    tokio::pin!(interrupt);
+    let mut server = spawn(&mut server)?;
+
+    let came_up = tokio::select! {
+        up = wait_ready(ready, teardown.ready_timeout) => up,
+        status = server.wait() => {
+            let status = status?;
+            return Err(io::Error::other(format!("the server exited ({status}) before listening on {ready}")));
+        }
+        () = &mut interrupt => return Ok(PairOutcome { server: stop(&mut server, teardown.grace).await?, client: Ended::NotStarted }),
+    };
+    if let Err(e) = came_up {
+        stop(&mut server, teardown.grace).await?;
+        return Err(e);
+    }
+
+    let mut client = match spawn(&mut client) {
+        Ok(client) => client,
+        Err(e) => {
+            stop(&mut server, teardown.grace).await?;
+            return Err(e);
+        }
+    };
+    tokio::select! {
+        status = client.wait() => {
+            let client = Ended::Exited(status?);
+            Ok(PairOutcome { server: stop(&mut server, teardown.grace).await?, client })
+        }
+        status = server.wait() => {
+            let server = Ended::Exited(status?);
+            client.start_kill()?;
+            client.wait().await?;
+            Ok(PairOutcome { server, client: Ended::Killed })
+        }
+        () = &mut interrupt => {
+            let client = stop(&mut client, teardown.grace).await?;
+            Ok(PairOutcome { server: stop(&mut server, teardown.grace).await?, client })
+        }
+    }
+}
+
+fn spawn(command: &mut Command) -> io::Result<Child> {
+    command.stdin(Stdio::null()).kill_on_drop(true);
+    #[cfg(unix)]
+    command.process_group(0);
+    command.spawn()
+}
+
+/// Tries to connect to `addr` until it works, or `limit` runs out.
+async fn wait_ready(addr: &str, limit: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + limit;
+    loop {
+        match TcpStream::connect(addr).await {
+            Ok(_) => return Ok(()),
+            Err(e) if Instant::now() >= deadline => {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("nothing listening on {addr} after {limit:?}: {e}")));
+            }
+            Err(_) => sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+/// Asks `child` to exit with `SIGINT`, and kills it if it hasn't within `grace`.
+async fn stop(child: &mut Child, grace: Duration) -> io::Result<Ended> {
+    if let Some(status) = child.try_wait()? {
+        return Ok(Ended::Exited(status));
+    }
+    interrupt(child)?;
+    match timeout(grace, child.wait()).await {
+        Ok(status) => Ok(Ended::Exited(status?)),
+        Err(_) => {
+            child.start_kill()?;
+            child.wait().await?;
+            Ok(Ended::Killed)
+        }
+    }
+}
+
+/// Sends `child` a Ctrl-C.
+#[cfg(unix)]
+fn interrupt(child: &mut Child) -> io::Result<()> {
+    let Some(pid) = child.id() else {
+        // Already reaped, so there's nobody to tell.
+        return Ok(());
+    };
+    let pid = libc::pid_t::try_from(pid).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "pid out of range"))?;
+    // SAFETY: `kill` takes plain integers and touches no memory of ours.
+    if unsafe { libc::kill(pid, libc::SIGINT) } == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Without Unix signals there's no asking nicely, so it's killed straight away.
+#[cfg(not(unix))]
+fn interrupt(child: &mut Child) -> io::Result<()> {
+    child.start_kill()
+}
@@ -0,0 +1,164 @@
+//! Finding the demos: every member of the workspace, what it's for and what it runs.
+//!
+//! Nothing is listed by hand. The members come from the workspace's `Cargo.toml`, each
+//! one's binaries from its own manifest and its layout (`src/main.rs` and `src/bin/*.rs`,
+//! as cargo finds them), and its description from `description` in the manifest or,
+//! failing that, the first sentence of its crate docs. A crate that has a server and a
+//! client to go with it says so in its manifest:
+//!
+//! ```toml
+//! [package.metadata.demo]
+//! server = "tcp_server_graceful_shutdown"
+//! client = "client"
+//! ready = "127.0.0.1:3011"
+//! ```
+//!
+//! where `ready` is the address the server listens on, so the client can wait for it.
+
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One member of the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Demo {
+    /// The package name, what `cargo -p` takes.
+    pub name: String,
+    pub description: Option<String>,
+    /// Its binaries; none for a library, whose tests are the demo.
+    pub bins: Vec<String>,
+    /// The binary `cargo run -p` would pick, if it can pick one.
+    pub default_bin: Option<String>,
+    /// A server and client to run together, if it has them.
+    pub pair: Option<Pair>,
+}
+
+/// A server binary, a client binary for it, and where the server listens.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Pair {
+    pub server: String,
+    pub client: String,
+    /// `host:port`; the client starts once a connection to it succeeds.
+    pub ready: String,
+}
+
+impl Demo {
+    pub fn is_library(&self) -> bool {
+        self.bins.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    package: Option<Package>,
+    #[serde(default)]
+    bin: Vec<BinTarget>,
+    workspace: Option<WorkspaceSection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    name: String,
+    description: Option<String>,
+    #[serde(rename = "default-run")]
+    default_run: Option<String>,
+    metadata: Option<Metadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    demo: Option<Pair>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BinTarget {
+    name: String,
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceSection {
+    members: Vec<String>,
+}
+
+/// The workspace this runner was built in, which is the one it's normally run from.
+pub fn default_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().expect("the runner is inside the workspace").to_path_buf()
+}
+
+/// Every member of the workspace at `root`, sorted by name.
+pub fn discover(root: &Path) -> io::Result<Vec<Demo>> {
+    let workspace = read_manifest(&root.join("Cargo.toml"))?
+        .workspace
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} has no [workspace]", root.display())))?;
+    let mut demos = workspace.members.iter().map(|member| load(&root.join(member))).collect::<io::Result<Vec<_>>>()?;
+    demos.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(demos)
+}
+
+/// The one called `name`, with what there is to choose from if it isn't there.
+pub fn find<'a>(demos: &'a [Demo], name: &str) -> Result<&'a Demo, String> {
+    demos.iter().find(|demo| demo.name == name).ok_or_else(|| {
+        let names: Vec<_> = demos.iter().map(|demo| demo.name.as_str()).collect();
+        format!("no demo called {name}; there's {}", names.join(", "))
+    })
+}
+
+fn read_manifest(path: &Path) -> io::Result<Manifest> {
+    let text = std::fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", path.display())))
+}
+
+fn load(dir: &Path) -> io::Result<Demo> {
+    let manifest = read_manifest(&dir.join("Cargo.toml"))?;
+    let package = manifest
+        .package
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} has no [package]", dir.display())))?;
+
+    // As cargo does it: the [[bin]]s, and then the files it finds by itself unless a
+    // [[bin]] has claimed them.
+    let claimed: Vec<PathBuf> = manifest.bin.iter().filter_map(|bin| bin.path.clone()).collect();
+    let mut bins: Vec<String> = manifest.bin.iter().map(|bin| bin.name.clone()).collect();
+    if dir.join("src/main.rs").is_file() && !claimed.iter().any(|path| path == Path::new("src/main.rs")) && !bins.contains(&package.name) {
+        bins.push(package.name.clone());
+    }
+    if let Ok(entries) = std::fs::read_dir(dir.join("src/bin")) {
+        let mut found: Vec<String> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+            .filter(|path| !claimed.iter().any(|claimed| dir.join(claimed) == *path))
+            .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+            .collect();
+        found.sort();
+        bins.extend(found);
+    }
+    let default_bin = match package.default_run {
+        Some(bin) => Some(bin),
+        None if bins.len() == 1 => bins.first().cloned(),
+        None => None,
+    };
+    let description = package.description.or_else(|| crate_docs(dir));
+    let pair = package.metadata.and_then(|metadata| metadata.demo);
+    Ok(Demo { name: package.name, description, bins, default_bin, pair })
+}
+
+/// The first sentence of the crate docs, from `src/main.rs` or else `src/lib.rs`.
+fn crate_docs(dir: &Path) -> Option<String> {
+    ["src/main.rs", "src/lib.rs"].iter().find_map(|file| {
+        let source = std::fs::read_to_string(dir.join(file)).ok()?;
+        let paragraph: Vec<&str> = source
+            .lines()
+            .skip_while(|line| !line.starts_with("//!"))
+            .map_while(|line| line.strip_prefix("//!"))
+            .map(str::trim)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        let paragraph = paragraph.join(" ");
+        let sentence = match paragraph.find(". ") {
+            Some(end) => &paragraph[..=end],
+            None => &paragraph,
+        };
+        (!sentence.is_empty()).then(|| sentence.to_string())
+    })
+}
@@ -0,0 +1,21 @@
+use demo_runner::{default_root, exercises};
+
+#[test]
+fn test_every_exercise_is_a_crate_in_the_exercises_workspace() {
+    let dir = exercises::dir(&default_root());
+    let exercises = exercises::load(&dir).unwrap();
+    assert!(!exercises.is_empty());
+
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    for exercise in &exercises {
+        assert!(dir.join(&exercise.name).join("src/lib.rs").is_file(), "{} has no src/lib.rs", exercise.name);
+        assert!(dir.join(&exercise.name).join("tests").is_dir(), "{} has no tests", exercise.name);
+        assert!(manifest.contains(&format!("\"{}\"", exercise.name)), "{} isn't a member", exercise.name);
+        assert!(!exercise.hint.is_empty(), "{} has no hint", exercise.name);
+    }
+    // Numbered in the order they're taken.
+    let names: Vec<_> = exercises.iter().map(|exercise| exercise.name.as_str()).collect();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+}
@@ -0,0 +1,82 @@
+#![cfg(unix)]
+
+use demo_runner::{Ended, Teardown, run_pair};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::process::Command;
+use tokio::time::{Instant, timeout};
+
+/// Stands in for a demo: `sh -c script`.
+fn sh(script: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.args(["-c", script]);
+    command
+}
+
+/// Something listening, so the server counts as up straight away.
+async fn listening() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    (listener, addr)
+}
+
+fn teardown(grace: Duration) -> Teardown {
+    Teardown { ready_timeout: Duration::from_secs(5), grace }
+}
+
+#[tokio::test]
+async fn test_the_server_is_interrupted_once_the_client_is_done() {
+    let (_listener, addr) = listening().await;
+    // `exec`, so the Ctrl-C reaches `sleep`, which dies of it.
+    let started = Instant::now();
+    let outcome = run_pair(sh("exec sleep 30"), sh("exit 0"), &addr, teardown(Duration::from_secs(10)), std::future::pending()).await.unwrap();
+
+    assert!(matches!(outcome.client, Ended::Exited(status) if status.success()), "{outcome:?}");
+    assert!(matches!(outcome.server, Ended::Exited(status) if !status.success()), "{outcome:?}");
+    assert!(started.elapsed() < Duration::from_secs(5), "it didn't wait out the grace period");
+}
+
+#[tokio::test]
+async fn test_a_server_ignoring_ctrl_c_is_killed_after_the_grace_period() {
+    let (_listener, addr) = listening().await;
+    // The client waits, so the server has set its trap by the time it's interrupted.
+    let outcome = run_pair(sh("trap '' INT; exec sleep 30"), sh("sleep 0.3"), &addr, teardown(Duration::from_millis(300)), std::future::pending()).await.unwrap();
+    assert_eq!(outcome.server, Ended::Killed);
+}
+
+#[tokio::test]
+async fn test_an_interrupt_stops_the_client_then_the_server() {
+    let (_listener, addr) = listening().await;
+    let interrupt = tokio::time::sleep(Duration::from_millis(300));
+    let outcome = timeout(
+        Duration::from_secs(5),
+        run_pair(sh("exec sleep 30"), sh("exec sleep 30"), &addr, teardown(Duration::from_secs(10)), interrupt),
+    )
+    .await
+    .expect("both stopped")
+    .unwrap();
+
+    assert!(matches!(outcome.client, Ended::Exited(status) if !status.success()), "{outcome:?}");
+    assert!(matches!(outcome.server, Ended::Exited(status) if !status.success()), "{outcome:?}");
+}
+
+#[tokio::test]
+async fn test_a_server_that_never_listens_is_an_error() {
+    // Bound and dropped, so nothing is listening there.
+    let addr = listening().await.1;
+    let e = run_pair(sh("exit 3"), sh("exit 0"), &addr, teardown(Duration::from_secs(1)), std::future::pending()).await.unwrap_err();
+    assert!(e.to_string().contains("before listening"), "{e}");
+
+    let e = run_pair(sh("exec sleep 30"), sh("exit 0"), &addr, Teardown { ready_timeout: Duration::from_millis(300), grace: Duration::from_secs(1) }, std::future::pending())
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::TimedOut, "{e}");
+}
+
+#[tokio::test]
+async fn test_a_server_dying_early_takes_the_client_with_it() {
+    let (_listener, addr) = listening().await;
+    let outcome = run_pair(sh("sleep 0.3; exit 1"), sh("exec sleep 30"), &addr, teardown(Duration::from_secs(10)), std::future::pending()).await.unwrap();
+    assert!(matches!(outcome.server, Ended::Exited(status) if status.code() == Some(1)), "{outcome:?}");
+    assert_eq!(outcome.client, Ended::Killed);
+}
@@ -0,0 +1,39 @@
+use demo_runner::{default_root, discover, find};
+
+#[test]
+fn test_finds_every_member_of_this_workspace() {
+    let demos = discover(&default_root()).unwrap();
+    for name in ["tcp_server_graceful_shutdown", "load_generator", "shutdown_util", "hello_tonic", "demo_runner"] {
+        assert!(find(&demos, name).is_ok(), "{name} is missing");
+    }
+    assert!(demos.windows(2).all(|pair| pair[0].name < pair[1].name), "sorted and unique");
+    // New crates need one too: a manifest `description`, or crate docs to take it from.
+    let undescribed: Vec<_> = demos.iter().filter(|demo| demo.description.is_none()).map(|demo| &demo.name).collect();
+    assert!(undescribed.is_empty(), "no description: {undescribed:?}");
+}
+
+#[test]
+fn test_reads_the_binaries_as_cargo_does() {
+    let demos = discover(&default_root()).unwrap();
+
+    let server = find(&demos, "tcp_server_graceful_shutdown").unwrap();
+    assert_eq!(server.bins, ["tcp_server_graceful_shutdown", "client"]);
+    assert_eq!(server.default_bin.as_deref(), Some("tcp_server_graceful_shutdown"));
+    let pair = server.pair.as_ref().expect("it has a client");
+    assert!(server.bins.contains(&pair.server) && server.bins.contains(&pair.client), "{pair:?}");
+
+    // Two [[bin]]s and a `src/main.rs` of its own, with nothing to say which runs.
+    let tonic = find(&demos, "hello_tonic").unwrap();
+    assert_eq!(tonic.bins, ["helloworld-server", "helloworld-client", "hello_tonic"]);
+    assert_eq!(tonic.default_bin, None);
+
+    let library = find(&demos, "shutdown_util").unwrap();
+    assert!(library.is_library() && library.pair.is_none(), "{library:?}");
+}
+
+#[test]
+fn test_an_unknown_demo_lists_the_known_ones() {
+    let demos = discover(&default_root()).unwrap();
+    let e = find(&demos, "no_such_demo").unwrap_err();
+    assert!(e.contains("no_such_demo") && e.contains("tcp_server_graceful_shutdown"), "{e}");
+}
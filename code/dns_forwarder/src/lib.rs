@@ -0,0 +1,149 @@
+//! A DNS forwarder: the UDP fundamentals applied to a real protocol.
+//!
+//! Clients send queries to us; we pass each one to an upstream resolver and relay the
+//! answer back. The interesting part is that one upstream socket serves every client
+//! at once, with answers arriving in whatever order the resolver sends them. The
+//! [`upstream`] module matches answers to the queries waiting for them with a map of
+//! `oneshot` senders, and each query runs as its own task with a timeout and retries:
+//!
+//! ```text
+//! client ──► accept loop ──spawn──► query task ──mpsc──► upstream task ──► resolver
+//!                                       ▲                     │
+//!                                       └──────oneshot────────┘
+//! ```
+//!
+//! Shutdown is the usual: stop reading client queries, let the queries already in
+//! flight finish (they're bounded by `query_timeout × (retries + 1)` anyway), then drop
+//! the last handle to the upstream task so it stops too.
+
+pub mod message;
+mod upstream;
+
+use shutdown_util::ShutdownController;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+
+/// Settings for [`run_forwarder`].
+#[derive(Debug, Clone)]
+pub struct ForwarderConfig {
+    /// The resolver queries are forwarded to.
+    pub upstream: SocketAddr,
+    /// How long to wait for the upstream to answer one attempt.
+    pub query_timeout: Duration,
+    /// How many more times to try a query that timed out.
+    pub retries: u32,
+    /// How long queries in flight get to finish after shutdown.
+    pub drain_timeout: Duration,
+}
+
+impl ForwarderConfig {
+    /// The defaults, forwarding to `upstream`.
+    pub fn new(upstream: SocketAddr) -> Self {
+        Self {
+            upstream,
+            query_timeout: Duration::from_secs(2),
+            retries: 2,
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Forwards every query received on `socket` to the upstream resolver until shutdown is
+/// signalled, then waits for the queries in flight.
+pub async fn run_forwarder(
+    socket: UdpSocket,
+    config: ForwarderConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let any: SocketAddr = match config.upstream {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let upstream_socket = UdpSocket::bind(any).await?;
+    upstream_socket.connect(config.upstream).await?;
+    let (upstream, upstream_task) = upstream::spawn(upstream_socket);
+
+    // Shared by the query tasks, which each send their own answer.
+    let socket = Arc::new(socket);
+    let mut buf = vec![0_u8; upstream::MAX_DATAGRAM];
+    let mut next_query_id = 0_u64;
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[forwarder] shutdown requested, no longer reading queries");
+                break;
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (n, peer) = match received {
+                    Ok(received) => received,
+                    Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset) => {
+                        println!("[forwarder] a client went away: {e}");
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let query = buf[..n].to_vec();
+                let Some(client_id) = message::id(&query).filter(|_| !message::is_response(&query)) else {
+                    println!("[forwarder] peer={peer} sent {n} byte(s) that aren't a DNS query, ignoring");
+                    continue;
+                };
+                next_query_id += 1;
+                let query_id = next_query_id;
+                let (socket, upstream, config) = (socket.clone(), upstream.clone(), config.clone());
+                controller.spawn(async move {
+                    let attempts = config.retries + 1;
+                    let reply = match upstream.exchange(&query, config.query_timeout, attempts).await {
+                        Ok(mut response) => {
+                            // Put the client's own ID back, so it recognises the answer.
+                            message::set_id(&mut response, client_id);
+                            println!("[forwarder] query={query_id} peer={peer} answered ({} byte(s))", response.len());
+                            response
+                        }
+                        Err(e) => {
+                            eprintln!("[forwarder] query={query_id} peer={peer} failed: {e}, sending SERVFAIL");
+                            message::servfail(&query)
+                        }
+                    };
+                    if let Err(e) = socket.send_to(&reply, peer).await {
+                        eprintln!("[forwarder] query={query_id} peer={peer} reply failed: {e}");
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every query ever answered piling up, and leaves the drain only the ones
+            // still in flight.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[forwarder] query task join error: {e}");
+                }
+            }
+        }
+    }
+
+    println!(
+        "[forwarder] waiting up to {:?} for {} query(s) in flight",
+        config.drain_timeout,
+        controller.active_tasks()
+    );
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[forwarder] query task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[forwarder] drain deadline hit, abandoned {} query(s)", report.aborted);
+    }
+
+    // The query tasks' handles are gone with them; this is the last one.
+    drop(upstream);
+    if let Err(e) = upstream_task.await {
+        eprintln!("[forwarder] upstream task failed: {e}");
+    }
+    println!("[forwarder] stopped");
+    Ok(())
+}
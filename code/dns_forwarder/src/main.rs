@@ -0,0 +1,65 @@
+use clap::Parser;
+use dns_forwarder::{ForwarderConfig, run_forwarder};
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+/// A DNS forwarder that relays queries to an upstream resolver and drains in-flight
+/// queries on Ctrl-C or SIGTERM.
+///
+/// Try it with `dig @127.0.0.1 -p 5353 example.com`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on. Port 53 would need root, so the default is 5353.
+    #[arg(long, default_value = "127.0.0.1:5353")]
+    bind: SocketAddr,
+    /// The resolver to forward to.
+    #[arg(long, default_value = "1.1.1.1:53")]
+    upstream: SocketAddr,
+    /// Seconds to wait for the upstream to answer one attempt.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    query_timeout: Duration,
+    /// How many more times to try a query that timed out.
+    #[arg(long, default_value_t = 2)]
+    retries: u32,
+    /// Seconds queries in flight get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = ForwarderConfig {
+        upstream: cli.upstream,
+        query_timeout: cli.query_timeout,
+        retries: cli.retries,
+        drain_timeout: cli.drain_timeout,
+    };
+
+    let socket = UdpSocket::bind(cli.bind).await?;
+    println!("[main] listening on udp://{}, forwarding to {}", socket.local_addr()?, cli.upstream);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let forwarder = tokio::spawn(run_forwarder(socket, config, controller, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match forwarder.await {
+        Ok(Ok(())) => println!("[main] forwarder exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] forwarder returned error: {e}"),
+        Err(e) => eprintln!("[main] forwarder task failed: {e}"),
+    }
+    Ok(())
+}
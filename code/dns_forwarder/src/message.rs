@@ -0,0 +1,111 @@
+//! Just enough of the DNS wire format (RFC 1035) for a forwarder.
+//!
+//! A forwarder doesn't need to understand questions or answers, only the fixed 12-byte
+//! header in front of them:
+//!
+//! ```text
+//! 0      2      4        6        8        10       12
+//! | ID   | flags| QDCOUNT| ANCOUNT| NSCOUNT| ARCOUNT| question...
+//! ```
+//!
+//! The ID is how a client matches an answer to its query, so it's the one field the
+//! forwarder rewrites on the way up and restores on the way back.
+
+/// The size of the fixed header.
+pub const HEADER_LEN: usize = 12;
+
+/// The QR bit in the first flags byte: set on responses, clear on queries.
+const QR: u8 = 0x80;
+/// The RA bit in the second flags byte: recursion available.
+const RA: u8 = 0x80;
+/// RCODE 2, "server failure".
+const SERVFAIL: u8 = 2;
+
+/// Returns the message's ID, or `None` if it's too short to be DNS.
+pub fn id(message: &[u8]) -> Option<u16> {
+    (message.len() >= HEADER_LEN).then(|| u16::from_be_bytes([message[0], message[1]]))
+}
+
+/// Overwrites the message's ID. `message` must be at least [`HEADER_LEN`] long.
+pub fn set_id(message: &mut [u8], id: u16) {
+    message[..2].copy_from_slice(&id.to_be_bytes());
+}
+
+/// Returns whether the message is a response.
+pub fn is_response(message: &[u8]) -> bool {
+    message.len() >= HEADER_LEN && message[2] & QR != 0
+}
+
+/// Turns a query into the SERVFAIL response a client gets when the upstream never
+/// answered: same ID and question, no records.
+///
+/// Answering at all matters. A client that hears nothing waits out its own timeout,
+/// often several seconds, before trying its next resolver; SERVFAIL lets it move on now.
+pub fn servfail(query: &[u8]) -> Vec<u8> {
+    let mut response = query.to_vec();
+    // Keep the opcode and RD bit from the query, mark it as a response.
+    response[2] |= QR;
+    response[3] = RA | SERVFAIL;
+    // The question stays (QDCOUNT), the answer and authority sections are empty.
+    // Any additional records, like an EDNS OPT, are echoed back as they were.
+    response[6..10].fill(0);
+    response
+}
+
+/// Builds a query for `name` with record type `qtype` (1 is A, 28 is AAAA), with
+/// recursion desired.
+pub fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    message.extend_from_slice(&id.to_be_bytes());
+    // Flags: standard query, RD set.
+    message.extend_from_slice(&[0x01, 0x00]);
+    // One question, nothing else.
+    message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.').filter(|label| !label.is_empty()) {
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&qtype.to_be_bytes());
+    // Class IN.
+    message.extend_from_slice(&1_u16.to_be_bytes());
+    message
+}
+
+/// Returns the response code of a response.
+pub fn rcode(message: &[u8]) -> Option<u8> {
+    (message.len() >= HEADER_LEN).then(|| message[3] & 0x0F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_layout_and_id_roundtrip() {
+        let mut message = query(0x1234, "example.com", 1);
+        assert_eq!(id(&message), Some(0x1234));
+        assert!(!is_response(&message));
+        assert_eq!(
+            &message[HEADER_LEN..],
+            b"\x07example\x03com\x00\x00\x01\x00\x01"
+        );
+
+        set_id(&mut message, 7);
+        assert_eq!(id(&message), Some(7));
+        assert_eq!(id(&message[..HEADER_LEN - 1]), None);
+    }
+
+    #[test]
+    fn test_servfail_keeps_id_and_question() {
+        let query = query(42, "example.com.", 28);
+        let response = servfail(&query);
+        assert_eq!(id(&response), Some(42));
+        assert!(is_response(&response));
+        assert_eq!(rcode(&response), Some(SERVFAIL));
+        // RD survives, the question is untouched.
+        assert_eq!(response[2] & 0x01, 0x01);
+        assert_eq!(&response[4..6], &[0, 1]);
+        assert_eq!(&response[HEADER_LEN..], &query[HEADER_LEN..]);
+    }
+}
@@ -0,0 +1,161 @@
+//! The one task that owns the upstream socket, and the map of queries waiting on it.
+//!
+//! Every query is forwarded over the same socket, so answers come back interleaved and
+//! in any order. The only thing tying an answer to its query is the DNS ID, and since
+//! two clients may well pick the same ID, the forwarder picks its own: the upstream task
+//! gives each query a fresh ID, remembers which `oneshot` to answer it on, and when an
+//! answer arrives looks its ID up in the map.
+//!
+//! Asking is an `mpsc` message carrying the query and the `oneshot::Sender`; answering
+//! is sending on that `oneshot`. The map never needs a lock because only this task
+//! touches it.
+
+use crate::message;
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// The largest payload a UDP datagram can carry over IPv4.
+pub(crate) const MAX_DATAGRAM: usize = 65_507;
+
+/// A query on its way to the upstream task.
+struct Query {
+    query: Vec<u8>,
+    reply: oneshot::Sender<Vec<u8>>,
+}
+
+/// A handle for forwarding queries. Cheap to clone; the upstream task stops once every
+/// handle is gone.
+#[derive(Clone)]
+pub(crate) struct Upstream {
+    tx: mpsc::Sender<Query>,
+}
+
+/// Starts the upstream task on `socket`, which must already be connected to the
+/// upstream resolver. A connected UDP socket only accepts datagrams from that address,
+/// so answers from anyone else are dropped by the kernel.
+pub(crate) fn spawn(socket: UdpSocket) -> (Upstream, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel(64);
+    (Upstream { tx }, tokio::spawn(run(socket, rx)))
+}
+
+impl Upstream {
+    /// Forwards `query` and waits up to `timeout` for the answer, trying `attempts`
+    /// times in all. The answer carries the forwarder's ID, not the query's.
+    pub(crate) async fn exchange(&self, query: &[u8], timeout: Duration, attempts: u32) -> io::Result<Vec<u8>> {
+        for attempt in 1..=attempts {
+            let (reply, answer) = oneshot::channel();
+            let outgoing = Query { query: query.to_vec(), reply };
+            if self.tx.send(outgoing).await.is_err() {
+                return Err(io::Error::other("the upstream task has stopped"));
+            }
+            // Each attempt gets a new ID, so a late answer to an earlier attempt is
+            // just an unknown ID. On a timeout `answer` is dropped, which is how the
+            // upstream task learns we stopped waiting.
+            match tokio::time::timeout(timeout, answer).await {
+                Ok(Ok(response)) => return Ok(response),
+                // The upstream task dropped our sender: sending failed. Try again.
+                Ok(Err(_)) => {}
+                Err(_) => println!("[upstream] attempt {attempt}/{attempts} timed out"),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, format!("no answer after {attempts} attempt(s)")))
+    }
+}
+
+async fn run(socket: UdpSocket, mut rx: mpsc::Receiver<Query>) {
+    let mut in_flight: HashMap<u16, oneshot::Sender<Vec<u8>>> = HashMap::new();
+    let mut next_id = 0_u16;
+    let mut buf = vec![0_u8; MAX_DATAGRAM];
+
+    loop {
+        tokio::select! {
+            next = rx.recv() => {
+                // Every handle is gone: the forwarder has drained.
+                let Some(Query { mut query, reply }) = next else { break };
+                let Some(id) = allocate_id(&mut in_flight, &mut next_id) else {
+                    eprintln!("[upstream] all 65536 IDs are in flight, dropping a query");
+                    continue;
+                };
+                message::set_id(&mut query, id);
+                if let Err(e) = socket.send(&query).await {
+                    eprintln!("[upstream] send failed: {e}");
+                    continue;
+                }
+                in_flight.insert(id, reply);
+            }
+            received = socket.recv(&mut buf) => {
+                let n = match received {
+                    Ok(n) => n,
+                    // ICMP port unreachable from an earlier send: the upstream isn't
+                    // listening. The queries will time out and be retried.
+                    Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset) => {
+                        eprintln!("[upstream] resolver unreachable: {e}");
+                        continue;
+                    }
+                    Err(e) => {
+                        eprintln!("[upstream] receive failed, stopping: {e}");
+                        break;
+                    }
+                };
+                let response = &buf[..n];
+                let Some(id) = message::id(response).filter(|_| message::is_response(response)) else {
+                    println!("[upstream] ignoring a {n}-byte datagram that isn't a DNS response");
+                    continue;
+                };
+                match in_flight.remove(&id) {
+                    Some(reply) => {
+                        if reply.send(response.to_vec()).is_err() {
+                            println!("[upstream] id={id} answered after its query gave up");
+                        }
+                    }
+                    None => println!("[upstream] id={id} matches no query in flight"),
+                }
+            }
+        }
+    }
+}
+
+/// Picks the next ID not in use by a query that's still waiting. Entries whose asker
+/// has given up are reclaimed when their ID comes round again.
+///
+/// Sequential IDs keep the example readable. A real forwarder picks them at random
+/// (and randomizes its source port too), or anyone who can guess the next ID can race
+/// the real resolver with a forged answer.
+fn allocate_id(in_flight: &mut HashMap<u16, oneshot::Sender<Vec<u8>>>, next_id: &mut u16) -> Option<u16> {
+    for _ in 0..=u16::MAX {
+        *next_id = next_id.wrapping_add(1);
+        match in_flight.get(next_id) {
+            Some(waiting) if !waiting.is_closed() => continue,
+            _ => {
+                in_flight.remove(next_id);
+                return Some(*next_id);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_id_skips_live_queries_and_reuses_abandoned_ones() {
+        let mut in_flight = HashMap::new();
+        let mut next_id = 0;
+        let (live, _live_rx) = oneshot::channel();
+        let (abandoned, abandoned_rx) = oneshot::channel();
+        in_flight.insert(1, live);
+        in_flight.insert(2, abandoned);
+        drop(abandoned_rx);
+
+        assert_eq!(allocate_id(&mut in_flight, &mut next_id), Some(2));
+        assert!(!in_flight.contains_key(&2));
+        assert_eq!(allocate_id(&mut in_flight, &mut next_id), Some(3));
+        assert!(in_flight.contains_key(&1));
+    }
+}
@@ -0,0 +1,151 @@
+use dns_forwarder::{ForwarderConfig, message, run_forwarder};
+use shutdown_util::{ShutdownController, ShutdownTrigger};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+/// How the fake resolver treats the queries it gets.
+#[derive(Clone, Copy)]
+enum Upstream {
+    /// Ignores the first `n` queries, then answers.
+    DropFirst(usize),
+    /// Never answers.
+    Silent,
+    /// Waits for two queries, then answers them in reverse order.
+    Reverse,
+    /// Answers each query after a delay.
+    Delay(Duration),
+}
+
+/// A resolver whose answer to a query is the query itself, marked as a response.
+async fn fake_upstream(behaviour: Upstream) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0_u8; 1500];
+        let mut seen = 0;
+        let mut held = Vec::new();
+        loop {
+            let (n, from) = socket.recv_from(&mut buf).await.unwrap();
+            seen += 1;
+            let mut answer = buf[..n].to_vec();
+            answer[2] |= 0x80;
+            match behaviour {
+                Upstream::DropFirst(skip) if seen <= skip => continue,
+                Upstream::DropFirst(_) => {}
+                Upstream::Silent => continue,
+                Upstream::Reverse => {
+                    held.push((answer, from));
+                    if held.len() == 2 {
+                        while let Some((answer, from)) = held.pop() {
+                            socket.send_to(&answer, from).await.unwrap();
+                        }
+                    }
+                    continue;
+                }
+                Upstream::Delay(delay) => tokio::time::sleep(delay).await,
+            }
+            socket.send_to(&answer, from).await.unwrap();
+        }
+    });
+    addr
+}
+
+async fn start_forwarder(config: ForwarderConfig) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let task = tokio::spawn(run_forwarder(socket, config, controller, shutdown_rx));
+    (addr, trigger, task)
+}
+
+fn fast_config(upstream: SocketAddr) -> ForwarderConfig {
+    ForwarderConfig {
+        query_timeout: Duration::from_millis(100),
+        ..ForwarderConfig::new(upstream)
+    }
+}
+
+async fn ask(forwarder: SocketAddr, query: &[u8]) -> Vec<u8> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.send_to(query, forwarder).await.unwrap();
+    let mut buf = [0_u8; 1500];
+    let n = timeout(Duration::from_secs(2), socket.recv(&mut buf)).await.unwrap().unwrap();
+    buf[..n].to_vec()
+}
+
+#[tokio::test]
+async fn test_same_client_id_from_two_clients_reaches_the_right_client() {
+    let upstream = fake_upstream(Upstream::Reverse).await;
+    let (addr, trigger, task) = start_forwarder(fast_config(upstream)).await;
+
+    // Both clients use ID 7; the upstream answers the second one first.
+    let first = message::query(7, "first.example", 1);
+    let second = message::query(7, "second.example", 1);
+    let (first_answer, second_answer) = tokio::join!(ask(addr, &first), async {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        ask(addr, &second).await
+    });
+
+    for (query, answer) in [(&first, &first_answer), (&second, &second_answer)] {
+        assert_eq!(message::id(answer), Some(7));
+        assert!(message::is_response(answer));
+        assert_eq!(&answer[message::HEADER_LEN..], &query[message::HEADER_LEN..]);
+    }
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_retries_after_a_lost_query() {
+    let upstream = fake_upstream(Upstream::DropFirst(1)).await;
+    let (addr, trigger, task) = start_forwarder(fast_config(upstream)).await;
+
+    let query = message::query(99, "example.com", 28);
+    let answer = ask(addr, &query).await;
+    assert_eq!(message::id(&answer), Some(99));
+    assert_eq!(message::rcode(&answer), Some(0));
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_servfail_when_the_upstream_never_answers() {
+    let upstream = fake_upstream(Upstream::Silent).await;
+    let (addr, trigger, task) = start_forwarder(fast_config(upstream)).await;
+
+    let query = message::query(5, "example.com", 1);
+    let answer = ask(addr, &query).await;
+    assert_eq!(message::id(&answer), Some(5));
+    assert!(message::is_response(&answer));
+    assert_eq!(message::rcode(&answer), Some(2));
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_waits_for_queries_in_flight() {
+    let upstream = fake_upstream(Upstream::Delay(Duration::from_millis(200))).await;
+    let config = ForwarderConfig {
+        query_timeout: Duration::from_secs(1),
+        ..ForwarderConfig::new(upstream)
+    };
+    let (addr, trigger, task) = start_forwarder(config).await;
+
+    let query = message::query(11, "slow.example", 1);
+    let answer = tokio::spawn(async move { ask(addr, &query).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    trigger.trigger();
+
+    let answer = answer.await.unwrap();
+    assert_eq!(message::id(&answer), Some(11));
+    assert_eq!(message::rcode(&answer), Some(0));
+    timeout(Duration::from_secs(2), task).await.unwrap().unwrap().unwrap();
+}
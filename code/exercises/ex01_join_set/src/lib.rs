@@ -0,0 +1,29 @@
+//! Exercise 1: run a batch of requests at once, and hand the answers back in order.
+//!
+//! `fetch` stands in for a network call: it takes a while, and how long depends on the
+//! id. Awaiting one after another takes as long as all of them added up; spawned as
+//! tasks they overlap, and the whole batch takes as long as the slowest. The catch is
+//! that tasks finish in whatever order they finish in, and the caller wants the answers
+//! in the order it asked.
+//!
+//! Implement `fetch_all` so the tests in `tests/check.rs` pass. Don't change `fetch`.
+
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// Pretends to look `id` up somewhere slow; higher ids take longer.
+pub async fn fetch(id: u32) -> String {
+    tokio::time::sleep(Duration::from_millis(100 * u64::from(id % 5 + 1))).await;
+    format!("item {id}")
+}
+
+/// Fetches every id at once and returns the answers in the order of `ids`.
+pub async fn fetch_all(ids: Vec<u32>) -> Vec<String> {
+    // Tasks rather than futures joined in place, so on a multi-threaded runtime they'd
+    // run in parallel too.
+    let tasks: JoinSet<(usize, String)> = JoinSet::new();
+    for (index, id) in ids.into_iter().enumerate() {
+        todo!("spawn `fetch({id})` into `tasks`, remembering that it was number {index}")
+    }
+    todo!("collect the {} answers from `tasks` back into the order they were asked for", tasks.len())
+}
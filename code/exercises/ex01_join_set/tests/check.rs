@@ -0,0 +1,24 @@
+use ex01_join_set::fetch_all;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[tokio::test(start_paused = true)]
+async fn test_answers_come_back_in_the_order_asked() {
+    let ids = vec![4, 0, 3, 1, 2];
+    let answers = fetch_all(ids.clone()).await;
+    assert_eq!(answers, ids.iter().map(|id| format!("item {id}")).collect::<Vec<_>>());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_the_fetches_overlap() {
+    let started = Instant::now();
+    fetch_all((0..20).collect()).await;
+    // One after another would be 20 fetches of 100-500ms: six seconds. All at once,
+    // it's the slowest one.
+    assert_eq!(started.elapsed(), Duration::from_millis(500));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_nothing_to_fetch() {
+    assert!(fetch_all(Vec::new()).await.is_empty());
+}
@@ -0,0 +1,25 @@
+//! Exercise 2: fix the blocked executor.
+//!
+//! `checksum` is ordinary synchronous code that takes a while: hashing a big file, say,
+//! or compressing it. Calling it from async code works, but for as long as it runs the
+//! worker thread it's on can't poll anything else. On a current-thread runtime that's
+//! every other task in the program: timers fire late, connections stall, and a
+//! heartbeat that should tick every few milliseconds doesn't tick at all.
+//!
+//! `checksum_async` is what async code should call instead, and it has exactly that
+//! problem. Fix it so the tests in `tests/check.rs` pass: same answer, but the runtime
+//! keeps running meanwhile. Don't change `checksum`.
+
+use std::time::Duration;
+
+/// A slow, synchronous checksum. The sleep stands in for the work.
+pub fn checksum(data: &[u8]) -> u64 {
+    std::thread::sleep(Duration::from_millis(200));
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// `checksum`, without holding up the other tasks on this runtime.
+pub async fn checksum_async(data: Vec<u8>) -> u64 {
+    // The right answer, and 200ms with the thread it's on blocked. Fix it.
+    checksum(&data)
+}
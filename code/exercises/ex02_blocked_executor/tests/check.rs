@@ -0,0 +1,44 @@
+use ex02_blocked_executor::{checksum, checksum_async};
+use interleaving::Recorder;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_same_answer_as_the_synchronous_version() {
+    let data: Vec<u8> = (0..=255).collect();
+    assert_eq!(checksum_async(data.clone()).await, checksum(&data));
+}
+
+// One thread for everything: if the checksum blocks it, the heartbeat can't tick.
+#[tokio::test(flavor = "current_thread")]
+async fn test_the_heartbeat_keeps_ticking_meanwhile() {
+    const HEARTBEAT: usize = 0;
+    const CHECKSUM: usize = 1;
+    let recorder = Recorder::new();
+    let heartbeat = tokio::spawn({
+        let recorder = recorder.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(10));
+            for beat in 0.. {
+                interval.tick().await;
+                recorder.record(HEARTBEAT, beat);
+            }
+        }
+    });
+    // Let the heartbeat start.
+    tokio::task::yield_now().await;
+
+    recorder.record(CHECKSUM, 0);
+    checksum_async(vec![1, 2, 3]).await;
+    recorder.record(CHECKSUM, 1);
+    // Give the heartbeat a beat after, so a stall shows as a gap before it rather than
+    // as the heartbeat simply stopping.
+    tokio::time::sleep(Duration::from_millis(15)).await;
+    heartbeat.abort();
+    // A beat every 10ms, give or take a busy machine; a blocked runtime misses 200ms of
+    // them, and the failure shows where.
+    let timeline = recorder.timeline();
+    if let Err(e) = timeline.max_gap(HEARTBEAT, Duration::from_millis(50)) {
+        panic!("{e}");
+    }
+    assert!(timeline.elapsed() >= Duration::from_millis(200), "the checksum takes 200ms:\n{timeline}");
+}
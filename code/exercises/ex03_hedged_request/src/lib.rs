@@ -0,0 +1,26 @@
+//! Exercise 3: a hedged request.
+//!
+//! Most requests to a replicated service come back quickly, and a few are slow: the one
+//! replica they landed on is busy, or collecting garbage. Hedging trims that tail. Send
+//! the request, and if it hasn't come back after `hedge_after`, send it again (to
+//! another replica) *without giving up on the first*; whichever answers first wins, and
+//! the other is dropped.
+//!
+//! Implement `hedged` so the tests in `tests/check.rs` pass. The first request must
+//! keep running while the second is in flight: restarting it would throw away the time
+//! it has already had.
+
+use std::future::Future;
+use std::time::Duration;
+
+/// Calls `request()` once, and a second time if the first hasn't finished after
+/// `hedge_after`; returns whichever answer comes first.
+pub async fn hedged<F, Fut, T>(mut request: F, hedge_after: Duration) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+{
+    let first = request();
+    tokio::pin!(first);
+    todo!("race `first` against a {hedge_after:?} timer, and if the timer wins, against a second `request()` too")
+}
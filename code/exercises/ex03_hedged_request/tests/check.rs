@@ -0,0 +1,41 @@
+use ex03_hedged_request::hedged;
+use std::cell::Cell;
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+/// A request that takes `delays[n]` on its nth call and answers with `n`.
+async fn run(delays: &[u64], hedge_after: u64) -> (usize, usize, Duration) {
+    let calls = Cell::new(0);
+    let started = Instant::now();
+    let answer = hedged(
+        || {
+            let n = calls.get();
+            calls.set(n + 1);
+            let delay = Duration::from_millis(delays[n]);
+            async move {
+                sleep(delay).await;
+                n
+            }
+        },
+        Duration::from_millis(hedge_after),
+    )
+    .await;
+    (answer, calls.get(), started.elapsed())
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_a_quick_answer_is_not_hedged() {
+    assert_eq!(run(&[30, 10], 50).await, (0, 1, Duration::from_millis(30)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_a_slow_first_request_is_hedged_and_the_second_wins() {
+    assert_eq!(run(&[500, 20], 50).await, (1, 2, Duration::from_millis(70)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_the_first_request_keeps_running_after_the_hedge() {
+    // The first is due at 80ms, the second at 50 + 100: the first must still win, at
+    // the time it would have without the hedge.
+    assert_eq!(run(&[80, 100], 50).await, (0, 2, Duration::from_millis(80)));
+}
@@ -0,0 +1,43 @@
+//! Exercise 4: batching with a deadline.
+//!
+//! Writing to a database or a log one item at a time pays the per-write cost every
+//! time, so items are batched: collected until there are `max` of them, then written
+//! together. Under load that fills batches quickly. When it's quiet, though, a lone item
+//! could sit there for ever waiting for company, so a batch also goes out once it has
+//! *lingered* long enough, `linger` after its first item arrived, however few it holds.
+//!
+//! The size half is done. Finish the linger half so the tests in `tests/check.rs` pass.
+//! Waiting for the next item and waiting for the deadline have to happen at the same
+//! time: that's a job for `tokio::select!`.
+
+use std::mem;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// Passes `items` on to `batches` in groups of at most `max`, each sent when full or
+/// `linger` after its first item, whichever is first. When `items` closes, whatever is
+/// left goes out as a last, short batch. Stops early if `batches` closes.
+pub async fn batch<T>(mut items: mpsc::Receiver<T>, batches: mpsc::Sender<Vec<T>>, max: usize, linger: Duration) {
+    let mut batch = Vec::with_capacity(max);
+    // When the batch in hand has to go, full or not; `None` while it's empty.
+    let mut deadline: Option<Instant> = None;
+    while let Some(item) = items.recv().await {
+        if batch.is_empty() {
+            deadline = Some(Instant::now() + linger);
+        }
+        batch.push(item);
+        if batch.len() == max {
+            deadline = None;
+            if batches.send(mem::take(&mut batch)).await.is_err() {
+                return;
+            }
+        }
+        if let Some(deadline) = deadline {
+            todo!("send the batch at {deadline:?} even if nothing else arrives, without stopping here to wait for it")
+        }
+    }
+    if !batch.is_empty() {
+        let _ = batches.send(batch).await;
+    }
+}
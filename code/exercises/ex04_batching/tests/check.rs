@@ -0,0 +1,66 @@
+use ex04_batching::batch;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, sleep, timeout};
+
+/// Starts a batcher of at most `max` lingering 100ms; returns its input and output.
+fn batcher(max: usize) -> (mpsc::Sender<u32>, mpsc::Receiver<Vec<u32>>) {
+    let (items_tx, items_rx) = mpsc::channel(16);
+    let (batches_tx, batches_rx) = mpsc::channel(16);
+    tokio::spawn(batch(items_rx, batches_tx, max, Duration::from_millis(100)));
+    (items_tx, batches_rx)
+}
+
+/// The next batch, and how long it took; a hung batcher fails instead of hanging.
+async fn next(batches: &mut mpsc::Receiver<Vec<u32>>) -> (Option<Vec<u32>>, Duration) {
+    let started = Instant::now();
+    let batch = timeout(Duration::from_secs(5), batches.recv()).await.expect("a batch within 5s");
+    (batch, started.elapsed())
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_a_full_batch_goes_straight_away() {
+    let (items, mut batches) = batcher(3);
+    for item in 1..=3 {
+        items.send(item).await.unwrap();
+    }
+    assert_eq!(next(&mut batches).await, (Some(vec![1, 2, 3]), Duration::ZERO));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_a_lone_item_goes_after_the_linger() {
+    let (items, mut batches) = batcher(3);
+    items.send(1).await.unwrap();
+    assert_eq!(next(&mut batches).await, (Some(vec![1]), Duration::from_millis(100)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_the_linger_counts_from_the_first_item() {
+    let (items, mut batches) = batcher(10);
+    let sender = tokio::spawn(async move {
+        for item in 1..=5 {
+            items.send(item).await.unwrap();
+            sleep(Duration::from_millis(30)).await;
+        }
+        // Still open: closing would flush the batch early.
+        items
+    });
+    // Items at 0, 30, 60 and 90ms make the first batch; more of them don't put it off.
+    assert_eq!(next(&mut batches).await, (Some(vec![1, 2, 3, 4]), Duration::from_millis(100)));
+    // The fifth, at 120ms, starts the next one, which goes at 220ms.
+    let items = sender.await.unwrap();
+    assert_eq!(next(&mut batches).await, (Some(vec![5]), Duration::from_millis(70)));
+    drop(items);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_closing_the_input_flushes_the_rest() {
+    let (items, mut batches) = batcher(3);
+    for item in 1..=4 {
+        items.send(item).await.unwrap();
+    }
+    drop(items);
+    assert_eq!(next(&mut batches).await.0, Some(vec![1, 2, 3]));
+    assert_eq!(next(&mut batches).await, (Some(vec![4]), Duration::ZERO));
+    assert_eq!(next(&mut batches).await.0, None, "the batcher finishes");
+}
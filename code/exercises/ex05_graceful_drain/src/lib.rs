@@ -0,0 +1,67 @@
+//! Exercise 5: make this shutdown drain correctly.
+//!
+//! A `Pool` runs workers, each doing one job after another. Shutting it down should go
+//! the way every server in the workshop does it: tell the workers to stop, let each
+//! finish the job it's in the middle of (half a job is worse than none: half a file
+//! written, half a transfer made), wait for them, but not for ever: whatever is still
+//! running at the deadline is aborted, and the caller hears how many that was.
+//!
+//! Two things need doing for the tests in `tests/check.rs` to pass. `shutdown` is
+//! missing. And `spawn_worker` has a bug, one the type checker can't see: look at what
+//! its `select!` does to a job that's running when the signal arrives.
+
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+
+/// How [`Pool::shutdown`] went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// Workers that stopped on their own, before the deadline.
+    pub finished: usize,
+    /// Workers still running at the deadline, and aborted.
+    pub aborted: usize,
+}
+
+/// Workers, and the signal that stops them.
+pub struct Pool {
+    shutdown: broadcast::Sender<()>,
+    workers: JoinSet<()>,
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pool {
+    pub fn new() -> Self {
+        let (shutdown, _) = broadcast::channel(1);
+        Self { shutdown, workers: JoinSet::new() }
+    }
+
+    /// Starts a worker that runs `job` over and over until the pool shuts down.
+    pub fn spawn_worker<F, Fut>(&mut self, mut job: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let mut shutdown = self.shutdown.subscribe();
+        self.workers.spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = shutdown.recv() => break,
+                    () = job() => {}
+                }
+            }
+        });
+    }
+
+    /// Stops the workers: each finishes the job it's in, and any still going after
+    /// `deadline` is aborted.
+    pub async fn shutdown(self, deadline: Duration) -> DrainReport {
+        todo!("tell the {} workers to stop, wait up to {deadline:?} for them, abort the rest", self.workers.len())
+    }
+}
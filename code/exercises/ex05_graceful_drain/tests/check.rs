@@ -0,0 +1,62 @@
+use ex05_graceful_drain::{DrainReport, Pool};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+/// How many jobs have started, and how many of those have finished.
+#[derive(Default)]
+struct Jobs {
+    started: AtomicU32,
+    done: AtomicU32,
+}
+
+/// Adds a worker whose jobs each take `length`.
+fn add_worker(pool: &mut Pool, jobs: &Arc<Jobs>, length: Duration) {
+    let jobs = jobs.clone();
+    pool.spawn_worker(move || {
+        let jobs = jobs.clone();
+        async move {
+            jobs.started.fetch_add(1, Ordering::Relaxed);
+            sleep(length).await;
+            jobs.done.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_workers_finish_the_job_they_are_in() {
+    let jobs = Arc::new(Jobs::default());
+    let mut pool = Pool::new();
+    for _ in 0..3 {
+        add_worker(&mut pool, &jobs, Duration::from_millis(40));
+    }
+    // Partway into each worker's third job.
+    sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    assert_eq!(pool.shutdown(Duration::from_secs(1)).await, DrainReport { finished: 3, aborted: 0 });
+    assert_eq!(started.elapsed(), Duration::from_millis(20), "it waited for the jobs in progress and no longer");
+    assert_eq!(jobs.started.load(Ordering::Relaxed), 9, "and no new ones started");
+    assert_eq!(jobs.done.load(Ordering::Relaxed), 9, "every job that started finished");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_a_stuck_worker_is_aborted_at_the_deadline() {
+    let jobs = Arc::new(Jobs::default());
+    let mut pool = Pool::new();
+    add_worker(&mut pool, &jobs, Duration::from_millis(10));
+    add_worker(&mut pool, &jobs, Duration::from_secs(3600));
+    sleep(Duration::from_millis(5)).await;
+
+    let started = Instant::now();
+    assert_eq!(pool.shutdown(Duration::from_millis(200)).await, DrainReport { finished: 1, aborted: 1 });
+    assert_eq!(started.elapsed(), Duration::from_millis(200));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_an_empty_pool_shuts_down_at_once() {
+    let started = Instant::now();
+    assert_eq!(Pool::new().shutdown(Duration::from_secs(1)).await, DrainReport { finished: 0, aborted: 0 });
+    assert_eq!(started.elapsed(), Duration::ZERO);
+}
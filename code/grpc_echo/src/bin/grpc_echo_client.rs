@@ -0,0 +1,62 @@
+use clap::Parser;
+use grpc_echo::proto::echo_client::EchoClient;
+use grpc_echo::proto::{EchoRequest, StreamingEchoRequest};
+use tonic::Code;
+use tonic_health::pb::HealthCheckRequest;
+use tonic_health::pb::health_client::HealthClient;
+
+/// Checks the server's health, makes one unary call, then streams echoes until the
+/// stream ends. Stop the server meanwhile to watch the stream end with UNAVAILABLE.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// The server to talk to.
+    #[arg(long, default_value = "http://127.0.0.1:50052")]
+    server: String,
+    /// How many messages to stream.
+    #[arg(long, default_value_t = 60)]
+    repeat: u32,
+    /// Milliseconds between streamed messages.
+    #[arg(long, default_value_t = 500)]
+    interval_ms: u32,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    // Both clients share one HTTP/2 connection.
+    let channel = tonic::transport::Endpoint::from_shared(cli.server)?.connect().await?;
+    let mut health = HealthClient::new(channel.clone());
+    let mut echo = EchoClient::new(channel);
+
+    let status = health
+        .check(HealthCheckRequest { service: "echo.Echo".into() })
+        .await?
+        .into_inner()
+        .status();
+    println!("[client] echo.Echo health: {status:?}");
+
+    let reply = echo.unary_echo(EchoRequest { message: "hello".into() }).await?;
+    println!("[client] unary echo: {}", reply.into_inner().message);
+
+    let request = StreamingEchoRequest {
+        message: "tick".into(),
+        repeat: cli.repeat,
+        interval_ms: cli.interval_ms,
+    };
+    let mut stream = echo.server_streaming_echo(request).await?.into_inner();
+    loop {
+        match stream.message().await {
+            Ok(Some(reply)) => println!("[client] stream #{}: {}", reply.sequence, reply.message),
+            Ok(None) => {
+                println!("[client] stream complete");
+                break;
+            }
+            Err(status) if status.code() == Code::Unavailable => {
+                println!("[client] server went away: {}", status.message());
+                break;
+            }
+            Err(status) => return Err(status.into()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! The graceful-shutdown fundamentals again, this time behind a framework.
+//!
+//! The raw-TCP example did everything by hand: an accept loop that stops on a
+//! `broadcast` signal, a task per connection, and a drain with a deadline. tonic does
+//! the accept loop and the per-connection tasks itself, so here the same ideas show up
+//! as configuration:
+//!
+//! - **stop accepting, finish in-flight calls**: `serve_with_incoming_shutdown` takes a
+//!   future; when it resolves, tonic stops accepting and waits for open calls, the
+//!   way our accept loop broke out and then waited on the `JoinSet`;
+//! - **tell the load balancer first**: the standard `grpc.health.v1.Health` service is
+//!   switched to `NOT_SERVING` as soon as shutdown is requested, and the server keeps
+//!   serving for `lame_duck` so health checkers notice before connections are refused;
+//! - **long-lived calls end themselves**: tonic's drain waits for *responses* to
+//!   complete, and a streaming response only completes when its stream does, so the
+//!   streaming echo watches the shutdown signal and ends with `UNAVAILABLE`, the status
+//!   that tells a gRPC client to retry elsewhere;
+//! - **the drain still gets a deadline**: the serve future is dropped if it hasn't
+//!   finished `drain_timeout` after the lame-duck period.
+//!
+//! `serve_with_incoming_shutdown` is `serve_with_shutdown` for a listener we bound
+//! ourselves, which lets tests use port 0.
+
+pub mod proto {
+    tonic::include_proto!("echo");
+}
+
+use proto::echo_server::{Echo, EchoServer};
+use proto::{EchoRequest, EchoResponse, StreamingEchoRequest};
+use shutdown_util::ShutdownController;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{sleep, timeout};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tonic_health::ServingStatus;
+
+/// The most messages one streaming call may ask for.
+pub const MAX_REPEAT: u32 = 1000;
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How long to keep serving, while reporting `NOT_SERVING`, before refusing
+    /// connections.
+    pub lame_duck: Duration,
+    /// How long in-flight calls get to finish after that.
+    pub drain_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            lame_duck: Duration::from_secs(1),
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The `Echo` service.
+pub struct EchoService {
+    /// Each streaming call resubscribes to this to learn about shutdown.
+    shutdown_rx: broadcast::Receiver<()>,
+    /// Set once shutdown has been requested, for calls that start too late to see the
+    /// signal itself.
+    draining: Arc<AtomicBool>,
+}
+
+impl EchoService {
+    pub fn new(shutdown_rx: broadcast::Receiver<()>, draining: Arc<AtomicBool>) -> Self {
+        Self { shutdown_rx, draining }
+    }
+}
+
+#[tonic::async_trait]
+impl Echo for EchoService {
+    async fn unary_echo(&self, request: Request<EchoRequest>) -> Result<Response<EchoResponse>, Status> {
+        let message = request.into_inner().message;
+        Ok(Response::new(EchoResponse { message, sequence: 0 }))
+    }
+
+    type ServerStreamingEchoStream = ReceiverStream<Result<EchoResponse, Status>>;
+
+    async fn server_streaming_echo(
+        &self,
+        request: Request<StreamingEchoRequest>,
+    ) -> Result<Response<Self::ServerStreamingEchoStream>, Status> {
+        let StreamingEchoRequest { message, repeat, interval_ms } = request.into_inner();
+        if repeat > MAX_REPEAT {
+            return Err(Status::invalid_argument(format!("repeat must be at most {MAX_REPEAT}")));
+        }
+        let every = Duration::from_millis(interval_ms.into());
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(Status::unavailable("server shutting down"));
+        }
+        let mut shutdown_rx = self.shutdown_rx.resubscribe();
+        let draining = self.draining.clone();
+        // A small buffer: a slow client slows the producer down instead of piling up
+        // responses in memory.
+        let (tx, rx) = mpsc::channel(4);
+
+        tokio::spawn(async move {
+            for sequence in 1..=repeat {
+                let reply = EchoResponse { message: message.clone(), sequence };
+                // An error means the client cancelled the call.
+                if tx.send(Ok(reply)).await.is_err() {
+                    return;
+                }
+                if sequence == repeat {
+                    break;
+                }
+                // The signal wakes us up promptly. The flag covers a call that
+                // resubscribed just after the signal went out, which only waits for
+                // the next message.
+                let stopping = tokio::select! {
+                    _ = shutdown_rx.recv() => true,
+                    _ = sleep(every) => draining.load(Ordering::SeqCst),
+                };
+                if stopping {
+                    let _ = tx.send(Err(Status::unavailable("server shutting down"))).await;
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Serves `Echo` and `grpc.health.v1.Health` on `listener` until shutdown is signalled,
+/// then reports `NOT_SERVING`, waits out the lame-duck period, and drains.
+pub async fn run_server(
+    listener: TcpListener,
+    config: ServerConfig,
+    controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let (mut reporter, health_service) = tonic_health::server::health_reporter();
+    reporter.set_serving::<EchoServer<EchoService>>().await;
+
+    let draining = Arc::new(AtomicBool::new(false));
+    let echo = EchoService::new(controller.subscribe(), draining.clone());
+    let mut signal_rx = controller.subscribe();
+    let lame_duck = config.lame_duck;
+    let serve = Server::builder()
+        .add_service(health_service)
+        .add_service(EchoServer::new(echo))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+            let _ = signal_rx.recv().await;
+            draining.store(true, Ordering::SeqCst);
+            reporter.set_not_serving::<EchoServer<EchoService>>().await;
+            // The empty name is the server as a whole.
+            reporter.set_service_status("", ServingStatus::NotServing).await;
+            println!("[server] health is NOT_SERVING, refusing connections in {lame_duck:?}");
+            sleep(lame_duck).await;
+        });
+    tokio::pin!(serve);
+
+    tokio::select! {
+        result = &mut serve => return result.map_err(io::Error::other),
+        _ = shutdown_rx.recv() => println!("[server] shutdown requested"),
+    }
+
+    match timeout(config.lame_duck + config.drain_timeout, serve).await {
+        Ok(result) => result.map_err(io::Error::other)?,
+        Err(_) => eprintln!("[server] drain deadline hit, dropping the calls still open"),
+    }
+    println!("[server] all calls finished");
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use clap::Parser;
+use grpc_echo::{ServerConfig, run_server};
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// A gRPC echo server with the standard health service, which reports `NOT_SERVING`
+/// and drains on Ctrl-C or SIGTERM.
+///
+/// Talk to it with `cargo run --bin grpc_echo_client`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:50052")]
+    bind: SocketAddr,
+    /// Seconds to keep serving, reported as NOT_SERVING, before refusing connections.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    lame_duck: Duration,
+    /// Seconds in-flight calls get to finish after that.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = ServerConfig {
+        lame_duck: cli.lame_duck,
+        drain_timeout: cli.drain_timeout,
+    };
+
+    let listener = TcpListener::bind(cli.bind).await?;
+    println!("[main] listening on http://{}", listener.local_addr()?);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let server = tokio::spawn(run_server(listener, config, controller, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match server.await {
+        Ok(Ok(())) => println!("[main] server exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
+        Err(e) => eprintln!("[main] server task failed: {e}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use grpc_echo::proto::echo_client::EchoClient;
+use grpc_echo::proto::{EchoRequest, StreamingEchoRequest};
+use grpc_echo::{MAX_REPEAT, ServerConfig, run_server};
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tonic::Code;
+use tonic::transport::Channel;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+async fn start_server(config: ServerConfig) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    spawn_server(|listener, controller, shutdown_rx| run_server(listener, config, controller, shutdown_rx)).await
+}
+
+async fn connect(addr: SocketAddr) -> Channel {
+    tonic::transport::Endpoint::from_shared(format!("http://{addr}"))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+async fn health_of(channel: &Channel, service: &str) -> ServingStatus {
+    HealthClient::new(channel.clone())
+        .check(HealthCheckRequest { service: service.into() })
+        .await
+        .unwrap()
+        .into_inner()
+        .status()
+}
+
+fn fast_config() -> ServerConfig {
+    ServerConfig {
+        lame_duck: Duration::from_millis(200),
+        drain_timeout: Duration::from_secs(2),
+    }
+}
+
+#[tokio::test]
+async fn test_unary_echo_and_health() {
+    let (addr, trigger, task) = start_server(fast_config()).await;
+    let channel = connect(addr).await;
+
+    let reply = EchoClient::new(channel.clone())
+        .unary_echo(EchoRequest { message: "hello".into() })
+        .await
+        .unwrap();
+    assert_eq!(reply.into_inner().message, "hello");
+    assert_eq!(health_of(&channel, "echo.Echo").await, ServingStatus::Serving);
+    assert_eq!(health_of(&channel, "").await, ServingStatus::Serving);
+
+    trigger.trigger();
+    timeout(Duration::from_secs(2), task).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_health_reports_not_serving_during_lame_duck() {
+    let config = ServerConfig {
+        lame_duck: Duration::from_millis(500),
+        ..fast_config()
+    };
+    let (addr, trigger, task) = start_server(config).await;
+    let channel = connect(addr).await;
+    assert_eq!(health_of(&channel, "echo.Echo").await, ServingStatus::Serving);
+
+    trigger.trigger();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(health_of(&channel, "echo.Echo").await, ServingStatus::NotServing);
+    assert_eq!(health_of(&channel, "").await, ServingStatus::NotServing);
+    // Still answering calls until the lame-duck period is over.
+    let reply = EchoClient::new(channel.clone())
+        .unary_echo(EchoRequest { message: "still here".into() })
+        .await
+        .unwrap();
+    assert_eq!(reply.into_inner().message, "still here");
+
+    drop(channel);
+    timeout(Duration::from_secs(3), task).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_stream_ends_with_unavailable_on_shutdown() {
+    let (addr, trigger, task) = start_server(fast_config()).await;
+    let mut echo = EchoClient::new(connect(addr).await);
+    let request = StreamingEchoRequest {
+        message: "tick".into(),
+        repeat: MAX_REPEAT,
+        interval_ms: 20,
+    };
+    let mut stream = echo.server_streaming_echo(request).await.unwrap().into_inner();
+    for expected in 1..=3 {
+        let reply = stream.message().await.unwrap().unwrap();
+        assert_eq!((reply.message.as_str(), reply.sequence), ("tick", expected));
+    }
+
+    trigger.trigger();
+    let status = timeout(Duration::from_secs(2), async {
+        loop {
+            match stream.message().await {
+                Ok(Some(_)) => continue,
+                Ok(None) => panic!("stream completed instead of being cut short"),
+                Err(status) => break status,
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(status.code(), Code::Unavailable);
+
+    drop(echo);
+    timeout(Duration::from_secs(3), task).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_rejects_oversized_stream_requests() {
+    let (addr, trigger, task) = start_server(fast_config()).await;
+    let mut echo = EchoClient::new(connect(addr).await);
+    let request = StreamingEchoRequest {
+        message: "tick".into(),
+        repeat: MAX_REPEAT + 1,
+        interval_ms: 0,
+    };
+    let status = echo.server_streaming_echo(request).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    drop(echo);
+    trigger.trigger();
+    timeout(Duration::from_secs(3), task).await.unwrap().unwrap().unwrap();
+}
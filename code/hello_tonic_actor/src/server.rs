@@ -28,7 +28,7 @@ impl Greeter for MyGreeter {
         let new_count = shared_state_actor::get_counter(&self.my_actor).await;
 
         let reply = hello_world::HelloReply {
-            message: format!("Hello {}!", new_count).into(),
+            message: format!("Hello {}!", new_count),
         };
 
         Ok(Response::new(reply))
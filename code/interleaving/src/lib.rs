@@ -0,0 +1,274 @@
+//! Checks how concurrent code interleaved, from when each task did what rather than
+//! from the order its output happened to come out in.
+//!
+//! Reading a log to see whether three tasks ran side by side or one after another
+//! works for a person at a workshop, and not for a test: the lines of a parallel run
+//! can come out in any order, and a serialized one can look interleaved if the tasks
+//! are short. So the code under test records an [`Event`] at each step instead (which
+//! task, which step, when), into a [`Recorder`] it's handed, and the test asks the
+//! resulting [`Timeline`] about properties:
+//!
+//! - [`in_step`](Timeline::in_step): every task reached each step within a window of
+//!   the others, as tasks that share the runtime fairly do;
+//! - [`serialized`](Timeline::serialized): each task ran start to finish before the
+//!   next began, as tasks blocking one thread do;
+//! - [`max_gap`](Timeline::max_gap): one task never went longer than a gap between
+//!   steps, as a heartbeat on an unblocked runtime doesn't.
+//!
+//! A broken property is a [`Violation`], which prints the whole timeline along with
+//! what was wrong, so a failing test shows what happened instead of just that it did.
+//! Times come from tokio's clock, so tests on a paused clock get exact ones.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Task `task` reached step `step`, `at` after the recorder was made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub task: usize,
+    pub step: usize,
+    pub at: Duration,
+}
+
+/// Where tasks record their events. Cheap to clone: every clone records into the same
+/// timeline.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    start: Instant,
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    /// Starts the clock.
+    pub fn new() -> Self {
+        Self { start: Instant::now(), events: Arc::default() }
+    }
+
+    pub fn record(&self, task: usize, step: usize) {
+        let at = self.start.elapsed();
+        self.events.lock().expect("recording doesn't panic").push(Event { task, step, at });
+    }
+
+    /// Since the recorder was made.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// What's been recorded so far.
+    pub fn timeline(&self) -> Timeline {
+        Timeline::from_events(self.events.lock().expect("recording doesn't panic").clone())
+    }
+}
+
+/// Recorded events, in the order they happened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    events: Vec<Event>,
+}
+
+impl Timeline {
+    pub fn from_events(mut events: Vec<Event>) -> Self {
+        // Stable, so events recorded at the same instant keep the order they were
+        // recorded in.
+        events.sort_by_key(|event| event.at);
+        Self { events }
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// From the recorder's start to the last event.
+    pub fn elapsed(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |event| event.at)
+    }
+
+    /// The tasks that recorded anything, in order.
+    pub fn tasks(&self) -> Vec<usize> {
+        let mut tasks: Vec<usize> = self.events.iter().map(|event| event.task).collect();
+        tasks.sort_unstable();
+        tasks.dedup();
+        tasks
+    }
+
+    /// The widest gap, over all steps, between the first task to reach a step and the
+    /// last, with the step it was at.
+    pub fn max_step_spread(&self) -> Option<(usize, Duration)> {
+        let mut steps: BTreeMap<usize, (Duration, Duration)> = BTreeMap::new();
+        for event in &self.events {
+            let (first, last) = steps.entry(event.step).or_insert((event.at, event.at));
+            *first = (*first).min(event.at);
+            *last = (*last).max(event.at);
+        }
+        // The earliest step of the widest, if there's a tie.
+        steps.into_iter().map(|(step, (first, last))| (step, last - first)).rev().max_by_key(|&(_, spread)| spread)
+    }
+
+    /// Every task reached every step within `window` of the others. Tasks making
+    /// progress together, that is, rather than one then another.
+    pub fn in_step(&self, window: Duration) -> Result<(), Violation> {
+        self.check("in step", || {
+            let tasks = self.tasks();
+            let mut steps: Vec<usize> = self.events.iter().map(|event| event.step).collect();
+            steps.sort_unstable();
+            steps.dedup();
+            for step in steps {
+                let reached: Vec<usize> = self.events.iter().filter(|event| event.step == step).map(|event| event.task).collect();
+                if let Some(missing) = tasks.iter().find(|task| !reached.contains(task)) {
+                    return Err(format!("task {missing} never reached step {step}"));
+                }
+            }
+            match self.max_step_spread() {
+                Some((step, spread)) if spread > window => Err(format!("step {step} was reached over {spread:?}, more than {window:?}")),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    /// Each task's events are together: once a task has recorded something and
+    /// another task has, the first never records again. Tasks running one after
+    /// another, that is, rather than taking turns.
+    pub fn serialized(&self) -> Result<(), Violation> {
+        self.check("serialized", || {
+            let mut finished: Vec<usize> = Vec::new();
+            for pair in self.events.windows(2) {
+                let (before, after) = (pair[0], pair[1]);
+                if before.task == after.task {
+                    continue;
+                }
+                if finished.contains(&after.task) {
+                    return Err(format!("task {} came back at {:?}, after task {} had started", after.task, after.at, before.task));
+                }
+                finished.push(before.task);
+            }
+            Ok(())
+        })
+    }
+
+    /// `task` never went longer than `gap` between two events, or before its first.
+    pub fn max_gap(&self, task: usize, gap: Duration) -> Result<(), Violation> {
+        self.check(&format!("task {task} at most {gap:?} between steps"), || {
+            let mut last = Duration::ZERO;
+            let mut any = false;
+            for event in self.events.iter().filter(|event| event.task == task) {
+                if event.at - last > gap {
+                    return Err(format!("nothing between {last:?} and {:?}", event.at));
+                }
+                last = event.at;
+                any = true;
+            }
+            if any { Ok(()) } else { Err("it never recorded anything".to_string()) }
+        })
+    }
+
+    fn check(&self, property: &str, check: impl FnOnce() -> Result<(), String>) -> Result<(), Violation> {
+        check().map_err(|detail| Violation {
+            property: property.to_string(),
+            detail,
+            timeline: self.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Timeline {
+    /// One line per event, for reading.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "+{:>5}ms  task {} step {}", event.at.as_millis(), event.task, event.step)?;
+        }
+        Ok(())
+    }
+}
+
+/// A property a timeline didn't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Which one.
+    pub property: String,
+    /// How it was broken.
+    pub detail: String,
+    pub timeline: Timeline,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not {}: {}\n{}", self.property, self.detail, self.timeline)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(task, step, ms)` triples.
+    fn timeline(events: &[(usize, usize, u64)]) -> Timeline {
+        Timeline::from_events(events.iter().map(|&(task, step, ms)| Event { task, step, at: Duration::from_millis(ms) }).collect())
+    }
+
+    #[test]
+    fn test_tasks_taking_turns_are_in_step_and_not_serialized() {
+        let turns = timeline(&[(0, 0, 0), (1, 0, 1), (0, 1, 60), (1, 1, 61)]);
+        assert_eq!(turns.in_step(Duration::from_millis(5)), Ok(()));
+        let e = turns.serialized().unwrap_err();
+        assert!(e.detail.contains("task 0 came back at 60ms"), "{e}");
+    }
+
+    #[test]
+    fn test_tasks_one_after_another_are_serialized_and_not_in_step() {
+        let queue = timeline(&[(0, 0, 0), (0, 1, 60), (1, 0, 120), (1, 1, 180)]);
+        assert_eq!(queue.serialized(), Ok(()));
+        let e = queue.in_step(Duration::from_millis(100)).unwrap_err();
+        assert_eq!(e.detail, "step 0 was reached over 120ms, more than 100ms");
+        // The whole timeline is in the message.
+        assert!(e.to_string().ends_with("+  180ms  task 1 step 1\n"), "{e}");
+    }
+
+    #[test]
+    fn test_a_task_that_skips_a_step_is_not_in_step() {
+        let e = timeline(&[(0, 0, 0), (1, 0, 0), (0, 1, 10)]).in_step(Duration::from_secs(1)).unwrap_err();
+        assert_eq!(e.detail, "task 1 never reached step 1");
+    }
+
+    #[test]
+    fn test_max_gap_counts_from_the_start() {
+        let beats = timeline(&[(0, 0, 10), (0, 1, 20), (1, 0, 25), (0, 2, 60)]);
+        assert_eq!(beats.max_gap(0, Duration::from_millis(40)), Ok(()));
+        assert_eq!(beats.max_gap(0, Duration::from_millis(30)).unwrap_err().detail, "nothing between 20ms and 60ms");
+        assert_eq!(beats.max_gap(1, Duration::from_millis(20)).unwrap_err().detail, "nothing between 0ns and 25ms");
+        assert_eq!(beats.max_gap(2, Duration::from_secs(1)).unwrap_err().detail, "it never recorded anything");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_the_recorder_uses_tokios_clock() {
+        let recorder = Recorder::new();
+        let tasks: Vec<_> = (0..2)
+            .map(|task| {
+                let recorder = recorder.clone();
+                tokio::spawn(async move {
+                    for step in 0..3 {
+                        recorder.record(task, step);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let timeline = recorder.timeline();
+        assert_eq!(timeline.tasks(), [0, 1]);
+        assert_eq!(timeline.elapsed(), Duration::from_millis(100));
+        assert_eq!(timeline.max_step_spread(), Some((0, Duration::ZERO)));
+    }
+}
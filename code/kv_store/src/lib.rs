@@ -0,0 +1,168 @@
+//! An in-memory key-value store over TCP, saved to a JSON file on shutdown.
+//!
+//! Every connection task gets an `Arc<Store>`, and the map inside sits behind a
+//! `RwLock`: any number of `GET`s can read at once, while a `SET` or `DEL` waits for
+//! them to finish and holds everyone else off while it writes. `STATS` shows how
+//! often a lock had to wait; hammer the server with writes from a few clients and
+//! watch `contended` climb.
+//!
+//! Shutdown has one more step than in the echo servers: once the connections have
+//! drained, nobody else can touch the map, so that's the moment to take a snapshot and
+//! write it out. Saving before the drain would lose writes that were still in flight.
+//! On the next start the server loads the file back.
+
+pub mod persist;
+mod protocol;
+mod store;
+
+pub use store::{Stats, Store};
+
+use protocol::Command;
+use shutdown_util::ShutdownController;
+use shutdown_util::backoff::AcceptBackoff;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct KvConfig {
+    /// Where the map is loaded from at startup and saved to at shutdown. `None` keeps
+    /// it in memory only.
+    pub data_file: Option<PathBuf>,
+    /// How long connections get to finish after shutdown.
+    pub drain_timeout: Duration,
+}
+
+/// Serves the store to clients accepted on `listener` until shutdown is signalled,
+/// then drains and saves.
+pub async fn run_server(
+    listener: TcpListener,
+    config: KvConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let map = match &config.data_file {
+        Some(path) => persist::load(path)?,
+        None => Default::default(),
+    };
+    println!("[server] loaded {} key(s)", map.len());
+    let store = Arc::new(Store::new(map));
+    let mut next_conn_id = 0_u64;
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[server] shutdown requested, no longer accepting");
+                break;
+            }
+            accepted = listener.accept() => {
+                let (socket, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let delay = backoff.on_error();
+                        eprintln!("[server] accept failed: {e}, retrying in {delay:?}");
+                        sleep(delay).await;
+                        continue;
+                    }
+                };
+                if let Some(failures) = backoff.on_success() {
+                    println!("[server] accepting again after {failures} failure(s)");
+                }
+                next_conn_id += 1;
+                let id = next_conn_id;
+                let store = store.clone();
+                let conn_shutdown = controller.subscribe();
+                controller.spawn(async move {
+                    match serve_client(socket, id, peer, &store, conn_shutdown).await {
+                        Ok(commands) => println!("[server] conn={id} peer={peer} closed after {commands} command(s)"),
+                        Err(e) => eprintln!("[server] conn={id} peer={peer} failed: {e}"),
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every client that ever disconnected piling up, and leaves the drain only the
+            // ones still open.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[server] connection task join error: {e}");
+                }
+            }
+        }
+    }
+    drop(listener);
+
+    println!(
+        "[server] waiting up to {:?} for {} connection(s) to finish",
+        config.drain_timeout,
+        controller.active_tasks()
+    );
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[server] connection task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[server] drain deadline hit, aborted {} connection(s)", report.aborted);
+    }
+
+    // Every connection task is gone, so the snapshot can't miss a write that was
+    // still on its way in.
+    let stats = store.stats();
+    println!(
+        "[server] {} read(s), {} write(s), {} contended lock acquisition(s)",
+        stats.reads, stats.writes, stats.contended
+    );
+    if let Some(path) = &config.data_file {
+        let snapshot = store.snapshot();
+        persist::save(path, &snapshot)?;
+        println!("[server] saved {} key(s) to {}", snapshot.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Answers one client's commands until it hangs up or the server shuts down. Returns
+/// how many commands it ran.
+async fn serve_client(
+    socket: TcpStream,
+    id: u64,
+    peer: SocketAddr,
+    store: &Store,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<u64> {
+    println!("[server] conn={id} peer={peer} connected");
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut commands = 0;
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                writer.write_all(b"BYE server shutting down\n").await?;
+                writer.shutdown().await?;
+                return Ok(commands);
+            }
+            // `next_line` is cancel safe: if shutdown wins, a half-read line stays
+            // buffered and is simply never answered.
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(commands);
+                };
+                let reply = match Command::parse(&line) {
+                    Ok(command) => {
+                        commands += 1;
+                        command.execute(store)
+                    }
+                    Err(e) => format!("ERR {e}"),
+                };
+                writer.write_all(format!("{reply}\n").as_bytes()).await?;
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use clap::Parser;
+use kv_store::{KvConfig, run_server};
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// A key-value store over TCP that saves its contents on Ctrl-C or SIGTERM.
+///
+/// Try it with `nc 127.0.0.1 7011` and type `SET name value`, `GET name`, `DEL name`
+/// or `STATS`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:7011")]
+    bind: SocketAddr,
+    /// JSON file the map is loaded from at startup and saved to at shutdown.
+    #[arg(long, default_value = "kv-store.json")]
+    data_file: PathBuf,
+    /// Keep the map in memory only; don't load or save it.
+    #[arg(long, conflicts_with = "data_file")]
+    no_persist: bool,
+    /// Seconds connections get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = KvConfig {
+        data_file: (!cli.no_persist).then_some(cli.data_file),
+        drain_timeout: cli.drain_timeout,
+    };
+
+    let listener = TcpListener::bind(cli.bind).await?;
+    println!("[main] listening on {}", listener.local_addr()?);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let server = tokio::spawn(run_server(listener, config, controller, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match server.await {
+        Ok(Ok(())) => println!("[main] server exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
+        Err(e) => eprintln!("[main] server task failed: {e}"),
+    }
+    Ok(())
+}
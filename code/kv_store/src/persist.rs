@@ -0,0 +1,27 @@
+//! Loading the map at startup and saving it at shutdown.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Reads the map saved in `path`, or an empty one if there's no file yet.
+pub fn load(path: &Path) -> io::Result<HashMap<String, String>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", path.display()))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes `map` to `path` as JSON.
+///
+/// The JSON goes to a temporary file that's renamed into place, so a crash halfway
+/// through leaves the previous save intact rather than a truncated file that won't
+/// load.
+pub fn save(path: &Path, map: &HashMap<String, String>) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let json = serde_json::to_vec_pretty(map).map_err(io::Error::other)?;
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, path)
+}
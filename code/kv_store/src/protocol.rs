@@ -0,0 +1,102 @@
+//! The text protocol: one command per line, one reply line per command.
+//!
+//! ```text
+//! SET greeting hello world    ->  OK
+//! GET greeting                ->  VALUE hello world
+//! DEL greeting                ->  DELETED
+//! GET greeting                ->  NOT_FOUND
+//! STATS                       ->  STATS reads=2 writes=2 contended=0 keys=0
+//! ```
+//!
+//! Keys are a single word; a value is the rest of the line, so it may contain spaces.
+//! Command names are case-insensitive.
+
+use crate::store::Store;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Command<'a> {
+    Get(&'a str),
+    Set(&'a str, &'a str),
+    Del(&'a str),
+    Stats,
+}
+
+impl<'a> Command<'a> {
+    pub(crate) fn parse(line: &'a str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim_start();
+        if name.is_empty() {
+            return Err("empty command".to_string());
+        }
+        let name = name.to_ascii_uppercase();
+        match (name.as_str(), args) {
+            ("GET", key) if is_key(key) => Ok(Command::Get(key)),
+            ("DEL", key) if is_key(key) => Ok(Command::Del(key)),
+            ("SET", args) => match args.split_once(' ') {
+                Some((key, value)) => Ok(Command::Set(key, value)),
+                None => Err("usage: SET key value".to_string()),
+            },
+            ("STATS", "") => Ok(Command::Stats),
+            ("GET" | "DEL", _) => Err(format!("usage: {name} key")),
+            _ => Err(format!("unknown command {name:?}")),
+        }
+    }
+
+    /// Runs the command against `store` and returns the reply line.
+    pub(crate) fn execute(self, store: &Store) -> String {
+        match self {
+            Command::Get(key) => match store.get(key) {
+                Some(value) => format!("VALUE {value}"),
+                None => "NOT_FOUND".to_string(),
+            },
+            Command::Set(key, value) => {
+                store.set(key.to_string(), value.to_string());
+                "OK".to_string()
+            }
+            Command::Del(key) => if store.del(key) { "DELETED" } else { "NOT_FOUND" }.to_string(),
+            Command::Stats => {
+                let stats = store.stats();
+                format!(
+                    "STATS reads={} writes={} contended={} keys={}",
+                    stats.reads,
+                    stats.writes,
+                    stats.contended,
+                    store.len()
+                )
+            }
+        }
+    }
+}
+
+fn is_key(word: &str) -> bool {
+    !word.is_empty() && !word.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse("GET a"), Ok(Command::Get("a")));
+        assert_eq!(Command::parse("set a hello world\r"), Ok(Command::Set("a", "hello world")));
+        assert_eq!(Command::parse("Del  a"), Ok(Command::Del("a")));
+        assert_eq!(Command::parse("STATS"), Ok(Command::Stats));
+        assert!(Command::parse("GET").is_err());
+        assert!(Command::parse("GET a b").is_err());
+        assert!(Command::parse("SET a").is_err());
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("INCR a").is_err());
+    }
+
+    #[test]
+    fn test_execute() {
+        let store = Store::default();
+        assert_eq!(Command::Set("a", "1 2").execute(&store), "OK");
+        assert_eq!(Command::Get("a").execute(&store), "VALUE 1 2");
+        assert_eq!(Command::Del("a").execute(&store), "DELETED");
+        assert_eq!(Command::Del("a").execute(&store), "NOT_FOUND");
+        assert_eq!(Command::Get("a").execute(&store), "NOT_FOUND");
+    }
+}
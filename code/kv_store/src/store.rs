@@ -0,0 +1,143 @@
+//! The map every connection shares, and the counters that show how often they got in
+//! each other's way.
+//!
+//! It's a `std::sync::RwLock`, not Tokio's: each command holds the lock for one
+//! `HashMap` operation and never across an `.await`, so a blocking lock is both correct
+//! and cheaper. (Holding a `std` guard across an `.await` wouldn't even compile in a
+//! spawned task, because the guard isn't `Send`.)
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+/// How often the lock was taken, and how often it had to wait.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub reads: u64,
+    pub writes: u64,
+    /// Lock acquisitions, read or write, that found the lock held in a conflicting
+    /// mode and had to block. Readers only conflict with a writer; a writer conflicts
+    /// with everyone.
+    pub contended: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Store {
+    map: RwLock<HashMap<String, String>>,
+    reads: AtomicU64,
+    writes: AtomicU64,
+    contended: AtomicU64,
+}
+
+impl Store {
+    pub fn new(map: HashMap<String, String>) -> Self {
+        Self {
+            map: RwLock::new(map),
+            ..Self::default()
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.read().get(key).cloned()
+    }
+
+    pub fn set(&self, key: String, value: String) {
+        self.write().insert(key, value);
+    }
+
+    pub fn del(&self, key: &str) -> bool {
+        self.write().remove(key).is_some()
+    }
+
+    /// A copy of the whole map, taken under one read lock so it's consistent. The
+    /// caller does the slow part (serializing, writing to disk) after the lock is gone.
+    pub fn snapshot(&self) -> HashMap<String, String> {
+        self.read().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Takes the read lock, counting it as contended if it wasn't free.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, String>> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        match self.map.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                self.map.read().unwrap()
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("store lock poisoned: {e}"),
+        }
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, String>> {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        match self.map.try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                self.map.write().unwrap()
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("store lock poisoned: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_get_set_del() {
+        let store = Store::default();
+        assert_eq!(store.get("a"), None);
+        store.set("a".into(), "1".into());
+        assert_eq!(store.get("a").as_deref(), Some("1"));
+        assert!(store.del("a"));
+        assert!(!store.del("a"));
+        assert_eq!(
+            store.stats(),
+            Stats {
+                reads: 2,
+                writes: 3,
+                contended: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_reader_waiting_on_a_writer_counts_as_contended() {
+        let store = Arc::new(Store::default());
+        let guard = store.map.write().unwrap();
+        let reader = std::thread::spawn({
+            let store = store.clone();
+            move || store.get("a")
+        });
+        // The reader counts the contention when its `try_read` fails, which can only
+        // happen while we hold the guard. Once the count shows up it's safe to let go.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while store.stats().contended == 0 {
+            assert!(Instant::now() < deadline, "the reader never found the lock taken");
+            std::thread::yield_now();
+        }
+        drop(guard);
+        assert_eq!(reader.join().unwrap(), None);
+        assert_eq!(store.stats().contended, 1);
+    }
+}
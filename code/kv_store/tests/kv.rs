@@ -0,0 +1,109 @@
+use kv_store::{KvConfig, persist, run_server};
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+async fn start_server(data_file: Option<PathBuf>) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    let config = KvConfig {
+        data_file,
+        drain_timeout: Duration::from_secs(2),
+    };
+    spawn_server(|listener, controller, shutdown_rx| run_server(listener, config, controller, shutdown_rx)).await
+}
+
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Client {
+    async fn connect(addr: SocketAddr) -> Self {
+        let (reader, writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    async fn call(&mut self, command: &str) -> String {
+        self.writer.write_all(format!("{command}\n").as_bytes()).await.unwrap();
+        self.line().await.expect("connection closed")
+    }
+
+    async fn line(&mut self) -> Option<String> {
+        timeout(Duration::from_secs(2), self.lines.next_line())
+            .await
+            .expect("timed out waiting for a reply")
+            .unwrap()
+    }
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("kv-store-{name}-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn cleanup(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn test_get_set_del_across_connections() {
+    let (addr, trigger, task) = start_server(None).await;
+    let mut a = Client::connect(addr).await;
+    let mut b = Client::connect(addr).await;
+
+    assert_eq!(a.call("SET greeting hello world").await, "OK");
+    assert_eq!(b.call("GET greeting").await, "VALUE hello world");
+    assert_eq!(b.call("DEL greeting").await, "DELETED");
+    assert_eq!(a.call("GET greeting").await, "NOT_FOUND");
+    assert!(a.call("FROB").await.starts_with("ERR "));
+
+    trigger.trigger();
+    assert_eq!(a.line().await.unwrap(), "BYE server shutting down");
+    assert_eq!(a.line().await, None);
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_map_survives_a_restart() {
+    let path = temp_file("restart");
+
+    let (addr, trigger, task) = start_server(Some(path.clone())).await;
+    let mut client = Client::connect(addr).await;
+    assert_eq!(client.call("SET a 1").await, "OK");
+    assert_eq!(client.call("SET b 2").await, "OK");
+    assert_eq!(client.call("DEL b").await, "DELETED");
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+
+    let saved = persist::load(&path).unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved["a"], "1");
+
+    let (addr, trigger, task) = start_server(Some(path.clone())).await;
+    let mut client = Client::connect(addr).await;
+    assert_eq!(client.call("GET a").await, "VALUE 1");
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+    cleanup(&path);
+}
+
+#[tokio::test]
+async fn test_corrupt_data_file_is_an_error() {
+    let path = temp_file("corrupt");
+    std::fs::write(&path, "not json").unwrap();
+
+    let (_, _trigger, task) = start_server(Some(path.clone())).await;
+    let err = task.await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    cleanup(&path);
+}
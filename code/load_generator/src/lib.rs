@@ -0,0 +1,449 @@
+//! Load for the echo servers: a number of connections, each sending a message, waiting
+//! for it to come back and timing the round trip, and a report of the throughput and
+//! the latency percentiles at the end.
+//!
+//! Without a rate every connection sends its next message as soon as the last one is
+//! back (a closed loop), which finds out how much the server can take. With one, the
+//! messages go out on a timetable instead, `rate / connections` a second on each
+//! connection, and each latency is measured from when the message was *due*, not from
+//! when it was sent. That matters when the server stalls. A connection waiting on a
+//! stalled echo can't send anything else, so the messages due in the meantime go out
+//! late, all at once when it recovers, and their latency includes the wait. Timing
+//! from the actual send would record one slow message and hide the rest of the stall
+//! (the "coordinated omission" that makes many load tools look better than the server
+//! would to real clients). The timetable only works while a connection keeps up: one
+//! slower than its share of the rate falls further behind, and the percentiles show it.
+//! A message that was only late because tokio's timer, with its millisecond resolution,
+//! fired a little after it was due is timed from when it went out: that wait is ours,
+//! not the server's.
+//!
+//! The connections open one after another over `ramp`, rather than all in the first
+//! millisecond, and nothing in the first `warmup` is counted, while connections are
+//! still opening and caches are cold. Then the measurement runs for `duration`.
+//!
+//! Each connection records into its own histogram (microseconds, three significant
+//! digits), so recording never contends; they're merged at the end. A connection that
+//! fails, to connect, mid-round-trip, or by the server hanging up (shutting down, say),
+//! stops there, and its error is in the report.
+//!
+//! For a soak (a long run to find leaks, in the server's connection handling or its
+//! `JoinSet`, before they find a workshop), `churn` makes each connection hang up after
+//! so many round trips and open a new one straight away, so the server sees a steady
+//! stream of connections coming and going rather than the same few for an hour; and
+//! `progress_interval` hands over a [`Progress`] snapshot that often, with this
+//! process's resident set, so a leak on the client's side shows up too. A message due
+//! while its connection was reopening is timed from when it was due, as if the reopen
+//! were a slow echo.
+
+mod protocol;
+
+pub use protocol::Protocol;
+
+use hdrhistogram::Histogram;
+use protocol::{Connection, Message};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::{Instant, Interval, MissedTickBehavior, interval, sleep_until, timeout};
+
+/// The longest latency the histograms can hold; anything slower is recorded as this.
+const HIGHEST_LATENCY: Duration = Duration::from_secs(60);
+
+/// Settings for [`run_load`].
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    /// The server.
+    pub addr: SocketAddr,
+    pub protocol: Protocol,
+    /// How many connections to open.
+    pub connections: usize,
+    /// Messages a second, over all the connections; `None` to send as fast as the echoes
+    /// come back.
+    pub rate: Option<f64>,
+    /// Bytes per message.
+    pub message_size: usize,
+    /// How long to measure for, after the warm-up.
+    pub duration: Duration,
+    /// How long to run before measuring.
+    pub warmup: Duration,
+    /// How long to take opening the connections.
+    pub ramp: Duration,
+    /// How long a round trip may take before the connection gives up on it.
+    pub timeout: Duration,
+    /// Round trips on each connection before it hangs up and opens a new one; `None`
+    /// to keep it for the whole run.
+    pub churn: Option<u64>,
+    /// How often to hand over a [`Progress`] snapshot; `None` for only the report at
+    /// the end.
+    pub progress_interval: Option<Duration>,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], 3011)),
+            protocol: Protocol::Raw,
+            connections: 8,
+            rate: None,
+            message_size: 64,
+            duration: Duration::from_secs(10),
+            warmup: Duration::from_secs(2),
+            ramp: Duration::ZERO,
+            timeout: Duration::from_secs(5),
+            churn: None,
+            progress_interval: None,
+        }
+    }
+}
+
+/// What one run measured.
+#[derive(Debug)]
+pub struct Report {
+    /// Connections opened, counting the ones `churn` opened in place of others.
+    pub connections: usize,
+    /// Echoes that came back inside the measurement window.
+    pub messages: u64,
+    /// Their payload bytes, one way.
+    pub bytes: u64,
+    /// How long the measurement window was; shorter than `duration` if stopped early.
+    pub elapsed: Duration,
+    /// The round-trip latencies, in microseconds, of the messages due inside the
+    /// window.
+    pub latency: Histogram<u64>,
+    /// Why connections stopped early, at most one each.
+    pub errors: Vec<io::Error>,
+}
+
+impl Report {
+    pub fn messages_per_sec(&self) -> f64 {
+        per_sec(self.messages, self.elapsed)
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        per_sec(self.bytes, self.elapsed)
+    }
+
+    /// The latency below which `quantile` (0.99 for p99) of the round trips came in.
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        Duration::from_micros(self.latency.value_at_quantile(quantile))
+    }
+}
+
+fn per_sec(count: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() { 0.0 } else { count as f64 / elapsed.as_secs_f64() }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "connections  {} opened, {} failed", self.connections, self.errors.len())?;
+        writeln!(
+            f,
+            "throughput   {} messages in {:.1?}: {:.1}/s, {:.2} MiB/s",
+            self.messages,
+            self.elapsed,
+            self.messages_per_sec(),
+            self.bytes_per_sec() / (1024.0 * 1024.0)
+        )?;
+        write!(f, "latency     ")?;
+        for (name, quantile) in [("p50", 0.5), ("p95", 0.95), ("p99", 0.99), ("p99.9", 0.999)] {
+            write!(f, " {name} {:.1?} ", self.percentile(quantile))?;
+        }
+        write!(f, " max {:.1?}", Duration::from_micros(self.latency.max()))?;
+        if let Some(e) = self.errors.first() {
+            write!(f, "\nfirst error  {e}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a run has got to, every `progress_interval`.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// Since the run started, warm-up included.
+    pub elapsed: Duration,
+    /// Echoes that came back since the last snapshot.
+    pub messages: u64,
+    /// How long since the last snapshot.
+    pub interval: Duration,
+    /// Connections opened so far.
+    pub opened: u64,
+    /// Connections open now.
+    pub open: u64,
+    /// Connections that have failed so far.
+    pub failed: u64,
+    /// This process's resident set, in bytes, where the platform says (Linux).
+    pub rss: Option<u64>,
+}
+
+impl Progress {
+    pub fn messages_per_sec(&self) -> f64 {
+        per_sec(self.messages, self.interval)
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>6.0?}  {:.1}/s, {} open, {} opened, {} failed",
+            self.elapsed,
+            self.messages_per_sec(),
+            self.open,
+            self.opened,
+            self.failed
+        )?;
+        match self.rss {
+            Some(rss) => write!(f, ", rss {:.1} MiB", rss as f64 / (1024.0 * 1024.0)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What the connections count as they go, for [`Progress`].
+#[derive(Debug, Default)]
+struct Counters {
+    messages: AtomicU64,
+    opened: AtomicU64,
+    closed: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// When one connection may do what.
+#[derive(Debug, Clone, Copy)]
+struct Timetable {
+    start: Instant,
+    measure_from: Instant,
+    end: Instant,
+}
+
+/// What one connection measured.
+struct Outcome {
+    opened: usize,
+    messages: u64,
+    latency: Histogram<u64>,
+    error: Option<io::Error>,
+}
+
+/// Runs the load described by `config` and reports on it. Finishes after the warm-up
+/// and `duration`, or when `stop` does, whichever is first; a round trip under way at
+/// the end gets to finish, one cut off by `stop` doesn't.
+pub async fn run_load(config: LoadConfig, stop: impl Future<Output = ()>) -> io::Result<Report> {
+    run_load_with_progress(config, stop, |_| {}).await
+}
+
+/// [`run_load`], calling `on_progress` every `progress_interval` along the way.
+pub async fn run_load_with_progress(config: LoadConfig, stop: impl Future<Output = ()>, mut on_progress: impl FnMut(&Progress)) -> io::Result<Report> {
+    if config.connections == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no connections to open"));
+    }
+    if config.rate.is_some_and(|rate| !rate.is_finite() || rate <= 0.0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the rate has to be a positive number"));
+    }
+    if config.churn == Some(0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "a connection has to make at least one round trip before churning"));
+    }
+    let started = Instant::now();
+    let measure_from = started + config.warmup;
+    let end = measure_from + config.duration;
+    // On each connection, one message this often; `interval` refuses zero.
+    let period = config.rate.map(|rate| Duration::from_secs_f64(config.connections as f64 / rate).max(Duration::from_nanos(1)));
+    let message = Arc::new(Message::new(config.message_size));
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let counters = Arc::new(Counters::default());
+
+    let mut tasks = JoinSet::new();
+    for n in 0..config.connections {
+        let timetable = Timetable {
+            start: started + config.ramp.mul_f64(n as f64 / config.connections as f64),
+            measure_from,
+            end,
+        };
+        tasks.spawn(drive(config.clone(), timetable, period, message.clone(), counters.clone(), stop_rx.clone()));
+    }
+
+    // At the end the connections stop by themselves, each after its last round trip.
+    // Only an early stop has to tell them.
+    tokio::pin!(stop);
+    let mut stopped_at = None;
+    let mut report = Report {
+        connections: 0,
+        messages: 0,
+        bytes: 0,
+        elapsed: Duration::ZERO,
+        latency: new_histogram(),
+        errors: Vec::new(),
+    };
+    let mut progress = config.progress_interval.map(|period| {
+        let mut ticks = interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick is straight away, before there's any progress.
+        ticks.reset();
+        ticks
+    });
+    let (mut last_progress, mut last_messages) = (started, 0);
+    loop {
+        tokio::select! {
+            () = &mut stop, if stopped_at.is_none() => {
+                let _ = stop_tx.send(true);
+                stopped_at = Some(Instant::now());
+            }
+            joined = tasks.join_next() => {
+                let Some(outcome) = joined else {
+                    break;
+                };
+                let outcome = outcome.map_err(io::Error::other)?;
+                report.connections += outcome.opened;
+                report.messages += outcome.messages;
+                report.latency.add(&outcome.latency).expect("every histogram has the same bounds");
+                report.errors.extend(outcome.error);
+            }
+            now = next_tick(&mut progress) => {
+                let messages = counters.messages.load(Ordering::Relaxed);
+                let opened = counters.opened.load(Ordering::Relaxed);
+                on_progress(&Progress {
+                    elapsed: now - started,
+                    messages: messages - last_messages,
+                    interval: now - last_progress,
+                    opened,
+                    open: opened - counters.closed.load(Ordering::Relaxed),
+                    failed: counters.failed.load(Ordering::Relaxed),
+                    rss: rss_bytes(),
+                });
+                (last_progress, last_messages) = (now, messages);
+            }
+        }
+    }
+    // Early if stopped, or if every connection failed.
+    let window_end = stopped_at.unwrap_or(end).min(Instant::now());
+    report.elapsed = window_end.saturating_duration_since(measure_from);
+    report.bytes = report.messages * config.message_size as u64;
+    Ok(report)
+}
+
+/// Waits for the next tick, or forever without a timer, so a `select!` can always
+/// have the branch.
+async fn next_tick(ticks: &mut Option<Interval>) -> Instant {
+    match ticks {
+        Some(ticks) => ticks.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// This process's resident set, in bytes, from Linux's `/proc/self/status`.
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.trim_start_matches("VmRSS:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn new_histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, HIGHEST_LATENCY.as_micros() as u64, 3).expect("valid bounds")
+}
+
+/// One connection's part: open it when its turn comes, then round trips until the end,
+/// opening a new one every `churn` round trips.
+async fn drive(
+    config: LoadConfig,
+    timetable: Timetable,
+    period: Option<Duration>,
+    message: Arc<Message>,
+    counters: Arc<Counters>,
+    mut stop: watch::Receiver<bool>,
+) -> Outcome {
+    let mut outcome = Outcome {
+        opened: 0,
+        messages: 0,
+        latency: new_histogram(),
+        error: None,
+    };
+    let stopped = async move {
+        let _ = stop.wait_for(|&stopped| stopped).await;
+    };
+    tokio::pin!(stopped);
+
+    tokio::select! {
+        () = sleep_until(timetable.start) => {}
+        () = &mut stopped => return outcome,
+    }
+    let mut ticks: Option<Interval> = None;
+    loop {
+        let mut conn = tokio::select! {
+            opened = Connection::open(config.addr, config.protocol) => match opened {
+                Ok(conn) => conn,
+                Err(e) => {
+                    counters.failed.fetch_add(1, Ordering::Relaxed);
+                    outcome.error = Some(e);
+                    return outcome;
+                }
+            },
+            () = &mut stopped => return outcome,
+        };
+        outcome.opened += 1;
+        counters.opened.fetch_add(1, Ordering::Relaxed);
+        // The first tick, once the first connection is open, is straight away; if a
+        // round trip overruns, the ticks it missed come one after another until the
+        // connection has caught up.
+        if ticks.is_none() {
+            ticks = period.map(interval);
+        }
+        let mut last_echo = Instant::now();
+        let mut round_trips = 0;
+
+        let churned = loop {
+            let due = match &mut ticks {
+                Some(ticks) => tokio::select! {
+                    due = ticks.tick() => due,
+                    () = &mut stopped => break false,
+                },
+                None => Instant::now(),
+            };
+            // Behind the timetable at the end, the messages still owed are never sent.
+            if due >= timetable.end || Instant::now() >= timetable.end {
+                break false;
+            }
+            // Held up by the last echo (or the reopen), or just by the timer.
+            let timed_from = if last_echo > due { due } else { Instant::now() };
+            let result = tokio::select! {
+                result = timeout(config.timeout, conn.round_trip(&message)) => result,
+                () = &mut stopped => break false,
+            };
+            match result {
+                Ok(Ok(())) => {
+                    last_echo = Instant::now();
+                    counters.messages.fetch_add(1, Ordering::Relaxed);
+                    if due >= timetable.measure_from {
+                        outcome.latency.saturating_record((last_echo - timed_from).as_micros() as u64);
+                    }
+                    if (timetable.measure_from..timetable.end).contains(&last_echo) {
+                        outcome.messages += 1;
+                    }
+                    round_trips += 1;
+                    if config.churn.is_some_and(|churn| round_trips >= churn) {
+                        break true;
+                    }
+                }
+                Ok(Err(e)) => {
+                    outcome.error = Some(e);
+                    break false;
+                }
+                Err(_) => {
+                    outcome.error = Some(io::Error::new(io::ErrorKind::TimedOut, format!("no echo within {:?}", config.timeout)));
+                    break false;
+                }
+            }
+        };
+        drop(conn);
+        counters.closed.fetch_add(1, Ordering::Relaxed);
+        counters.failed.fetch_add(u64::from(outcome.error.is_some()), Ordering::Relaxed);
+        if !churned {
+            return outcome;
+        }
+    }
+}
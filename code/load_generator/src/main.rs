@@ -0,0 +1,121 @@
+use clap::Parser;
+use load_generator::{LoadConfig, Protocol, run_load_with_progress};
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Puts an echo server under load and reports the throughput and latency percentiles.
+///
+/// For example, against the graceful shutdown server (`cargo run --release -p
+/// tcp_server_graceful_shutdown`): `cargo run --release -p load_generator --
+/// --connections 64 --rate 20000 --size 256`. Ctrl-C stops early and still reports.
+///
+/// Before a workshop, `--soak 60` runs for an hour, reopening every connection after
+/// 100 round trips and printing progress every 10 seconds; start the server with
+/// `--soak-report` to see its side, and look for numbers that only go up.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// The server's address.
+    #[arg(long, default_value = "127.0.0.1:3011")]
+    addr: SocketAddr,
+    /// How the server delimits messages.
+    #[arg(long, value_enum, default_value_t)]
+    protocol: Protocol,
+    /// How many connections to open.
+    #[arg(short, long, default_value_t = 8)]
+    connections: usize,
+    /// Messages a second, over all the connections. Without it, each connection sends
+    /// its next message as soon as the last one is back.
+    #[arg(long, value_name = "PER_SEC")]
+    rate: Option<f64>,
+    /// Bytes per message.
+    #[arg(long, value_name = "BYTES", default_value_t = 64)]
+    size: usize,
+    /// Seconds to measure for, after the warm-up.
+    #[arg(long, value_name = "SECS", default_value = "10", value_parser = parse_secs)]
+    duration: Duration,
+    /// Seconds to run before measuring.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    warmup: Duration,
+    /// Seconds to take opening the connections, one after another.
+    #[arg(long, value_name = "SECS", default_value = "0", value_parser = parse_secs)]
+    ramp: Duration,
+    /// Seconds a round trip may take before its connection gives up.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    timeout: Duration,
+    /// Round trips on each connection before it hangs up and opens a new one.
+    #[arg(long, value_name = "ROUND_TRIPS")]
+    churn: Option<u64>,
+    /// Print the throughput, the connection counts and this process's memory every
+    /// SECS.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    progress: Option<Duration>,
+    /// Soak for MINUTES instead of measuring for `--duration`: `--churn` defaults to 100
+    /// and `--progress` to 10.
+    #[arg(long, value_name = "MINUTES")]
+    soak: Option<f64>,
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut cli = Cli::parse();
+    if let Some(minutes) = cli.soak {
+        cli.duration = match Duration::try_from_secs_f64(minutes * 60.0) {
+            Ok(duration) => duration,
+            Err(e) => {
+                eprintln!("[main] --soak {minutes}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        cli.churn.get_or_insert(100);
+        cli.progress.get_or_insert(Duration::from_secs(10));
+    }
+    let config = LoadConfig {
+        addr: cli.addr,
+        protocol: cli.protocol,
+        connections: cli.connections,
+        rate: cli.rate,
+        message_size: cli.size,
+        duration: cli.duration,
+        warmup: cli.warmup,
+        ramp: cli.ramp,
+        timeout: cli.timeout,
+        churn: cli.churn,
+        progress_interval: cli.progress,
+    };
+    let rate = cli.rate.map_or("as fast as the echoes come back".to_string(), |rate| format!("{rate}/s"));
+    println!(
+        "[main] {} connection(s) to {}, {}-byte {:?} messages, {rate}; measuring for {:?} after {:?}",
+        cli.connections, cli.addr, cli.size, cli.protocol, cli.duration, cli.warmup
+    );
+
+    if let Some(churn) = cli.churn {
+        println!("[main] each connection reopens after {churn} round trips");
+    }
+
+    match run_load_with_progress(config, ctrl_c(), |progress| println!("[main] {progress}")).await {
+        Ok(report) => {
+            println!("{report}");
+            if report.connections == 0 { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+        }
+        Err(e) => {
+            eprintln!("[main] {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn ctrl_c() {
+    match tokio::signal::ctrl_c().await {
+        Ok(()) => println!("[main] stopping early"),
+        Err(e) => {
+            eprintln!("[main] can't listen for Ctrl-C, so there's no stopping early: {e}");
+            std::future::pending().await
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! The three ways of asking for an echo, one message at a time on one connection.
+//!
+//! A round trip is over when as much has come back as was sent (`raw`), when one whole
+//! frame has come back (`length`), or when one whole line has (`lines`). The reply
+//! isn't compared with the message: the line servers put `echo: ` in front, and some
+//! examples answer with something else entirely. It only has to arrive.
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_util::codec::{Framed, LengthDelimitedCodec, LinesCodec};
+
+/// How messages are delimited on the wire.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    /// Bytes, and the same number of bytes back.
+    #[default]
+    Raw,
+    /// A 4-byte big-endian length, then the payload, as `--framing length` expects.
+    Length,
+    /// A newline-terminated line, and any line back.
+    Lines,
+}
+
+/// One client connection.
+pub(crate) enum Connection {
+    Raw { socket: TcpStream, reply: Vec<u8> },
+    Length(Framed<TcpStream, LengthDelimitedCodec>),
+    Lines(Framed<TcpStream, LinesCodec>),
+}
+
+impl Connection {
+    pub(crate) async fn open(addr: SocketAddr, protocol: Protocol) -> io::Result<Self> {
+        let socket = TcpStream::connect(addr).await?;
+        // Each message is one write, and the next waits for its echo: Nagle would only
+        // ever delay them.
+        socket.set_nodelay(true)?;
+        Ok(match protocol {
+            Protocol::Raw => Connection::Raw { socket, reply: Vec::new() },
+            Protocol::Length => Connection::Length(Framed::new(socket, LengthDelimitedCodec::new())),
+            Protocol::Lines => Connection::Lines(Framed::new(socket, LinesCodec::new())),
+        })
+    }
+
+    /// Sends `message` and waits for the reply. The server hanging up first is an
+    /// `UnexpectedEof` error.
+    pub(crate) async fn round_trip(&mut self, message: &Message) -> io::Result<()> {
+        match self {
+            Connection::Raw { socket, reply } => {
+                socket.write_all(&message.bytes).await?;
+                reply.resize(message.bytes.len(), 0);
+                socket.read_exact(reply).await?;
+            }
+            Connection::Length(frames) => {
+                frames.send(message.bytes.clone()).await?;
+                frames.next().await.ok_or_else(hung_up)??;
+            }
+            Connection::Lines(lines) => {
+                lines.send(message.line.as_str()).await.map_err(io::Error::other)?;
+                lines.next().await.ok_or_else(hung_up)?.map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The message every connection sends over and over, made once.
+pub(crate) struct Message {
+    bytes: Bytes,
+    line: String,
+}
+
+impl Message {
+    pub(crate) fn new(size: usize) -> Self {
+        let line = "x".repeat(size);
+        Self {
+            bytes: Bytes::from(line.clone().into_bytes()),
+            line,
+        }
+    }
+}
+
+fn hung_up() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection")
+}
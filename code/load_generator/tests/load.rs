@@ -0,0 +1,151 @@
+use load_generator::{LoadConfig, Protocol, Report, run_load, run_load_with_progress};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::{Framing, Server, ServerHandle};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::{Instant, sleep, timeout};
+
+async fn start_server(framing: Framing) -> ServerHandle {
+    Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .framing(framing)
+        .build()
+        .await
+        .expect("bind ephemeral port")
+        .start()
+}
+
+/// Short enough for a test, long enough to see something.
+fn config(addr: SocketAddr) -> LoadConfig {
+    LoadConfig {
+        addr,
+        connections: 4,
+        duration: Duration::from_millis(500),
+        warmup: Duration::from_millis(100),
+        ..LoadConfig::default()
+    }
+}
+
+async fn run(config: LoadConfig) -> Report {
+    timeout(Duration::from_secs(10), run_load(config, std::future::pending()))
+        .await
+        .expect("the run ends by itself")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_measures_every_protocol() {
+    for (framing, protocol) in [(Framing::Raw, Protocol::Raw), (Framing::Length, Protocol::Length), (Framing::Lines, Protocol::Lines)] {
+        let server = start_server(framing).await;
+        let report = run(LoadConfig { protocol, ..config(server.local_addr()) }).await;
+
+        assert_eq!(report.connections, 4, "{protocol:?}");
+        assert!(report.errors.is_empty(), "{protocol:?}: {:?}", report.errors);
+        assert!(report.messages > 0, "{protocol:?}");
+        assert_eq!(report.bytes, report.messages * 64);
+        assert!(report.percentile(0.5) <= report.percentile(0.99));
+        assert!(report.percentile(0.99) <= Duration::from_micros(report.latency.max()));
+
+        server.shutdown();
+        server.await_terminated().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_keeps_to_the_rate() {
+    let server = start_server(Framing::Raw).await;
+    let report = run(LoadConfig { rate: Some(400.0), ..config(server.local_addr()) }).await;
+
+    // 400 a second over half a second, give or take a tick at either end of each
+    // connection, and some slack for a busy machine; nothing from the warm-up.
+    assert!((170..=210).contains(&report.messages), "{} messages", report.messages);
+    assert!((170..=205).contains(&report.latency.len()), "{} latencies", report.latency.len());
+    assert_eq!(report.elapsed, Duration::from_millis(500));
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stops_early_when_asked() {
+    let server = start_server(Framing::Raw).await;
+    let started = Instant::now();
+    let stop = sleep(Duration::from_millis(300));
+    let config = LoadConfig { duration: Duration::from_secs(60), ..config(server.local_addr()) };
+    let report = run_load(config, stop).await.unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(report.elapsed < Duration::from_millis(300), "{:?}", report.elapsed);
+    assert!(report.messages > 0);
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_a_server_hanging_up_is_an_error() {
+    // Echoes one message on each connection, then closes it.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 64];
+                socket.read_exact(&mut buf).await?;
+                socket.write_all(&buf).await
+            });
+        }
+    });
+    let report = run(LoadConfig { warmup: Duration::ZERO, ..config(addr) }).await;
+
+    assert_eq!(report.connections, 4);
+    assert_eq!(report.messages, 4);
+    assert_eq!(report.errors.len(), 4);
+    assert!(report.errors.iter().all(|e| e.kind() == io::ErrorKind::UnexpectedEof), "{:?}", report.errors);
+    // Nothing left to wait for once they've all failed.
+    assert!(report.elapsed < Duration::from_millis(500));
+}
+
+#[tokio::test]
+async fn test_nobody_listening() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let report = run(config(addr)).await;
+
+    assert_eq!(report.connections, 0);
+    assert_eq!(report.errors.len(), 4);
+    assert_eq!(report.messages_per_sec(), 0.0);
+}
+
+#[tokio::test]
+async fn test_churn_keeps_opening_connections() {
+    let server = start_server(Framing::Raw).await;
+    let mut snapshots = Vec::new();
+    let config = LoadConfig {
+        churn: Some(5),
+        progress_interval: Some(Duration::from_millis(100)),
+        ..config(server.local_addr())
+    };
+    let report = timeout(Duration::from_secs(10), run_load_with_progress(config, std::future::pending(), |progress| snapshots.push(*progress)))
+        .await
+        .expect("the run ends by itself")
+        .unwrap();
+
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert!(report.connections > 4, "{} connections", report.connections);
+    assert!(snapshots.len() >= 3, "{snapshots:?}");
+    let last = snapshots.last().unwrap();
+    assert!(last.opened > 4 && last.open <= 4 && last.failed == 0, "{last}");
+    assert!(snapshots.windows(2).all(|pair| pair[0].opened <= pair[1].opened && pair[0].elapsed < pair[1].elapsed), "{snapshots:?}");
+    if cfg!(target_os = "linux") {
+        assert!(last.rss.is_some_and(|rss| rss > 0), "{last}");
+    }
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
@@ -0,0 +1,193 @@
+//! What's under `async`/`await`: a future written by hand, and an executor to run it.
+//!
+//! A future is polled: asked whether it's done yet. If it isn't, it returns
+//! `Poll::Pending`, and it has to arrange for the [`Waker`] in the `Context` it was
+//! polled with to be woken once it might be; the executor polls it again only then. So
+//! a future that returns `Pending` and never arranges a wake is never polled again, and
+//! whatever awaits it hangs, with nothing on any thread to say so.
+//!
+//! [`Delay`] is done at an instant. The first time it's polled too early it starts a
+//! timer thread that sleeps until then and wakes whichever waker it was last polled
+//! with, which matters, as a future can be moved to another task between polls.
+//! [`ForgetfulDelay`] is the same, except that its timer thread marks it done and wakes
+//! nobody: it's done, but nobody is told.
+//!
+//! [`block_on`] is an executor, for one future on the calling thread: it polls, parks
+//! the thread until the waker is woken, and polls again. It gives up after a limit
+//! without a wake, so the hang shows as `None` rather than as a thread stuck for good.
+
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+/// What a delay and its timer thread share.
+#[derive(Debug)]
+struct Shared {
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// A future that's done at `when`, with a thread of its own to say so.
+#[derive(Debug)]
+pub struct Delay {
+    when: Instant,
+    shared: Option<Arc<Mutex<Shared>>>,
+}
+
+impl Delay {
+    pub fn new(after: Duration) -> Self {
+        Self { when: Instant::now() + after, shared: None }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.when {
+            return Poll::Ready(());
+        }
+        match &self.shared {
+            Some(shared) => {
+                let mut shared = shared.lock().unwrap();
+                if shared.done {
+                    return Poll::Ready(());
+                }
+                // Polled again before its time, maybe by another task: the timer has to
+                // wake this one, not the last.
+                if !shared.waker.as_ref().is_some_and(|waker| waker.will_wake(cx.waker())) {
+                    shared.waker = Some(cx.waker().clone());
+                }
+            }
+            None => {
+                let shared = Arc::new(Mutex::new(Shared { done: false, waker: Some(cx.waker().clone()) }));
+                let when = self.when;
+                let timer = shared.clone();
+                thread::spawn(move || {
+                    thread::sleep(when.saturating_duration_since(Instant::now()));
+                    let mut timer = timer.lock().unwrap();
+                    timer.done = true;
+                    // Woken outside the lock, in case the waker polls right there.
+                    let waker = timer.waker.take();
+                    drop(timer);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                });
+                self.shared = Some(shared);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// [`Delay`] with the bug: its timer thread marks it done but never wakes the waker, so
+/// it completes only if something else happens to poll it after its time.
+#[derive(Debug)]
+pub struct ForgetfulDelay {
+    when: Instant,
+    done: Option<Arc<AtomicBool>>,
+}
+
+impl ForgetfulDelay {
+    pub fn new(after: Duration) -> Self {
+        Self { when: Instant::now() + after, done: None }
+    }
+}
+
+impl Future for ForgetfulDelay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.when {
+            return Poll::Ready(());
+        }
+        match &self.done {
+            Some(done) if done.load(Ordering::Acquire) => return Poll::Ready(()),
+            Some(_) => {}
+            None => {
+                let done = Arc::new(AtomicBool::new(false));
+                let when = self.when;
+                let timer = done.clone();
+                thread::spawn(move || {
+                    thread::sleep(when.saturating_duration_since(Instant::now()));
+                    // Done, and nobody told.
+                    timer.store(true, Ordering::Release);
+                });
+                self.done = Some(done);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// A waker for [`block_on`]: wakes by unparking the thread that's waiting, and notes
+/// that it was woken, so a spurious unpark isn't mistaken for a wake.
+struct ThreadWaker {
+    thread: Thread,
+    woken: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// How [`block_on`] got on with a future: what it returned, if it finished, and how many
+/// times it was polled.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Ran<T> {
+    pub output: Option<T>,
+    pub polls: usize,
+}
+
+/// Runs `future` to completion on this thread, polling it once at the start and then
+/// once for each wake, and gives up if `limit` goes by without one.
+pub fn block_on<F: Future>(future: F, limit: Duration) -> Ran<F::Output> {
+    let mut future = pin!(future);
+    let waker = Arc::new(ThreadWaker { thread: thread::current(), woken: AtomicBool::new(false) });
+    let mut polls = 0;
+    let context_waker = Waker::from(waker.clone());
+    let mut cx = Context::from_waker(&context_waker);
+    loop {
+        polls += 1;
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Ran { output: Some(output), polls };
+        }
+        let deadline = Instant::now() + limit;
+        while !waker.woken.swap(false, Ordering::Acquire) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ran { output: None, polls };
+            }
+            thread::park_timeout(left);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_a_delay_thats_due_is_ready_at_once_with_no_thread() {
+        let mut delay = Delay::new(Duration::ZERO);
+        assert!(Pin::new(&mut delay).poll(&mut Context::from_waker(Waker::noop())).is_ready());
+        assert!(delay.shared.is_none());
+    }
+
+    #[test]
+    fn test_block_on_polls_a_ready_future_once() {
+        assert_eq!(block_on(async { 42 }, Duration::ZERO), Ran { output: Some(42), polls: 1 });
+    }
+}
@@ -0,0 +1,55 @@
+use clap::Parser;
+use manual_future::{Delay, ForgetfulDelay, block_on};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// Runs the hand-written delays, on the hand-written executor and then on tokio.
+///
+/// `Delay` finishes at its time, polled twice: once too early, and once when its timer
+/// thread wakes it. `ForgetfulDelay` is done just as soon, but its timer wakes nobody,
+/// so it's polled once and never again: neither executor has any reason to look at it,
+/// and it's given up on after `--limit`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Seconds each delay is for.
+    #[arg(long, value_name = "SECS", default_value = "0.2", value_parser = parse_secs)]
+    after: Duration,
+    /// Seconds to wait for each before calling it a hang.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    limit: Duration,
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let start = Instant::now();
+    let ran = block_on(Delay::new(cli.after), cli.limit);
+    println!("[block_on] Delay: finished {}, after {:?} and {} poll(s)", ran.output.is_some(), start.elapsed(), ran.polls);
+    let start = Instant::now();
+    let ran = block_on(ForgetfulDelay::new(cli.after), cli.limit);
+    println!("[block_on] ForgetfulDelay: finished {}, after {:?} and {} poll(s)", ran.output.is_some(), start.elapsed(), ran.polls);
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("[main] can't start a runtime: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    runtime.block_on(async {
+        let start = Instant::now();
+        let finished = tokio::time::timeout(cli.limit, tokio::spawn(Delay::new(cli.after))).await.is_ok();
+        println!("[tokio] Delay: finished {finished}, after {:?}", start.elapsed());
+        let start = Instant::now();
+        let delay = tokio::spawn(ForgetfulDelay::new(cli.after));
+        tokio::time::sleep(cli.limit).await;
+        println!("[tokio] ForgetfulDelay: finished {}, after {:?}", delay.is_finished(), start.elapsed());
+        delay.abort();
+    });
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,49 @@
+//! The hand-written delays, on the hand-written executor and on tokio.
+
+use manual_future::{Delay, ForgetfulDelay, Ran, block_on};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Waker};
+use std::time::{Duration, Instant};
+
+const AFTER: Duration = Duration::from_millis(50);
+/// Long enough, next to `AFTER`, to call a delay that hasn't finished a hang.
+const LIMIT: Duration = Duration::from_millis(500);
+
+#[test]
+fn test_a_delay_wakes_its_executor_once_at_its_time() {
+    let start = Instant::now();
+    // Once too early, and once when it's woken.
+    assert_eq!(block_on(Delay::new(AFTER), LIMIT), Ran { output: Some(()), polls: 2 });
+    let took = start.elapsed();
+    assert!((AFTER..LIMIT).contains(&took), "{took:?}");
+}
+
+#[test]
+fn test_a_delay_that_forgets_to_wake_hangs_its_executor() {
+    // Polled once, found too early, and never again.
+    assert_eq!(block_on(ForgetfulDelay::new(AFTER), LIMIT), Ran { output: None, polls: 1 });
+}
+
+#[tokio::test]
+async fn test_a_delay_that_forgets_to_wake_hangs_a_tokio_task() {
+    let delay = tokio::spawn(ForgetfulDelay::new(AFTER));
+    tokio::time::sleep(LIMIT).await;
+    assert!(!delay.is_finished());
+    delay.abort();
+}
+
+#[tokio::test]
+async fn test_a_delay_finishes_in_a_tokio_task() {
+    tokio::time::timeout(LIMIT, tokio::spawn(Delay::new(AFTER))).await.expect("it finishes").unwrap();
+}
+
+#[tokio::test]
+async fn test_a_delay_wakes_the_task_it_was_last_polled_by() {
+    let mut delay = Delay::new(AFTER);
+    // Polled first with a waker that does nothing, as if by a task that's gone since.
+    assert!(Pin::new(&mut delay).poll(&mut Context::from_waker(Waker::noop())).is_pending());
+    // An `await` doesn't poll again until it's woken, so this hangs if the timer wakes
+    // the first waker.
+    tokio::time::timeout(LIMIT, tokio::spawn(delay)).await.expect("it wakes this task").unwrap();
+}
@@ -0,0 +1,173 @@
+//! Streams written by hand: [`Stream::poll_next`] implemented directly, as
+//! `manual_future` does `Future::poll`.
+//!
+//! A stream is a future that can be ready more than once. Each call of `poll_next`
+//! returns one of:
+//!
+//! - `Poll::Ready(Some(item))`: the next item. The caller may poll again straight away,
+//!   and often will, so a stream has nothing to do about waking here.
+//! - `Poll::Pending`: no item yet. As with a future, the stream has to have arranged for
+//!   the context's waker to be woken when there might be one; the easy way, and
+//!   almost always the right one, is only to return `Pending` when something it polled
+//!   itself returned `Pending`, having been handed the same context and so registered
+//!   the same waker. Returning `Pending` without that hangs whoever's waiting.
+//! - `Poll::Ready(None)`: it's finished. Polling a finished stream again is allowed by
+//!   the trait but may do anything, panic included; both here return `None` again,
+//!   which is what `FusedStream` promises, and say so by implementing it.
+//!
+//! [`Ticks`] yields a tick every period, a given number of times, off a tokio `Sleep` it
+//! resets after each. [`Chunks`] reads any `AsyncRead`, a socket say, and yields exactly
+//! `size` bytes at a time, however the reads happen to split them, with whatever is left
+//! at the end of it. Neither knows anything of who consumes it: `StreamExt::next` turns
+//! each into a future for its next item, which is what `select!` wants, as `main` does.
+
+use futures::Stream;
+use futures::stream::FusedStream;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// A tick every `period`, `count` times, the first a period after it's made. Each is the
+/// tick's number, from 1, and when it was due.
+#[derive(Debug)]
+pub struct Ticks {
+    // Boxed, as `Sleep` can't be moved once it's been polled and `Ticks` has to be
+    // movable: anything can hold it and `next()` it.
+    sleep: Pin<Box<Sleep>>,
+    period: Duration,
+    ticked: u64,
+    count: u64,
+}
+
+impl Ticks {
+    pub fn new(period: Duration, count: u64) -> Self {
+        Self { sleep: Box::pin(tokio::time::sleep(period)), period, ticked: 0, count }
+    }
+}
+
+impl Stream for Ticks {
+    type Item = (u64, Instant);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.ticked == self.count {
+            return Poll::Ready(None);
+        }
+        // `Pending` only if the sleep is, which has registered `cx`'s waker with the
+        // timer: waking when the next tick is due is the timer's job, not ours.
+        ready!(self.sleep.as_mut().poll(cx));
+        let due = self.sleep.deadline();
+        // From when it was due, not from now, so a late consumer doesn't push every later
+        // tick back.
+        let period = self.period;
+        self.sleep.as_mut().reset(due + period);
+        self.ticked += 1;
+        Poll::Ready(Some((self.ticked, due)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = usize::try_from(self.count - self.ticked).unwrap_or(usize::MAX);
+        (left, Some(left))
+    }
+}
+
+impl FusedStream for Ticks {
+    fn is_terminated(&self) -> bool {
+        self.ticked == self.count
+    }
+}
+
+/// `reader`'s bytes, `size` at a time: every chunk is full but the last, which is what
+/// was left when the reader ended, if anything was. An error reading ends it, after
+/// it's yielded the error.
+#[derive(Debug)]
+pub struct Chunks<R> {
+    reader: R,
+    size: usize,
+    /// The chunk it's filling, with room for `size`.
+    chunk: Vec<u8>,
+    filled: usize,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> Chunks<R> {
+    pub fn new(reader: R, size: usize) -> Self {
+        assert!(size > 0, "chunks have to hold something");
+        Self { reader, size, chunk: vec![0; size], filled: 0, done: false }
+    }
+
+    /// Hands the chunk filled so far back, and starts a new one.
+    fn take(&mut self) -> Vec<u8> {
+        let mut chunk = std::mem::replace(&mut self.chunk, vec![0; self.size]);
+        chunk.truncate(self.filled);
+        self.filled = 0;
+        chunk
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for Chunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Every field is `Unpin`, so it can be had mutably without any pinning to keep.
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        // Reads for as long as they're ready: a read that returns some of a chunk isn't a
+        // reason to return `Pending`, as nothing would then wake this to read the rest.
+        while this.filled < this.size {
+            let mut buf = ReadBuf::new(&mut this.chunk[this.filled..]);
+            match Pin::new(&mut this.reader).poll_read(cx, &mut buf) {
+                // The reader has registered the waker, so when it's readable this is
+                // polled again and carries on filling the same chunk.
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => {
+                    this.done = true;
+                    return Poll::Ready((this.filled > 0).then(|| Ok(this.take())));
+                }
+                Poll::Ready(Ok(())) => this.filled += buf.filled().len(),
+            }
+        }
+        Poll::Ready(Some(Ok(this.take())))
+    }
+}
+
+impl<R: AsyncRead + Unpin> FusedStream for Chunks<R> {
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ticks_come_a_period_apart_and_then_end() {
+        let start = Instant::now();
+        let period = Duration::from_millis(100);
+        let ticks: Vec<_> = Ticks::new(period, 3).collect().await;
+        assert_eq!(ticks, [(1, start + period), (2, start + 2 * period), (3, start + 3 * period)]);
+    }
+
+    #[tokio::test]
+    async fn test_a_finished_stream_stays_finished() {
+        let mut ticks = Ticks::new(Duration::ZERO, 0);
+        assert!(ticks.is_terminated());
+        assert_eq!(ticks.next().await, None);
+        assert_eq!(ticks.next().await, None);
+        let mut chunks = Chunks::new(&b"ab"[..], 4);
+        assert_eq!(chunks.next().await.unwrap().unwrap(), b"ab");
+        assert_eq!(chunks.next().await.map(|chunk| chunk.unwrap()), None);
+        assert!(chunks.is_terminated());
+        assert_eq!(chunks.next().await.map(|chunk| chunk.unwrap()), None);
+    }
+}
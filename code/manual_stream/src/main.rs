@@ -0,0 +1,95 @@
+use clap::Parser;
+use futures::StreamExt;
+use manual_stream::{Chunks, Ticks};
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+
+/// Reads a socket in fixed-size chunks while a ticker ticks, both hand-written streams,
+/// taking whichever has an item next in a `select!` loop.
+///
+/// A server on localhost sends a line of text a few bytes at a time, `--pause` between
+/// writes, so the chunks come whenever enough of it has arrived and the ticks carry on
+/// between them. The loop ends when the server's done and the last chunk is in.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Bytes in a chunk.
+    #[arg(long, default_value_t = 8)]
+    chunk: usize,
+    /// Seconds between ticks.
+    #[arg(long, value_name = "SECS", default_value = "0.25", value_parser = parse_secs)]
+    tick: Duration,
+    /// Seconds between the server's writes.
+    #[arg(long, value_name = "SECS", default_value = "0.1", value_parser = parse_secs)]
+    pause: Duration,
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+/// Sends `text` to the one client that connects, three bytes at a time.
+async fn trickle(listener: TcpListener, text: &'static [u8], pause: Duration) -> std::io::Result<()> {
+    let (mut socket, _) = listener.accept().await?;
+    for piece in text.chunks(3) {
+        socket.write_all(piece).await?;
+        tokio::time::sleep(pause).await;
+    }
+    socket.shutdown().await
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if cli.chunk == 0 {
+        eprintln!("[main] --chunk has to be at least 1");
+        return ExitCode::FAILURE;
+    }
+    let listener = match TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("[main] can't listen: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let addr = listener.local_addr().expect("it's bound");
+    let server = tokio::spawn(trickle(listener, b"the quick brown fox jumps over the lazy dog", cli.pause));
+    let socket = match TcpStream::connect(addr).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("[main] can't connect: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let start = Instant::now();
+    let mut chunks = Chunks::new(socket, cli.chunk);
+    // Enough to outlast the server; the loop stops with the chunks, not the ticks.
+    let mut ticks = Ticks::new(cli.tick, u64::MAX);
+    loop {
+        tokio::select! {
+            chunk = chunks.next() => match chunk {
+                Some(Ok(chunk)) => println!("[chunks] {:>8.1?} {:?}", start.elapsed(), String::from_utf8_lossy(&chunk)),
+                Some(Err(e)) => {
+                    eprintln!("[main] reading: {e}");
+                    return ExitCode::FAILURE;
+                }
+                None => break,
+            },
+            Some((tick, due)) = ticks.next() => println!("[ticks]  {:>8.1?} tick {tick}, {:.1?} after it was due", start.elapsed(), due.elapsed()),
+        }
+    }
+    println!("[main] {:>8.1?} the socket's done", start.elapsed());
+    match server.await {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(e)) => {
+            eprintln!("[main] the server: {e}");
+            ExitCode::FAILURE
+        }
+        Err(_) => ExitCode::FAILURE,
+    }
+}
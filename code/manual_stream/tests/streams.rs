@@ -0,0 +1,63 @@
+//! The hand-written streams, read over an in-memory pipe and consumed together in
+//! `select!`.
+
+use futures::StreamExt;
+use manual_stream::{Chunks, Ticks};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWriteExt, ReadBuf};
+
+#[tokio::test]
+async fn test_chunks_are_full_however_the_writes_split_them() {
+    let (mut writer, reader) = tokio::io::duplex(64);
+    tokio::spawn(async move {
+        for piece in [&b"he"[..], b"llo w", b"o", b"rld"] {
+            writer.write_all(piece).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+    });
+    let chunks: Vec<_> = Chunks::new(reader, 4).map(|chunk| chunk.unwrap()).collect().await;
+    assert_eq!(chunks, [&b"hell"[..], b"o wo", b"rld"]);
+}
+
+/// A reader that fails every read.
+struct Broken;
+
+impl AsyncRead for Broken {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+    }
+}
+
+#[tokio::test]
+async fn test_a_read_error_is_yielded_and_ends_the_chunks() {
+    let mut chunks = Chunks::new(Broken, 4);
+    assert_eq!(chunks.next().await.unwrap().unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+    assert!(chunks.next().await.is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_ticks_keep_coming_in_select_while_the_chunks_wait() {
+    let (mut writer, reader) = tokio::io::duplex(64);
+    tokio::spawn(async move {
+        writer.write_all(b"abcd").await.unwrap();
+        // Five ticks' worth of nothing to read.
+        tokio::time::sleep(Duration::from_millis(550)).await;
+        writer.write_all(b"efgh").await.unwrap();
+    });
+    let mut chunks = Chunks::new(reader, 4);
+    let mut ticks = Ticks::new(Duration::from_millis(100), 100);
+    let mut seen = Vec::new();
+    loop {
+        tokio::select! {
+            chunk = chunks.next() => match chunk {
+                Some(chunk) => seen.push(String::from_utf8(chunk.unwrap()).unwrap()),
+                None => break,
+            },
+            Some((tick, _)) = ticks.next() => seen.push(format!("tick {tick}")),
+        }
+    }
+    assert_eq!(seen, ["abcd", "tick 1", "tick 2", "tick 3", "tick 4", "tick 5", "efgh"]);
+}
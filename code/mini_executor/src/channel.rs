@@ -0,0 +1,120 @@
+//! An unbounded channel, as simple as one can be: a queue behind a mutex, and the waker
+//! of a receiver that found it empty.
+//!
+//! [`Receiver::recv`]'s future checks the queue each time it's polled; if it's empty and
+//! there are still senders, it keeps the waker it was polled with, and whichever of
+//! [`Sender::send`] or the last sender's drop comes next wakes it. The check and the
+//! keeping are under the same lock as the send, so a value can't arrive in between and
+//! be missed.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Debug)]
+struct Shared<T> {
+    items: VecDeque<T>,
+    senders: usize,
+    waker: Option<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A channel with any number of senders and one receiver, and no bound.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(Shared { items: VecDeque::new(), senders: 1, waker: None }));
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Queues `item`, and wakes the receiver if it's waiting. Never waits itself, so it's
+    /// as happy on a plain thread as in a task.
+    pub fn send(&self, item: T) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.items.push_back(item);
+        shared.wake();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().unwrap().senders += 1;
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.senders -= 1;
+        // The receiver has to find out there'll be nothing more.
+        if shared.senders == 0 {
+            shared.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// The next item, or `None` once the queue's empty and the senders have all gone.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+}
+
+/// [`Receiver::recv`]'s future.
+#[derive(Debug)]
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut shared = self.receiver.shared.lock().unwrap();
+        if let Some(item) = shared.items.pop_front() {
+            return Poll::Ready(Some(item));
+        }
+        if shared.senders == 0 {
+            return Poll::Ready(None);
+        }
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Waker;
+
+    #[test]
+    fn test_it_ends_after_the_last_item_once_the_senders_have_gone() {
+        let (sender, mut receiver) = channel();
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(Pin::new(&mut receiver.recv()).poll(&mut cx).is_pending());
+        sender.send(1);
+        drop(sender.clone());
+        drop(sender);
+        assert_eq!(Pin::new(&mut receiver.recv()).poll(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(Pin::new(&mut receiver.recv()).poll(&mut cx), Poll::Ready(None));
+    }
+}
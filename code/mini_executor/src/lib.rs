@@ -0,0 +1,161 @@
+//! An executor with no tokio in it: a queue of tasks that are ready to be polled, and a
+//! thread that polls them.
+//!
+//! A task is a spawned future, boxed, with a way back onto the queue: its waker is the
+//! task itself, by way of `futures`' [`ArcWake`], and waking it pushes it onto the
+//! queue, once however many times it's woken before it's polled again. [`Executor::run`]
+//! pops a task, polls it with its own waker, and drops it if it's done; when the queue
+//! is empty it sleeps until something wakes a task, from a timer thread, another task
+//! or anywhere else. That's all a runtime's scheduler is, less the work stealing, the
+//! IO driver and the timer wheel: tokio's tasks are woken the same way, by their
+//! futures keeping the waker they were polled with and waking it when they might be
+//! able to make progress.
+//!
+//! [`Spawner`] spawns onto it, from outside or from inside a task. [`channel`] is a
+//! channel to run on it, as simple as one can be, with the same waker bookkeeping as
+//! `manual_future`'s `Delay`.
+
+pub mod channel;
+
+use futures::future::BoxFuture;
+use futures::task::{ArcWake, waker_ref};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The tasks that are ready, and how many there are in all.
+#[derive(Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    woken: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    ready: VecDeque<Arc<Task>>,
+    /// Spawned and not yet finished, ready or not.
+    live: usize,
+}
+
+impl Queue {
+    fn push(&self, task: Arc<Task>) {
+        self.state.lock().unwrap().ready.push_back(task);
+        self.woken.notify_one();
+    }
+}
+
+/// A spawned future, and the queue it goes back onto when it's woken.
+struct Task {
+    /// Taken once it's finished, so a late wake has nothing to poll.
+    future: Mutex<Option<BoxFuture<'static, ()>>>,
+    /// On the queue already: woken since it was last polled.
+    queued: AtomicBool,
+    queue: Arc<Queue>,
+}
+
+impl ArcWake for Task {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        // Once is enough: however many wakes there were, one poll sees what they were for.
+        if !arc_self.queued.swap(true, Ordering::AcqRel) {
+            arc_self.queue.push(arc_self.clone());
+        }
+    }
+}
+
+/// Spawns tasks onto an [`Executor`], from any thread, and from inside its tasks.
+#[derive(Clone)]
+pub struct Spawner {
+    queue: Arc<Queue>,
+}
+
+impl Spawner {
+    /// Queues `future` to be polled for the first time.
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        let task = Arc::new(Task { future: Mutex::new(Some(Box::pin(future))), queued: AtomicBool::new(true), queue: self.queue.clone() });
+        self.queue.state.lock().unwrap().live += 1;
+        self.queue.push(task);
+    }
+}
+
+/// How [`Executor::run`] got on: how many polls it made, and how many tasks finished and
+/// didn't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ran {
+    pub polls: usize,
+    pub finished: usize,
+    pub unfinished: usize,
+}
+
+/// Polls the tasks spawned by its [`Spawner`]s, on the thread that runs it.
+#[derive(Default)]
+pub struct Executor {
+    queue: Arc<Queue>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        Spawner { queue: self.queue.clone() }
+    }
+
+    /// Polls tasks as they're ready until every task spawned has finished, including
+    /// those spawned while it runs, or until `limit` goes by with none ready: a task
+    /// that's never woken would otherwise keep it waiting for good.
+    pub fn run(&self, limit: Duration) -> Ran {
+        let mut ran = Ran { polls: 0, finished: 0, unfinished: 0 };
+        loop {
+            let Some(task) = self.next(limit, &mut ran) else {
+                return ran;
+            };
+            // Cleared before the poll, so a wake during it queues the task again.
+            task.queued.store(false, Ordering::Release);
+            let mut slot = task.future.lock().unwrap();
+            let Some(future) = slot.as_mut() else {
+                continue;
+            };
+            let waker = waker_ref(&task);
+            ran.polls += 1;
+            if let Poll::Ready(()) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                slot.take();
+                ran.finished += 1;
+                self.queue.state.lock().unwrap().live -= 1;
+            }
+        }
+    }
+
+    /// The next ready task, waiting up to `limit` for one; `None` once they've all
+    /// finished, or when the wait runs out, with the ones left in `ran`.
+    fn next(&self, limit: Duration, ran: &mut Ran) -> Option<Arc<Task>> {
+        let deadline = Instant::now() + limit;
+        let mut state = self.queue.state.lock().unwrap();
+        loop {
+            if let Some(task) = state.ready.pop_front() {
+                return Some(task);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if state.live == 0 || left.is_zero() {
+                ran.unfinished = state.live;
+                return None;
+            }
+            state = self.queue.woken.wait_timeout(state, left).unwrap().0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_nothing_spawned_it_returns_at_once() {
+        let start = Instant::now();
+        assert_eq!(Executor::new().run(Duration::from_secs(10)), Ran { polls: 0, finished: 0, unfinished: 0 });
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
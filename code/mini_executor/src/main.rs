@@ -0,0 +1,54 @@
+use clap::Parser;
+use manual_future::Delay;
+use mini_executor::Executor;
+use mini_executor::channel::channel;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// Runs a producer and a consumer on the mini executor, with no tokio anywhere.
+///
+/// The producer sends `--items` numbers over the hand-written channel, waiting on a
+/// hand-written `Delay` before each, and spawns a task of its own for every other one to
+/// show spawning from inside a task; the consumer prints them as they come. Every poll
+/// the executor makes is because something woke a task, or spawned one: the producer's
+/// when its delay is up, a spawned task's first, and the consumer's when a number comes.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Numbers to send.
+    #[arg(long, default_value_t = 5)]
+    items: u64,
+    /// Milliseconds before each.
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    gap_ms: u64,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let gap = Duration::from_millis(cli.gap_ms);
+    let executor = Executor::new();
+    let spawner = executor.spawner();
+    let (sender, mut receiver) = channel();
+    let start = Instant::now();
+
+    executor.spawner().spawn(async move {
+        for i in 0..cli.items {
+            Delay::new(gap).await;
+            if i % 2 == 0 {
+                sender.send(format!("{i}, from the producer"));
+            } else {
+                let sender = sender.clone();
+                spawner.spawn(async move { sender.send(format!("{i}, from a task the producer spawned")) });
+            }
+        }
+    });
+    executor.spawner().spawn(async move {
+        while let Some(item) = receiver.recv().await {
+            println!("[consumer] {:>8.1?} {item}", start.elapsed());
+        }
+        println!("[consumer] {:>8.1?} the senders have gone", start.elapsed());
+    });
+
+    let ran = executor.run(gap + Duration::from_secs(1));
+    println!("[main] {} poll(s), {} task(s) finished, {} not", ran.polls, ran.finished, ran.unfinished);
+    if ran.unfinished == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
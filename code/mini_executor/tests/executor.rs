@@ -0,0 +1,84 @@
+//! Tasks on the mini executor: the hand-written delays, the channel, and tasks that
+//! spawn more.
+
+use manual_future::{Delay, ForgetfulDelay};
+use mini_executor::channel::channel;
+use mini_executor::{Executor, Ran};
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// Long enough to call an executor with nothing ready stuck.
+const LIMIT: Duration = Duration::from_millis(500);
+
+#[test]
+fn test_a_delay_is_polled_once_early_and_once_when_it_wakes() {
+    let executor = Executor::new();
+    executor.spawner().spawn(Delay::new(Duration::from_millis(50)));
+    assert_eq!(executor.run(LIMIT), Ran { polls: 2, finished: 1, unfinished: 0 });
+}
+
+#[test]
+fn test_delays_in_separate_tasks_overlap() {
+    let executor = Executor::new();
+    for _ in 0..4 {
+        executor.spawner().spawn(Delay::new(Duration::from_millis(100)));
+    }
+    let start = Instant::now();
+    assert_eq!(executor.run(LIMIT).finished, 4);
+    // 100ms, not 400ms.
+    let took = start.elapsed();
+    assert!(took < Duration::from_millis(300), "{took:?}");
+}
+
+#[test]
+fn test_a_task_thats_never_woken_is_left_unfinished() {
+    let executor = Executor::new();
+    executor.spawner().spawn(ForgetfulDelay::new(Duration::from_millis(50)));
+    executor.spawner().spawn(Delay::new(Duration::from_millis(50)));
+    assert_eq!(executor.run(LIMIT), Ran { polls: 3, finished: 1, unfinished: 1 });
+}
+
+#[test]
+fn test_waking_twice_before_a_poll_polls_once() {
+    let executor = Executor::new();
+    let mut polled = false;
+    executor.spawner().spawn(poll_fn(move |cx| {
+        if polled {
+            return Poll::Ready(());
+        }
+        polled = true;
+        cx.waker().wake_by_ref();
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }));
+    assert_eq!(executor.run(LIMIT).polls, 2);
+}
+
+#[test]
+fn test_the_channel_delivers_in_order_and_ends_when_the_senders_go() {
+    let executor = Executor::new();
+    let (sender, mut receiver) = channel();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let spawner = executor.spawner();
+    executor.spawner().spawn(async move {
+        for i in 0..3 {
+            let sender = sender.clone();
+            // Each sent from a task of its own, spawned from this one, in turn.
+            spawner.spawn(async move {
+                Delay::new(Duration::from_millis(10 * (i + 1))).await;
+                sender.send(i);
+            });
+        }
+    });
+    let into = received.clone();
+    executor.spawner().spawn(async move {
+        while let Some(i) = receiver.recv().await {
+            into.lock().unwrap().push(i);
+        }
+    });
+    let ran = executor.run(LIMIT);
+    assert_eq!((ran.finished, ran.unfinished), (5, 0));
+    assert_eq!(*received.lock().unwrap(), [0, 1, 2]);
+}
@@ -0,0 +1,62 @@
+//! Feeds arbitrary bytes to `RespCodec`, in arbitrary pieces, the way a connection
+//! would: decode until it wants more, append the next read, repeat, until the input
+//! runs out or a protocol error ends the connection.
+//!
+//! Beyond not panicking (or overflowing the stack), it checks that:
+//! * no frame comes out bigger than the bytes that went in, so a length the client
+//!   claims never turns into an allocation of that size;
+//! * what's left in the buffer after `Ok(None)` is no more than was fed in;
+//! * every frame that parses encodes to bytes that parse back to the same frame.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use mini_redis::{Frame, RespCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the size of each read.
+    let Some((&chunk, data)) = data.split_first() else {
+        return;
+    };
+    let chunk = usize::from(chunk).max(1);
+
+    let mut codec = RespCodec::default();
+    let mut buf = BytesMut::new();
+    let mut fed = 0;
+    for piece in data.chunks(chunk) {
+        buf.extend_from_slice(piece);
+        fed += piece.len();
+        loop {
+            match codec.decode(&mut buf) {
+                Ok(Some(frame)) => {
+                    assert!(size(&frame) <= fed, "{frame:?} from {fed} bytes");
+                    round_trip(frame);
+                }
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+        assert!(buf.len() <= fed);
+    }
+});
+
+/// Roughly how much memory the frame holds: its bytes, plus one for every element so
+/// empty strings and arrays count too. Never more than the bytes it was parsed from.
+fn size(frame: &Frame) -> usize {
+    match frame {
+        Frame::Simple(s) | Frame::Error(s) => s.len() + 1,
+        Frame::Bulk(bytes) => bytes.len() + 1,
+        Frame::Integer(_) | Frame::Null => 1,
+        Frame::Array(frames) => 1 + frames.iter().map(size).sum::<usize>(),
+    }
+}
+
+fn round_trip(frame: Frame) {
+    let mut codec = RespCodec::default();
+    let mut encoded = BytesMut::new();
+    codec.encode(frame.clone(), &mut encoded).unwrap();
+    assert_eq!(codec.decode(&mut encoded).unwrap(), Some(frame));
+    assert!(encoded.is_empty());
+}
@@ -0,0 +1,171 @@
+//! Turning request frames into commands, and commands into replies.
+//!
+//! Supported, with Redis's reply conventions:
+//!
+//! - `PING [message]`
+//! - `GET key`: the value, or null
+//! - `SET key value [EX seconds | PX milliseconds]`: `+OK`
+//! - `DEL key [key ...]`: how many keys were removed
+//! - `EXPIRE key seconds`: `1` if the key exists, else `0`
+//! - `TTL key`: seconds left, `-1` for no expiry, `-2` for no such key
+
+use crate::db::{Db, Ttl};
+use crate::frame::Frame;
+use bytes::Bytes;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Command {
+    Ping(Option<Bytes>),
+    Get(String),
+    Set {
+        key: String,
+        value: Bytes,
+        ttl: Option<Duration>,
+    },
+    Del(Vec<String>),
+    Expire(String, i64),
+    Ttl(String),
+}
+
+impl Command {
+    /// Reads a command from a client frame: an array of bulk strings, name first. The
+    /// error is the message for the `-ERR` reply.
+    pub(crate) fn from_frame(frame: Frame) -> Result<Command, String> {
+        let Frame::Array(parts) = frame else {
+            return Err("ERR expected an array of bulk strings".to_string());
+        };
+        let mut args = parts
+            .into_iter()
+            .map(|part| match part {
+                Frame::Bulk(bytes) => Ok(bytes),
+                Frame::Simple(s) => Ok(Bytes::from(s)),
+                _ => Err("ERR expected an array of bulk strings".to_string()),
+            })
+            .collect::<Result<Vec<Bytes>, String>>()?
+            .into_iter();
+        let name = args.next().ok_or("ERR empty command")?;
+        let name = String::from_utf8_lossy(&name).to_ascii_uppercase();
+        let args: Vec<Bytes> = args.collect();
+        let wrong_args = || format!("ERR wrong number of arguments for '{}' command", name.to_ascii_lowercase());
+
+        let command = match (name.as_str(), args.as_slice()) {
+            ("PING", []) => Command::Ping(None),
+            ("PING", [message]) => Command::Ping(Some(message.clone())),
+            ("GET", [key]) => Command::Get(text(key)?),
+            ("SET", [key, value, options @ ..]) => Command::Set {
+                key: text(key)?,
+                value: value.clone(),
+                ttl: parse_set_options(options)?,
+            },
+            ("DEL", keys) if !keys.is_empty() => Command::Del(keys.iter().map(text).collect::<Result<_, _>>()?),
+            ("EXPIRE", [key, seconds]) => Command::Expire(text(key)?, integer(seconds)?),
+            ("TTL", [key]) => Command::Ttl(text(key)?),
+            ("PING" | "GET" | "SET" | "DEL" | "EXPIRE" | "TTL", _) => return Err(wrong_args()),
+            _ => return Err(format!("ERR unknown command '{name}'")),
+        };
+        Ok(command)
+    }
+
+    /// Runs the command and returns the reply frame.
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Command::Ping(None) => Frame::Simple("PONG".to_string()),
+            Command::Ping(Some(message)) => Frame::Bulk(message),
+            Command::Get(key) => db.get(&key).map_or(Frame::Null, Frame::Bulk),
+            Command::Set { key, value, ttl } => {
+                db.set(key, value, ttl);
+                Frame::Simple("OK".to_string())
+            }
+            Command::Del(keys) => Frame::Integer(keys.iter().filter(|key| db.del(key)).count() as i64),
+            // As in Redis, a deadline that's already passed deletes the key.
+            Command::Expire(key, seconds) if seconds <= 0 => Frame::Integer(i64::from(db.del(&key))),
+            Command::Expire(key, seconds) => Frame::Integer(i64::from(db.expire(&key, Duration::from_secs(seconds as u64)))),
+            Command::Ttl(key) => Frame::Integer(match db.ttl(&key) {
+                Ttl::Missing => -2,
+                Ttl::Forever => -1,
+                // Rounded to the nearest second, like Redis.
+                Ttl::Remaining(left) => ((left.as_millis() + 500) / 1000) as i64,
+            }),
+        }
+    }
+}
+
+fn parse_set_options(options: &[Bytes]) -> Result<Option<Duration>, String> {
+    match options {
+        [] => Ok(None),
+        [unit, amount] => {
+            let amount = integer(amount)?;
+            if amount <= 0 {
+                return Err("ERR invalid expire time in 'set' command".to_string());
+            }
+            match text(unit)?.to_ascii_uppercase().as_str() {
+                "EX" => Ok(Some(Duration::from_secs(amount as u64))),
+                "PX" => Ok(Some(Duration::from_millis(amount as u64))),
+                _ => Err("ERR syntax error".to_string()),
+            }
+        }
+        _ => Err("ERR syntax error".to_string()),
+    }
+}
+
+fn text(bytes: &Bytes) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| "ERR keys must be UTF-8".to_string())
+}
+
+fn integer(bytes: &Bytes) -> Result<i64, String> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| "ERR value is not an integer or out of range".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(parts: &[&str]) -> Result<Command, String> {
+        Command::from_frame(Frame::command(parts))
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse(&["ping"]), Ok(Command::Ping(None)));
+        assert_eq!(parse(&["GET", "a"]), Ok(Command::Get("a".into())));
+        assert_eq!(
+            parse(&["set", "a", "1", "px", "1500"]),
+            Ok(Command::Set {
+                key: "a".into(),
+                value: Bytes::from_static(b"1"),
+                ttl: Some(Duration::from_millis(1500)),
+            })
+        );
+        assert_eq!(parse(&["DEL", "a", "b"]), Ok(Command::Del(vec!["a".into(), "b".into()])));
+        assert_eq!(parse(&["EXPIRE", "a", "10"]), Ok(Command::Expire("a".into(), 10)));
+        assert_eq!(parse(&["TTL", "a"]), Ok(Command::Ttl("a".into())));
+    }
+
+    #[test]
+    fn test_bad_commands_get_redis_style_errors() {
+        assert_eq!(parse(&["GET"]), Err("ERR wrong number of arguments for 'get' command".into()));
+        assert_eq!(parse(&["SET", "a", "1", "EX"]), Err("ERR syntax error".into()));
+        assert_eq!(parse(&["SET", "a", "1", "EX", "0"]), Err("ERR invalid expire time in 'set' command".into()));
+        assert_eq!(parse(&["EXPIRE", "a", "soon"]), Err("ERR value is not an integer or out of range".into()));
+        assert_eq!(parse(&["FLUSHALL"]), Err("ERR unknown command 'FLUSHALL'".into()));
+        assert!(Command::from_frame(Frame::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let db = Db::default();
+        let run = |parts: &[&str]| parse(parts).unwrap().apply(&db);
+        assert_eq!(run(&["GET", "a"]), Frame::Null);
+        assert_eq!(run(&["SET", "a", "1"]), Frame::Simple("OK".into()));
+        assert_eq!(run(&["TTL", "a"]), Frame::Integer(-1));
+        assert_eq!(run(&["EXPIRE", "a", "100"]), Frame::Integer(1));
+        assert_eq!(run(&["TTL", "a"]), Frame::Integer(100));
+        assert_eq!(run(&["DEL", "a", "b"]), Frame::Integer(1));
+        assert_eq!(run(&["TTL", "a"]), Frame::Integer(-2));
+        assert_eq!(run(&["EXPIRE", "a", "100"]), Frame::Integer(0));
+    }
+}
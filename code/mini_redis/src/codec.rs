@@ -0,0 +1,65 @@
+//! [`Frame`] parsing plugged into `tokio_util::codec`, so a connection is just a
+//! `Framed` stream of frames in and a sink of frames out.
+
+use crate::frame::{Frame, FrameError, Scanner};
+use bytes::BytesMut;
+use std::{fmt, io};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[derive(Debug, Default)]
+pub struct RespCodec {
+    /// How much of the frame still being received has been checked already.
+    scanner: Scanner,
+}
+
+/// A connection-level failure: either the bytes weren't RESP or the socket failed.
+#[derive(Debug)]
+pub enum ProtocolError {
+    Frame(FrameError),
+    Io(io::Error),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Frame(e) => write!(f, "protocol error: {e}"),
+            ProtocolError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<FrameError> for ProtocolError {
+    fn from(e: FrameError) -> Self {
+        ProtocolError::Frame(e)
+    }
+}
+
+// `Decoder` requires this: `FramedRead` reports read errors through our error type.
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        ProtocolError::Io(e)
+    }
+}
+
+impl Decoder for RespCodec {
+    type Item = Frame;
+    type Error = ProtocolError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, ProtocolError> {
+        if self.scanner.complete(src)?.is_none() {
+            return Ok(None);
+        }
+        Ok(Frame::parse(src)?)
+    }
+}
+
+impl Encoder<Frame> for RespCodec {
+    type Error = ProtocolError;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), ProtocolError> {
+        frame.encode(dst);
+        Ok(())
+    }
+}
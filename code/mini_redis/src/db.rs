@@ -0,0 +1,199 @@
+//! The keyspace, and the background task that deletes keys when they expire.
+//!
+//! Expiry deadlines are kept twice: on each entry, so a lookup can tell whether its key
+//! is still live, and in a `BTreeSet` ordered by deadline, so the purge task can find
+//! the next key due without scanning them all. The purge task sleeps until that
+//! deadline with `tokio::time::sleep_until`; setting an earlier expiry wakes it through
+//! a `Notify` so it can go back to sleep on the new, sooner one.
+//!
+//! Lookups also treat a key past its deadline as missing. The purge task may be a
+//! little late (it's just another task waiting for the scheduler), and a `GET` in that
+//! window shouldn't see a key that has already expired.
+
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{Notify, broadcast};
+use tokio::time::{Instant, sleep_until};
+
+#[derive(Debug)]
+struct Entry {
+    value: Bytes,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires_at.is_none_or(|at| at > now)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    expirations: BTreeSet<(Instant, String)>,
+}
+
+impl State {
+    fn clear_expiry(&mut self, key: &str) {
+        if let Some(at) = self.entries.get_mut(key).and_then(|entry| entry.expires_at.take()) {
+            self.expirations.remove(&(at, key.to_string()));
+        }
+    }
+
+    fn next_expiry(&self) -> Option<Instant> {
+        self.expirations.first().map(|(at, _)| *at)
+    }
+}
+
+/// How long a key has left, as `TTL` reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Ttl {
+    Missing,
+    Forever,
+    Remaining(Duration),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Db {
+    state: Mutex<State>,
+    expiry_changed: Notify,
+}
+
+impl Db {
+    pub(crate) fn get(&self, key: &str) -> Option<Bytes> {
+        let state = self.state.lock().unwrap();
+        let entry = state.entries.get(key)?;
+        entry.is_live(Instant::now()).then(|| entry.value.clone())
+    }
+
+    /// Stores `value`, replacing any existing expiry with `ttl` (or none), as `SET` does.
+    pub(crate) fn set(&self, key: String, value: Bytes, ttl: Option<Duration>) {
+        let mut state = self.state.lock().unwrap();
+        state.clear_expiry(&key);
+        let expires_at = ttl.map(|ttl| Instant::now() + ttl);
+        if let Some(at) = expires_at {
+            state.expirations.insert((at, key.clone()));
+        }
+        state.entries.insert(key, Entry { value, expires_at });
+        drop(state);
+        if expires_at.is_some() {
+            self.expiry_changed.notify_one();
+        }
+    }
+
+    /// Gives an existing key a new expiry. Returns `false` if there's no such key.
+    pub(crate) fn expire(&self, key: &str, ttl: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if !state.entries.get(key).is_some_and(|entry| entry.is_live(now)) {
+            return false;
+        }
+        state.clear_expiry(key);
+        let at = now + ttl;
+        state.expirations.insert((at, key.to_string()));
+        state.entries.get_mut(key).unwrap().expires_at = Some(at);
+        drop(state);
+        self.expiry_changed.notify_one();
+        true
+    }
+
+    pub(crate) fn ttl(&self, key: &str) -> Ttl {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match state.entries.get(key) {
+            Some(entry) if entry.is_live(now) => match entry.expires_at {
+                Some(at) => Ttl::Remaining(at - now),
+                None => Ttl::Forever,
+            },
+            _ => Ttl::Missing,
+        }
+    }
+
+    pub(crate) fn del(&self, key: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.clear_expiry(key);
+        state
+            .entries
+            .remove(key)
+            .is_some_and(|entry| entry.is_live(Instant::now()))
+    }
+
+    /// Deletes every key whose deadline has passed and returns when the next one is due.
+    fn purge_expired(&self) -> Option<Instant> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        while let Some((at, key)) = state.expirations.first().cloned() {
+            if at > now {
+                break;
+            }
+            state.expirations.pop_first();
+            state.entries.remove(&key);
+        }
+        state.next_expiry()
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+}
+
+/// Deletes keys as they expire, until shutdown is signalled.
+pub(crate) async fn purge_expired_keys(db: std::sync::Arc<Db>, mut shutdown_rx: broadcast::Receiver<()>) {
+    loop {
+        let next = db.purge_expired();
+        let sleep = async {
+            match next {
+                Some(at) => sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = shutdown_rx.recv() => return,
+            () = sleep => {}
+            // `notify_one` leaves a permit if we weren't waiting yet, so an expiry set
+            // between `purge_expired` and here still wakes us.
+            () = db.expiry_changed.notified() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_set_replaces_expiry() {
+        let db = Db::default();
+        db.set("a".into(), Bytes::from_static(b"1"), Some(Duration::from_secs(60)));
+        assert!(matches!(db.ttl("a"), Ttl::Remaining(_)));
+        db.set("a".into(), Bytes::from_static(b"2"), None);
+        assert_eq!(db.ttl("a"), Ttl::Forever);
+        assert_eq!(db.ttl("b"), Ttl::Missing);
+        assert!(!db.expire("b", Duration::from_secs(1)));
+        assert!(db.del("a"));
+        assert!(!db.del("a"));
+    }
+
+    #[tokio::test]
+    async fn test_expired_keys_are_purged() {
+        let db = Arc::new(Db::default());
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let purger = tokio::spawn(purge_expired_keys(db.clone(), shutdown_rx));
+
+        db.set("short".into(), Bytes::from_static(b"1"), Some(Duration::from_millis(50)));
+        db.set("long".into(), Bytes::from_static(b"2"), Some(Duration::from_secs(60)));
+        db.set("forever".into(), Bytes::from_static(b"3"), None);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(db.get("short"), None);
+        assert_eq!(db.len(), 2, "the expired key should be gone, not just hidden");
+        assert_eq!(db.get("long").as_deref(), Some(&b"2"[..]));
+
+        shutdown_tx.send(()).unwrap();
+        purger.await.unwrap();
+    }
+}
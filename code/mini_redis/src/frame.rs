@@ -0,0 +1,411 @@
+//! RESP2 frames, and parsing them out of a buffer that may hold only part of one.
+//!
+//! RESP2 has five types. Each starts with a one-byte tag and ends with `\r\n`:
+//!
+//! ```text
+//! +OK\r\n                    simple string
+//! -ERR unknown command\r\n   error
+//! :42\r\n                    integer
+//! $5\r\nhello\r\n            bulk string: a length, then that many bytes ($-1 is null)
+//! *2\r\n$3\r\nGET\r\n$1\r\na\r\n   array: a count, then that many frames (*-1 is null)
+//! ```
+//!
+//! TCP hands us bytes, not frames, so the parser has to cope with a buffer that ends
+//! halfway through one. It walks the buffer by index without consuming anything and
+//! returns `Ok(None)` the moment it runs out of bytes. Only once a whole frame has
+//! parsed does [`Frame::parse`] advance the buffer past it.
+//!
+//! Called on every read, that would parse a big frame over and over from its first
+//! byte, copying every bulk string each time, until the last piece arrives. So the
+//! codec asks a [`Scanner`] first. It only checks where each element ends, remembers
+//! how far it got, and on the next read carries on from there; the frame is parsed
+//! once, when the scanner says all of it is in.
+//!
+//! Every length in a frame comes from the client, so each one is capped before we act
+//! on it: a 2 GB bulk string, a line that never ends or arrays nested a million deep
+//! are protocol errors, not allocations or stack overflows.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fmt;
+
+/// The largest bulk string we accept, the same as Redis's default `proto-max-bulk-len`.
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// The most elements an array may claim to have.
+pub const MAX_ARRAY_LEN: usize = 1024 * 1024;
+/// How long a `+`, `-`, `:`, `$` or `*` line may get before we give up on finding its
+/// `\r\n`.
+pub const MAX_LINE_LEN: usize = 64 * 1024;
+/// How deeply arrays may nest. Parsing recurses once per level.
+pub const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Bytes),
+    /// Both the null bulk string (`$-1`) and the null array (`*-1`); we only ever send
+    /// the former.
+    Null,
+    Array(Vec<Frame>),
+}
+
+/// Why a buffer doesn't hold valid RESP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    InvalidTag(u8),
+    InvalidInteger,
+    InvalidLength(i64),
+    InvalidUtf8,
+    MissingCrlf,
+    TooLarge,
+    TooDeep,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::InvalidTag(tag) => write!(f, "invalid frame type byte {:?}", char::from(*tag)),
+            FrameError::InvalidInteger => write!(f, "invalid integer"),
+            FrameError::InvalidLength(len) => write!(f, "invalid length {len}"),
+            FrameError::InvalidUtf8 => write!(f, "simple string is not UTF-8"),
+            FrameError::MissingCrlf => write!(f, "bulk string not followed by CRLF"),
+            FrameError::TooLarge => write!(f, "frame exceeds size limits"),
+            FrameError::TooDeep => write!(f, "arrays nested more than {MAX_DEPTH} deep"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl Frame {
+    /// Takes one complete frame off the front of `buf`, or returns `Ok(None)` and
+    /// leaves `buf` untouched if it doesn't hold a whole one yet.
+    pub fn parse(buf: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+        match parse_at(buf, 0, 0)? {
+            Some((frame, end)) => {
+                buf.advance(end);
+                Ok(Some(frame))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Appends the wire form of this frame to `dst`.
+    pub fn encode(&self, dst: &mut BytesMut) {
+        match self {
+            Frame::Simple(s) => put_line(dst, b'+', s.as_bytes()),
+            Frame::Error(s) => put_line(dst, b'-', s.as_bytes()),
+            Frame::Integer(n) => put_line(dst, b':', n.to_string().as_bytes()),
+            Frame::Bulk(bytes) => {
+                put_line(dst, b'$', bytes.len().to_string().as_bytes());
+                dst.put_slice(bytes);
+                dst.put_slice(b"\r\n");
+            }
+            Frame::Null => dst.put_slice(b"$-1\r\n"),
+            Frame::Array(frames) => {
+                put_line(dst, b'*', frames.len().to_string().as_bytes());
+                for frame in frames {
+                    frame.encode(dst);
+                }
+            }
+        }
+    }
+
+    /// A command as clients send it: an array of bulk strings.
+    pub fn command(parts: &[&str]) -> Frame {
+        Frame::Array(parts.iter().map(|part| Frame::Bulk(Bytes::copy_from_slice(part.as_bytes()))).collect())
+    }
+}
+
+/// Finds where the frame at the front of a buffer ends, a read at a time, without
+/// going back over what earlier reads already covered.
+#[derive(Debug, Default)]
+pub struct Scanner {
+    /// Where the next element to check starts. Everything before it is whole.
+    pos: usize,
+    /// How far the search for that element's `\r\n` has got.
+    searched: usize,
+    /// How many elements each array we're inside still needs, innermost last.
+    open: Vec<usize>,
+}
+
+impl Scanner {
+    /// Returns the length of the frame at the front of `buf` once all of it is there.
+    ///
+    /// Between calls `buf` may only grow. After `Some`, the scanner starts over and
+    /// expects the frame to have been taken off the front. Only the lengths are checked
+    /// here; the rest of the frame is checked when it's parsed.
+    pub fn complete(&mut self, buf: &[u8]) -> Result<Option<usize>, FrameError> {
+        loop {
+            let Some(&tag) = buf.get(self.pos) else {
+                return Ok(None);
+            };
+            let start = self.pos + 1;
+            let from = self.searched.max(start);
+            let Some(found) = buf[from..].windows(2).position(|pair| pair == b"\r\n") else {
+                if buf.len() - start > MAX_LINE_LEN {
+                    return Err(FrameError::TooLarge);
+                }
+                // The last byte may be a `\r` whose `\n` hasn't arrived yet.
+                self.searched = (buf.len() - 1).max(start);
+                return Ok(None);
+            };
+            let line_end = from + found;
+            let line = &buf[start..line_end];
+            let next = line_end + 2;
+            let end = match tag {
+                b'+' | b'-' | b':' => next,
+                b'$' => match parse_len(line, MAX_BULK_LEN)? {
+                    Some(len) => next + len + 2,
+                    None => next,
+                },
+                b'*' => match parse_len(line, MAX_ARRAY_LEN)? {
+                    Some(_) if self.open.len() == MAX_DEPTH => return Err(FrameError::TooDeep),
+                    Some(len) if len > 0 => {
+                        self.open.push(len);
+                        self.pos = next;
+                        self.searched = 0;
+                        continue;
+                    }
+                    _ => next,
+                },
+                other => return Err(FrameError::InvalidTag(other)),
+            };
+            if buf.len() < end {
+                // Only a bulk string's body is missing: next time, start at its line's end.
+                self.searched = line_end;
+                return Ok(None);
+            }
+            self.pos = end;
+            self.searched = 0;
+            // That element may have been the last one an array needed, and that array
+            // the last one its parent needed.
+            loop {
+                match self.open.last_mut() {
+                    None => {
+                        *self = Scanner::default();
+                        return Ok(Some(end));
+                    }
+                    Some(1) => {
+                        self.open.pop();
+                    }
+                    Some(remaining) => {
+                        *remaining -= 1;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn put_line(dst: &mut BytesMut, tag: u8, body: &[u8]) {
+    dst.put_u8(tag);
+    dst.put_slice(body);
+    dst.put_slice(b"\r\n");
+}
+
+/// Parses the frame starting at `pos`, returning it and the index just past its end.
+fn parse_at(buf: &[u8], pos: usize, depth: usize) -> Result<Option<(Frame, usize)>, FrameError> {
+    let Some(&tag) = buf.get(pos) else {
+        return Ok(None);
+    };
+    let Some((line, next)) = read_line(buf, pos + 1)? else {
+        return Ok(None);
+    };
+    let frame = match tag {
+        b'+' => Frame::Simple(utf8(line)?),
+        b'-' => Frame::Error(utf8(line)?),
+        b':' => Frame::Integer(parse_int(line)?),
+        b'$' => {
+            let Some(len) = parse_len(line, MAX_BULK_LEN)? else {
+                return Ok(Some((Frame::Null, next)));
+            };
+            let end = next + len;
+            if buf.len() < end + 2 {
+                return Ok(None);
+            }
+            if &buf[end..end + 2] != b"\r\n" {
+                return Err(FrameError::MissingCrlf);
+            }
+            return Ok(Some((Frame::Bulk(Bytes::copy_from_slice(&buf[next..end])), end + 2)));
+        }
+        b'*' => {
+            let Some(len) = parse_len(line, MAX_ARRAY_LEN)? else {
+                return Ok(Some((Frame::Null, next)));
+            };
+            if depth == MAX_DEPTH {
+                return Err(FrameError::TooDeep);
+            }
+            // Not `with_capacity(len)`: the count is the client's claim, and it
+            // hasn't sent the elements yet.
+            let mut frames = Vec::new();
+            let mut pos = next;
+            for _ in 0..len {
+                let Some((frame, end)) = parse_at(buf, pos, depth + 1)? else {
+                    return Ok(None);
+                };
+                frames.push(frame);
+                pos = end;
+            }
+            return Ok(Some((Frame::Array(frames), pos)));
+        }
+        other => return Err(FrameError::InvalidTag(other)),
+    };
+    Ok(Some((frame, next)))
+}
+
+/// Finds the `\r\n` ending the line that starts at `pos`, returning the line without
+/// it and the index after it.
+fn read_line(buf: &[u8], pos: usize) -> Result<Option<(&[u8], usize)>, FrameError> {
+    let rest = &buf[pos.min(buf.len())..];
+    match rest.windows(2).position(|pair| pair == b"\r\n") {
+        Some(end) => Ok(Some((&rest[..end], pos + end + 2))),
+        None if rest.len() > MAX_LINE_LEN => Err(FrameError::TooLarge),
+        None => Ok(None),
+    }
+}
+
+fn utf8(line: &[u8]) -> Result<String, FrameError> {
+    String::from_utf8(line.to_vec()).map_err(|_| FrameError::InvalidUtf8)
+}
+
+fn parse_int(line: &[u8]) -> Result<i64, FrameError> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(FrameError::InvalidInteger)
+}
+
+/// A `$` or `*` length: `None` for the null value, otherwise checked against `max`.
+fn parse_len(line: &[u8], max: usize) -> Result<Option<usize>, FrameError> {
+    match parse_int(line)? {
+        -1 => Ok(None),
+        len if len < 0 => Err(FrameError::InvalidLength(len)),
+        len => match usize::try_from(len) {
+            Ok(len) if len <= max => Ok(Some(len)),
+            _ => Err(FrameError::TooLarge),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(input: &[u8]) -> Result<Option<Frame>, FrameError> {
+        Frame::parse(&mut BytesMut::from(input))
+    }
+
+    #[test]
+    fn test_parse_each_type() {
+        assert_eq!(parse_all(b"+OK\r\n"), Ok(Some(Frame::Simple("OK".into()))));
+        assert_eq!(parse_all(b"-ERR no\r\n"), Ok(Some(Frame::Error("ERR no".into()))));
+        assert_eq!(parse_all(b":-42\r\n"), Ok(Some(Frame::Integer(-42))));
+        assert_eq!(parse_all(b"$5\r\nhe\r\no\r\n"), Ok(Some(Frame::Bulk(Bytes::from_static(b"he\r\no")))));
+        assert_eq!(parse_all(b"$0\r\n\r\n"), Ok(Some(Frame::Bulk(Bytes::new()))));
+        assert_eq!(parse_all(b"$-1\r\n"), Ok(Some(Frame::Null)));
+        assert_eq!(parse_all(b"*-1\r\n"), Ok(Some(Frame::Null)));
+        assert_eq!(
+            parse_all(b"*2\r\n:1\r\n*1\r\n+x\r\n"),
+            Ok(Some(Frame::Array(vec![Frame::Integer(1), Frame::Array(vec![Frame::Simple("x".into())])])))
+        );
+    }
+
+    #[test]
+    fn test_partial_frames_wait_for_more_bytes_one_byte_at_a_time() {
+        let mut wire = BytesMut::new();
+        Frame::command(&["SET", "key", "value"]).encode(&mut wire);
+        Frame::Integer(7).encode(&mut wire);
+
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        for &byte in wire.iter() {
+            buf.put_u8(byte);
+            while let Some(frame) = Frame::parse(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames, [Frame::command(&["SET", "key", "value"]), Frame::Integer(7)]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_scanner_finds_each_frame_end_one_byte_at_a_time() {
+        let frames = [
+            Frame::command(&["SET", "key", "va\r\nlue"]),
+            Frame::Array(vec![Frame::Array(vec![]), Frame::Null, Frame::Array(vec![Frame::Integer(1)])]),
+            Frame::Simple("OK".into()),
+            Frame::Bulk(Bytes::new()),
+        ];
+        let mut wire = BytesMut::new();
+        for frame in &frames {
+            frame.encode(&mut wire);
+        }
+
+        let mut scanner = Scanner::default();
+        let mut buf = BytesMut::new();
+        let mut parsed = Vec::new();
+        for &byte in wire.iter() {
+            buf.put_u8(byte);
+            if let Some(len) = scanner.complete(&buf).unwrap() {
+                let before = buf.len();
+                parsed.push(Frame::parse(&mut buf).unwrap().unwrap());
+                assert_eq!(before - buf.len(), len);
+            } else {
+                assert_eq!(Frame::parse(&mut buf.clone()), Ok(None));
+            }
+        }
+        assert_eq!(parsed, frames);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_scanner_rejects_what_the_parser_rejects() {
+        let complete = |input: &[u8]| Scanner::default().complete(input);
+        assert_eq!(complete(b"?x\r\n"), Err(FrameError::InvalidTag(b'?')));
+        assert_eq!(complete(b"$-2\r\n"), Err(FrameError::InvalidLength(-2)));
+        assert_eq!(complete(b"$99999999999\r\n"), Err(FrameError::TooLarge));
+        assert_eq!(complete(&[b'+'; MAX_LINE_LEN + 2]), Err(FrameError::TooLarge));
+        assert_eq!(complete(&b"*1\r\n".repeat(MAX_DEPTH + 1)), Err(FrameError::TooDeep));
+        assert_eq!(complete(&b"*1\r\n".repeat(MAX_DEPTH)), Ok(None));
+    }
+
+    #[test]
+    fn test_incomplete_frame_leaves_buffer_alone() {
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$1\r\n"[..]);
+        assert_eq!(Frame::parse(&mut buf), Ok(None));
+        assert_eq!(buf.len(), 17);
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let frames = [
+            Frame::Simple("OK".into()),
+            Frame::Error("ERR x".into()),
+            Frame::Integer(i64::MIN),
+            Frame::Bulk(Bytes::from_static(b"\x00\r\n")),
+            Frame::Null,
+            Frame::Array(vec![Frame::Null, Frame::command(&["PING"])]),
+        ];
+        for frame in frames {
+            let mut buf = BytesMut::new();
+            frame.encode(&mut buf);
+            assert_eq!(Frame::parse(&mut buf), Ok(Some(frame)));
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_malformed_input_is_rejected() {
+        assert_eq!(parse_all(b"?x\r\n"), Err(FrameError::InvalidTag(b'?')));
+        assert_eq!(parse_all(b":12a\r\n"), Err(FrameError::InvalidInteger));
+        assert_eq!(parse_all(b"$-2\r\n"), Err(FrameError::InvalidLength(-2)));
+        assert_eq!(parse_all(b"$3\r\nabcd\r\n"), Err(FrameError::MissingCrlf));
+        assert_eq!(parse_all(b"$99999999999\r\n"), Err(FrameError::TooLarge));
+        assert_eq!(parse_all(b"+\xff\r\n"), Err(FrameError::InvalidUtf8));
+        assert_eq!(parse_all(&[b'+'; MAX_LINE_LEN + 2]), Err(FrameError::TooLarge));
+        assert_eq!(parse_all(&b"*1\r\n".repeat(MAX_DEPTH + 1)), Err(FrameError::TooDeep));
+    }
+}
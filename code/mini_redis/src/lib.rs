@@ -0,0 +1,164 @@
+//! A small Redis-compatible server: a hand-written RESP2 parser, a keyspace with
+//! expiry, and the usual graceful shutdown.
+//!
+//! The layers, from the socket up:
+//!
+//! - [`frame`] turns bytes into RESP frames, coping with frames split across reads;
+//! - [`codec`] plugs that into `tokio_util::codec::Framed`;
+//! - `cmd` reads a command out of a frame and applies it to `db`;
+//! - `db` holds the keys and runs the background task that deletes them on expiry.
+//!
+//! Because reading the next frame is the only thing a connection waits on, pipelining
+//! comes for free: a client can send ten commands in one write and gets ten replies,
+//! in order. Shutdown only ever lands between commands, never halfway through one.
+//!
+//! `redis-cli -p 6380` works as a client for the commands supported here.
+
+pub mod codec;
+mod cmd;
+mod db;
+pub mod frame;
+
+pub use codec::{ProtocolError, RespCodec};
+pub use frame::Frame;
+
+use cmd::Command;
+use db::Db;
+use futures::{SinkExt, StreamExt};
+use shutdown_util::ShutdownController;
+use shutdown_util::backoff::AcceptBackoff;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::sleep;
+use tokio_util::codec::Framed;
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How long connections get to finish after shutdown.
+    pub drain_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Serves clients accepted on `listener` until shutdown is signalled, then drains.
+pub async fn run_server(
+    listener: TcpListener,
+    config: ServerConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let db = Arc::new(Db::default());
+    controller.spawn(db::purge_expired_keys(db.clone(), controller.subscribe()));
+    let mut next_conn_id = 0_u64;
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[server] shutdown requested, no longer accepting");
+                break;
+            }
+            accepted = listener.accept() => {
+                let (socket, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let delay = backoff.on_error();
+                        eprintln!("[server] accept failed: {e}, retrying in {delay:?}");
+                        sleep(delay).await;
+                        continue;
+                    }
+                };
+                if let Some(failures) = backoff.on_success() {
+                    println!("[server] accepting again after {failures} failure(s)");
+                }
+                next_conn_id += 1;
+                let id = next_conn_id;
+                let db = db.clone();
+                let conn_shutdown = controller.subscribe();
+                controller.spawn(async move {
+                    match serve_client(socket, id, peer, &db, conn_shutdown).await {
+                        Ok(commands) => println!("[server] conn={id} peer={peer} closed after {commands} command(s)"),
+                        Err(e) => eprintln!("[server] conn={id} peer={peer} failed: {e}"),
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every client that ever hung up piling up, and leaves the drain only the ones
+            // still open.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[server] task join error: {e}");
+                }
+            }
+        }
+    }
+    drop(listener);
+
+    println!(
+        "[server] waiting up to {:?} for {} task(s) to finish",
+        config.drain_timeout,
+        controller.active_tasks()
+    );
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[server] task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[server] drain deadline hit, aborted {} task(s)", report.aborted);
+    } else {
+        println!("[server] all connections finished");
+    }
+    Ok(())
+}
+
+/// Answers one client's commands until it hangs up or the server shuts down. Returns
+/// how many commands it ran.
+async fn serve_client(
+    socket: TcpStream,
+    id: u64,
+    peer: SocketAddr,
+    db: &Db,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> Result<u64, ProtocolError> {
+    println!("[server] conn={id} peer={peer} connected");
+    let mut frames = Framed::new(socket, RespCodec::default());
+    let mut commands = 0;
+
+    loop {
+        let frame = tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(commands),
+            // Cancel safe: `Framed` keeps any partial frame in its buffer.
+            frame = frames.next() => frame,
+        };
+        let frame = match frame {
+            None => return Ok(commands),
+            Some(Ok(frame)) => frame,
+            // Like Redis: explain, then hang up, since we can't tell where the next
+            // frame starts.
+            Some(Err(ProtocolError::Frame(e))) => {
+                frames.send(Frame::Error(format!("ERR Protocol error: {e}"))).await?;
+                return Err(e.into());
+            }
+            Some(Err(e)) => return Err(e),
+        };
+        let reply = match Command::from_frame(frame) {
+            Ok(command) => {
+                commands += 1;
+                command.apply(db)
+            }
+            Err(message) => Frame::Error(message),
+        };
+        frames.send(reply).await?;
+    }
+}
@@ -0,0 +1,53 @@
+use clap::Parser;
+use mini_redis::{ServerConfig, run_server};
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// A mini Redis (GET, SET, DEL, EXPIRE, TTL, PING) that shuts down gracefully on
+/// Ctrl-C or SIGTERM.
+///
+/// Try it with `redis-cli -p 6380`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on. Not 6379, so it won't clash with a real Redis.
+    #[arg(long, default_value = "127.0.0.1:6380")]
+    bind: SocketAddr,
+    /// Seconds connections get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = ServerConfig {
+        drain_timeout: cli.drain_timeout,
+    };
+
+    let listener = TcpListener::bind(cli.bind).await?;
+    println!("[main] listening on {}", listener.local_addr()?);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let server = tokio::spawn(run_server(listener, config, controller, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match server.await {
+        Ok(Ok(())) => println!("[main] server exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
+        Err(e) => eprintln!("[main] server task failed: {e}"),
+    }
+    Ok(())
+}
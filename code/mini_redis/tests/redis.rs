@@ -0,0 +1,112 @@
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use mini_redis::{Frame, RespCodec, ServerConfig, run_server};
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_util::codec::Framed;
+
+async fn start_server() -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    spawn_server(|listener, controller, shutdown_rx| run_server(listener, ServerConfig::default(), controller, shutdown_rx)).await
+}
+
+async fn connect(addr: SocketAddr) -> Framed<TcpStream, RespCodec> {
+    Framed::new(TcpStream::connect(addr).await.unwrap(), RespCodec::default())
+}
+
+async fn call(client: &mut Framed<TcpStream, RespCodec>, parts: &[&str]) -> Frame {
+    client.send(Frame::command(parts)).await.unwrap();
+    timeout(Duration::from_secs(2), client.next())
+        .await
+        .expect("timed out waiting for a reply")
+        .expect("connection closed")
+        .unwrap()
+}
+
+fn bulk(s: &'static str) -> Frame {
+    Frame::Bulk(Bytes::from_static(s.as_bytes()))
+}
+
+#[tokio::test]
+async fn test_get_set_del() {
+    let (addr, trigger, task) = start_server().await;
+    let mut client = connect(addr).await;
+
+    assert_eq!(call(&mut client, &["PING"]).await, Frame::Simple("PONG".into()));
+    assert_eq!(call(&mut client, &["GET", "k"]).await, Frame::Null);
+    assert_eq!(call(&mut client, &["SET", "k", "hello world"]).await, Frame::Simple("OK".into()));
+    assert_eq!(call(&mut client, &["GET", "k"]).await, bulk("hello world"));
+    assert_eq!(call(&mut client, &["DEL", "k", "missing"]).await, Frame::Integer(1));
+    assert!(matches!(call(&mut client, &["NOPE"]).await, Frame::Error(_)));
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+    assert!(client.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_keys_expire() {
+    let (addr, trigger, task) = start_server().await;
+    let mut client = connect(addr).await;
+
+    assert_eq!(call(&mut client, &["SET", "short", "1", "PX", "100"]).await, Frame::Simple("OK".into()));
+    assert_eq!(call(&mut client, &["SET", "later", "2"]).await, Frame::Simple("OK".into()));
+    assert_eq!(call(&mut client, &["EXPIRE", "later", "60"]).await, Frame::Integer(1));
+    assert_eq!(call(&mut client, &["TTL", "later"]).await, Frame::Integer(60));
+    assert_eq!(call(&mut client, &["GET", "short"]).await, bulk("1"));
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(call(&mut client, &["GET", "short"]).await, Frame::Null);
+    assert_eq!(call(&mut client, &["TTL", "short"]).await, Frame::Integer(-2));
+    assert_eq!(call(&mut client, &["GET", "later"]).await, bulk("2"));
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_pipelined_commands_split_across_writes() {
+    let (addr, trigger, task) = start_server().await;
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+
+    let wire = b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$3\r\nGET\r\n$1\r\na\r\n";
+    // Cut mid-frame, mid-length and mid-payload: the server must wait for the rest.
+    for chunk in [&wire[..7], &wire[7..20], &wire[20..40], &wire[40..]] {
+        socket.write_all(chunk).await.unwrap();
+        socket.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let expected = b"+OK\r\n$1\r\n1\r\n";
+    let mut reply = vec![0_u8; expected.len()];
+    timeout(Duration::from_secs(2), socket.read_exact(&mut reply))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(reply, expected);
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_malformed_frame_gets_an_error_and_a_hangup() {
+    let (addr, trigger, task) = start_server().await;
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+
+    socket.write_all(b"?garbage\r\n").await.unwrap();
+    let mut reply = String::new();
+    timeout(Duration::from_secs(2), socket.read_to_string(&mut reply))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(reply.starts_with("-ERR Protocol error:"), "got {reply:?}");
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
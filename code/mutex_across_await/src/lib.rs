@@ -0,0 +1,203 @@
+//! A lock held across an `.await`, and what to do instead.
+//!
+//! The tasks here each do a few rounds of the same thing: fetch a value, which takes
+//! an await, and add it to a total they share behind a mutex. The obvious way, with
+//! `std::sync::Mutex`, holds the guard while it awaits the fetch, and doesn't spawn:
+//!
+//! ```compile_fail
+//! use std::sync::{Arc, Mutex};
+//! use std::time::Duration;
+//!
+//! let total = Arc::new(Mutex::new(0u64));
+//! tokio::spawn(async move {
+//!     let mut total = total.lock().unwrap();
+//!     tokio::time::sleep(Duration::from_millis(5)).await;
+//!     *total += 1;
+//! });
+//! ```
+//!
+//! "future cannot be sent between threads safely": `std::sync::MutexGuard<'_, u64>`
+//! isn't `Send`, and the future holds it across the await.
+//!
+//! A std guard has to be unlocked on the thread that locked it, and a task may be
+//! resumed on another thread after any await. That's the compiler's way of saying the
+//! design is wrong, but the same futures run together in one task, with `join_all`,
+//! compile, and clippy's `await_holding_lock` is all that stands in the way. Then with
+//! two or more of them it deadlocks: the first locks and waits on its fetch, the second
+//! blocks the whole thread waiting for the lock, and the first can never be polled again
+//! to let it go. With one it works, which is how it gets past a test.
+//!
+//! There are two fixes:
+//!
+//! - [`Approach::TokioAcrossAwait`]: `tokio::sync::Mutex`, whose `lock().await` waits
+//!   as a task rather than blocking the thread, and whose guard is `Send`. It works, but
+//!   the critical section still spans the fetch, so every round of every task waits for
+//!   every other's: the fetches happen one at a time.
+//! - [`Approach::Narrowed`]: the fetch done first, outside any lock, and the std mutex
+//!   locked just for the addition, with no await inside. The fetches overlap, and the
+//!   lock's held for nanoseconds. What to reach for first; tokio's is for when the
+//!   critical section really has to await, such as for an IO resource shared whole.
+//!
+//! [`run`] does a [`Workload`] each way and measures how long it took and how long each
+//! round waited for the lock.
+
+use std::future::Future;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Tasks that each do `rounds` rounds, every fetch taking `fetch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workload {
+    pub tasks: usize,
+    pub rounds: u64,
+    pub fetch: Duration,
+}
+
+impl Workload {
+    /// What the total comes to once every round of every task has added its value.
+    pub fn expected_total(&self) -> u64 {
+        self.tasks as u64 * (0..self.rounds).sum::<u64>()
+    }
+}
+
+/// How the tasks share the total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approach {
+    /// A std guard held across the fetch, in one task, as that's the only way it
+    /// compiles.
+    StdAcrossAwait,
+    /// A tokio guard held across the fetch, a task each.
+    TokioAcrossAwait,
+    /// The fetch first, then a std guard just for the addition, a task each.
+    Narrowed,
+}
+
+impl Approach {
+    pub const ALL: [Approach; 3] = [Approach::StdAcrossAwait, Approach::TokioAcrossAwait, Approach::Narrowed];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Approach::StdAcrossAwait => "std across await",
+            Approach::TokioAcrossAwait => "tokio across await",
+            Approach::Narrowed => "narrowed",
+        }
+    }
+}
+
+/// How a run went: how long it took, the total it came to, and how long each round
+/// waited for the lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contention {
+    pub elapsed: Duration,
+    pub total: u64,
+    pub waits: Vec<Duration>,
+}
+
+impl Contention {
+    pub fn mean_wait(&self) -> Duration {
+        self.waits.iter().sum::<Duration>() / self.waits.len().max(1) as u32
+    }
+
+    pub fn max_wait(&self) -> Duration {
+        self.waits.iter().copied().max().unwrap_or_default()
+    }
+}
+
+/// The await each round makes: a value that takes `fetch` to get.
+async fn fetch(round: u64, fetch: Duration) -> u64 {
+    tokio::time::sleep(fetch).await;
+    round
+}
+
+#[expect(clippy::await_holding_lock, reason = "the pitfall on show")]
+async fn std_across_await(total: Arc<std::sync::Mutex<u64>>, workload: Workload) -> Vec<Duration> {
+    let mut waits = Vec::new();
+    for round in 0..workload.rounds {
+        let asked = Instant::now();
+        let mut total = total.lock().unwrap();
+        waits.push(asked.elapsed());
+        *total += fetch(round, workload.fetch).await;
+    }
+    waits
+}
+
+async fn tokio_across_await(total: Arc<tokio::sync::Mutex<u64>>, workload: Workload) -> Vec<Duration> {
+    let mut waits = Vec::new();
+    for round in 0..workload.rounds {
+        let asked = Instant::now();
+        let mut total = total.lock().await;
+        waits.push(asked.elapsed());
+        *total += fetch(round, workload.fetch).await;
+    }
+    waits
+}
+
+async fn narrowed(total: Arc<std::sync::Mutex<u64>>, workload: Workload) -> Vec<Duration> {
+    let mut waits = Vec::new();
+    for round in 0..workload.rounds {
+        let value = fetch(round, workload.fetch).await;
+        let asked = Instant::now();
+        // The guard's dropped at the end of the statement, long before the next await.
+        *total.lock().unwrap() += value;
+        waits.push(asked.elapsed());
+    }
+    waits
+}
+
+/// Runs `futures`, a task each, and gathers their waits.
+async fn spawn_all<F>(futures: impl Iterator<Item = F>) -> Vec<Duration>
+where
+    F: Future<Output = Vec<Duration>> + Send + 'static,
+{
+    let handles: Vec<_> = futures.map(tokio::spawn).collect();
+    futures::future::join_all(handles).await.into_iter().flat_map(|waits| waits.expect("a task doesn't panic")).collect()
+}
+
+async fn contend(approach: Approach, workload: Workload) -> (u64, Vec<Duration>) {
+    match approach {
+        Approach::StdAcrossAwait => {
+            let total = Arc::new(std::sync::Mutex::new(0));
+            let waits = futures::future::join_all((0..workload.tasks).map(|_| std_across_await(total.clone(), workload))).await;
+            (*total.lock().unwrap(), waits.concat())
+        }
+        Approach::TokioAcrossAwait => {
+            let total = Arc::new(tokio::sync::Mutex::new(0));
+            let waits = spawn_all((0..workload.tasks).map(|_| tokio_across_await(total.clone(), workload))).await;
+            (*total.lock().await, waits)
+        }
+        Approach::Narrowed => {
+            let total = Arc::new(std::sync::Mutex::new(0));
+            let waits = spawn_all((0..workload.tasks).map(|_| narrowed(total.clone(), workload))).await;
+            (*total.lock().unwrap(), waits)
+        }
+    }
+}
+
+/// Does `workload` the `approach` way on a multi-thread runtime of `workers`, on a thread
+/// of its own, and returns how it went, or `None` if it hadn't finished after `limit`:
+/// deadlocked, in which case that thread and its runtime are left stuck for good.
+pub fn run(approach: Approach, workload: Workload, workers: usize, limit: Duration) -> Option<Contention> {
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(workers).enable_all().build().expect("building a runtime");
+        let start = Instant::now();
+        let (total, waits) = runtime.block_on(contend(approach, workload));
+        let _ = done.send(Contention { elapsed: start.elapsed(), total, waits });
+    });
+    finished.recv_timeout(limit).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waits_summarise() {
+        let contention = Contention { elapsed: Duration::ZERO, total: 0, waits: vec![Duration::from_millis(1), Duration::from_millis(5)] };
+        assert_eq!(contention.mean_wait(), Duration::from_millis(3));
+        assert_eq!(contention.max_wait(), Duration::from_millis(5));
+        let none = Contention { waits: Vec::new(), ..contention };
+        assert_eq!((none.mean_wait(), none.max_wait()), (Duration::ZERO, Duration::ZERO));
+    }
+}
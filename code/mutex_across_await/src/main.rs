@@ -0,0 +1,63 @@
+use clap::Parser;
+use mutex_across_await::{Approach, Workload, run};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Has tasks fetch values and add them to a shared total, with the lock held across the
+/// fetch or not, and tabulates how long each way took and how long the lock kept the
+/// rounds waiting.
+///
+/// A std guard held across the fetch deadlocks, with more than one task, and is given
+/// up on after `--limit`; tokio's guard across it works, but the fetches go one at a
+/// time and every round waits for the others'; the narrowed critical section overlaps
+/// them and hardly waits at all.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Tasks sharing the total.
+    #[arg(long, default_value_t = 8)]
+    tasks: usize,
+    /// Fetches each task makes.
+    #[arg(long, default_value_t = 10)]
+    rounds: u64,
+    /// Milliseconds each fetch takes.
+    #[arg(long, value_name = "MS", default_value_t = 5)]
+    fetch_ms: u64,
+    /// Worker threads for each run's runtime.
+    #[arg(long, default_value_t = 2)]
+    workers: usize,
+    /// Seconds to give each run before calling it deadlocked.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    limit: Duration,
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if cli.tasks == 0 || cli.workers == 0 {
+        eprintln!("[main] --tasks and --workers have to be at least 1");
+        return ExitCode::FAILURE;
+    }
+    let workload = Workload { tasks: cli.tasks, rounds: cli.rounds, fetch: Duration::from_millis(cli.fetch_ms) };
+    println!("[main] {} task(s) x {} fetch(es) of {:?}, on {} worker(s)", workload.tasks, workload.rounds, workload.fetch, cli.workers);
+
+    let mut wrong = false;
+    println!("\n{:20} {:>12} {:>12} {:>12}", "lock", "took", "mean wait", "max wait");
+    for approach in Approach::ALL {
+        match run(approach, workload, cli.workers, cli.limit) {
+            Some(contention) => {
+                wrong |= contention.total != workload.expected_total();
+                println!("{:20} {:>12.1?} {:>12.1?} {:>12.1?}", approach.name(), contention.elapsed, contention.mean_wait(), contention.max_wait());
+            }
+            None => println!("{:20} deadlocked: no end after {:?}", approach.name(), cli.limit),
+        }
+    }
+    if wrong {
+        eprintln!("[main] a total came out wrong");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
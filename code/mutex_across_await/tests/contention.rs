@@ -0,0 +1,40 @@
+//! Each approach on the same small workload, on the real clock.
+
+use mutex_across_await::{Approach, Workload, run};
+use std::time::Duration;
+
+const WORKLOAD: Workload = Workload { tasks: 4, rounds: 5, fetch: Duration::from_millis(10) };
+/// Several times what the slowest approach that finishes takes.
+const LIMIT: Duration = Duration::from_secs(2);
+
+#[test]
+fn test_a_std_guard_across_an_await_deadlocks_with_two_futures() {
+    assert_eq!(run(Approach::StdAcrossAwait, Workload { tasks: 2, ..WORKLOAD }, 2, Duration::from_millis(500)), None);
+}
+
+#[test]
+fn test_a_std_guard_across_an_await_gets_away_with_one_future() {
+    let workload = Workload { tasks: 1, ..WORKLOAD };
+    assert_eq!(run(Approach::StdAcrossAwait, workload, 2, LIMIT).expect("it finishes").total, workload.expected_total());
+}
+
+#[test]
+fn test_a_tokio_guard_across_an_await_does_the_fetches_one_at_a_time() {
+    let contention = run(Approach::TokioAcrossAwait, WORKLOAD, 2, LIMIT).expect("it finishes");
+    assert_eq!(contention.total, WORKLOAD.expected_total());
+    // Twenty fetches of 10ms, in turn.
+    let serial = WORKLOAD.fetch * (WORKLOAD.tasks as u32 * WORKLOAD.rounds as u32);
+    assert!(contention.elapsed >= serial, "{:?}", contention.elapsed);
+    // Most rounds wait for the other three tasks' fetches.
+    assert!(contention.mean_wait() >= WORKLOAD.fetch, "{:?}", contention.mean_wait());
+}
+
+#[test]
+fn test_a_narrowed_critical_section_overlaps_the_fetches() {
+    let contention = run(Approach::Narrowed, WORKLOAD, 2, LIMIT).expect("it finishes");
+    assert_eq!(contention.total, WORKLOAD.expected_total());
+    // Five rounds of 10ms, the tasks together: nowhere near the 200ms of doing them in turn.
+    let serial = WORKLOAD.fetch * (WORKLOAD.tasks as u32 * WORKLOAD.rounds as u32);
+    assert!(contention.elapsed < serial / 2, "{:?}", contention.elapsed);
+    assert!(contention.max_wait() < WORKLOAD.fetch / 2, "{:?}", contention.max_wait());
+}
@@ -0,0 +1,109 @@
+//! Blocking on a future from inside async code, which sync code called from async code
+//! ends up wanting to do: a synchronous trait method with an async client behind it,
+//! say, or a callback from a library that knows nothing of tokio.
+//!
+//! Each case here is a whole program's worth: it starts a runtime, as `#[tokio::main]`
+//! would, and from inside it waits for [`answer`] like that.
+//!
+//! - [`runtime_block_on`]: a second runtime's `block_on`. Panics: a thread that's
+//!   running a runtime can't start another, as the outer one's tasks would stop while
+//!   it ran.
+//! - [`handle_block_on`]: `Handle::current().block_on`. Panics for the same reason, and
+//!   would deadlock on a current-thread runtime if it didn't, since the thread that has
+//!   to run the future is the one waiting for it.
+//! - [`drop_runtime`]: a runtime made for the purpose, and dropped in async code.
+//!   Panics: dropping a runtime waits for its blocking threads.
+//! - [`futures_block_on`]: `futures::executor::block_on`, which doesn't know about tokio
+//!   and so never panics. On a current-thread runtime it deadlocks instead: it parks the
+//!   only thread that could run the task it's waiting for.
+//!
+//! And the ways that work, both by blocking a thread that the runtime doesn't need:
+//!
+//! - [`spawn_blocking`]: `Handle::current()`, taken in async code, and its `block_on`
+//!   on the blocking pool, awaited. Works on either flavour of runtime.
+//! - [`block_in_place`]: `Handle::current().block_on` inside `block_in_place`, which
+//!   hands this worker's other tasks to another thread first. Multi-thread only.
+//!
+//! Better than either is not to block at all: make the sync code async, or give it a
+//! channel to send its request on and an answer to wait for, as `second_runtime` does.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// What each case waits for: a value from a task that the runtime has to run, and a
+/// timer it has to drive.
+pub async fn answer() -> u64 {
+    tokio::spawn(async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        42
+    })
+    .await
+    .expect("the answer doesn't panic")
+}
+
+fn multi_thread() -> Runtime {
+    Builder::new_multi_thread().worker_threads(2).enable_all().build().expect("building a runtime")
+}
+
+fn current_thread() -> Runtime {
+    Builder::new_current_thread().enable_all().build().expect("building a runtime")
+}
+
+/// `Runtime::block_on` inside async code. Panics.
+pub fn runtime_block_on() -> u64 {
+    multi_thread().block_on(async {
+        let inner = current_thread();
+        inner.block_on(answer())
+    })
+}
+
+/// `Handle::current().block_on` inside async code. Panics.
+pub fn handle_block_on() -> u64 {
+    multi_thread().block_on(async { Handle::current().block_on(answer()) })
+}
+
+/// A runtime dropped inside async code, after it's done its job. Panics when it's
+/// dropped.
+pub fn drop_runtime() -> u64 {
+    multi_thread().block_on(async {
+        // Made and used on a thread of its own, which is allowed, and handed back.
+        let (inner, answer) = thread::spawn(|| {
+            let inner = current_thread();
+            let answer = inner.block_on(answer());
+            (inner, answer)
+        })
+        .join()
+        .expect("the thread doesn't panic");
+        drop(inner);
+        answer
+    })
+}
+
+/// `futures::executor::block_on` inside async code on a current-thread runtime.
+/// Deadlocks; `None` if it's still stuck after `limit`, in which case its thread is
+/// left stuck for good.
+pub fn futures_block_on(limit: Duration) -> Option<u64> {
+    let (done, answered) = mpsc::channel();
+    thread::spawn(move || {
+        let answer = current_thread().block_on(async { futures::executor::block_on(answer()) });
+        let _ = done.send(answer);
+    });
+    answered.recv_timeout(limit).ok()
+}
+
+/// `Handle::block_on` on the blocking pool, awaited from async code on a current-thread
+/// runtime. Works.
+pub fn spawn_blocking() -> u64 {
+    current_thread().block_on(async {
+        let handle = Handle::current();
+        tokio::task::spawn_blocking(move || handle.block_on(answer())).await.expect("the answer doesn't panic")
+    })
+}
+
+/// `Handle::block_on` inside `block_in_place`, in async code on a multi-thread runtime.
+/// Works.
+pub fn block_in_place() -> u64 {
+    multi_thread().block_on(async { tokio::task::block_in_place(|| Handle::current().block_on(answer())) })
+}
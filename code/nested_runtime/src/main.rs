@@ -0,0 +1,58 @@
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Blocks on a future from inside async code, one way per subcommand.
+///
+/// `runtime-block-on`, `handle-block-on` and `drop-runtime` panic, and say why;
+/// `futures-block-on` deadlocks, and gives up after `--limit`; `spawn-blocking` and
+/// `block-in-place` work. Each starts a runtime of its own, so run them one at a time.
+#[derive(Debug, Parser)]
+struct Cli {
+    #[command(subcommand)]
+    case: Case,
+}
+
+#[derive(Debug, Subcommand)]
+enum Case {
+    /// `Runtime::block_on` on a second runtime. Panics.
+    RuntimeBlockOn,
+    /// `Handle::current().block_on`. Panics.
+    HandleBlockOn,
+    /// Drop a runtime. Panics.
+    DropRuntime,
+    /// `futures::executor::block_on`, on a current-thread runtime. Deadlocks.
+    FuturesBlockOn {
+        /// Seconds to wait for it before giving up.
+        #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+        limit: Duration,
+    },
+    /// `Handle::block_on` inside `spawn_blocking`, on a current-thread runtime. Works.
+    SpawnBlocking,
+    /// `Handle::block_on` inside `block_in_place`, on a multi-thread runtime. Works.
+    BlockInPlace,
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+fn main() -> ExitCode {
+    let answer = match Cli::parse().case {
+        Case::RuntimeBlockOn => nested_runtime::runtime_block_on(),
+        Case::HandleBlockOn => nested_runtime::handle_block_on(),
+        Case::DropRuntime => nested_runtime::drop_runtime(),
+        Case::FuturesBlockOn { limit } => match nested_runtime::futures_block_on(limit) {
+            Some(answer) => answer,
+            None => {
+                eprintln!("[main] still no answer after {limit:?}: block_on parked the only thread that could run the task it's waiting for");
+                return ExitCode::FAILURE;
+            }
+        },
+        Case::SpawnBlocking => nested_runtime::spawn_blocking(),
+        Case::BlockInPlace => nested_runtime::block_in_place(),
+    };
+    println!("[main] the answer is {answer}");
+    ExitCode::SUCCESS
+}
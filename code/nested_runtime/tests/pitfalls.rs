@@ -0,0 +1,40 @@
+//! Each case run to the end, or to its panic, or as far as its deadlock.
+
+use std::panic;
+use std::time::Duration;
+
+/// Runs `case`, and returns its panic's message.
+fn panic_message(case: fn() -> u64) -> String {
+    let panic = panic::catch_unwind(case).expect_err("it panics");
+    panic.downcast_ref::<&str>().map(|message| message.to_string()).or_else(|| panic.downcast_ref::<String>().cloned()).unwrap_or_default()
+}
+
+#[test]
+fn test_a_second_runtime_cant_block_on_inside_async_code() {
+    let message = panic_message(nested_runtime::runtime_block_on);
+    assert!(message.contains("Cannot start a runtime from within a runtime"), "{message}");
+}
+
+#[test]
+fn test_the_current_handle_cant_block_on_inside_async_code() {
+    let message = panic_message(nested_runtime::handle_block_on);
+    assert!(message.contains("Cannot start a runtime from within a runtime"), "{message}");
+}
+
+#[test]
+fn test_a_runtime_cant_be_dropped_inside_async_code() {
+    let message = panic_message(nested_runtime::drop_runtime);
+    assert!(message.contains("Cannot drop a runtime in a context where blocking is not allowed"), "{message}");
+}
+
+#[test]
+fn test_futures_block_on_deadlocks_a_current_thread_runtime() {
+    // The answer takes 10ms when it can be had at all.
+    assert_eq!(nested_runtime::futures_block_on(Duration::from_millis(500)), None);
+}
+
+#[test]
+fn test_blocking_on_a_thread_the_runtime_doesnt_need_works() {
+    assert_eq!(nested_runtime::spawn_blocking(), 42);
+    assert_eq!(nested_runtime::block_in_place(), 42);
+}
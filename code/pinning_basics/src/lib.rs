@@ -0,0 +1,212 @@
+//! Why `Pin` exists, what `Unpin` means, and the two ways this repo pins things.
+//!
+//! An `async` block that borrows one of its own locals across an `await`, as in
+//!
+//! ```text
+//! async {
+//!     let buf = [1u8; 16];
+//!     let head = &buf[..4];
+//!     tokio::task::yield_now().await;
+//!     head.iter().sum::<u8>()
+//! }
+//! ```
+//!
+//! compiles to a state machine that holds `buf` and `head` both, with `head` pointing
+//! into `buf`: into itself. Move it after the first poll and `buf` goes with it, but
+//! `head` still points where `buf` was. [`SelfRef`] is that state machine written out
+//! by hand, with a check the compiler's doesn't have: it compares the address it kept
+//! with where its buffer is now, and says [`Moved`] instead of reading through it.
+//!
+//! `Future::poll` takes `self: Pin<&mut Self>` to rule the move out. A `Pin` is a
+//! promise that what it points to won't move again until it's dropped, and there are
+//! only two ways to make one for a type that needs it: `Box::pin`, which puts it on
+//! the heap, where moving the box leaves it where it is, and `pin!`, or tokio's
+//! `tokio::pin!`, which pins it on the stack and shadows the name so it can't be
+//! moved. Both are safe; `Pin::new`, on a plain `&mut`, is allowed only for `Unpin`
+//! types: the ones that don't mind being moved, which is almost all of them. Every
+//! `async` block and generator stream counts as not `Unpin`, as the compiler can't tell
+//! whether it borrows from itself, and so does [`Guarded`], from its `PhantomPinned`.
+//! [`Unguarded`] is the same future without it, and so forgets the first poll's promise
+//! as soon as it's moved.
+//!
+//! That's why the repo does what it does: `manual_stream`'s `Ticks` boxes its `Sleep`,
+//! and so is `Unpin` and can have `next()` called on it wherever it is, where
+//! `stream_generators`' ticks, a generator, has to be pinned first. A future that
+//! `select!` polls again on each turn of a loop, through a `&mut`, has to be pinned
+//! before the loop, as in [`ticks_before`]; futures of different types in one `Vec`
+//! are boxed into the same `Pin<Box<dyn Future>>`, which is `Unpin` as well, for
+//! `select_all` to take, as in [`first_done`].
+
+use futures::StreamExt;
+use std::future::Future;
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The answer [`SelfRef`] gives when it's polled after being moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Moved;
+
+/// The `async` block in the module's docs, by hand: a buffer, and after the first poll
+/// a pointer into it, which the second poll sums the first four bytes through. `P` is
+/// `PhantomPinned` for [`Guarded`], which makes it not `Unpin`, or `()` for
+/// [`Unguarded`], which doesn't.
+#[derive(Debug)]
+pub struct SelfRef<P> {
+    buf: [u8; 16],
+    /// Null until the first poll; `head` in the `async` block.
+    head: *const u8,
+    _pin: P,
+}
+
+/// A [`SelfRef`] that has to be pinned to be polled, and so can't be moved in between.
+pub type Guarded = SelfRef<PhantomPinned>;
+/// A [`SelfRef`] that can be polled with `Pin::new`, and moved after.
+pub type Unguarded = SelfRef<()>;
+
+impl<P: Default> SelfRef<P> {
+    pub fn new() -> Self {
+        Self { buf: [1; 16], head: ptr::null(), _pin: P::default() }
+    }
+}
+
+impl<P: Default> Default for SelfRef<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> SelfRef<P> {
+    /// Whether its pointer, if it has one yet, points into itself.
+    pub fn is_intact(&self) -> bool {
+        self.head.is_null() || ptr::eq(self.head, self.buf.as_ptr())
+    }
+}
+
+impl<P> Future for SelfRef<P> {
+    type Output = Result<u8, Moved>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safe: nothing here moves it. Pinning is a promise about what happens between
+        // polls, which for `Unguarded` nothing enforces.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.head.is_null() {
+            // The first poll: take the pointer, and wait, like the `await`.
+            this.head = this.buf.as_ptr();
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        if !this.is_intact() {
+            // The compiler's state machine would read through it here, from wherever
+            // the buffer used to be.
+            return Poll::Ready(Err(Moved));
+        }
+        // Safe: it points at `buf`, where it is now, and `buf` has 16 bytes.
+        let head = unsafe { std::slice::from_raw_parts(this.head, 4) };
+        Poll::Ready(Ok(head.iter().sum()))
+    }
+}
+
+/// How many of `ticks` come before `deadline` goes by. The deadline's sleep is made once
+/// and polled again by `select!` on each turn of the loop, through `&mut`, so it's
+/// pinned first, on the stack; the ticks are `Unpin`, as they box their own sleep.
+pub async fn ticks_before(mut ticks: manual_stream::Ticks, deadline: Duration) -> u64 {
+    let sleep = tokio::time::sleep(deadline);
+    tokio::pin!(sleep);
+    let mut seen = 0;
+    loop {
+        tokio::select! {
+            _ = &mut sleep => return seen,
+            tick = ticks.next() => match tick {
+                Some(_) => seen += 1,
+                None => return seen,
+            },
+        }
+    }
+}
+
+/// A future of any type, boxed and pinned, so that futures of different types fit in
+/// one `Vec`.
+pub type BoxedJob = Pin<Box<dyn Future<Output = &'static str> + Send>>;
+
+/// Three jobs, each a different type of future, that take longer the later they are.
+pub fn jobs(step: Duration) -> Vec<BoxedJob> {
+    vec![
+        Box::pin(async move {
+            tokio::time::sleep(3 * step).await;
+            "a sleep"
+        }),
+        Box::pin(async move {
+            manual_stream::Ticks::new(step, 2).count().await;
+            "two ticks"
+        }),
+        Box::pin(async move {
+            tokio::time::sleep_until(Instant::now() + step).await;
+            "a sleep_until"
+        }),
+    ]
+}
+
+/// Runs `jobs` together, and returns what each said, in the order they finished.
+/// `select_all` wants `Unpin` futures, as it holds them in a `Vec` and moves them
+/// about; boxed and pinned, they are.
+pub async fn first_done(mut jobs: Vec<BoxedJob>) -> Vec<&'static str> {
+    let mut done = Vec::new();
+    while !jobs.is_empty() {
+        let (said, _, rest) = futures::future::select_all(jobs).await;
+        done.push(said);
+        jobs = rest;
+    }
+    done
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::pin;
+    use std::task::Waker;
+
+    fn poll<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn test_a_pinned_self_ref_reads_through_its_own_pointer() {
+        let mut guarded = pin!(Guarded::new());
+        assert!(poll(guarded.as_mut()).is_pending());
+        assert_eq!(poll(guarded), Poll::Ready(Ok(4)));
+    }
+
+    #[test]
+    fn test_an_unguarded_self_ref_is_fine_if_it_stays_put() {
+        let mut unguarded = Unguarded::new();
+        assert!(poll(Pin::new(&mut unguarded)).is_pending());
+        assert_eq!(poll(Pin::new(&mut unguarded)), Poll::Ready(Ok(4)));
+    }
+
+    #[test]
+    fn test_an_unguarded_self_ref_moved_between_polls_points_where_it_was() {
+        let mut unguarded = Unguarded::new();
+        assert!(poll(Pin::new(&mut unguarded)).is_pending());
+        // Moved to the heap, as a task would be if it were spawned now.
+        let mut moved = Box::new(unguarded);
+        assert!(!moved.is_intact());
+        assert_eq!(poll(Pin::new(&mut *moved)), Poll::Ready(Err(Moved)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_the_deadline_is_polled_across_the_loop_not_made_anew() {
+        // Had it been made inside the loop, each tick would put it back, and all ten
+        // would come.
+        let ticks = manual_stream::Ticks::new(Duration::from_millis(100), 10);
+        assert_eq!(ticks_before(ticks, Duration::from_millis(350)).await, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_boxed_jobs_of_different_types_finish_in_order_of_length() {
+        assert_eq!(first_done(jobs(Duration::from_millis(100))).await, ["a sleep_until", "two ticks", "a sleep"]);
+    }
+}
@@ -0,0 +1,43 @@
+use futures::StreamExt;
+use pinning_basics::{Guarded, Unguarded, first_done, jobs, ticks_before};
+use std::pin::{Pin, pin};
+use std::task::{Context, Waker};
+use std::time::Duration;
+
+/// Polls a future once, with a waker that does nothing, for the steps that want to stop
+/// between polls and do something to it.
+fn poll_once<F: Future>(future: Pin<&mut F>) -> std::task::Poll<F::Output> {
+    future.poll(&mut Context::from_waker(Waker::noop()))
+}
+
+/// Walks through pinning, a step at a time: the state an `async` block keeps, the
+/// self-referential future moved and pinned, which of the repo's streams need pinning
+/// before `next()`, and `tokio::pin!` and `Box::pin` before `select!` and `select_all`.
+#[tokio::main]
+async fn main() {
+    let block = async {
+        let buf = [1u8; 1024];
+        let head = &buf[..4];
+        tokio::task::yield_now().await;
+        head.iter().sum::<u8>()
+    };
+    println!("[state] an async block holding a 1KiB buffer across an await is {} bytes: the buffer's in the future itself", size_of_val(&block));
+
+    let mut unguarded = Unguarded::new();
+    let _ = poll_once(Pin::new(&mut unguarded));
+    let mut moved = Box::new(unguarded);
+    println!("[move] Unguarded, polled, moved to the heap and polled again: {:?}", poll_once(Pin::new(&mut *moved)));
+    let mut guarded = Box::pin(Guarded::new());
+    let _ = poll_once(guarded.as_mut());
+    println!("[pin] Guarded, pinned in a box, polled twice: {:?}; Pin::new on a Guarded wouldn't compile", poll_once(guarded.as_mut()));
+
+    let period = Duration::from_millis(100);
+    let mut by_hand = manual_stream::Ticks::new(period, 1);
+    println!("[unpin] manual_stream's Ticks, with next() where it lies: {:?}", by_hand.next().await.map(|(tick, _)| tick));
+    let mut generated = pin!(stream_generators::ticks(period, 1));
+    println!("[unpin] stream_generators' ticks, pinned first: {:?}", generated.next().await.map(|(tick, _)| tick));
+
+    let seen = ticks_before(manual_stream::Ticks::new(period, 10), Duration::from_millis(350)).await;
+    println!("[select] {seen} of 10 ticks before a 350ms deadline, pinned with tokio::pin! to poll it on every turn");
+    println!("[select_all] three boxed futures of different types, in the order they finished: {:?}", first_done(jobs(period)).await);
+}
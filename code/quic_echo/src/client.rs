@@ -0,0 +1,64 @@
+//! The demo client: one connection, several streams at a time on it, until the server
+//! closes the connection.
+
+use quinn::{Connection, ConnectionError, Endpoint};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::{MissedTickBehavior, interval};
+
+/// The most an echo reply may be; the demo's messages are far smaller.
+const MAX_REPLY: usize = 64 * 1024;
+
+/// Sends `payload` on a new bidirectional stream and returns what comes back.
+pub async fn echo(conn: &Connection, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let (mut send, mut recv) = conn.open_bi().await?;
+    send.write_all(payload).await?;
+    // Finishing our side is what tells the server the message is complete.
+    send.finish().map_err(io::Error::other)?;
+    recv.read_to_end(MAX_REPLY).await.map_err(io::Error::other)
+}
+
+/// Connects to `addr` and, every `every`, sends `streams` messages at once, each on its
+/// own stream, printing the echoes.
+///
+/// Returns why the connection ended, which after a server shutdown is
+/// `ApplicationClosed` with [`SHUTDOWN_CODE`](crate::SHUTDOWN_CODE).
+pub async fn run_demo_client(
+    endpoint: &Endpoint,
+    addr: SocketAddr,
+    name: &str,
+    every: Duration,
+    streams: usize,
+) -> io::Result<ConnectionError> {
+    let conn = endpoint.connect(addr, crate::tls::SERVER_NAME).map_err(io::Error::other)?.await?;
+    println!("[{name}] connected to {addr}");
+    let mut ticks = interval(every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut round = 0_u64;
+    let mut in_flight = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            reason = conn.closed() => {
+                println!("[{name}] connection closed: {reason}");
+                return Ok(reason);
+            }
+            _ = ticks.tick() => {
+                round += 1;
+                for i in 1..=streams {
+                    let conn = conn.clone();
+                    let message = format!("round {round} stream {i} from {name}");
+                    in_flight.spawn(async move { (message.clone(), echo(&conn, message.as_bytes()).await) });
+                }
+            }
+            Some(Ok((message, reply))) = in_flight.join_next(), if !in_flight.is_empty() => match reply {
+                Ok(reply) if reply == message.as_bytes() => println!("[{name}] echo: {message}"),
+                Ok(reply) => eprintln!("[{name}] echo mismatch: sent {message:?}, got {:?}", String::from_utf8_lossy(&reply)),
+                // Streams cut off by the close fail here; `closed()` says why.
+                Err(e) => eprintln!("[{name}] stream failed: {e}"),
+            },
+        }
+    }
+}
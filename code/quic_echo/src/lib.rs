@@ -0,0 +1,191 @@
+//! The echo server again, over QUIC.
+//!
+//! QUIC changes the shape of the problem. One UDP socket (the `Endpoint`) carries many
+//! connections, and each connection carries many independent bidirectional streams, so
+//! there are three levels to shut down instead of one:
+//!
+//! 1. **The endpoint** stops taking new connections (`set_server_config(None)` makes it
+//!    refuse them outright, instead of leaving them queued).
+//! 2. **Each connection** stops accepting new streams and gives the streams already
+//!    open `stream_grace` to finish their echo.
+//! 3. **Then the connection is closed** with an application error code,
+//!    [`SHUTDOWN_CODE`]. Unlike a TCP FIN this is explicit: the peer's `closed()` sees
+//!    `ApplicationClosed` with our code and reason, so it can tell "server went away"
+//!    from "network broke" and decide whether to reconnect.
+//!
+//! Closing a connection only *queues* the CONNECTION_CLOSE frame. If the process exited
+//! right away the frame would never leave, and clients would sit there until their idle
+//! timeout. So the last step is `Endpoint::wait_idle`, which waits for the close to be
+//! sent.
+
+pub mod client;
+pub mod tls;
+
+use quinn::{ConnectionError, Endpoint, Incoming, RecvStream, SendStream, VarInt};
+use shutdown_util::ShutdownController;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+/// The application error code a connection is closed with when the server shuts down.
+/// QUIC leaves application codes entirely to the application; 0 conventionally means
+/// "no error", so this is the first one with a meaning of its own.
+pub const SHUTDOWN_CODE: VarInt = VarInt::from_u32(1);
+
+/// The reason sent along with [`SHUTDOWN_CODE`].
+pub const SHUTDOWN_REASON: &[u8] = b"server shutting down";
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct EchoConfig {
+    /// How long streams that are already open get to finish after shutdown, before their
+    /// connection is closed under them.
+    pub stream_grace: Duration,
+    /// How long connections get to close after shutdown. Keep it above `stream_grace`.
+    pub drain_timeout: Duration,
+}
+
+impl Default for EchoConfig {
+    fn default() -> Self {
+        Self {
+            stream_grace: Duration::from_secs(2),
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Echoes every bidirectional stream opened on `endpoint` until shutdown is signalled,
+/// then closes each connection with [`SHUTDOWN_CODE`] and waits for the closes to be
+/// sent.
+pub async fn run_server(
+    endpoint: Endpoint,
+    config: EchoConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let mut next_conn_id = 0_u64;
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[server] shutdown requested, no longer accepting");
+                break;
+            }
+            incoming = endpoint.accept() => {
+                // `None` means the endpoint itself was closed.
+                let Some(incoming) = incoming else { break };
+                next_conn_id += 1;
+                let id = next_conn_id;
+                let peer = incoming.remote_address();
+                let config = config.clone();
+                let conn_shutdown = controller.subscribe();
+                controller.spawn(async move {
+                    match serve_connection(incoming, id, peer, &config, conn_shutdown).await {
+                        Ok(streams) => println!("[server] conn={id} peer={peer} closed after {streams} stream(s)"),
+                        Err(e) => eprintln!("[server] conn={id} peer={peer} failed: {e}"),
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every connection that ever closed piling up, and leaves the drain only the
+            // ones still open.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[server] connection task join error: {e}");
+                }
+            }
+        }
+    }
+    endpoint.set_server_config(None);
+
+    println!(
+        "[server] waiting up to {:?} for {} connection(s) to close",
+        config.drain_timeout,
+        controller.active_tasks()
+    );
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[server] connection task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[server] drain deadline hit, aborted {} connection(s)", report.aborted);
+    }
+
+    // Aborted connections were dropped, not closed; this closes them too, then waits
+    // for every CONNECTION_CLOSE to go out.
+    endpoint.close(SHUTDOWN_CODE, SHUTDOWN_REASON);
+    if timeout(config.drain_timeout, endpoint.wait_idle()).await.is_err() {
+        eprintln!("[server] gave up waiting for the endpoint to go idle");
+    }
+    println!("[server] all connections closed");
+    Ok(())
+}
+
+/// Completes the handshake, then echoes each stream the client opens on its own task.
+/// Returns how many streams it served.
+async fn serve_connection(
+    incoming: Incoming,
+    id: u64,
+    peer: SocketAddr,
+    config: &EchoConfig,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> Result<u64, ConnectionError> {
+    // A shutdown signal sent during the handshake waits in `shutdown_rx`, so the loop
+    // below closes the connection properly as soon as it exists.
+    let conn = incoming.await?;
+    println!("[server] conn={id} peer={peer} connected");
+    let mut streams = JoinSet::new();
+    let mut served = 0;
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => break,
+            accepted = conn.accept_bi() => match accepted {
+                Ok((send, recv)) => {
+                    served += 1;
+                    let stream_id = send.id().index();
+                    streams.spawn(async move {
+                        match echo_stream(send, recv).await {
+                            Ok(bytes) => println!("[server] conn={id} stream={stream_id} echoed {bytes} byte(s)"),
+                            Err(e) => eprintln!("[server] conn={id} stream={stream_id} failed: {e}"),
+                        }
+                    });
+                }
+                // The client closed the connection: that's how a QUIC client says goodbye.
+                Err(ConnectionError::ApplicationClosed(close)) => {
+                    println!("[server] conn={id} client closed with code {}", close.error_code);
+                    return Ok(served);
+                }
+                Err(e) => return Err(e),
+            },
+            // Reap finished streams as we go so the set doesn't grow with every stream.
+            Some(_) = streams.join_next(), if !streams.is_empty() => {}
+        }
+    }
+
+    if !streams.is_empty() {
+        println!("[server] conn={id} giving {} open stream(s) {:?} to finish", streams.len(), config.stream_grace);
+        let finished = timeout(config.stream_grace, async {
+            while streams.join_next().await.is_some() {}
+        })
+        .await;
+        if finished.is_err() {
+            println!("[server] conn={id} {} stream(s) still open, closing anyway", streams.len());
+        }
+    }
+    conn.close(SHUTDOWN_CODE, SHUTDOWN_REASON);
+    Ok(served)
+}
+
+/// Echoes one stream until the client finishes its side, then finishes ours.
+async fn echo_stream(mut send: SendStream, mut recv: RecvStream) -> io::Result<u64> {
+    let bytes = tokio::io::copy(&mut recv, &mut send).await?;
+    send.finish().map_err(io::Error::other)?;
+    // `finish` only queues the end of the stream. Wait for the client to acknowledge
+    // it, or closing the connection right after could throw away the tail of the echo.
+    send.stopped().await.map_err(io::Error::other)?;
+    Ok(bytes)
+}
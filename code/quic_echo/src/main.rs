@@ -0,0 +1,90 @@
+use clap::Parser;
+use quic_echo::client::run_demo_client;
+use quic_echo::{EchoConfig, run_server, tls};
+use quinn::Endpoint;
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// A QUIC echo server that closes every connection with an application error code on
+/// Ctrl-C or SIGTERM.
+///
+/// The certificate is generated fresh on each run, so the built-in demo clients are the
+/// easiest way to talk to it.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// UDP address to listen on.
+    #[arg(long, default_value = "127.0.0.1:3013")]
+    bind: SocketAddr,
+    /// How many demo clients to start alongside the server.
+    #[arg(long, default_value_t = 1)]
+    demo_clients: usize,
+    /// How many streams each demo client opens at once.
+    #[arg(long, default_value_t = 3)]
+    streams: usize,
+    /// Seconds between the demo clients' rounds of messages.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    demo_interval: Duration,
+    /// Seconds open streams get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    stream_grace: Duration,
+    /// Seconds connections get to close after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = EchoConfig {
+        stream_grace: cli.stream_grace,
+        drain_timeout: cli.drain_timeout,
+    };
+
+    let (server_config, cert) = tls::self_signed_server()?;
+    let endpoint = Endpoint::server(server_config, cli.bind)?;
+    let addr = endpoint.local_addr()?;
+    println!("[main] listening on quic://{addr}");
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let server = tokio::spawn(run_server(endpoint, config, controller, shutdown_rx));
+
+    // One client endpoint (one UDP socket) can carry any number of connections.
+    let mut client = Endpoint::client("127.0.0.1:0".parse().expect("valid address"))?;
+    client.set_default_client_config(tls::client_trusting(cert)?);
+    let demos: Vec<_> = (1..=cli.demo_clients)
+        .map(|i| {
+            let client = client.clone();
+            let (every, streams) = (cli.demo_interval, cli.streams);
+            tokio::spawn(async move {
+                let name = format!("demo{i}");
+                if let Err(e) = run_demo_client(&client, addr, &name, every, streams).await {
+                    eprintln!("[{name}] failed: {e}");
+                }
+            })
+        })
+        .collect();
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match server.await {
+        Ok(Ok(())) => println!("[main] server exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
+        Err(e) => eprintln!("[main] server task failed: {e}"),
+    }
+    for demo in demos {
+        let _ = demo.await;
+    }
+    client.wait_idle().await;
+    Ok(())
+}
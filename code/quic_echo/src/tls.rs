@@ -0,0 +1,28 @@
+//! QUIC always runs over TLS 1.3, so even a local demo needs a certificate. This makes a
+//! throwaway self-signed one and the matching server and client configs.
+
+use quinn::{ClientConfig, ServerConfig};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use std::io;
+use std::sync::Arc;
+
+/// The name the certificate is issued for; clients must connect using it.
+pub const SERVER_NAME: &str = "localhost";
+
+/// Creates a server config with a fresh self-signed certificate for [`SERVER_NAME`].
+///
+/// Returns the certificate too: it's the only thing a client can trust for it.
+pub fn self_signed_server() -> io::Result<(ServerConfig, CertificateDer<'static>)> {
+    let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()]).map_err(io::Error::other)?;
+    let cert = certified.cert.der().clone();
+    let key = PrivatePkcs8KeyDer::from(certified.signing_key.serialize_der());
+    let config = ServerConfig::with_single_cert(vec![cert.clone()], key.into()).map_err(io::Error::other)?;
+    Ok((config, cert))
+}
+
+/// Creates a client config that trusts `cert` and nothing else.
+pub fn client_trusting(cert: CertificateDer<'static>) -> io::Result<ClientConfig> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert).map_err(io::Error::other)?;
+    ClientConfig::with_root_certificates(Arc::new(roots)).map_err(io::Error::other)
+}
@@ -0,0 +1,119 @@
+use quic_echo::client::{echo, run_demo_client};
+use quic_echo::{EchoConfig, SHUTDOWN_CODE, SHUTDOWN_REASON, run_server, tls};
+use quinn::{Connection, ConnectionError, Endpoint};
+use shutdown_util::{ShutdownController, ShutdownTrigger};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+struct TestServer {
+    addr: SocketAddr,
+    trigger: ShutdownTrigger,
+    task: JoinHandle<std::io::Result<()>>,
+    client: Endpoint,
+}
+
+async fn start_server(config: EchoConfig) -> TestServer {
+    let (server_config, cert) = tls::self_signed_server().unwrap();
+    let endpoint = Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = endpoint.local_addr().unwrap();
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let task = tokio::spawn(run_server(endpoint, config, controller, shutdown_rx));
+
+    let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    client.set_default_client_config(tls::client_trusting(cert).unwrap());
+    TestServer { addr, trigger, task, client }
+}
+
+async fn connect(server: &TestServer) -> Connection {
+    server.client.connect(server.addr, tls::SERVER_NAME).unwrap().await.unwrap()
+}
+
+fn assert_shutdown_close(reason: ConnectionError) {
+    match reason {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, SHUTDOWN_CODE);
+            assert_eq!(&close.reason[..], SHUTDOWN_REASON);
+        }
+        other => panic!("expected the shutdown close, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_echoes_many_streams_on_one_connection() {
+    let server = start_server(EchoConfig::default()).await;
+    let conn = connect(&server).await;
+
+    let mut streams = tokio::task::JoinSet::new();
+    for i in 0..10 {
+        let conn = conn.clone();
+        streams.spawn(async move {
+            let message = format!("stream {i} ").repeat(100);
+            let reply = echo(&conn, message.as_bytes()).await.unwrap();
+            assert_eq!(reply, message.as_bytes());
+        });
+    }
+    while let Some(joined) = streams.join_next().await {
+        joined.unwrap();
+    }
+
+    server.trigger.trigger();
+    assert_shutdown_close(timeout(Duration::from_secs(2), conn.closed()).await.unwrap());
+    timeout(Duration::from_secs(2), server.task).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_open_stream_finishes_before_the_close() {
+    let server = start_server(EchoConfig::default()).await;
+    let conn = connect(&server).await;
+
+    let (mut send, mut recv) = conn.open_bi().await.unwrap();
+    send.write_all(b"first half, ").await.unwrap();
+    // Give the server a moment to accept the stream before it's told to stop.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    server.trigger.trigger();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    send.write_all(b"second half").await.unwrap();
+    send.finish().unwrap();
+    let reply = timeout(Duration::from_secs(2), recv.read_to_end(1024)).await.unwrap().unwrap();
+    assert_eq!(reply, b"first half, second half");
+
+    assert_shutdown_close(timeout(Duration::from_secs(2), conn.closed()).await.unwrap());
+    timeout(Duration::from_secs(2), server.task).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_stuck_stream_is_cut_off_after_stream_grace() {
+    let config = EchoConfig {
+        stream_grace: Duration::from_millis(100),
+        ..EchoConfig::default()
+    };
+    let server = start_server(config).await;
+    let conn = connect(&server).await;
+
+    // Never finished, so the server's echo of it never ends on its own.
+    let (mut send, _recv) = conn.open_bi().await.unwrap();
+    send.write_all(b"never finished").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    server.trigger.trigger();
+    assert_shutdown_close(timeout(Duration::from_secs(2), conn.closed()).await.unwrap());
+    timeout(Duration::from_secs(2), server.task).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_demo_client_reports_the_shutdown_code() {
+    let server = start_server(EchoConfig::default()).await;
+    let (client, addr) = (server.client.clone(), server.addr);
+    let demo = tokio::spawn(async move { run_demo_client(&client, addr, "demo", Duration::from_millis(20), 3).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    server.trigger.trigger();
+    let reason = timeout(Duration::from_secs(2), demo).await.unwrap().unwrap().unwrap();
+    assert_shutdown_close(reason);
+    server.task.await.unwrap().unwrap();
+}
@@ -0,0 +1,164 @@
+//! Async recursion: walking a directory tree with `tokio::fs`, each directory's
+//! subdirectories walked at once, and as many directories read at a time as a
+//! [`Limit`] allows.
+//!
+//! The obvious way to write it doesn't compile:
+//!
+//! ```compile_fail,E0733
+//! use recursive_walk::Tally;
+//! use std::io;
+//! use std::path::PathBuf;
+//!
+//! async fn walk(dir: PathBuf) -> io::Result<Tally> {
+//!     let mut tally = Tally { dirs: 1, ..Tally::default() };
+//!     let mut entries = tokio::fs::read_dir(&dir).await?;
+//!     while let Some(entry) = entries.next_entry().await? {
+//!         if entry.file_type().await?.is_dir() {
+//!             tally.add(walk(entry.path()).await?);
+//!         }
+//!     }
+//!     Ok(tally)
+//! }
+//! ```
+//!
+//! "recursion in an async fn requires boxing": a recursive `async fn` call must
+//! introduce indirection such as `Box::pin` to avoid an infinitely sized future.
+//!
+//! An `async fn`'s future holds the futures it's awaiting, so one that awaits itself
+//! would have to hold itself, and be infinitely big. The way out is to box the inner
+//! one: the outer future holds a pointer to it, which is the same size however deep the
+//! recursion goes. There are three ways to write that, all here, and all doing the same
+//! walk:
+//!
+//! - [`walk_boxed`]: a plain `fn` returning `BoxFuture`, an `async move` block boxed
+//!   with `.boxed()`. What there was before either of the others, and still the clearest
+//!   about what's going on.
+//! - [`walk_attribute`]: an `async fn` with `#[async_recursion]`, which rewrites it
+//!   into the first.
+//! - [`walk_box_pin`]: an `async fn` that boxes just the recursive call, with
+//!   `Box::pin`, which the compiler has accepted since Rust 1.77.
+//!
+//! Each allocates once per directory, which next to reading the directory is nothing.
+//!
+//! The bound is a semaphore, and a permit is held only while a directory is being read,
+//! never while its subdirectories are walked: one held across the recursion would be
+//! held by every directory from the root down, and a tree deeper than there are permits
+//! would deadlock, each level waiting on the next for a permit the levels above it have.
+
+use async_recursion::async_recursion;
+use futures::FutureExt;
+use futures::future::{BoxFuture, try_join_all};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Semaphore;
+
+/// What a walk found: files, directories including the one it started from, and the
+/// files' bytes. Symlinks are neither, and aren't followed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+}
+
+impl Tally {
+    pub fn add(&mut self, other: Tally) {
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.bytes += other.bytes;
+    }
+}
+
+/// How many directories may be read at once, shared by every step of a walk, and the
+/// most that have been.
+#[derive(Debug, Clone)]
+pub struct Limit {
+    permits: Arc<Semaphore>,
+    reading: Arc<AtomicUsize>,
+    max_reading: Arc<AtomicUsize>,
+}
+
+impl Limit {
+    pub fn new(at_once: usize) -> Self {
+        assert!(at_once > 0, "a walk has to be able to read something");
+        Self { permits: Arc::new(Semaphore::new(at_once)), reading: Arc::default(), max_reading: Arc::default() }
+    }
+
+    /// The most directories that have been read at once, so far.
+    pub fn max_reading(&self) -> usize {
+        self.max_reading.load(Ordering::Relaxed)
+    }
+
+    /// Reads `dir`, once there's a permit for it, and returns the tally of it and its
+    /// files, and its subdirectories to walk next.
+    async fn read(&self, dir: &Path) -> io::Result<(Tally, Vec<PathBuf>)> {
+        let _permit = self.permits.acquire().await.expect("the semaphore is never closed");
+        let now = self.reading.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_reading.fetch_max(now, Ordering::Relaxed);
+        let read = list(dir).await;
+        self.reading.fetch_sub(1, Ordering::Relaxed);
+        read
+    }
+}
+
+async fn list(dir: &Path) -> io::Result<(Tally, Vec<PathBuf>)> {
+    let mut tally = Tally { dirs: 1, ..Tally::default() };
+    let mut subdirs = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        // The entry's own type: a symlink is a symlink, whatever it points to.
+        let kind = entry.file_type().await?;
+        if kind.is_dir() {
+            subdirs.push(entry.path());
+        } else if kind.is_file() {
+            tally.files += 1;
+            tally.bytes += entry.metadata().await?.len();
+        }
+    }
+    Ok((tally, subdirs))
+}
+
+/// Walks `dir` and everything under it, with a `BoxFuture` made by hand.
+pub fn walk_boxed(dir: PathBuf, limit: Limit) -> BoxFuture<'static, io::Result<Tally>> {
+    async move {
+        let (mut tally, subdirs) = limit.read(&dir).await?;
+        for sub in try_join_all(subdirs.into_iter().map(|sub| walk_boxed(sub, limit.clone()))).await? {
+            tally.add(sub);
+        }
+        Ok(tally)
+    }
+    .boxed()
+}
+
+/// Walks `dir` and everything under it, boxed by `#[async_recursion]`.
+#[async_recursion]
+pub async fn walk_attribute(dir: PathBuf, limit: Limit) -> io::Result<Tally> {
+    let (mut tally, subdirs) = limit.read(&dir).await?;
+    for sub in try_join_all(subdirs.into_iter().map(|sub| walk_attribute(sub, limit.clone()))).await? {
+        tally.add(sub);
+    }
+    Ok(tally)
+}
+
+/// Walks `dir` and everything under it, boxing only the recursive calls.
+pub async fn walk_box_pin(dir: PathBuf, limit: Limit) -> io::Result<Tally> {
+    let (mut tally, subdirs) = limit.read(&dir).await?;
+    for sub in try_join_all(subdirs.into_iter().map(|sub| Box::pin(walk_box_pin(sub, limit.clone())))).await? {
+        tally.add(sub);
+    }
+    Ok(tally)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tallies_add_up() {
+        let mut tally = Tally { files: 1, dirs: 1, bytes: 10 };
+        tally.add(Tally { files: 2, dirs: 3, bytes: 5 });
+        assert_eq!(tally, Tally { files: 3, dirs: 4, bytes: 15 });
+    }
+}
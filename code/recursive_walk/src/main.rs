@@ -0,0 +1,78 @@
+use clap::{Parser, ValueEnum};
+use recursive_walk::{Limit, Tally, walk_attribute, walk_box_pin, walk_boxed};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+
+/// Walks a directory tree with `tokio::fs`, counting its files, directories and bytes,
+/// with each of the three ways of writing async recursion in turn or with just one.
+///
+/// Every directory's subdirectories are walked at once, but only `--at-once` directories
+/// are read at a time; the line for each walk says how many were at most.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// The directory to walk.
+    #[arg(default_value = ".")]
+    dir: PathBuf,
+    /// Which way to write the recursion; each in turn by default.
+    #[arg(long, value_enum)]
+    how: Option<How>,
+    /// Directories read at once.
+    #[arg(long, value_name = "N", default_value_t = 8)]
+    at_once: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum How {
+    /// A plain fn returning BoxFuture.
+    Boxed,
+    /// An async fn with #[async_recursion].
+    Attribute,
+    /// An async fn that Box::pins its recursive calls.
+    BoxPin,
+}
+
+impl How {
+    const ALL: [How; 3] = [How::Boxed, How::Attribute, How::BoxPin];
+
+    async fn walk(self, dir: PathBuf, limit: Limit) -> std::io::Result<Tally> {
+        match self {
+            How::Boxed => walk_boxed(dir, limit).await,
+            How::Attribute => walk_attribute(dir, limit).await,
+            How::BoxPin => walk_box_pin(dir, limit).await,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if cli.at_once == 0 {
+        eprintln!("[main] --at-once has to be at least 1");
+        return ExitCode::FAILURE;
+    }
+    let hows = cli.how.map_or(How::ALL.to_vec(), |how| vec![how]);
+    for how in hows {
+        let limit = Limit::new(cli.at_once);
+        let start = Instant::now();
+        // Spawned, as a walk in a server would be, which wants its future `Send`.
+        let walked = tokio::spawn(how.walk(cli.dir.clone(), limit.clone())).await.expect("the walk doesn't panic");
+        match walked {
+            Ok(tally) => println!(
+                "[main] {:?}: {} file(s), {} dir(s), {} byte(s) under {} in {:.1?}, at most {} dir(s) read at once",
+                how,
+                tally.files,
+                tally.dirs,
+                tally.bytes,
+                cli.dir.display(),
+                start.elapsed(),
+                limit.max_reading()
+            ),
+            Err(e) => {
+                eprintln!("[main] can't walk {}: {e}", cli.dir.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
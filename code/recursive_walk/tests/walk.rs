@@ -0,0 +1,66 @@
+//! The three walks over the same tree, made in the temp directory for each test.
+
+use recursive_walk::{Limit, Tally, walk_attribute, walk_box_pin, walk_boxed};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A fresh, empty directory for `name`.
+fn scratch(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("recursive-walk-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    root
+}
+
+/// `fan` subdirectories under `dir`, and under each of them, `depth` levels down, with two
+/// files in every directory: one of 10 bytes and one of 100.
+fn tree(dir: &Path, fan: usize, depth: usize) -> Tally {
+    std::fs::write(dir.join("small"), [0; 10]).unwrap();
+    std::fs::write(dir.join("big"), [0; 100]).unwrap();
+    let mut tally = Tally { files: 2, dirs: 1, bytes: 110 };
+    if depth > 0 {
+        for i in 0..fan {
+            let sub = dir.join(format!("sub{i}"));
+            std::fs::create_dir(&sub).unwrap();
+            tally.add(tree(&sub, fan, depth - 1));
+        }
+    }
+    tally
+}
+
+#[tokio::test]
+async fn test_every_walk_tallies_the_whole_tree() {
+    let root = scratch("whole");
+    let expected = tree(&root, 3, 3);
+    assert_eq!(expected.dirs, 1 + 3 + 9 + 27);
+    assert_eq!(walk_boxed(root.clone(), Limit::new(4)).await.unwrap(), expected);
+    assert_eq!(walk_attribute(root.clone(), Limit::new(4)).await.unwrap(), expected);
+    assert_eq!(walk_box_pin(root.clone(), Limit::new(4)).await.unwrap(), expected);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_no_more_directories_are_read_at_once_than_the_limit() {
+    let root = scratch("limit");
+    tree(&root, 4, 3);
+    let limit = Limit::new(2);
+    tokio::spawn(walk_boxed(root.clone(), limit.clone())).await.unwrap().unwrap();
+    assert!((1..=2).contains(&limit.max_reading()), "{}", limit.max_reading());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_a_tree_deeper_than_the_permits_doesnt_deadlock() {
+    let root = scratch("deep");
+    let expected = tree(&root, 1, 30);
+    let walked = tokio::time::timeout(Duration::from_secs(10), walk_box_pin(root.clone(), Limit::new(1))).await.expect("it finishes");
+    assert_eq!(walked.unwrap(), expected);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_a_missing_directory_is_an_error() {
+    let root = scratch("missing").join("not-there");
+    let error = walk_attribute(root, Limit::new(1)).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+}
@@ -0,0 +1,270 @@
+//! Samples a tokio runtime's `RuntimeMetrics` from a thread of its own.
+//!
+//! A thread rather than a task, because a sampler running on the runtime it watches
+//! stops sampling exactly when the numbers get interesting: block every worker and its
+//! task is never polled, so the last report is from before the trouble. A plain thread
+//! holding a `Handle` reads the same counters whatever the workers are doing.
+//!
+//! Most of what tokio counts says what the workers did, not what's waiting for them,
+//! and only once they say so: a worker publishes its busy time and park count when it
+//! parks or between batches of tasks, so one stuck in a long poll looks idle until it
+//! comes back. So every tick the sampler also spawns a probe, an empty task, and
+//! reports how long the probes wait to be polled: a few microseconds on a healthy
+//! runtime, as long as the blocking on a blocked one, with the probes piling up in the
+//! global queue meanwhile.
+//!
+//! The blocking pool and the workers' own queues are only counted in a build with
+//! `--cfg tokio_unstable`; in any other those fields don't exist.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, RuntimeMetrics};
+
+/// What the runtime looked like at one tick, and what it did since the one before.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// How long the activity below covers: since the previous sample, or for the one
+    /// [`Sampler::stop`] returns, since the sampler started.
+    pub interval: Duration,
+    pub workers: usize,
+    pub alive_tasks: usize,
+    /// Tasks waiting in the runtime's shared queue: spawned from outside it (as the
+    /// probes are), or overflowed from a worker's own queue.
+    pub global_queue_depth: usize,
+    /// The share of `interval` each worker spent running tasks, from 0.0 to 1.0.
+    pub busy: Vec<f64>,
+    /// How many times each worker ran out of work and went to sleep during `interval`.
+    pub parks: Vec<u64>,
+    pub probe: Probe,
+    /// Tasks waiting in each worker's own queue.
+    #[cfg(tokio_unstable)]
+    pub local_queue_depths: Vec<usize>,
+    /// Threads in the blocking pool, busy or not.
+    #[cfg(tokio_unstable)]
+    pub blocking_threads: usize,
+    #[cfg(tokio_unstable)]
+    pub idle_blocking_threads: usize,
+    /// `spawn_blocking` calls waiting for a thread.
+    #[cfg(tokio_unstable)]
+    pub blocking_queue_depth: usize,
+}
+
+/// How long the probes had to wait.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Probe {
+    /// None has been spawned yet: this is the first tick.
+    NotYet,
+    /// Every probe has been polled; the latest waited this long.
+    Ran(Duration),
+    /// The oldest probe still hasn't been polled, and was spawned this long ago.
+    Waiting(Duration),
+}
+
+/// The counters that only mean something as a difference between two readings.
+struct Counters {
+    at: Instant,
+    busy: Vec<Duration>,
+    parks: Vec<u64>,
+}
+
+impl Counters {
+    fn read(metrics: &RuntimeMetrics) -> Self {
+        let workers = 0..metrics.num_workers();
+        Self {
+            at: Instant::now(),
+            busy: workers.clone().map(|w| metrics.worker_total_busy_duration(w)).collect(),
+            parks: workers.map(|w| metrics.worker_park_count(w)).collect(),
+        }
+    }
+}
+
+impl Sample {
+    fn take(metrics: &RuntimeMetrics, since: &Counters, now: &Counters, probe: Probe) -> Self {
+        let interval = now.at - since.at;
+        let busy = now
+            .busy
+            .iter()
+            .zip(&since.busy)
+            .map(|(now, then)| ((*now - *then).as_secs_f64() / interval.as_secs_f64().max(f64::EPSILON)).min(1.0))
+            .collect();
+        Self {
+            interval,
+            workers: metrics.num_workers(),
+            alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            busy,
+            parks: now.parks.iter().zip(&since.parks).map(|(now, then)| now - then).collect(),
+            probe,
+            #[cfg(tokio_unstable)]
+            local_queue_depths: (0..metrics.num_workers()).map(|w| metrics.worker_local_queue_depth(w)).collect(),
+            #[cfg(tokio_unstable)]
+            blocking_threads: metrics.num_blocking_threads(),
+            #[cfg(tokio_unstable)]
+            idle_blocking_threads: metrics.num_idle_blocking_threads(),
+            #[cfg(tokio_unstable)]
+            blocking_queue_depth: metrics.blocking_queue_depth(),
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let busy: Vec<String> = self.busy.iter().map(|busy| format!("{:.0}%", busy * 100.0)).collect();
+        write!(
+            f,
+            "workers={} tasks={} global_queue={} busy=[{}] parks={:?} {}",
+            self.workers,
+            self.alive_tasks,
+            self.global_queue_depth,
+            busy.join(", "),
+            self.parks,
+            self.probe
+        )?;
+        #[cfg(tokio_unstable)]
+        write!(
+            f,
+            " local_queues={:?} blocking_threads={} (idle {}) blocking_queue={}",
+            self.local_queue_depths, self.blocking_threads, self.idle_blocking_threads, self.blocking_queue_depth
+        )?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::NotYet => write!(f, "probe=none yet"),
+            Probe::Ran(waited) => write!(f, "probe=waited {waited:.1?}"),
+            Probe::Waiting(waiting) => write!(f, "probe=still waiting after {waiting:.1?}"),
+        }
+    }
+}
+
+/// The probes spawned so far that may not have been polled yet, oldest first.
+#[derive(Default)]
+struct Probes {
+    pending: VecDeque<(Instant, Arc<OnceLock<Duration>>)>,
+    last_wait: Option<Duration>,
+}
+
+impl Probes {
+    fn spawn(&mut self, handle: &Handle) {
+        let spawned = Instant::now();
+        let waited = Arc::new(OnceLock::new());
+        let slot = waited.clone();
+        // Into the global queue, since we're not on one of the runtime's threads.
+        handle.spawn(async move {
+            let _ = slot.set(spawned.elapsed());
+        });
+        self.pending.push_back((spawned, waited));
+    }
+
+    fn check(&mut self) -> Probe {
+        while let Some((_, waited)) = self.pending.front() {
+            let Some(&waited) = waited.get() else {
+                break;
+            };
+            self.last_wait = Some(waited);
+            self.pending.pop_front();
+        }
+        match (self.pending.front(), self.last_wait) {
+            (Some((spawned, _)), _) => Probe::Waiting(spawned.elapsed()),
+            (None, Some(waited)) => Probe::Ran(waited),
+            (None, None) => Probe::NotYet,
+        }
+    }
+}
+
+/// A running sampler. Dropping it stops the thread too, without the last sample.
+pub struct Sampler {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<Sample>,
+}
+
+impl Sampler {
+    /// Samples the runtime behind `handle` every `period`, handing each sample to
+    /// `report` on the sampler's thread.
+    pub fn spawn<F>(handle: Handle, period: Duration, mut report: F) -> io::Result<Self>
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::Builder::new().name("runtime-sampler".to_string()).spawn(move || {
+            let metrics = handle.metrics();
+            let first = Counters::read(&metrics);
+            let mut previous = Counters::read(&metrics);
+            let mut probes = Probes::default();
+            // Sleeping on the channel is what lets `stop` cut a tick short.
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(period) {
+                let now = Counters::read(&metrics);
+                report(&Sample::take(&metrics, &previous, &now, probes.check()));
+                probes.spawn(&handle);
+                previous = now;
+            }
+            Sample::take(&metrics, &first, &Counters::read(&metrics), probes.check())
+        })?;
+        Ok(Self { stop, thread })
+    }
+
+    /// Stops sampling and returns one last sample, whose activity covers the whole time
+    /// since [`spawn`](Self::spawn). Blocks until the sampler's thread has finished,
+    /// which is at most one `report` away: call it from outside the runtime, or from
+    /// `spawn_blocking`.
+    pub fn stop(self) -> Sample {
+        drop(self.stop);
+        self.thread.join().expect("the sampler thread panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_probes_wait_while_the_only_thread_is_blocked() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let collected = samples.clone();
+        let sampler = Sampler::spawn(runtime.handle().clone(), Duration::from_millis(20), move |sample| {
+            collected.lock().unwrap().push(sample.clone());
+        })
+        .unwrap();
+
+        runtime.block_on(async { thread::sleep(Duration::from_millis(200)) });
+        // Give the probes a chance to run, and the sampler to notice. Stopped while the
+        // runtime's still running: the sleep ends on a multiple of the period, as a probe
+        // is spawned, and once `block_on` has returned nothing would poll that probe.
+        let last = runtime.block_on(async {
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            tokio::task::spawn_blocking(move || sampler.stop()).await.unwrap()
+        });
+
+        let samples = samples.lock().unwrap();
+        let blocked = samples.iter().find(|sample| matches!(sample.probe, Probe::Waiting(_))).expect("a probe waited");
+        assert!(blocked.global_queue_depth >= 1, "{blocked}");
+        assert!(matches!(last.probe, Probe::Ran(_)), "{last}");
+        assert!(last.interval >= Duration::from_millis(260), "{last}");
+    }
+
+    #[test]
+    fn test_busy_share_of_a_spinning_worker() {
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(2).enable_all().build().unwrap();
+        let sampler = Sampler::spawn(runtime.handle().clone(), Duration::from_secs(60), |_| {}).unwrap();
+        runtime.block_on(async {
+            tokio::spawn(async { thread::sleep(Duration::from_millis(100)) }).await.unwrap();
+        });
+        // The worker publishes its busy time when it parks, just after.
+        thread::sleep(Duration::from_millis(50));
+        let sample = sampler.stop();
+
+        assert_eq!((sample.workers, sample.busy.len(), sample.parks.len()), (2, 2, 2));
+        let busiest = sample.busy.iter().copied().fold(0.0, f64::max);
+        assert!(busiest > 0.5, "{sample}");
+    }
+}
@@ -0,0 +1,212 @@
+//! A second runtime, on threads of its own, for the work that would block the first.
+//!
+//! Some of a server's work blocks however it's written: a synchronous client library
+//! for a database, a big compression, a call into C. `spawn_blocking` suits a little of
+//! it, now and then. A whole subsystem of it is better off on a runtime of its own, whose
+//! workers it may block as much as it likes, with the server's runtime handing it jobs
+//! over a channel and awaiting each answer on a `oneshot`. The server's workers only
+//! ever wait on the channel, so they're free to answer everything else straight away,
+//! however much slow work there is; the slow work gets only as many threads as it was
+//! given, and the channel's bound pushes back on the connections sending it once
+//! they're all busy, rather than letting a queue grow without end.
+//!
+//! The server here echoes lines, except `work ...` ones, which are slow work: they
+//! take [`Work::cost`] of blocking to answer. [`Work::Inline`] does that right there
+//! on the server's runtime, as the example not to follow; [`Work::Dedicated`] hands it
+//! to a [`SlowWork`] runtime. [`echo_latency`] times the echoes, with the `load_generator`
+//! crate, while other connections keep the slow work busy.
+
+use futures::{SinkExt, StreamExt};
+use load_generator::{LoadConfig, Protocol, Report, run_load};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::codec::{Framed, LinesCodec};
+
+/// What a line starts with to be slow work rather than an echo.
+pub const WORK: &str = "work ";
+
+/// The slow work itself: a synchronous call that takes `cost` to answer `input`, like
+/// a query through a blocking database client.
+pub fn slow_job(input: &str, cost: Duration) -> String {
+    thread::sleep(cost);
+    format!("done {}", input.len())
+}
+
+/// A job for the second runtime, and where to send its answer.
+struct Job {
+    input: String,
+    reply: oneshot::Sender<String>,
+}
+
+/// A runtime of `threads` workers, on a thread of its own, doing [`slow_job`]s sent to
+/// it from any other runtime. Its workers block on each job; that's what they're for.
+/// Dropping it stops taking jobs, lets the ones running finish, and waits for the
+/// thread.
+#[derive(Debug)]
+pub struct SlowWork {
+    jobs: Option<mpsc::Sender<Job>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl SlowWork {
+    /// Starts the runtime, taking jobs of `cost` each, with room for `queue` of them to
+    /// wait for a worker before senders have to.
+    pub fn start(threads: usize, queue: usize, cost: Duration) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(threads).thread_name("slow-work").enable_all().build()?;
+        let (jobs, mut incoming) = mpsc::channel::<Job>(queue);
+        let thread = thread::Builder::new().name("slow-work-runtime".to_string()).spawn(move || {
+            runtime.block_on(async {
+                let mut running = tokio::task::JoinSet::new();
+                while let Some(job) = incoming.recv().await {
+                    running.spawn(async move {
+                        // Nobody to tell if the connection that asked has gone.
+                        let _ = job.reply.send(slow_job(&job.input, cost));
+                    });
+                    // Reaps the finished ones as it goes, so the set doesn't grow.
+                    while running.try_join_next().is_some() {}
+                }
+                running.join_all().await;
+            });
+        })?;
+        Ok(Self { jobs: Some(jobs), thread: Some(thread) })
+    }
+
+    /// Hands `input` to the runtime, waiting for room in the queue if it's full, and
+    /// waits for the answer.
+    pub async fn submit(&self, input: String) -> io::Result<String> {
+        let (reply, answer) = oneshot::channel();
+        let jobs = self.jobs.as_ref().expect("only taken on drop");
+        jobs.send(Job { input, reply }).await.map_err(|_| stopped())?;
+        answer.await.map_err(|_| stopped())
+    }
+}
+
+impl Drop for SlowWork {
+    fn drop(&mut self) {
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            // A job that panicked has said so on stderr already.
+            let _ = thread.join();
+        }
+    }
+}
+
+fn stopped() -> io::Error {
+    io::Error::other("the slow work runtime has stopped")
+}
+
+/// Where the server does its slow work.
+#[derive(Debug, Clone)]
+pub enum Work {
+    /// On the server's own runtime, blocking a worker each time.
+    Inline { cost: Duration },
+    /// On a second runtime.
+    Dedicated { slow: Arc<SlowWork>, cost: Duration },
+}
+
+impl Work {
+    /// How long a job blocks for, wherever it is.
+    pub fn cost(&self) -> Duration {
+        match self {
+            Self::Inline { cost } | Self::Dedicated { cost, .. } => *cost,
+        }
+    }
+
+    async fn answer(&self, input: &str) -> io::Result<String> {
+        match self {
+            Self::Inline { cost } => Ok(slow_job(input, *cost)),
+            Self::Dedicated { slow, .. } => slow.submit(input.to_string()).await,
+        }
+    }
+}
+
+/// Accepts connections on `listener` for ever, a task each, echoing lines and doing
+/// `work` for the `work ...` ones.
+pub async fn serve(listener: TcpListener, work: Work) -> io::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let work = work.clone();
+        tokio::spawn(async move {
+            // One client's broken connection is its own business.
+            let _ = connection(socket, &work).await;
+        });
+    }
+}
+
+/// Starts a server doing `work` on a runtime of `workers` of its own, on an unused port
+/// on localhost, and returns the runtime, which stops the server when it's dropped, and
+/// where the server is.
+pub fn start_server(workers: usize, work: Work) -> io::Result<(Runtime, SocketAddr)> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(workers).thread_name("server").enable_all().build()?;
+    let listener = runtime.block_on(TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))))?;
+    let addr = listener.local_addr()?;
+    runtime.spawn(serve(listener, work));
+    Ok((runtime, addr))
+}
+
+/// Times echoes from the server at `addr` as `config` says, the address and protocol
+/// aside, while `work_clients` other connections keep asking it for slow work.
+pub async fn echo_latency(addr: SocketAddr, work_clients: usize, config: LoadConfig) -> io::Result<Report> {
+    let mut load = tokio::task::JoinSet::new();
+    for _ in 0..work_clients {
+        load.spawn(work_client(addr));
+    }
+    let report = run_load(LoadConfig { addr, protocol: Protocol::Lines, ..config }, std::future::pending()).await;
+    load.abort_all();
+    report
+}
+
+/// Connects to the server at `addr` and asks it for slow work, a job after another, for
+/// as long as it answers: load to keep the slow work busy with.
+pub async fn work_client(addr: SocketAddr) -> io::Result<()> {
+    let mut lines = Framed::new(TcpStream::connect(addr).await?, LinesCodec::new());
+    loop {
+        lines.send(format!("{WORK}a job")).await.map_err(io::Error::other)?;
+        lines.next().await.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?.map_err(io::Error::other)?;
+    }
+}
+
+async fn connection(socket: TcpStream, work: &Work) -> io::Result<()> {
+    socket.set_nodelay(true)?;
+    let mut lines = Framed::new(socket, LinesCodec::new());
+    while let Some(line) = lines.next().await {
+        let line = line.map_err(io::Error::other)?;
+        let reply = match line.strip_prefix(WORK) {
+            Some(input) => work.answer(input).await?,
+            None => line,
+        };
+        lines.send(reply).await.map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_the_second_runtime_answers_as_the_job_would() {
+        let slow = SlowWork::start(2, 4, Duration::from_millis(10)).unwrap();
+        assert_eq!(slow.submit("abc".to_string()).await.unwrap(), slow_job("abc", Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_the_jobs_get_as_many_threads_as_it_has() {
+        let slow = Arc::new(SlowWork::start(2, 8, Duration::from_millis(100)).unwrap());
+        let start = Instant::now();
+        let jobs: Vec<_> = (0..4).map(|_| slow.submit(String::new())).collect();
+        for answer in futures::future::join_all(jobs).await {
+            answer.unwrap();
+        }
+        // Two at a time: 200ms, not 100ms or 400ms.
+        let took = start.elapsed();
+        assert!((Duration::from_millis(190)..Duration::from_millis(390)).contains(&took), "{took:?}");
+    }
+}
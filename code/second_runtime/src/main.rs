@@ -0,0 +1,104 @@
+use clap::Parser;
+use load_generator::LoadConfig;
+use second_runtime::{SlowWork, Work, echo_latency, start_server};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Times an echo server's echoes three times over: with no slow work, with connections
+/// asking it for slow work that it does on its own runtime, and with the same slow work
+/// handed to a second runtime on threads of its own.
+///
+/// Each echo is timed from when it was due, at `--rate` a second, so a stall shows as
+/// every echo due during it being late rather than as one slow one. Doing the work
+/// inline, every worker of the server's spends most of its time in a job, and the
+/// echoes wait behind them; on a second runtime they're as quick as with no slow work
+/// at all. The server, the slow work and the clients each have a runtime of their own,
+/// so the clients' timing isn't held up by what they're timing.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Worker threads for the server's runtime.
+    #[arg(long, default_value_t = 2)]
+    workers: usize,
+    /// Worker threads for the second runtime, each doing a job at a time.
+    #[arg(long, value_name = "THREADS", default_value_t = 2)]
+    slow_threads: usize,
+    /// Milliseconds each job blocks for.
+    #[arg(long, value_name = "MS", default_value_t = 50)]
+    job_ms: u64,
+    /// Connections asking for slow work, each a job after another.
+    #[arg(long, default_value_t = 4)]
+    work_clients: usize,
+    /// Echoes a second to time, over four connections. A rate the server can't keep up
+    /// with leaves nothing to time, as every echo that comes back was due before the
+    /// window did.
+    #[arg(long, value_name = "PER_SEC", default_value_t = 40.0)]
+    rate: f64,
+    /// Seconds to time each for, after a half-second warm-up.
+    #[arg(long, value_name = "SECS", default_value = "3", value_parser = parse_secs)]
+    duration: Duration,
+}
+
+fn parse_secs(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let cost = Duration::from_millis(cli.job_ms);
+    let slow = match SlowWork::start(cli.slow_threads, cli.slow_threads, cost) {
+        Ok(slow) => Arc::new(slow),
+        Err(e) => {
+            eprintln!("[main] can't start the second runtime: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let clients = match tokio::runtime::Runtime::new() {
+        Ok(clients) => clients,
+        Err(e) => {
+            eprintln!("[main] can't start the clients' runtime: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let config = LoadConfig { connections: 4, rate: Some(cli.rate), duration: cli.duration, warmup: Duration::from_millis(500), ..LoadConfig::default() };
+    let scenarios = [
+        ("no slow work", 0, Work::Dedicated { slow: slow.clone(), cost }),
+        ("slow work inline", cli.work_clients, Work::Inline { cost }),
+        ("slow work on a second runtime", cli.work_clients, Work::Dedicated { slow, cost }),
+    ];
+
+    let mut rows = Vec::new();
+    for (name, work_clients, work) in scenarios {
+        println!("[main] {name}: {work_clients} connection(s) asking for {cost:?} jobs, {} echoes a second timed", cli.rate);
+        let (server, addr) = match start_server(cli.workers, work) {
+            Ok(server) => server,
+            Err(e) => {
+                eprintln!("[main] can't start the server: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let report = match clients.block_on(echo_latency(addr, work_clients, config.clone())) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("[main] {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        println!("{report}");
+        rows.push((name, report));
+        // Waits for any job a worker is in the middle of.
+        drop(server);
+    }
+
+    println!("\n{:30} {:>10} {:>10} {:>10}", "echo latency", "p50", "p99", "max");
+    for (name, report) in rows {
+        if report.latency.is_empty() {
+            println!("{name:30} fell behind the rate and never caught up");
+            continue;
+        }
+        let max = Duration::from_micros(report.latency.max());
+        println!("{name:30} {:>10.1?} {:>10.1?} {max:>10.1?}", report.percentile(0.5), report.percentile(0.99));
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,37 @@
+//! The server's echoes under slow work, done inline and on a second runtime, timed on
+//! the real clock with the load generator.
+
+use load_generator::LoadConfig;
+use second_runtime::{SlowWork, Work, echo_latency, start_server};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Each job's blocking: long next to an echo, short enough for a test.
+const COST: Duration = Duration::from_millis(50);
+
+/// The 99th percentile of the echoes' latency, with `work` and four connections asking
+/// for it, on a server with two workers. Few enough echoes that a server doing the work
+/// inline keeps up with them, if only just.
+fn p99(work: Work) -> Duration {
+    let (server, addr) = start_server(2, work).unwrap();
+    let config = LoadConfig { connections: 2, rate: Some(20.0), duration: Duration::from_millis(1000), warmup: Duration::from_millis(200), ..LoadConfig::default() };
+    let report = tokio::runtime::Runtime::new().unwrap().block_on(echo_latency(addr, 4, config)).unwrap();
+    drop(server);
+    assert!(report.errors.is_empty(), "{report}");
+    assert!(report.messages > 0, "{report}");
+    report.percentile(0.99)
+}
+
+#[test]
+fn test_slow_work_inline_holds_up_the_echoes() {
+    let p99 = p99(Work::Inline { cost: COST });
+    // Four connections' jobs on two workers: most echoes wait for one to finish.
+    assert!(p99 >= COST / 2, "{p99:?}");
+}
+
+#[test]
+fn test_slow_work_on_a_second_runtime_leaves_the_echoes_alone() {
+    let slow = Arc::new(SlowWork::start(2, 2, COST).unwrap());
+    let p99 = p99(Work::Dedicated { slow, cost: COST });
+    assert!(p99 < COST / 5, "{p99:?}");
+}
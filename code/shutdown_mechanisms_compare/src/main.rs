@@ -0,0 +1,288 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, broadcast, watch};
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
+
+/// How many workers each mechanism has to stop.
+const WORKERS: usize = 500;
+
+/// How long a probe waits before deciding a signal isn't coming.
+const PATIENCE: Duration = Duration::from_millis(50);
+
+/// One line of the matrix. The behaviour columns are what the probes saw, not what the
+/// docs say, so a tokio upgrade that changed one would show up here.
+struct Row {
+    mechanism: &'static str,
+    stop_all: Duration,
+    late_subscriber: String,
+    falling_behind: String,
+    triggered_twice: String,
+}
+
+#[tokio::main]
+async fn main() {
+    println!("=== Stopping {WORKERS} workers, and what each mechanism does at the edges ===\n");
+    let rows = vec![broadcast_row().await, token_row().await, watch_row().await, notify_row().await];
+
+    println!(
+        "{:<18} {:>12}  {:<26} {:<24} {:<16}",
+        "mechanism", "stop all", "late subscriber", "falling behind", "triggered twice"
+    );
+    for row in &rows {
+        println!(
+            "{:<18} {:>12}  {:<26} {:<24} {:<16}",
+            row.mechanism,
+            format!("{:.2?}", row.stop_all),
+            row.late_subscriber,
+            row.falling_behind,
+            row.triggered_twice
+        );
+    }
+
+    println!();
+    println!("stop all:        from the trigger until the last worker has exited");
+    println!("late subscriber: subscribes only after the trigger; does it still find out?");
+    println!("falling behind:  triggered three times before anyone looked");
+    println!("triggered twice: how many times a waiter that was already waiting wakes up");
+}
+
+/// One step of the workload: a little async work that the shutdown has to interrupt.
+async fn work() {
+    sleep(Duration::from_millis(1)).await;
+}
+
+/// Lets the workers get going, pulls `trigger`, and times how long they take to exit.
+async fn time_stop(workers: &mut JoinSet<()>, trigger: impl FnOnce()) -> Duration {
+    sleep(Duration::from_millis(20)).await;
+    let started = Instant::now();
+    trigger();
+    while workers.join_next().await.is_some() {}
+    started.elapsed()
+}
+
+/// Whether `wake` completes within [`PATIENCE`].
+async fn woke(wake: impl Future) -> bool {
+    timeout(PATIENCE, wake).await.is_ok()
+}
+
+async fn broadcast_row() -> Row {
+    let (tx, _) = broadcast::channel::<()>(16);
+    let mut workers = JoinSet::new();
+    for _ in 0..WORKERS {
+        let mut rx = tx.subscribe();
+        workers.spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = rx.recv() => break,
+                    () = work() => {}
+                }
+            }
+        });
+    }
+    let stop_all = time_stop(&mut workers, || {
+        let _ = tx.send(());
+    })
+    .await;
+
+    // A receiver only gets what is sent after it subscribed.
+    let (tx, _early) = broadcast::channel::<()>(16);
+    let _ = tx.send(());
+    let mut late = tx.subscribe();
+    let late_sees = woke(late.recv()).await;
+
+    // Capacity one, three sends: the oldest two are overwritten.
+    let (tx, mut rx) = broadcast::channel::<u32>(1);
+    for signal in 1..=3 {
+        let _ = tx.send(signal);
+    }
+    let falling_behind = match rx.recv().await {
+        Err(broadcast::error::RecvError::Lagged(missed)) => match rx.recv().await {
+            Ok(newest) => format!("Lagged({missed}), then #{newest}"),
+            Err(e) => format!("Lagged({missed}), then {e}"),
+        },
+        other => format!("{other:?}"),
+    };
+
+    // Every send is its own message.
+    let (tx, mut rx) = broadcast::channel::<()>(16);
+    let _ = tx.send(());
+    let _ = tx.send(());
+    let mut wakes = 0;
+    for _ in 0..3 {
+        wakes += usize::from(woke(rx.recv()).await);
+    }
+
+    Row {
+        mechanism: "broadcast",
+        stop_all,
+        late_subscriber: if late_sees { "sees it" } else { "misses it" }.to_string(),
+        falling_behind,
+        triggered_twice: format!("{wakes} messages"),
+    }
+}
+
+async fn token_row() -> Row {
+    let root = CancellationToken::new();
+    let mut workers = JoinSet::new();
+    for _ in 0..WORKERS {
+        let token = root.child_token();
+        workers.spawn(async move {
+            loop {
+                tokio::select! {
+                    () = token.cancelled() => break,
+                    () = work() => {}
+                }
+            }
+        });
+    }
+    let stop_all = time_stop(&mut workers, || root.cancel()).await;
+
+    // A child of a cancelled token is born cancelled.
+    let root = CancellationToken::new();
+    root.cancel();
+    let late_sees = root.child_token().is_cancelled();
+
+    // Nothing is queued: cancelling is setting a flag, however many times it happens.
+    let token = CancellationToken::new();
+    for _ in 0..3 {
+        token.cancel();
+    }
+    let falling_behind = if token.is_cancelled() { "nothing queued: a state" } else { "lost" };
+
+    // `cancelled()` stays ready, so a waiter that loops sees it every time.
+    let token = CancellationToken::new();
+    token.cancel();
+    token.cancel();
+    let mut wakes = 0;
+    for _ in 0..3 {
+        wakes += usize::from(woke(token.cancelled()).await);
+    }
+
+    Row {
+        mechanism: "CancellationToken",
+        stop_all,
+        late_subscriber: if late_sees { "sees it" } else { "misses it" }.to_string(),
+        falling_behind: falling_behind.to_string(),
+        triggered_twice: format!("ready {wakes} of 3 times"),
+    }
+}
+
+async fn watch_row() -> Row {
+    let (tx, _) = watch::channel(false);
+    let mut workers = JoinSet::new();
+    for _ in 0..WORKERS {
+        let mut rx = tx.subscribe();
+        workers.spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = rx.wait_for(|&stop| stop) => break,
+                    () = work() => {}
+                }
+            }
+        });
+    }
+    let stop_all = time_stop(&mut workers, || {
+        tx.send_replace(true);
+    })
+    .await;
+
+    // The value is there for anyone who looks, but a new receiver counts it as seen.
+    let (tx, _) = watch::channel(false);
+    tx.send_replace(true);
+    let mut late = tx.subscribe();
+    let late_value = *late.borrow();
+    let late_changed = woke(late.changed()).await;
+    let late_subscriber = match (late_value, late_changed) {
+        (true, false) => "sees value, no changed()".to_string(),
+        (value, changed) => format!("value={value}, changed()={changed}"),
+    };
+
+    // Only the newest value is kept.
+    let (tx, mut rx) = watch::channel(0_u32);
+    for value in 1..=3 {
+        tx.send_replace(value);
+    }
+    let mut changes = 0;
+    for _ in 0..3 {
+        changes += usize::from(woke(rx.changed()).await);
+    }
+    let falling_behind = format!("{changes} changed(), sees #{}", *rx.borrow());
+
+    // Two sends before the receiver looks collapse into one change.
+    let (tx, mut rx) = watch::channel(false);
+    tx.send_replace(true);
+    tx.send_replace(true);
+    let mut wakes = 0;
+    for _ in 0..3 {
+        wakes += usize::from(woke(rx.changed()).await);
+    }
+
+    Row {
+        mechanism: "watch",
+        stop_all,
+        late_subscriber,
+        falling_behind,
+        triggered_twice: format!("{wakes} changed()"),
+    }
+}
+
+async fn notify_row() -> Row {
+    let notify = Arc::new(Notify::new());
+    let mut workers = JoinSet::new();
+    for _ in 0..WORKERS {
+        let notify = notify.clone();
+        workers.spawn(async move {
+            // Registered before the first step of work, so a `notify_waiters` that comes
+            // while we're busy still counts; `notified()` made afresh inside the loop
+            // would miss it.
+            let stop = notify.notified();
+            tokio::pin!(stop);
+            stop.as_mut().enable();
+            loop {
+                tokio::select! {
+                    () = &mut stop => break,
+                    () = work() => {}
+                }
+            }
+        });
+    }
+    let stop_all = time_stop(&mut workers, || notify.notify_waiters()).await;
+
+    // `notify_waiters` only wakes whoever is waiting right then.
+    let notify = Notify::new();
+    notify.notify_waiters();
+    let late_sees = woke(notify.notified()).await;
+
+    // `notify_one` with nobody waiting stores a permit, but only one.
+    let notify = Notify::new();
+    for _ in 0..3 {
+        notify.notify_one();
+    }
+    let mut permits = 0;
+    for _ in 0..3 {
+        permits += usize::from(woke(notify.notified()).await);
+    }
+
+    // A waiter wakes once; the second `notify_waiters` finds nobody registered.
+    let notify = Notify::new();
+    let waiting = notify.notified();
+    tokio::pin!(waiting);
+    waiting.as_mut().enable();
+    notify.notify_waiters();
+    notify.notify_waiters();
+    let mut wakes = usize::from(woke(waiting).await);
+    for _ in 0..2 {
+        wakes += usize::from(woke(notify.notified()).await);
+    }
+
+    Row {
+        mechanism: "Notify",
+        stop_all,
+        late_subscriber: if late_sees { "sees it" } else { "misses it" }.to_string(),
+        falling_behind: format!("{permits} of 3 (one permit)"),
+        triggered_twice: format!("{wakes} wake"),
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+type Stop = Pin<Box<dyn Future<Output = ()> + Send>>;
+type StageCallback = Box<dyn FnMut(&StageReport) + Send>;
+
+/// Stops a set of named subsystems in dependency order.
+///
+/// Each subsystem registers the future that stops it, a deadline for that future, and
+/// the subsystems it depends on: the ones it uses while it runs, which therefore have
+/// to outlive it. `shutdown` then works from the outside in. A subsystem nothing else
+/// depends on stops first; one that others depend on stops once all of them have.
+/// Subsystems that become free at the same time make up a stage and stop concurrently,
+/// each bounded by its own deadline, and the next stage starts when the slowest of
+/// them is done (or has been cut off).
+///
+/// ```
+/// # use shutdown_orchestrator::Orchestrator;
+/// # use std::time::Duration;
+/// # async fn demo() {
+/// let mut orchestrator = Orchestrator::new();
+/// orchestrator.register("stats", Duration::from_secs(1), async { /* final summary */ });
+/// orchestrator
+///     .register("connections", Duration::from_secs(5), async { /* drain */ })
+///     .depends_on(["stats"]);
+/// // `connections` stops first, then `stats`.
+/// let report = orchestrator.shutdown().await.unwrap();
+/// assert!(report.is_clean());
+/// # }
+/// ```
+pub struct Orchestrator {
+    subsystems: Vec<Subsystem>,
+    on_stage: Option<StageCallback>,
+}
+
+struct Subsystem {
+    name: String,
+    deadline: Duration,
+    depends_on: Vec<String>,
+    stop: Stop,
+}
+
+/// A subsystem that has just been registered, to say what it depends on.
+pub struct Registration<'a> {
+    subsystem: &'a mut Subsystem,
+}
+
+/// How one subsystem's stop went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Its stop future finished within the deadline.
+    Stopped,
+    /// The deadline passed first, and the stop future was dropped. Whatever it owned is
+    /// dropped with it (a `JoinSet` aborts its tasks), but nothing more forceful happens.
+    MissedDeadline,
+    /// Its stop future panicked.
+    Panicked,
+}
+
+/// One subsystem's part in a shutdown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsystemReport {
+    pub name: String,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+}
+
+/// One stage of a shutdown: the subsystems that stopped together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageReport {
+    /// Which stage this was, counting from 1.
+    pub number: usize,
+    /// How many stages the shutdown has in all.
+    pub of: usize,
+    /// In the order they were registered.
+    pub subsystems: Vec<SubsystemReport>,
+    pub elapsed: Duration,
+}
+
+/// Everything that happened during a shutdown, stage by stage.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub stages: Vec<StageReport>,
+}
+
+/// Why a set of subsystems can't be put in order. Nothing is stopped when `shutdown`
+/// returns one of these.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    /// Two subsystems were registered under the same name.
+    Duplicate(String),
+    /// A subsystem depends on one that was never registered.
+    UnknownDependency { subsystem: String, dependency: String },
+    /// These subsystems depend on each other in a circle, so none can go first.
+    Cycle(Vec<String>),
+}
+
+impl Orchestrator {
+    /// Creates an orchestrator with no subsystems.
+    pub fn new() -> Self {
+        Self {
+            subsystems: Vec::new(),
+            on_stage: None,
+        }
+    }
+
+    /// Registers a subsystem that `stop` stops, given at most `deadline` to do it.
+    ///
+    /// `stop` isn't polled until the subsystem's turn comes, so anything it owns (a
+    /// sender whose drop ends another task, say) lives until then.
+    pub fn register<F>(&mut self, name: impl Into<String>, deadline: Duration, stop: F) -> Registration<'_>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.subsystems.push(Subsystem {
+            name: name.into(),
+            deadline,
+            depends_on: Vec::new(),
+            stop: Box::pin(stop),
+        });
+        Registration {
+            subsystem: self.subsystems.last_mut().expect("just pushed"),
+        }
+    }
+
+    /// Calls `callback` as each stage finishes, for progress logs while a long drain
+    /// is still running.
+    pub fn on_stage(&mut self, callback: impl FnMut(&StageReport) + Send + 'static) {
+        self.on_stage = Some(Box::new(callback));
+    }
+
+    /// The order `shutdown` would stop things in: the names in each stage.
+    pub fn stages(&self) -> Result<Vec<Vec<&str>>, GraphError> {
+        let plan = self.plan()?;
+        Ok(plan
+            .into_iter()
+            .map(|stage| stage.into_iter().map(|i| self.subsystems[i].name.as_str()).collect())
+            .collect())
+    }
+
+    /// Stops every subsystem, stage by stage.
+    pub async fn shutdown(mut self) -> Result<ShutdownReport, GraphError> {
+        let plan = self.plan()?;
+        let of = plan.len();
+        let mut subsystems: Vec<Option<Subsystem>> = self.subsystems.drain(..).map(Some).collect();
+        let mut report = ShutdownReport::default();
+
+        for (number, stage) in plan.into_iter().enumerate() {
+            let started = Instant::now();
+            let mut running = JoinSet::new();
+            let mut names = HashMap::new();
+            for &i in &stage {
+                let Subsystem { name, deadline, stop, .. } = subsystems[i].take().expect("each subsystem is in one stage");
+                let id = running
+                    .spawn(async move {
+                        let started = Instant::now();
+                        let finished = timeout(deadline, stop).await.is_ok();
+                        (finished, started.elapsed())
+                    })
+                    .id();
+                names.insert(id, (i, name));
+            }
+
+            let mut stopped = Vec::with_capacity(stage.len());
+            while let Some(joined) = running.join_next_with_id().await {
+                let (id, outcome, elapsed) = match joined {
+                    Ok((id, (true, elapsed))) => (id, Outcome::Stopped, elapsed),
+                    Ok((id, (false, elapsed))) => (id, Outcome::MissedDeadline, elapsed),
+                    Err(e) => (e.id(), Outcome::Panicked, started.elapsed()),
+                };
+                let (i, name) = names.remove(&id).expect("every task was recorded");
+                stopped.push((i, SubsystemReport { name, outcome, elapsed }));
+            }
+            stopped.sort_by_key(|&(i, _)| i);
+
+            let stage = StageReport {
+                number: number + 1,
+                of,
+                subsystems: stopped.into_iter().map(|(_, report)| report).collect(),
+                elapsed: started.elapsed(),
+            };
+            if let Some(callback) = &mut self.on_stage {
+                callback(&stage);
+            }
+            report.stages.push(stage);
+        }
+        Ok(report)
+    }
+
+    /// Groups the subsystems into stages, by index.
+    fn plan(&self) -> Result<Vec<Vec<usize>>, GraphError> {
+        let mut index = HashMap::new();
+        for (i, subsystem) in self.subsystems.iter().enumerate() {
+            if index.insert(subsystem.name.as_str(), i).is_some() {
+                return Err(GraphError::Duplicate(subsystem.name.clone()));
+            }
+        }
+        // How many subsystems that depend on each one are still running.
+        let mut dependents = vec![0_usize; self.subsystems.len()];
+        for subsystem in &self.subsystems {
+            for dependency in &subsystem.depends_on {
+                let Some(&i) = index.get(dependency.as_str()) else {
+                    return Err(GraphError::UnknownDependency {
+                        subsystem: subsystem.name.clone(),
+                        dependency: dependency.clone(),
+                    });
+                };
+                dependents[i] += 1;
+            }
+        }
+
+        let mut planned = vec![false; self.subsystems.len()];
+        let mut stages = Vec::new();
+        while planned.iter().any(|&done| !done) {
+            let stage: Vec<usize> = (0..self.subsystems.len()).filter(|&i| !planned[i] && dependents[i] == 0).collect();
+            if stage.is_empty() {
+                let stuck = (0..self.subsystems.len()).filter(|&i| !planned[i]);
+                return Err(GraphError::Cycle(stuck.map(|i| self.subsystems[i].name.clone()).collect()));
+            }
+            for &i in &stage {
+                planned[i] = true;
+                for dependency in &self.subsystems[i].depends_on {
+                    dependents[index[dependency.as_str()]] -= 1;
+                }
+            }
+            stages.push(stage);
+        }
+        Ok(stages)
+    }
+}
+
+impl Default for Orchestrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registration<'_> {
+    /// Says that this subsystem uses `names` while it runs: it will be stopped before
+    /// any of them.
+    pub fn depends_on<I>(self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.subsystem.depends_on.extend(names.into_iter().map(Into::into));
+        self
+    }
+}
+
+impl ShutdownReport {
+    /// How long the whole shutdown took.
+    pub fn elapsed(&self) -> Duration {
+        self.stages.iter().map(|stage| stage.elapsed).sum()
+    }
+
+    /// Whether every subsystem stopped within its deadline.
+    pub fn is_clean(&self) -> bool {
+        self.failed().next().is_none()
+    }
+
+    /// The subsystems that missed their deadline or panicked.
+    pub fn failed(&self) -> impl Iterator<Item = &SubsystemReport> {
+        self.stages
+            .iter()
+            .flat_map(|stage| &stage.subsystems)
+            .filter(|subsystem| subsystem.outcome != Outcome::Stopped)
+    }
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::Duplicate(name) => write!(f, "`{name}` is registered twice"),
+            GraphError::UnknownDependency { subsystem, dependency } => {
+                write!(f, "`{subsystem}` depends on `{dependency}`, which isn't registered")
+            }
+            GraphError::Cycle(names) => write!(f, "dependency cycle among {}", names.join(", ")),
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    fn stop(log: &Log, name: &'static str) -> impl Future<Output = ()> + Send + 'static {
+        let log = log.clone();
+        async move { log.lock().unwrap().push(name) }
+    }
+
+    #[tokio::test]
+    async fn test_dependents_stop_before_what_they_depend_on() {
+        let log = Log::default();
+        let mut orchestrator = Orchestrator::new();
+        orchestrator.register("config", Duration::from_secs(1), stop(&log, "config"));
+        orchestrator.register("stats", Duration::from_secs(1), stop(&log, "stats"));
+        orchestrator
+            .register("connections", Duration::from_secs(1), stop(&log, "connections"))
+            .depends_on(["stats", "config"]);
+        orchestrator
+            .register("listeners", Duration::from_secs(1), stop(&log, "listeners"))
+            .depends_on(["connections"]);
+        orchestrator.register("control", Duration::from_secs(1), stop(&log, "control"));
+
+        assert_eq!(
+            orchestrator.stages().unwrap(),
+            [vec!["listeners", "control"], vec!["connections"], vec!["config", "stats"]]
+        );
+        let report = orchestrator.shutdown().await.unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.stages.len(), 3);
+
+        let log = log.lock().unwrap();
+        let position = |name| log.iter().position(|&logged| logged == name).unwrap();
+        assert!(position("listeners") < position("connections"));
+        assert!(position("connections") < position("stats"));
+        assert!(position("connections") < position("config"));
+    }
+
+    #[tokio::test]
+    async fn test_bad_graphs_are_refused_before_anything_stops() {
+        let log = Log::default();
+        let mut cycle = Orchestrator::new();
+        cycle.register("a", Duration::from_secs(1), stop(&log, "a")).depends_on(["b"]);
+        cycle.register("b", Duration::from_secs(1), stop(&log, "b")).depends_on(["a"]);
+        cycle.register("c", Duration::from_secs(1), stop(&log, "c"));
+        assert_eq!(cycle.shutdown().await.unwrap_err(), GraphError::Cycle(vec!["a".into(), "b".into()]));
+        // `c` could have gone first, but a half-done shutdown is worse than none.
+        assert!(log.lock().unwrap().is_empty());
+
+        let mut unknown = Orchestrator::new();
+        unknown.register("a", Duration::from_secs(1), async {}).depends_on(["nope"]);
+        let error = unknown.shutdown().await.unwrap_err();
+        assert_eq!(error.to_string(), "`a` depends on `nope`, which isn't registered");
+
+        let mut twice = Orchestrator::new();
+        twice.register("a", Duration::from_secs(1), async {});
+        twice.register("a", Duration::from_secs(1), async {});
+        assert_eq!(twice.stages().unwrap_err(), GraphError::Duplicate("a".into()));
+    }
+
+    #[tokio::test]
+    async fn test_a_hung_or_panicking_stop_doesnt_hold_up_the_rest() {
+        let log = Log::default();
+        let mut orchestrator = Orchestrator::new();
+        orchestrator.register("stats", Duration::from_secs(1), stop(&log, "stats"));
+        orchestrator
+            .register("stuck", Duration::from_millis(50), std::future::pending())
+            .depends_on(["stats"]);
+        orchestrator
+            .register("broken", Duration::from_secs(1), async { panic!("stop failed") })
+            .depends_on(["stats"]);
+        let stages = Arc::new(Mutex::new(Vec::new()));
+        let seen = stages.clone();
+        orchestrator.on_stage(move |stage| seen.lock().unwrap().push((stage.number, stage.of)));
+
+        let report = orchestrator.shutdown().await.unwrap();
+        let outcomes: Vec<_> = report.failed().map(|failed| (failed.name.as_str(), failed.outcome)).collect();
+        assert_eq!(outcomes, [("stuck", Outcome::MissedDeadline), ("broken", Outcome::Panicked)]);
+        assert_eq!(*log.lock().unwrap(), ["stats"]);
+        assert_eq!(*stages.lock().unwrap(), [(1, 2), (2, 2)]);
+    }
+}
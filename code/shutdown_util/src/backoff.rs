@@ -0,0 +1,65 @@
+//! Waiting a little longer after each failed `accept`.
+//!
+//! Most accept errors are about the process, not the client: out of file descriptors
+//! (`EMFILE`, `ENFILE`), out of memory. Retrying straight away just fails again, and
+//! the accept loop spins a core while printing the same error. This is the simple
+//! version of the backoff in `tcp_server_graceful_shutdown`, for the servers built on
+//! this crate: no jitter and no giving up, just a delay that doubles up to a ceiling
+//! and starts over once an accept succeeds.
+
+use std::time::Duration;
+
+/// The first retry waits this long.
+const BASE_DELAY: Duration = Duration::from_millis(5);
+/// No retry waits longer than this.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// How long to wait before the next `accept`, given the failures so far.
+#[derive(Debug, Default)]
+pub struct AcceptBackoff {
+    failures: u32,
+}
+
+impl AcceptBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a failure and returns how long to wait before trying again.
+    pub fn on_error(&mut self) -> Duration {
+        self.failures += 1;
+        BASE_DELAY.saturating_mul(1 << (self.failures - 1).min(16)).min(MAX_DELAY)
+    }
+
+    /// Records a success and returns how many failures preceded it, if any.
+    pub fn on_success(&mut self) -> Option<u32> {
+        let failures = std::mem::take(&mut self.failures);
+        (failures > 0).then_some(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_doubles_up_to_the_ceiling() {
+        let mut backoff = AcceptBackoff::new();
+        assert_eq!(backoff.on_error(), BASE_DELAY);
+        assert_eq!(backoff.on_error(), BASE_DELAY * 2);
+        for _ in 0..30 {
+            assert!(backoff.on_error() <= MAX_DELAY);
+        }
+        assert_eq!(backoff.on_error(), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_success_starts_over() {
+        let mut backoff = AcceptBackoff::new();
+        assert_eq!(backoff.on_success(), None);
+        backoff.on_error();
+        backoff.on_error();
+        assert_eq!(backoff.on_success(), Some(2));
+        assert_eq!(backoff.on_error(), BASE_DELAY);
+    }
+}
@@ -0,0 +1,384 @@
+pub mod backoff;
+pub mod cli;
+pub mod signal;
+pub mod state;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+use state::{Registration, ShutdownState};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::{JoinError, JoinSet};
+
+/// Coordinates a graceful shutdown.
+///
+/// This is the pattern from `tcp_server_graceful_shutdown` pulled out into a reusable type:
+/// a `broadcast` channel fans a single "stop" signal out to every subscriber, and a `JoinSet`
+/// keeps track of the tasks that have to finish before the service is considered idle.
+pub struct ShutdownController {
+    shutdown_tx: broadcast::Sender<()>,
+    tasks: JoinSet<()>,
+    state: ShutdownState,
+}
+
+/// The outcome of a deadline-bounded drain.
+#[derive(Debug, Default)]
+pub struct DrainReport {
+    /// Join errors from tasks that panicked before the deadline (or while being aborted).
+    pub errors: Vec<JoinError>,
+    /// How many tasks were still running at the deadline and were cut short by the abort.
+    pub aborted: usize,
+}
+
+/// A cloneable handle that can trigger shutdown without owning the controller.
+///
+/// The controller usually lives inside the server task (it needs `&mut` access to spawn
+/// and drain tasks), so whoever decides *when* to stop holds one of these instead.
+#[derive(Clone)]
+pub struct ShutdownTrigger {
+    shutdown_tx: broadcast::Sender<()>,
+    state: ShutdownState,
+}
+
+impl ShutdownController {
+    /// Creates a controller with no tracked tasks and no subscribers.
+    pub fn new() -> Self {
+        let (shutdown_tx, _) = broadcast::channel(16);
+        Self {
+            shutdown_tx,
+            tasks: JoinSet::new(),
+            state: ShutdownState::new(),
+        }
+    }
+
+    /// Returns a receiver that will see the next shutdown signal.
+    ///
+    /// Like any `broadcast` receiver, it only sees signals sent *after* it was created.
+    pub fn subscribe(&self) -> broadcast::Receiver<()> {
+        self.shutdown_tx.subscribe()
+    }
+
+    /// Like `subscribe`, but for a connection that's only just been accepted: `None` if
+    /// the shutdown has already begun, when the receiver might have missed the signal
+    /// and the connection should be turned away instead. Keep the registration until
+    /// the connection is done; see [`state`] for why the order matters.
+    pub fn subscribe_if_running(&self) -> Option<(broadcast::Receiver<()>, Registration)> {
+        // Subscribe first: once registered, the signal can't have been sent yet.
+        let shutdown_rx = self.shutdown_tx.subscribe();
+        let registration = self.state.register()?;
+        Some((shutdown_rx, registration))
+    }
+
+    /// Whether `trigger` has been called, here or on a `ShutdownTrigger`.
+    pub fn is_shutting_down(&self) -> bool {
+        self.state.is_shutting_down()
+    }
+
+    /// Returns a handle that can trigger shutdown from another task.
+    pub fn trigger_handle(&self) -> ShutdownTrigger {
+        ShutdownTrigger {
+            shutdown_tx: self.shutdown_tx.clone(),
+            state: self.state.clone(),
+        }
+    }
+
+    /// Sends the shutdown signal to every current subscriber.
+    pub fn trigger(&self) {
+        self.state.begin_shutdown();
+        // An error only means nobody is subscribed, which is fine.
+        let _ = self.shutdown_tx.send(());
+    }
+
+    /// Spawns a task that `wait_idle` will wait for.
+    pub fn spawn<F>(&mut self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    /// Like `spawn`, but the task is called `name` in tokio-console.
+    ///
+    /// Task names need the `console` feature and a build with `--cfg tokio_unstable`;
+    /// without them this is the same as `spawn`.
+    pub fn spawn_named<F>(&mut self, name: &str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        #[cfg(all(tokio_unstable, feature = "console"))]
+        self.tasks
+            .build_task()
+            .name(name)
+            .spawn(task)
+            .expect("spawning only fails outside a runtime, where `spawn` would panic too");
+        #[cfg(not(all(tokio_unstable, feature = "console")))]
+        {
+            let _ = name;
+            self.tasks.spawn(task);
+        }
+    }
+
+    /// Returns the number of tracked tasks that have not been drained yet.
+    pub fn active_tasks(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Waits for the next tracked task to finish, or returns `None` if there are none.
+    ///
+    /// A server that calls this while it runs finds out about a panicking task as it
+    /// happens, rather than at the drain, and doesn't keep every finished task in the
+    /// set until then.
+    pub async fn join_next(&mut self) -> Option<Result<(), JoinError>> {
+        self.tasks.join_next().await
+    }
+
+    /// Waits for every tracked task to finish.
+    ///
+    /// This does not send the shutdown signal itself; call `trigger` first (or have
+    /// someone holding a `ShutdownTrigger` do it) so the tasks know to stop.
+    ///
+    /// # Returns
+    /// The join errors of any tasks that panicked or were cancelled.
+    pub async fn wait_idle(&mut self) -> Vec<JoinError> {
+        let mut errors = Vec::new();
+        join_all_into(&mut self.tasks, &mut errors).await;
+        errors
+    }
+
+    /// Waits up to `deadline` for every tracked task to finish, then aborts the rest.
+    ///
+    /// Aborting is the last resort: an aborted task is dropped at its next `.await`,
+    /// so it gets no chance to say goodbye to its peer. The returned report says how
+    /// many tasks that happened to.
+    pub async fn wait_idle_timeout(&mut self, deadline: Duration) -> DrainReport {
+        let mut report = DrainReport::default();
+        let drained = tokio::time::timeout(deadline, join_all_into(&mut self.tasks, &mut report.errors)).await;
+        if drained.is_ok() {
+            return report;
+        }
+
+        // Only the tasks the abort actually stopped count: one that finished in the
+        // meantime, or was mid-poll and finished that poll, comes back `Ok`.
+        self.tasks.abort_all();
+        while let Some(joined) = self.tasks.join_next().await {
+            match joined {
+                Err(e) if e.is_cancelled() => report.aborted += 1,
+                Err(e) => report.errors.push(e),
+                Ok(()) => {}
+            }
+        }
+        report
+    }
+}
+
+async fn join_all_into(tasks: &mut JoinSet<()>, errors: &mut Vec<JoinError>) {
+    while let Some(joined) = tasks.join_next().await {
+        if let Err(e) = joined {
+            errors.push(e);
+        }
+    }
+}
+
+impl Default for ShutdownController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownTrigger {
+    /// Sends the shutdown signal to every current subscriber.
+    pub fn trigger(&self) {
+        self.state.begin_shutdown();
+        let _ = self.shutdown_tx.send(());
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::{sleep, timeout};
+
+    #[tokio::test]
+    async fn test_wait_idle_with_no_tasks() {
+        let mut controller = ShutdownController::new();
+        let errors = controller.wait_idle().await;
+        assert!(errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_trigger_without_subscribers() {
+        let controller = ShutdownController::new();
+        controller.trigger();
+        controller.trigger_handle().trigger();
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_tasks_to_see_signal() {
+        let mut controller = ShutdownController::new();
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..5 {
+            let mut shutdown_rx = controller.subscribe();
+            let finished = finished.clone();
+            controller.spawn(async move {
+                let _ = shutdown_rx.recv().await;
+                finished.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert_eq!(controller.active_tasks(), 5);
+
+        controller.trigger();
+        let errors = controller.wait_idle().await;
+
+        assert!(errors.is_empty());
+        assert_eq!(finished.load(Ordering::SeqCst), 5);
+        assert_eq!(controller.active_tasks(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_from_trigger_handle() {
+        let mut controller = ShutdownController::new();
+        let trigger = controller.trigger_handle();
+        let mut shutdown_rx = controller.subscribe();
+        controller.spawn(async move {
+            let _ = shutdown_rx.recv().await;
+        });
+
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(10)).await;
+            trigger.trigger();
+        });
+
+        let drained = timeout(Duration::from_secs(1), controller.wait_idle()).await;
+        assert!(drained.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_drain_does_not_complete_before_trigger() {
+        let mut controller = ShutdownController::new();
+        let mut shutdown_rx = controller.subscribe();
+        controller.spawn(async move {
+            let _ = shutdown_rx.recv().await;
+        });
+
+        let drained = timeout(Duration::from_millis(50), controller.wait_idle()).await;
+        assert!(drained.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drain_reports_panicked_tasks() {
+        let mut controller = ShutdownController::new();
+        controller.spawn(async {
+            panic!("connection handler blew up");
+        });
+        controller.spawn(async {});
+
+        let errors = controller.wait_idle().await;
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].is_panic());
+    }
+
+    #[tokio::test]
+    async fn test_join_next_reaps_tasks_one_at_a_time() {
+        let mut controller = ShutdownController::new();
+        assert!(controller.join_next().await.is_none());
+
+        controller.spawn(async {
+            panic!("connection handler blew up");
+        });
+        let joined = controller.join_next().await.expect("one task was spawned");
+        assert!(joined.unwrap_err().is_panic());
+        assert_eq!(controller.active_tasks(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_deadline_not_needed() {
+        let mut controller = ShutdownController::new();
+        let mut shutdown_rx = controller.subscribe();
+        controller.spawn(async move {
+            let _ = shutdown_rx.recv().await;
+        });
+
+        controller.trigger();
+        let report = controller.wait_idle_timeout(Duration::from_secs(1)).await;
+        assert_eq!(report.aborted, 0);
+        assert!(report.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drain_deadline_aborts_stragglers() {
+        let mut controller = ShutdownController::new();
+        let mut shutdown_rx = controller.subscribe();
+        controller.spawn(async move {
+            let _ = shutdown_rx.recv().await;
+        });
+        for _ in 0..2 {
+            // Ignores the signal entirely, like a connection stuck in a write.
+            controller.spawn(std::future::pending());
+        }
+
+        controller.trigger();
+        let report = controller.wait_idle_timeout(Duration::from_millis(50)).await;
+        assert_eq!(report.aborted, 2);
+        assert!(report.errors.is_empty());
+        assert_eq!(controller.active_tasks(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_task_that_finishes_despite_the_abort_is_not_counted() {
+        let mut controller = ShutdownController::new();
+        let mut shutdown_rx = controller.subscribe();
+        controller.spawn(async move {
+            let _ = shutdown_rx.recv().await;
+            // Still inside this poll at the deadline, so the abort can't stop it.
+            std::thread::sleep(Duration::from_millis(200));
+        });
+
+        controller.trigger();
+        let report = controller.wait_idle_timeout(Duration::from_millis(50)).await;
+        assert_eq!(report.aborted, 0);
+        assert!(report.errors.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_deadline_is_exact_on_a_paused_clock() {
+        let mut controller = ShutdownController::new();
+        let mut shutdown_rx = controller.subscribe();
+        controller.spawn(async move {
+            let _ = shutdown_rx.recv().await;
+            // Takes its time closing, but finishes well inside the deadline.
+            tokio::time::sleep(Duration::from_secs(9)).await;
+        });
+        controller.spawn(std::future::pending());
+
+        controller.trigger();
+        let started = tokio::time::Instant::now();
+        let report = controller.wait_idle_timeout(Duration::from_secs(10)).await;
+        assert_eq!(started.elapsed(), Duration::from_secs(10));
+        assert_eq!(report.aborted, 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_late_subscriber_once_shutting_down() {
+        let controller = ShutdownController::new();
+        let (mut early, _registration) = controller.subscribe_if_running().expect("still running");
+        controller.trigger_handle().trigger();
+
+        assert!(controller.is_shutting_down());
+        assert!(controller.subscribe_if_running().is_none());
+        assert!(early.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_late_subscriber_misses_earlier_signal() {
+        let controller = ShutdownController::new();
+        let _early = controller.subscribe();
+        controller.trigger();
+
+        let mut late = controller.subscribe();
+        assert!(late.try_recv().is_err());
+    }
+}
@@ -0,0 +1,35 @@
+//! Knowing when the process has been asked to stop.
+
+use std::io;
+
+/// Waits until the OS asks the process to stop, and returns the name of the signal.
+///
+/// On Unix that's `SIGINT` (Ctrl-C) or `SIGTERM` (what `kill`, systemd and container
+/// runtimes send). On Windows it's Ctrl-C or Ctrl-Break. Anywhere else we fall back
+/// to plain `ctrl_c()`.
+pub async fn shutdown_signal() -> io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|()| "SIGINT"),
+            _ = sigterm.recv() => Ok("SIGTERM"),
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let mut ctrl_break = tokio::signal::windows::ctrl_break()?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|()| "Ctrl-C"),
+            _ = ctrl_break.recv() => Ok("Ctrl-Break"),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        tokio::signal::ctrl_c().await.map(|()| "Ctrl-C")
+    }
+}
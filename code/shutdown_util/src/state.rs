@@ -0,0 +1,104 @@
+//! The bookkeeping a graceful shutdown rests on, without the async parts: is the
+//! shutdown on yet, and how many connections are still in.
+//!
+//! The channel and the `JoinSet` in [`ShutdownController`](crate::ShutdownController)
+//! carry the signal and wait for the tasks, but two races live in the gaps between
+//! them, and both come down to ordering:
+//! * A connection accepted just as the shutdown begins subscribes to the channel after
+//!   the signal was sent, never hears it, and holds the drain up until its deadline.
+//!   So admitting one and beginning the shutdown have to happen one at a time: a
+//!   connection is admitted only if the shutdown hasn't begun, and then the shutdown
+//!   can't begin until the admission is done.
+//! * The drain mustn't be declared over while a connection is halfway through being
+//!   admitted. Counting it in in the same step as the check above means there's no
+//!   moment when it is neither refused nor counted.
+//!
+//! One lock around both facts makes each step atomic. It's tiny and only held for a
+//! field update, and the `loom` feature swaps it for loom's, so that
+//! `tests/loom.rs` can check every interleaving of the steps.
+
+#[cfg(feature = "loom")]
+use loom::sync::{Arc, Mutex};
+#[cfg(not(feature = "loom"))]
+use std::sync::{Arc, Mutex};
+
+/// Whether the shutdown has begun, and who's still registered. Clones share the state.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownState {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    shutting_down: bool,
+    registered: usize,
+}
+
+/// Keeps a connection counted until dropped.
+#[derive(Debug)]
+#[must_use = "dropping it unregisters straight away"]
+pub struct Registration {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl ShutdownState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a new connection in, unless the shutdown has begun.
+    pub fn register(&self) -> Option<Registration> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.shutting_down {
+            return None;
+        }
+        inner.registered += 1;
+        Some(Registration { inner: self.inner.clone() })
+    }
+
+    /// Begins the shutdown: from now on nothing new is registered. Returns whether
+    /// this call was the one that began it.
+    pub fn begin_shutdown(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        !std::mem::replace(&mut inner.shutting_down, true)
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.inner.lock().unwrap().shutting_down
+    }
+
+    /// How many registrations haven't been dropped yet.
+    pub fn registered(&self) -> usize {
+        self.inner.lock().unwrap().registered
+    }
+
+    /// Whether the shutdown has begun and everyone registered has gone.
+    pub fn is_drained(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.shutting_down && inner.registered == 0
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.inner.lock().unwrap().registered -= 1;
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_is_refused_once_shutting_down() {
+        let state = ShutdownState::new();
+        let registration = state.register().expect("still running");
+        assert!(state.begin_shutdown());
+        assert!(!state.begin_shutdown());
+        assert!(state.register().is_none());
+
+        assert!(!state.is_drained());
+        drop(registration);
+        assert!(state.is_drained());
+    }
+}
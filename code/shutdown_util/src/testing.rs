@@ -0,0 +1,30 @@
+//! A test fixture for the servers built on `ShutdownController`.
+
+use crate::{ShutdownController, ShutdownTrigger};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// Binds a listener to a free port on 127.0.0.1 and spawns `run` with it, a fresh
+/// controller and a receiver subscribed to it, the way each server's `run_server`
+/// takes them.
+///
+/// # Returns
+/// The address the server listens on, a trigger that shuts it down, and the task,
+/// which ends with whatever `run` returned.
+pub async fn spawn_server<F, Fut>(run: F) -> (SocketAddr, ShutdownTrigger, JoinHandle<io::Result<()>>)
+where
+    F: FnOnce(TcpListener, ShutdownController, broadcast::Receiver<()>) -> Fut,
+    Fut: Future<Output = io::Result<()>> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("binding to a free port");
+    let addr = listener.local_addr().expect("a bound listener has an address");
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let task = tokio::spawn(run(listener, controller, shutdown_rx));
+    (addr, trigger, task)
+}
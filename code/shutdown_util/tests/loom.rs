@@ -0,0 +1,83 @@
+//! Every interleaving of admitting connections and shutting down, checked by loom.
+//! Only built with the `loom` feature:
+//!
+//! ```text
+//! cargo test -p shutdown_util --features loom --release --test loom
+//! ```
+#![cfg(feature = "loom")]
+
+use loom::sync::Arc;
+use loom::sync::atomic::{AtomicBool, Ordering};
+use loom::thread;
+use shutdown_util::state::ShutdownState;
+
+/// The accept loop admits a connection while someone else begins the shutdown.
+/// Whichever goes first, a connection admitted is one the drain waits for, and once
+/// the shutdown has begun nothing more gets in.
+#[test]
+fn test_nothing_is_admitted_after_the_shutdown_is_observed() {
+    loom::model(|| {
+        let state = ShutdownState::new();
+        let accept = thread::spawn({
+            let state = state.clone();
+            move || state.register()
+        });
+
+        state.begin_shutdown();
+        assert!(state.register().is_none());
+
+        match accept.join().unwrap() {
+            Some(registration) => {
+                assert!(!state.is_drained());
+                drop(registration);
+                assert!(state.is_drained());
+            }
+            None => assert!(state.is_drained()),
+        }
+    });
+}
+
+/// A connection is being admitted while the drain checks whether it's done. If the
+/// drain says so, the connection either never got in or has already finished.
+#[test]
+fn test_drain_never_completes_while_a_task_is_registering() {
+    loom::model(|| {
+        let state = ShutdownState::new();
+        let working = Arc::new(AtomicBool::new(false));
+        let task = thread::spawn({
+            let (state, working) = (state.clone(), working.clone());
+            move || {
+                if let Some(registration) = state.register() {
+                    working.store(true, Ordering::SeqCst);
+                    // ... serve the connection ...
+                    working.store(false, Ordering::SeqCst);
+                    drop(registration);
+                }
+            }
+        });
+
+        state.begin_shutdown();
+        if state.is_drained() {
+            assert!(!working.load(Ordering::SeqCst));
+        }
+
+        task.join().unwrap();
+        assert!(state.is_drained());
+    });
+}
+
+/// Two triggers at once, say a signal and the control socket: exactly one of them
+/// begins the shutdown.
+#[test]
+fn test_only_one_trigger_begins_the_shutdown() {
+    loom::model(|| {
+        let state = ShutdownState::new();
+        let other = thread::spawn({
+            let state = state.clone();
+            move || state.begin_shutdown()
+        });
+        let began_here = state.begin_shutdown();
+        let began_there = other.join().unwrap();
+        assert!(began_here != began_there);
+    });
+}
@@ -0,0 +1,238 @@
+//! Server-Sent Events: one HTTP response that never finishes, until shutdown says so.
+//!
+//! An SSE client makes an ordinary GET and the server answers with
+//! `Content-Type: text/event-stream`, then keeps the body open and writes an event
+//! whenever it has one. For a server that makes every client a long-lived task: axum's
+//! graceful shutdown stops accepting and then waits for responses to *finish*, and an
+//! endless stream never does. So the streams have to end themselves.
+//!
+//! Here a ticker task publishes [`ServerEvent`]s on a `broadcast` channel, and every
+//! client's stream turns them into SSE events. On shutdown the ticker publishes
+//! [`ServerEvent::ShuttingDown`]; each stream forwards that as a final `shutdown` event
+//! and ends, which completes its response and lets the drain finish.
+//!
+//! Quiet streams are a problem of their own: proxies and load balancers tend to cut
+//! idle connections. [`KeepAlive`] sends each client a `: keep-alive` comment whenever
+//! its stream has been silent for `keep_alive`, which clients ignore but keeps the
+//! connection busy.
+
+use axum::Router;
+use axum::extract::State;
+use axum::response::Sse;
+use axum::response::sse::{Event, KeepAlive};
+use axum::routing::get;
+use futures::Stream;
+use shutdown_util::ShutdownController;
+use std::convert::Infallible;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::time::{MissedTickBehavior, interval, timeout};
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct SseConfig {
+    /// How often the ticker publishes a `tick` event.
+    pub tick_interval: Duration,
+    /// How long a stream may stay silent before it gets a keep-alive comment.
+    pub keep_alive: Duration,
+    /// How long streams get to finish after shutdown.
+    pub drain_timeout: Duration,
+}
+
+impl Default for SseConfig {
+    fn default() -> Self {
+        Self {
+            // Longer than `keep_alive`, so the comments show up between ticks.
+            tick_interval: Duration::from_secs(3),
+            keep_alive: Duration::from_secs(1),
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What gets published to every connected client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerEvent {
+    /// The ticker fired for the n-th time.
+    Tick(u64),
+    /// The number of connected clients changed.
+    Connections(usize),
+    /// The server is stopping; streams end after passing this on.
+    ShuttingDown,
+}
+
+/// The state shared by the ticker and every stream.
+struct Hub {
+    events: broadcast::Sender<ServerEvent>,
+    connections: AtomicUsize,
+    shutting_down: AtomicBool,
+}
+
+impl Hub {
+    fn publish(&self, event: ServerEvent) {
+        // An error only means no client is connected right now.
+        let _ = self.events.send(event);
+    }
+}
+
+/// Counts one connected client for as long as its stream lives. Hyper drops the stream
+/// when the client goes away, so that's when the count goes down again.
+struct ClientGuard {
+    hub: Arc<Hub>,
+}
+
+impl ClientGuard {
+    fn new(hub: Arc<Hub>) -> Self {
+        let connections = hub.connections.fetch_add(1, Ordering::SeqCst) + 1;
+        hub.publish(ServerEvent::Connections(connections));
+        Self { hub }
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        let connections = self.hub.connections.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.hub.publish(ServerEvent::Connections(connections));
+    }
+}
+
+/// Serves `GET /events` on `listener` until shutdown is signalled, then ends every
+/// stream with a `shutdown` event and waits for the responses to complete.
+pub async fn run_server(
+    listener: TcpListener,
+    config: SseConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let (events, _) = broadcast::channel(64);
+    let hub = Arc::new(Hub {
+        events,
+        connections: AtomicUsize::new(0),
+        shutting_down: AtomicBool::new(false),
+    });
+
+    controller.spawn(ticker(hub.clone(), config.tick_interval, controller.subscribe()));
+
+    let app = Router::new()
+        .route("/events", get(events_handler))
+        .with_state((hub.clone(), config.keep_alive));
+    let mut graceful_rx = controller.subscribe();
+    let serve = axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = graceful_rx.recv().await;
+        })
+        .into_future();
+    tokio::pin!(serve);
+
+    tokio::select! {
+        result = &mut serve => return result,
+        _ = shutdown_rx.recv() => println!("[server] shutdown requested, no longer accepting"),
+    }
+
+    println!(
+        "[server] waiting up to {:?} for {} stream(s) to end",
+        config.drain_timeout,
+        hub.connections.load(Ordering::SeqCst)
+    );
+    match timeout(config.drain_timeout, serve).await {
+        Ok(result) => result?,
+        // Dropping the serve future drops whatever connections are left.
+        Err(_) => eprintln!(
+            "[server] drain deadline hit, dropped {} stream(s)",
+            hub.connections.load(Ordering::SeqCst)
+        ),
+    }
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[server] ticker join error: {e}");
+    }
+    println!("[server] all streams ended");
+    Ok(())
+}
+
+/// Publishes a tick every `every` until shutdown, then tells the streams to end.
+async fn ticker(hub: Arc<Hub>, every: Duration, mut shutdown_rx: broadcast::Receiver<()>) {
+    let mut ticks = interval(every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick of an interval is immediate; skip it so tick 1 comes after `every`.
+    ticks.tick().await;
+    let mut count = 0;
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => break,
+            _ = ticks.tick() => {
+                count += 1;
+                hub.publish(ServerEvent::Tick(count));
+            }
+        }
+    }
+    // Set the flag before publishing: a stream that subscribes too late to see the
+    // event is then guaranteed to see the flag.
+    hub.shutting_down.store(true, Ordering::SeqCst);
+    hub.publish(ServerEvent::ShuttingDown);
+}
+
+async fn events_handler(
+    State((hub, keep_alive)): State<(Arc<Hub>, Duration)>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    Sse::new(client_stream(hub)).keep_alive(KeepAlive::new().interval(keep_alive).text("keep-alive"))
+}
+
+/// Where a client's stream is in its life.
+enum Phase {
+    Listening,
+    SayingGoodbye,
+    Done,
+}
+
+/// One client's view of the hub: its events, as SSE, ending after `shutdown`.
+fn client_stream(hub: Arc<Hub>) -> impl Stream<Item = Result<Event, Infallible>> {
+    // Subscribe before counting ourselves in, so our own `connections` event arrives.
+    let rx = hub.events.subscribe();
+    let phase = if hub.shutting_down.load(Ordering::SeqCst) {
+        Phase::SayingGoodbye
+    } else {
+        Phase::Listening
+    };
+    let guard = ClientGuard::new(hub);
+
+    futures::stream::unfold((rx, guard, phase), |(mut rx, guard, phase)| async move {
+        match phase {
+            Phase::Done => None,
+            Phase::SayingGoodbye => Some((Ok(shutdown_event()), (rx, guard, Phase::Done))),
+            Phase::Listening => loop {
+                match rx.recv().await {
+                    Ok(ServerEvent::ShuttingDown) | Err(broadcast::error::RecvError::Closed) => {
+                        break Some((Ok(shutdown_event()), (rx, guard, Phase::Done)));
+                    }
+                    Ok(event) => break Some((Ok(to_sse(&event)), (rx, guard, Phase::Listening))),
+                    // Only the latest tick and count matter, so skipping is fine.
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                }
+            },
+        }
+    })
+}
+
+/// What a stream sends last.
+fn shutdown_event() -> Event {
+    // Browsers' EventSource reconnects on its own when a stream ends; `retry` asks it to
+    // give the server a moment before trying.
+    Event::default()
+        .event("shutdown")
+        .data("server shutting down")
+        .retry(Duration::from_secs(5))
+}
+
+fn to_sse(event: &ServerEvent) -> Event {
+    match event {
+        ServerEvent::Tick(n) => Event::default().event("tick").id(n.to_string()).data(n.to_string()),
+        ServerEvent::Connections(n) => Event::default().event("connections").data(n.to_string()),
+        ServerEvent::ShuttingDown => shutdown_event(),
+    }
+}
@@ -0,0 +1,61 @@
+use clap::Parser;
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use sse_events::{SseConfig, run_server};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// An SSE server that streams ticks and the client count, and ends every stream with a
+/// `shutdown` event on Ctrl-C or SIGTERM.
+///
+/// Watch it with `curl -N http://127.0.0.1:9011/events`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:9011")]
+    bind: SocketAddr,
+    /// Seconds between tick events.
+    #[arg(long, value_name = "SECS", default_value = "3", value_parser = parse_secs)]
+    tick: Duration,
+    /// Seconds of silence before a client gets a keep-alive comment.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    keep_alive: Duration,
+    /// Seconds streams get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = SseConfig {
+        tick_interval: cli.tick,
+        keep_alive: cli.keep_alive,
+        drain_timeout: cli.drain_timeout,
+    };
+
+    let listener = TcpListener::bind(cli.bind).await?;
+    println!("[main] streaming on http://{}/events", listener.local_addr()?);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let server = tokio::spawn(run_server(listener, config, controller, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match server.await {
+        Ok(Ok(())) => println!("[main] server exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
+        Err(e) => eprintln!("[main] server task failed: {e}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use sse_events::{SseConfig, run_server};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+async fn start_server(config: SseConfig) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    spawn_server(|listener, controller, shutdown_rx| run_server(listener, config, controller, shutdown_rx)).await
+}
+
+fn fast_config() -> SseConfig {
+    SseConfig {
+        tick_interval: Duration::from_millis(200),
+        keep_alive: Duration::from_millis(50),
+        drain_timeout: Duration::from_secs(2),
+    }
+}
+
+async fn subscribe(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /events HTTP/1.1\r\nHost: test\r\nAccept: text/event-stream\r\n\r\n")
+        .await
+        .unwrap();
+    stream
+}
+
+/// Reads until `received` contains `needle`. The body is chunked, but the chunk-size
+/// lines don't get in the way of a substring search.
+async fn read_until(stream: &mut TcpStream, received: &mut String, needle: &str) {
+    let mut buf = [0_u8; 1024];
+    timeout(Duration::from_secs(2), async {
+        while !received.contains(needle) {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "stream ended before {needle:?}, got {received:?}");
+            received.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for {needle:?}, got {received:?}"));
+}
+
+#[tokio::test]
+async fn test_streams_ticks_and_keep_alive_comments() {
+    let (addr, trigger, task) = start_server(fast_config()).await;
+    let mut stream = subscribe(addr).await;
+    let mut received = String::new();
+
+    read_until(&mut stream, &mut received, "text/event-stream").await;
+    read_until(&mut stream, &mut received, "event: connections\ndata: 1\n").await;
+    read_until(&mut stream, &mut received, ": keep-alive\n").await;
+    read_until(&mut stream, &mut received, "event: tick\nid: 1\ndata: 1\n").await;
+    read_until(&mut stream, &mut received, "event: tick\nid: 2\ndata: 2\n").await;
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_connection_count_follows_clients() {
+    let (addr, trigger, task) = start_server(fast_config()).await;
+    let mut first = subscribe(addr).await;
+    let mut received = String::new();
+    read_until(&mut first, &mut received, "event: connections\ndata: 1\n").await;
+
+    let mut second = subscribe(addr).await;
+    read_until(&mut first, &mut received, "event: connections\ndata: 2\n").await;
+    let mut other = String::new();
+    read_until(&mut second, &mut other, "event: connections\ndata: 2\n").await;
+
+    // The server notices on its next write, at the latest the next keep-alive.
+    received.clear();
+    drop(second);
+    read_until(&mut first, &mut received, "event: connections\ndata: 1\n").await;
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_ends_streams_with_a_final_event() {
+    let (addr, trigger, task) = start_server(fast_config()).await;
+    let mut streams = Vec::new();
+    for _ in 0..3 {
+        let mut stream = subscribe(addr).await;
+        read_until(&mut stream, &mut String::new(), "event: connections").await;
+        streams.push(stream);
+    }
+
+    trigger.trigger();
+    for mut stream in streams {
+        let mut received = String::new();
+        read_until(&mut stream, &mut received, "event: shutdown\ndata: server shutting down\nretry:5000\n").await;
+        // The response completes and the connection closes, so the read reaches EOF.
+        let mut rest = Vec::new();
+        timeout(Duration::from_secs(2), stream.read_to_end(&mut rest)).await.unwrap().unwrap();
+        received.push_str(&String::from_utf8_lossy(&rest));
+        assert!(received.ends_with("0\r\n\r\n"), "expected the final chunk, got {received:?}");
+    }
+    timeout(Duration::from_secs(2), task).await.unwrap().unwrap().unwrap();
+}
@@ -0,0 +1,145 @@
+//! Streams written as generators, with `async_stream`'s `stream!` and `try_stream!`.
+//!
+//! `manual_stream` implements `poll_next` by hand: its state is in fields, each call
+//! picks up where the last left off, and keeping to the contract about `Pending` is up
+//! to whoever writes it. A generator is an async block that `yield`s: its state is its
+//! local variables, held across each `await` and `yield` by the state machine the
+//! compiler makes of it, and every `Pending` comes from something it awaited, so the
+//! contract keeps itself. [`ticks`] is `manual_stream`'s `Ticks` again, in a loop of
+//! five lines. The price is a macro, whose errors are less clear than the compiler's,
+//! and a stream that isn't `Unpin`, so it has to be pinned before `next()` can be
+//! called on it.
+//!
+//! A generator runs only as far as its consumer asks: it's suspended at each `yield`
+//! until the next item is wanted. [`items`] reads a paged [`Api`] a page at a time, and
+//! fetches the next page only once its consumer has had every item of the last;
+//! [`lines`] reads a file a line at a time. Backpressure comes for free, then, and the
+//! consumer sets it: [`process_all`] handles items with `buffer_unordered`, at most
+//! `limit` at once, and that pulls a new item from the stream only as one finishes, so
+//! a slow consumer slows the fetching in turn, rather than pages piling up in memory.
+
+use async_stream::{stream, try_stream};
+use futures::{Stream, StreamExt};
+use std::io;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::time::Instant;
+
+/// A page of [`Api`] results, and the cursor for the next page if there is one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub items: Vec<u64>,
+    pub next: Option<u64>,
+}
+
+/// A paged API behind a network: the items `0..total`, `per_page` at a time, each page
+/// taking `latency` to fetch. Clones share the count of fetches.
+#[derive(Debug, Clone)]
+pub struct Api {
+    total: u64,
+    per_page: u64,
+    latency: Duration,
+    fetches: Arc<AtomicUsize>,
+}
+
+impl Api {
+    pub fn new(total: u64, per_page: u64, latency: Duration) -> Self {
+        assert!(per_page > 0, "pages have to hold something");
+        Self { total, per_page, latency, fetches: Arc::default() }
+    }
+
+    /// The page starting at `cursor`.
+    pub async fn page(&self, cursor: u64) -> Page {
+        self.fetches.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(self.latency).await;
+        let end = (cursor + self.per_page).min(self.total);
+        Page { items: (cursor..end).collect(), next: (end < self.total).then_some(end) }
+    }
+
+    /// How many pages have been fetched so far, from this and its clones.
+    pub fn fetches(&self) -> usize {
+        self.fetches.load(Ordering::Relaxed)
+    }
+}
+
+/// Every item `api` has, in order, a page at a time: each page is fetched only when the
+/// consumer asks for an item past the last one's.
+pub fn items(api: Api) -> impl Stream<Item = u64> {
+    stream! {
+        let mut cursor = Some(0);
+        while let Some(at) = cursor {
+            let page = api.page(at).await;
+            for item in page.items {
+                yield item;
+            }
+            cursor = page.next;
+        }
+    }
+}
+
+/// The lines of the file at `path`, each read when it's asked for. An error opening or
+/// reading it is the last item.
+pub fn lines(path: PathBuf) -> impl Stream<Item = io::Result<String>> {
+    try_stream! {
+        let mut lines = BufReader::new(File::open(&path).await?).lines();
+        while let Some(line) = lines.next_line().await? {
+            yield line;
+        }
+    }
+}
+
+/// `manual_stream::Ticks` as a generator: a tick every `period`, `count` times, each its
+/// number and when it was due.
+pub fn ticks(period: Duration, count: u64) -> impl Stream<Item = (u64, Instant)> {
+    stream! {
+        let mut due = Instant::now() + period;
+        for tick in 1..=count {
+            tokio::time::sleep_until(due).await;
+            yield (tick, due);
+            due += period;
+        }
+    }
+}
+
+/// What [`process_all`] made of a stream: the results, in the order they finished, and
+/// the most that were being processed at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Processed {
+    pub results: Vec<u64>,
+    pub max_in_flight: usize,
+}
+
+/// Counts the items being processed, and the most there have been at once.
+#[derive(Debug, Default)]
+struct Gauge {
+    now: AtomicUsize,
+    max: AtomicUsize,
+}
+
+async fn process(item: u64, cost: Duration, gauge: &Gauge) -> u64 {
+    let now = gauge.now.fetch_add(1, Ordering::Relaxed) + 1;
+    gauge.max.fetch_max(now, Ordering::Relaxed);
+    tokio::time::sleep(cost).await;
+    gauge.now.fetch_sub(1, Ordering::Relaxed);
+    item * 2
+}
+
+/// Processes every item of `items`, each taking `cost`, up to `limit` at once. Each
+/// result is handed to `on_result` as it's done, along with how long since the start.
+pub async fn process_all(items: impl Stream<Item = u64>, limit: usize, cost: Duration, mut on_result: impl FnMut(u64, Duration)) -> Processed {
+    let start = Instant::now();
+    let gauge = Gauge::default();
+    // Pinned here, as a generator isn't `Unpin` and `next()` wants the stream that is.
+    let mut done = pin!(items.map(|item| process(item, cost, &gauge)).buffer_unordered(limit));
+    let mut results = Vec::new();
+    while let Some(result) = done.next().await {
+        on_result(result, start.elapsed());
+        results.push(result);
+    }
+    Processed { results, max_in_flight: gauge.max.load(Ordering::Relaxed) }
+}
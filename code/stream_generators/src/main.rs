@@ -0,0 +1,68 @@
+use clap::Parser;
+use futures::StreamExt;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::process::ExitCode;
+use std::time::Duration;
+use stream_generators::{Api, items, lines, process_all};
+
+/// Processes a paged API's items, fetched by a generator, with `buffer_unordered`, and
+/// then reads a file a line at a time with another.
+///
+/// Each result is printed with how many pages have been fetched by then: the fetching
+/// keeps only as far ahead as the processing has room for, `--limit` items, so with
+/// slow processing the pages come no faster than they're needed.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Items the API has.
+    #[arg(long, default_value_t = 40)]
+    total: u64,
+    /// Items on each of its pages.
+    #[arg(long, default_value_t = 10)]
+    per_page: u64,
+    /// Milliseconds each page takes to fetch.
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    page_ms: u64,
+    /// Items processed at once.
+    #[arg(long, default_value_t = 4)]
+    limit: usize,
+    /// Milliseconds each item takes to process.
+    #[arg(long, value_name = "MS", default_value_t = 50)]
+    item_ms: u64,
+    /// A file to read the lines of; this crate's manifest by default.
+    #[arg(long, value_name = "PATH")]
+    file: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if cli.per_page == 0 || cli.limit == 0 {
+        eprintln!("[main] --per-page and --limit have to be at least 1");
+        return ExitCode::FAILURE;
+    }
+    let api = Api::new(cli.total, cli.per_page, Duration::from_millis(cli.page_ms));
+    let processed = process_all(items(api.clone()), cli.limit, Duration::from_millis(cli.item_ms), |result, at| {
+        println!("[process] {at:>8.1?} {result:>4}, with {} page(s) fetched", api.fetches());
+    })
+    .await;
+    println!("[process] {} item(s), at most {} at once, from {} page(s)", processed.results.len(), processed.max_in_flight, api.fetches());
+
+    let path = cli.file.unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"));
+    let mut lines = pin!(lines(path.clone()));
+    let mut count = 0;
+    while let Some(line) = lines.next().await {
+        match line {
+            Ok(line) => {
+                count += 1;
+                println!("[lines] {count:>4} {line}");
+            }
+            Err(e) => {
+                eprintln!("[main] reading {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    println!("[lines] {count} line(s) of {}", path.display());
+    ExitCode::SUCCESS
+}
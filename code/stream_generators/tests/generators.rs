@@ -0,0 +1,66 @@
+//! The generators, how far they run ahead of their consumers, and the ticker next to
+//! the hand-written one, on paused time.
+
+use futures::StreamExt;
+use std::pin::pin;
+use std::time::Duration;
+use stream_generators::{Api, items, lines, process_all, ticks};
+
+const PAGE: Duration = Duration::from_millis(100);
+
+#[tokio::test(start_paused = true)]
+async fn test_items_come_in_order_across_the_pages_and_end_after_the_last() {
+    let api = Api::new(25, 10, PAGE);
+    let all: Vec<_> = items(api.clone()).collect().await;
+    assert_eq!(all, (0..25).collect::<Vec<_>>());
+    assert_eq!(api.fetches(), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_a_page_is_fetched_only_when_its_items_are_wanted() {
+    let api = Api::new(100, 10, PAGE);
+    let mut items = pin!(items(api.clone()));
+    assert_eq!(api.fetches(), 0, "nothing until it's polled");
+    for _ in 0..10 {
+        items.next().await.unwrap();
+    }
+    assert_eq!(api.fetches(), 1);
+    items.next().await.unwrap();
+    assert_eq!(api.fetches(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_buffer_unordered_holds_the_fetching_back_to_the_processing() {
+    let api = Api::new(100, 10, PAGE);
+    let mut fetches_seen = Vec::new();
+    let processed = process_all(items(api.clone()), 4, Duration::from_millis(50), |_, _| fetches_seen.push(api.fetches())).await;
+    assert_eq!(processed.max_in_flight, 4);
+    let mut results = processed.results;
+    results.sort_unstable();
+    assert_eq!(results, (0..100).map(|item| item * 2).collect::<Vec<_>>());
+    // After each result, only as many pages as cover the items finished and in flight:
+    // never the next before the consumer's room reaches into it.
+    for (done, fetches) in fetches_seen.into_iter().enumerate() {
+        assert!(fetches <= (done + 1 + 4).div_ceil(10), "{fetches} pages with {} done", done + 1);
+    }
+}
+
+#[tokio::test]
+async fn test_lines_streams_a_file_and_yields_the_error_for_one_that_isnt_there() {
+    let path = std::env::temp_dir().join(format!("stream-generators-lines-{}.txt", std::process::id()));
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    let read: Vec<_> = lines(path.clone()).map(|line| line.unwrap()).collect().await;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read, ["one", "two", "three"]);
+
+    let mut missing = pin!(lines(path));
+    assert_eq!(missing.next().await.unwrap().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    assert!(missing.next().await.is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_the_generator_ticks_as_the_hand_written_ticker_does() {
+    let period = Duration::from_millis(100);
+    let (generated, by_hand) = tokio::join!(ticks(period, 5).collect::<Vec<_>>(), manual_stream::Ticks::new(period, 5).collect::<Vec<_>>());
+    assert_eq!(generated, by_hand);
+}
@@ -0,0 +1,178 @@
+//! Synchronous code calling async code, from threads of its own.
+//!
+//! A legacy subsystem that runs on plain threads, say a worker pool or a C library's
+//! callbacks, often needs the async parts of the program: a client that only has an
+//! async API, a connection pool, a cache that the async side fills. It can't `.await`,
+//! and it mustn't start a runtime of its own for each call.
+//!
+//! The bridge is a task on the program's runtime, spawned through a [`Handle`] that
+//! sync code can be given, taking [`Request`]s over an `mpsc` channel. Each request
+//! carries a `oneshot` for its answer. A [`BridgeClient`] on a sync thread sends with
+//! `blocking_send` and waits with `blocking_recv`: it blocks its own thread, which is
+//! what sync code expects, and never one of the runtime's. The bridge answers each
+//! request in a task of its own, so a slow call from one thread doesn't hold up the
+//! others'.
+//!
+//! [`Bridge::shutdown`] stops it taking requests, answers the ones already sent, and
+//! waits for them, without waiting for the threads: a client called after that gets an
+//! error rather than hanging.
+//!
+//! (Where a sync thread only needs one async call now and then, `handle.block_on(...)`
+//! on a handle it's been given does that with no bridge. It has to be a thread that
+//! isn't the runtime's, as `nested_runtime` shows.)
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::task::{JoinHandle, JoinSet};
+
+/// The async code the sync threads need: a key-value store behind a network, every call
+/// taking `latency` to answer.
+#[derive(Debug, Clone)]
+pub struct Store {
+    entries: Arc<Mutex<HashMap<String, String>>>,
+    latency: Duration,
+}
+
+impl Store {
+    pub fn new(latency: Duration) -> Self {
+        Self { entries: Arc::default(), latency }
+    }
+
+    /// The value at `key`, if there is one.
+    pub async fn get(&self, key: &str) -> Option<String> {
+        tokio::time::sleep(self.latency).await;
+        self.entries.lock().await.get(key).cloned()
+    }
+
+    /// Sets `key` to `value`, and returns the value it had.
+    pub async fn put(&self, key: String, value: String) -> Option<String> {
+        tokio::time::sleep(self.latency).await;
+        self.entries.lock().await.insert(key, value)
+    }
+}
+
+/// A call for the bridge to make, and where to send its answer.
+#[derive(Debug)]
+pub enum Request {
+    Get { key: String, reply: oneshot::Sender<Option<String>> },
+    Put { key: String, value: String, reply: oneshot::Sender<Option<String>> },
+}
+
+impl Request {
+    async fn answer(self, store: Store) {
+        // Nobody to tell if the thread that asked has gone.
+        let _ = match self {
+            Self::Get { key, reply } => reply.send(store.get(&key).await),
+            Self::Put { key, value, reply } => reply.send(store.put(key, value).await),
+        };
+    }
+}
+
+/// The bridge's end on the runtime: a task answering [`Request`]s from any number of
+/// [`BridgeClient`]s.
+#[derive(Debug)]
+pub struct Bridge {
+    requests: mpsc::Sender<Request>,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Bridge {
+    /// Starts the bridge on the runtime behind `handle`, answering from `store`, with room
+    /// for `queue` requests to wait before the clients sending them have to. The handle
+    /// is all it needs, so sync code can start it as well as async code.
+    pub fn start(handle: &Handle, store: Store, queue: usize) -> Self {
+        let (requests, incoming) = mpsc::channel(queue);
+        let (stop, stopped) = oneshot::channel();
+        let task = handle.spawn(serve(incoming, stopped, store));
+        Self { requests, stop, task }
+    }
+
+    /// A client for a sync thread to call the store through.
+    pub fn client(&self) -> BridgeClient {
+        BridgeClient { requests: self.requests.clone() }
+    }
+
+    /// Stops taking requests, answers the ones sent already, and waits until they're
+    /// answered. Clients still held by threads get an error from then on.
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(());
+        // A request that panicked has said so on stderr already.
+        let _ = self.task.await;
+    }
+}
+
+async fn serve(mut incoming: mpsc::Receiver<Request>, mut stop: oneshot::Receiver<()>, store: Store) {
+    let mut running = JoinSet::new();
+    loop {
+        tokio::select! {
+            request = incoming.recv() => match request {
+                Some(request) => {
+                    running.spawn(request.answer(store.clone()));
+                    // Reaps the finished ones as it goes, so the set doesn't grow.
+                    while running.try_join_next().is_some() {}
+                }
+                None => break,
+            },
+            // Sent, or the bridge dropped without a shutdown: either way, stop.
+            _ = &mut stop => {
+                // Refuses new requests, but leaves the queued ones to be received.
+                incoming.close();
+                while let Some(request) = incoming.recv().await {
+                    running.spawn(request.answer(store.clone()));
+                }
+                break;
+            }
+        }
+    }
+    running.join_all().await;
+}
+
+/// A sync thread's end of the bridge. Its calls block the thread they're made on until
+/// the answer comes back, so they mustn't be made on one of the runtime's: tokio panics
+/// if they are, rather than let them deadlock.
+#[derive(Debug, Clone)]
+pub struct BridgeClient {
+    requests: mpsc::Sender<Request>,
+}
+
+impl BridgeClient {
+    /// The value at `key`, if there is one.
+    pub fn get(&self, key: &str) -> io::Result<Option<String>> {
+        self.call(|reply| Request::Get { key: key.to_string(), reply })
+    }
+
+    /// Sets `key` to `value`, and returns the value it had.
+    pub fn put(&self, key: &str, value: &str) -> io::Result<Option<String>> {
+        self.call(|reply| Request::Put { key: key.to_string(), value: value.to_string(), reply })
+    }
+
+    fn call<T>(&self, request: impl FnOnce(oneshot::Sender<T>) -> Request) -> io::Result<T> {
+        let (reply, answer) = oneshot::channel();
+        // Waits for room in the queue if it's full.
+        self.requests.blocking_send(request(reply)).map_err(|_| stopped())?;
+        answer.blocking_recv().map_err(|_| stopped())
+    }
+}
+
+fn stopped() -> io::Error {
+    io::Error::other("the bridge has shut down")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_the_store_returns_what_was_put() {
+        let store = Store::new(Duration::ZERO);
+        assert_eq!(store.put("a".to_string(), "1".to_string()).await, None);
+        assert_eq!(store.put("a".to_string(), "2".to_string()).await.as_deref(), Some("1"));
+        assert_eq!(store.get("a").await.as_deref(), Some("2"));
+        assert_eq!(store.get("b").await, None);
+    }
+}
@@ -0,0 +1,85 @@
+use clap::Parser;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+use sync_async_bridge::{Bridge, BridgeClient, Store};
+use tokio::runtime::Handle;
+
+/// Legacy threads calling an async store through a bridge on a single-threaded runtime.
+///
+/// Each of `--threads` plain threads puts `--calls` keys in the store and reads them
+/// back, a blocking call at a time, while a heartbeat on the runtime shows that it's
+/// never held up by them: the threads only ever block themselves. Then the bridge shuts
+/// down, once the threads are done, and one more call shows what a thread that's still
+/// around gets.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Legacy threads calling the store.
+    #[arg(long, default_value_t = 4)]
+    threads: usize,
+    /// Keys each thread puts and reads back.
+    #[arg(long, default_value_t = 5)]
+    calls: usize,
+    /// Milliseconds each of the store's calls takes.
+    #[arg(long, value_name = "MS", default_value_t = 20)]
+    latency_ms: u64,
+}
+
+/// A legacy thread's work: synchronous code, knowing nothing of the runtime, calling the
+/// store through `client`.
+fn legacy_worker(id: usize, calls: usize, client: BridgeClient) -> std::io::Result<()> {
+    for call in 0..calls {
+        client.put(&format!("{id}/{call}"), &format!("value {call} from thread {id}"))?;
+    }
+    for call in 0..calls {
+        let value = client.get(&format!("{id}/{call}"))?;
+        println!("[thread {id}] {id}/{call} = {value:?}");
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let store = Store::new(Duration::from_millis(cli.latency_ms));
+    let bridge = Bridge::start(&Handle::current(), store, cli.threads);
+
+    let heartbeat = tokio::spawn(async {
+        let start = Instant::now();
+        let mut interval = tokio::time::interval(Duration::from_millis(50));
+        loop {
+            interval.tick().await;
+            println!("[heartbeat] {:?}", start.elapsed());
+        }
+    });
+
+    let start = Instant::now();
+    let threads: Vec<_> = (0..cli.threads)
+        .map(|id| {
+            let client = bridge.client();
+            thread::spawn(move || legacy_worker(id, cli.calls, client))
+        })
+        .collect();
+    // Joining a thread blocks, so it's done off the runtime, same as any other blocking.
+    let joined = tokio::task::spawn_blocking(move || threads.into_iter().map(|thread| thread.join()).collect::<Vec<_>>()).await;
+    let mut failed = false;
+    for result in joined.expect("joining doesn't panic") {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                eprintln!("[main] a thread's call failed: {e}");
+                failed = true;
+            }
+            Err(_) => failed = true,
+        }
+    }
+    println!("[main] {} threads made {} calls each in {:?}", cli.threads, 2 * cli.calls, start.elapsed());
+    heartbeat.abort();
+
+    let late = bridge.client();
+    bridge.shutdown().await;
+    println!("[main] the bridge has shut down");
+    let late = tokio::task::spawn_blocking(move || late.get("0/0")).await.expect("the call doesn't panic");
+    println!("[main] a call after that: {late:?}");
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
@@ -0,0 +1,79 @@
+//! Plain threads calling the store through the bridge, on a runtime of the test's own.
+
+use std::thread;
+use std::time::{Duration, Instant};
+use sync_async_bridge::{Bridge, Store};
+use tokio::runtime::{Builder, Runtime};
+
+fn runtime() -> Runtime {
+    Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+/// Drives the runtime on a thread of its own, as a program's main thread would, until
+/// the returned sender is dropped or sent to.
+fn drive(runtime: Runtime) -> (tokio::sync::oneshot::Sender<()>, thread::JoinHandle<Runtime>) {
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let thread = thread::spawn(move || {
+        let _ = runtime.block_on(stopped);
+        runtime
+    });
+    (stop, thread)
+}
+
+#[test]
+fn test_a_sync_thread_gets_what_it_put() {
+    let runtime = runtime();
+    let bridge = Bridge::start(runtime.handle(), Store::new(Duration::from_millis(5)), 4);
+    let client = bridge.client();
+    let (stop, driver) = drive(runtime);
+
+    assert_eq!(client.put("a", "1").unwrap(), None);
+    assert_eq!(client.put("a", "2").unwrap().as_deref(), Some("1"));
+    assert_eq!(client.get("a").unwrap().as_deref(), Some("2"));
+    assert_eq!(client.get("b").unwrap(), None);
+
+    drop(stop);
+    driver.join().unwrap().block_on(bridge.shutdown());
+}
+
+#[test]
+fn test_the_threads_calls_overlap() {
+    let runtime = runtime();
+    let bridge = Bridge::start(runtime.handle(), Store::new(Duration::from_millis(100)), 4);
+    let (stop, driver) = drive(runtime);
+
+    let start = Instant::now();
+    let threads: Vec<_> = (0..4)
+        .map(|id| {
+            let client = bridge.client();
+            thread::spawn(move || client.put(&id.to_string(), "x").unwrap())
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    // Four 100ms calls at once on one runtime thread: 100ms, not 400ms.
+    let took = start.elapsed();
+    assert!(took < Duration::from_millis(300), "{took:?}");
+
+    drop(stop);
+    driver.join().unwrap().block_on(bridge.shutdown());
+}
+
+#[test]
+fn test_shutdown_answers_the_calls_already_made_and_refuses_the_rest() {
+    let runtime = runtime();
+    let bridge = Bridge::start(runtime.handle(), Store::new(Duration::from_millis(200)), 4);
+    let client = bridge.client();
+    let early = bridge.client();
+    let caller = thread::spawn(move || early.put("a", "1"));
+    // Long enough for the call to be sent; the runtime isn't running to answer it yet.
+    thread::sleep(Duration::from_millis(50));
+
+    let start = Instant::now();
+    runtime.block_on(bridge.shutdown());
+    assert!(start.elapsed() >= Duration::from_millis(100), "shutdown didn't wait for the call");
+    assert_eq!(caller.join().unwrap().unwrap(), None);
+    // This thread still holds a client, and shutdown didn't wait for it to be dropped.
+    assert!(client.get("a").is_err());
+}
@@ -0,0 +1,106 @@
+//! The backend pool, shared between the accept loop and the health checker.
+//!
+//! Both sides only ever touch atomics: the accept loop bumps a cursor and reads health
+//! flags, the checker writes them. No lock means a slow probe can never hold up picking
+//! a backend for a new connection. The cost is that the two can briefly disagree (a
+//! backend that just died may be picked once more before the checker notices), which
+//! the accept loop handles anyway by trying the next one when a connect fails.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// One upstream address and whether it's currently believed to be up.
+#[derive(Debug)]
+pub(crate) struct Backend {
+    pub(crate) addr: SocketAddr,
+    healthy: AtomicBool,
+}
+
+impl Backend {
+    pub(crate) fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Records the outcome of a probe or connect. Returns `true` if that changed the
+    /// backend's state, so the caller can log transitions rather than every probe.
+    pub(crate) fn set_healthy(&self, healthy: bool) -> bool {
+        self.healthy.swap(healthy, Ordering::Relaxed) != healthy
+    }
+}
+
+/// Every backend, plus the round-robin cursor.
+#[derive(Debug)]
+pub(crate) struct Backends {
+    backends: Vec<Backend>,
+    next: AtomicUsize,
+}
+
+impl Backends {
+    /// Starts with every backend marked healthy; the first round of probes corrects
+    /// that within one interval.
+    pub(crate) fn new(addrs: &[SocketAddr]) -> Self {
+        Self {
+            backends: addrs
+                .iter()
+                .map(|&addr| Backend {
+                    addr,
+                    healthy: AtomicBool::new(true),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Backend> {
+        self.backends.iter()
+    }
+
+    /// The healthy backends in the order this connection should try them: round-robin
+    /// for the first choice, then the rest as fallbacks.
+    pub(crate) fn candidates(&self) -> impl Iterator<Item = &Backend> {
+        let len = self.backends.len();
+        // `fetch_add` wraps on overflow, and taking it modulo `len` keeps the rotation
+        // even across the wrap (close enough, anyway, after 2^64 connections).
+        let start = if len == 0 { 0 } else { self.next.fetch_add(1, Ordering::Relaxed) % len };
+        (0..len)
+            .map(move |i| &self.backends[(start + i) % len])
+            .filter(|backend| backend.is_healthy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(n: u16) -> Backends {
+        let addrs: Vec<SocketAddr> = (1..=n).map(|port| SocketAddr::from(([127, 0, 0, 1], port))).collect();
+        Backends::new(&addrs)
+    }
+
+    fn first_ports(backends: &Backends, rounds: usize) -> Vec<u16> {
+        (0..rounds)
+            .map(|_| backends.candidates().next().unwrap().addr.port())
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin_rotates_through_every_backend() {
+        let backends = pool(3);
+        assert_eq!(first_ports(&backends, 6), [1, 2, 3, 1, 2, 3]);
+        let fallbacks: Vec<u16> = backends.candidates().map(|b| b.addr.port()).collect();
+        assert_eq!(fallbacks, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_unhealthy_backends_are_skipped() {
+        let backends = pool(3);
+        assert!(backends.backends[1].set_healthy(false));
+        assert!(!backends.backends[1].set_healthy(false));
+        assert_eq!(first_ports(&backends, 4), [1, 3, 3, 1]);
+
+        for backend in backends.iter() {
+            backend.set_healthy(false);
+        }
+        assert!(backends.candidates().next().is_none());
+    }
+}
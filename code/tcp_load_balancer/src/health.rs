@@ -0,0 +1,52 @@
+//! Active health checks: a background task that tries to connect to every backend on
+//! a fixed interval.
+//!
+//! A TCP connect is the crudest probe there is (it proves something is listening, not
+//! that it's answering correctly) but it needs no cooperation from the backend. Probes
+//! for different backends run concurrently, so one backend that black-holes SYNs costs
+//! a round one probe timeout, not one per backend.
+
+use crate::backends::Backends;
+use futures::future::join_all;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::time::{MissedTickBehavior, interval, timeout};
+
+/// Probes every backend each `every` until shutdown is signalled.
+pub(crate) async fn run_health_checks(
+    backends: Arc<Backends>,
+    every: Duration,
+    probe_timeout: Duration,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) {
+    let mut ticks = interval(every);
+    // If a round overruns the interval, start the next one a full interval later
+    // rather than firing a burst of catch-up rounds.
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => return,
+            _ = ticks.tick() => {}
+        }
+        // A round that's in progress when shutdown arrives gets cut short at its next
+        // `.await`: probes own nothing worth finishing.
+        tokio::select! {
+            _ = shutdown_rx.recv() => return,
+            () = probe_all(&backends, probe_timeout) => {}
+        }
+    }
+}
+
+async fn probe_all(backends: &Backends, probe_timeout: Duration) {
+    join_all(backends.iter().map(|backend| async move {
+        let healthy = matches!(timeout(probe_timeout, TcpStream::connect(backend.addr)).await, Ok(Ok(_)));
+        if backend.set_healthy(healthy) {
+            let state = if healthy { "up" } else { "DOWN" };
+            println!("[health] backend {} is {state}", backend.addr);
+        }
+    }))
+    .await;
+}
@@ -0,0 +1,144 @@
+//! A round-robin TCP load balancer with health checks.
+//!
+//! Two pieces run side by side and share one `Arc<Backends>`:
+//!
+//! - the accept loop, which hands each client to the next healthy backend and proxies
+//!   it with `copy_bidirectional`, one task per connection;
+//! - a health checker, which probes every backend on an interval and flips its flag
+//!   when it goes down or comes back.
+//!
+//! Both live under one `ShutdownController`. The checker is just another tracked task
+//! that listens for the signal, so the drain after shutdown waits for it like it waits
+//! for the connections. As in `tcp_proxy_graceful_shutdown`, proxied connections
+//! aren't told about shutdown (a balancer can't speak the protocol it carries); they
+//! finish by themselves or are cut off at the drain deadline.
+
+mod backends;
+mod health;
+
+use backends::Backends;
+use shutdown_util::ShutdownController;
+use shutdown_util::backoff::AcceptBackoff;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+
+/// Settings for [`run_balancer`].
+#[derive(Debug, Clone)]
+pub struct BalancerConfig {
+    /// The backends to spread connections over.
+    pub backends: Vec<SocketAddr>,
+    /// How often every backend is probed.
+    pub health_interval: Duration,
+    /// How long a probe, or a connect for a real client, may take.
+    pub connect_timeout: Duration,
+    /// How long in-flight connections get to finish after shutdown.
+    pub drain_timeout: Duration,
+}
+
+/// Balances connections accepted on `listener` until shutdown is signalled, then
+/// drains.
+pub async fn run_balancer(
+    listener: TcpListener,
+    config: BalancerConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    if config.backends.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no backends to balance over"));
+    }
+    let backends = Arc::new(Backends::new(&config.backends));
+    controller.spawn(health::run_health_checks(
+        backends.clone(),
+        config.health_interval,
+        config.connect_timeout,
+        controller.subscribe(),
+    ));
+    let mut next_conn_id = 0_u64;
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[lb] shutdown requested, no longer accepting");
+                break;
+            }
+            accepted = listener.accept() => {
+                let (client, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let delay = backoff.on_error();
+                        eprintln!("[lb] accept failed: {e}, retrying in {delay:?}");
+                        sleep(delay).await;
+                        continue;
+                    }
+                };
+                if let Some(failures) = backoff.on_success() {
+                    println!("[lb] accepting again after {failures} failure(s)");
+                }
+                next_conn_id += 1;
+                let id = next_conn_id;
+                let backends = backends.clone();
+                let connect_timeout = config.connect_timeout;
+                controller.spawn(async move {
+                    match balance(client, &backends, connect_timeout).await {
+                        Ok((backend, up, down)) => {
+                            println!("[lb] conn={id} peer={peer} closed via {backend}: {up}B up, {down}B down");
+                        }
+                        Err(e) => eprintln!("[lb] conn={id} peer={peer} failed: {e}"),
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every connection ever balanced piling up, and leaves the drain only the ones
+            // still open.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[lb] task join error: {e}");
+                }
+            }
+        }
+    }
+    drop(listener);
+
+    println!(
+        "[lb] waiting up to {:?} for {} task(s) to finish",
+        config.drain_timeout,
+        controller.active_tasks()
+    );
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[lb] task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[lb] drain deadline hit, cut off {} connection(s)", report.aborted);
+    } else {
+        println!("[lb] all connections finished");
+    }
+    Ok(())
+}
+
+/// Connects `client` to the first healthy backend that answers and proxies until both
+/// sides are done.
+async fn balance(mut client: TcpStream, backends: &Backends, connect_timeout: Duration) -> io::Result<(SocketAddr, u64, u64)> {
+    for backend in backends.candidates() {
+        match timeout(connect_timeout, TcpStream::connect(backend.addr)).await {
+            Ok(Ok(mut upstream)) => {
+                let (up, down) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+                return Ok((backend.addr, up, down));
+            }
+            // Passive health check: don't wait for the next probe to stop sending
+            // clients to a backend that just refused one.
+            Ok(Err(_)) | Err(_) => {
+                if backend.set_healthy(false) {
+                    println!("[health] backend {} is DOWN (connect failed)", backend.addr);
+                }
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::ConnectionRefused, "no healthy backend"))
+}
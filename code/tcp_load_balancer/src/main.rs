@@ -0,0 +1,65 @@
+use clap::Parser;
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_load_balancer::{BalancerConfig, run_balancer};
+use tokio::net::TcpListener;
+
+/// Spreads TCP connections over several backends, round-robin, skipping unhealthy ones.
+///
+/// For example, with two echo servers:
+/// `cargo run -p tcp_server_graceful_shutdown -- --bind 127.0.0.1:3011` (and `3012`), then
+/// `cargo run -p tcp_load_balancer -- --backend 127.0.0.1:3011 --backend 127.0.0.1:3012`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:5011")]
+    bind: SocketAddr,
+    /// A backend address; repeat for each one.
+    #[arg(long = "backend", value_name = "ADDR", required = true)]
+    backends: Vec<SocketAddr>,
+    /// Seconds between health probes of every backend.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    health_interval: Duration,
+    /// Seconds a connect to a backend (probe or real) may take.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    connect_timeout: Duration,
+    /// Seconds in-flight connections get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "10", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let listener = TcpListener::bind(cli.bind).await?;
+    println!("[main] balancing {} over {:?}", listener.local_addr()?, cli.backends);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let config = BalancerConfig {
+        backends: cli.backends,
+        health_interval: cli.health_interval,
+        connect_timeout: cli.connect_timeout,
+        drain_timeout: cli.drain_timeout,
+    };
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let balancer = tokio::spawn(run_balancer(listener, config, controller, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match balancer.await {
+        Ok(Ok(())) => println!("[main] balancer exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] balancer returned error: {e}"),
+        Err(e) => eprintln!("[main] balancer task failed: {e}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_load_balancer::{BalancerConfig, run_balancer};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+/// A backend that answers every connection with its name and hangs up.
+async fn start_backend(name: &'static str) -> (SocketAddr, JoinHandle<()>) {
+    use tokio::io::AsyncWriteExt;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let task = tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.write_all(name.as_bytes()).await;
+        }
+    });
+    (addr, task)
+}
+
+async fn start_balancer(backends: Vec<SocketAddr>) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    let config = BalancerConfig {
+        backends,
+        health_interval: Duration::from_millis(50),
+        connect_timeout: Duration::from_millis(500),
+        drain_timeout: Duration::from_secs(2),
+    };
+    spawn_server(|listener, controller, shutdown_rx| run_balancer(listener, config, controller, shutdown_rx)).await
+}
+
+async fn who_answers(addr: SocketAddr) -> String {
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    let mut name = String::new();
+    timeout(Duration::from_secs(2), socket.read_to_string(&mut name))
+        .await
+        .unwrap()
+        .unwrap();
+    name
+}
+
+#[tokio::test]
+async fn test_connections_rotate_over_backends() {
+    let (a, _a_task) = start_backend("a").await;
+    let (b, _b_task) = start_backend("b").await;
+    let (addr, trigger, task) = start_balancer(vec![a, b]).await;
+
+    let mut names = Vec::new();
+    for _ in 0..4 {
+        names.push(who_answers(addr).await);
+    }
+    assert_eq!(names, ["a", "b", "a", "b"]);
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_dead_backend_is_skipped() {
+    let (a, a_task) = start_backend("a").await;
+    let (b, _b_task) = start_backend("b").await;
+    let (addr, trigger, task) = start_balancer(vec![a, b]).await;
+
+    a_task.abort();
+    let _ = a_task.await;
+    // Whether the probe or a failed connect notices first, every client lands on `b`.
+    for _ in 0..4 {
+        assert_eq!(who_answers(addr).await, "b");
+    }
+
+    trigger.trigger();
+    timeout(Duration::from_secs(2), task)
+        .await
+        .expect("the health checker should stop on shutdown too")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_no_healthy_backend_closes_the_client() {
+    let dead = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let (addr, trigger, task) = start_balancer(vec![dead]).await;
+
+    assert_eq!(who_answers(addr).await, "");
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
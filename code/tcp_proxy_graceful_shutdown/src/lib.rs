@@ -0,0 +1,152 @@
+//! A TCP proxy that forwards each connection to one upstream and shuts down gracefully.
+//!
+//! Each accepted client gets a connection to the upstream, and
+//! `tokio::io::copy_bidirectional` shovels bytes both ways until both directions have
+//! hit EOF. It also passes half-closes through: when the client finishes sending, the
+//! upstream gets a FIN, and this direction is done while the other keeps going.
+//!
+//! Shutdown looks different from the echo server's, because a proxy has no say in the
+//! protocol it carries. It can't write a polite "shutting down" into someone else's
+//! byte stream. All it can do is stop accepting and give the transfers already under
+//! way a deadline to finish by themselves. Whatever is still running at the deadline is
+//! aborted.
+//!
+//! Is aborting `copy_bidirectional` safe? It's cancel safe in the sense that nothing
+//! breaks: dropping the future drops both sockets, and both peers see the connection
+//! close. But it has a buffer per direction, and bytes already read from one side and
+//! not yet written to the other are lost. That's acceptable here only because the
+//! connections are torn down anyway. Cancelling a copy and then carrying on with the
+//! sockets would silently drop data.
+
+use shutdown_util::ShutdownController;
+use shutdown_util::backoff::AcceptBackoff;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::{Instant, sleep, timeout};
+
+/// Settings for [`run_proxy`].
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// Where to forward connections, as `host:port`.
+    pub upstream: String,
+    /// How long connecting to the upstream may take.
+    pub connect_timeout: Duration,
+    /// How long in-flight transfers get to finish after shutdown before being aborted.
+    pub drain_timeout: Duration,
+}
+
+/// Accepts on `listener` and forwards every connection to the upstream until shutdown
+/// is signalled, then drains.
+pub async fn run_proxy(
+    listener: TcpListener,
+    config: ProxyConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let config = Arc::new(config);
+    let mut next_conn_id = 0_u64;
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[proxy] shutdown requested, no longer accepting");
+                break;
+            }
+            accepted = listener.accept() => {
+                let (client, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let delay = backoff.on_error();
+                        eprintln!("[proxy] accept failed: {e}, retrying in {delay:?}");
+                        sleep(delay).await;
+                        continue;
+                    }
+                };
+                if let Some(failures) = backoff.on_success() {
+                    println!("[proxy] accepting again after {failures} failure(s)");
+                }
+                next_conn_id += 1;
+                let id = next_conn_id;
+                let config = config.clone();
+                // The connection tasks don't subscribe to shutdown: there's nothing for
+                // them to do differently when it comes. They just run to completion or
+                // get aborted at the drain deadline.
+                controller.spawn(async move {
+                    match proxy_connection(client, &config).await {
+                        Ok(transfer) => println!("[proxy] conn={id} peer={peer} closed {transfer}"),
+                        Err(e) => eprintln!("[proxy] conn={id} peer={peer} failed: {e}"),
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every connection ever proxied piling up, and leaves the drain only the
+            // transfers still under way.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[proxy] connection task join error: {e}");
+                }
+            }
+        }
+    }
+    // Close the listening socket now, so new clients are refused straight away rather
+    // than left waiting in the backlog for an accept that never comes.
+    drop(listener);
+
+    let in_flight = controller.active_tasks();
+    println!("[proxy] waiting up to {:?} for {in_flight} transfer(s) to finish", config.drain_timeout);
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[proxy] connection task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[proxy] drain deadline hit, cut off {} transfer(s)", report.aborted);
+    } else {
+        println!("[proxy] all transfers finished");
+    }
+    Ok(())
+}
+
+/// How much one proxied connection moved.
+#[derive(Debug)]
+struct Transfer {
+    upstream: SocketAddr,
+    to_upstream: u64,
+    to_client: u64,
+    duration: Duration,
+}
+
+impl std::fmt::Display for Transfer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "via {}: {}B up, {}B down in {:?}",
+            self.upstream, self.to_upstream, self.to_client, self.duration
+        )
+    }
+}
+
+async fn proxy_connection(mut client: TcpStream, config: &ProxyConfig) -> io::Result<Transfer> {
+    let started = Instant::now();
+    let mut upstream = match timeout(config.connect_timeout, TcpStream::connect(&config.upstream)).await {
+        Ok(connected) => connected.map_err(|e| io::Error::new(e.kind(), format!("upstream {}: {e}", config.upstream)))?,
+        Err(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("upstream {} didn't answer within {:?}", config.upstream, config.connect_timeout),
+            ));
+        }
+    };
+    let upstream_addr = upstream.peer_addr()?;
+    let (to_upstream, to_client) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(Transfer {
+        upstream: upstream_addr,
+        to_upstream,
+        to_client,
+        duration: started.elapsed(),
+    })
+}
@@ -0,0 +1,61 @@
+use clap::Parser;
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_proxy_graceful_shutdown::{ProxyConfig, run_proxy};
+use tokio::net::TcpListener;
+
+/// Forwards TCP connections to an upstream, draining in-flight transfers on shutdown.
+///
+/// For example, in front of the echo server:
+/// `cargo run -p tcp_proxy_graceful_shutdown -- --upstream 127.0.0.1:3011`, then
+/// `cargo run --bin client -- --addr 127.0.0.1:4011`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:4011")]
+    bind: SocketAddr,
+    /// Where to forward connections, as host:port.
+    #[arg(long, default_value = "127.0.0.1:3011")]
+    upstream: String,
+    /// Seconds connecting to the upstream may take.
+    #[arg(long, value_name = "SECS", default_value = "3", value_parser = parse_secs)]
+    connect_timeout: Duration,
+    /// Seconds in-flight transfers get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "10", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let listener = TcpListener::bind(cli.bind).await?;
+    println!("[main] forwarding {} -> {}", listener.local_addr()?, cli.upstream);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let config = ProxyConfig {
+        upstream: cli.upstream,
+        connect_timeout: cli.connect_timeout,
+        drain_timeout: cli.drain_timeout,
+    };
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let proxy = tokio::spawn(run_proxy(listener, config, controller, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match proxy.await {
+        Ok(Ok(())) => println!("[main] proxy exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] proxy returned error: {e}"),
+        Err(e) => eprintln!("[main] proxy task failed: {e}"),
+    }
+    Ok(())
+}
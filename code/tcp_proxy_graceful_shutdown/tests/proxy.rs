@@ -0,0 +1,107 @@
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_proxy_graceful_shutdown::{ProxyConfig, run_proxy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+/// A bare-bones upstream that echoes every connection until EOF.
+async fn start_echo_upstream() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                let _ = writer.shutdown().await;
+            });
+        }
+    });
+    addr
+}
+
+async fn start_proxy(upstream: SocketAddr, drain_timeout: Duration) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    let config = ProxyConfig {
+        upstream: upstream.to_string(),
+        connect_timeout: Duration::from_secs(1),
+        drain_timeout,
+    };
+    spawn_server(|listener, controller, shutdown_rx| run_proxy(listener, config, controller, shutdown_rx)).await
+}
+
+async fn echo(socket: &mut TcpStream, msg: &[u8]) -> Vec<u8> {
+    socket.write_all(msg).await.unwrap();
+    let mut reply = vec![0_u8; msg.len()];
+    socket.read_exact(&mut reply).await.unwrap();
+    reply
+}
+
+#[tokio::test]
+async fn test_forwards_both_ways_and_passes_half_close_through() {
+    let (addr, trigger, task) = start_proxy(start_echo_upstream().await, Duration::from_secs(5)).await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(echo(&mut client, b"through the proxy").await, b"through the proxy");
+    client.write_all(b"last words").await.unwrap();
+    client.shutdown().await.unwrap();
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).await.unwrap();
+    assert_eq!(rest, b"last words");
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_in_flight_transfer_keeps_working_during_the_drain() {
+    let (addr, trigger, task) = start_proxy(start_echo_upstream().await, Duration::from_secs(5)).await;
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(echo(&mut client, b"before").await, b"before");
+
+    trigger.trigger();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    // No new connections...
+    assert!(TcpStream::connect(addr).await.is_err());
+    // ...but the existing one carries on until the client is done with it.
+    assert_eq!(echo(&mut client, b"during").await, b"during");
+    drop(client);
+
+    timeout(Duration::from_secs(2), task)
+        .await
+        .expect("the proxy should stop once the last transfer ends")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_drain_deadline_cuts_off_idle_transfers() {
+    let (addr, trigger, task) = start_proxy(start_echo_upstream().await, Duration::from_millis(200)).await;
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(echo(&mut client, b"hello").await, b"hello");
+
+    trigger.trigger();
+    timeout(Duration::from_secs(2), task).await.unwrap().unwrap().unwrap();
+    let mut buf = [0_u8; 16];
+    let read = timeout(Duration::from_secs(2), client.read(&mut buf)).await.unwrap();
+    assert!(read.is_err() || read.is_ok_and(|n| n == 0));
+}
+
+#[tokio::test]
+async fn test_unreachable_upstream_closes_the_client() {
+    // Bind and drop to get a port nothing is listening on.
+    let dead = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let (addr, trigger, task) = start_proxy(dead, Duration::from_secs(1)).await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let mut buf = Vec::new();
+    let read = timeout(Duration::from_secs(2), client.read_to_end(&mut buf)).await.unwrap();
+    assert!(read.is_err() || buf.is_empty());
+
+    trigger.trigger();
+    task.await.unwrap().unwrap();
+}
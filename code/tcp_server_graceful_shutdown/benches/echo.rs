@@ -0,0 +1,147 @@
+//! Echo round trips over loopback, for the three ways the server can move the bytes:
+//! the raw handler, which reads and writes in lock-step; `split_halves`, which hands
+//! every chunk from a reader task to a writer task through a queue; and
+//! `--framing length`, which goes through the codec on both sides.
+//!
+//! * `latency`: one client, one message at a time, for a few message sizes. What's
+//!   timed is the round trip, write to last byte back.
+//! * `throughput`: 1 KiB messages from a growing number of clients at once, each
+//!   waiting for its echo before sending the next, reported in bytes echoed per second.
+//!
+//! Connecting isn't timed, and every server runs with `TCP_NODELAY` on, as do the
+//! clients: otherwise the delayed ACK on loopback holds some echoes back by up to 40ms
+//! and the numbers measure that instead.
+//!
+//! `cargo bench -p tcp_server_graceful_shutdown --bench echo`, or add e.g. `-- latency/raw`
+//! to pick out some of them. Criterion keeps the last run in `target/criterion` and says
+//! how the next one compares.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tcp_server_graceful_shutdown::client::{EchoClient, FramedEchoClient};
+use tcp_server_graceful_shutdown::{Framing, Server, ServerHandle};
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+
+const SIZES: [usize; 3] = [64, 1024, 16 * 1024];
+const CONCURRENCY: [usize; 3] = [1, 8, 32];
+const THROUGHPUT_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+enum Path {
+    Raw,
+    Split,
+    Length,
+}
+
+impl Path {
+    const ALL: [Path; 3] = [Path::Raw, Path::Split, Path::Length];
+
+    fn name(self) -> &'static str {
+        match self {
+            Path::Raw => "raw",
+            Path::Split => "split",
+            Path::Length => "length",
+        }
+    }
+
+    async fn start(self) -> ServerHandle {
+        Server::builder()
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .nodelay(true)
+            .framing(if matches!(self, Path::Length) { Framing::Length } else { Framing::Raw })
+            .split_halves(matches!(self, Path::Split))
+            .build()
+            .await
+            .expect("bind ephemeral port")
+            .start()
+    }
+
+    async fn connect(self, addr: SocketAddr) -> Client {
+        let socket = TcpStream::connect(addr).await.unwrap();
+        socket.set_nodelay(true).unwrap();
+        match self {
+            Path::Raw | Path::Split => Client::Raw(EchoClient::new(socket)),
+            Path::Length => Client::Framed(FramedEchoClient::new(socket)),
+        }
+    }
+}
+
+enum Client {
+    Raw(EchoClient),
+    Framed(FramedEchoClient),
+}
+
+impl Client {
+    async fn echo(&mut self, msg: &[u8]) {
+        let reply = match self {
+            Client::Raw(client) => client.echo(msg).await,
+            Client::Framed(client) => client.echo(msg).await,
+        };
+        assert_eq!(reply.unwrap().len(), msg.len());
+    }
+}
+
+/// `iters` round trips on each of `clients` connections at once, and how long they took.
+async fn run(path: Path, addr: SocketAddr, clients: usize, size: usize, iters: u64) -> Duration {
+    let mut connected = Vec::with_capacity(clients);
+    for _ in 0..clients {
+        connected.push(path.connect(addr).await);
+    }
+    let msg = vec![b'x'; size];
+
+    let started = Instant::now();
+    let tasks: Vec<_> = connected
+        .into_iter()
+        .map(|mut client| {
+            let msg = msg.clone();
+            tokio::spawn(async move {
+                for _ in 0..iters {
+                    client.echo(&msg).await;
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    started.elapsed()
+}
+
+fn benches(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let servers: Vec<_> = Path::ALL.iter().map(|&path| (path, rt.block_on(path.start()))).collect();
+
+    let mut latency = c.benchmark_group("latency");
+    for (path, server) in &servers {
+        let addr = server.local_addr();
+        for size in SIZES {
+            latency.throughput(Throughput::Bytes(size as u64));
+            latency.bench_with_input(BenchmarkId::new(path.name(), size), &size, |b, &size| {
+                b.to_async(&rt).iter_custom(|iters| run(*path, addr, 1, size, iters));
+            });
+        }
+    }
+    latency.finish();
+
+    let mut throughput = c.benchmark_group("throughput");
+    for (path, server) in &servers {
+        let addr = server.local_addr();
+        for clients in CONCURRENCY {
+            throughput.throughput(Throughput::Bytes((THROUGHPUT_SIZE * clients) as u64));
+            throughput.bench_with_input(BenchmarkId::new(path.name(), clients), &clients, |b, &clients| {
+                b.to_async(&rt).iter_custom(|iters| run(*path, addr, clients, THROUGHPUT_SIZE, iters));
+            });
+        }
+    }
+    throughput.finish();
+
+    for (_, server) in servers {
+        server.shutdown();
+        rt.block_on(server.await_terminated()).unwrap();
+    }
+}
+
+criterion_group!(echo, benches);
+criterion_main!(echo);
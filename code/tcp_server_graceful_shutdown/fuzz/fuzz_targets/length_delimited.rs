@@ -0,0 +1,43 @@
+//! Feeds arbitrary bytes, in arbitrary pieces, to the decoder `--framing length` reads
+//! with, until the input runs out or a decode error ends the connection.
+//!
+//! The length prefix is the client's to choose, and the decoder reserves room for the
+//! frame as soon as it has read the prefix. So besides not panicking, it checks the
+//! buffer never grows past what the message size limit allows: without the limit, four
+//! bytes could ask for 4 GiB.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use tcp_server_graceful_shutdown::length_decoder;
+use tokio_util::codec::Decoder;
+
+/// Small, so the memory check has teeth; the default is 1 MiB.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+const PREFIX: usize = 4;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the size of each read.
+    let Some((&chunk, data)) = data.split_first() else {
+        return;
+    };
+    let chunk = usize::from(chunk).max(1);
+
+    let mut decoder = length_decoder(MAX_MESSAGE_SIZE);
+    let mut buf = BytesMut::new();
+    for piece in data.chunks(chunk) {
+        buf.extend_from_slice(piece);
+        loop {
+            match decoder.decode(&mut buf) {
+                Ok(Some(frame)) => assert!(frame.len() <= MAX_MESSAGE_SIZE),
+                Ok(None) => break,
+                Err(_) => return,
+            }
+            // `BytesMut` may round a reservation up, but never to more than double.
+            assert!(buf.capacity() <= 2 * (MAX_MESSAGE_SIZE + PREFIX) + chunk, "{} bytes reserved", buf.capacity());
+        }
+    }
+    // The client hangs up: a partial frame left over is an error, not a panic.
+    let _ = decoder.decode_eof(&mut buf);
+});
@@ -0,0 +1,38 @@
+//! Feeds arbitrary bytes, in arbitrary pieces, to the codec `--framing lines` reads
+//! with, configured as the server is by default, until the input runs out or a decode
+//! error ends the connection.
+//!
+//! Besides not panicking, it checks that no line comes out longer than
+//! `max_line_length`, and that while the codec waits for a newline it never holds more
+//! than that either: a client that never sends one mustn't be able to fill our memory.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use tcp_server_graceful_shutdown::{ServerConfig, lines_codec};
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the size of each read.
+    let Some((&chunk, data)) = data.split_first() else {
+        return;
+    };
+    let chunk = usize::from(chunk).max(1);
+
+    let config = ServerConfig::default();
+    let mut codec = lines_codec(&config);
+    let mut buf = BytesMut::new();
+    for piece in data.chunks(chunk) {
+        buf.extend_from_slice(piece);
+        loop {
+            match codec.decode(&mut buf) {
+                Ok(Some(line)) => assert!(line.len() <= config.max_line_length),
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+        assert!(buf.len() <= config.max_line_length, "{} bytes buffered", buf.len());
+    }
+    let _ = codec.decode_eof(&mut buf);
+});
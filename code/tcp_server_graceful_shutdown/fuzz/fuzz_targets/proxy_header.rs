@@ -0,0 +1,31 @@
+//! Feeds arbitrary bytes to the PROXY header parser, as the start of a connection.
+//!
+//! Besides not panicking, it checks that the parser reads only as far as the header
+//! goes, at most the 16-byte version 2 preamble and the 64 KiB its length field can
+//! claim, and that a header which parses, encoded again in either version, parses back
+//! to the same addresses.
+
+#![no_main]
+
+use futures::executor::block_on;
+use libfuzzer_sys::fuzz_target;
+use tcp_server_graceful_shutdown::proxy::{ProxyVersion, read_header};
+
+const LONGEST_HEADER: usize = 16 + u16::MAX as usize;
+
+fuzz_target!(|data: &[u8]| {
+    // A slice never has to wait for more, so the future finishes on the first poll.
+    let mut rest = data;
+    let Ok(header) = block_on(read_header(&mut rest)) else {
+        return;
+    };
+    assert!(data.len() - rest.len() <= LONGEST_HEADER);
+
+    let Some(header) = header else {
+        return;
+    };
+    for version in [ProxyVersion::V1, ProxyVersion::V2] {
+        let encoded = header.encode(version);
+        assert_eq!(block_on(read_header(&mut encoded.as_slice())).unwrap(), Some(header), "{version:?}");
+    }
+});
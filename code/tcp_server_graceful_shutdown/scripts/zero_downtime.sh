@@ -0,0 +1,30 @@
+#!/usr/bin/env bash
+# Zero-downtime restart: a second server takes over the port while a client keeps
+# connecting, and the first one drains.
+#
+#   ./scripts/zero_downtime.sh
+#
+# Watch for "took over the port" from the new server, then the old one's
+# "shutdown requested" and drain. The client loop shouldn't see a single
+# failed connection.
+set -euo pipefail
+cd "$(dirname "$0")/.."
+
+cargo build --quiet --bin tcp_server_graceful_shutdown --bin client
+server=../target/debug/tcp_server_graceful_shutdown
+client=../target/debug/client
+pid_file=$(mktemp -u /tmp/echo-server.XXXXXX.pid)
+
+"$server" --reuseport --pid-file "$pid_file" 2>&1 | sed 's/^/[old] /' &
+sleep 1
+
+# A client that connects every 100ms; any failure is printed with "error".
+( for i in $(seq 40); do "$client" --message "ping $i" 2>&1 | sed 's/^/[client] /'; sleep 0.1; done ) &
+clients=$!
+sleep 1
+
+"$server" --takeover --pid-file "$pid_file" 2>&1 | sed 's/^/[new] /' &
+wait "$clients"
+
+kill -TERM "$(cat "$pid_file")"
+wait
@@ -0,0 +1,214 @@
+//! Accept loops: one task per listening socket, all feeding one channel.
+//!
+//! With several listeners (say `127.0.0.1:3011` and `[::1]:3011`) there are several
+//! `accept` calls to wait on at once. Each listener gets its own task, running its own
+//! backoff, and hands what it accepts to the server's main loop over an `mpsc`
+//! channel. The main loop stays the only place that spawns connection tasks, so they
+//! all end up in the same `JoinSet` and hear the same shutdown signal however they
+//! arrived.
+//!
+//! The accept loops don't listen for shutdown themselves. The main loop stops reading
+//! the channel and aborts them, which is fine because an accept loop owns nothing
+//! worth cleaning up: it's either parked in `accept` (cancellation safe) or holding one
+//! just-accepted socket that we'd have turned away anyway.
+//!
+//! With an accept rate limit, the loops also wait their turn at a shared
+//! [`AcceptRate`] before each `accept`.
+
+use crate::accept_rate::AcceptRate;
+use crate::backoff::AcceptBackoff;
+use crate::config::OverloadPolicy;
+use crate::listener::Listener;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+/// Whether an accepted connection got a slot.
+pub(crate) enum Admission {
+    /// Serve it. The permit (if there is a limit) is held for the connection's lifetime.
+    Admitted(Option<OwnedSemaphorePermit>),
+    /// The server is full and the policy is `Reject`.
+    Busy,
+}
+
+/// A connection one of the accept loops picked up.
+pub(crate) struct Accepted<S> {
+    pub(crate) socket: S,
+    pub(crate) peer_addr: SocketAddr,
+    pub(crate) admission: Admission,
+}
+
+/// Binds a listener on `addr`.
+///
+/// An IPv6 wildcard like `[::]:3011` normally accepts IPv4 clients too (as
+/// `::ffff:1.2.3.4`), at least on Linux, and then a second `0.0.0.0:3011` fails with
+/// "address in use". Setting `IPV6_V6ONLY` makes each listener handle one family, so
+/// `--bind 0.0.0.0:3011 --bind [::]:3011` behaves the same everywhere.
+///
+/// With `reuseport`, `SO_REUSEPORT` lets another process (a newer version of us, say)
+/// bind the same port while we're still running; on Linux the kernel then spreads new
+/// connections across every listener that set it. That's the basis of a zero-downtime
+/// restart: start the new server, then drain the old one.
+pub(crate) fn bind_listener(addr: SocketAddr, reuseport: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    // What `TcpListener::bind` does too on Unix: lets a restarted server bind the port
+    // while connections from its previous run are still in TIME_WAIT.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    if reuseport {
+        set_reuse_port(&socket)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    // tokio needs the socket non-blocking before it can register it with the reactor.
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin")))))]
+fn set_reuse_port(_socket: &Socket) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "SO_REUSEPORT isn't available on this platform"))
+}
+
+/// The running accept loops and the channel they report on.
+pub(crate) struct Acceptors<S> {
+    tasks: JoinSet<()>,
+    accepted_rx: mpsc::Receiver<io::Result<Accepted<S>>>,
+}
+
+impl<S: Send + 'static> Acceptors<S> {
+    /// Spawns one accept loop per listener.
+    pub(crate) fn spawn<L: Listener<Stream = S>>(
+        listeners: Vec<L>,
+        limiter: Option<Arc<Semaphore>>,
+        when_full: OverloadPolicy,
+        accept_rate: Option<Arc<AcceptRate>>,
+        max_accept_failures: Option<u32>,
+    ) -> Self {
+        let (accepted_tx, accepted_rx) = mpsc::channel(16);
+        let mut tasks = JoinSet::new();
+        for listener in listeners {
+            let name = listener.local_addr().map_or_else(|_| "accept".to_string(), |addr| format!("accept {addr}"));
+            crate::tasks::spawn_in(&mut tasks, &name, accept_loop(
+                listener,
+                limiter.clone(),
+                when_full,
+                accept_rate.clone(),
+                AcceptBackoff::new(max_accept_failures),
+                accepted_tx.clone(),
+            ));
+        }
+        Self { tasks, accepted_rx }
+    }
+
+    /// The next accepted connection, or the error that made a listener give up.
+    ///
+    /// Cancellation safe, so it can sit in a `select!` next to the shutdown branch.
+    pub(crate) async fn next(&mut self) -> Option<io::Result<Accepted<S>>> {
+        self.accepted_rx.recv().await
+    }
+
+    /// Stops every accept loop and waits until they're gone.
+    pub(crate) async fn shutdown(mut self) {
+        self.accepted_rx.close();
+        self.tasks.shutdown().await;
+    }
+}
+
+async fn accept_loop<L: Listener>(
+    mut listener: L,
+    limiter: Option<Arc<Semaphore>>,
+    when_full: OverloadPolicy,
+    accept_rate: Option<Arc<AcceptRate>>,
+    mut backoff: AcceptBackoff,
+    accepted_tx: mpsc::Sender<io::Result<Accepted<L::Stream>>>,
+) {
+    let local = listener
+        .local_addr()
+        .map_or_else(|_| "listener".to_string(), |addr| addr.to_string());
+    loop {
+        if let Some(accept_rate) = &accept_rate {
+            accept_rate.ready().await;
+        }
+        let accepted = accept_with_permit(&mut listener, limiter.as_ref(), when_full).await;
+        if let (Some(accept_rate), Ok(_)) = (&accept_rate, &accepted) {
+            accept_rate.admit();
+        }
+        if accepted.is_ok()
+            && let Some(failures) = backoff.on_success()
+        {
+            info!(listener = %local, failures, "accepting again");
+        }
+        let accepted = match accepted {
+            Ok(accepted) => Ok(accepted),
+            Err(e) => match backoff.on_error() {
+                Some(delay) => {
+                    warn!(listener = %local, error = %e, ?delay, "accept failed, retrying");
+                    sleep(delay).await;
+                    continue;
+                }
+                None => {
+                    error!(listener = %local, error = %e, failures = backoff.failures(), "accept keeps failing, giving up");
+                    Err(io::Error::new(e.kind(), format!("accept on {local} keeps failing: {e}")))
+                }
+            },
+        };
+        let gave_up = accepted.is_err();
+        // The main loop is gone or has stopped listening: nothing left to do.
+        if accepted_tx.send(accepted).await.is_err() || gave_up {
+            return;
+        }
+    }
+}
+
+/// Accepts the next connection, respecting the connection limit.
+///
+/// With `OverloadPolicy::Wait` the permit is taken *before* `accept`, so a full server
+/// stops pulling connections off the listen backlog and the kernel queues (and
+/// eventually refuses) new clients for us. With `Reject` we accept first and only then
+/// try for a permit, so we can tell the client why we're hanging up.
+async fn accept_with_permit<L: Listener>(
+    listener: &mut L,
+    limiter: Option<&Arc<Semaphore>>,
+    when_full: OverloadPolicy,
+) -> io::Result<Accepted<L::Stream>> {
+    let accepted = |socket, peer_addr, admission| Accepted {
+        socket,
+        peer_addr,
+        admission,
+    };
+    let Some(limiter) = limiter else {
+        let (socket, peer_addr) = listener.accept().await?;
+        return Ok(accepted(socket, peer_addr, Admission::Admitted(None)));
+    };
+
+    match when_full {
+        OverloadPolicy::Wait => {
+            let permit = limiter.clone().acquire_owned().await.expect("connection limiter is never closed");
+            let (socket, peer_addr) = listener.accept().await?;
+            Ok(accepted(socket, peer_addr, Admission::Admitted(Some(permit))))
+        }
+        OverloadPolicy::Reject => {
+            let (socket, peer_addr) = listener.accept().await?;
+            let slot = match limiter.clone().try_acquire_owned() {
+                Ok(permit) => Admission::Admitted(Some(permit)),
+                Err(_) => Admission::Busy,
+            };
+            Ok(accepted(socket, peer_addr, slot))
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! A limit on how fast new connections are admitted, shared by every accept loop.
+//!
+//! The per-connection [`rate_limit`](crate::rate_limit) budgets only help once a
+//! connection exists. A flood of new connections costs us before any of them has sent a
+//! byte: a task, buffers, a TLS handshake. So the accept loops also share a leaky
+//! bucket: admitted connections pour in, the bucket drains at `accept_rate` a second,
+//! and it holds `accept_burst`. While it's full an accept loop doesn't call `accept` at
+//! all. New clients wait in the kernel's listen backlog, and once that's full too the
+//! kernel refuses them, without us spending anything on them.
+//!
+//! The bucket is kept as a single instant (the "theoretical arrival time" of the
+//! generic cell rate algorithm): when it would be empty if nothing else came in. Each
+//! admission pushes that point back by one interval, and a new connection fits as long
+//! as it's no more than the burst allowance in the future. No timer drains anything;
+//! the clock does.
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::{Instant, sleep_until};
+
+/// Admits at most `rate` connections a second, in bursts of up to `burst`.
+#[derive(Debug)]
+pub(crate) struct AcceptRate {
+    /// The time one admission takes to drain out of the bucket.
+    interval: Duration,
+    /// How far ahead of now the bucket may be before it counts as full.
+    tolerance: Duration,
+    /// When the bucket will be empty.
+    empty_at: Mutex<Instant>,
+}
+
+impl AcceptRate {
+    pub(crate) fn new(rate: u32, burst: u32) -> Self {
+        let interval = Duration::from_secs(1) / rate.max(1);
+        Self {
+            interval,
+            tolerance: interval * burst.max(1).saturating_sub(1),
+            empty_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until there's room for one more connection.
+    ///
+    /// This only looks; [`admit`](Self::admit) takes the room once a connection has
+    /// actually arrived. Otherwise an accept loop parked in `accept` on a quiet listener
+    /// would be holding a slot nobody is using. Two loops can both see room for one
+    /// connection and both admit one, which overshoots by a connection and then
+    /// corrects itself, since both admissions count.
+    pub(crate) async fn ready(&self) {
+        loop {
+            let at = self.ready_at();
+            if at <= Instant::now() {
+                return;
+            }
+            sleep_until(at).await;
+        }
+    }
+
+    /// Counts a connection that was just accepted.
+    pub(crate) fn admit(&self) {
+        self.admit_at(Instant::now());
+    }
+
+    fn ready_at(&self) -> Instant {
+        let empty_at = *self.empty_at.lock().unwrap();
+        // `checked_sub` because an `Instant` can't go before whenever the clock began.
+        empty_at.checked_sub(self.tolerance).unwrap_or(empty_at)
+    }
+
+    fn admit_at(&self, now: Instant) {
+        let mut empty_at = self.empty_at.lock().unwrap();
+        *empty_at = (*empty_at).max(now) + self.interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_one_per_interval() {
+        let rate = AcceptRate::new(10, 3);
+        let now = Instant::now();
+        *rate.empty_at.lock().unwrap() = now;
+
+        // Three go through at once...
+        for _ in 0..3 {
+            assert!(rate.ready_at() <= now);
+            rate.admit_at(now);
+        }
+        // ...and the fourth waits for one to drain out.
+        assert_eq!(rate.ready_at(), now + Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_a_quiet_spell_empties_the_bucket() {
+        let rate = AcceptRate::new(10, 2);
+        let start = Instant::now();
+        *rate.empty_at.lock().unwrap() = start;
+        rate.admit_at(start);
+        rate.admit_at(start);
+        assert!(rate.ready_at() > start);
+
+        // Long after, the old admissions count for nothing: a full burst again.
+        let later = start + Duration::from_secs(5);
+        assert!(rate.ready_at() <= later);
+        rate.admit_at(later);
+        assert!(rate.ready_at() <= later);
+    }
+}
@@ -0,0 +1,106 @@
+//! systemd socket activation: listening on sockets we inherited instead of bound.
+//!
+//! With a `.socket` unit, systemd binds the port itself and starts the service on the
+//! first connection (or at boot), passing the listening sockets as already-open file
+//! descriptors starting at 3. `LISTEN_FDS` says how many there are and `LISTEN_PID`
+//! which process they're meant for; a child we spawned would inherit the variables but
+//! not match the pid, so it won't mistake unrelated descriptors for listeners.
+//!
+//! Nothing here is tokio-specific until the end: the descriptor becomes a
+//! `std::net::TcpListener`, is switched to non-blocking (tokio's reactor needs that,
+//! and systemd hands sockets over blocking), and only then goes to
+//! `TcpListener::from_std`. The payoff is that systemd keeps the socket open across
+//! restarts, so connections that arrive while we drain and come back up wait in the
+//! backlog instead of being refused.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::net::TcpListener;
+
+/// The first inherited descriptor, after stdin, stdout and stderr.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Set once the descriptors have been claimed, so a second server in the same process
+/// can't end up owning (and closing) the same ones.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Takes the listeners systemd passed in, or returns `None` if we weren't started by
+/// socket activation (or they were already taken).
+pub(crate) fn systemd_listeners() -> io::Result<Option<Vec<TcpListener>>> {
+    let count = listen_fds(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    )?;
+    let Some(count) = count else {
+        return Ok(None);
+    };
+    if TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(None);
+    }
+    adopt(count).map(Some)
+}
+
+/// Works out how many descriptors are ours from the two environment variables.
+fn listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, own_pid: u32) -> io::Result<Option<u32>> {
+    let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) else {
+        return Ok(None);
+    };
+    if listen_pid.parse::<u32>().ok() != Some(own_pid) {
+        return Ok(None);
+    }
+    let count: u32 = listen_fds
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("bad LISTEN_FDS value {listen_fds:?}")))?;
+    Ok((count > 0).then_some(count))
+}
+
+#[cfg(target_os = "linux")]
+fn adopt(count: u32) -> io::Result<Vec<TcpListener>> {
+    use socket2::{Socket, Type};
+    use std::os::fd::{FromRawFd, RawFd};
+
+    (0..count)
+        .map(|i| {
+            let fd = LISTEN_FDS_START + i as RawFd;
+            // SAFETY: systemd passed us this descriptor for our pid, and `TAKEN` makes
+            // sure it's wrapped only once, so nothing else owns it.
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            if socket.r#type()? != Type::STREAM || !socket.is_listener()? {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("inherited fd {fd} is not a listening TCP socket"),
+                ));
+            }
+            // Inherited descriptors don't have close-on-exec set; without it our own
+            // child processes would keep the port open.
+            socket.set_cloexec(true)?;
+            socket.set_nonblocking(true)?;
+            TcpListener::from_std(socket.into())
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn adopt(_count: u32) -> io::Result<Vec<TcpListener>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "socket activation is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_fds_only_counts_for_our_pid() {
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42).unwrap(), Some(2));
+        assert_eq!(listen_fds(Some("41"), Some("2"), 42).unwrap(), None);
+        assert_eq!(listen_fds(None, Some("2"), 42).unwrap(), None);
+        assert_eq!(listen_fds(Some("42"), None, 42).unwrap(), None);
+        assert_eq!(listen_fds(Some("42"), Some("0"), 42).unwrap(), None);
+        assert!(listen_fds(Some("42"), Some("two"), 42).is_err());
+    }
+}
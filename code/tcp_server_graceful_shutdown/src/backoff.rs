@@ -0,0 +1,93 @@
+//! Backing off when `accept` keeps failing.
+//!
+//! Most accept errors are about the process, not the client: out of file descriptors
+//! (`EMFILE`), out of memory, and so on. Retrying straight away just fails again, and
+//! the accept loop spins a core at 100% while printing the same error. Waiting a little
+//! longer after each failure gives the server time to close sockets and recover.
+
+use rand::Rng;
+use std::time::Duration;
+
+/// The first retry waits up to this long.
+const BASE_DELAY: Duration = Duration::from_millis(5);
+/// No retry waits longer than this.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Tracks consecutive accept failures and how long to wait before the next attempt.
+#[derive(Debug)]
+pub(crate) struct AcceptBackoff {
+    failures: u32,
+    max_failures: Option<u32>,
+}
+
+impl AcceptBackoff {
+    /// Gives up after `max_failures` failures in a row, or never if `None`.
+    pub(crate) fn new(max_failures: Option<u32>) -> Self {
+        Self { failures: 0, max_failures }
+    }
+
+    /// Records a failure and returns how long to wait, or `None` once we should give up.
+    ///
+    /// The ceiling doubles with every failure up to `MAX_DELAY`, and the actual delay is
+    /// picked at random between half the ceiling and the ceiling ("equal jitter"), so
+    /// several servers hitting the same limit don't all retry in lockstep.
+    pub(crate) fn on_error(&mut self) -> Option<Duration> {
+        self.failures += 1;
+        if self.max_failures.is_some_and(|max| self.failures >= max) {
+            return None;
+        }
+        let ceiling = BASE_DELAY.saturating_mul(1 << (self.failures - 1).min(16)).min(MAX_DELAY);
+        let half = ceiling / 2;
+        Some(half + rand::rng().random_range(Duration::ZERO..=half))
+    }
+
+    /// Records a success and returns how many failures preceded it, if any.
+    pub(crate) fn on_success(&mut self) -> Option<u32> {
+        let failures = std::mem::take(&mut self.failures);
+        (failures > 0).then_some(failures)
+    }
+
+    /// The current run of consecutive failures.
+    pub(crate) fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_grows_and_is_capped() {
+        let mut backoff = AcceptBackoff::new(None);
+        let first = backoff.on_error().unwrap();
+        assert!(first >= BASE_DELAY / 2 && first <= BASE_DELAY);
+
+        let mut previous_ceiling = BASE_DELAY;
+        for _ in 0..30 {
+            let delay = backoff.on_error().unwrap();
+            let ceiling = (previous_ceiling * 2).min(MAX_DELAY);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{delay:?} outside {ceiling:?}");
+            previous_ceiling = ceiling;
+        }
+    }
+
+    #[test]
+    fn test_gives_up_after_max_failures() {
+        let mut backoff = AcceptBackoff::new(Some(3));
+        assert!(backoff.on_error().is_some());
+        assert!(backoff.on_error().is_some());
+        assert!(backoff.on_error().is_none());
+    }
+
+    #[test]
+    fn test_success_resets_the_count() {
+        let mut backoff = AcceptBackoff::new(Some(3));
+        assert_eq!(backoff.on_success(), None);
+        backoff.on_error();
+        backoff.on_error();
+        assert_eq!(backoff.on_success(), Some(2));
+        assert_eq!(backoff.failures(), 0);
+        assert!(backoff.on_error().is_some());
+    }
+}
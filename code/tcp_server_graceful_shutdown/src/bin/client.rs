@@ -0,0 +1,112 @@
+use clap::Parser;
+use shutdown_util::cli::parse_secs;
+use std::io;
+use std::net::SocketAddr;
+use tcp_server_graceful_shutdown::Framing;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::client::{ProxyHeaderOptions, connect, run_client, run_slow_client, run_tls_client};
+use tcp_server_graceful_shutdown::proxy::ProxyVersion;
+use tcp_server_graceful_shutdown::{telemetry, tls};
+use throttled_stream::{Throttle, ThrottledStream};
+use tokio::task::JoinSet;
+use tracing::{Instrument, info_span};
+use tracing_subscriber::prelude::*;
+
+/// Sends messages to the graceful shutdown echo server and prints the replies.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address of the echo server.
+    #[arg(long, default_value = "127.0.0.1:3011")]
+    addr: SocketAddr,
+    /// Framing the server was started with.
+    #[arg(long, value_enum, default_value_t = Framing::Raw)]
+    framing: Framing,
+    /// Message to send.
+    #[arg(long, default_value = "hello")]
+    message: String,
+    /// How many times each client sends the message.
+    #[arg(long, default_value_t = 1)]
+    count: usize,
+    /// Seconds to wait between messages. With --framing lines the client keeps answering
+    /// the server's heartbeat PINGs meanwhile.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    pause: Option<Duration>,
+    /// How many clients run at the same time, each on its own connection.
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+    /// Flood the server without reading the echoes, to trip its write timeout.
+    #[arg(long)]
+    slow: bool,
+    /// With --slow, read one buffer every SECS instead of never.
+    #[arg(long, value_name = "SECS", requires = "slow", value_parser = parse_secs)]
+    read_delay: Option<Duration>,
+    /// Connect over TLS, trusting this PEM certificate (the server's --tls-ca-out).
+    #[arg(long, value_name = "PATH", conflicts_with = "slow")]
+    tls_ca: Option<PathBuf>,
+    /// Present this certificate and key for mutual TLS (the server's --tls-client-out).
+    #[arg(long, value_name = "PATH", requires = "tls_ca")]
+    tls_identity: Option<PathBuf>,
+    /// Start each connection with a PROXY header, for a server run with --proxy-protocol.
+    #[arg(long, value_name = "VERSION", value_enum, conflicts_with_all = ["slow", "tls_ca"])]
+    proxy_header: Option<ProxyVersion>,
+    /// The client address the PROXY header claims (default: our real address).
+    #[arg(long, value_name = "ADDR", requires = "proxy_header")]
+    proxy_source: Option<SocketAddr>,
+    /// Simulate a slow network: everything the server sends takes SECS longer to arrive.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, conflicts_with_all = ["slow", "tls_ca"])]
+    latency: Option<Duration>,
+    /// Simulate a slow network: move at most BYTES a second in each direction.
+    #[arg(long, value_name = "BYTES", conflicts_with_all = ["slow", "tls_ca"])]
+    bandwidth: Option<u64>,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    // No terminal layer: the replies printed below are the output. With the `otel`
+    // feature each client's connect and requests are exported as a trace.
+    let (otel, _telemetry) = telemetry::layer("echo_client");
+    tracing_subscriber::registry().with(otel).init();
+    let cli = Cli::parse();
+    let ca = cli.tls_ca.as_deref().map(tls::load_cert).transpose()?;
+    let identity = cli.tls_identity.as_deref().map(tls::load_identity).transpose()?.map(Arc::new);
+    let throttle = Throttle {
+        latency: cli.latency.unwrap_or_default(),
+        read_bytes_per_sec: cli.bandwidth,
+        write_bytes_per_sec: cli.bandwidth,
+    };
+
+    let mut clients = JoinSet::new();
+    for i in 1..=cli.concurrency {
+        let name = format!("client-{i}");
+        let msg = format!("{} from {name}", cli.message);
+        let (addr, count, framing, pause) = (cli.addr, cli.count, cli.framing, cli.pause);
+        let proxy = cli.proxy_header.map(|version| ProxyHeaderOptions {
+            version,
+            source: cli.proxy_source,
+        });
+        let (slow, read_delay) = (cli.slow, cli.read_delay);
+        let (ca, identity) = (ca.clone(), identity.clone());
+        let span = info_span!("client", %name);
+        clients.spawn(async move {
+            let result = if slow {
+                run_slow_client(&name, addr, msg.as_bytes(), read_delay).await.map(drop)
+            } else if let Some(ca) = ca {
+                run_tls_client(&name, addr, msg.as_bytes(), count, &ca, identity.as_deref()).await
+            } else {
+                let socket = connect(addr, proxy).await.map(|socket| ThrottledStream::new(socket, throttle));
+                match socket {
+                    Ok(socket) => run_client(&name, socket, msg.as_bytes(), count, framing, pause).await,
+                    Err(e) => Err(e),
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("[{name}] error: {e}");
+            }
+        }.instrument(span));
+    }
+    clients.join_all().await;
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! Cancellation safety, shown with the smallest protocol where it bites: fixed-size
+//! records, echoed back one at a time.
+//!
+//! Every handler in this crate races its reads against the shutdown signal in a
+//! `select!`. Whenever another branch wins, the read's future is dropped, and whatever
+//! it had done so far goes with it. For `read` and `read_buf` that's nothing: they
+//! either complete with data or haven't touched anything yet, so dropping one costs
+//! nothing. Tokio's docs call that *cancellation safe*. `read_exact` is not: it makes
+//! several reads to fill its buffer, and its progress lives in the future. Drop it
+//! halfway and the bytes it already took off the socket are gone. The next call starts
+//! from the beginning of the buffer with whatever comes after them, and every record
+//! from there on is off by the lost bytes.
+//!
+//! It takes two things to go wrong together, which is why it survives testing: a
+//! record that arrives in more than one piece, and another branch firing in between.
+//! Over loopback the first hardly ever happens. Over a real network, with a heartbeat
+//! in the `select!`, it happens the first time a record straddles two packets.
+//!
+//! [`echo_records_broken`] has the bug and [`echo_records`] the usual fix: the data
+//! lives in a buffer owned by the loop, not by the future. `read_buf` appends to it,
+//! and whole records are taken off the front. When a read is cancelled, the buffer
+//! still holds everything that arrived. (The other fix is to create the `read_exact`
+//! future once, outside the loop, and poll the same pinned future on every pass, so
+//! it's never dropped; that works for one read, but not once the handler also has to
+//! write between reads.)
+
+use bytes::BytesMut;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tokio::time::{MissedTickBehavior, interval};
+
+/// How long a record is.
+pub const RECORD_LEN: usize = 8;
+
+/// Echoes `RECORD_LEN`-byte records until the peer hangs up or shutdown is signalled,
+/// and loses data whenever `tick` fires halfway through a record.
+///
+/// The tick stands in for the heartbeat, idle check or stats flush that a real handler
+/// has in its `select!`. Anything that wins the race will do, including shutdown.
+pub async fn echo_records_broken<S>(mut socket: S, mut shutdown_rx: broadcast::Receiver<()>, tick: Duration) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut ticks = interval(tick);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut record = [0_u8; RECORD_LEN];
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(()),
+            _ = ticks.tick() => {}
+            // Wrong: a tick that arrives after the first half of a record drops this
+            // future, and the half with it.
+            read = socket.read_exact(&mut record) => match read {
+                Ok(_) => socket.write_all(&record).await?,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            },
+        }
+    }
+}
+
+/// Echoes `RECORD_LEN`-byte records, like [`echo_records_broken`] but without the bug:
+/// a partial record waits in a buffer that outlives every cancelled read.
+pub async fn echo_records<S>(mut socket: S, mut shutdown_rx: broadcast::Receiver<()>, tick: Duration) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut ticks = interval(tick);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut buf = BytesMut::with_capacity(RECORD_LEN * 16);
+    loop {
+        while buf.len() >= RECORD_LEN {
+            let record = buf.split_to(RECORD_LEN);
+            socket.write_all(&record).await?;
+        }
+        tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(()),
+            _ = ticks.tick() => {}
+            // Cancellation safe: if the tick wins, nothing was read.
+            read = socket.read_buf(&mut buf) => {
+                if read? == 0 {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+    use tokio::time::{sleep, timeout};
+
+    const TICK: Duration = Duration::from_millis(5);
+
+    /// Sends two records with a pause in the middle of the first, long enough for
+    /// several ticks, and returns the first record that comes back.
+    async fn first_echo(mut client: DuplexStream) -> [u8; RECORD_LEN] {
+        client.write_all(b"reco").await.unwrap();
+        sleep(TICK * 10).await;
+        client.write_all(b"rd-1record-2").await.unwrap();
+
+        let mut echo = [0_u8; RECORD_LEN];
+        timeout(Duration::from_secs(1), client.read_exact(&mut echo)).await.unwrap().unwrap();
+        echo
+    }
+
+    #[tokio::test]
+    async fn test_read_exact_inside_select_loses_half_a_record() {
+        let (client, server) = tokio::io::duplex(64);
+        let (_tx, shutdown_rx) = broadcast::channel(1);
+        tokio::spawn(echo_records_broken(server, shutdown_rx, TICK));
+
+        // "reco" went down with a cancelled `read_exact`; every record after it is
+        // shifted by four bytes.
+        assert_eq!(&first_echo(client).await, b"rd-1reco");
+    }
+
+    #[tokio::test]
+    async fn test_a_buffer_owned_by_the_loop_keeps_half_a_record() {
+        let (client, server) = tokio::io::duplex(64);
+        let (_tx, shutdown_rx) = broadcast::channel(1);
+        tokio::spawn(echo_records(server, shutdown_rx, TICK));
+
+        assert_eq!(&first_echo(client).await, b"record-1");
+    }
+}
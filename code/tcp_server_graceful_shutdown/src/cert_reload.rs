@@ -0,0 +1,70 @@
+//! Picks up a rotated certificate without restarting the server.
+//!
+//! A background task polls the certificate and key files and, when either changes,
+//! loads them into a fresh `rustls::ServerConfig` and publishes it on a `watch`
+//! channel. The accept loop reads the latest value for every new connection, so:
+//!
+//! - connections that are already up keep the certificate they were handshaken with;
+//! - there's no lock on the hot path, just a `borrow` and an `Arc` clone;
+//! - a file that doesn't parse (say, caught half-written) is logged and skipped, and
+//!   the previous certificate stays in use until the next change.
+//!
+//! `watch` fits because only the newest value matters: if the files change twice
+//! between two accepts, nobody needs to see the intermediate certificate.
+
+use crate::config::CertFiles;
+use crate::{tasks, tls};
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio::time::{MissedTickBehavior, interval};
+use tokio_rustls::rustls;
+use tracing::{info, warn};
+
+/// What we compare between polls. Size as well as mtime, since a coarse mtime can
+/// miss a rewrite that lands in the same tick.
+type Fingerprint = [Option<(SystemTime, u64)>; 2];
+
+/// Loads `files` and spawns a task that republishes them whenever they change. The
+/// task stops once every receiver is gone.
+pub(crate) fn watch_cert_files(
+    files: CertFiles,
+    poll_interval: Duration,
+) -> io::Result<watch::Receiver<Arc<rustls::ServerConfig>>> {
+    let mut seen = fingerprint(&files);
+    let (tx, rx) = watch::channel(tls::load_server_config(&files)?);
+
+    // Blocking: `fingerprint` and the reload use `std::fs`.
+    tasks::spawn("tls cert reloader (blocks on std::fs each tick)", async move {
+        let mut ticks = interval(poll_interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticks.tick().await;
+        loop {
+            tokio::select! {
+                () = tx.closed() => return,
+                _ = ticks.tick() => {}
+            }
+            let current = fingerprint(&files);
+            if current == seen {
+                continue;
+            }
+            seen = current;
+            match tls::load_server_config(&files) {
+                Ok(config) => {
+                    tx.send_replace(config);
+                    info!(cert = %files.cert.display(), "reloaded the certificate");
+                }
+                Err(e) => warn!(error = %e, "keeping the previous certificate"),
+            }
+        }
+    });
+    Ok(rx)
+}
+
+fn fingerprint(files: &CertFiles) -> Fingerprint {
+    [&files.cert, &files.key].map(|path| {
+        let meta = std::fs::metadata(path).ok()?;
+        Some((meta.modified().ok()?, meta.len()))
+    })
+}
@@ -0,0 +1,187 @@
+//! Chaos mode: the server misbehaves on purpose, now and then.
+//!
+//! Timeouts and error paths are the parts of a network program that run least often,
+//! so they're the parts most likely to be wrong. With chaos on, every connection's
+//! socket is wrapped in a [`ChaosStream`] that sometimes:
+//!
+//! - stalls a read for a while, as if the network went quiet (try it against
+//!   `--idle-timeout` and a client's read timeout);
+//! - accepts only part of a write, which is always allowed and which code that assumes
+//!   one `write` per message gets wrong;
+//! - resets the connection: `SO_LINGER` set to zero, then closed, so the peer gets an
+//!   RST instead of a FIN and sees "connection reset by peer".
+//!
+//! and new connections are sometimes left waiting before anyone serves them, as if
+//! the accept queue were backed up.
+//!
+//! The wrapper sits below TLS and the framing codecs, so they see the faults the way
+//! they would see them from a real network. Chaos can be switched on and off while the
+//! server runs with `CHAOS ON` and `CHAOS OFF` on the control socket; the wrapper reads
+//! the switch on every operation, so open connections follow it too.
+
+use crate::config::ChaosConfig;
+use crate::listener::Connection;
+use rand::Rng;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Sleep, sleep};
+use tracing::{info, warn};
+
+/// The fault probabilities and the switch that turns them on, shared by every
+/// connection.
+#[derive(Debug)]
+pub(crate) struct Chaos {
+    enabled: AtomicBool,
+    config: ChaosConfig,
+}
+
+impl Chaos {
+    pub(crate) fn new(config: ChaosConfig) -> Self {
+        Self {
+            enabled: AtomicBool::new(config.enabled),
+            config,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The reply to `CHAOS` on the control socket.
+    pub(crate) fn describe(&self) -> String {
+        let ChaosConfig { read_stall, stall, partial_write, reset, delayed_accept, accept_delay, .. } = &self.config;
+        format!(
+            "chaos {} read_stall={read_stall} ({stall:?}) partial_write={partial_write} reset={reset} delayed_accept={delayed_accept} ({accept_delay:?})",
+            if self.is_enabled() { "on" } else { "off" },
+        )
+    }
+
+    /// How long to hold a new connection back, if this one is unlucky.
+    pub(crate) fn accept_delay(&self) -> Option<Duration> {
+        self.roll(self.config.delayed_accept).then_some(self.config.accept_delay)
+    }
+
+    /// `true` with probability `p`, while chaos is on.
+    fn roll(&self, p: f64) -> bool {
+        self.is_enabled() && p > 0.0 && rand::rng().random_bool(p.min(1.0))
+    }
+}
+
+/// A connection that injects faults while chaos is on.
+pub(crate) struct ChaosStream<S> {
+    inner: S,
+    chaos: Arc<Chaos>,
+    /// Whether this read has already had its chance of a fault. A read is polled again
+    /// and again until data arrives; without this every poll would be a new roll.
+    rolled: bool,
+    stall: Option<Pin<Box<Sleep>>>,
+    reset: bool,
+}
+
+impl<S: Connection> ChaosStream<S> {
+    pub(crate) fn new(inner: S, chaos: Arc<Chaos>) -> Self {
+        Self {
+            inner,
+            chaos,
+            rolled: false,
+            stall: None,
+            reset: false,
+        }
+    }
+
+    fn reset_error() -> io::Error {
+        io::Error::new(io::ErrorKind::ConnectionReset, "chaos: injected reset")
+    }
+
+    /// Makes the close an RST, where the stream has a socket to reset, and fails every
+    /// operation from now on.
+    fn inject_reset(&mut self) -> io::Error {
+        info!("chaos: resetting the connection");
+        if let Err(e) = self.inner.reset_on_close() {
+            warn!(error = %e, "chaos: couldn't set SO_LINGER");
+        }
+        self.reset = true;
+        Self::reset_error()
+    }
+}
+
+impl<S: Connection> AsyncRead for ChaosStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.reset {
+            return Poll::Ready(Err(Self::reset_error()));
+        }
+        if !this.rolled {
+            this.rolled = true;
+            if this.chaos.roll(this.chaos.config.reset) {
+                return Poll::Ready(Err(this.inject_reset()));
+            }
+            if this.chaos.roll(this.chaos.config.read_stall) {
+                info!(stall = ?this.chaos.config.stall, "chaos: stalling a read");
+                this.stall = Some(Box::pin(sleep(this.chaos.config.stall)));
+            }
+        }
+        if let Some(stall) = &mut this.stall {
+            ready!(stall.as_mut().poll(cx));
+            this.stall = None;
+        }
+        let result = ready!(Pin::new(&mut this.inner).poll_read(cx, buf));
+        this.rolled = false;
+        Poll::Ready(result)
+    }
+}
+
+impl<S: Connection> AsyncWrite for ChaosStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.reset {
+            return Poll::Ready(Err(Self::reset_error()));
+        }
+        let len = if buf.len() > 1 && this.chaos.roll(this.chaos.config.partial_write) {
+            // Whoever called us has to come back for the rest.
+            rand::rng().random_range(1..buf.len())
+        } else {
+            buf.len()
+        };
+        Pin::new(&mut this.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.reset {
+            return Poll::Ready(Err(Self::reset_error()));
+        }
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nothing_happens_while_chaos_is_off() {
+        let chaos = Chaos::new(ChaosConfig {
+            delayed_accept: 1.0,
+            ..ChaosConfig::default()
+        });
+        assert_eq!(chaos.accept_delay(), None);
+        chaos.set_enabled(true);
+        assert_eq!(chaos.accept_delay(), Some(ChaosConfig::default().accept_delay));
+        assert!(chaos.describe().starts_with("chaos on "));
+    }
+}
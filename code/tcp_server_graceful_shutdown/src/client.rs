@@ -0,0 +1,420 @@
+use crate::config::Framing;
+use crate::proxy::{ProxyHeader, ProxyVersion};
+use crate::tls::{self, Identity};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::sleep;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_util::codec::{Framed, LengthDelimitedCodec, LinesCodec, LinesCodecError};
+use tracing::instrument;
+
+/// A single connection to the echo server, over plain TCP or (with
+/// [`connect_tls`](EchoClient::connect_tls)) over TLS.
+pub struct EchoClient<S = TcpStream> {
+    socket: S,
+}
+
+impl EchoClient {
+    /// Connects to the echo server at `addr`.
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let socket = TcpStream::connect(addr).await?;
+        Ok(Self::new(socket))
+    }
+}
+
+impl EchoClient<TlsStream<TcpStream>> {
+    /// Connects to a server started with `--tls`, trusting the certificate `ca`. Pass an
+    /// `identity` to present when the server runs with `--mtls`.
+    pub async fn connect_tls(addr: SocketAddr, ca: &CertificateDer<'static>, identity: Option<&Identity>) -> io::Result<Self> {
+        let connector = tls::connector(ca, identity)?;
+        let socket = TcpStream::connect(addr).await?;
+        // The demo server certificate lists the loopback IPs, so the address we
+        // dialled doubles as the name to check it against.
+        let socket = connector.connect(ServerName::IpAddress(addr.ip().into()), socket).await?;
+        Ok(Self::new(socket))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> EchoClient<S> {
+    /// Wraps a stream that's already connected, e.g. one that has sent a PROXY header.
+    pub fn new(socket: S) -> Self {
+        Self { socket }
+    }
+
+    /// Sends `msg` and waits until the same number of bytes has come back.
+    ///
+    /// TCP is a byte stream, so the echo may arrive split across several reads; we keep
+    /// reading until we have it all. If the server closes the connection first (for
+    /// example because it is shutting down), whatever did arrive is returned.
+    #[instrument(skip_all, fields(len = msg.len()))]
+    pub async fn echo(&mut self, msg: &[u8]) -> io::Result<Vec<u8>> {
+        self.socket.write_all(msg).await?;
+
+        let mut reply = Vec::with_capacity(msg.len());
+        let mut buf = [0_u8; 1024];
+        while reply.len() < msg.len() {
+            let n = self.socket.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            reply.extend_from_slice(&buf[..n]);
+        }
+        Ok(reply)
+    }
+
+    /// Gives back the underlying stream, e.g. to wait for the server's farewell message.
+    pub fn into_inner(self) -> S {
+        self.socket
+    }
+}
+
+/// A connection to a server running with `Framing::Length`.
+pub struct FramedEchoClient<S = TcpStream> {
+    frames: Framed<S, LengthDelimitedCodec>,
+}
+
+impl FramedEchoClient {
+    /// Connects to the echo server at `addr`.
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let socket = TcpStream::connect(addr).await?;
+        Ok(Self::new(socket))
+    }
+
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> FramedEchoClient<S> {
+    /// Wraps a socket that's already connected.
+    pub fn new(socket: S) -> Self {
+        Self {
+            frames: Framed::new(socket, LengthDelimitedCodec::new()),
+        }
+    }
+
+    /// Sends `msg` as one frame and waits for the echoed frame.
+    ///
+    /// No "keep reading until we have enough" loop needed here: the codec only hands
+    /// us complete frames.
+    #[instrument(skip_all, fields(len = msg.len()))]
+    pub async fn echo(&mut self, msg: &[u8]) -> io::Result<Vec<u8>> {
+        self.frames.send(Bytes::copy_from_slice(msg)).await?;
+        self.next_message()
+            .await
+            .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection")))
+    }
+
+    /// Waits for the next frame from the server, or `None` once it hangs up.
+    pub async fn next_message(&mut self) -> Option<io::Result<Vec<u8>>> {
+        self.frames.next().await.map(|frame| frame.map(|frame| frame.to_vec()))
+    }
+}
+
+/// A connection to a server running with `Framing::Lines`.
+pub struct LineEchoClient<S = TcpStream> {
+    lines: Framed<S, LinesCodec>,
+    goaway: Option<Duration>,
+}
+
+impl LineEchoClient {
+    /// Connects to the echo server at `addr`.
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let socket = TcpStream::connect(addr).await?;
+        Ok(Self::new(socket))
+    }
+
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> LineEchoClient<S> {
+    /// Wraps a socket that's already connected.
+    pub fn new(socket: S) -> Self {
+        Self {
+            lines: Framed::new(socket, LinesCodec::new()),
+            goaway: None,
+        }
+    }
+
+    /// Sends `line` (a newline is added) and waits for the server's reply line.
+    ///
+    /// A `GOAWAY` notice that arrives first is noted (see [`goaway`](Self::goaway)) and
+    /// skipped: the server still answers what we sent before we heard it. So is a
+    /// heartbeat `PING`, after answering it.
+    #[instrument(skip_all, fields(len = line.len()))]
+    pub async fn echo(&mut self, line: &str) -> io::Result<String> {
+        self.lines.send(line).await.map_err(lines_error)?;
+        loop {
+            let reply = self
+                .next_line()
+                .await
+                .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection")))?;
+            if !self.handle_notice(&reply).await? {
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// Stays connected for `duration` without sending anything of our own, answering
+    /// heartbeats. Returns early, with the line, if the server says anything else.
+    pub async fn wait(&mut self, duration: Duration) -> io::Result<Option<String>> {
+        let deadline = sleep(duration);
+        tokio::pin!(deadline);
+        loop {
+            let line = tokio::select! {
+                () = &mut deadline => return Ok(None),
+                line = self.next_line() => line,
+            };
+            let line = line.unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection")))?;
+            if !self.handle_notice(&line).await? {
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    /// Deals with a line that isn't a reply: notes a `GOAWAY`, answers a `PING`.
+    /// Returns whether `line` was one of those.
+    async fn handle_notice(&mut self, line: &str) -> io::Result<bool> {
+        if line == "PING" {
+            self.lines.send("PONG").await.map_err(lines_error)?;
+            return Ok(true);
+        }
+        match parse_goaway(line) {
+            Some(deadline) => {
+                self.goaway = Some(deadline);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The deadline from the server's `GOAWAY`, once one has arrived during an echo. From
+    /// then on the polite thing is to stop sending new work and call [`bye`](Self::bye).
+    pub fn goaway(&self) -> Option<Duration> {
+        self.goaway
+    }
+
+    /// Answers a `GOAWAY`: tells the server we're done and waits for it to hang up.
+    #[instrument(skip_all)]
+    pub async fn bye(&mut self) -> io::Result<()> {
+        self.lines.send("BYE").await.map_err(lines_error)?;
+        while let Some(line) = self.next_line().await {
+            line?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the next line from the server, or `None` once it hangs up.
+    pub async fn next_line(&mut self) -> Option<io::Result<String>> {
+        self.lines.next().await.map(|line| line.map_err(lines_error))
+    }
+}
+
+/// Recognises the server's `GOAWAY <ms>` notice.
+fn parse_goaway(line: &str) -> Option<Duration> {
+    let ms = line.strip_prefix("GOAWAY ")?.parse().ok()?;
+    Some(Duration::from_millis(ms))
+}
+
+fn lines_error(e: LinesCodecError) -> io::Error {
+    match e {
+        LinesCodecError::Io(e) => e,
+        LinesCodecError::MaxLineLengthExceeded => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// The PROXY header a client sends first, pretending to be a load balancer.
+#[derive(Debug, Clone, Copy)]
+pub struct ProxyHeaderOptions {
+    /// Which format to send.
+    pub version: ProxyVersion,
+    /// The client address to claim, or `None` for our real one.
+    pub source: Option<SocketAddr>,
+}
+
+/// Connects to `addr` and sends a PROXY header, as a balancer would, before handing
+/// the socket back.
+pub async fn connect_with_proxy_header(addr: SocketAddr, options: ProxyHeaderOptions) -> io::Result<TcpStream> {
+    let mut socket = TcpStream::connect(addr).await?;
+    let header = ProxyHeader {
+        source: options.source.unwrap_or(socket.local_addr()?),
+        destination: addr,
+    };
+    socket.write_all(&header.encode(options.version)).await?;
+    Ok(socket)
+}
+
+/// Like [`run_client`] with `Framing::Raw`, but over TLS, trusting `ca` and presenting
+/// `identity` if the server asks for a client certificate.
+pub async fn run_tls_client(
+    name: &str,
+    addr: SocketAddr,
+    msg: &[u8],
+    count: usize,
+    ca: &CertificateDer<'static>,
+    identity: Option<&Identity>,
+) -> io::Result<()> {
+    let mut client = EchoClient::connect_tls(addr, ca, identity).await?;
+    for _ in 0..count {
+        let reply = client.echo(msg).await?;
+        println!("[{name}] received over TLS: {}", String::from_utf8_lossy(&reply));
+    }
+    Ok(())
+}
+
+/// Connects to `addr`, starting with a PROXY header if `proxy` is set.
+#[instrument(skip(proxy))]
+pub async fn connect(addr: SocketAddr, proxy: Option<ProxyHeaderOptions>) -> io::Result<TcpStream> {
+    match proxy {
+        Some(options) => connect_with_proxy_header(addr, options).await,
+        None => TcpStream::connect(addr).await,
+    }
+}
+
+/// Echoes `msg` `count` times over `socket`, and prints each reply prefixed by `name`.
+/// With `pause` set, waits that long before each message after the first, answering
+/// heartbeats meanwhile in `Framing::Lines`.
+///
+/// `socket` is any stream, so the demo client can slow its connection down with a
+/// [`ThrottledStream`](throttled_stream::ThrottledStream) first.
+pub async fn run_client<S: AsyncRead + AsyncWrite + Unpin>(
+    name: &str,
+    socket: S,
+    msg: &[u8],
+    count: usize,
+    framing: Framing,
+    pause: Option<Duration>,
+) -> io::Result<()> {
+    let pause_before = |i: usize| pause.filter(|_| i > 0);
+    match framing {
+        // An auto-detecting server takes raw echo like any other protocol.
+        Framing::Raw | Framing::Auto => {
+            let mut client = EchoClient::new(socket);
+            for i in 0..count {
+                if let Some(pause) = pause_before(i) {
+                    sleep(pause).await;
+                }
+                let reply = client.echo(msg).await?;
+                println!("[{name}] received: {}", String::from_utf8_lossy(&reply));
+            }
+        }
+        Framing::Length => {
+            let mut client = FramedEchoClient::new(socket);
+            for i in 0..count {
+                if let Some(pause) = pause_before(i) {
+                    sleep(pause).await;
+                }
+                let reply = client.echo(msg).await?;
+                println!("[{name}] received frame: {}", String::from_utf8_lossy(&reply));
+            }
+        }
+        Framing::Lines => {
+            let mut client = LineEchoClient::new(socket);
+            let line = String::from_utf8_lossy(msg);
+            for i in 0..count {
+                if let Some(pause) = pause_before(i)
+                    && let Some(said) = client.wait(pause).await?
+                {
+                    println!("[{name}] server said: {said}");
+                }
+                let reply = client.echo(&line).await?;
+                println!("[{name}] received line: {reply}");
+                if let Some(deadline) = client.goaway() {
+                    println!("[{name}] server going away within {deadline:?}; stopping and saying BYE");
+                    client.bye().await?;
+                    break;
+                }
+            }
+        }
+        // Not an echo server in this mode, so `msg` is ignored and we poll `/health`
+        // over one keep-alive connection instead.
+        Framing::Http => {
+            let mut socket = BufReader::new(socket);
+            for i in 0..count {
+                if let Some(pause) = pause_before(i) {
+                    sleep(pause).await;
+                }
+                let (status, body) = http_get(&mut socket, "/health").await?;
+                println!("[{name}] GET /health: {status} {}", body.trim_end());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends one `GET` over a keep-alive connection and returns the status line and body.
+/// Only as much HTTP as talking to our own server needs: no chunked bodies, no redirects.
+#[instrument(skip(socket))]
+async fn http_get<S: AsyncRead + AsyncWrite + Unpin>(socket: &mut BufReader<S>, path: &str) -> io::Result<(String, String)> {
+    socket
+        .get_mut()
+        .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+        .await?;
+    let mut status = String::new();
+    if socket.read_line(&mut status).await? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection"));
+    }
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        socket.read_line(&mut header).await?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("bad Content-Length: {e}")))?;
+        }
+    }
+    let mut body = vec![0_u8; content_length];
+    socket.read_exact(&mut body).await?;
+    Ok((status.trim_end().to_string(), String::from_utf8_lossy(&body).into_owned()))
+}
+
+/// Keeps sending `msg` without reading the echoes (or reading one buffer every
+/// `read_delay`), until the server gives up on us.
+///
+/// The server's writes fill our receive buffer, then its own send buffer, and then
+/// block. That's when its write timeout fires and it drops the connection, which shows
+/// up here as a failed write. Returns how many bytes we managed to send.
+pub async fn run_slow_client(name: &str, addr: SocketAddr, msg: &[u8], read_delay: Option<Duration>) -> io::Result<u64> {
+    let socket = TcpStream::connect(addr).await?;
+    let (mut reader, mut writer) = socket.into_split();
+
+    let chunk = msg.repeat((16 * 1024 / msg.len().max(1)).max(1));
+    let writing = async {
+        let mut sent = 0_u64;
+        loop {
+            if let Err(e) = writer.write_all(&chunk).await {
+                println!("[{name}] write failed after {sent} bytes: {e}");
+                return sent;
+            }
+            sent += chunk.len() as u64;
+        }
+    };
+    let reading = async {
+        let Some(read_delay) = read_delay else {
+            return std::future::pending().await;
+        };
+        let mut buf = [0_u8; 1024];
+        loop {
+            sleep(read_delay).await;
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => return std::future::pending().await,
+                Ok(n) => println!("[{name}] slowly read {n} bytes"),
+            }
+        }
+    };
+
+    tokio::select! {
+        sent = writing => Ok(sent),
+        never = reading => never,
+    }
+}
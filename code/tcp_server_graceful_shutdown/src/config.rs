@@ -0,0 +1,338 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Which shutdown mechanism the server uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ShutdownMode {
+    /// A `broadcast` channel, fanned out via `ShutdownController`.
+    #[default]
+    Broadcast,
+    /// A `CancellationToken` tree with one child token per connection.
+    Token,
+    /// The same token tree, with the connections tracked by a `TaskTracker` instead of
+    /// a `JoinSet`.
+    Tracker,
+    /// The same token tree, with the server waiting for every connection's clone of a
+    /// sentinel `mpsc::Sender` to be dropped instead of tracking the tasks.
+    Sentinel,
+}
+
+/// How the byte stream is split into messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Framing {
+    /// No framing: echo whatever each `read` returns.
+    #[default]
+    Raw,
+    /// `LengthDelimitedCodec`: a 4-byte big-endian length, then the payload.
+    Length,
+    /// `LinesCodec`: newline-terminated UTF-8 lines, echoed back as `echo: <line>`.
+    /// Handy with `nc` or telnet.
+    Lines,
+    /// Not an echo at all: a minimal hand-written HTTP/1.1 server answering `/health`,
+    /// `/stats` and `POST /shutdown`. Try it with `curl`.
+    Http,
+    /// Any of `Raw`, `Lines` and `Http`, told apart by what each client sends first, so
+    /// `curl`, `nc` and the echo client can all use the same port.
+    Auto,
+}
+
+/// What the server does to each message before sending it back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Transform {
+    /// Nothing: a faithful echo.
+    #[default]
+    None,
+    /// Uppercase it (only ASCII letters, outside `Framing::Lines`).
+    Uppercase,
+    /// Reverse it: by character in `Framing::Lines`, by byte otherwise.
+    Reverse,
+    /// Wait `transform_delay` before answering.
+    Delay,
+    /// Don't answer, with probability `drop_probability`.
+    Drop,
+}
+
+/// What the accept loop does when `max_connections` are already being served.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverloadPolicy {
+    /// Stop accepting until a slot frees up; new clients wait in the kernel's backlog.
+    #[default]
+    Wait,
+    /// Keep accepting, but answer "server busy" and close straight away.
+    Reject,
+}
+
+/// What happens to a connection that goes over its rate limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RateLimitPolicy {
+    /// Stop reading until the budget has refilled, so TCP slows the client down.
+    #[default]
+    Delay,
+    /// Tell the client and close the connection.
+    Disconnect,
+}
+
+/// Where to find the server's certificate chain and private key, both in PEM form.
+/// The two may be the same file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertFiles {
+    /// The certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// The private key.
+    pub key: PathBuf,
+}
+
+/// The faults chaos mode injects, each with its own probability from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    /// Start with chaos on. The control socket can switch it on and off later.
+    pub enabled: bool,
+    /// The chance that a read stalls for `stall` first.
+    pub read_stall: f64,
+    /// How long a stalled read waits.
+    pub stall: Duration,
+    /// The chance that a write only takes part of what it was given.
+    pub partial_write: f64,
+    /// The chance, on each read, that the connection is reset instead.
+    pub reset: f64,
+    /// The chance that a new connection waits `accept_delay` before it's served.
+    pub delayed_accept: f64,
+    /// How long a delayed connection waits.
+    pub accept_delay: Duration,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            read_stall: 0.05,
+            stall: Duration::from_secs(2),
+            partial_write: 0.2,
+            reset: 0.01,
+            delayed_accept: 0.2,
+            accept_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// How long each subsystem gets to stop at shutdown. They stop in dependency order:
+/// the listeners and control sessions, then the connections (given
+/// `ServerConfig::drain_timeout`), then what the connections use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StopDeadlines {
+    /// The accept loops.
+    pub listeners: Duration,
+    /// The control socket's sessions, which say goodbye like connections do.
+    pub control: Duration,
+    /// The stats aggregator, adding up the connections' reports.
+    pub stats: Duration,
+    /// The stats file's final write.
+    pub flusher: Duration,
+    /// The connection list's final sweep.
+    pub sweeper: Duration,
+    /// The config file watcher.
+    pub reloader: Duration,
+    /// The runtime sampler's final sample.
+    pub sampler: Duration,
+}
+
+impl Default for StopDeadlines {
+    fn default() -> Self {
+        Self {
+            listeners: Duration::from_secs(1),
+            control: Duration::from_secs(2),
+            stats: Duration::from_secs(1),
+            flusher: Duration::from_secs(2),
+            sweeper: Duration::from_secs(1),
+            reloader: Duration::from_secs(1),
+            sampler: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Settings shared by the accept loop and every connection handler.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// The shutdown mechanism.
+    pub mode: ShutdownMode,
+    /// How messages are delimited on the wire.
+    pub framing: Framing,
+    /// The largest frame accepted in `Framing::Length` mode, and the largest request
+    /// body in `Framing::Http`, in bytes.
+    pub max_message_size: usize,
+    /// The longest line accepted in `Framing::Lines` mode, in bytes.
+    pub max_line_length: usize,
+    /// Serve `Framing::Raw` connections with separate reader and writer tasks.
+    pub split_halves: bool,
+    /// How many chunks the reader may queue for the writer in `split_halves` mode.
+    pub outbound_queue: usize,
+    /// How long a `split_halves` connection gets, once shutdown starts, to write out
+    /// what's still queued for its peer. Whatever is left then is dropped and counted.
+    /// Keep it below `drain_timeout`.
+    pub shutdown_flush_timeout: Duration,
+    /// Set `TCP_NODELAY` on accepted sockets, turning off Nagle's algorithm.
+    pub nodelay: bool,
+    /// Turn on TCP keepalive, probing after this long without traffic.
+    pub keepalive: Option<Duration>,
+    /// Set `SO_LINGER` on accepted sockets.
+    pub linger: Option<Duration>,
+    /// Stop the server after this many `accept` errors in a row, or `None` to keep trying.
+    pub max_accept_failures: Option<u32>,
+    /// Expect every connection to start with a PROXY protocol header (v1 or v2) and log
+    /// the client it names instead of the address we see.
+    pub proxy_protocol: bool,
+    /// Serve TLS, with a certificate from a demo CA generated at startup.
+    pub tls: bool,
+    /// Require clients to present a certificate signed by the demo CA. Implies `tls`.
+    pub mtls: bool,
+    /// Serve TLS with the certificate in these files instead of the demo CA, picking up
+    /// changes to them without a restart. Implies `tls`.
+    pub tls_files: Option<CertFiles>,
+    /// How often to check `tls_files` for changes.
+    pub tls_reload_interval: Duration,
+    /// How long a client may take to send its PROXY header and complete the TLS handshake.
+    pub handshake_timeout: Duration,
+    /// Read settings from this TOML file on startup, on top of the ones set here, and
+    /// keep watching it: changes to the timeouts and message size reach connections
+    /// that are already open.
+    pub config_file: Option<PathBuf>,
+    /// How often to check `config_file` for changes.
+    pub config_reload_interval: Duration,
+    /// The addresses to listen on, one listener (and accept loop) each.
+    pub bind: Vec<SocketAddr>,
+    /// Set `SO_REUSEPORT` on the listeners, so another process can bind the same port.
+    pub reuseport: bool,
+    /// Also listen here for operator commands (`STATS`, `CONNS`, `DRAIN <secs>`,
+    /// `ABORT`). Must be a loopback address, and needs `ShutdownMode::Broadcast`.
+    pub control: Option<SocketAddr>,
+    /// Also serve Prometheus metrics here, at `GET /metrics`, until the very end of
+    /// shutdown. Needs `ShutdownMode::Broadcast`.
+    pub metrics: Option<SocketAddr>,
+    /// Use the listeners systemd passed in (`LISTEN_FDS`) if there are any, and only
+    /// bind `bind` when there aren't.
+    pub socket_activation: bool,
+    /// How long a single echo write may take before the connection is dropped.
+    pub write_timeout: Duration,
+    /// How long a connection may sit without sending anything, or `None` to wait forever.
+    pub idle_timeout: Option<Duration>,
+    /// How long to wait for connections to finish after shutdown before aborting them.
+    pub drain_timeout: Duration,
+    /// How long each of the other subsystems gets to stop, after the connections.
+    pub stop_deadlines: StopDeadlines,
+    /// Write the live stats (as `/stats` and `STATS` give them) to this file every
+    /// `stats_flush_interval`, and once more at shutdown.
+    pub stats_file: Option<PathBuf>,
+    /// How often to write `stats_file`.
+    pub stats_flush_interval: Duration,
+    /// How often to sweep closed connections out of the list `CONNS` reads.
+    pub sweep_interval: Duration,
+    /// Log the tokio runtime's metrics this often, and once more over the whole run at
+    /// shutdown. `/metrics` gets them too. Broadcast mode only.
+    pub runtime_metrics_interval: Option<Duration>,
+    /// Log the resident set and the task and connection counts this often, for spotting
+    /// leaks in a long run under churning load; see `soak`. Broadcast mode only.
+    pub soak_report_interval: Option<Duration>,
+    /// Draw a live dashboard of the connections and the shutdown on the terminal, which
+    /// has to be one. Needs the `tui` feature and `ShutdownMode::Broadcast`.
+    pub tui: bool,
+    /// In `Framing::Lines`, announce shutdown with `GOAWAY <ms>` and give the client this
+    /// long to finish and reply `BYE`, instead of a one-way farewell. Keep it below
+    /// `drain_timeout`.
+    pub goaway: Option<Duration>,
+    /// In `Framing::Lines`, send `PING` this often and expect a `PONG` back, or `None`
+    /// for no heartbeat.
+    pub heartbeat: Option<Duration>,
+    /// How many `PING`s in a row may go unanswered before the connection is closed.
+    pub heartbeat_misses: u32,
+    /// Faults to inject into connections while chaos mode is on.
+    pub chaos: ChaosConfig,
+    /// Panic the task of every Nth connection as soon as it's admitted, to try out
+    /// the panic handling. Broadcast mode only, and only ever for testing.
+    pub panic_injection: Option<u64>,
+    /// Shut the server down once this many connection tasks have panicked, or `None`
+    /// to keep going however many do. Broadcast mode only.
+    pub max_panics: Option<u64>,
+    /// What to do to each message before echoing it, in the raw, length-delimited and
+    /// line handlers.
+    pub transform: Transform,
+    /// How long `Transform::Delay` holds each reply back.
+    pub transform_delay: Duration,
+    /// The chance, from 0 to 1, that `Transform::Drop` swallows a reply.
+    pub drop_probability: f64,
+    /// The maximum number of connections served at once, or `None` for no limit.
+    pub max_connections: Option<usize>,
+    /// What to do with new connections while at `max_connections`.
+    pub when_full: OverloadPolicy,
+    /// Admit at most this many new connections a second, across all listeners, or
+    /// `None` for no limit. The rest wait in the listen backlog.
+    pub accept_rate: Option<u32>,
+    /// How many connections may be admitted at once, after a quiet spell, before
+    /// `accept_rate` applies.
+    pub accept_burst: u32,
+    /// Let each connection send at most this many bytes a second, or `None` for no
+    /// limit. Only the raw handler enforces it.
+    pub rate_limit_bytes: Option<u64>,
+    /// Let each connection send at most this many messages a second (reads, in raw
+    /// mode), or `None` for no limit.
+    pub rate_limit_messages: Option<u32>,
+    /// What to do with a connection over `rate_limit_bytes` or `rate_limit_messages`.
+    pub over_rate_limit: RateLimitPolicy,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            mode: ShutdownMode::Broadcast,
+            framing: Framing::Raw,
+            max_message_size: 1024 * 1024,
+            max_line_length: 1024,
+            split_halves: false,
+            outbound_queue: 32,
+            shutdown_flush_timeout: Duration::from_secs(1),
+            nodelay: false,
+            keepalive: None,
+            linger: None,
+            max_accept_failures: Some(100),
+            proxy_protocol: false,
+            tls: false,
+            mtls: false,
+            tls_files: None,
+            tls_reload_interval: Duration::from_secs(2),
+            handshake_timeout: Duration::from_secs(5),
+            config_file: None,
+            config_reload_interval: Duration::from_secs(2),
+            bind: vec![SocketAddr::from(([127, 0, 0, 1], 3011))],
+            reuseport: false,
+            control: None,
+            metrics: None,
+            socket_activation: false,
+            write_timeout: Duration::from_secs(2),
+            idle_timeout: None,
+            drain_timeout: Duration::from_secs(5),
+            stop_deadlines: StopDeadlines::default(),
+            stats_file: None,
+            stats_flush_interval: Duration::from_secs(5),
+            sweep_interval: Duration::from_secs(30),
+            runtime_metrics_interval: None,
+            soak_report_interval: None,
+            tui: false,
+            goaway: None,
+            heartbeat: None,
+            heartbeat_misses: 3,
+            chaos: ChaosConfig::default(),
+            panic_injection: None,
+            max_panics: None,
+            transform: Transform::None,
+            transform_delay: Duration::from_secs(1),
+            drop_probability: 0.5,
+            max_connections: None,
+            when_full: OverloadPolicy::Wait,
+            accept_rate: None,
+            accept_burst: 10,
+            rate_limit_bytes: None,
+            rate_limit_messages: None,
+            over_rate_limit: RateLimitPolicy::Delay,
+        }
+    }
+}
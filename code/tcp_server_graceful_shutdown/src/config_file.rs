@@ -0,0 +1,218 @@
+//! Settings from a TOML file, re-read while the server runs.
+//!
+//! ```toml
+//! bind = ["127.0.0.1:3011"]
+//! write_timeout_secs = 2
+//! idle_timeout_secs = 30
+//! drain_timeout_secs = 5
+//! max_connections = 100
+//! max_message_size = 65536
+//! ```
+//!
+//! Every key is optional; whatever the file leaves out keeps the value it was given
+//! in code (or on the command line). The file is read once in `ServerBuilder::build`,
+//! before the listeners are bound, and then polled the same way as the certificate
+//! files in [`cert_reload`](crate::cert_reload). Only some settings can change under a
+//! running server, the [`Limits`]: a background task publishes them on a `watch`
+//! channel and the connection handlers look at the latest value on every pass round
+//! their loop. The rest (the bind address, the connection limit, the drain deadline)
+//! are logged as needing a restart.
+//!
+//! Each reload starts again from the settings given in code, so deleting a key from
+//! the file puts its old value back, and a file that doesn't parse is logged and
+//! skipped like a half-written certificate.
+
+use crate::config::ServerConfig;
+use crate::tasks;
+use serde::Deserialize;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{MissedTickBehavior, interval};
+use tracing::{info, warn};
+
+/// The contents of the configuration file.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    bind: Option<Vec<SocketAddr>>,
+    write_timeout_secs: Option<f64>,
+    idle_timeout_secs: Option<f64>,
+    drain_timeout_secs: Option<f64>,
+    handshake_timeout_secs: Option<f64>,
+    max_connections: Option<usize>,
+    max_message_size: Option<usize>,
+}
+
+impl ConfigFile {
+    fn parse(text: &str) -> Result<Self, String> {
+        let file: Self = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        // Checked here rather than at use, so a bad value is refused as a whole file.
+        for secs in [file.write_timeout_secs, file.idle_timeout_secs, file.drain_timeout_secs, file.handshake_timeout_secs]
+            .into_iter()
+            .flatten()
+        {
+            Duration::try_from_secs_f64(secs).map_err(|e| format!("{secs} isn't a duration in seconds: {e}"))?;
+        }
+        Ok(file)
+    }
+
+    /// `base` with the file's values on top.
+    fn apply(&self, base: &ServerConfig) -> ServerConfig {
+        let secs = |secs: f64| Duration::from_secs_f64(secs);
+        let mut config = base.clone();
+        if let Some(bind) = &self.bind {
+            config.bind = bind.clone();
+        }
+        if let Some(timeout) = self.write_timeout_secs {
+            config.write_timeout = secs(timeout);
+        }
+        if let Some(timeout) = self.idle_timeout_secs {
+            config.idle_timeout = Some(secs(timeout));
+        }
+        if let Some(timeout) = self.drain_timeout_secs {
+            config.drain_timeout = secs(timeout);
+        }
+        if let Some(timeout) = self.handshake_timeout_secs {
+            config.handshake_timeout = secs(timeout);
+        }
+        if let Some(max) = self.max_connections {
+            config.max_connections = Some(max);
+        }
+        if let Some(max) = self.max_message_size {
+            config.max_message_size = max;
+        }
+        config
+    }
+}
+
+/// The settings a connection picks up while it's open.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Limits {
+    pub(crate) write_timeout: Duration,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) max_message_size: usize,
+}
+
+impl From<&ServerConfig> for Limits {
+    fn from(config: &ServerConfig) -> Self {
+        Self {
+            write_timeout: config.write_timeout,
+            idle_timeout: config.idle_timeout,
+            max_message_size: config.max_message_size,
+        }
+    }
+}
+
+/// A receiver that always sees `config`'s limits, for servers without a file. The
+/// sender is dropped straight away; `borrow` still works, and `changed` never fires.
+pub(crate) fn fixed(config: &ServerConfig) -> watch::Receiver<Limits> {
+    watch::channel(Limits::from(config)).1
+}
+
+/// Reads the file at `path` and returns `base` with its values applied, along with a
+/// receiver for the limits, which a spawned task updates whenever the file changes. The
+/// task stops once every receiver is gone; its handle is returned too, so shutdown can
+/// wait for that.
+pub(crate) fn watch_config_file(
+    path: PathBuf,
+    base: ServerConfig,
+    poll_interval: Duration,
+) -> io::Result<(ServerConfig, watch::Receiver<Limits>, JoinHandle<()>)> {
+    let mut seen = fingerprint(&path);
+    let mut current = load(&path)?.apply(&base);
+    let (tx, rx) = watch::channel(Limits::from(&current));
+
+    let config = current.clone();
+    // Blocking: `fingerprint` and `load` use `std::fs`.
+    let watcher = tasks::spawn("config watcher (blocks on std::fs each tick)", async move {
+        let mut ticks = interval(poll_interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticks.tick().await;
+        loop {
+            tokio::select! {
+                () = tx.closed() => return,
+                _ = ticks.tick() => {}
+            }
+            let fingerprint = fingerprint(&path);
+            if fingerprint == seen {
+                continue;
+            }
+            seen = fingerprint;
+            let updated = match load(&path) {
+                Ok(file) => file.apply(&base),
+                Err(e) => {
+                    warn!(error = %e, "keeping the previous settings");
+                    continue;
+                }
+            };
+            for name in restart_needed(&current, &updated) {
+                info!(setting = name, file = %path.display(), "changed; restart to apply it");
+            }
+            let limits = Limits::from(&updated);
+            if tx.send_if_modified(|live| std::mem::replace(live, limits) != limits) {
+                info!(file = %path.display(), ?limits, "reloaded");
+            }
+            current = updated;
+        }
+    });
+    Ok((config, rx, watcher))
+}
+
+fn load(path: &Path) -> io::Result<ConfigFile> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("reading {}: {e}", path.display())))?;
+    ConfigFile::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", path.display())))
+}
+
+/// The settings that differ between `old` and `new` but only take effect at startup.
+fn restart_needed(old: &ServerConfig, new: &ServerConfig) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if old.bind != new.bind {
+        changed.push("bind");
+    }
+    if old.drain_timeout != new.drain_timeout {
+        changed.push("drain_timeout_secs");
+    }
+    if old.handshake_timeout != new.handshake_timeout {
+        changed.push("handshake_timeout_secs");
+    }
+    if old.max_connections != new.max_connections {
+        changed.push("max_connections");
+    }
+    changed
+}
+
+/// Size as well as mtime, as in `cert_reload`.
+fn fingerprint(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_values_override_the_base() {
+        let file = ConfigFile::parse("bind = [\"127.0.0.1:4000\"]\nwrite_timeout_secs = 0.5\nmax_connections = 3\n").unwrap();
+        let config = file.apply(&ServerConfig::default());
+        assert_eq!(config.bind, vec![SocketAddr::from(([127, 0, 0, 1], 4000))]);
+        assert_eq!(config.write_timeout, Duration::from_millis(500));
+        assert_eq!(config.max_connections, Some(3));
+        // Untouched by the file.
+        assert_eq!(config.drain_timeout, ServerConfig::default().drain_timeout);
+        assert_eq!(restart_needed(&ServerConfig::default(), &config), ["bind", "max_connections"]);
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_files() {
+        assert!(ConfigFile::parse("write_timeout = 2").unwrap_err().contains("unknown field"));
+        assert!(ConfigFile::parse("idle_timeout_secs = -1").is_err());
+        assert!(ConfigFile::parse("max_connections = \"many\"").is_err());
+        assert_eq!(ConfigFile::parse("").unwrap(), ConfigFile::default());
+    }
+}
@@ -0,0 +1,497 @@
+use crate::chaos::ChaosStream;
+use crate::config::{Framing, RateLimitPolicy, ServerConfig};
+use crate::config_file::Limits;
+use crate::error::{ConnectionError, Deadline};
+use crate::event_log::{Event, EventLog};
+use crate::stats::ConnStats;
+use crate::http::Admin;
+use crate::listener::Connection;
+use crate::rate_limit::{self, RateLimiter};
+use crate::transform::Transformer;
+use crate::{framing, http, proxy, sniff, split};
+use bytes::BytesMut;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, watch};
+use tokio::time::{Duration, Instant, sleep, timeout};
+use tokio_rustls::TlsAcceptor;
+use tracing::{Span, field, info, info_span, instrument, warn};
+
+/// How much we ask the kernel for per read.
+const READ_CHUNK: usize = 1024;
+
+/// Who a connection is and how much it has moved, shared with its handler.
+///
+/// Every log line about a connection is logged inside its [`span`](Self::span)
+/// (`conn{id=7 peer=127.0.0.1:51234}`), so the lines of one client can be picked out
+/// of a busy demo with `grep 'conn{id=7 '`. Behind a PROXY protocol balancer the span
+/// gains `client=<client>` once the header has arrived, and the `Display` form, which
+/// `CONNS` and the task names use, reads `conn=7 peer=<client> via=<balancer>`. The
+/// counters are atomics because in `split_halves` mode the reading and writing happen
+/// in two different tasks.
+#[derive(Debug)]
+pub(crate) struct ConnInfo {
+    pub(crate) id: u64,
+    /// The peer's address, or whatever names it on transports without one.
+    pub(crate) peer: String,
+    /// The real client, as reported by a PROXY header.
+    source: OnceLock<SocketAddr>,
+    /// On tokio's clock, like every timeout here, so a paused test clock moves it too.
+    started: Instant,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    bytes_dropped: AtomicU64,
+    events: Option<EventLog>,
+}
+
+impl ConnInfo {
+    pub(crate) fn new(id: u64, peer: impl fmt::Display) -> Self {
+        Self {
+            id,
+            peer: peer.to_string(),
+            source: OnceLock::new(),
+            started: Instant::now(),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            bytes_dropped: AtomicU64::new(0),
+            events: None,
+        }
+    }
+
+    /// Records what happens to the connection in `events` as well, if there's a log.
+    pub(crate) fn with_event_log(mut self, events: Option<EventLog>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub(crate) fn record(&self, event: impl FnOnce(u64) -> Event) {
+        if let Some(events) = &self.events {
+            events.record(event(self.id));
+        }
+    }
+
+    /// The span the connection's task runs in, from the accept on.
+    pub(crate) fn span(&self) -> Span {
+        info_span!("conn", id = self.id, peer = %self.peer, client = field::Empty)
+    }
+
+    /// Records the client address a PROXY header named. Only the first call counts.
+    pub(crate) fn set_source(&self, source: SocketAddr) {
+        let _ = self.source.set(source);
+    }
+
+    /// Counts `n` payload bytes received from the peer.
+    pub(crate) fn record_in(&self, n: usize) {
+        self.bytes_in.fetch_add(n as u64, Ordering::Relaxed);
+        self.record(|conn| Event::Read { conn, bytes: n });
+    }
+
+    /// Counts `n` payload bytes echoed back to the peer.
+    pub(crate) fn record_out(&self, n: usize) {
+        self.bytes_out.fetch_add(n as u64, Ordering::Relaxed);
+        self.record(|conn| Event::Wrote { conn, bytes: n });
+    }
+
+    /// Counts `n` bytes owed to the peer that were thrown away at shutdown.
+    pub(crate) fn record_dropped(&self, n: usize) {
+        self.bytes_dropped.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// A snapshot of the counters, for the stats aggregator.
+    pub(crate) fn stats(&self) -> ConnStats {
+        ConnStats {
+            bytes_read: self.bytes_in.load(Ordering::Relaxed),
+            bytes_written: self.bytes_out.load(Ordering::Relaxed),
+            bytes_dropped: self.bytes_dropped.load(Ordering::Relaxed),
+            duration: self.started.elapsed(),
+        }
+    }
+
+    /// Logs the one-line summary for a connection that has just finished.
+    pub(crate) fn log_closed(&self, result: &Result<(), ConnectionError>) {
+        let bytes_in = self.bytes_in.load(Ordering::Relaxed);
+        let bytes_out = self.bytes_out.load(Ordering::Relaxed);
+        let dropped_at_shutdown = self.bytes_dropped.load(Ordering::Relaxed);
+        let duration = self.started.elapsed();
+        match result {
+            Ok(()) => info!(bytes_in, bytes_out, dropped_at_shutdown, ?duration, "closed"),
+            Err(e) => warn!(bytes_in, bytes_out, dropped_at_shutdown, ?duration, error = e as &dyn std::error::Error, "closed with an error"),
+        }
+    }
+}
+
+impl fmt::Display for ConnInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.source.get() {
+            Some(source) => write!(f, "conn={} peer={source} via={}", self.id, self.peer),
+            None => write!(f, "conn={} peer={}", self.id, self.peer),
+        }
+    }
+}
+
+/// Serves one connection, after reading a PROXY header if `config.proxy_protocol` is on
+/// and a TLS handshake if `tls` is set.
+///
+/// Both take a round trip or more, and a peer that connects and then says nothing
+/// would hold them open forever, so they share a deadline. They also race the shutdown
+/// signal: until they complete there's no channel to send a farewell over, so a
+/// connection caught mid-handshake is simply dropped.
+pub(crate) async fn serve_connection(
+    socket: impl Connection,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    limits: watch::Receiver<Limits>,
+    conn: &Arc<ConnInfo>,
+    tls: Option<&TlsAcceptor>,
+    admin: &Admin,
+) -> Result<(), ConnectionError> {
+    if let Some(delay) = admin.chaos.accept_delay() {
+        info!(?delay, "chaos: left waiting before it's served");
+        tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(()),
+            () = sleep(delay) => {}
+        }
+    }
+    // Always wrapped, since chaos can be switched on while the connection is open.
+    let mut socket = ChaosStream::new(socket, admin.chaos.clone());
+    if config.proxy_protocol {
+        let header = tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(()),
+            header = timeout(config.handshake_timeout, proxy::read_header(&mut socket)) => match header {
+                Ok(Ok(header)) => header,
+                Ok(Err(e)) => return Err(ConnectionError::decoding(e)),
+                Err(_) => return Err(ConnectionError::Timeout(Deadline::ProxyHeader)),
+            },
+        };
+        match header {
+            Some(header) => {
+                conn.set_source(header.source);
+                Span::current().record("client", field::display(header.source));
+                info!(destination = %header.destination, "proxied");
+            }
+            None => info!("PROXY header without addresses, keeping the peer address"),
+        }
+    }
+    let Some(acceptor) = tls else {
+        return serve_stream(socket, shutdown_rx, config, limits, conn, admin).await;
+    };
+    let stream = tokio::select! {
+        _ = shutdown_rx.recv() => return Ok(()),
+        handshake = timeout(config.handshake_timeout, acceptor.accept(socket)) => match handshake {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => return Err(ConnectionError::Tls(e)),
+            Err(_) => return Err(ConnectionError::Timeout(Deadline::TlsHandshake)),
+        },
+    };
+    serve_stream(stream, shutdown_rx, config, limits, conn, admin).await
+}
+
+/// Serves a connected byte stream with the handler for the configured framing, or for
+/// the one the client turns out to speak in `Framing::Auto`.
+async fn serve_stream<S>(
+    socket: S,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    limits: watch::Receiver<Limits>,
+    conn: &Arc<ConnInfo>,
+    admin: &Admin,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    if config.framing != Framing::Auto {
+        return dispatch(config.framing, socket, shutdown_rx, config, limits, conn, admin).await;
+    }
+    let Some((framing, socket)) = sniff::sniff(socket, &mut shutdown_rx, config.handshake_timeout).await? else {
+        return Ok(());
+    };
+    info!(?framing, "sniffed");
+    dispatch(framing, socket, shutdown_rx, config, limits, conn, admin).await
+}
+
+async fn dispatch<S>(
+    framing: Framing,
+    socket: S,
+    shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    limits: watch::Receiver<Limits>,
+    conn: &Arc<ConnInfo>,
+    admin: &Admin,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    match framing {
+        Framing::Raw if config.split_halves => split::handle_split(socket, shutdown_rx, config, conn).await,
+        Framing::Raw => handle_connection(socket, shutdown_rx, config, limits, conn).await,
+        Framing::Length => framing::handle_length_delimited(socket, shutdown_rx, config, limits, conn).await,
+        Framing::Lines => framing::handle_lines(socket, shutdown_rx, config, conn).await,
+        Framing::Http => http::handle_http(socket, shutdown_rx, config, conn, admin).await,
+        Framing::Auto => unreachable!("sniffing always settles on a concrete framing"),
+    }
+}
+
+/// Echoes everything the peer sends until it disconnects or shutdown is signalled.
+///
+/// Nothing in here is TCP-specific, so it takes any byte stream: the Windows named
+/// pipe server reuses it unchanged, and the unit tests below script one with
+/// `tokio_test`'s mock, down to how the bytes are chunked and which call fails.
+#[instrument(skip_all)]
+pub(crate) async fn handle_connection<S>(
+    mut socket: S,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    mut limits: watch::Receiver<Limits>,
+    conn: &ConnInfo,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // A `BytesMut` instead of a `[u8; 1024]` on the stack: the buffer lives on the heap
+    // (so it doesn't bloat the future, which holds every local across an `.await`), and
+    // `read_buf` appends into its spare capacity without us tracking lengths by hand.
+    let mut buf = BytesMut::with_capacity(READ_CHUNK);
+
+    // One timer for the whole connection, set from the last read on every pass, since
+    // a reload may have changed the timeout in between. Re-creating a `sleep` inside
+    // the loop would work too, but resetting avoids re-registering it.
+    let idle = sleep(Duration::ZERO);
+    tokio::pin!(idle);
+    let mut last_read = Instant::now();
+    let mut rate_limiter = RateLimiter::new(config);
+    let transformer = Transformer::new(config);
+
+    loop {
+        // The latest limits, looked at once per pass: a write already under way keeps
+        // the timeout it started with.
+        let Limits { write_timeout, idle_timeout, .. } = *limits.borrow_and_update();
+        if let Some(idle_timeout) = idle_timeout {
+            idle.as_mut().reset(last_read + idle_timeout);
+        }
+        tokio::select! {
+            // Only here to wake us up for the next pass. Without a config file the
+            // sender is already gone, `changed` fails, and the branch switches off.
+            Ok(()) = limits.changed() => {}
+            // Ticks ten times a second while there's a limit, and never otherwise.
+            () = rate_limit::refill(&mut rate_limiter) => {}
+            () = &mut idle, if idle_timeout.is_some() => {
+                socket.write_all(b"idle timeout, closing connection\n").await?;
+                return Ok(socket.shutdown().await?);
+            }
+            recv = shutdown_rx.recv() => {
+                conn.record(|conn| Event::ShutdownSeen { conn });
+                match recv {
+                    Ok(()) => {
+                        socket.write_all(b"server shutting down\n").await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) | Err(broadcast::error::RecvError::Closed) => {}
+                }
+                // Over TLS this sends close_notify; without it the client can't tell our
+                // goodbye from a truncation attack and reports an unexpected EOF.
+                return Ok(socket.shutdown().await?);
+            }
+            // Over the limit in `Delay` mode, we simply don't read; see `rate_limit`.
+            read_result = socket.read_buf(&mut buf), if !rate_limiter.as_ref().is_some_and(RateLimiter::is_exhausted) => {
+                match read_result {
+                    // The peer has shut down its write side (sent FIN), but it may still be
+                    // reading. Everything we owe it has already been written, since each
+                    // echo finishes before the next read, so say we're done too. Just
+                    // dropping the socket would also close it, but `shutdown` waits for
+                    // the FIN to be queued and reports errors rather than swallowing them.
+                    Ok(0) => return Ok(socket.shutdown().await?),
+                    Ok(n) => {
+                        conn.record_in(n);
+                        last_read = Instant::now();
+                        if let Some(limiter) = &mut rate_limiter {
+                            limiter.spend(n);
+                            if limiter.is_exhausted() && config.over_rate_limit == RateLimitPolicy::Disconnect {
+                                socket.write_all(b"rate limit exceeded, closing connection\n").await?;
+                                socket.shutdown().await?;
+                                return Err(ConnectionError::RateLimited);
+                            }
+                        }
+                        transformer.apply_bytes(&mut buf);
+                        if transformer.should_reply().await {
+                            match timeout(write_timeout, socket.write_all(&buf)).await {
+                                Ok(result) => result?,
+                                Err(_) => return Err(ConnectionError::Timeout(Deadline::Write)),
+                            }
+                            conn.record_out(n);
+                        }
+                        // Everything is echoed, so forget it. `clear` keeps the allocation,
+                        // and `reserve` finds the space free again instead of growing, so
+                        // one connection reuses the same 1 KiB however much it echoes.
+                        buf.clear();
+                        buf.reserve(READ_CHUNK);
+                    }
+                    Err(e) => return Err(ConnectionError::Read(e)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_file;
+    use std::io;
+    use tokio::io::DuplexStream;
+    use tokio::task::JoinHandle;
+    use tokio_test::io::{Builder, Mock};
+
+    type Handler = JoinHandle<Result<(), ConnectionError>>;
+
+    /// Serves one end of an in-memory pipe with the raw handler; the test holds the other.
+    fn serve(config: ServerConfig) -> (DuplexStream, broadcast::Sender<()>, Arc<ConnInfo>, Handler) {
+        let (client, server) = tokio::io::duplex(64);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let conn = Arc::new(ConnInfo::new(1, "test"));
+        let task = tokio::spawn({
+            let conn = conn.clone();
+            async move { handle_connection(server, shutdown_rx, &config, config_file::fixed(&config), &conn).await }
+        });
+        (client, shutdown_tx, conn, task)
+    }
+
+    /// Moves the paused clock on by `by` and lets everything that was waiting for it run.
+    async fn advance(by: Duration) {
+        tokio::time::advance(by).await;
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_timeout_drops_a_client_that_stops_reading() {
+        let config = ServerConfig { write_timeout: Duration::from_secs(2), ..ServerConfig::default() };
+        let (mut client, _shutdown_tx, _conn, task) = serve(config);
+        // The first echo fills the pipe back to the client, the second waits for room.
+        client.write_all(&[0; 64]).await.unwrap();
+        advance(Duration::ZERO).await;
+        client.write_all(&[0; 64]).await.unwrap();
+        advance(Duration::ZERO).await;
+
+        advance(Duration::from_millis(1999)).await;
+        assert!(!task.is_finished());
+        advance(Duration::from_millis(1)).await;
+        assert!(task.is_finished());
+        assert!(matches!(task.await.unwrap(), Err(ConnectionError::Timeout(Deadline::Write))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_counts_from_the_last_read() {
+        let config = ServerConfig { idle_timeout: Some(Duration::from_secs(5)), ..ServerConfig::default() };
+        let (mut client, _shutdown_tx, conn, task) = serve(config);
+        advance(Duration::from_secs(3)).await;
+        client.write_all(b"hi").await.unwrap();
+        let mut echo = [0; 2];
+        client.read_exact(&mut echo).await.unwrap();
+
+        // Five seconds since the read, not since the connection opened.
+        advance(Duration::from_millis(4999)).await;
+        assert!(!task.is_finished());
+        advance(Duration::from_millis(1)).await;
+        task.await.unwrap().unwrap();
+        let mut farewell = Vec::new();
+        client.read_to_end(&mut farewell).await.unwrap();
+        assert_eq!(farewell, b"idle timeout, closing connection\n");
+        assert_eq!(conn.stats().duration, Duration::from_secs(8));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_idle_timeout_waits_for_the_shutdown() {
+        let (mut client, shutdown_tx, _conn, task) = serve(ServerConfig::default());
+        advance(Duration::from_secs(24 * 60 * 60)).await;
+        assert!(!task.is_finished());
+
+        shutdown_tx.send(()).unwrap();
+        task.await.unwrap().unwrap();
+        let mut farewell = Vec::new();
+        client.read_to_end(&mut farewell).await.unwrap();
+        assert_eq!(farewell, b"server shutting down\n");
+    }
+
+    /// Runs the raw handler over a scripted stream. The mock panics on any write it
+    /// wasn't told to expect, and when dropped with some of the script left over.
+    async fn run_mock(mock: Mock, shutdown_rx: broadcast::Receiver<()>, conn: &ConnInfo) -> Result<(), ConnectionError> {
+        let config = ServerConfig::default();
+        handle_connection(mock, shutdown_rx, &config, config_file::fixed(&config), conn).await
+    }
+
+    #[tokio::test]
+    async fn test_echoes_each_read_as_it_arrives() {
+        // A message split across reads is echoed piece by piece, and a short write is
+        // finished off rather than dropped.
+        let mock = Builder::new()
+            .read(b"hel")
+            .write(b"hel")
+            .read(b"lo, world")
+            .write(b"lo, ")
+            .write(b"world")
+            .build();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let conn = ConnInfo::new(1, "mock");
+        run_mock(mock, shutdown_rx, &conn).await.unwrap();
+        let stats = conn.stats();
+        assert_eq!((stats.bytes_read, stats.bytes_written), (12, 12));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_between_reads_says_goodbye() {
+        let mock = Builder::new().read(b"hi").write(b"hi").write(b"server shutting down\n").build();
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let events = EventLog::new();
+        let conn = Arc::new(ConnInfo::new(1, "mock").with_event_log(Some(events.clone())));
+        let task = tokio::spawn({
+            let conn = conn.clone();
+            async move { run_mock(mock, shutdown_rx, &conn).await }
+        });
+        // Only once the echo is out, so the signal lands while the handler waits to read.
+        events.wait_for(|event| matches!(event, Event::Wrote { .. })).await;
+        shutdown_tx.send(()).unwrap();
+        task.await.unwrap().unwrap();
+
+        let events: Vec<_> = events.events().await.into_iter().map(|recorded| recorded.event).collect();
+        assert_eq!(events, [Event::Read { conn: 1, bytes: 2 }, Event::Wrote { conn: 1, bytes: 2 }, Event::ShutdownSeen { conn: 1 }]);
+    }
+
+    #[tokio::test]
+    async fn test_write_error_ends_the_connection() {
+        let mock = Builder::new()
+            .read(b"hi")
+            .write_error(io::Error::from(io::ErrorKind::BrokenPipe))
+            .build();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let conn = ConnInfo::new(1, "mock");
+        let result = run_mock(mock, shutdown_rx, &conn).await;
+        assert!(matches!(result, Err(ConnectionError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe));
+        assert_eq!(conn.stats().bytes_written, 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_goodbye_is_reported() {
+        let mock = Builder::new()
+            .write_error(io::Error::from(io::ErrorKind::ConnectionReset))
+            .build();
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        shutdown_tx.send(()).unwrap();
+        let result = run_mock(mock, shutdown_rx, &ConnInfo::new(1, "mock")).await;
+        assert!(matches!(result, Err(ConnectionError::Io(e)) if e.kind() == io::ErrorKind::ConnectionReset));
+    }
+
+    #[tokio::test]
+    async fn test_read_error_is_kept_apart_from_write_errors() {
+        let mock = Builder::new()
+            .read(b"hi")
+            .write(b"hi")
+            .read_error(io::Error::from(io::ErrorKind::ConnectionReset))
+            .build();
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let result = run_mock(mock, shutdown_rx, &ConnInfo::new(1, "mock")).await;
+        assert!(matches!(result, Err(ConnectionError::Read(e)) if e.kind() == io::ErrorKind::ConnectionReset));
+    }
+}
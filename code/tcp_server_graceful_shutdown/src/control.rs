@@ -0,0 +1,152 @@
+//! The control socket: a second listener, on localhost only, where an operator can ask
+//! the running server what it's doing and tell it to stop.
+//!
+//! The protocol is one command per line, answered with `OK ...` or `ERR ...`:
+//!
+//! ```text
+//! STATS          OK {"uptime_secs":...}       the live counters, as JSON
+//! CONNS          OK 2 connection(s)            followed by one line per connection
+//! DRAIN <secs>   OK draining for up to 3s     graceful shutdown with this deadline
+//! ABORT          OK aborting                  shutdown without waiting for anyone
+//! CHAOS [ON|OFF] OK chaos on read_stall=...    switch fault injection, or just ask
+//! ```
+//!
+//! Sessions don't touch the server's state themselves. Each command goes to the accept
+//! loop over an `mpsc` channel, carrying a `oneshot` sender for the reply: the command
+//! pattern, with the accept loop as the actor that owns the state. That's what lets
+//! `DRAIN` change the drain deadline and break out of the loop, which no other task
+//! could do.
+
+use futures::{SinkExt, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_util::codec::{Framed, LinesCodec};
+
+/// The longest command line accepted.
+const MAX_COMMAND: usize = 256;
+
+/// A parsed command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Request {
+    Stats,
+    Conns,
+    Drain(Duration),
+    Abort,
+    /// Switch chaos mode on or off, or with `None` just report it.
+    Chaos(Option<bool>),
+}
+
+/// A request on its way to the accept loop, with somewhere to send the answer.
+pub(crate) struct Command {
+    pub(crate) request: Request,
+    pub(crate) reply: oneshot::Sender<String>,
+}
+
+/// Parses one command line. Command names are case-insensitive.
+pub(crate) fn parse(line: &str) -> Result<Request, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default().to_ascii_uppercase();
+    let arg = words.next();
+    if words.next().is_some() {
+        return Err("too many arguments".into());
+    }
+    match (name.as_str(), arg) {
+        ("STATS", None) => Ok(Request::Stats),
+        ("CONNS", None) => Ok(Request::Conns),
+        ("ABORT", None) => Ok(Request::Abort),
+        ("STATS" | "CONNS" | "ABORT", Some(_)) => Err(format!("{name} takes no arguments")),
+        ("DRAIN", Some(secs)) => secs
+            .parse()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .map(Request::Drain)
+            .ok_or_else(|| format!("bad deadline {secs:?}, expected seconds")),
+        ("DRAIN", None) => Err("usage: DRAIN <secs>".into()),
+        ("CHAOS", None) => Ok(Request::Chaos(None)),
+        ("CHAOS", Some(switch)) if switch.eq_ignore_ascii_case("on") => Ok(Request::Chaos(Some(true))),
+        ("CHAOS", Some(switch)) if switch.eq_ignore_ascii_case("off") => Ok(Request::Chaos(Some(false))),
+        ("CHAOS", Some(_)) => Err("usage: CHAOS [ON|OFF]".into()),
+        ("", _) => Err("empty command".into()),
+        _ => Err(format!("unknown command {name:?}, expected STATS, CONNS, DRAIN <secs>, ABORT or CHAOS [ON|OFF]")),
+    }
+}
+
+/// Accepts on the control listener, or never completes if there isn't one, so the
+/// accept loop can race it in a `select!` either way.
+pub(crate) async fn accept(listener: Option<&TcpListener>) -> io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Serves one operator: reads commands and writes replies until they hang up or the
+/// server shuts down.
+pub(crate) async fn session(socket: TcpStream, commands: mpsc::Sender<Command>, mut shutdown_rx: broadcast::Receiver<()>) {
+    let mut lines = Framed::new(socket, LinesCodec::new_with_max_length(MAX_COMMAND));
+
+    loop {
+        let line = tokio::select! {
+            _ = shutdown_rx.recv() => {
+                let _ = lines.send("server shutting down").await;
+                return;
+            }
+            line = lines.next() => match line {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => {
+                    let _ = lines.send(format!("ERR {e}")).await;
+                    return;
+                }
+                None => return,
+            },
+        };
+        let reply = match parse(&line) {
+            Ok(request) => ask(&commands, request).await,
+            Err(e) => format!("ERR {e}"),
+        };
+        if lines.send(reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends `request` to the accept loop and waits for its answer.
+async fn ask(commands: &mpsc::Sender<Command>, request: Request) -> String {
+    let (reply, answer) = oneshot::channel();
+    if commands.send(Command { request, reply }).await.is_err() {
+        return "ERR server is shutting down".into();
+    }
+    // The loop may have stopped after taking the command but before answering it.
+    answer.await.unwrap_or_else(|_| "ERR server is shutting down".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse("STATS"), Ok(Request::Stats));
+        assert_eq!(parse("  conns "), Ok(Request::Conns));
+        assert_eq!(parse("DRAIN 2.5"), Ok(Request::Drain(Duration::from_millis(2500))));
+        assert_eq!(parse("abort"), Ok(Request::Abort));
+        assert_eq!(parse("CHAOS"), Ok(Request::Chaos(None)));
+        assert_eq!(parse("chaos on"), Ok(Request::Chaos(Some(true))));
+        assert_eq!(parse("CHAOS Off"), Ok(Request::Chaos(Some(false))));
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_commands() {
+        assert!(parse("").is_err());
+        assert!(parse("DRAIN").is_err());
+        assert!(parse("DRAIN soon").is_err());
+        assert!(parse("DRAIN -1").is_err());
+        assert!(parse("STATS now").is_err());
+        assert!(parse("DRAIN 1 2").is_err());
+        assert!(parse("CHAOS maybe").is_err());
+        assert!(parse("RESTART").unwrap_err().contains("unknown command"));
+    }
+}
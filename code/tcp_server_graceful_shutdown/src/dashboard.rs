@@ -0,0 +1,261 @@
+//! `--tui`: a live dashboard in the terminal instead of the log, for watching a drain
+//! happen, or projecting one.
+//!
+//! Four times a second it draws the open connections (the list `CONNS` reads, with each
+//! one's traffic and age), the connections accepted per second over the last minute
+//! (from the live counters behind `/stats`), and where the shutdown has got to.
+//!
+//! The render loop is one more subsystem with a place in the shutdown. It starts with
+//! the accept loop, is told as each shutdown stage finishes, and is stopped last, after
+//! the report, so its final frame says how the shutdown went; that frame stays up for a
+//! moment, or until a key press, before the terminal is handed back. In raw mode Ctrl-C
+//! is a key press rather than a signal, so the dashboard handles it: `q`, Esc and Ctrl-C
+//! start the same shutdown SIGINT would.
+//!
+//! Drawing writes to stdout synchronously, a few kilobytes a frame; on a terminal
+//! that's fast enough not to matter. Anything else writing to the terminal meanwhile,
+//! the log included, garbles the picture, which is why `--tui` turns the log off.
+
+use crate::connection::ConnInfo;
+use crate::registry::Registry;
+use crate::stats::LiveStats;
+use crate::tasks;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Sparkline, Table};
+use ratatui::{DefaultTerminal, Frame};
+use shutdown_util::ShutdownTrigger;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{MissedTickBehavior, interval, sleep};
+use tracing::error;
+
+/// How often the screen is redrawn.
+const FRAME: Duration = Duration::from_millis(250);
+/// How many seconds of accept rate the sparkline shows.
+const HISTORY: usize = 60;
+/// How long the final frame stays up without a key press.
+const LINGER: Duration = Duration::from_secs(3);
+
+/// Where the server is in its life, as the header shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Phase {
+    Running,
+    /// The accept loop has stopped; `progress` says which stage finished last.
+    ShuttingDown { since: Instant, progress: Option<String> },
+    /// The shutdown report is in; `failed` names what didn't stop cleanly.
+    Done { elapsed: Duration, failed: Vec<String> },
+}
+
+/// The running dashboard.
+pub(crate) struct Dashboard {
+    phase: watch::Sender<Phase>,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Dashboard {
+    /// Takes over the terminal and starts drawing. `None`, logged, if there's no
+    /// terminal to take over.
+    pub(crate) fn spawn(registry: Arc<Registry>, stats: Arc<LiveStats>, shutdown: ShutdownTrigger) -> Option<Self> {
+        let terminal = match ratatui::try_init() {
+            Ok(terminal) => terminal,
+            Err(e) => {
+                error!(error = %e, "couldn't take over the terminal, running without the dashboard");
+                return None;
+            }
+        };
+        let (phase, phase_rx) = watch::channel(Phase::Running);
+        let (stop, stop_rx) = oneshot::channel();
+        let task = tasks::spawn("dashboard", render_loop(terminal, registry, stats, shutdown, phase_rx, stop_rx));
+        Some(Self { phase, stop, task })
+    }
+
+    /// Where the phase is updated from, e.g. the orchestrator's stage callback.
+    pub(crate) fn phase(&self) -> watch::Sender<Phase> {
+        self.phase.clone()
+    }
+
+    /// Draws the final frame, leaves it up for a moment and gives the terminal back.
+    pub(crate) async fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.task.await;
+    }
+}
+
+/// What one frame shows, gathered before drawing.
+struct View<'a> {
+    phase: &'a Phase,
+    uptime: Duration,
+    accepted: u64,
+    active: u64,
+    panics: u64,
+    conns: Vec<Arc<ConnInfo>>,
+    accepts_per_sec: &'a VecDeque<u64>,
+}
+
+async fn render_loop(
+    mut terminal: DefaultTerminal,
+    registry: Arc<Registry>,
+    stats: Arc<LiveStats>,
+    shutdown: ShutdownTrigger,
+    phase: watch::Receiver<Phase>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let mut events = EventStream::new();
+    let mut frame = interval(FRAME);
+    frame.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut per_second = interval(Duration::from_secs(1));
+    per_second.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut accepts_per_sec = VecDeque::with_capacity(HISTORY);
+    let mut accepted_before = stats.accepted();
+
+    let redraw = |terminal: &mut DefaultTerminal, accepts_per_sec: &VecDeque<u64>| {
+        let view = View {
+            phase: &phase.borrow(),
+            uptime: stats.uptime(),
+            accepted: stats.accepted(),
+            active: stats.active(),
+            panics: stats.panics(),
+            conns: registry.live(),
+            accepts_per_sec,
+        };
+        terminal.draw(|frame| draw(frame, &view)).map(drop)
+    };
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            _ = frame.tick() => {}
+            _ = per_second.tick() => {
+                let accepted = stats.accepted();
+                if accepts_per_sec.len() == HISTORY {
+                    accepts_per_sec.pop_front();
+                }
+                accepts_per_sec.push_back(accepted - accepted_before);
+                accepted_before = accepted;
+            }
+            Some(Ok(event)) = events.next() => {
+                if is_quit(&event) {
+                    shutdown.trigger();
+                }
+            }
+        }
+        if let Err(e) = redraw(&mut terminal, &accepts_per_sec) {
+            ratatui::restore();
+            error!(error = %e, "couldn't draw the dashboard, stopping it");
+            return;
+        }
+    }
+
+    let _ = redraw(&mut terminal, &accepts_per_sec);
+    tokio::select! {
+        () = sleep(LINGER) => {}
+        _ = events.next() => {}
+    }
+    ratatui::restore();
+}
+
+fn is_quit(event: &Event) -> bool {
+    let Event::Key(key) = event else {
+        return false;
+    };
+    key.kind == KeyEventKind::Press
+        && match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => true,
+            KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+            _ => false,
+        }
+}
+
+fn draw(frame: &mut Frame, view: &View) {
+    let [header, conns, rate, footer] =
+        Layout::vertical([Constraint::Length(3), Constraint::Min(4), Constraint::Length(6), Constraint::Length(1)]).areas(frame.area());
+
+    let (phase, color) = match view.phase {
+        Phase::Running => ("running".to_string(), Color::Green),
+        Phase::ShuttingDown { since, progress } => {
+            let progress = progress.as_deref().unwrap_or("waiting for the first stage");
+            (format!("shutting down for {:.1?}: {progress}", since.elapsed()), Color::Yellow)
+        }
+        Phase::Done { elapsed, failed } if failed.is_empty() => (format!("shut down cleanly in {elapsed:.1?}"), Color::Green),
+        Phase::Done { elapsed, failed } => (format!("shut down in {elapsed:.1?}, but not cleanly: {}", failed.join(", ")), Color::Red),
+    };
+    let summary = Line::from(vec![
+        phase.fg(color).bold(),
+        format!(
+            "   up {:.0?}  accepted {}  active {}  panicked {}",
+            view.uptime, view.accepted, view.active, view.panics
+        )
+        .into(),
+    ]);
+    frame.render_widget(Paragraph::new(summary).block(Block::bordered().title(" echo server ")), header);
+
+    let rows = view.conns.iter().map(|conn| {
+        let stats = conn.stats();
+        Row::new([
+            conn.id.to_string(),
+            conn.peer.clone(),
+            stats.bytes_read.to_string(),
+            stats.bytes_written.to_string(),
+            format!("{:.1?}", stats.duration),
+        ])
+    });
+    let widths = [Constraint::Length(6), Constraint::Min(22), Constraint::Length(12), Constraint::Length(12), Constraint::Length(10)];
+    let table = Table::new(rows, widths)
+        .header(Row::new(["id", "peer", "bytes in", "bytes out", "age"]).style(Style::new().bold()))
+        .block(Block::bordered().title(format!(" {} open connection(s) ", view.conns.len())));
+    frame.render_widget(table, conns);
+
+    let data: Vec<u64> = view.accepts_per_sec.iter().copied().collect();
+    let latest = data.last().copied().unwrap_or_default();
+    let sparkline = Sparkline::default()
+        .data(&data)
+        .style(Style::new().fg(Color::Cyan))
+        .block(Block::bordered().title(format!(" accepted per second, last {HISTORY}s (now {latest}) ")));
+    frame.render_widget(sparkline, rate);
+
+    frame.render_widget(Line::from("q, Esc or Ctrl-C: shut down").dim(), footer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_draws_the_connections_and_the_phase() {
+        let conn = Arc::new(ConnInfo::new(7, SocketAddr::from(([127, 0, 0, 1], 40000))));
+        conn.record_in(5);
+        let history = VecDeque::from([0, 2, 1]);
+        let phase = Phase::ShuttingDown {
+            since: Instant::now(),
+            progress: Some("stage 1/3 done: listeners".to_string()),
+        };
+        let view = View {
+            phase: &phase,
+            uptime: Duration::from_secs(12),
+            accepted: 3,
+            active: 1,
+            panics: 0,
+            conns: vec![conn],
+            accepts_per_sec: &history,
+        };
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| draw(frame, &view)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("stage 1/3 done: listeners"), "{screen}");
+        assert!(screen.contains("accepted 3  active 1"), "{screen}");
+        assert!(screen.contains("1 open connection(s)"), "{screen}");
+        assert!(screen.contains("127.0.0.1:40000"), "{screen}");
+        assert!(screen.contains("(now 1)"), "{screen}");
+    }
+}
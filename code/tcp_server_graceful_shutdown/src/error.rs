@@ -0,0 +1,129 @@
+//! The ways the server and its connections can fail.
+//!
+//! Both enums keep the error that caused them as their `source`, so nothing the OS or
+//! a codec said is lost, but they say what was going on at the time: a read and a write
+//! can fail with the same `io::ErrorKind` for very different reasons. Their messages
+//! stop there and leave the cause to `source`, so a reporter that walks the chain (the
+//! log's `error.sources`, anyhow's `{:#}`) doesn't print it twice. The helpers
+//! underneath (binding, the PROXY parser, TLS setup) stay in `io::Error`; the
+//! boundaries that callers see, a connection handler's result and `Server`'s API, are
+//! where it gets sorted into one of these.
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use thiserror::Error;
+use tokio::task::JoinError;
+
+/// Why serving a connection went wrong. A peer hanging up, or the server saying goodbye
+/// at shutdown, isn't an error.
+#[derive(Debug, Error)]
+pub enum ConnectionError {
+    /// Reading from the peer failed, typically because it reset the connection.
+    #[error("read failed")]
+    Read(#[source] io::Error),
+    /// Something the peer has a deadline for took too long.
+    #[error("{0} timed out")]
+    Timeout(Deadline),
+    /// The peer sent something the protocol doesn't allow: a malformed PROXY header,
+    /// a frame or line over the limit, text that isn't UTF-8.
+    #[error("protocol violation")]
+    Protocol(#[source] io::Error),
+    /// The TLS handshake failed.
+    #[error("TLS handshake failed")]
+    Tls(#[source] io::Error),
+    /// The peer went over its rate limit in `RateLimitPolicy::Disconnect` mode.
+    #[error("rate limit exceeded")]
+    RateLimited,
+    /// The server was shutting down and the drain deadline came before the connection
+    /// finished.
+    #[error("aborted at the drain deadline")]
+    ShutdownInterrupted,
+    /// One of the tasks serving the connection, in `split_halves` mode, panicked.
+    #[error("connection task failed")]
+    Task(#[source] JoinError),
+    /// Any other I/O error, most often writing to a peer that has gone away.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl ConnectionError {
+    /// Sorts an error from a decoder or parser: bad input is the peer's fault, anything
+    /// else is the read failing.
+    pub(crate) fn decoding(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::InvalidData {
+            ConnectionError::Protocol(e)
+        } else {
+            ConnectionError::Read(e)
+        }
+    }
+}
+
+/// What a [`ConnectionError::Timeout`] was waiting for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    /// The PROXY protocol header, within `handshake_timeout`.
+    ProxyHeader,
+    /// The TLS handshake, within `handshake_timeout`.
+    TlsHandshake,
+    /// The first bytes of a `Framing::Auto` connection, within `handshake_timeout`.
+    Sniff,
+    /// A reply to the peer, within `write_timeout`.
+    Write,
+}
+
+impl fmt::Display for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Deadline::ProxyHeader => "PROXY header",
+            Deadline::TlsHandshake => "TLS handshake",
+            Deadline::Sniff => "sniffing the first bytes",
+            Deadline::Write => "write",
+        })
+    }
+}
+
+/// Why the server couldn't start, or stopped with an error.
+#[derive(Debug, Error)]
+pub enum ServerError {
+    /// The settings contradict each other or can't work, whatever the environment.
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+    /// The config file couldn't be read or parsed.
+    #[error("couldn't load the config file")]
+    ConfigFile(#[source] io::Error),
+    /// A listen address couldn't be bound.
+    #[error("couldn't listen on {addr}")]
+    Bind {
+        addr: SocketAddr,
+        #[source]
+        source: io::Error,
+    },
+    /// `accept` failed more times in a row than `max_accept_failures` allows.
+    #[error("giving up on accept after repeated failures")]
+    Accept(#[source] io::Error),
+    /// The server task itself panicked.
+    #[error("server task failed")]
+    Task(#[source] JoinError),
+    /// Anything else: systemd's sockets, the certificates, the control socket.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoding_errors_blame_the_peer_only_for_bad_data() {
+        let bad = ConnectionError::decoding(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+        assert!(matches!(bad, ConnectionError::Protocol(_)));
+        assert_eq!(bad.to_string(), "protocol violation");
+        let cause = std::error::Error::source(&bad).expect("the decoder's error is the source");
+        assert_eq!(cause.to_string(), "line too long");
+
+        let reset = ConnectionError::decoding(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(matches!(reset, ConnectionError::Read(_)));
+        assert!(std::error::Error::source(&reset).is_some());
+    }
+}
@@ -0,0 +1,117 @@
+//! A record of what the server did, in order, for tests to make assertions about the
+//! shutdown without scraping the log.
+//!
+//! Hand an [`EventLog`] to [`ServerBuilder::event_log`](crate::ServerBuilder::event_log)
+//! and the server sends it an [`Event`] as each connection is accepted, reads, writes,
+//! sees the shutdown signal and closes, and once the drain is over. Every clone feeds
+//! the same channel, so the order the events come out in is the order they were
+//! recorded in, across all the tasks: if a connection's `Wrote` comes before its
+//! `ShutdownSeen`, the reply was on its way before the connection heard about the
+//! shutdown.
+//!
+//! Broadcast mode only. A connection that is shut down before its handler gets going,
+//! in a PROXY or TLS handshake or while its framing is being sniffed, has no
+//! `ShutdownSeen`, just a `Closed`; one whose task panicked or was aborted at the drain
+//! deadline has no `Closed` either.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{Mutex, mpsc};
+
+/// Something the server did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A connection was admitted; `conn` is its id, as the log's `conn` span has it.
+    Accepted { conn: u64 },
+    /// The connection received `bytes` of payload.
+    Read { conn: u64, bytes: usize },
+    /// The connection echoed `bytes` back.
+    Wrote { conn: u64, bytes: usize },
+    /// The connection's handler got the shutdown signal.
+    ShutdownSeen { conn: u64 },
+    /// The connection's task has finished.
+    Closed { conn: u64 },
+    /// The drain is over: every connection task has finished, `aborted` of them cut
+    /// off at the drain deadline.
+    Drained { aborted: usize },
+}
+
+/// An event, and when it was recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recorded {
+    pub at: Instant,
+    pub event: Event,
+}
+
+/// Where the events go. Cheap to clone; every clone records into the same log.
+#[derive(Clone)]
+pub struct EventLog {
+    tx: mpsc::UnboundedSender<Recorded>,
+    received: Arc<Mutex<Received>>,
+}
+
+/// What's come out of the channel so far, and the channel the rest will come out of.
+struct Received {
+    rx: mpsc::UnboundedReceiver<Recorded>,
+    events: Vec<Recorded>,
+}
+
+impl Received {
+    fn catch_up(&mut self) {
+        while let Ok(recorded) = self.rx.try_recv() {
+            self.events.push(recorded);
+        }
+    }
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            tx,
+            received: Arc::new(Mutex::new(Received { rx, events: Vec::new() })),
+        }
+    }
+
+    pub(crate) fn record(&self, event: Event) {
+        // We hold a receiver, so the channel can't be closed.
+        let _ = self.tx.send(Recorded { at: Instant::now(), event });
+    }
+
+    /// Everything recorded so far, oldest first.
+    pub async fn events(&self) -> Vec<Recorded> {
+        let mut received = self.received.lock().await;
+        received.catch_up();
+        received.events.clone()
+    }
+
+    /// Waits for the first event that `matches`, which may have been recorded already.
+    /// Never returns if none ever does, so put a timeout around it.
+    pub async fn wait_for(&self, matches: impl Fn(&Event) -> bool) -> Recorded {
+        let mut received = self.received.lock().await;
+        received.catch_up();
+        if let Some(recorded) = received.events.iter().find(|recorded| matches(&recorded.event)) {
+            return *recorded;
+        }
+        loop {
+            let recorded = received.rx.recv().await.expect("we hold a sender");
+            received.events.push(recorded);
+            if matches(&recorded.event) {
+                return recorded;
+            }
+        }
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLog").finish_non_exhaustive()
+    }
+}
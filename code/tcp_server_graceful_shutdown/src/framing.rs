@@ -0,0 +1,381 @@
+use crate::config::ServerConfig;
+use crate::config_file::Limits;
+use crate::connection::ConnInfo;
+use crate::error::{ConnectionError, Deadline};
+use crate::event_log::Event;
+use crate::transform::Transformer;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, watch};
+use tokio::time::{Duration, Instant, Interval, interval_at, sleep, timeout};
+use tokio_util::codec::{Framed, FramedRead, FramedWrite, LengthDelimitedCodec, LengthDelimitedCodecError, LinesCodec, LinesCodecError};
+use tracing::{info, instrument};
+
+/// The decoder the length-delimited handler reads with, before a config reload changes
+/// its limit. Public, like [`lines_codec`], so the fuzz targets decode exactly what the
+/// server would.
+pub fn length_decoder(max_message_size: usize) -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder().max_frame_length(max_message_size).new_codec()
+}
+
+/// The codec the line handler reads and writes with.
+pub fn lines_codec(config: &ServerConfig) -> LinesCodec {
+    LinesCodec::new_with_max_length(config.max_line_length)
+}
+
+/// Echoes length-prefixed messages: a 4-byte big-endian length followed by the payload.
+///
+/// The raw handler echoes whatever each `read` happens to return, which may be half a
+/// message or three messages glued together. With a codec, `FramedRead` does the
+/// buffering and splitting for us and we only ever see whole messages. `next` keeps any
+/// partial frame in its internal buffer, so it's safe to race it against shutdown.
+///
+/// The read and write sides get separate codecs because `max_frame_length` applies to
+/// encoding too: with a single `Framed`, a small limit would also stop us sending our
+/// own (longer) error and farewell messages.
+#[instrument(skip_all)]
+pub(crate) async fn handle_length_delimited<S>(
+    socket: S,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    mut limits: watch::Receiver<Limits>,
+    conn: &ConnInfo,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let transformer = Transformer::new(config);
+    let (reader, writer) = tokio::io::split(socket);
+    let mut incoming = FramedRead::new(reader, length_decoder(config.max_message_size));
+    let mut outgoing = FramedWrite::new(writer, LengthDelimitedCodec::new());
+    // Newer tokio-util releases let the codec take `&[u8]` too, so `close`, which sends
+    // nothing, has to be told which kind of sink it's closing.
+
+    let idle = sleep(Duration::ZERO);
+    tokio::pin!(idle);
+    let mut last_frame = Instant::now();
+
+    loop {
+        // As in the raw handler, the latest limits on every pass. The codec takes a new
+        // maximum between frames; one it's halfway through was already checked.
+        let Limits { write_timeout, idle_timeout, max_message_size } = *limits.borrow_and_update();
+        incoming.decoder_mut().set_max_frame_length(max_message_size);
+        if let Some(idle_timeout) = idle_timeout {
+            idle.as_mut().reset(last_frame + idle_timeout);
+        }
+        tokio::select! {
+            Ok(()) = limits.changed() => {}
+            recv = shutdown_rx.recv() => {
+                conn.record(|conn| Event::ShutdownSeen { conn });
+                if recv.is_ok() {
+                    outgoing.send(Bytes::from_static(b"server shutting down")).await?;
+                }
+                return Ok(SinkExt::<Bytes>::close(&mut outgoing).await?);
+            }
+            () = &mut idle, if idle_timeout.is_some() => {
+                outgoing.send(Bytes::from_static(b"idle timeout, closing connection")).await?;
+                return Ok(SinkExt::<Bytes>::close(&mut outgoing).await?);
+            }
+            frame = incoming.next() => {
+                match frame {
+                    // The peer sent FIN. `close` flushes anything still buffered in the sink
+                    // and then shuts down our write side.
+                    None => return Ok(SinkExt::<Bytes>::close(&mut outgoing).await?),
+                    Some(Ok(mut frame)) => {
+                        conn.record_in(frame.len());
+                        last_frame = Instant::now();
+                        transformer.apply_bytes(&mut frame);
+                        if !transformer.should_reply().await {
+                            continue;
+                        }
+                        let len = frame.len();
+                        match timeout(write_timeout, outgoing.send(frame.freeze())).await {
+                            Ok(result) => {
+                                result?;
+                                conn.record_out(len);
+                            }
+                            Err(_) => return Err(ConnectionError::Timeout(Deadline::Write)),
+                        }
+                    }
+                    // The codec checks the length prefix before buffering anything, so an
+                    // oversized frame is refused without reading its payload.
+                    Some(Err(e)) if is_frame_too_big(&e) => {
+                        let reply = format!("error: message larger than {max_message_size} bytes, closing connection");
+                        outgoing.send(Bytes::from(reply)).await?;
+                        // Send our FIN along with the reply. The payload we never read is
+                        // still in the receive buffer, and closing a socket with unread data
+                        // makes the kernel answer with a reset that can overtake the reply.
+                        SinkExt::<Bytes>::close(&mut outgoing).await?;
+                        return Err(ConnectionError::Protocol(e));
+                    }
+                    Some(Err(e)) => return Err(ConnectionError::decoding(e)),
+                }
+            }
+        }
+    }
+}
+
+/// Echoes newline-terminated lines back as `echo: <line>`.
+///
+/// `LinesCodec` strips the trailing `\n` (and `\r`, for telnet) and enforces a maximum
+/// line length, so a client that never sends a newline can't make us buffer forever.
+/// Any decoding error ends the `Framed` stream (the next `next()` returns `None`), so
+/// for an overlong line or invalid UTF-8 we explain what went wrong and then hang up.
+///
+/// With `config.goaway` set, shutdown starts a short negotiation instead of a one-way
+/// farewell: we send `GOAWAY <ms>`, keep echoing whatever the client still had in
+/// flight, and close once it answers `BYE` or the deadline passes. Our replies all
+/// start with `echo: ` or `error: `, so a client can't mistake an echo for the notice.
+///
+/// With `config.heartbeat` set there's one more timer in the loop, an `interval` that
+/// sends `PING`. The client answers `PONG` (which isn't echoed), and if
+/// `heartbeat_misses` pings in a row get no answer we hang up. TCP keepalive does
+/// something similar, but only tells us the peer's kernel is there; a `PONG` means the
+/// client application is still reading and answering. A `PONG` doesn't count as
+/// activity for the idle timeout.
+#[instrument(skip_all)]
+pub(crate) async fn handle_lines<S>(
+    socket: S,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    conn: &ConnInfo,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut lines = Framed::new(socket, lines_codec(config));
+
+    let idle_timeout = config.idle_timeout;
+    let idle = sleep(idle_timeout.unwrap_or_default());
+    tokio::pin!(idle);
+    // The first ping goes out one period in, not the moment the client connects.
+    let mut heartbeat = config.heartbeat.map(|period| interval_at(Instant::now() + period, period));
+    let mut unanswered = 0;
+
+    loop {
+        tokio::select! {
+            recv = shutdown_rx.recv() => {
+                conn.record(|conn| Event::ShutdownSeen { conn });
+                if recv.is_ok() {
+                    match config.goaway {
+                        Some(deadline) => return go_away(lines, deadline, config, conn).await,
+                        None => lines.send("server shutting down").await.map_err(into_io)?,
+                    }
+                }
+                return close(&mut lines).await;
+            }
+            () = &mut idle, if idle_timeout.is_some() => {
+                lines.send("idle timeout, closing connection").await.map_err(into_io)?;
+                return close(&mut lines).await;
+            }
+            () = next_heartbeat(&mut heartbeat) => {
+                if unanswered >= config.heartbeat_misses {
+                    info!(unanswered, "missed too many heartbeats, closing");
+                    lines.send("heartbeat timeout, closing connection").await.map_err(into_io)?;
+                    return close(&mut lines).await;
+                }
+                lines.send("PING").await.map_err(into_io)?;
+                unanswered += 1;
+            }
+            line = lines.next() => {
+                match line {
+                    None => return close(&mut lines).await,
+                    Some(Ok(line)) if line == "PONG" && heartbeat.is_some() => unanswered = 0,
+                    Some(Ok(line)) => {
+                        if let Some(idle_timeout) = idle_timeout {
+                            idle.as_mut().reset(Instant::now() + idle_timeout);
+                        }
+                        echo_line(&mut lines, line, config, conn).await?;
+                    }
+                    Some(Err(e)) => return Err(reject_line(&mut lines, e, config).await),
+                }
+            }
+        }
+    }
+}
+
+/// Announces shutdown with `GOAWAY <ms>` and serves the client until it says `BYE`, it
+/// hangs up, or `deadline` passes.
+///
+/// The deadline should be shorter than the server's drain timeout, or the connection
+/// task gets aborted before it can close cleanly.
+async fn go_away<S>(mut lines: Framed<S, LinesCodec>, deadline: Duration, config: &ServerConfig, conn: &ConnInfo) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    lines
+        .send(format!("GOAWAY {}", deadline.as_millis()))
+        .await
+        .map_err(into_io)?;
+    let expired = sleep(deadline);
+    tokio::pin!(expired);
+
+    loop {
+        tokio::select! {
+            () = &mut expired => {
+                info!(?deadline, "no BYE in time, closing anyway");
+                break;
+            }
+            line = lines.next() => match line {
+                None => break,
+                Some(Ok(line)) if line == "BYE" => {
+                    info!("said BYE, closing");
+                    break;
+                }
+                // The answer to a ping sent just before shutdown.
+                Some(Ok(line)) if line == "PONG" && config.heartbeat.is_some() => {}
+                Some(Ok(line)) => echo_line(&mut lines, line, config, conn).await?,
+                Some(Err(e)) => return Err(reject_line(&mut lines, e, config).await),
+            },
+        }
+    }
+    close(&mut lines).await
+}
+
+/// Waits for the next heartbeat tick, or forever if there's no heartbeat, so a
+/// `select!` can always have the branch.
+async fn next_heartbeat(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(heartbeat) => {
+            heartbeat.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn echo_line<S>(lines: &mut Framed<S, LinesCodec>, line: String, config: &ServerConfig, conn: &ConnInfo) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    conn.record_in(line.len());
+    let transformer = Transformer::new(config);
+    let line = transformer.apply_line(line);
+    if !transformer.should_reply().await {
+        return Ok(());
+    }
+    let reply = format!("echo: {line}");
+    let len = reply.len();
+    match timeout(config.write_timeout, lines.send(reply)).await {
+        Ok(result) => {
+            result.map_err(into_io)?;
+            conn.record_out(len);
+            Ok(())
+        }
+        Err(_) => Err(ConnectionError::Timeout(Deadline::Write)),
+    }
+}
+
+/// Flushes what's left and shuts down our write side.
+async fn close<S>(lines: &mut Framed<S, LinesCodec>) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    Ok(SinkExt::<String>::close(lines).await.map_err(into_io)?)
+}
+
+/// Tells the client why its line was refused, if that's worth explaining, and returns
+/// the error that ends the connection.
+async fn reject_line<S>(lines: &mut Framed<S, LinesCodec>, e: LinesCodecError, config: &ServerConfig) -> ConnectionError
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reply, e) = match e {
+        LinesCodecError::MaxLineLengthExceeded => (
+            format!("error: line longer than {} bytes, closing connection", config.max_line_length),
+            io::Error::new(io::ErrorKind::InvalidData, "line too long"),
+        ),
+        LinesCodecError::Io(e) if e.kind() == io::ErrorKind::InvalidData => {
+            ("error: invalid UTF-8, closing connection".to_string(), e)
+        }
+        LinesCodecError::Io(e) => return ConnectionError::Read(e),
+    };
+    match lines.send(reply).await {
+        Ok(()) => ConnectionError::Protocol(e),
+        Err(send_error) => into_io(send_error).into(),
+    }
+}
+
+fn is_frame_too_big(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<LengthDelimitedCodecError>())
+}
+
+fn into_io(e: LinesCodecError) -> io::Error {
+    match e {
+        LinesCodecError::Io(e) => e,
+        LinesCodecError::MaxLineLengthExceeded => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines};
+    use tokio::task::JoinHandle;
+
+    type Client = Lines<BufReader<DuplexStream>>;
+    type Handler = JoinHandle<Result<(), ConnectionError>>;
+
+    fn serve_lines(config: ServerConfig) -> (Client, broadcast::Sender<()>, Handler) {
+        let (client, server) = tokio::io::duplex(1024);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let task = tokio::spawn(async move { handle_lines(server, shutdown_rx, &config, &ConnInfo::new(1, "test")).await });
+        (BufReader::new(client).lines(), shutdown_tx, task)
+    }
+
+    fn heartbeat_config() -> ServerConfig {
+        ServerConfig {
+            heartbeat: Some(Duration::from_secs(1)),
+            heartbeat_misses: 3,
+            ..ServerConfig::default()
+        }
+    }
+
+    /// The next line from the server, and how long it took to come. With the clock
+    /// paused, tokio jumps it straight to the next timer whenever everything is waiting,
+    /// so that's exactly the server's own timing.
+    async fn next_line(lines: &mut Client) -> (String, Duration) {
+        let started = Instant::now();
+        let line = lines.next_line().await.unwrap().expect("still connected");
+        (line, started.elapsed())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unanswered_heartbeats_close_the_connection() {
+        let (mut lines, _shutdown_tx, task) = serve_lines(heartbeat_config());
+        for _ in 0..3 {
+            assert_eq!(next_line(&mut lines).await, ("PING".to_string(), Duration::from_secs(1)));
+        }
+        // The fourth tick finds three pings unanswered.
+        assert_eq!(next_line(&mut lines).await, ("heartbeat timeout, closing connection".to_string(), Duration::from_secs(1)));
+        task.await.unwrap().unwrap();
+        assert!(lines.next_line().await.unwrap().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_answered_heartbeats_keep_the_connection_open() {
+        let (mut lines, shutdown_tx, task) = serve_lines(heartbeat_config());
+        for _ in 0..10 {
+            assert_eq!(next_line(&mut lines).await.0, "PING");
+            lines.get_mut().get_mut().write_all(b"PONG\n").await.unwrap();
+        }
+        assert!(!task.is_finished());
+
+        shutdown_tx.send(()).unwrap();
+        assert_eq!(next_line(&mut lines).await, ("server shutting down".to_string(), Duration::ZERO));
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_goaway_closes_at_its_deadline_without_a_bye() {
+        let config = ServerConfig { goaway: Some(Duration::from_secs(5)), ..ServerConfig::default() };
+        let (mut lines, shutdown_tx, task) = serve_lines(config);
+        shutdown_tx.send(()).unwrap();
+        assert_eq!(next_line(&mut lines).await.0, "GOAWAY 5000");
+
+        let started = Instant::now();
+        task.await.unwrap().unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,76 @@
+//! Handing a port over from a running server to its replacement.
+//!
+//! With `SO_REUSEPORT` on both, a new server can listen on the port the old one is
+//! still serving. The restart then goes:
+//!
+//! 1. the new server binds and starts accepting, so there's never a moment with
+//!    nobody listening;
+//! 2. it reads the old server's pid from the pid file and sends it `SIGTERM`;
+//! 3. the old server runs its normal graceful shutdown: it stops accepting, says
+//!    goodbye to its clients and drains, while new clients all land on the new one.
+//!
+//! The signal is the whole protocol between the two processes, which keeps the old
+//! server ignorant of the handoff: it just sees an ordinary `SIGTERM`. The pid file is
+//! the rendezvous, and each server only removes it if it still names itself.
+//!
+//! One wrinkle: on Linux each `SO_REUSEPORT` listener has its own accept queue, and
+//! connections still sitting in the old one when it closes are reset. The window is
+//! small (the old server stops accepting and closes straight away), but it's there.
+
+use std::io;
+use std::path::Path;
+
+/// Reads the pid a running server left in `path`, or `None` if there's no file.
+pub fn read_pid_file(path: &Path) -> io::Result<Option<u32>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => contents.trim().parse().map(Some).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: not a pid: {e}", path.display()),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Records our pid in `path`, replacing whatever was there.
+///
+/// Written to a temporary file first and renamed into place, so a server reading it
+/// at the same moment sees the old pid or the new one, never half of one.
+pub fn write_pid_file(path: &Path) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, format!("{}\n", std::process::id()))?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Removes `path` if it still holds our pid; if a replacement has overwritten it,
+/// the file is theirs now.
+pub fn remove_pid_file_if_ours(path: &Path) -> io::Result<()> {
+    if read_pid_file(path)? == Some(std::process::id()) {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Asks the server with `pid` to shut down gracefully, by sending it `SIGTERM`.
+#[cfg(unix)]
+pub fn ask_to_drain(pid: u32) -> io::Result<()> {
+    let pid = libc::pid_t::try_from(pid).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "pid out of range"))?;
+    // SAFETY: `kill` takes plain integers and touches no memory of ours.
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Asks the server with `pid` to shut down gracefully. Not available here: there's
+/// no `SIGTERM`, and no `SO_REUSEPORT` to share the port with either.
+#[cfg(not(unix))]
+pub fn ask_to_drain(_pid: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "handing over to a new server needs Unix signals",
+    ))
+}
@@ -0,0 +1,432 @@
+//! A hand-rolled, deliberately minimal HTTP/1.1 responder (`--framing http`).
+//!
+//! Instead of echoing, the connection answers four endpoints:
+//!
+//! - `GET /health`: `200 ok`, for load balancer probes;
+//! - `GET /stats`: live server counters as JSON;
+//! - `GET /metrics`: the same and a little more, for Prometheus (see `prometheus`);
+//! - `POST /shutdown`: `202`, then the same broadcast shutdown as Ctrl-C.
+//!
+//! No HTTP crate is involved, to show what one does for you. Requests are parsed
+//! incrementally: bytes accumulate in a buffer until it holds a whole head (everything
+//! up to the blank line), and whatever follows stays put for the next request, so
+//! pipelined requests work without trying. Keep-alive follows the version defaults:
+//! HTTP/1.1 keeps the connection open unless the client says `Connection: close`, and
+//! HTTP/1.0 closes it unless the client says `Connection: keep-alive`.
+//!
+//! On shutdown an idle keep-alive connection is simply closed, which HTTP allows at
+//! any time between requests. A connection caught halfway through a request gets to
+//! finish it, and the response carries `Connection: close`.
+//!
+//! Left out on purpose: chunked bodies (refused with `501`), `Expect: 100-continue`,
+//! header folding, and anything but the two 1.x versions.
+
+use crate::chaos::Chaos;
+use crate::config::ServerConfig;
+use crate::connection::ConnInfo;
+use crate::error::{ConnectionError, Deadline};
+use crate::event_log::Event;
+use crate::prometheus;
+use crate::stats::LiveStats;
+use bytes::{Buf, BytesMut};
+use shutdown_util::ShutdownTrigger;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::time::{Duration, Instant, Sleep, sleep, timeout};
+use tracing::{info, instrument};
+
+/// The most a request line plus headers may take. Anything bigger gets a `431`.
+const MAX_HEAD_LEN: usize = 8 * 1024;
+/// How much we ask the kernel for per read.
+const READ_CHUNK: usize = 1024;
+/// How long a scrape on the `--metrics` listener may take, request and response.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the endpoints need from beyond their own connection.
+pub(crate) struct Admin {
+    pub(crate) stats: Arc<LiveStats>,
+    pub(crate) shutdown: ShutdownTrigger,
+    pub(crate) chaos: Arc<Chaos>,
+}
+
+/// The parts of a request head we act on.
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    /// Without the query string.
+    path: String,
+    keep_alive: bool,
+    content_length: usize,
+}
+
+/// Why a request was refused before routing.
+#[derive(Debug, PartialEq, Eq)]
+enum HttpError {
+    BadRequest(&'static str),
+    HeadTooLarge,
+    BodyTooLarge,
+    ChunkedBody,
+    UnsupportedVersion,
+}
+
+impl HttpError {
+    fn response(&self) -> Response {
+        let (status, message) = match self {
+            HttpError::BadRequest(why) => (400, *why),
+            HttpError::BodyTooLarge => (413, "request body too large"),
+            HttpError::HeadTooLarge => (431, "request head too large"),
+            HttpError::ChunkedBody => (501, "chunked request bodies are not supported"),
+            HttpError::UnsupportedVersion => (505, "only HTTP/1.0 and HTTP/1.1 are supported"),
+        };
+        Response::text(status, format!("{message}\n"))
+    }
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+    allow: Option<&'static str>,
+}
+
+impl Response {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+            allow: None,
+        }
+    }
+
+    fn method_not_allowed(allow: &'static str) -> Self {
+        Self {
+            allow: Some(allow),
+            ..Self::text(405, "method not allowed\n")
+        }
+    }
+
+    /// The response as bytes on the wire. `HEAD` gets the headers of the `GET`
+    /// response, `Content-Length` included, but no body.
+    fn encode(&self, keep_alive: bool, include_body: bool) -> Vec<u8> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: {}\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len(),
+            if keep_alive { "keep-alive" } else { "close" },
+        );
+        if let Some(allow) = self.allow {
+            head.push_str(&format!("Allow: {allow}\r\n"));
+        }
+        head.push_str("\r\n");
+        let mut bytes = head.into_bytes();
+        if include_body {
+            bytes.extend_from_slice(self.body.as_bytes());
+        }
+        bytes
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+/// Parses the request head at the start of `buf`. Returns the request and the head's
+/// length, or `Ok(None)` if the blank line that ends it hasn't arrived yet.
+fn parse_request(buf: &[u8]) -> Result<Option<(Request, usize)>, HttpError> {
+    let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return if buf.len() > MAX_HEAD_LEN { Err(HttpError::HeadTooLarge) } else { Ok(None) };
+    };
+    if end > MAX_HEAD_LEN {
+        return Err(HttpError::HeadTooLarge);
+    }
+    let head = std::str::from_utf8(&buf[..end]).map_err(|_| HttpError::BadRequest("request head is not UTF-8"))?;
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(HttpError::BadRequest("malformed request line"));
+    };
+    if method.is_empty() || !target.starts_with('/') {
+        return Err(HttpError::BadRequest("malformed request line"));
+    }
+    let mut keep_alive = match version {
+        "HTTP/1.1" => true,
+        "HTTP/1.0" => false,
+        v if v.starts_with("HTTP/") => return Err(HttpError::UnsupportedVersion),
+        _ => return Err(HttpError::BadRequest("malformed request line")),
+    };
+
+    let mut content_length = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(HttpError::BadRequest("malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().map_err(|_| HttpError::BadRequest("invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(HttpError::ChunkedBody);
+        } else if name.eq_ignore_ascii_case("connection") {
+            for option in value.split(',').map(str::trim) {
+                if option.eq_ignore_ascii_case("close") {
+                    keep_alive = false;
+                } else if option.eq_ignore_ascii_case("keep-alive") {
+                    keep_alive = true;
+                }
+            }
+        }
+    }
+
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    Ok(Some((
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            keep_alive,
+            content_length,
+        },
+        end + 4,
+    )))
+}
+
+/// Picks the response for `request`. The `bool` is whether to shut the server down
+/// once it's sent.
+fn route(request: &Request, admin: &Admin) -> (Response, bool) {
+    match (request.path.as_str(), request.method.as_str()) {
+        ("/health", "GET" | "HEAD") => (Response::text(200, "ok\n"), false),
+        ("/health", _) => (Response::method_not_allowed("GET, HEAD"), false),
+        ("/stats", "GET" | "HEAD") => {
+            let response = Response {
+                content_type: "application/json",
+                ..Response::text(200, admin.stats.to_json() + "\n")
+            };
+            (response, false)
+        }
+        ("/stats", _) => (Response::method_not_allowed("GET, HEAD"), false),
+        ("/metrics", "GET" | "HEAD") => (metrics_response(), false),
+        ("/metrics", _) => (Response::method_not_allowed("GET, HEAD"), false),
+        // POST only: a GET must be safe to repeat, and browsers, crawlers and link
+        // previews all send GETs without asking anyone.
+        ("/shutdown", "POST") => (Response::text(202, "shutting down\n"), true),
+        ("/shutdown", _) => (Response::method_not_allowed("POST"), false),
+        _ => (Response::text(404, "not found\n"), false),
+    }
+}
+
+fn metrics_response() -> Response {
+    Response {
+        content_type: "text/plain; version=0.0.4; charset=utf-8",
+        ..Response::text(200, prometheus::render())
+    }
+}
+
+/// Answers one request on the `--metrics` listener, where `/metrics` is the only
+/// endpoint, and closes the connection. No keep-alive and no shutdown handling: a
+/// scrape is over in a moment, and the listener outlives the drain anyway.
+pub(crate) async fn answer_scrape(mut socket: TcpStream) -> io::Result<()> {
+    let scrape = async {
+        let mut buf = BytesMut::with_capacity(READ_CHUNK);
+        let (response, include_body) = loop {
+            match parse_request(&buf) {
+                Ok(Some((request, _))) => {
+                    let response = match (request.path.as_str(), request.method.as_str()) {
+                        ("/metrics", "GET" | "HEAD") => metrics_response(),
+                        ("/metrics", _) => Response::method_not_allowed("GET, HEAD"),
+                        _ => Response::text(404, "not found\n"),
+                    };
+                    break (response, request.method != "HEAD");
+                }
+                Ok(None) => {}
+                Err(e) => break (e.response(), true),
+            }
+            if socket.read_buf(&mut buf).await? == 0 {
+                return Ok(());
+            }
+        };
+        socket.write_all(&response.encode(false, include_body)).await?;
+        socket.shutdown().await
+    };
+    timeout(SCRAPE_TIMEOUT, scrape)
+        .await
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "the scrape took too long")))
+}
+
+/// Serves HTTP requests until the client closes, asks to close, or shutdown is
+/// signalled between requests.
+#[instrument(skip_all)]
+pub(crate) async fn handle_http<S>(
+    mut socket: S,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    conn: &ConnInfo,
+    admin: &Admin,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = BytesMut::with_capacity(READ_CHUNK);
+    let mut draining = false;
+
+    let idle_timeout = config.idle_timeout;
+    let idle = sleep(idle_timeout.unwrap_or_default());
+    tokio::pin!(idle);
+
+    loop {
+        // Answer everything already buffered before reading again: a client may have
+        // pipelined several requests into one write.
+        let parsed = parse_request(&buf).and_then(|parsed| match parsed {
+            Some((request, _)) if request.content_length > config.max_message_size => Err(HttpError::BodyTooLarge),
+            other => Ok(other),
+        });
+        match parsed {
+            Err(e) => {
+                info!(error = ?e, "bad request");
+                send(&mut socket, &e.response().encode(false, true), config, conn).await?;
+                return Ok(socket.shutdown().await?);
+            }
+            // We don't use request bodies, but a body we didn't skip would be read as
+            // the start of the next request.
+            Ok(Some((request, head_len))) if buf.len() >= head_len + request.content_length => {
+                let len = head_len + request.content_length;
+                buf.advance(len);
+                conn.record_in(len);
+                idle_reset(&mut idle, idle_timeout);
+
+                let (response, shut_down) = route(&request, admin);
+                let keep_alive = request.keep_alive && !draining && !shut_down;
+                info!(method = %request.method, path = %request.path, status = %response.status, "request");
+                send(&mut socket, &response.encode(keep_alive, request.method != "HEAD"), config, conn).await?;
+                if shut_down {
+                    info!("asked for shutdown over HTTP");
+                    admin.shutdown.trigger();
+                }
+                if !keep_alive {
+                    return Ok(socket.shutdown().await?);
+                }
+                continue;
+            }
+            Ok(_) => {}
+        }
+        // Draining, and no half-received request to finish: we're done.
+        if draining && buf.is_empty() {
+            return Ok(socket.shutdown().await?);
+        }
+
+        tokio::select! {
+            recv = shutdown_rx.recv(), if !draining => {
+                conn.record(|conn| Event::ShutdownSeen { conn });
+                // A closed channel means nobody will ever signal; only a real signal
+                // ends keep-alive.
+                if recv.is_ok() {
+                    draining = true;
+                }
+            }
+            () = &mut idle, if idle_timeout.is_some() => return Ok(socket.shutdown().await?),
+            read = socket.read_buf(&mut buf) => match read {
+                // A client may hang up between requests (that's how HTTP/1.0 ends) or
+                // give up on one halfway; either way there's no one left to answer.
+                Ok(0) => return Ok(socket.shutdown().await?),
+                Ok(_) => idle_reset(&mut idle, idle_timeout),
+                Err(e) => return Err(ConnectionError::Read(e)),
+            },
+        }
+    }
+}
+
+fn idle_reset(idle: &mut Pin<&mut Sleep>, idle_timeout: Option<Duration>) {
+    if let Some(idle_timeout) = idle_timeout {
+        idle.as_mut().reset(Instant::now() + idle_timeout);
+    }
+}
+
+async fn send<S>(socket: &mut S, bytes: &[u8], config: &ServerConfig, conn: &ConnInfo) -> Result<(), ConnectionError>
+where
+    S: AsyncWrite + Unpin,
+{
+    match timeout(config.write_timeout, socket.write_all(bytes)).await {
+        Ok(result) => {
+            result?;
+            conn.record_out(bytes.len());
+            Ok(())
+        }
+        Err(_) => Err(ConnectionError::Timeout(Deadline::Write)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(head: &str) -> Result<Option<Request>, HttpError> {
+        parse_request(head.as_bytes()).map(|parsed| parsed.map(|(request, _)| request))
+    }
+
+    #[test]
+    fn test_parse_waits_for_the_blank_line() {
+        assert_eq!(parse("GET /health HTTP/1.1\r\nHost: x\r\n"), Ok(None));
+        let (request, len) = parse_request(b"GET /stats?pretty HTTP/1.1\r\nHost: x\r\n\r\nGET /next").unwrap().unwrap();
+        assert_eq!(
+            request,
+            Request {
+                method: "GET".into(),
+                path: "/stats".into(),
+                keep_alive: true,
+                content_length: 0,
+            }
+        );
+        assert_eq!(len, 39);
+    }
+
+    #[test]
+    fn test_keep_alive_follows_version_and_connection_header() {
+        let keep_alive = |head: &str| parse(head).unwrap().unwrap().keep_alive;
+        assert!(keep_alive("GET / HTTP/1.1\r\n\r\n"));
+        assert!(!keep_alive("GET / HTTP/1.1\r\nConnection: close\r\n\r\n"));
+        assert!(!keep_alive("GET / HTTP/1.0\r\n\r\n"));
+        assert!(keep_alive("GET / HTTP/1.0\r\nconnection: Keep-Alive\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_bad_requests_are_refused() {
+        assert_eq!(parse("GET /\r\n\r\n"), Err(HttpError::BadRequest("malformed request line")));
+        assert_eq!(parse("GET health HTTP/1.1\r\n\r\n"), Err(HttpError::BadRequest("malformed request line")));
+        assert_eq!(parse("GET / HTTP/2\r\n\r\n"), Err(HttpError::UnsupportedVersion));
+        assert_eq!(parse("GET / HTTP/1.1\r\nno colon\r\n\r\n"), Err(HttpError::BadRequest("malformed header")));
+        assert_eq!(
+            parse("POST / HTTP/1.1\r\nContent-Length: lots\r\n\r\n"),
+            Err(HttpError::BadRequest("invalid Content-Length"))
+        );
+        assert_eq!(parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"), Err(HttpError::ChunkedBody));
+        let huge = format!("GET / HTTP/1.1\r\nX: {}", "a".repeat(MAX_HEAD_LEN));
+        assert_eq!(parse(&huge), Err(HttpError::HeadTooLarge));
+    }
+
+    #[test]
+    fn test_head_response_has_length_but_no_body() {
+        let response = Response::text(200, "ok\n");
+        let full = String::from_utf8(response.encode(true, true)).unwrap();
+        let head = String::from_utf8(response.encode(true, false)).unwrap();
+        assert!(full.ends_with("\r\n\r\nok\n"));
+        assert!(head.ends_with("Content-Length: 3\r\nConnection: keep-alive\r\n\r\n"));
+    }
+}
@@ -0,0 +1,48 @@
+mod accept;
+mod accept_rate;
+mod activation;
+mod backoff;
+pub mod cancel_safety;
+mod cert_reload;
+mod chaos;
+pub mod client;
+pub mod config;
+mod config_file;
+mod connection;
+mod control;
+#[cfg(feature = "tui")]
+mod dashboard;
+mod error;
+pub mod event_log;
+mod framing;
+pub mod handoff;
+mod http;
+pub mod listener;
+mod panics;
+mod periodic;
+#[cfg(windows)]
+pub mod pipe;
+mod prometheus;
+pub mod proxy;
+mod rate_limit;
+mod registry;
+mod runtime_sampler;
+mod sentinel;
+mod server;
+mod sniff;
+mod soak;
+mod sockopt;
+mod split;
+mod stats;
+mod stats_file;
+mod tasks;
+pub mod telemetry;
+pub mod tls;
+mod token;
+mod tracker;
+mod transform;
+
+pub use config::{CertFiles, ChaosConfig, Framing, OverloadPolicy, RateLimitPolicy, ServerConfig, ShutdownMode, StopDeadlines, Transform};
+pub use error::{ConnectionError, Deadline, ServerError};
+pub use framing::{length_decoder, lines_codec};
+pub use server::{Server, ServerBuilder, ServerHandle};
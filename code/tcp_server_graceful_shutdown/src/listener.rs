@@ -0,0 +1,73 @@
+//! What the server accepts connections from, so it can run over more than a tokio
+//! `TcpListener`: a simulated network's listener, in the turmoil tests, or anything
+//! else that hands out connected byte streams.
+//!
+//! Hand some to [`ServerBuilder::build_with_listeners`] and the whole server runs on
+//! them: the accept loops with their backoff, admission and accept rate, every framing,
+//! TLS, the stats, the drain and the shutdown stages.
+//!
+//! [`ServerBuilder::build_with_listeners`]: crate::ServerBuilder::build_with_listeners
+//!
+//! A few things only mean something on a real socket: the options `sockopt` sets, and
+//! the `SO_LINGER` trick chaos mode uses to reset a connection. [`Connection`] has a
+//! method for each, which does nothing unless the stream has a socket underneath.
+
+use crate::config::ServerConfig;
+use crate::sockopt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Something to accept connections from.
+pub trait Listener: Send + 'static {
+    type Stream: Connection;
+
+    /// Waits for the next connection. Must be cancel-safe: the accept loops are aborted
+    /// at shutdown, and a connection it was about to return mustn't be lost.
+    fn accept(&mut self) -> impl Future<Output = io::Result<(Self::Stream, SocketAddr)>> + Send;
+
+    /// The address this listener accepts connections on.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+/// An accepted connection: a byte stream, and whatever socket there is underneath.
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    /// Applies the socket options in `config` and logs what the stream ended up with.
+    /// Does nothing by default, for streams with no options to set.
+    fn configure(&self, config: &ServerConfig) {
+        let _ = config;
+    }
+
+    /// Makes closing the stream reset the connection rather than shut it down. Not
+    /// supported by default.
+    fn reset_on_close(&self) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this stream has no socket to reset"))
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept(&mut self) -> impl Future<Output = io::Result<(Self::Stream, SocketAddr)>> + Send {
+        TcpListener::accept(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpListener::local_addr(self)
+    }
+}
+
+impl Connection for TcpStream {
+    fn configure(&self, config: &ServerConfig) {
+        sockopt::configure_and_log(self, config);
+    }
+
+    fn reset_on_close(&self) -> io::Result<()> {
+        // With a zero linger time the kernel throws away anything unsent and resets the
+        // connection instead of shutting it down.
+        socket2::SockRef::from(self).set_linger(Some(Duration::ZERO))
+    }
+}
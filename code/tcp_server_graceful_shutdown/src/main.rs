@@ -1,131 +1,434 @@
-use std::io;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::broadcast;
-use tokio::task::JoinSet;
-use tokio::time::{Duration, timeout};
+use clap::Parser;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{error, info};
+use tracing_subscriber::prelude::*;
+use tcp_server_graceful_shutdown::{CertFiles, ChaosConfig, Framing, OverloadPolicy, RateLimitPolicy, Server, ServerConfig, ShutdownMode, StopDeadlines, Transform, handoff, telemetry};
+use tcp_server_graceful_shutdown::telemetry::LogFormat;
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    let addr = "127.0.0.1:3011";
-    let listener = TcpListener::bind(addr).await?;
-    println!("[main] listening on {addr}");
-
-    let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(16);
-    let server_task = tokio::spawn(run_server(listener, shutdown_rx));
-
-    tokio::time::sleep(Duration::from_millis(150)).await;
+/// An echo server that drains its connections gracefully on SIGINT/SIGTERM.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Shutdown mechanism to use.
+    #[arg(long, value_enum, default_value_t = ShutdownMode::Broadcast)]
+    mode: ShutdownMode,
+    /// How messages are delimited on the wire.
+    #[arg(long, value_enum, default_value_t = Framing::Raw)]
+    framing: Framing,
+    /// Largest frame accepted with --framing length (or request body with --framing
+    /// http), in bytes.
+    #[arg(long, value_name = "BYTES", default_value_t = 1024 * 1024)]
+    max_message_size: usize,
+    /// Longest line accepted with --framing lines, in bytes.
+    #[arg(long, value_name = "BYTES", default_value_t = 1024)]
+    max_line_length: usize,
+    /// Serve raw connections with a reader task and a writer task per connection.
+    #[arg(long)]
+    split_halves: bool,
+    /// Chunks that may wait for the writer task with --split-halves.
+    #[arg(long, value_name = "N", default_value_t = 32)]
+    outbound_queue: usize,
+    /// Seconds a --split-halves connection gets at shutdown to flush its queue.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    shutdown_flush_timeout: Duration,
+    /// Set TCP_NODELAY on accepted sockets (turn off Nagle's algorithm).
+    #[arg(long)]
+    nodelay: bool,
+    /// Turn on TCP keepalive, probing after SECS without traffic.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    keepalive: Option<Duration>,
+    /// Set SO_LINGER on accepted sockets; 0 makes close send a reset.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    linger: Option<Duration>,
+    /// Stop after N accept errors in a row (0 keeps retrying forever).
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    max_accept_failures: u32,
+    /// Expect a PROXY protocol (v1 or v2) header on every connection, as sent by
+    /// HAProxy and most cloud load balancers, and log the client it names.
+    #[arg(long)]
+    proxy_protocol: bool,
+    /// Serve TLS with a certificate from a demo CA generated at startup.
+    #[arg(long)]
+    tls: bool,
+    /// Serve TLS and require a client certificate from the demo CA (implies --tls).
+    #[arg(long)]
+    mtls: bool,
+    /// Where TLS mode writes the demo CA certificate, for the client's --tls-ca.
+    #[arg(long, value_name = "PATH", default_value = "echo-ca.pem")]
+    tls_ca_out: PathBuf,
+    /// Where --mtls writes a client certificate and key, for the client's --tls-identity.
+    #[arg(long, value_name = "PATH", default_value = "echo-client.pem")]
+    tls_client_out: PathBuf,
+    /// Serve TLS with this PEM certificate chain instead of a demo CA, reloading it
+    /// when the file changes.
+    #[arg(long, value_name = "PATH", conflicts_with = "mtls")]
+    tls_cert: Option<PathBuf>,
+    /// The PEM private key for --tls-cert, if it isn't in the same file.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Seconds between checks of --tls-cert and --tls-key for changes.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    tls_reload_interval: Duration,
+    /// Seconds a client may take to send its PROXY header and complete the TLS handshake.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    handshake_timeout: Duration,
+    /// Read settings from this TOML file, on top of the flags, and apply changes to its
+    /// timeouts and message size while running. See `server.toml` for the keys.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Seconds between checks of --config for changes.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    config_reload_interval: Duration,
+    /// Address to listen on; repeat to listen on several, e.g. `--bind [::1]:3011`.
+    #[arg(long, default_value = "127.0.0.1:3011")]
+    bind: Vec<SocketAddr>,
+    /// Set SO_REUSEPORT, so another instance can listen on the same port (Unix only).
+    #[arg(long)]
+    reuseport: bool,
+    /// Also accept operator commands on this (loopback) address, e.g. 127.0.0.1:3021:
+    /// STATS, CONNS, DRAIN <secs> and ABORT, one per line.
+    #[arg(long, value_name = "ADDR")]
+    control: Option<SocketAddr>,
+    /// Serve Prometheus metrics at http://ADDR/metrics, e.g. 127.0.0.1:9011 (broadcast
+    /// mode). With --framing http they're at /metrics on the server itself as well.
+    #[arg(long, value_name = "ADDR")]
+    metrics: Option<SocketAddr>,
+    /// Write our pid here once listening, for a later --takeover to find.
+    #[arg(long, value_name = "PATH")]
+    pid_file: Option<PathBuf>,
+    /// Start alongside the server named in --pid-file, then tell it to drain: a
+    /// zero-downtime restart. Implies --reuseport; the old server needs it too.
+    #[arg(long, requires = "pid_file")]
+    takeover: bool,
+    /// Use the listening sockets systemd passes in (LISTEN_FDS) when started by a
+    /// `.socket` unit; --bind is only used when there are none.
+    #[arg(long)]
+    socket_activation: bool,
+    /// Serve over this Windows named pipe (e.g. `\\.\pipe\echo`) instead of TCP.
+    #[cfg(windows)]
+    #[arg(long, value_name = "NAME")]
+    pipe: Option<String>,
+    /// Seconds a single echo write may take before the connection is dropped.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    write_timeout: Duration,
+    /// Seconds a connection may stay silent before it is closed (never if omitted).
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    idle_timeout: Option<Duration>,
+    /// Seconds to wait for connections to finish after shutdown before aborting them.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+    /// Write the live stats as JSON to PATH every --stats-flush-interval, and once more
+    /// at shutdown.
+    #[arg(long, value_name = "PATH")]
+    stats_file: Option<PathBuf>,
+    /// Seconds between writes of --stats-file.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    stats_flush_interval: Duration,
+    /// Seconds between sweeps of closed connections out of the control socket's list.
+    #[arg(long, value_name = "SECS", default_value = "30", value_parser = parse_secs)]
+    sweep_interval: Duration,
+    /// Log the tokio runtime's metrics (worker busy time, queue depths, how long a probe
+    /// task waits to run) every SECS, and once more at shutdown.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    runtime_metrics: Option<Duration>,
+    /// Log the resident set, the connection tasks and the runtime's live tasks every
+    /// SECS (10 if not given), to catch leaks in a long run under churning load.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs, num_args = 0..=1, default_missing_value = "10")]
+    soak_report: Option<Duration>,
+    /// How to write the log: text, or a JSON object per line for `jq`.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Show a live dashboard of the connections, the accept rate and the shutdown in
+    /// the terminal instead of the log; `q` shuts down. Needs the `tui` feature.
+    #[arg(long)]
+    tui: bool,
+    /// With --framing lines, send `GOAWAY <ms>` on shutdown and give clients SECS to
+    /// finish and answer `BYE`.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    goaway: Option<Duration>,
+    /// With --framing lines, send `PING` every SECS and expect `PONG` back.
+    #[arg(long, value_name = "SECS", value_parser = parse_secs)]
+    heartbeat: Option<Duration>,
+    /// How many `PING`s in a row may go unanswered before a connection is closed.
+    #[arg(long, value_name = "N", default_value = "3")]
+    heartbeat_misses: u32,
+    /// Start with chaos mode on: randomly stalled reads, partial writes, resets and
+    /// delayed accepts. Switch it with `CHAOS ON|OFF` on the --control socket.
+    #[arg(long)]
+    chaos: bool,
+    /// The chance, from 0 to 1, that a read stalls in chaos mode.
+    #[arg(long, value_name = "P", default_value = "0.05")]
+    chaos_read_stall: f64,
+    /// Seconds a stalled read waits.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    chaos_stall: Duration,
+    /// The chance that a write only takes part of its buffer in chaos mode.
+    #[arg(long, value_name = "P", default_value = "0.2")]
+    chaos_partial_write: f64,
+    /// The chance, on each read, that the connection is reset in chaos mode.
+    #[arg(long, value_name = "P", default_value = "0.01")]
+    chaos_reset: f64,
+    /// The chance that a new connection is left waiting in chaos mode.
+    #[arg(long, value_name = "P", default_value = "0.2")]
+    chaos_delayed_accept: f64,
+    /// Seconds a delayed connection waits before it's served.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    chaos_accept_delay: Duration,
+    /// For testing: make the task of every Nth connection panic as soon as it's
+    /// admitted.
+    #[arg(long, value_name = "N")]
+    panic_injection: Option<u64>,
+    /// Shut down once N connection tasks have panicked (never if omitted).
+    #[arg(long, value_name = "N")]
+    max_panics: Option<u64>,
+    /// Change each message before echoing it, to stand in for a misbehaving server.
+    #[arg(long, value_enum, default_value_t = Transform::None)]
+    transform: Transform,
+    /// Seconds --transform delay holds each reply back.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    transform_delay: Duration,
+    /// The chance, from 0 to 1, that --transform drop swallows a reply.
+    #[arg(long, value_name = "P", default_value = "0.5")]
+    drop_probability: f64,
+    /// Maximum number of connections served at once (unlimited if omitted).
+    #[arg(long, value_name = "N")]
+    max_connections: Option<usize>,
+    /// What to do with new connections while at --max-connections.
+    #[arg(long, value_enum, default_value_t = OverloadPolicy::Wait)]
+    when_full: OverloadPolicy,
+    /// New connections admitted a second, across all listeners (unlimited if omitted).
+    #[arg(long, value_name = "N")]
+    accept_rate: Option<u32>,
+    /// Connections admitted at once after a quiet spell, before --accept-rate applies.
+    #[arg(long, value_name = "N", default_value = "10")]
+    accept_burst: u32,
+    /// Bytes a second each raw connection may send (unlimited if omitted).
+    #[arg(long, value_name = "BYTES")]
+    rate_limit_bytes: Option<u64>,
+    /// Messages (reads) a second each raw connection may send (unlimited if omitted).
+    #[arg(long, value_name = "N")]
+    rate_limit_messages: Option<u32>,
+    /// What to do with a connection over --rate-limit-bytes or --rate-limit-messages.
+    #[arg(long, value_enum, default_value_t = RateLimitPolicy::Delay)]
+    over_rate_limit: RateLimitPolicy,
+}
 
-    run_client("client-1", addr, b"hello from client 1").await?;
-    run_client("client-2", addr, b"hello from client 2").await?;
+impl Cli {
+    fn server_config(&self) -> ServerConfig {
+        ServerConfig {
+            mode: self.mode,
+            framing: self.framing,
+            max_message_size: self.max_message_size,
+            max_line_length: self.max_line_length,
+            split_halves: self.split_halves,
+            outbound_queue: self.outbound_queue,
+            shutdown_flush_timeout: self.shutdown_flush_timeout,
+            nodelay: self.nodelay,
+            keepalive: self.keepalive,
+            linger: self.linger,
+            max_accept_failures: (self.max_accept_failures > 0).then_some(self.max_accept_failures),
+            proxy_protocol: self.proxy_protocol,
+            tls: self.tls,
+            mtls: self.mtls,
+            tls_files: self.tls_cert.clone().map(|cert| CertFiles {
+                key: self.tls_key.clone().unwrap_or_else(|| cert.clone()),
+                cert,
+            }),
+            tls_reload_interval: self.tls_reload_interval,
+            handshake_timeout: self.handshake_timeout,
+            config_file: self.config.clone(),
+            config_reload_interval: self.config_reload_interval,
+            bind: self.bind.clone(),
+            reuseport: self.reuseport || self.takeover,
+            control: self.control,
+            metrics: self.metrics,
+            socket_activation: self.socket_activation,
+            write_timeout: self.write_timeout,
+            idle_timeout: self.idle_timeout,
+            drain_timeout: self.drain_timeout,
+            stop_deadlines: StopDeadlines::default(),
+            stats_file: self.stats_file.clone(),
+            stats_flush_interval: self.stats_flush_interval,
+            sweep_interval: self.sweep_interval,
+            runtime_metrics_interval: self.runtime_metrics,
+            soak_report_interval: self.soak_report,
+            tui: self.tui,
+            goaway: self.goaway,
+            heartbeat: self.heartbeat,
+            heartbeat_misses: self.heartbeat_misses,
+            chaos: ChaosConfig {
+                enabled: self.chaos,
+                read_stall: self.chaos_read_stall,
+                stall: self.chaos_stall,
+                partial_write: self.chaos_partial_write,
+                reset: self.chaos_reset,
+                delayed_accept: self.chaos_delayed_accept,
+                accept_delay: self.chaos_accept_delay,
+            },
+            panic_injection: self.panic_injection,
+            max_panics: self.max_panics,
+            transform: self.transform,
+            transform_delay: self.transform_delay,
+            drop_probability: self.drop_probability,
+            max_connections: self.max_connections,
+            when_full: self.when_full,
+            accept_rate: self.accept_rate,
+            accept_burst: self.accept_burst,
+            rate_limit_bytes: self.rate_limit_bytes,
+            rate_limit_messages: self.rate_limit_messages,
+            over_rate_limit: self.over_rate_limit,
+        }
+    }
+}
 
-    println!("[main] sending shutdown signal");
-    let _ = shutdown_tx.send(());
+// Without it the console would connect and see nothing: tokio only reports its tasks
+// to `tracing` in an unstable build.
+#[cfg(all(feature = "console", not(tokio_unstable)))]
+compile_error!("the console feature needs RUSTFLAGS=\"--cfg tokio_unstable\"");
 
-    match server_task.await {
-        Ok(Ok(())) => println!("[main] server exited cleanly"),
-        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
-        Err(e) => eprintln!("[main] server task join error: {e}"),
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    if cli.tui && !std::io::stdout().is_terminal() {
+        return Err("--tui needs a terminal to draw on".into());
+    }
+    let _telemetry = init_tracing(cli.log_format, cli.tui);
+    #[cfg(windows)]
+    if let Some(pipe_name) = &cli.pipe {
+        return Ok(run_pipe(pipe_name, cli.server_config()).await?);
     }
 
-    Ok(())
-}
-
-async fn run_server(
-    listener: TcpListener,
-    mut shutdown_rx: broadcast::Receiver<()>,
-) -> io::Result<()> {
-    let mut connections = JoinSet::new();
+    // Read before we overwrite it with our own pid.
+    let previous = match (&cli.pid_file, cli.takeover) {
+        (Some(pid_file), true) => handoff::read_pid_file(pid_file)?,
+        _ => None,
+    };
 
-    loop {
-        tokio::select! {
-            recv = shutdown_rx.recv() => {
-                match recv {
-                    Ok(()) => {
-                        println!("[server] shutdown requested");
-                        break;
-                    }
-                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
-                        eprintln!("[server] shutdown receiver lagged, skipped {skipped} signal(s)");
-                        break;
-                    }
-                    Err(broadcast::error::RecvError::Closed) => {
-                        println!("[server] shutdown channel closed");
-                        break;
-                    }
-                }
-            }
-            accepted = listener.accept() => {
-                match accepted {
-                    Ok((socket, peer_addr)) => {
-                        println!("[server] accepted {peer_addr}");
-                        let conn_shutdown = shutdown_rx.resubscribe();
-                        connections.spawn(async move {
-                            if let Err(e) = handle_connection(socket, conn_shutdown).await {
-                                eprintln!("[server] connection {peer_addr} error: {e}");
-                            }
-                        });
-                    }
-                    Err(e) => {
-                        eprintln!("[server] accept error: {e}");
-                    }
-                }
-            }
+    let server = Server::builder().config(cli.server_config()).build().await?;
+    let from = if server.is_socket_activated() { ", from systemd" } else { "" };
+    for addr in server.local_addrs()? {
+        info!("listening on {addr} ({:?} mode{from})", cli.mode);
+    }
+    if let Some(path) = &cli.config {
+        info!(
+            "settings from {}; checking it for changes every {:?}",
+            path.display(),
+            cli.config_reload_interval
+        );
+    }
+    if let Some(addr) = server.metrics_addr() {
+        info!("metrics on http://{addr}/metrics");
+    }
+    if let Some(addr) = server.control_addr() {
+        info!("control socket on {addr}; try `echo STATS | nc {} {}`", addr.ip(), addr.port());
+    }
+    if let Some(cert) = &cli.tls_cert {
+        info!(
+            "TLS on with {}; checking it for changes every {:?}",
+            cert.display(),
+            cli.tls_reload_interval
+        );
+    } else if let Some(pki) = server.tls_pki() {
+        std::fs::write(&cli.tls_ca_out, pki.ca().cert_pem())?;
+        info!(
+            "TLS on; CA certificate written to {0}, connect with --tls-ca {0}",
+            cli.tls_ca_out.display()
+        );
+        if cli.mtls {
+            std::fs::write(&cli.tls_client_out, pki.client().to_pem_bundle())?;
+            info!(
+                "client certificates required; one is in {0}, add --tls-identity {0}",
+                cli.tls_client_out.display()
+            );
         }
     }
+    info!("press Ctrl-C (or send SIGTERM) to shut down");
 
-    println!("[server] waiting for active connections to finish");
-    while let Some(joined) = connections.join_next().await {
-        if let Err(e) = joined {
-            eprintln!("[server] connection task join error: {e}");
+    let mut handle = server.start();
+    if let Some(pid_file) = &cli.pid_file {
+        handoff::write_pid_file(pid_file)?;
+    }
+    match previous {
+        // Only now that we're accepting: until this point the old server is the only
+        // one there is.
+        Some(pid) => match handoff::ask_to_drain(pid) {
+            Ok(()) => info!("took over the port; asked pid {pid} to drain"),
+            Err(e) => error!("couldn't signal pid {pid}: {e}"),
+        },
+        None if cli.takeover => info!("no running server to take over from"),
+        None => {}
+    }
+    // Or until the server stops by itself, told to on the control socket or the dashboard.
+    tokio::select! {
+        () = wait_for_signal() => {
+            info!("shutting down");
+            handle.shutdown();
         }
+        () = handle.finished() => {}
+    }
+
+    match handle.await_terminated().await {
+        Ok(()) => info!("server exited cleanly"),
+        Err(e) => error!(error = &e as &dyn std::error::Error, "server returned error"),
+    }
+    if let Some(pid_file) = &cli.pid_file {
+        handoff::remove_pid_file_if_ours(pid_file)?;
     }
-    println!("[server] all connection tasks finished");
 
     Ok(())
 }
 
-async fn handle_connection(
-    mut socket: TcpStream,
-    mut shutdown_rx: broadcast::Receiver<()>,
-) -> io::Result<()> {
-    let mut buf = [0_u8; 1024];
+/// Runs the named pipe server until Ctrl-C, with the same shutdown sequence as TCP.
+#[cfg(windows)]
+async fn run_pipe(pipe_name: &str, config: ServerConfig) -> std::io::Result<()> {
+    use shutdown_util::ShutdownController;
+    use tcp_server_graceful_shutdown::pipe;
 
-    loop {
-        tokio::select! {
-            recv = shutdown_rx.recv() => {
-                match recv {
-                    Ok(()) => {
-                        socket.write_all(b"server shutting down\n").await?;
-                    }
-                    Err(broadcast::error::RecvError::Lagged(_)) | Err(broadcast::error::RecvError::Closed) => {}
-                }
-                return Ok(());
-            }
-            read_result = socket.read(&mut buf) => {
-                match read_result {
-                    Ok(0) => return Ok(()),
-                    Ok(n) => {
-                        if let Err(e) = timeout(Duration::from_secs(2), socket.write_all(&buf[..n])).await {
-                            return Err(io::Error::new(io::ErrorKind::TimedOut, format!("write timeout: {e}")));
-                        }
-                    }
-                    Err(e) => {
-                        return Err(io::Error::new(io::ErrorKind::ConnectionReset, format!("read failed: {e}")));
-                    }
-                }
-            }
-        }
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let name = pipe_name.to_string();
+    let server = tokio::spawn(async move { pipe::run_pipe_server(&name, controller, shutdown_rx, config).await });
+    info!("listening on {pipe_name}");
+    info!("press Ctrl-C (or Ctrl-Break) to shut down");
+
+    wait_for_signal().await;
+    info!("shutting down");
+    trigger.trigger();
+    match server.await {
+        Ok(Ok(())) => info!("server exited cleanly"),
+        Ok(Err(e)) => error!("server returned error: {e}"),
+        Err(e) => error!("server task failed: {e}"),
     }
+    Ok(())
 }
 
-async fn run_client(name: &str, addr: &str, msg: &[u8]) -> io::Result<()> {
-    let mut socket = TcpStream::connect(addr).await?;
-    socket.write_all(msg).await?;
-
-    let mut buf = [0_u8; 1024];
-    let n = socket.read(&mut buf).await?;
-    println!("[{name}] received: {}", String::from_utf8_lossy(&buf[..n]));
+/// Logs through `tracing` to stdout, as text or JSON lines (see [`telemetry`]), at the
+/// levels `RUST_LOG` asks for (`info` when it isn't set; try `RUST_LOG=debug` or
+/// `RUST_LOG=tcp_server_graceful_shutdown::split=info`).
+/// With the `console` feature the same subscriber serves tokio-console on
+/// 127.0.0.1:6669, where every task the server spawns has a name; with `otel` it also
+/// exports the spans (see [`telemetry`]). With `--tui` nothing goes to stdout.
+fn init_tracing(format: LogFormat, tui: bool) -> telemetry::Telemetry {
+    // The dashboard has the terminal to itself.
+    let fmt = (!tui).then(|| telemetry::fmt_layer(format));
+    let registry = tracing_subscriber::registry().with(fmt);
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
+    let (otel, telemetry) = telemetry::layer("tcp_server_graceful_shutdown");
+    registry.with(otel).init();
+    telemetry
+}
 
-    Ok(())
+/// Waits for SIGINT/SIGTERM. If we can't install the handler, shut down right away
+/// rather than leaving a server nobody can stop.
+async fn wait_for_signal() {
+    match shutdown_signal().await {
+        Ok(name) => info!("received {name}"),
+        Err(e) => error!("failed to listen for shutdown signals: {e}"),
+    }
 }
@@ -0,0 +1,53 @@
+//! What a connection task that panicked leaves behind.
+//!
+//! A panic in a spawned task doesn't take the server down: tokio catches it at the
+//! task boundary and hands it back as the task's `JoinError`, whose payload is
+//! whatever was passed to `panic!`. That's a `&'static str` for a literal message, a
+//! `String` for a formatted one, and anything at all for `std::panic::panic_any`, so
+//! getting the message back out is a matter of guessing the type.
+//!
+//! The default panic hook will already have printed the message and where it came
+//! from to stderr, on whichever worker thread the task happened to be running. The
+//! server's own log line is the one that says it was a connection task.
+
+use std::any::Any;
+use tokio::task::JoinError;
+
+/// A one-line description of why a task didn't finish: its panic message, or that it
+/// was cancelled.
+pub(crate) fn describe(e: JoinError) -> String {
+    let id = e.id();
+    match e.try_into_panic() {
+        Ok(payload) => format!("task {id} panicked: {}", message(payload.as_ref())),
+        Err(e) => format!("task {id} {e}"),
+    }
+}
+
+/// The message a panic was raised with, when it was raised with one.
+fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "<not a string>"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_describes_literal_formatted_and_other_payloads() {
+        let literal = tokio::spawn(async { panic!("literal") }).await.unwrap_err();
+        assert!(describe(literal).ends_with("panicked: literal"));
+
+        let n = 7;
+        let formatted = tokio::spawn(async move { panic!("formatted {n}") }).await.unwrap_err();
+        assert!(describe(formatted).ends_with("panicked: formatted 7"));
+
+        let other = tokio::spawn(async { std::panic::panic_any(42_u8) }).await.unwrap_err();
+        assert!(describe(other).ends_with("panicked: <not a string>"));
+    }
+}
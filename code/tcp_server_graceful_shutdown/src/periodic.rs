@@ -0,0 +1,112 @@
+//! Background jobs that run on a timer rather than for a peer: the stats file's
+//! flusher and the connection list's sweeper.
+//!
+//! They drain differently from connections. There's nobody to say goodbye to, but a
+//! job caught in the middle of a tick may be halfway through writing a file or pruning
+//! a table, and cancelling it there leaves that half done. So stopping one doesn't
+//! cancel anything: the stop request is only looked at between ticks, a tick that's
+//! already running is finished first, and then the job gets one last run to persist
+//! whatever it keeps. The shutdown orchestrator's deadline is what bounds all of that;
+//! a job that misses it is dropped, and dropping a job aborts it.
+
+use crate::{panics, tasks};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::{MissedTickBehavior, interval};
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{Instrument, error, info_span};
+
+/// Which run of a job this is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Run {
+    /// One of the regular ones, every period.
+    Tick,
+    /// The last one, at shutdown, once the tick in progress (if any) has finished.
+    Final,
+}
+
+/// A running job. Dropping it without [`stop`](Self::stop) aborts it at its next
+/// `.await`, mid-tick or not, and skips the final run.
+pub(crate) struct Periodic {
+    name: &'static str,
+    stop: oneshot::Sender<()>,
+    task: AbortOnDropHandle<()>,
+}
+
+impl Periodic {
+    /// Runs `job` every `period`, starting now, and once more when stopped.
+    pub(crate) fn spawn<F, Fut>(name: &'static str, period: Duration, mut job: F) -> Self
+    where
+        F: FnMut(Run) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let (stop, mut stop_rx) = oneshot::channel::<()>();
+        let task = tasks::spawn(&format!("periodic job: {name}"), async move {
+            let mut ticks = interval(period);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    // The tick runs in the branch's body, after the `select!` has picked
+                    // it: a stop request that comes in meanwhile waits for it to finish.
+                    _ = ticks.tick() => job(Run::Tick).await,
+                }
+            }
+            job(Run::Final).await;
+        }.instrument(info_span!("job", name)));
+        Self { name, stop, task: AbortOnDropHandle::new(task) }
+    }
+
+    /// Waits for the tick in progress, then for the final run.
+    pub(crate) async fn stop(self) {
+        let _ = self.stop.send(());
+        if let Err(e) = self.task.await {
+            error!(job = self.name, "{}", panics::describe(e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test_stop_lets_the_current_tick_finish_before_the_final_run() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let log = runs.clone();
+        let job = Periodic::spawn("test", Duration::from_secs(60), move |run| {
+            let log = log.clone();
+            async move {
+                log.lock().unwrap().push(format!("{run:?} started"));
+                sleep(Duration::from_millis(100)).await;
+                log.lock().unwrap().push(format!("{run:?} finished"));
+            }
+        });
+
+        // The first tick fires straight away; stop in the middle of it.
+        sleep(Duration::from_millis(30)).await;
+        job.stop().await;
+        assert_eq!(*runs.lock().unwrap(), ["Tick started", "Tick finished", "Final started", "Final finished"]);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_a_job_aborts_it_without_a_final_run() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let log = runs.clone();
+        let job = Periodic::spawn("test", Duration::from_secs(60), move |run| {
+            let log = log.clone();
+            async move {
+                sleep(Duration::from_millis(100)).await;
+                log.lock().unwrap().push(run);
+            }
+        });
+
+        sleep(Duration::from_millis(30)).await;
+        drop(job);
+        sleep(Duration::from_millis(150)).await;
+        assert!(runs.lock().unwrap().is_empty());
+    }
+}
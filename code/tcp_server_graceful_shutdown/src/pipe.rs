@@ -0,0 +1,79 @@
+//! The echo server over a Windows named pipe instead of TCP.
+//!
+//! A named pipe server works differently from a listener: there's no `accept` that
+//! hands out new sockets. Instead we create a pipe *instance*, wait for one client to
+//! `connect` to it, and then create a fresh instance for the next client. Once
+//! connected, though, it's just a byte stream like any other, so the connection is
+//! served by the same `handle_connection` as TCP, and the shutdown mechanics (one
+//! `broadcast` receiver per connection, drain with a deadline) are the same too.
+//!
+//! Only the raw echo handler is wired up here; framing, the connection limit and the
+//! TCP socket options don't apply.
+
+use crate::config::ServerConfig;
+use crate::config_file;
+use crate::connection::{ConnInfo, handle_connection};
+use shutdown_util::ShutdownController;
+use std::io;
+use std::sync::Arc;
+use tokio::net::windows::named_pipe::ServerOptions;
+use tokio::sync::broadcast;
+use tracing::{Instrument, error, info, warn};
+
+/// Serves `pipe_name` (such as `\\.\pipe\echo`) until `shutdown_rx` fires, then drains
+/// the connections for up to `config.drain_timeout`.
+pub async fn run_pipe_server(
+    pipe_name: &str,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    config: ServerConfig,
+) -> io::Result<()> {
+    let config = Arc::new(config);
+    // `first_pipe_instance` makes a second server on the same name fail here, instead
+    // of quietly taking turns with us at serving clients.
+    let mut server = ServerOptions::new().first_pipe_instance(true).create(pipe_name)?;
+    let mut next_conn_id = 0_u64;
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                info!("shutdown requested");
+                break;
+            }
+            // Cancellation safe: if shutdown wins, no client connection is lost.
+            connected = server.connect() => {
+                connected?;
+                // Put the next instance in place before handing this one off, so there's
+                // always one for the next client to connect to.
+                let client = std::mem::replace(&mut server, ServerOptions::new().create(pipe_name)?);
+                next_conn_id += 1;
+                let conn = ConnInfo::new(next_conn_id, format!("{pipe_name}#{next_conn_id}"));
+                let span = conn.span();
+                // Too late to be sure of hearing the signal; see `ShutdownState`.
+                let Some((conn_shutdown, registration)) = controller.subscribe_if_running() else {
+                    span.in_scope(|| info!("refused: the server is shutting down"));
+                    continue;
+                };
+                span.in_scope(|| info!("connected"));
+                let config = config.clone();
+                controller.spawn_named(&conn.to_string(), async move {
+                    let result = handle_connection(client, conn_shutdown, &config, config_file::fixed(&config), &conn).await;
+                    conn.log_closed(&result);
+                    drop(registration);
+                }.instrument(span));
+            }
+        }
+    }
+
+    info!(drain_timeout = ?config.drain_timeout, connections = controller.active_tasks(), "waiting for the connection tasks to finish");
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        error!(error = %e, "connection task join error");
+    }
+    if report.aborted > 0 {
+        warn!(aborted = report.aborted, "drain deadline hit, force-closed the rest");
+    } else {
+        info!("all connection tasks finished");
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Prometheus metrics, for `GET /metrics` on the HTTP framing and on the `--metrics`
+//! listener.
+//!
+//! The counting itself goes through the `metrics` facade: `counter!` and friends hand
+//! the value to whichever recorder the process installed, and cost next to nothing when
+//! there's none, so the same calls work in a library, a test or another example crate
+//! without any of them knowing where the numbers end up. This module installs the
+//! Prometheus recorder, once per process, and renders what it has collected in the text
+//! format scrapers read.
+//!
+//! The names, all prefixed `echo_`:
+//!
+//! ```text
+//! echo_connections_accepted_total   counter  connections admitted
+//! echo_connections_active           gauge    admitted and not yet finished
+//! echo_bytes_echoed_total           counter  bytes written back, counted as connections close
+//! echo_connection_panics_total      counter  connection tasks that panicked
+//! echo_shutdown_drain_seconds       gauge    how long the last drain took
+//! ```
+//!
+//! They're recorded next to the live counters `/stats` shows, so broadcast mode is the
+//! only one that has them. With `runtime_metrics_interval` set, each sample of the
+//! runtime adds:
+//!
+//! ```text
+//! tokio_workers                     gauge    worker threads
+//! tokio_alive_tasks                 gauge    tasks spawned and not yet finished
+//! tokio_global_queue_depth          gauge    tasks waiting in the shared queue
+//! tokio_probe_wait_seconds          gauge    how long a probe task waited to be polled
+//! ```
+
+use crate::{http, tasks};
+use metrics::Unit;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+use tracing::{debug, warn};
+
+pub(crate) const ACCEPTED: &str = "echo_connections_accepted_total";
+pub(crate) const ACTIVE: &str = "echo_connections_active";
+pub(crate) const BYTES_ECHOED: &str = "echo_bytes_echoed_total";
+pub(crate) const PANICS: &str = "echo_connection_panics_total";
+pub(crate) const DRAIN_SECONDS: &str = "echo_shutdown_drain_seconds";
+pub(crate) const RUNTIME_WORKERS: &str = "tokio_workers";
+pub(crate) const RUNTIME_ALIVE_TASKS: &str = "tokio_alive_tasks";
+pub(crate) const RUNTIME_GLOBAL_QUEUE: &str = "tokio_global_queue_depth";
+pub(crate) const RUNTIME_PROBE_WAIT: &str = "tokio_probe_wait_seconds";
+
+/// How long to wait before accepting again after an accept error, such as running out
+/// of file descriptors.
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+static HANDLE: OnceLock<Option<PrometheusHandle>> = OnceLock::new();
+
+/// Installs the Prometheus recorder, unless this process already has one. Every server
+/// in the process shares it, so their numbers add up.
+pub(crate) fn install() {
+    HANDLE.get_or_init(|| {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        if let Err(e) = metrics::set_global_recorder(recorder) {
+            // Someone else's recorder is getting the numbers; ours would stay empty.
+            warn!(error = %e, "not serving metrics, another recorder is installed");
+            return None;
+        }
+        metrics::describe_counter!(ACCEPTED, "Connections admitted.");
+        metrics::describe_gauge!(ACTIVE, "Connections admitted and not yet finished.");
+        metrics::describe_counter!(BYTES_ECHOED, Unit::Bytes, "Bytes written back, counted as connections close.");
+        metrics::describe_counter!(PANICS, "Connection tasks that panicked.");
+        metrics::describe_gauge!(DRAIN_SECONDS, Unit::Seconds, "How long the last shutdown drain took.");
+        metrics::describe_gauge!(RUNTIME_WORKERS, "Worker threads.");
+        metrics::describe_gauge!(RUNTIME_ALIVE_TASKS, "Tasks spawned and not yet finished.");
+        metrics::describe_gauge!(RUNTIME_GLOBAL_QUEUE, "Tasks waiting in the runtime's shared queue.");
+        metrics::describe_gauge!(RUNTIME_PROBE_WAIT, Unit::Seconds, "How long the latest probe task waited to be polled.");
+        Some(handle)
+    });
+}
+
+/// Everything recorded so far, in the Prometheus text format.
+pub(crate) fn render() -> String {
+    match HANDLE.get() {
+        Some(Some(handle)) => handle.render(),
+        _ => String::new(),
+    }
+}
+
+/// Answers scrapes on `listener` until dropped. Each one is a connection of its own,
+/// closed after the response.
+pub(crate) async fn serve(listener: TcpListener) {
+    let mut scrapes = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((socket, peer)) => {
+                    tasks::spawn_in(&mut scrapes, &format!("metrics scrape {peer}"), async move {
+                        if let Err(e) = http::answer_scrape(socket).await {
+                            debug!(%peer, error = %e, "metrics scrape failed");
+                        }
+                    });
+                }
+                Err(e) => {
+                    warn!(error = %e, "metrics listener couldn't accept");
+                    sleep(ACCEPT_RETRY).await;
+                }
+            },
+            Some(_) = scrapes.join_next() => {}
+        }
+    }
+}
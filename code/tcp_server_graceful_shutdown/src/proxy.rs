@@ -0,0 +1,311 @@
+//! The HAProxy PROXY protocol, versions 1 and 2.
+//!
+//! Behind a TCP load balancer every connection comes from the balancer's address, so
+//! that's all `accept` can tell us. A balancer speaking the PROXY protocol sends one
+//! header before any application data, naming the real client and the address it
+//! dialled. Version 1 is a line of text, version 2 a binary block behind a 12-byte
+//! signature; both are described in
+//! <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+//!
+//! The header has to be the very first thing on the connection, before the TLS
+//! handshake too. Trusting it is only safe when every client really comes through the
+//! balancer: anyone who can reach the port directly can claim any address they like.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// What every version 2 header starts with. It can't be mistaken for a version 1 line
+/// or for the start of TLS or HTTP.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest version 1 line the spec allows, `\r\n` included.
+const V1_MAX_LEN: usize = 107;
+
+/// Which version of the header to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProxyVersion {
+    /// The human-readable `PROXY TCP4 ...` line.
+    V1,
+    /// The binary format.
+    V2,
+}
+
+/// The addresses a PROXY header carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    /// The real client.
+    pub source: SocketAddr,
+    /// The address the client connected to, on the balancer.
+    pub destination: SocketAddr,
+}
+
+impl ProxyHeader {
+    /// Serializes the header. If one address is IPv4 and the other IPv6, the IPv4 one
+    /// is sent IPv4-mapped, since both formats need the two to match.
+    pub fn encode(&self, version: ProxyVersion) -> Vec<u8> {
+        let (source, destination) = match (self.source, self.destination) {
+            (SocketAddr::V4(_), SocketAddr::V4(_)) | (SocketAddr::V6(_), SocketAddr::V6(_)) => {
+                (self.source, self.destination)
+            }
+            _ => (to_v6(self.source), to_v6(self.destination)),
+        };
+        match version {
+            ProxyVersion::V1 => {
+                let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+                format!(
+                    "PROXY {family} {} {} {} {}\r\n",
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port()
+                )
+                .into_bytes()
+            }
+            ProxyVersion::V2 => {
+                let mut out = V2_SIGNATURE.to_vec();
+                // Version 2, command PROXY.
+                out.push(0x21);
+                match (source.ip(), destination.ip()) {
+                    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                        // AF_INET, STREAM; two addresses and two ports.
+                        out.push(0x11);
+                        out.extend_from_slice(&12_u16.to_be_bytes());
+                        out.extend_from_slice(&src.octets());
+                        out.extend_from_slice(&dst.octets());
+                    }
+                    (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                        out.push(0x21);
+                        out.extend_from_slice(&36_u16.to_be_bytes());
+                        out.extend_from_slice(&src.octets());
+                        out.extend_from_slice(&dst.octets());
+                    }
+                    _ => unreachable!("families were matched up above"),
+                }
+                out.extend_from_slice(&source.port().to_be_bytes());
+                out.extend_from_slice(&destination.port().to_be_bytes());
+                out
+            }
+        }
+    }
+}
+
+fn to_v6(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(v4) => SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port()),
+        v6 => v6,
+    }
+}
+
+/// Reads a PROXY header, either version, from the start of `reader`.
+///
+/// Returns `None` for headers that deliberately carry no addresses (`UNKNOWN` in
+/// version 1, `LOCAL` in version 2, which balancers use for their own health checks).
+/// Reads exactly the header and nothing more, so whatever follows is left for the
+/// protocol handler.
+pub async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<ProxyHeader>> {
+    // The shortest valid header, "PROXY UNKNOWN\r\n", is longer than this, so reading
+    // it can't eat into the payload.
+    let mut start = [0_u8; 12];
+    reader.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        let mut fixed = [0_u8; 4];
+        reader.read_exact(&mut fixed).await?;
+        let mut body = vec![0_u8; usize::from(u16::from_be_bytes([fixed[2], fixed[3]]))];
+        reader.read_exact(&mut body).await?;
+        parse_v2(fixed[0], fixed[1], &body)
+    } else if start.starts_with(b"PROXY ") {
+        // The line's length isn't known up front and we mustn't read past its end, so
+        // go a byte at a time. That's a read call per byte, but only for ~50 bytes once
+        // per connection.
+        let mut line = start.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() == V1_MAX_LEN {
+                return Err(invalid("PROXY v1 line too long"));
+            }
+            line.push(reader.read_u8().await?);
+        }
+        parse_v1(&line)
+    } else {
+        Err(invalid("connection didn't start with a PROXY header"))
+    }
+}
+
+/// Parses a version 1 line, `\r\n` included.
+fn parse_v1(line: &[u8]) -> io::Result<Option<ProxyHeader>> {
+    let line = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.strip_suffix("\r\n"))
+        .ok_or_else(|| invalid("malformed PROXY v1 line"))?;
+    let mut fields = line.split(' ');
+    if fields.next() != Some("PROXY") {
+        return Err(invalid("malformed PROXY v1 line"));
+    }
+    let family = fields.next();
+    if family == Some("UNKNOWN") {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = fields.collect();
+    let [src_ip, dst_ip, src_port, dst_port] = fields[..] else {
+        return Err(invalid("PROXY v1 line needs two addresses and two ports"));
+    };
+    let (src_ip, dst_ip) = match family {
+        Some("TCP4") => (
+            IpAddr::V4(parse_field::<Ipv4Addr>(src_ip)?),
+            IpAddr::V4(parse_field::<Ipv4Addr>(dst_ip)?),
+        ),
+        Some("TCP6") => (
+            IpAddr::V6(parse_field::<Ipv6Addr>(src_ip)?),
+            IpAddr::V6(parse_field::<Ipv6Addr>(dst_ip)?),
+        ),
+        _ => return Err(invalid("unknown PROXY v1 protocol family")),
+    };
+    Ok(Some(ProxyHeader {
+        source: SocketAddr::new(src_ip, parse_field(src_port)?),
+        destination: SocketAddr::new(dst_ip, parse_field(dst_port)?),
+    }))
+}
+
+fn parse_field<T: std::str::FromStr>(field: &str) -> io::Result<T> {
+    field
+        .parse()
+        .map_err(|_| invalid(format!("bad field {field:?} in PROXY v1 line")))
+}
+
+/// Parses a version 2 header from its version/command byte, its family byte and the
+/// address block that follows the fixed part.
+fn parse_v2(version_command: u8, family: u8, body: &[u8]) -> io::Result<Option<ProxyHeader>> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY header version"));
+    }
+    match version_command & 0x0f {
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unknown PROXY v2 command")),
+    }
+    // Anything after the addresses is TLVs (TLS details, unique ids and such), which
+    // we don't need, so it's skipped.
+    let (source, destination) = match family >> 4 {
+        // AF_INET
+        1 => {
+            let block: &[u8; 12] = body
+                .get(..12)
+                .and_then(|block| block.try_into().ok())
+                .ok_or_else(|| invalid("PROXY v2 address block too short"))?;
+            let src: [u8; 4] = block[0..4].try_into().unwrap();
+            let dst: [u8; 4] = block[4..8].try_into().unwrap();
+            (
+                SocketAddr::new(IpAddr::from(src), u16::from_be_bytes([block[8], block[9]])),
+                SocketAddr::new(IpAddr::from(dst), u16::from_be_bytes([block[10], block[11]])),
+            )
+        }
+        // AF_INET6
+        2 => {
+            let block: &[u8; 36] = body
+                .get(..36)
+                .and_then(|block| block.try_into().ok())
+                .ok_or_else(|| invalid("PROXY v2 address block too short"))?;
+            let src: [u8; 16] = block[0..16].try_into().unwrap();
+            let dst: [u8; 16] = block[16..32].try_into().unwrap();
+            (
+                SocketAddr::new(IpAddr::from(src), u16::from_be_bytes([block[32], block[33]])),
+                SocketAddr::new(IpAddr::from(dst), u16::from_be_bytes([block[34], block[35]])),
+            )
+        }
+        // AF_UNSPEC or AF_UNIX: nothing we could log as a socket address.
+        _ => return Ok(None),
+    };
+    Ok(Some(ProxyHeader { source, destination }))
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(source: &str, destination: &str) -> ProxyHeader {
+        ProxyHeader {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        }
+    }
+
+    async fn read_all(bytes: &[u8]) -> io::Result<Option<ProxyHeader>> {
+        read_header(&mut &bytes[..]).await
+    }
+
+    #[tokio::test]
+    async fn test_round_trips_both_versions_and_families() {
+        for h in [
+            header("192.168.0.1:56324", "10.0.0.1:443"),
+            header("[2001:db8::1]:7000", "[::1]:3011"),
+        ] {
+            for version in [ProxyVersion::V1, ProxyVersion::V2] {
+                assert_eq!(read_all(&h.encode(version)).await.unwrap(), Some(h), "{version:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parses_the_spec_example_and_stops_at_the_header() {
+        let mut input: &[u8] = b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nGET /";
+        let parsed = read_header(&mut input).await.unwrap();
+        assert_eq!(parsed, Some(header("192.168.0.1:56324", "192.168.0.11:443")));
+        assert_eq!(input, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_and_local_carry_no_addresses() {
+        assert_eq!(read_all(b"PROXY UNKNOWN\r\n").await.unwrap(), None);
+        assert_eq!(read_all(b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n").await.unwrap(), None);
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(read_all(&local).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_v2_skips_trailing_tlvs() {
+        let h = header("203.0.113.7:1234", "198.51.100.1:80");
+        let mut bytes = h.encode(ProxyVersion::V2);
+        // Bump the length and append a NOOP TLV: type 0x04, length 2.
+        let tlv = [0x04, 0x00, 0x02, 0xaa, 0xbb];
+        let len = u16::from_be_bytes([bytes[14], bytes[15]]) + tlv.len() as u16;
+        bytes[14..16].copy_from_slice(&len.to_be_bytes());
+        bytes.extend_from_slice(&tlv);
+        assert_eq!(read_all(&bytes).await.unwrap(), Some(h));
+    }
+
+    #[tokio::test]
+    async fn test_mixed_families_are_sent_ipv4_mapped() {
+        let h = header("192.0.2.1:5000", "[::1]:3011");
+        let parsed = read_all(&h.encode(ProxyVersion::V1)).await.unwrap().unwrap();
+        assert_eq!(parsed.source, "[::ffff:192.0.2.1]:5000".parse().unwrap());
+        assert_eq!(parsed.destination, h.destination);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_malformed_headers() {
+        for bad in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"PROXY TCP4 1.2.3.4 5.6.7.8 80\r\n",
+            b"PROXY TCP4 1.2.3.4 ::1 80 443\r\n",
+            b"PROXY TCP4 1.2.3.4 5.6.7.8 80 99999\r\n",
+            b"PROXY SCTP 1.2.3.4 5.6.7.8 80 443\r\n",
+        ] {
+            let err = read_all(bad).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", String::from_utf8_lossy(bad));
+        }
+
+        let too_long = format!("PROXY TCP4 {}\r\n", "1".repeat(V1_MAX_LEN));
+        assert_eq!(read_all(too_long.as_bytes()).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut truncated = header("10.0.0.1:1", "10.0.0.2:2").encode(ProxyVersion::V2);
+        truncated.truncate(20);
+        assert_eq!(read_all(&truncated).await.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
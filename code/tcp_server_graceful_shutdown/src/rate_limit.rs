@@ -0,0 +1,142 @@
+//! Per-connection rate limits: so many bytes, and so many messages, a second.
+//!
+//! Each limit is a token bucket. It starts full with a second's worth of tokens, every
+//! message spends some, and a `tokio::time::interval` ticking ten times a second puts
+//! them back. That interval is just another branch of the connection's `select!` loop,
+//! next to the read and the shutdown signal, so the limiter needs no task of its own.
+//!
+//! A message is allowed to spend more than is left, putting the bucket into debt.
+//! Otherwise a read larger than the bucket could never go through, and checking before
+//! reading doesn't work anyway: we don't know how big the next read is until it's
+//! done. What happens once the bucket's empty is [`RateLimitPolicy`]'s choice:
+//!
+//! - `Delay` switches off the read branch until the ticks have paid the debt back.
+//!   We stop reading, the socket's receive buffer fills, and TCP's flow control slows
+//!   the client down for us.
+//! - `Disconnect` tells the client and hangs up.
+
+use crate::config::ServerConfig;
+use std::time::Duration;
+use tokio::time::{Interval, MissedTickBehavior, interval};
+
+/// How often the buckets are topped up.
+const REFILL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// One limit: `rate` tokens a second, holding at most `rate`.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        let rate = rate as f64;
+        Self { rate, tokens: rate }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.tokens = (self.tokens + self.rate * elapsed.as_secs_f64()).min(self.rate);
+    }
+
+    /// Spends `n` tokens, whether they're there or not.
+    fn spend(&mut self, n: u64) {
+        self.tokens -= n as f64;
+    }
+
+    /// Whether anything is left to spend.
+    fn is_empty(&self) -> bool {
+        self.tokens <= 0.0
+    }
+}
+
+/// The byte and message buckets of one connection, and the timer that refills them.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    bytes: Option<TokenBucket>,
+    messages: Option<TokenBucket>,
+    refill: Interval,
+}
+
+impl RateLimiter {
+    /// A limiter for `config`'s limits, or `None` if it doesn't set any.
+    pub(crate) fn new(config: &ServerConfig) -> Option<Self> {
+        if config.rate_limit_bytes.is_none() && config.rate_limit_messages.is_none() {
+            return None;
+        }
+        let mut refill = interval(REFILL_INTERVAL);
+        // After a stall, one tick with the real elapsed time, not a burst of them.
+        refill.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Some(Self {
+            bytes: config.rate_limit_bytes.map(TokenBucket::new),
+            messages: config.rate_limit_messages.map(|rate| TokenBucket::new(rate.into())),
+            refill,
+        })
+    }
+
+    /// Accounts for a message of `len` bytes.
+    pub(crate) fn spend(&mut self, len: usize) {
+        if let Some(bytes) = &mut self.bytes {
+            bytes.spend(len as u64);
+        }
+        if let Some(messages) = &mut self.messages {
+            messages.spend(1);
+        }
+    }
+
+    /// Whether either bucket is empty (or in debt): the connection is over its limit.
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.bytes.as_ref().is_some_and(TokenBucket::is_empty) || self.messages.as_ref().is_some_and(TokenBucket::is_empty)
+    }
+
+    /// Waits for the next tick and tops the buckets up.
+    async fn refill(&mut self) {
+        let before = self.refill.period();
+        self.refill.tick().await;
+        for bucket in [&mut self.bytes, &mut self.messages].into_iter().flatten() {
+            bucket.refill(before);
+        }
+    }
+}
+
+/// Refills `limiter`'s buckets on its next tick, or never completes if there's no
+/// limiter, so a `select!` can always have the branch.
+pub(crate) async fn refill(limiter: &mut Option<RateLimiter>) {
+    match limiter {
+        Some(limiter) => limiter.refill().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_goes_into_debt_and_refills_up_to_its_rate() {
+        let mut bucket = TokenBucket::new(100);
+        bucket.spend(250);
+        assert!(bucket.is_empty());
+        bucket.refill(Duration::from_secs(1));
+        assert!(bucket.is_empty(), "still 50 tokens in debt");
+        bucket.refill(Duration::from_millis(600));
+        assert!(!bucket.is_empty());
+        bucket.refill(Duration::from_secs(10));
+        assert_eq!(bucket.tokens, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_limiter_counts_messages_as_well_as_bytes() {
+        let config = ServerConfig {
+            rate_limit_messages: Some(2),
+            ..ServerConfig::default()
+        };
+        let mut limiter = RateLimiter::new(&config).unwrap();
+        limiter.spend(1);
+        assert!(!limiter.is_exhausted());
+        limiter.spend(1);
+        assert!(limiter.is_exhausted());
+
+        assert!(RateLimiter::new(&ServerConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,79 @@
+//! The connections `CONNS` lists, and the background job that sweeps out the ones
+//! that have closed.
+//!
+//! Entries are `Weak`, so a finished connection isn't kept alive by the list, but the
+//! entry itself stays behind until someone removes it. The sweeper does that every
+//! `sweep_interval`, instead of the accept loop pausing now and then to do it. At
+//! shutdown it's stopped after the connections and sweeps once more: anything still
+//! registered then is a connection whose task never let go of it.
+
+use crate::connection::ConnInfo;
+use crate::periodic::{Periodic, Run};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tracing::info;
+
+/// The connections that have been admitted, by id, some of them closed since.
+#[derive(Debug, Default)]
+pub(crate) struct Registry {
+    conns: Mutex<BTreeMap<u64, Weak<ConnInfo>>>,
+}
+
+impl Registry {
+    pub(crate) fn insert(&self, conn: &Arc<ConnInfo>) {
+        self.conns.lock().unwrap().insert(conn.id, Arc::downgrade(conn));
+    }
+
+    /// The connections still open, oldest first.
+    pub(crate) fn live(&self) -> Vec<Arc<ConnInfo>> {
+        self.conns.lock().unwrap().values().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Removes the entries of closed connections, and says how many there were.
+    pub(crate) fn sweep(&self) -> usize {
+        let mut conns = self.conns.lock().unwrap();
+        let before = conns.len();
+        conns.retain(|_, conn| conn.strong_count() > 0);
+        before - conns.len()
+    }
+
+    fn len(&self) -> usize {
+        self.conns.lock().unwrap().len()
+    }
+}
+
+/// Sweeps `registry` every `period`, and once more when stopped.
+pub(crate) fn spawn_sweeper(registry: Arc<Registry>, period: Duration) -> Periodic {
+    Periodic::spawn("sweeper", period, move |run| {
+        let registry = registry.clone();
+        async move {
+            let swept = registry.sweep();
+            if run == Run::Final {
+                info!(swept, still_registered = registry.len(), "final sweep");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_sweep_removes_only_closed_connections() {
+        let registry = Registry::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1));
+        let open = Arc::new(ConnInfo::new(1, addr));
+        let closed = Arc::new(ConnInfo::new(2, addr));
+        registry.insert(&open);
+        registry.insert(&closed);
+        drop(closed);
+
+        assert_eq!(registry.live().len(), 1);
+        assert_eq!(registry.sweep(), 1);
+        assert_eq!(registry.sweep(), 0);
+        assert_eq!(registry.live()[0].id, 1);
+    }
+}
@@ -0,0 +1,52 @@
+//! The tokio runtime's own metrics, sampled every `runtime_metrics_interval` and logged,
+//! with the headline numbers set as gauges for `/metrics` too.
+//!
+//! The sampling happens on a thread of its own, not in a task like the other periodic
+//! jobs (see the `runtime_metrics` crate for why): it's the one job that has to keep
+//! going when the workers don't. The config watcher and the certificate reloader block
+//! on `std::fs` each tick; on a slow disk this is where it shows, as probes that wait.
+
+use crate::prometheus;
+use runtime_metrics::{Probe, Sample, Sampler};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tracing::{error, info, warn};
+
+/// Starts sampling the runtime we're on. Without a sampler the server runs on, so
+/// failing to start one is only worth a warning.
+pub(crate) fn spawn(period: Duration) -> Option<Sampler> {
+    let sampler = Sampler::spawn(Handle::current(), period, |sample| {
+        record(sample);
+        info!("runtime {sample}");
+    });
+    match sampler {
+        Ok(sampler) => Some(sampler),
+        Err(e) => {
+            warn!(error = %e, "couldn't start the runtime sampler, going without");
+            None
+        }
+    }
+}
+
+/// Takes the final sample, over the whole run. Stopping waits for the sampler's thread
+/// to finish, so it's done off the runtime's workers.
+pub(crate) async fn stop(sampler: Sampler) {
+    match tokio::task::spawn_blocking(move || sampler.stop()).await {
+        Ok(sample) => {
+            record(&sample);
+            info!("runtime over the whole run: {sample}");
+        }
+        Err(e) => error!(error = %e, "the runtime sampler failed"),
+    }
+}
+
+fn record(sample: &Sample) {
+    let probe_wait = match sample.probe {
+        Probe::Ran(waited) | Probe::Waiting(waited) => waited,
+        Probe::NotYet => Duration::ZERO,
+    };
+    metrics::gauge!(prometheus::RUNTIME_WORKERS).set(sample.workers as f64);
+    metrics::gauge!(prometheus::RUNTIME_ALIVE_TASKS).set(sample.alive_tasks as f64);
+    metrics::gauge!(prometheus::RUNTIME_GLOBAL_QUEUE).set(sample.global_queue_depth as f64);
+    metrics::gauge!(prometheus::RUNTIME_PROBE_WAIT).set(probe_wait.as_secs_f64());
+}
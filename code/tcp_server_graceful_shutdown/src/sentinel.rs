@@ -0,0 +1,123 @@
+//! The token variant once more, draining without tracking the connection tasks at all:
+//! "every sender dropped" is the signal that they're done.
+//!
+//! Each connection task holds a clone of an `mpsc::Sender<()>` that never sends
+//! anything. When a task finishes, however it finishes (returning, panicking, being
+//! aborted), its clone is dropped with it. Once the server drops its own, `recv` on
+//! the other end returns `None` exactly when the last connection is gone. The stats
+//! aggregator already knows it's done the same way.
+//!
+//! Compared with the other two ways of draining:
+//! * It composes with anything that has a `Drop`. A sentinel can ride along inside a
+//!   struct, a sub-task the connection spawns, or a future handed to another library,
+//!   and the server still waits for it, without any of them knowing about a `JoinSet`
+//!   or a `TaskTracker`.
+//! * It can only say "all done". It can't count who's left (`len` on a `JoinSet` or a
+//!   tracker), say which task panicked, or abort anything: force-closing at the
+//!   deadline takes a second token, as with the tracker.
+//! * A clone that leaks, one stashed in a long-lived struct by mistake, makes the drain
+//!   wait for the deadline every time, and nothing says whose it was.
+//!
+//! The server's own end is a `DropGuard` idiom too, the other way round: the guard on
+//! the root token cancels it whenever this function returns or is dropped, so no
+//! connection outlives the server without being told. It can't stand in for cancelling
+//! by hand when an accept error ends the loop early, though: the drain comes before the
+//! return, and connections nobody has told to close would sit it out to the deadline.
+
+use crate::accept::{Accepted, Acceptors};
+use crate::accept_rate::AcceptRate;
+use crate::config::{OverloadPolicy, ServerConfig};
+use crate::connection::ConnInfo;
+use crate::error::{ConnectionError, ServerError};
+use crate::listener::{Connection, Listener};
+use crate::{stats, tasks, token};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, error, info, warn};
+
+pub async fn run_server<L: Listener>(
+    listeners: Vec<L>,
+    root_token: CancellationToken,
+    config: ServerConfig,
+) -> Result<(), ServerError> {
+    let config = Arc::new(config);
+    let _cancel_on_return = root_token.clone().drop_guard();
+    // Nothing is ever sent, so a capacity of one is plenty.
+    let (sentinel, mut all_done) = mpsc::channel::<()>(1);
+    let abort = CancellationToken::new();
+    let accept_rate = config.accept_rate.map(|rate| Arc::new(AcceptRate::new(rate, config.accept_burst)));
+    let mut acceptors = Acceptors::spawn(listeners, None, OverloadPolicy::Wait, accept_rate, config.max_accept_failures);
+    let mut result = Ok(());
+    let mut next_conn_id = 0_u64;
+    let (stats_tx, stats_task) = stats::spawn_aggregator();
+
+    loop {
+        tokio::select! {
+            _ = root_token.cancelled() => {
+                info!("root token cancelled");
+                break;
+            }
+            accepted = acceptors.next() => {
+                // Cancelling the root is what tells the open connections to finish.
+                let Accepted { socket, peer_addr, .. } = match accepted {
+                    Some(Ok(accepted)) => accepted,
+                    Some(Err(e)) => {
+                        result = Err(ServerError::Accept(e));
+                        root_token.cancel();
+                        break;
+                    }
+                    None => {
+                        root_token.cancel();
+                        break;
+                    }
+                };
+                next_conn_id += 1;
+                let conn = ConnInfo::new(next_conn_id, peer_addr);
+                let span = conn.span();
+                span.in_scope(|| {
+                    info!("accepted");
+                    socket.configure(&config);
+                });
+                let conn_token = root_token.child_token();
+                let abort = abort.clone();
+                let config = config.clone();
+                let stats_tx = stats_tx.clone();
+                let sentinel = sentinel.clone();
+                // The `JoinHandle` is dropped straight away: nobody joins this task.
+                tasks::spawn(&conn.to_string(), async move {
+                    let _sentinel = sentinel;
+                    let result = tokio::select! {
+                        () = abort.cancelled() => Err(ConnectionError::ShutdownInterrupted),
+                        result = token::handle_connection(socket, conn_token, &config, &conn) => result,
+                    };
+                    conn.log_closed(&result);
+                    let _ = stats_tx.send(conn.stats()).await;
+                }.instrument(span));
+            }
+        }
+    }
+    acceptors.shutdown().await;
+    // Ours is the one sender that isn't in a connection; until it goes, `recv` can't
+    // return `None`.
+    drop(sentinel);
+
+    // There's no count to log: a sender can't tell how many clones of it are left.
+    info!(drain_timeout = ?config.drain_timeout, "waiting for the connection tasks to finish");
+    if timeout(config.drain_timeout, all_done.recv()).await.is_ok() {
+        info!("all connection tasks finished");
+    } else {
+        abort.cancel();
+        all_done.recv().await;
+        warn!("drain deadline hit, force-closed the rest");
+    }
+
+    drop(stats_tx);
+    match stats_task.await {
+        Ok(summary) => summary.log(),
+        Err(e) => error!(error = %e, "stats aggregator failed"),
+    }
+
+    result
+}
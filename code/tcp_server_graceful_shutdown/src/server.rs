@@ -0,0 +1,1025 @@
+use crate::accept::{self, Accepted, Acceptors, Admission};
+use crate::accept_rate::AcceptRate;
+use crate::chaos::Chaos;
+use crate::config::{CertFiles, ChaosConfig, Framing, OverloadPolicy, RateLimitPolicy, ServerConfig, ShutdownMode, StopDeadlines, Transform};
+use crate::config_file::{self, Limits};
+use crate::connection::{ConnInfo, serve_connection};
+use crate::control::{self, Command, Request};
+#[cfg(feature = "tui")]
+use crate::dashboard::{Dashboard, Phase};
+use crate::error::ServerError;
+use crate::event_log::{Event, EventLog};
+use crate::http::Admin;
+use crate::listener::{Connection, Listener};
+use crate::tls::{self, DemoPki};
+use crate::registry::{self, Registry};
+use crate::soak::{self, Soak};
+use crate::{activation, cert_reload, panics, prometheus, runtime_sampler, sentinel, stats, stats_file, tasks, token, tracker};
+use shutdown_orchestrator::{Orchestrator, Outcome, StageReport};
+use shutdown_util::{ShutdownController, ShutdownTrigger};
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{Semaphore, broadcast, mpsc, watch};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_rustls::{TlsAcceptor, rustls};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{Instrument, error, info, warn};
+
+/// How long aborted connection tasks get to be joined, past the drain deadline.
+const ABORT_GRACE: Duration = Duration::from_secs(1);
+
+/// Builds a [`Server`] step by step.
+///
+/// ```no_run
+/// # async fn demo() -> Result<(), tcp_server_graceful_shutdown::ServerError> {
+/// use std::time::Duration;
+/// use tcp_server_graceful_shutdown::Server;
+///
+/// let handle = Server::builder()
+///     .bind("127.0.0.1:0".parse().unwrap())
+///     .write_timeout(Duration::from_secs(1))
+///     .build()
+///     .await?
+///     .start();
+/// handle.shutdown();
+/// handle.await_terminated().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ServerBuilder {
+    config: ServerConfig,
+    event_log: Option<EventLog>,
+}
+
+impl ServerBuilder {
+    /// Replaces every setting at once, e.g. with values parsed from the command line.
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the shutdown mechanism.
+    pub fn mode(mut self, mode: ShutdownMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// Sets how messages are delimited on the wire.
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.config.framing = framing;
+        self
+    }
+
+    /// Sets the largest frame accepted in `Framing::Length` mode (and request body in
+    /// `Framing::Http`).
+    pub fn max_message_size(mut self, max: usize) -> Self {
+        self.config.max_message_size = max;
+        self
+    }
+
+    /// Sets the longest line accepted in `Framing::Lines` mode.
+    pub fn max_line_length(mut self, max: usize) -> Self {
+        self.config.max_line_length = max;
+        self
+    }
+
+    /// Serves raw connections with separate reader and writer tasks when `true`.
+    pub fn split_halves(mut self, split: bool) -> Self {
+        self.config.split_halves = split;
+        self
+    }
+
+    /// Sets how many chunks may wait for the writer task in `split_halves` mode.
+    pub fn outbound_queue(mut self, capacity: usize) -> Self {
+        self.config.outbound_queue = capacity;
+        self
+    }
+
+    /// Sets how long a `split_halves` connection may spend flushing its queue once
+    /// shutdown starts.
+    pub fn shutdown_flush_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_flush_timeout = timeout;
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on accepted sockets when `true`.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.config.nodelay = nodelay;
+        self
+    }
+
+    /// Turns on TCP keepalive, probing after `idle` without traffic.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.config.keepalive = Some(idle);
+        self
+    }
+
+    /// Sets `SO_LINGER` on accepted sockets.
+    pub fn linger(mut self, linger: Duration) -> Self {
+        self.config.linger = Some(linger);
+        self
+    }
+
+    /// Sets how many `accept` errors in a row stop the server (`None` retries forever).
+    pub fn max_accept_failures(mut self, max: Option<u32>) -> Self {
+        self.config.max_accept_failures = max;
+        self
+    }
+
+    /// Reads a PROXY protocol header at the start of each connection when `true`. Only
+    /// turn this on behind a balancer that sends one; anyone else can claim any address.
+    pub fn proxy_protocol(mut self, proxy_protocol: bool) -> Self {
+        self.config.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    /// Serves TLS with a certificate from a freshly generated demo CA when `true`.
+    pub fn tls(mut self, tls: bool) -> Self {
+        self.config.tls = tls;
+        self
+    }
+
+    /// Requires clients to authenticate with a certificate from the demo CA when
+    /// `true`. Turns TLS on as well.
+    pub fn mtls(mut self, mtls: bool) -> Self {
+        self.config.mtls = mtls;
+        self
+    }
+
+    /// Serves TLS with the certificate chain and key in `files`, reloading them when
+    /// they change, instead of generating a demo CA.
+    pub fn tls_files(mut self, files: CertFiles) -> Self {
+        self.config.tls_files = Some(files);
+        self
+    }
+
+    /// Sets how often the certificate files are checked for changes.
+    pub fn tls_reload_interval(mut self, interval: Duration) -> Self {
+        self.config.tls_reload_interval = interval;
+        self
+    }
+
+    /// Sets how long a client may take to send its PROXY header and finish the TLS
+    /// handshake.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.config.handshake_timeout = timeout;
+        self
+    }
+
+    /// Reads settings from the TOML file at `path` when building, and applies changes
+    /// to it while the server runs. The keys are listed in the crate's `server.toml`.
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.config_file = Some(path.into());
+        self
+    }
+
+    /// Sets how often the config file is checked for changes.
+    pub fn config_reload_interval(mut self, interval: Duration) -> Self {
+        self.config.config_reload_interval = interval;
+        self
+    }
+
+    /// Listens on `addr` only, replacing any addresses set before. Use port 0 to let
+    /// the OS pick a free port.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.config.bind = vec![addr];
+        self
+    }
+
+    /// Listens on `addr` as well as on the addresses already set.
+    pub fn also_bind(mut self, addr: SocketAddr) -> Self {
+        self.config.bind.push(addr);
+        self
+    }
+
+    /// Sets `SO_REUSEPORT` on the listeners when `true`, so a second server (typically
+    /// the next version, during a restart) can listen on the same port at the same time.
+    pub fn reuseport(mut self, enabled: bool) -> Self {
+        self.config.reuseport = enabled;
+        self
+    }
+
+    /// Opens a control socket on `addr`, which must be a loopback address, for
+    /// operators to query the server and tell it to drain. Broadcast mode only.
+    pub fn control(mut self, addr: SocketAddr) -> Self {
+        self.config.control = Some(addr);
+        self
+    }
+
+    /// Serves Prometheus metrics at `GET /metrics` on `addr`, from the moment the server
+    /// starts until the end of its shutdown. Broadcast mode only.
+    pub fn metrics(mut self, addr: SocketAddr) -> Self {
+        self.config.metrics = Some(addr);
+        self
+    }
+
+    /// Takes over the listening sockets systemd passed in when started by socket
+    /// activation, falling back to binding the configured addresses otherwise.
+    pub fn socket_activation(mut self, enabled: bool) -> Self {
+        self.config.socket_activation = enabled;
+        self
+    }
+
+    /// Sets how long a single echo write may take.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = timeout;
+        self
+    }
+
+    /// Closes connections that send nothing for `timeout`.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long shutdown waits for connections before aborting them.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.config.drain_timeout = timeout;
+        self
+    }
+
+    /// Sets how long each subsystem after the connections gets to stop.
+    pub fn stop_deadlines(mut self, deadlines: StopDeadlines) -> Self {
+        self.config.stop_deadlines = deadlines;
+        self
+    }
+
+    /// Writes the live stats to `path` every `stats_flush_interval`, and at shutdown.
+    pub fn stats_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.stats_file = Some(path.into());
+        self
+    }
+
+    /// Sets how often the stats file is written.
+    pub fn stats_flush_interval(mut self, interval: Duration) -> Self {
+        self.config.stats_flush_interval = interval;
+        self
+    }
+
+    /// Sets how often closed connections are swept out of the list `CONNS` reads.
+    pub fn sweep_interval(mut self, interval: Duration) -> Self {
+        self.config.sweep_interval = interval;
+        self
+    }
+
+    /// Logs the tokio runtime's metrics every `interval`, and once more at shutdown.
+    pub fn runtime_metrics_interval(mut self, interval: Duration) -> Self {
+        self.config.runtime_metrics_interval = Some(interval);
+        self
+    }
+
+    /// Logs the resident set and the task and connection counts every `interval`, so a
+    /// leak shows up over a long run. Broadcast mode only.
+    pub fn soak_report_interval(mut self, interval: Duration) -> Self {
+        self.config.soak_report_interval = Some(interval);
+        self
+    }
+
+    /// Records what the server does in `events`, for a test to check the order of.
+    /// Broadcast mode only.
+    pub fn event_log(mut self, events: EventLog) -> Self {
+        self.event_log = Some(events);
+        self
+    }
+
+    /// Draws a live dashboard on the terminal instead of relying on the log. Needs the
+    /// `tui` feature.
+    pub fn tui(mut self, tui: bool) -> Self {
+        self.config.tui = tui;
+        self
+    }
+
+    /// Makes `Framing::Lines` connections negotiate shutdown: the server sends `GOAWAY <ms>`
+    /// and waits up to `deadline` for the client's `BYE`.
+    pub fn goaway(mut self, deadline: Duration) -> Self {
+        self.config.goaway = Some(deadline);
+        self
+    }
+
+    /// Makes `Framing::Lines` connections send `PING` every `period` and close once
+    /// `heartbeat_misses` of them in a row go unanswered.
+    pub fn heartbeat(mut self, period: Duration) -> Self {
+        self.config.heartbeat = Some(period);
+        self
+    }
+
+    /// Sets how many unanswered `PING`s close a connection.
+    pub fn heartbeat_misses(mut self, misses: u32) -> Self {
+        self.config.heartbeat_misses = misses;
+        self
+    }
+
+    /// Sets the faults chaos mode injects, and whether it starts on.
+    pub fn chaos(mut self, chaos: ChaosConfig) -> Self {
+        self.config.chaos = chaos;
+        self
+    }
+
+    /// Makes the task of every `n`th connection panic as soon as it's admitted. For
+    /// testing the panic handling only.
+    pub fn panic_injection(mut self, n: u64) -> Self {
+        self.config.panic_injection = Some(n);
+        self
+    }
+
+    /// Shuts the server down once `max` connection tasks have panicked.
+    pub fn max_panics(mut self, max: u64) -> Self {
+        self.config.max_panics = Some(max);
+        self
+    }
+
+    /// Sets what the server does to each message before echoing it.
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.config.transform = transform;
+        self
+    }
+
+    /// Sets how long `Transform::Delay` holds each reply back.
+    pub fn transform_delay(mut self, delay: Duration) -> Self {
+        self.config.transform_delay = delay;
+        self
+    }
+
+    /// Sets the chance, from 0 to 1, that `Transform::Drop` swallows a reply.
+    pub fn drop_probability(mut self, probability: f64) -> Self {
+        self.config.drop_probability = probability;
+        self
+    }
+
+    /// Limits how many connections are served at once.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.config.max_connections = Some(max);
+        self
+    }
+
+    /// Sets what happens to new connections while the server is full.
+    pub fn when_full(mut self, policy: OverloadPolicy) -> Self {
+        self.config.when_full = policy;
+        self
+    }
+
+    /// Admits at most `rate` new connections a second.
+    pub fn accept_rate(mut self, rate: u32) -> Self {
+        self.config.accept_rate = Some(rate);
+        self
+    }
+
+    /// Sets how many connections may arrive at once before the accept rate applies.
+    pub fn accept_burst(mut self, burst: u32) -> Self {
+        self.config.accept_burst = burst;
+        self
+    }
+
+    /// Limits each connection to `rate` bytes a second.
+    pub fn rate_limit_bytes(mut self, rate: u64) -> Self {
+        self.config.rate_limit_bytes = Some(rate);
+        self
+    }
+
+    /// Limits each connection to `rate` messages a second.
+    pub fn rate_limit_messages(mut self, rate: u32) -> Self {
+        self.config.rate_limit_messages = Some(rate);
+        self
+    }
+
+    /// Sets what happens to a connection over its rate limit.
+    pub fn over_rate_limit(mut self, policy: RateLimitPolicy) -> Self {
+        self.config.over_rate_limit = policy;
+        self
+    }
+
+    /// Binds the listeners. Binding here, rather than in `start`, means address-in-use
+    /// errors show up before anything is spawned.
+    pub async fn build(self) -> Result<Server, ServerError> {
+        self.build_from(|config| {
+            let inherited = if config.socket_activation {
+                activation::systemd_listeners()?
+            } else {
+                None
+            };
+            if let Some(listeners) = inherited {
+                return Ok((listeners, true));
+            }
+            if config.bind.is_empty() {
+                return Err(ServerError::InvalidConfig("no address to listen on".to_string()));
+            }
+            let listeners = config
+                .bind
+                .iter()
+                .map(|&addr| accept::bind_listener(addr, config.reuseport).map_err(|source| ServerError::Bind { addr, source }))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((listeners, false))
+        })
+        .await
+    }
+
+    /// Serves connections from `listeners` instead of binding the `bind` addresses:
+    /// `bind`, `reuseport` and `socket_activation` are ignored, from the config file
+    /// too. The control socket and the metrics listener, if set, are still bound.
+    pub async fn build_with_listeners<L: Listener>(self, listeners: Vec<L>) -> Result<Server<L>, ServerError> {
+        if listeners.is_empty() {
+            return Err(ServerError::InvalidConfig("no listener to accept from".to_string()));
+        }
+        self.build_from(|_| Ok((listeners, false))).await
+    }
+
+    /// Everything `build` does, with the listeners (and whether systemd passed them in)
+    /// from `listen`, once the config file has had its say.
+    async fn build_from<L: Listener>(
+        mut self,
+        listen: impl FnOnce(&ServerConfig) -> Result<(Vec<L>, bool), ServerError>,
+    ) -> Result<Server<L>, ServerError> {
+        // First, since the file can change any of the settings below, even `bind`.
+        let (limits, config_watcher) = match self.config.config_file.clone() {
+            Some(path) => {
+                let interval = self.config.config_reload_interval;
+                let (config, limits, watcher) =
+                    config_file::watch_config_file(path, self.config, interval).map_err(ServerError::ConfigFile)?;
+                self.config = config;
+                (limits, Some(watcher))
+            }
+            None => (config_file::fixed(&self.config), None),
+        };
+        let control = match self.config.control {
+            // Anyone who can reach it can shut the server down, so it stays on this host.
+            Some(addr) if !addr.ip().is_loopback() => {
+                return Err(ServerError::InvalidConfig(format!(
+                    "the control socket must be on a loopback address, not {addr}"
+                )));
+            }
+            Some(_) if self.config.mode != ShutdownMode::Broadcast => {
+                return Err(ServerError::InvalidConfig("the control socket needs broadcast mode".to_string()));
+            }
+            Some(addr) => Some(accept::bind_listener(addr, false).map_err(|source| ServerError::Bind { addr, source })?),
+            None => None,
+        };
+        let metrics = match self.config.metrics {
+            Some(_) if self.config.mode != ShutdownMode::Broadcast => {
+                return Err(ServerError::InvalidConfig("the metrics listener needs broadcast mode".to_string()));
+            }
+            Some(addr) => Some(accept::bind_listener(addr, false).map_err(|source| ServerError::Bind { addr, source })?),
+            None => None,
+        };
+        if self.config.tui && cfg!(not(feature = "tui")) {
+            return Err(ServerError::InvalidConfig("the dashboard needs the tui feature".to_string()));
+        }
+        if self.event_log.is_some() && self.config.mode != ShutdownMode::Broadcast {
+            return Err(ServerError::InvalidConfig("the event log needs broadcast mode".to_string()));
+        }
+        if self.config.soak_report_interval.is_some() && self.config.mode != ShutdownMode::Broadcast {
+            return Err(ServerError::InvalidConfig("the soak report needs broadcast mode".to_string()));
+        }
+        if self.config.tui && self.config.mode != ShutdownMode::Broadcast {
+            return Err(ServerError::InvalidConfig("the dashboard needs broadcast mode".to_string()));
+        }
+        // The token variants echo raw bytes and nothing else; better to say so than to
+        // serve without the limit or the timeout someone asked for.
+        if self.config.mode != ShutdownMode::Broadcast {
+            let unsupported = [
+                (self.config.max_connections.is_some(), "the connection limit"),
+                (self.config.idle_timeout.is_some(), "the idle timeout"),
+                (self.config.framing != Framing::Raw, "framing"),
+                (self.config.proxy_protocol, "the PROXY protocol"),
+            ];
+            if let Some((_, setting)) = unsupported.iter().find(|(asked, _)| *asked) {
+                return Err(ServerError::InvalidConfig(format!("{setting} needs broadcast mode")));
+            }
+        }
+        let wants_tls =self.config.tls || self.config.mtls || self.config.tls_files.is_some();
+        if wants_tls && self.config.mode != ShutdownMode::Broadcast {
+            return Err(ServerError::InvalidConfig("TLS needs broadcast mode".to_string()));
+        }
+        // Now, so the counts start with the first connection rather than the first scrape.
+        if metrics.is_some() || self.config.framing == Framing::Http {
+            prometheus::install();
+        }
+        let (listeners, socket_activated) = listen(&self.config)?;
+        let (tls, pki) = if let Some(files) = &self.config.tls_files {
+            if self.config.mtls {
+                return Err(ServerError::InvalidConfig(
+                    "mutual TLS needs the demo CA, so it can't be combined with certificate files".to_string(),
+                ));
+            }
+            let updates = cert_reload::watch_cert_files(files.clone(), self.config.tls_reload_interval)?;
+            (Some(updates), None)
+        } else if self.config.tls || self.config.mtls {
+            let pki = DemoPki::generate()?;
+            // Nothing will ever be sent on this one; the receiver just keeps the
+            // initial value, so both sources look the same to the accept loop.
+            let (_, updates) = watch::channel(tls::server_config(&pki, self.config.mtls)?);
+            (Some(updates), Some(pki))
+        } else {
+            (None, None)
+        };
+        Ok(Server {
+            listeners,
+            socket_activated,
+            control,
+            metrics,
+            limits,
+            config_watcher,
+            tls,
+            pki,
+            config: self.config,
+            event_log: self.event_log,
+        })
+    }
+}
+
+/// A bound, not yet running, echo server, accepting from TCP listeners unless it was
+/// built with [`ServerBuilder::build_with_listeners`].
+pub struct Server<L = TcpListener> {
+    listeners: Vec<L>,
+    socket_activated: bool,
+    control: Option<TcpListener>,
+    metrics: Option<TcpListener>,
+    limits: watch::Receiver<Limits>,
+    /// The task re-reading `config_file`, if there is one.
+    config_watcher: Option<JoinHandle<()>>,
+    tls: Option<watch::Receiver<Arc<rustls::ServerConfig>>>,
+    pki: Option<DemoPki>,
+    config: ServerConfig,
+    event_log: Option<EventLog>,
+}
+
+impl Server {
+    /// Starts configuring a server with the default settings.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+}
+
+impl<L: Listener> Server<L> {
+    /// The first address the server is actually listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listeners[0].local_addr()
+    }
+
+    /// Every address the server is listening on, in the order they were added.
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.listeners.iter().map(L::local_addr).collect()
+    }
+
+    /// The address of the control socket, if there is one.
+    pub fn control_addr(&self) -> Option<SocketAddr> {
+        self.control.as_ref().map(|listener| listener.local_addr().expect("a bound listener always has a local address"))
+    }
+
+    /// The address `/metrics` is served on, if it has a listener of its own.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics.as_ref().map(|listener| listener.local_addr().expect("a bound listener always has a local address"))
+    }
+
+    /// Whether the listeners came from systemd rather than from binding `bind`.
+    pub fn is_socket_activated(&self) -> bool {
+        self.socket_activated
+    }
+
+    /// The demo CA and the certificates it issued, if TLS is on without certificate
+    /// files. Clients need to trust the CA, and in mutual TLS mode present its client
+    /// certificate.
+    pub fn tls_pki(&self) -> Option<&DemoPki> {
+        self.pki.as_ref()
+    }
+
+    /// Spawns the accept loops and returns a handle to control them.
+    pub fn start(self) -> ServerHandle {
+        let local_addrs = self
+            .local_addrs()
+            .expect("a bound listener always has a local address");
+        let control_addr = self.control_addr();
+        let metrics_addr = self.metrics_addr();
+
+        let (shutdown, task) = match self.config.mode {
+            ShutdownMode::Broadcast => {
+                let controller = ShutdownController::new();
+                let trigger = controller.trigger_handle();
+                // Subscribe before spawning: a signal sent before the task's first poll
+                // would otherwise go to nobody and the server would never stop.
+                let shutdown_rx = controller.subscribe();
+                let task = tasks::spawn("server", run_server(self, controller, shutdown_rx));
+                (Shutdown::Broadcast(trigger), task)
+            }
+            ShutdownMode::Token => {
+                let root_token = CancellationToken::new();
+                let task = tasks::spawn("server", token::run_server(self.listeners, root_token.clone(), self.config));
+                (Shutdown::Token(root_token), task)
+            }
+            ShutdownMode::Tracker => {
+                let root_token = CancellationToken::new();
+                let task = tasks::spawn("server", tracker::run_server(self.listeners, root_token.clone(), self.config));
+                (Shutdown::Token(root_token), task)
+            }
+            ShutdownMode::Sentinel => {
+                let root_token = CancellationToken::new();
+                let task = tasks::spawn("server", sentinel::run_server(self.listeners, root_token.clone(), self.config));
+                (Shutdown::Token(root_token), task)
+            }
+        };
+
+        ServerHandle {
+            local_addrs,
+            control_addr,
+            metrics_addr,
+            shutdown,
+            task,
+            result: None,
+        }
+    }
+}
+
+enum Shutdown {
+    Broadcast(ShutdownTrigger),
+    Token(CancellationToken),
+}
+
+/// Controls a running server.
+pub struct ServerHandle {
+    local_addrs: Vec<SocketAddr>,
+    control_addr: Option<SocketAddr>,
+    metrics_addr: Option<SocketAddr>,
+    shutdown: Shutdown,
+    task: JoinHandle<Result<(), ServerError>>,
+    result: Option<Result<(), ServerError>>,
+}
+
+impl ServerHandle {
+    /// The first address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    /// Every address the server is listening on.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// The address of the control socket, if there is one.
+    pub fn control_addr(&self) -> Option<SocketAddr> {
+        self.control_addr
+    }
+
+    /// The address `/metrics` is served on, if it has a listener of its own.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// Asks the server to stop accepting and drain its connections. Returns immediately.
+    pub fn shutdown(&self) {
+        match &self.shutdown {
+            Shutdown::Broadcast(trigger) => trigger.trigger(),
+            Shutdown::Token(token) => token.cancel(),
+        }
+    }
+
+    /// Waits for the server to stop, whoever asked it to: `shutdown`, `DRAIN` on the
+    /// control socket, `max_panics`, a key press on the dashboard. Cancel-safe; the
+    /// result waits for `await_terminated`.
+    pub async fn finished(&mut self) {
+        if self.result.is_none() {
+            self.result = Some(match (&mut self.task).await {
+                Ok(result) => result,
+                Err(e) => Err(ServerError::Task(e)),
+            });
+        }
+    }
+
+    /// Waits for the server to finish draining.
+    pub async fn await_terminated(mut self) -> Result<(), ServerError> {
+        self.finished().await;
+        self.result.take().expect("finished leaves the result")
+    }
+}
+
+async fn run_server<L: Listener>(server: Server<L>, mut controller: ShutdownController, mut shutdown_rx: broadcast::Receiver<()>) -> Result<(), ServerError> {
+    let Server { listeners, control, metrics, limits, config_watcher, tls, config, event_log, .. } = server;
+    let config = Arc::new(config);
+    let limiter = config.max_connections.map(|n| Arc::new(Semaphore::new(n)));
+    let accept_rate = config.accept_rate.map(|rate| Arc::new(AcceptRate::new(rate, config.accept_burst)));
+    let mut acceptors = Acceptors::spawn(listeners, limiter, config.when_full, accept_rate, config.max_accept_failures);
+    let mut result = Ok(());
+    let mut next_conn_id = 0_u64;
+    let (stats_tx, stats_task) = stats::spawn_aggregator();
+    let admin = Arc::new(Admin {
+        stats: Arc::new(stats::LiveStats::new()),
+        shutdown: controller.trigger_handle(),
+        chaos: Arc::new(Chaos::new(config.chaos.clone())),
+    });
+    let flusher = config
+        .stats_file
+        .clone()
+        .map(|path| stats_file::spawn_flusher(path, config.stats_flush_interval, admin.stats.clone()));
+    // The control socket's sessions ask us for things over `commands`; see `control`.
+    let (commands_tx, mut commands_rx) = mpsc::channel(8);
+    let mut control_sessions = JoinSet::new();
+    // What `CONNS` lists, kept short by a job of its own.
+    let registry = Arc::new(Registry::default());
+    let sweeper = registry::spawn_sweeper(registry.clone(), config.sweep_interval);
+    let sampler = config.runtime_metrics_interval.and_then(runtime_sampler::spawn);
+    let mut soak = config.soak_report_interval.map(Soak::new);
+    let mut drain_timeout = config.drain_timeout;
+    // Not one of the subsystems below: it's dropped, and aborted, only as this function
+    // returns, so a scrape in the middle of the shutdown still gets an answer.
+    let _metrics = metrics.map(|listener| AbortOnDropHandle::new(tasks::spawn("metrics endpoint", prometheus::serve(listener))));
+    // Not a subsystem either: it's told how each stage went, and stops after the report.
+    #[cfg(feature = "tui")]
+    let dashboard = config
+        .tui
+        .then(|| Dashboard::spawn(registry.clone(), admin.stats.clone(), controller.trigger_handle()))
+        .flatten();
+
+    loop {
+        tokio::select! {
+            recv = shutdown_rx.recv() => {
+                match recv {
+                    Ok(()) => {
+                        info!("shutdown requested");
+                        break;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "shutdown receiver lagged");
+                        break;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        info!("shutdown channel closed");
+                        break;
+                    }
+                }
+            }
+            accepted = acceptors.next() => {
+                // Giving up on accepting is a shutdown like any other: the connections
+                // still open have to hear about it, or they sit out the drain deadline.
+                let Accepted { socket, peer_addr, admission } = match accepted {
+                    Some(Ok(accepted)) => accepted,
+                    Some(Err(e)) => {
+                        result = Err(ServerError::Accept(e));
+                        controller.trigger();
+                        break;
+                    }
+                    None => {
+                        controller.trigger();
+                        break;
+                    }
+                };
+                next_conn_id += 1;
+                let conn = ConnInfo::new(next_conn_id, peer_addr).with_event_log(event_log.clone());
+                let span = conn.span();
+                match admission {
+                    Admission::Busy => {
+                        span.in_scope(|| info!("rejected: at max connections"));
+                        let write_timeout = config.write_timeout;
+                        controller.spawn_named(&format!("reject {conn}"), async move {
+                            reject_busy(socket, write_timeout).await;
+                        }.instrument(span));
+                    }
+                    Admission::Admitted(permit) => {
+                        // The shutdown may have begun since this pass started, and then a
+                        // receiver made now could have missed the signal. Such a latecomer
+                        // is closed, like the ones still waiting in the backlog.
+                        let Some((conn_shutdown, registration)) = controller.subscribe_if_running() else {
+                            span.in_scope(|| info!("refused: the server is shutting down"));
+                            continue;
+                        };
+                        span.in_scope(|| {
+                            info!("accepted");
+                            socket.configure(&config);
+                        });
+                        conn.record(|conn| Event::Accepted { conn });
+                        let conn = Arc::new(conn);
+                        registry.insert(&conn);
+                        let config = config.clone();
+                        let stats_tx = stats_tx.clone();
+                        let limits = limits.clone();
+                        let admin = admin.clone();
+                        admin.stats.opened();
+                        // Whatever certificate is current now; a reload mid-handshake
+                        // doesn't affect this connection.
+                        let tls = tls.as_ref().map(|updates| TlsAcceptor::from(updates.borrow().clone()));
+                        let inject_panic = config.panic_injection.is_some_and(|n| conn.id.is_multiple_of(n));
+                        controller.spawn_named(&conn.to_string(), async move {
+                            if inject_panic {
+                                panic!("injected panic in {conn}");
+                            }
+                            let result = serve_connection(socket, conn_shutdown, &config, limits, &conn, tls.as_ref(), &admin).await;
+                            conn.log_closed(&result);
+                            conn.record(|conn| Event::Closed { conn });
+                            admin.stats.closed(&conn.stats());
+                            // Only fails if the aggregator is gone, and then nobody's counting.
+                            let _ = stats_tx.send(conn.stats()).await;
+                            // The slot is freed only once the connection is completely done.
+                            drop((permit, registration));
+                        }.instrument(span));
+                    }
+                }
+            }
+            accepted = control::accept(control.as_ref()) => match accepted {
+                Ok((socket, peer)) => {
+                    info!(%peer, "control session connected");
+                    let session = control::session(socket, commands_tx.clone(), controller.subscribe());
+                    tasks::spawn_in(&mut control_sessions, &format!("control session peer={peer}"), session);
+                }
+                Err(e) => warn!(error = %e, "control socket accept failed"),
+            },
+            Some(Command { request, reply }) = commands_rx.recv() => {
+                let (answer, stop) = match request {
+                    Request::Stats => (format!("OK {}", admin.stats.to_json()), None),
+                    Request::Conns => (list_conns(&registry), None),
+                    Request::Chaos(switch) => {
+                        if let Some(enabled) = switch {
+                            admin.chaos.set_enabled(enabled);
+                            info!(enabled, "chaos switched on the control socket");
+                        }
+                        (format!("OK {}", admin.chaos.describe()), None)
+                    }
+                    Request::Drain(deadline) => (format!("OK draining for up to {deadline:?}"), Some(deadline)),
+                    Request::Abort => ("OK aborting".to_string(), Some(Duration::ZERO)),
+                };
+                let _ = reply.send(answer);
+                if let Some(deadline) = stop {
+                    info!(?deadline, "shutdown requested on the control socket");
+                    drain_timeout = deadline;
+                    // Tells the connections (and control sessions) like any other shutdown.
+                    controller.trigger();
+                    break;
+                }
+            }
+            Some(_) = control_sessions.join_next(), if !control_sessions.is_empty() => {}
+            () = soak::next_report(&mut soak) => {
+                if let Some(soak) = &mut soak {
+                    soak.report(&admin.stats, controller.active_tasks(), registry.live().len());
+                }
+            }
+            // Reaping as we go is what lets a panic be noticed now rather than at the
+            // drain. Rejections run in the controller too, but they never panic, so
+            // every panic here is a connection's.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => match joined {
+                Ok(()) => {}
+                Err(e) if e.is_panic() => {
+                    let panics = admin.stats.panicked();
+                    error!("connection {}", panics::describe(e));
+                    if config.max_panics.is_some_and(|max| panics >= max) {
+                        error!(panics, "too many connection tasks have panicked, shutting down");
+                        controller.trigger();
+                        break;
+                    }
+                }
+                Err(e) => warn!("connection {}", panics::describe(e)),
+            },
+        }
+    }
+    #[cfg(feature = "tui")]
+    let phase = dashboard.as_ref().map(|dashboard| {
+        let phase = dashboard.phase();
+        phase.send_replace(Phase::ShuttingDown { since: Instant::now(), progress: None });
+        phase
+    });
+    // Shutdown is the startup in reverse. Connections report to the stats aggregator,
+    // count towards the stats file's final totals, leave entries for the sweeper and
+    // read the limits the config watcher publishes, so all four have to outlive them.
+    // The periodic jobs finish the tick they're in and make one last run on the way out
+    // (see `periodic`), so only then do the totals and the list say how the drain
+    // ended. The runtime sampler goes with them, so its final sample covers the drain.
+    // Nothing new may arrive while they drain, so the listeners go before them.
+    // Each stop future owns what it stops, and isn't polled until everything depending
+    // on it is done.
+    let deadlines = config.stop_deadlines;
+    let mut orchestrator = Orchestrator::new();
+    orchestrator
+        .register("listeners", deadlines.listeners, async move {
+            acceptors.shutdown().await;
+            drop(control);
+        })
+        .depends_on(["connections"]);
+    // `wait_idle_timeout` keeps to the drain deadline by itself, aborting what's left
+    // and saying so, but aborted tasks still have to be joined: a little extra, so the
+    // orchestrator's own deadline only catches a drain that's truly stuck.
+    let live_stats = admin.stats.clone();
+    orchestrator
+        .register("connections", drain_timeout + ABORT_GRACE, async move {
+            info!(?drain_timeout, connections = controller.active_tasks(), "waiting for the connection tasks to finish");
+            let started = Instant::now();
+            let report = controller.wait_idle_timeout(drain_timeout).await;
+            metrics::gauge!(prometheus::DRAIN_SECONDS).set(started.elapsed().as_secs_f64());
+            for e in report.errors {
+                if e.is_panic() {
+                    live_stats.panicked();
+                }
+                error!("connection {}", panics::describe(e));
+            }
+            if report.aborted > 0 {
+                warn!(aborted = report.aborted, "drain deadline hit, force-closed the rest");
+            } else {
+                info!("all connection tasks finished");
+            }
+            if let Some(events) = event_log {
+                events.record(Event::Drained { aborted: report.aborted });
+            }
+        })
+        .depends_on(["stats aggregator", "stats flusher", "sweeper", "config watcher", "runtime sampler"]);
+    // Sessions saw the shutdown too and hang up once they've said so; a reply that was
+    // waiting on us gets "ERR server is shutting down" when `commands_rx` goes. A
+    // session that misses the deadline is aborted with the `JoinSet`.
+    orchestrator.register("control sessions", deadlines.control, async move {
+        drop(commands_rx);
+        while control_sessions.join_next().await.is_some() {}
+    });
+    // Every connection task has finished or been aborted, taking its sender with it;
+    // dropping ours closes the channel and lets the aggregator return. Connections cut
+    // off by the drain deadline never got to report, so they're missing from the totals.
+    orchestrator.register("stats aggregator", deadlines.stats, async move {
+        drop(stats_tx);
+        match stats_task.await {
+            Ok(summary) => summary.log(),
+            Err(e) => error!(error = %e, "stats aggregator failed"),
+        }
+    });
+    orchestrator.register("stats flusher", deadlines.flusher, async move {
+        if let Some(flusher) = flusher {
+            flusher.stop().await;
+        }
+    });
+    orchestrator.register("sweeper", deadlines.sweeper, sweeper.stop());
+    orchestrator.register("runtime sampler", deadlines.sampler, async move {
+        if let Some(sampler) = sampler {
+            runtime_sampler::stop(sampler).await;
+        }
+    });
+    // The watcher stops once nobody holds a receiver, and with the connections gone
+    // ours is the last one.
+    orchestrator.register("config watcher", deadlines.reloader, async move {
+        drop(limits);
+        if let Some(watcher) = config_watcher {
+            let _ = watcher.await;
+        }
+    });
+    orchestrator.on_stage(move |stage| {
+        log_stage(stage);
+        #[cfg(feature = "tui")]
+        if let Some(phase) = &phase {
+            let names: Vec<_> = stage.subsystems.iter().map(|subsystem| subsystem.name.as_str()).collect();
+            phase.send_modify(|phase| {
+                if let Phase::ShuttingDown { progress, .. } = phase {
+                    *progress = Some(format!("stage {}/{} done: {}", stage.number, stage.of, names.join(", ")));
+                }
+            });
+        }
+    });
+    let report = orchestrator.shutdown().await.expect("the subsystem graph is fixed and has no cycles");
+    let failed: Vec<_> = report.failed().map(|subsystem| subsystem.name.as_str()).collect();
+    if failed.is_empty() {
+        info!(elapsed = ?report.elapsed(), "shutdown done");
+    } else {
+        warn!(elapsed = ?report.elapsed(), failed = %failed.join(", "), "shutdown done, but not everything stopped cleanly");
+    }
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = dashboard {
+        let failed = failed.iter().map(|name| name.to_string()).collect();
+        dashboard.phase().send_replace(Phase::Done { elapsed: report.elapsed(), failed });
+        dashboard.stop().await;
+    }
+
+    result
+}
+
+/// Logs how each subsystem in a finished shutdown stage went.
+fn log_stage(stage: &StageReport) {
+    for subsystem in &stage.subsystems {
+        let (number, of, name, elapsed) = (stage.number, stage.of, &subsystem.name, subsystem.elapsed);
+        match subsystem.outcome {
+            Outcome::Stopped => info!("shutdown stage {number}/{of} {name}: stopped in {elapsed:.1?}"),
+            Outcome::MissedDeadline => warn!("shutdown stage {number}/{of} {name}: missed its deadline, moving on"),
+            Outcome::Panicked => error!("shutdown stage {number}/{of} {name}: panicked while stopping"),
+        }
+    }
+}
+
+/// The reply to `CONNS`: a count, then one line per connection still open.
+fn list_conns(registry: &Registry) -> String {
+    let conns = registry.live();
+    let mut reply = format!("OK {} connection(s)", conns.len());
+    for conn in conns {
+        let stats = conn.stats();
+        reply.push_str(&format!(
+            "\n{conn} in={}B out={}B age={:.1?}",
+            stats.bytes_read, stats.bytes_written, stats.duration
+        ));
+    }
+    reply
+}
+
+/// Tells a client we're full and hangs up. Bounded by the write timeout so a client
+/// that never reads can't pin the task.
+async fn reject_busy(mut socket: impl AsyncWrite + Unpin, write_timeout: Duration) {
+    let _ = tokio::time::timeout(write_timeout, async {
+        socket.write_all(b"server busy\n").await?;
+        socket.shutdown().await
+    })
+    .await;
+}
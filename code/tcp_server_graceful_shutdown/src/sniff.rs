@@ -0,0 +1,179 @@
+//! `Framing::Auto`: one port, three protocols, told apart by the first bytes.
+//!
+//! Raw echo, the line protocol and HTTP look nothing alike once a client has said
+//! something: an HTTP request opens with a method and a space, a line client sends UTF-8
+//! text ending in a newline, and anything else is raw bytes. So we read a little, put
+//! it in a peek buffer, decide, and hand the connection to that protocol's handler as
+//! a [`Rewind`] stream that replays the peeked bytes before the rest. The handlers
+//! never know anybody looked.
+//!
+//! `TcpStream::peek` would save the copy, but the connection may be TLS or chaos by the
+//! time it gets here, and those have no `peek`. Reading and replaying works for any
+//! stream.
+//!
+//! Raw echo is the only protocol recognised by what *doesn't* arrive: a raw client's
+//! first message need not end in a newline, so the server waits [`SNIFF_WINDOW`] to be
+//! sure no newline is coming, and a raw client's first echo is that much later.
+
+use crate::config::Framing;
+use crate::error::{ConnectionError, Deadline};
+use bytes::{Buf, BytesMut};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::sync::broadcast;
+use tokio::time::{Instant, timeout, timeout_at};
+
+/// How long to wait for the rest of a first message that could still be a line or a
+/// request.
+const SNIFF_WINDOW: Duration = Duration::from_millis(100);
+
+/// The most we read before deciding. A first line longer than this is raw bytes.
+const PEEK_LIMIT: usize = 1024;
+
+/// The methods our HTTP responder knows how to answer, or refuse.
+const METHODS: [&[u8]; 7] = [b"GET ", b"HEAD ", b"POST ", b"PUT ", b"DELETE ", b"OPTIONS ", b"PATCH "];
+
+/// Reads the first bytes from `socket` and works out which protocol the client speaks.
+///
+/// The first byte has to arrive within `handshake_timeout`, like a PROXY header or a TLS
+/// handshake would. `None` means shutdown was signalled first, or the peer hung up
+/// without a word.
+pub(crate) async fn sniff<S>(
+    mut socket: S,
+    shutdown_rx: &mut broadcast::Receiver<()>,
+    handshake_timeout: Duration,
+) -> Result<Option<(Framing, Rewind<S>)>, ConnectionError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut peeked = BytesMut::with_capacity(PEEK_LIMIT);
+    let first = tokio::select! {
+        _ = shutdown_rx.recv() => return Ok(None),
+        read = timeout(handshake_timeout, socket.read_buf(&mut peeked)) => read,
+    };
+    match first {
+        Ok(Ok(0)) => return Ok(None),
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => return Err(ConnectionError::Read(e)),
+        Err(_) => return Err(ConnectionError::Timeout(Deadline::Sniff)),
+    }
+
+    let deadline = Instant::now() + SNIFF_WINDOW;
+    let framing = loop {
+        if let Some(framing) = classify(&peeked) {
+            break framing;
+        }
+        if peeked.len() >= PEEK_LIMIT {
+            break Framing::Raw;
+        }
+        let more = tokio::select! {
+            _ = shutdown_rx.recv() => return Ok(None),
+            read = timeout_at(deadline, socket.read_buf(&mut peeked)) => read,
+        };
+        match more {
+            Ok(Ok(0)) | Err(_) => break Framing::Raw,
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(ConnectionError::Read(e)),
+        }
+    };
+    Ok(Some((framing, Rewind { peeked, inner: socket })))
+}
+
+/// Decides from the bytes so far, or `None` while they could still be more than one
+/// thing.
+fn classify(peeked: &[u8]) -> Option<Framing> {
+    for method in METHODS {
+        if peeked.starts_with(method) {
+            return Some(Framing::Http);
+        }
+    }
+    let line = match peeked.iter().position(|&b| b == b'\n') {
+        Some(end) => &peeked[..end],
+        None => peeked,
+    };
+    match std::str::from_utf8(line) {
+        // A character cut off at the end of a read is fine; one that can never be
+        // valid isn't text.
+        Err(e) if e.error_len().is_some() => Some(Framing::Raw),
+        _ if line.len() < peeked.len() => Some(Framing::Lines),
+        _ => None,
+    }
+}
+
+/// A stream that replays the bytes already read from it before reading more.
+pub(crate) struct Rewind<S> {
+    peeked: BytesMut,
+    inner: S,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewind<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.peeked.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        let n = this.peeked.len().min(buf.remaining());
+        buf.put_slice(&this.peeked[..n]);
+        this.peeked.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewind<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_the_first_bytes() {
+        assert_eq!(classify(b"GET /health HTTP/1.1\r\n"), Some(Framing::Http));
+        assert_eq!(classify(b"POST "), Some(Framing::Http));
+        assert_eq!(classify(b"hello\n"), Some(Framing::Lines));
+        assert_eq!(classify("grüß dich\nand more".as_bytes()), Some(Framing::Lines));
+        assert_eq!(classify(b"\xff\xfe binary"), Some(Framing::Raw));
+
+        // Could still become any of them.
+        assert_eq!(classify(b"GE"), None);
+        assert_eq!(classify(b"hello"), None);
+        assert_eq!(classify(&"ü".as_bytes()[..1]), None);
+    }
+
+    #[tokio::test]
+    async fn test_rewind_replays_the_peeked_bytes_first() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let (_tx, mut shutdown_rx) = broadcast::channel(1);
+        tokio::io::AsyncWriteExt::write_all(&mut server, b"hi there").await.unwrap();
+        drop(server);
+
+        let (framing, mut rewind) = sniff(&mut client, &mut shutdown_rx, Duration::from_secs(1)).await.unwrap().unwrap();
+        assert_eq!(framing, Framing::Raw);
+        let mut everything = Vec::new();
+        rewind.read_to_end(&mut everything).await.unwrap();
+        assert_eq!(everything, b"hi there");
+    }
+
+    #[tokio::test]
+    async fn test_a_silent_client_times_out() {
+        let (mut client, _server) = tokio::io::duplex(64);
+        let (_tx, mut shutdown_rx) = broadcast::channel(1);
+
+        let silent = sniff(&mut client, &mut shutdown_rx, Duration::from_millis(20)).await;
+        assert!(matches!(silent, Err(ConnectionError::Timeout(Deadline::Sniff))));
+    }
+}
@@ -0,0 +1,92 @@
+//! The soak report: for a server left running under churning load for an hour before a
+//! workshop, a line every `soak_report_interval` with the numbers that only grow if
+//! something leaks.
+//!
+//! A connection that's closed should leave nothing behind, so with the load steady
+//! every count here should be too. The connection tasks in the `JoinSet` climbing
+//! past the open connections means finished tasks aren't being reaped; the runtime's
+//! live tasks climbing past those means something spawned outside the `JoinSet` never
+//! finishes; the connection list climbing past both means the sweeper isn't keeping
+//! up; and the resident set growing with none of them means memory that outlives its
+//! connection. Growth is shown against the first report, after the allocator has
+//! settled in a little, rather than against startup.
+
+use crate::stats::LiveStats;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::time::{Interval, MissedTickBehavior, interval};
+use tracing::info;
+
+/// The report's timer and what it compares against.
+pub(crate) struct Soak {
+    ticks: Interval,
+    first_rss: Option<u64>,
+    reports: u64,
+}
+
+impl Soak {
+    pub(crate) fn new(period: Duration) -> Self {
+        let mut ticks = interval(period);
+        // A report is a snapshot: one missed while the server was busy isn't owed.
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick is straight away, before there's anything to say.
+        ticks.reset();
+        Self { ticks, first_rss: None, reports: 0 }
+    }
+
+    /// Logs one report. `connection_tasks` is what the server's `JoinSet` holds and
+    /// `listed` what the connection list does.
+    pub(crate) fn report(&mut self, stats: &LiveStats, connection_tasks: usize, listed: usize) {
+        self.reports += 1;
+        let rss = rss_bytes();
+        if self.first_rss.is_none() {
+            self.first_rss = rss;
+        }
+        let rss_growth = rss.zip(self.first_rss).map(|(rss, first)| rss as i64 - first as i64);
+        info!(
+            report = self.reports,
+            uptime = ?stats.uptime(),
+            rss_kib = rss.map(|rss| rss / 1024),
+            rss_growth_kib = rss_growth.map(|growth| growth / 1024),
+            accepted = stats.accepted(),
+            open = stats.active(),
+            connection_tasks,
+            runtime_tasks = Handle::current().metrics().num_alive_tasks(),
+            listed,
+            "soak report"
+        );
+    }
+}
+
+/// Waits for the next report, or forever without a soak report, so a `select!` can
+/// always have the branch.
+pub(crate) async fn next_report(soak: &mut Option<Soak>) {
+    match soak {
+        Some(soak) => {
+            soak.ticks.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// This process's resident set, in bytes, where the platform says (Linux's
+/// `/proc/self/status`); `None` elsewhere.
+pub(crate) fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.trim_start_matches("VmRSS:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_rss_is_read_on_linux() {
+        let rss = rss_bytes().expect("Linux has /proc/self/status");
+        // Anything running the test suite is bigger than a page and smaller than a terabyte.
+        assert!((4096..1 << 40).contains(&rss), "{rss}");
+    }
+}
@@ -0,0 +1,89 @@
+//! Per-connection TCP options.
+//!
+//! Echo latency is mostly Nagle's algorithm meeting delayed ACKs: with `TCP_NODELAY`
+//! off, a small write can sit in the kernel for up to ~40 ms on Linux (up to 200 ms on
+//! Windows) waiting for the peer's ACK. Defaults differ per OS, which is why the same
+//! demo feels snappier on one laptop than another, so each connection logs what it got.
+
+use crate::config::ServerConfig;
+use socket2::{SockRef, TcpKeepalive};
+use std::io;
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// Applies the options from `config` to an accepted socket.
+///
+/// Options left unset in `config` keep whatever the OS (or the listener) gave us.
+pub(crate) fn configure(socket: &TcpStream, config: &ServerConfig) -> io::Result<()> {
+    if config.nodelay {
+        socket.set_nodelay(true)?;
+    }
+
+    // `TcpStream` only covers nodelay and TTL; for the rest we borrow the socket as a
+    // `socket2` socket. `SockRef` doesn't take ownership, so nothing is closed on drop.
+    let sock = SockRef::from(socket);
+    if let Some(idle) = config.keepalive {
+        let keepalive = TcpKeepalive::new().with_time(idle);
+        // Not every platform lets us pick the probe interval; elsewhere the OS default
+        // is used.
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd", windows))]
+        let keepalive = keepalive.with_interval(idle);
+        sock.set_tcp_keepalive(&keepalive)?;
+    }
+    if let Some(linger) = config.linger {
+        // `Some(0)` means "reset, don't FIN" on close: unsent data is thrown away.
+        sock.set_linger(Some(linger))?;
+    }
+    Ok(())
+}
+
+/// Reads the options back from the socket, as the OS actually set them.
+pub(crate) fn describe(socket: &TcpStream) -> io::Result<String> {
+    let sock = SockRef::from(socket);
+    Ok(format!(
+        "nodelay={} keepalive={} linger={:?} send_buffer={} recv_buffer={}",
+        sock.tcp_nodelay()?,
+        sock.keepalive()?,
+        sock.linger()?,
+        sock.send_buffer_size()?,
+        sock.recv_buffer_size()?,
+    ))
+}
+
+/// Configures `socket` and logs the result; a failure is logged but not fatal.
+pub(crate) fn configure_and_log(socket: &TcpStream, config: &ServerConfig) {
+    if let Err(e) = configure(socket, config) {
+        warn!(error = %e, "failed to set socket options");
+    }
+    match describe(socket) {
+        Ok(options) => info!("socket options: {options}"),
+        Err(e) => warn!(error = %e, "failed to read socket options"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_configure_applies_requested_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+
+        let config = ServerConfig {
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(30)),
+            linger: Some(Duration::from_secs(1)),
+            ..ServerConfig::default()
+        };
+        configure(&socket, &config).unwrap();
+
+        let options = describe(&socket).unwrap();
+        assert!(options.contains("nodelay=true"), "{options}");
+        assert!(options.contains("keepalive=true"), "{options}");
+        assert!(options.contains("linger=Some(1s)"), "{options}");
+    }
+}
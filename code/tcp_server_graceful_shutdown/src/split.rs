@@ -0,0 +1,228 @@
+//! The raw echo handler again, but with the socket split into a reader task and a
+//! writer task that talk over an `mpsc` channel.
+//!
+//! `tokio::io::split` hands out two owned halves, so each can move into its own task.
+//! (A plain `TcpStream` also has `into_split`, which skips the lock `io::split` puts
+//! between the halves, but the generic version works for TLS streams too.) That's the
+//! usual shape once reading and writing stop being lock-step (a chat server, say, where
+//! messages for a client arrive from elsewhere). The price is that shutdown now has to
+//! reach both halves:
+//!
+//! - the reader is the one listening for the shutdown signal. It queues the farewell and
+//!   then drops its `Sender`, which is how the writer learns that nothing else is coming;
+//! - the writer drains the queue, half-closes the socket and exits when `recv` returns
+//!   `None`. If a write fails or times out it exits early, dropping the `Receiver`, and
+//!   the reader notices via `Sender::closed` instead of reading into a channel nobody
+//!   empties.
+//!
+//! The queue is also where echoes can be lost at shutdown. A peer that has stopped
+//! reading leaves them stuck there, and draining them could take a `write_timeout` per
+//! chunk, well past the drain deadline, where the task would be aborted and nobody
+//! would know what was lost. So the writer listens for the signal too, and from then on
+//! has `shutdown_flush_timeout` to finish. What it hasn't written by then, queued or
+//! half sent (the farewell included), is thrown away and counted in the connection's
+//! stats.
+
+use crate::config::ServerConfig;
+use crate::connection::ConnInfo;
+use crate::error::{ConnectionError, Deadline};
+use crate::event_log::Event;
+use crate::tasks;
+use bytes::{Bytes, BytesMut};
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant, sleep, sleep_until, timeout_at};
+use tracing::{info, instrument, warn};
+
+/// How much we ask the kernel for per read.
+const READ_CHUNK: usize = 1024;
+
+/// Echoes everything the peer sends, using separate reader and writer tasks.
+#[instrument(skip_all)]
+pub(crate) async fn handle_split<S>(
+    socket: S,
+    shutdown_rx: broadcast::Receiver<()>,
+    config: &ServerConfig,
+    conn: &Arc<ConnInfo>,
+) -> Result<(), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(socket);
+    // Bounded, so a peer that doesn't read can't make us queue its echoes forever. Once
+    // it's full the reader stops reading, the kernel's receive buffer fills up, and the
+    // peer's own writes start to block: backpressure all the way back to the sender.
+    let (tx, rx) = mpsc::channel(config.outbound_queue.max(1));
+
+    // A `JoinSet` rather than two bare `tokio::spawn`s: if this task is aborted (say
+    // the drain timeout ran out), dropping the set aborts both halves with it.
+    let mut halves = JoinSet::new();
+    // Only sees signals from here on, but one that came earlier finds nothing queued
+    // yet: the reader sees it before its first read.
+    let writer_shutdown = shutdown_rx.resubscribe();
+    let read = read_half(reader, tx, shutdown_rx, config.idle_timeout, conn.clone());
+    let write = write_half(writer, rx, writer_shutdown, config.write_timeout, config.shutdown_flush_timeout, conn.clone());
+    tasks::spawn_in(&mut halves, &format!("{conn} reader"), read);
+    tasks::spawn_in(&mut halves, &format!("{conn} writer"), write);
+
+    let mut result = Ok(());
+    while let Some(joined) = halves.join_next().await {
+        let half_result = joined.map_err(ConnectionError::Task).and_then(|r| r);
+        if result.is_ok() {
+            result = half_result;
+        }
+    }
+    result
+}
+
+// Each half runs in a task of its own, and a task doesn't inherit the span of the
+// one that spawned it; these spans, created as the halves are, keep them in it.
+#[instrument(skip_all)]
+async fn read_half<S: AsyncRead>(
+    mut reader: ReadHalf<S>,
+    tx: mpsc::Sender<Bytes>,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    idle_timeout: Option<Duration>,
+    conn: Arc<ConnInfo>,
+) -> Result<(), ConnectionError> {
+    let mut buf = BytesMut::with_capacity(READ_CHUNK);
+    let idle = sleep(idle_timeout.unwrap_or_default());
+    tokio::pin!(idle);
+
+    loop {
+        tokio::select! {
+            recv = shutdown_rx.recv() => {
+                conn.record(|conn| Event::ShutdownSeen { conn });
+                if recv.is_ok() {
+                    // If the writer is already gone there's no one to say goodbye to.
+                    let _ = tx.send(Bytes::from_static(b"server shutting down\n")).await;
+                }
+                return Ok(());
+            }
+            () = &mut idle, if idle_timeout.is_some() => {
+                let _ = tx.send(Bytes::from_static(b"idle timeout, closing connection\n")).await;
+                return Ok(());
+            }
+            () = tx.closed() => return Ok(()),
+            read_result = reader.read_buf(&mut buf) => {
+                match read_result {
+                    Ok(0) => return Ok(()),
+                    Ok(n) => {
+                        conn.record_in(n);
+                        if let Some(idle_timeout) = idle_timeout {
+                            idle.as_mut().reset(Instant::now() + idle_timeout);
+                        }
+                        // Hand the bytes to the writer without copying: `split` carves off
+                        // what we've read as its own `BytesMut` sharing our allocation. Once
+                        // the writer drops every chunk, `reserve` can reuse that memory;
+                        // while chunks are still queued it has to allocate afresh.
+                        let chunk = buf.split().freeze();
+                        buf.reserve(READ_CHUNK);
+                        match tx.try_send(chunk) {
+                            Ok(()) => {}
+                            Err(mpsc::error::TrySendError::Full(chunk)) => {
+                                info!("outbound queue full, pausing reads until the peer catches up");
+                                // While we wait here nothing else in this `select!` runs; that
+                                // is fine, the writer's timeout bounds how long it can take.
+                                // An error means the writer gave up at the flush deadline.
+                                if let Err(mpsc::error::SendError(chunk)) = tx.send(chunk).await {
+                                    conn.record_dropped(chunk.len());
+                                    return Ok(());
+                                }
+                            }
+                            Err(mpsc::error::TrySendError::Closed(_)) => return Ok(()),
+                        }
+                    }
+                    Err(e) => return Err(ConnectionError::Read(e)),
+                }
+            }
+        }
+    }
+}
+
+#[instrument(skip_all)]
+async fn write_half<S: AsyncWrite>(
+    mut writer: WriteHalf<S>,
+    mut rx: mpsc::Receiver<Bytes>,
+    mut shutdown_rx: broadcast::Receiver<()>,
+    write_timeout: Duration,
+    flush_timeout: Duration,
+    conn: Arc<ConnInfo>,
+) -> Result<(), ConnectionError> {
+    // No deadline but each write's own until shutdown.
+    let mut flush_by = None;
+    loop {
+        let chunk = tokio::select! {
+            _ = shutdown_rx.recv(), if flush_by.is_none() => {
+                flush_by = Some(Instant::now() + flush_timeout);
+                continue;
+            }
+            () = sleep_until(flush_by.unwrap_or_else(Instant::now)), if flush_by.is_some() => {
+                drop_queued(&mut rx, &conn);
+                break;
+            }
+            chunk = rx.recv() => match chunk {
+                Some(chunk) => chunk,
+                None => break,
+            },
+        };
+        let write_by = Instant::now() + write_timeout;
+        let written = write_chunk(&mut writer, &chunk, write_by, &mut shutdown_rx, &mut flush_by, flush_timeout).await?;
+        conn.record_out(written);
+        if written < chunk.len() {
+            if flush_by.is_none_or(|flush_by| write_by < flush_by) {
+                return Err(ConnectionError::Timeout(Deadline::Write));
+            }
+            warn!("couldn't flush the queue by the shutdown deadline");
+            conn.record_dropped(chunk.len() - written);
+            drop_queued(&mut rx, &conn);
+            break;
+        }
+    }
+    // The reader is gone, whether because of EOF or shutdown, and the queue is flushed.
+    // Dropping the socket would close it too, but shutting down explicitly sends the
+    // FIN (and, for TLS, the close_notify alert) now and lets us see the error.
+    Ok(writer.shutdown().await?)
+}
+
+/// Writes as much of `chunk` as it can before `write_by`, or before the flush deadline
+/// once shutdown has set one, and says how much that was.
+///
+/// A write stuck on a peer that doesn't read is exactly when shutdown matters, so
+/// the signal is raced here too, one `write` at a time. `write`, unlike `write_all`,
+/// is cancellation safe: it has either written something or nothing.
+async fn write_chunk<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    chunk: &[u8],
+    write_by: Instant,
+    shutdown_rx: &mut broadcast::Receiver<()>,
+    flush_by: &mut Option<Instant>,
+    flush_timeout: Duration,
+) -> io::Result<usize> {
+    let mut written = 0;
+    while written < chunk.len() {
+        let deadline = flush_by.map_or(write_by, |flush_by| flush_by.min(write_by));
+        tokio::select! {
+            _ = shutdown_rx.recv(), if flush_by.is_none() => *flush_by = Some(Instant::now() + flush_timeout),
+            write = timeout_at(deadline, writer.write(&chunk[written..])) => match write {
+                Ok(Ok(0)) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(Ok(n)) => written += n,
+                Ok(Err(e)) => return Err(e),
+                Err(_) => break,
+            },
+        }
+    }
+    Ok(written)
+}
+
+/// Closes the queue and counts what was still in it. A reader waiting for room gets
+/// its chunk back and counts that one itself.
+fn drop_queued(rx: &mut mpsc::Receiver<Bytes>, conn: &ConnInfo) {
+    rx.close();
+    while let Ok(chunk) = rx.try_recv() {
+        conn.record_dropped(chunk.len());
+    }
+}
@@ -0,0 +1,278 @@
+//! Per-connection statistics, aggregated in one task.
+//!
+//! Every connection task sends a `ConnStats` when it finishes, and a single aggregator
+//! task owns the totals. Nothing is shared, so there's no lock to contend on. The
+//! aggregator also knows when it's done without being told: `recv` returns `None` once
+//! every `Sender` is gone, which happens exactly when the server and all of its
+//! connection tasks have let go of theirs.
+//!
+//! Each report is folded into the totals as it arrives and then forgotten, so a server
+//! that runs for days uses no more memory for its stats than one that runs for seconds.
+//! The duration percentiles come from a fixed-size random sample of the connections.
+
+use crate::{prometheus, tasks};
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// How many reports can queue up before a finishing connection waits for the aggregator.
+const CHANNEL_CAPACITY: usize = 256;
+/// How many connection durations the percentiles are taken from. Up to this many
+/// connections they're exact.
+const DURATION_SAMPLES: usize = 4096;
+
+/// What one connection did, reported when it closes.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConnStats {
+    pub(crate) bytes_read: u64,
+    pub(crate) bytes_written: u64,
+    /// Echoes still queued, or half written, when the shutdown flush deadline passed.
+    pub(crate) bytes_dropped: u64,
+    pub(crate) duration: Duration,
+}
+
+/// Totals over every connection that reported.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct StatsSummary {
+    pub(crate) connections: usize,
+    pub(crate) bytes_read: u64,
+    pub(crate) bytes_written: u64,
+    pub(crate) bytes_dropped: u64,
+    pub(crate) p50: Duration,
+    pub(crate) p99: Duration,
+}
+
+impl StatsSummary {
+    /// Prints the end-of-run summary.
+    pub(crate) fn log(&self) {
+        info!(
+            "served {} connection(s), {}B in, {}B out, {}B dropped at shutdown, duration p50={:?} p99={:?}",
+            self.connections, self.bytes_read, self.bytes_written, self.bytes_dropped, self.p50, self.p99
+        );
+    }
+}
+
+/// What the aggregator keeps between reports.
+#[derive(Debug, Default)]
+struct Totals {
+    connections: usize,
+    bytes_read: u64,
+    bytes_written: u64,
+    bytes_dropped: u64,
+    /// A uniform sample of every duration reported so far, at most `DURATION_SAMPLES`
+    /// of them ("reservoir sampling"): once it's full, the `n`th report replaces a
+    /// random one with a chance of `DURATION_SAMPLES / n`.
+    durations: Vec<Duration>,
+}
+
+impl Totals {
+    fn add(&mut self, stats: ConnStats) {
+        self.connections += 1;
+        self.bytes_read += stats.bytes_read;
+        self.bytes_written += stats.bytes_written;
+        self.bytes_dropped += stats.bytes_dropped;
+        if self.durations.len() < DURATION_SAMPLES {
+            self.durations.push(stats.duration);
+        } else {
+            let slot = rand::rng().random_range(0..self.connections);
+            if let Some(kept) = self.durations.get_mut(slot) {
+                *kept = stats.duration;
+            }
+        }
+    }
+
+    fn summary(mut self) -> StatsSummary {
+        self.durations.sort_unstable();
+        StatsSummary {
+            connections: self.connections,
+            bytes_read: self.bytes_read,
+            bytes_written: self.bytes_written,
+            bytes_dropped: self.bytes_dropped,
+            p50: percentile(&self.durations, 50),
+            p99: percentile(&self.durations, 99),
+        }
+    }
+}
+
+/// Running totals that can be read while the server is up, for the HTTP `/stats`
+/// endpoint. The aggregator above only produces its summary once everything has
+/// stopped; this is the other half of the trade, a few shared atomics that every
+/// connection bumps. Each bump is recorded for `/metrics` too.
+#[derive(Debug)]
+pub(crate) struct LiveStats {
+    started: Instant,
+    accepted: AtomicU64,
+    active: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    bytes_dropped: AtomicU64,
+    panics: AtomicU64,
+}
+
+impl LiveStats {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            accepted: AtomicU64::new(0),
+            active: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            bytes_dropped: AtomicU64::new(0),
+            panics: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a connection that has just been admitted.
+    pub(crate) fn opened(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_add(1, Ordering::Relaxed);
+        metrics::counter!(prometheus::ACCEPTED).increment(1);
+        metrics::gauge!(prometheus::ACTIVE).increment(1);
+    }
+
+    /// Counts a connection that has finished, adding its traffic to the totals.
+    pub(crate) fn closed(&self, stats: &ConnStats) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(stats.bytes_read, Ordering::Relaxed);
+        self.bytes_out.fetch_add(stats.bytes_written, Ordering::Relaxed);
+        self.bytes_dropped.fetch_add(stats.bytes_dropped, Ordering::Relaxed);
+        metrics::gauge!(prometheus::ACTIVE).decrement(1);
+        metrics::counter!(prometheus::BYTES_ECHOED).increment(stats.bytes_written);
+    }
+
+    /// Counts a connection whose task panicked, and so never got to say it closed.
+    /// Returns how many have panicked so far.
+    pub(crate) fn panicked(&self) -> u64 {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        metrics::gauge!(prometheus::ACTIVE).decrement(1);
+        metrics::counter!(prometheus::PANICS).increment(1);
+        self.panics.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// How long since the server started.
+    pub(crate) fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub(crate) fn accepted(&self) -> u64 {
+        self.accepted.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn active(&self) -> u64 {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "tui")]
+    pub(crate) fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// The totals as a JSON object. Traffic only counts connections that have closed.
+    pub(crate) fn to_json(&self) -> String {
+        format!(
+            r#"{{"uptime_secs":{:.3},"connections_accepted":{},"connections_active":{},"bytes_in":{},"bytes_out":{},"bytes_dropped_at_shutdown":{},"connection_panics":{}}}"#,
+            self.started.elapsed().as_secs_f64(),
+            self.accepted.load(Ordering::Relaxed),
+            self.active.load(Ordering::Relaxed),
+            self.bytes_in.load(Ordering::Relaxed),
+            self.bytes_out.load(Ordering::Relaxed),
+            self.bytes_dropped.load(Ordering::Relaxed),
+            self.panics.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Nearest-rank percentile of an already sorted slice.
+fn percentile(sorted: &[Duration], pct: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Starts the aggregator. Hand a clone of the sender to every connection, then drop the
+/// original and await the handle for the summary.
+pub(crate) fn spawn_aggregator() -> (mpsc::Sender<ConnStats>, JoinHandle<StatsSummary>) {
+    let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let task = tasks::spawn("stats aggregator", async move {
+        let mut totals = Totals::default();
+        while let Some(stats) = rx.recv().await {
+            totals.add(stats);
+        }
+        totals.summary()
+    });
+    (tx, task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn(ms: u64) -> ConnStats {
+        ConnStats {
+            bytes_read: 10,
+            bytes_written: 8,
+            bytes_dropped: 0,
+            duration: Duration::from_millis(ms),
+        }
+    }
+
+    #[test]
+    fn test_percentiles_use_nearest_rank() {
+        let durations: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&durations, 50), Duration::from_millis(50));
+        assert_eq!(percentile(&durations, 99), Duration::from_millis(99));
+        assert_eq!(percentile(&durations[..1], 99), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_totals_keep_a_bounded_sample_of_durations() {
+        let mut totals = Totals::default();
+        let reports = 3 * DURATION_SAMPLES as u64;
+        for ms in 1..=reports {
+            totals.add(conn(ms));
+        }
+        assert_eq!(totals.durations.len(), DURATION_SAMPLES);
+
+        let summary = totals.summary();
+        assert_eq!(summary.connections, reports as usize);
+        assert_eq!(summary.bytes_read, 10 * reports);
+        assert_eq!(summary.bytes_written, 8 * reports);
+        // A sample, so only roughly where the true percentiles are.
+        let p50 = summary.p50.as_millis() as u64;
+        assert!(p50.abs_diff(reports / 2) < reports / 10, "p50={p50}ms of {reports}");
+        assert!(summary.p99 >= summary.p50);
+    }
+
+    #[tokio::test]
+    async fn test_aggregator_finishes_when_all_senders_are_dropped() {
+        let (tx, task) = spawn_aggregator();
+        let mut reporters = Vec::new();
+        for ms in [30, 10, 20] {
+            let tx = tx.clone();
+            reporters.push(tokio::spawn(async move { tx.send(conn(ms)).await.unwrap() }));
+        }
+        drop(tx);
+        for reporter in reporters {
+            reporter.await.unwrap();
+        }
+
+        let summary = task.await.unwrap();
+        assert_eq!(
+            summary,
+            StatsSummary {
+                connections: 3,
+                bytes_read: 30,
+                bytes_written: 24,
+                bytes_dropped: 0,
+                p50: Duration::from_millis(20),
+                p99: Duration::from_millis(30),
+            }
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! A background job that writes the live stats to a file every few seconds, for
+//! anything outside the server that wants to follow along (`watch cat stats.json`).
+//!
+//! Each write goes to a temporary file that is then renamed over the real one, so a
+//! reader never sees half a snapshot. At shutdown the job is stopped only after the
+//! connections have gone, and writes one last snapshot on its way out: the file a
+//! stopped server leaves behind has the final totals, not whatever they were at the
+//! last tick.
+
+use crate::periodic::{Periodic, Run};
+use crate::stats::LiveStats;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Writes `stats` to `path` every `period`, and once more when stopped.
+pub(crate) fn spawn_flusher(path: PathBuf, period: Duration, stats: Arc<LiveStats>) -> Periodic {
+    Periodic::spawn("stats", period, move |run| {
+        let (path, stats) = (path.clone(), stats.clone());
+        async move {
+            match (run, write(&path, &stats).await) {
+                (Run::Tick, Ok(())) => {}
+                (Run::Tick, Err(e)) => warn!(file = %path.display(), error = %e, "couldn't write the stats"),
+                (Run::Final, Ok(())) => info!(file = %path.display(), "wrote the final stats"),
+                (Run::Final, Err(e)) => error!(file = %path.display(), error = %e, "couldn't write the final stats"),
+            }
+        }
+    })
+}
+
+async fn write(path: &Path, stats: &LiveStats) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    tokio::fs::write(&temp, format!("{}\n", stats.to_json())).await?;
+    tokio::fs::rename(&temp, path).await
+}
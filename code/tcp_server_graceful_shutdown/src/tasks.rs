@@ -0,0 +1,59 @@
+//! Spawning tasks with names, for tokio-console.
+//!
+//! The console lists every task with the place it was spawned, which for a server
+//! is the same few lines over and over. A name says which connection, which listener,
+//! which background job. Names are part of tokio's unstable API, and need its `tracing`
+//! feature too, so they only stick in a `console` build; in any other these are plain
+//! `spawn`s and the names go nowhere.
+//!
+//! The names are also where the server owns up to blocking. The config watcher and
+//! the certificate reloader check their files with plain `std::fs` calls, each a few
+//! microseconds on a local disk but a blocked worker thread on a slow one; their names
+//! say so, so the console's busy-poll warnings can be matched to them.
+
+use std::future::Future;
+use tokio::task::{JoinHandle, JoinSet};
+
+/// `tokio::spawn`, with a name.
+#[cfg(all(tokio_unstable, feature = "console"))]
+pub(crate) fn spawn<F>(name: &str, task: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::task::Builder::new()
+        .name(name)
+        .spawn(task)
+        .expect("spawning only fails outside a runtime, where `tokio::spawn` would panic too")
+}
+
+#[cfg(not(all(tokio_unstable, feature = "console")))]
+pub(crate) fn spawn<F>(_name: &str, task: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(task)
+}
+
+/// `JoinSet::spawn`, with a name.
+#[cfg(all(tokio_unstable, feature = "console"))]
+pub(crate) fn spawn_in<T, F>(set: &mut JoinSet<T>, name: &str, task: F)
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    set.build_task()
+        .name(name)
+        .spawn(task)
+        .expect("spawning only fails outside a runtime, where `JoinSet::spawn` would panic too");
+}
+
+#[cfg(not(all(tokio_unstable, feature = "console")))]
+pub(crate) fn spawn_in<T, F>(set: &mut JoinSet<T>, _name: &str, task: F)
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    set.spawn(task);
+}
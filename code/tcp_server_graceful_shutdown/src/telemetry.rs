@@ -0,0 +1,132 @@
+//! Where the spans go besides the terminal: with the `otel` feature, to an OpenTelemetry
+//! collector over OTLP/HTTP (Jaeger and Tempo both take it on port 4318). The endpoint
+//! and the rest come from the usual `OTEL_EXPORTER_OTLP_*` variables.
+//!
+//! Each connection the server accepts is one trace, rooted at its `conn` span. What
+//! ends up inside it depends on the span travelling with the work:
+//! * A spawned task doesn't inherit the span of the task that spawned it; it starts
+//!   with none, and its spans would be traces of their own. The connection tasks, the
+//!   split-mode reader and writer and the periodic jobs each get a span explicitly
+//!   (`.instrument`, or `#[instrument]` on the function they run), created while the
+//!   parent is current, so in the collector they hang under it.
+//! * A future raced in `select!` carries its span with it. Whichever branch wins, its
+//!   work is recorded where it belongs; a losing branch is dropped, and its span closes
+//!   there, as short as the time it spent waiting.
+//!
+//! The client's traces are separate from the server's. Nothing in the echo protocol
+//! carries a trace context across the connection, so match them up by the client's
+//! local address, which the server records as `peer`.
+//!
+//! What goes to the terminal is text by default. With [`LogFormat::Json`] it's one
+//! object per line instead, for `jq` or a test to pick apart:
+//!
+//! ```text
+//! {"timestamp":"…","level":"INFO","message":"closed","bytes_in":5,"bytes_out":5,
+//!  "dropped_at_shutdown":0,"duration":"1.2ms","target":"…",
+//!  "span":{"id":7,"peer":"127.0.0.1:50712","name":"conn"}}
+//! ```
+//!
+//! The event's own fields sit at the top level next to `message`, so a failure has
+//! its `error` there; the span it happened in, if any, is under `span`, and for a
+//! connection's events that's `conn`, with the connection's id. For instance
+//! `jq -c 'select(.span.name == "conn") | {conn: .span.id, message, bytes_out, error}'`.
+
+use tracing::Subscriber;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// How the log is written to the terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// A line of text per event, inside its spans.
+    #[default]
+    Text,
+    /// A JSON object per event.
+    Json,
+}
+
+/// Keeps the exporter running. Dropping it sends the spans still buffered, so hold it
+/// until the end of `main`.
+#[must_use = "dropping it stops the export"]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// The filter the terminal output uses, from `RUST_LOG`, `info` when it isn't set.
+/// Exported spans go through one of their own, built the same way.
+pub fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+/// The layer that writes the log to stdout in `format`, filtered by [`env_filter`].
+pub fn fmt_layer<S>(format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let layer = tracing_subscriber::fmt::layer();
+    match format {
+        LogFormat::Text => layer.with_filter(env_filter()).boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_filter(env_filter())
+            .boxed(),
+    }
+}
+
+/// The layer that exports spans as `service_name`, and the guard that keeps it going.
+#[cfg(feature = "otel")]
+pub fn layer<S>(service_name: &'static str) -> (impl Layer<S>, Telemetry)
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::SpanExporter;
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    // Logging isn't up yet: this runs while it's being set up.
+    let provider = match SpanExporter::builder().with_http().build() {
+        Ok(exporter) => Some(
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(service_name).build())
+                .build(),
+        ),
+        Err(e) => {
+            eprintln!("not exporting spans, couldn't set up the OTLP exporter: {e}");
+            None
+        }
+    };
+    let layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(service_name))
+            .with_filter(env_filter())
+    });
+    (layer, Telemetry { provider })
+}
+
+/// Without the `otel` feature there's nothing to export to.
+#[cfg(not(feature = "otel"))]
+pub fn layer<S>(service_name: &'static str) -> (impl Layer<S>, Telemetry)
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let _ = service_name;
+    (tracing_subscriber::layer::Identity::new(), Telemetry {})
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("couldn't flush the last spans: {e}");
+        }
+    }
+}
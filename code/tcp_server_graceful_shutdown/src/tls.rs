@@ -0,0 +1,214 @@
+//! TLS for the echo server, using a throwaway certificate authority.
+//!
+//! Real deployments load certificates from disk; for a demo we mint a CA at startup and
+//! use it to sign a server certificate (for `localhost`, `127.0.0.1` and `::1`) and a
+//! client certificate. The CA certificate is written out so the client can trust the
+//! server, and in mutual TLS mode the client identity is written out too, so the
+//! server can trust the client. rustls is set up with the `ring` provider explicitly,
+//! so there's no process-wide default to install first.
+//!
+//! A server can also load its certificate from disk instead; see `cert_reload` for how
+//! it picks up a rotated one.
+
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    KeyUsagePurpose,
+};
+use crate::config::CertFiles;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::crypto::{CryptoProvider, ring};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// A certificate together with its private key.
+#[derive(Clone)]
+pub struct Identity {
+    cert: CertificateDer<'static>,
+    cert_pem: String,
+    key_pem: String,
+}
+
+impl Identity {
+    /// The certificate.
+    pub fn cert(&self) -> &CertificateDer<'static> {
+        &self.cert
+    }
+
+    /// The certificate in PEM form, ready to write to a file.
+    pub fn cert_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /// Certificate and private key in one PEM file, the format [`load_identity`] reads.
+    pub fn to_pem_bundle(&self) -> String {
+        format!("{}{}", self.cert_pem, self.key_pem)
+    }
+
+    fn key(&self) -> io::Result<PrivateKeyDer<'static>> {
+        PrivateKeyDer::from_pem_slice(self.key_pem.as_bytes()).map_err(io::Error::other)
+    }
+}
+
+/// A demo certificate authority and the two certificates it signed.
+pub struct DemoPki {
+    ca: Identity,
+    server: Identity,
+    client: Identity,
+}
+
+impl DemoPki {
+    /// Generates a fresh CA, server certificate and client certificate.
+    pub fn generate() -> io::Result<Self> {
+        let mut ca_params = CertificateParams::new(Vec::new()).map_err(io::Error::other)?;
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "echo server demo CA");
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        let ca_key = KeyPair::generate().map_err(io::Error::other)?;
+        let ca_key_pem = ca_key.serialize_pem();
+        let ca = CertifiedIssuer::self_signed(ca_params, ca_key).map_err(io::Error::other)?;
+
+        let server_names = ["localhost", "127.0.0.1", "::1"].map(String::from).to_vec();
+        let server = signed_identity(server_names, "echo server", ExtendedKeyUsagePurpose::ServerAuth, &ca)?;
+        let client = signed_identity(Vec::new(), "echo client", ExtendedKeyUsagePurpose::ClientAuth, &ca)?;
+
+        Ok(Self {
+            ca: Identity {
+                cert: ca.der().clone(),
+                cert_pem: ca.pem(),
+                key_pem: ca_key_pem,
+            },
+            server,
+            client,
+        })
+    }
+
+    /// The CA certificate: what both sides trust.
+    pub fn ca(&self) -> &Identity {
+        &self.ca
+    }
+
+    /// The certificate the server presents.
+    pub fn server(&self) -> &Identity {
+        &self.server
+    }
+
+    /// A certificate a client can present in mutual TLS mode.
+    pub fn client(&self) -> &Identity {
+        &self.client
+    }
+}
+
+fn signed_identity(
+    names: Vec<String>,
+    common_name: &str,
+    usage: ExtendedKeyUsagePurpose,
+    ca: &Issuer<'_, KeyPair>,
+) -> io::Result<Identity> {
+    let mut params = CertificateParams::new(names).map_err(io::Error::other)?;
+    params.distinguished_name.push(DnType::CommonName, common_name);
+    params.extended_key_usages = vec![usage];
+    let key = KeyPair::generate().map_err(io::Error::other)?;
+    let cert = params.signed_by(&key, ca).map_err(io::Error::other)?;
+    Ok(Identity {
+        cert: cert.der().clone(),
+        cert_pem: cert.pem(),
+        key_pem: key.serialize_pem(),
+    })
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn roots(ca: &CertificateDer<'static>) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.clone()).map_err(io::Error::other)?;
+    Ok(roots)
+}
+
+/// Builds the server side of the handshake for the demo CA's certificate.
+///
+/// With `require_client_cert`, a client must present a certificate signed by the demo
+/// CA or the handshake fails. rustls does the checking; we only see the outcome, as the
+/// error from `accept` (e.g. "peer sent no certificates").
+pub(crate) fn server_config(pki: &DemoPki, require_client_cert: bool) -> io::Result<Arc<rustls::ServerConfig>> {
+    let builder = rustls::ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?;
+    let builder = if require_client_cert {
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots(pki.ca.cert())?), provider())
+            .build()
+            .map_err(io::Error::other)?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    let config = builder
+        .with_single_cert(vec![pki.server.cert.clone()], pki.server.key()?)
+        .map_err(io::Error::other)?;
+    Ok(Arc::new(config))
+}
+
+/// Builds the server side of the handshake from a certificate chain and key on disk.
+pub(crate) fn load_server_config(files: &CertFiles) -> io::Result<Arc<rustls::ServerConfig>> {
+    let chain = CertificateDer::pem_file_iter(&files.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid_pem(&files.cert, e))?;
+    if chain.is_empty() {
+        return Err(invalid_pem(&files.cert, "no certificates found"));
+    }
+    let key = PrivateKeyDer::from_pem_file(&files.key).map_err(|e| invalid_pem(&files.key, e))?;
+    let config = rustls::ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(io::Error::other)?;
+    Ok(Arc::new(config))
+}
+
+/// Builds a connector that trusts `ca` and, if given, presents `identity` when the
+/// server asks for a client certificate.
+pub fn connector(ca: &CertificateDer<'static>, identity: Option<&Identity>) -> io::Result<TlsConnector> {
+    let builder = rustls::ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots(ca)?);
+    let config = match identity {
+        Some(identity) => builder
+            .with_client_auth_cert(vec![identity.cert.clone()], identity.key()?)
+            .map_err(io::Error::other)?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Reads a PEM certificate, such as the CA the server writes with `--tls-ca-out`.
+pub fn load_cert(path: &Path) -> io::Result<CertificateDer<'static>> {
+    CertificateDer::from_pem_file(path).map_err(|e| invalid_pem(path, e))
+}
+
+/// Reads a certificate and key bundle, such as the one the server writes with
+/// `--tls-client-out`.
+pub fn load_identity(path: &Path) -> io::Result<Identity> {
+    let pem = std::fs::read_to_string(path)?;
+    let cert = CertificateDer::from_pem_slice(pem.as_bytes()).map_err(|e| invalid_pem(path, e))?;
+    // Check the key parses now rather than at connect time.
+    PrivateKeyDer::from_pem_slice(pem.as_bytes()).map_err(|e| invalid_pem(path, e))?;
+    Ok(Identity {
+        cert,
+        cert_pem: pem.clone(),
+        key_pem: pem,
+    })
+}
+
+fn invalid_pem(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", path.display()))
+}
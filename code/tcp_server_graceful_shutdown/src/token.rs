@@ -0,0 +1,158 @@
+//! The same echo server as `main.rs`, but shut down with a `CancellationToken` tree
+//! instead of a `broadcast` channel.
+//!
+//! The differences worth comparing:
+//! * Each connection gets a `child_token()`. Cancelling the root cancels every child,
+//!   but cancelling a child only affects that one connection.
+//! * A token is a *state*, not a message. A child created after the root was cancelled
+//!   is already cancelled, whereas a broadcast receiver created after `send` never sees
+//!   the signal. There's no `Lagged` or `Closed` case to handle either.
+//!
+//! This variant is kept deliberately small: it honours the listen addresses, the write
+//! and drain timeouts, the socket options, the accept backoff and the accept rate from
+//! `ServerConfig`, but not the connection limit, the idle timeout, framing, the PROXY
+//! protocol or TLS, and building a server that asks for any of them in this mode fails.
+
+use crate::accept::{Accepted, Acceptors};
+use crate::accept_rate::AcceptRate;
+use crate::config::{OverloadPolicy, ServerConfig};
+use crate::connection::ConnInfo;
+use crate::error::{ConnectionError, Deadline, ServerError};
+use crate::listener::{Connection, Listener};
+use crate::{panics, stats, tasks};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, error, info, instrument, warn};
+
+pub async fn run_server<L: Listener>(
+    listeners: Vec<L>,
+    root_token: CancellationToken,
+    config: ServerConfig,
+) -> Result<(), ServerError> {
+    let config = Arc::new(config);
+    let mut connections = JoinSet::new();
+    // No connection limit here, so the overload policy never comes into play.
+    let accept_rate = config.accept_rate.map(|rate| Arc::new(AcceptRate::new(rate, config.accept_burst)));
+    let mut acceptors = Acceptors::spawn(listeners, None, OverloadPolicy::Wait, accept_rate, config.max_accept_failures);
+    let mut result = Ok(());
+    let mut next_conn_id = 0_u64;
+    let (stats_tx, stats_task) = stats::spawn_aggregator();
+
+    loop {
+        tokio::select! {
+            _ = root_token.cancelled() => {
+                info!("root token cancelled");
+                break;
+            }
+            accepted = acceptors.next() => {
+                // Cancelling the root is what tells the open connections to finish.
+                let Accepted { socket, peer_addr, .. } = match accepted {
+                    Some(Ok(accepted)) => accepted,
+                    Some(Err(e)) => {
+                        result = Err(ServerError::Accept(e));
+                        root_token.cancel();
+                        break;
+                    }
+                    None => {
+                        root_token.cancel();
+                        break;
+                    }
+                };
+                next_conn_id += 1;
+                let conn = ConnInfo::new(next_conn_id, peer_addr);
+                let span = conn.span();
+                span.in_scope(|| {
+                    info!("accepted");
+                    socket.configure(&config);
+                });
+                let conn_token = root_token.child_token();
+                let config = config.clone();
+                let stats_tx = stats_tx.clone();
+                let name = conn.to_string();
+                tasks::spawn_in(&mut connections, &name, async move {
+                    let result = handle_connection(socket, conn_token, &config, &conn).await;
+                    conn.log_closed(&result);
+                    let _ = stats_tx.send(conn.stats()).await;
+                }.instrument(span));
+            }
+            // Without this the set keeps every finished connection until the drain,
+            // and a panic only gets logged then.
+            Some(joined) = connections.join_next(), if !connections.is_empty() => {
+                if let Err(e) = joined {
+                    error!("connection {}", panics::describe(e));
+                }
+            }
+        }
+    }
+    acceptors.shutdown().await;
+
+    info!(drain_timeout = ?config.drain_timeout, connections = connections.len(), "waiting for the connection tasks to finish");
+    let drained = timeout(config.drain_timeout, async {
+        while let Some(joined) = connections.join_next().await {
+            if let Err(e) = joined {
+                error!("connection {}", panics::describe(e));
+            }
+        }
+    })
+    .await;
+
+    if drained.is_ok() {
+        info!("all connection tasks finished");
+    } else {
+        let remaining = connections.len();
+        connections.abort_all();
+        while connections.join_next().await.is_some() {}
+        warn!(aborted = remaining, "drain deadline hit, force-closed the rest");
+    }
+
+    drop(stats_tx);
+    match stats_task.await {
+        Ok(summary) => summary.log(),
+        Err(e) => error!(error = %e, "stats aggregator failed"),
+    }
+
+    // Unlike a broadcast receiver created after `send`, a token created after
+    // `cancel` is born cancelled: late subscribers can't miss the signal.
+    if root_token.is_cancelled() {
+        let late = root_token.child_token();
+        info!(is_cancelled = late.is_cancelled(), "child token created after cancel");
+    }
+
+    result
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn handle_connection(
+    mut socket: impl AsyncRead + AsyncWrite + Unpin,
+    token: CancellationToken,
+    config: &ServerConfig,
+    conn: &ConnInfo,
+) -> Result<(), ConnectionError> {
+    let mut buf = [0_u8; 1024];
+
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => {
+                socket.write_all(b"server shutting down\n").await?;
+                return Ok(());
+            }
+            read_result = socket.read(&mut buf) => {
+                match read_result {
+                    Ok(0) => return Ok(()),
+                    Ok(n) => {
+                        conn.record_in(n);
+                        match timeout(config.write_timeout, socket.write_all(&buf[..n])).await {
+                            Ok(result) => result?,
+                            Err(_) => return Err(ConnectionError::Timeout(Deadline::Write)),
+                        }
+                        conn.record_out(n);
+                    }
+                    Err(e) => return Err(ConnectionError::Read(e)),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! The token variant again, with its connections tracked by a `TaskTracker` instead of
+//! a `JoinSet`.
+//!
+//! `tracker.close(); tracker.wait().await` reads like `while join_next().await`, but the
+//! two keep track of different things:
+//! * A `JoinSet` holds on to every task until it's joined: its output, or the
+//!   `JoinError` if it panicked. That's how the other variants can report a panicking
+//!   connection. It's also a slow leak in a server that only joins at shutdown, since
+//!   every finished connection waits in the set until then. A tracker only counts tasks
+//!   that are still running, and forgets one the moment it finishes. There's no output
+//!   to collect: whatever a connection has to report it reports itself, from inside its
+//!   task, and a panic goes to the panic hook and nowhere else.
+//! * A `JoinSet` can `abort_all`. A tracker can't abort anything, so force-closing at
+//!   the drain deadline needs a second token that every connection task races, and the
+//!   tasks have to cooperate by reaching an `.await`, just as with `abort`.
+//! * `wait` takes `&self` and a tracker is cheap to clone, so anyone can wait for the
+//!   drain (a health check, a metrics task), not only whoever owns the set. But it only
+//!   completes once the tracker is both closed and empty: forget `close` and it waits
+//!   forever, even with nothing running.
+//!
+//! Like the token variant, this one only honours the listen addresses, the write and
+//! drain timeouts, the socket options, the accept backoff and the accept rate.
+
+use crate::accept::{Accepted, Acceptors};
+use crate::accept_rate::AcceptRate;
+use crate::config::{OverloadPolicy, ServerConfig};
+use crate::connection::ConnInfo;
+use crate::error::{ConnectionError, ServerError};
+use crate::listener::{Connection, Listener};
+use crate::{stats, tasks, token};
+use std::sync::Arc;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{Instrument, error, info, warn};
+
+pub async fn run_server<L: Listener>(
+    listeners: Vec<L>,
+    root_token: CancellationToken,
+    config: ServerConfig,
+) -> Result<(), ServerError> {
+    let config = Arc::new(config);
+    let tracker = TaskTracker::new();
+    // What `abort_all` would do: cancelling it drops every connection at its next
+    // `.await`, farewell or not.
+    let abort = CancellationToken::new();
+    let accept_rate = config.accept_rate.map(|rate| Arc::new(AcceptRate::new(rate, config.accept_burst)));
+    let mut acceptors = Acceptors::spawn(listeners, None, OverloadPolicy::Wait, accept_rate, config.max_accept_failures);
+    let mut result = Ok(());
+    let mut next_conn_id = 0_u64;
+    let (stats_tx, stats_task) = stats::spawn_aggregator();
+
+    loop {
+        tokio::select! {
+            _ = root_token.cancelled() => {
+                info!("root token cancelled");
+                break;
+            }
+            accepted = acceptors.next() => {
+                // Cancelling the root is what tells the open connections to finish.
+                let Accepted { socket, peer_addr, .. } = match accepted {
+                    Some(Ok(accepted)) => accepted,
+                    Some(Err(e)) => {
+                        result = Err(ServerError::Accept(e));
+                        root_token.cancel();
+                        break;
+                    }
+                    None => {
+                        root_token.cancel();
+                        break;
+                    }
+                };
+                next_conn_id += 1;
+                let conn = ConnInfo::new(next_conn_id, peer_addr);
+                let span = conn.span();
+                span.in_scope(|| {
+                    info!("accepted");
+                    socket.configure(&config);
+                });
+                let conn_token = root_token.child_token();
+                let abort = abort.clone();
+                let config = config.clone();
+                let stats_tx = stats_tx.clone();
+                let name = conn.to_string();
+                tasks::spawn(&name, tracker.track_future(async move {
+                    let result = tokio::select! {
+                        () = abort.cancelled() => Err(ConnectionError::ShutdownInterrupted),
+                        result = token::handle_connection(socket, conn_token, &config, &conn) => result,
+                    };
+                    // Nobody will join this task, so this is the only report it makes.
+                    conn.log_closed(&result);
+                    let _ = stats_tx.send(conn.stats()).await;
+                }.instrument(span)));
+            }
+        }
+    }
+    acceptors.shutdown().await;
+    // From here on `wait` can finish: nothing more will be spawned.
+    tracker.close();
+
+    info!(drain_timeout = ?config.drain_timeout, connections = tracker.len(), "waiting for the connection tasks to finish");
+    if timeout(config.drain_timeout, tracker.wait()).await.is_ok() {
+        info!("all connection tasks finished");
+    } else {
+        let remaining = tracker.len();
+        abort.cancel();
+        tracker.wait().await;
+        warn!(aborted = remaining, "drain deadline hit, force-closed the rest");
+    }
+
+    drop(stats_tx);
+    match stats_task.await {
+        Ok(summary) => summary.log(),
+        Err(e) => error!(error = %e, "stats aggregator failed"),
+    }
+
+    result
+}
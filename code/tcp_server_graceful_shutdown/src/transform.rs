@@ -0,0 +1,98 @@
+//! Things the server can do to a message instead of echoing it faithfully.
+//!
+//! Uppercasing and reversing make it obvious which reply belongs to which request.
+//! Delaying and dropping turn the server into a misbehaving peer, which is what the
+//! client-side timeout and retry examples need to be tried against: `--transform delay`
+//! with a delay longer than the client's timeout, or `--transform drop` to see a retry
+//! loop at work.
+//!
+//! A delay is a plain `sleep` between reading a message and answering it, so it holds
+//! the connection's loop up: a shutdown signal that arrives meanwhile is only seen
+//! once the reply is out, like any other slow write.
+
+use crate::config::{ServerConfig, Transform};
+use rand::Rng;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// `config`'s transform, ready to apply.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Transformer {
+    transform: Transform,
+    delay: Duration,
+    drop_probability: f64,
+}
+
+impl Transformer {
+    pub(crate) fn new(config: &ServerConfig) -> Self {
+        Self {
+            transform: config.transform,
+            delay: config.transform_delay,
+            drop_probability: config.drop_probability.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Decides whether to answer at all, waiting first in `Transform::Delay` mode.
+    /// `false` means the reply is dropped.
+    pub(crate) async fn should_reply(&self) -> bool {
+        match self.transform {
+            Transform::Delay => {
+                sleep(self.delay).await;
+                true
+            }
+            Transform::Drop => !rand::rng().random_bool(self.drop_probability),
+            Transform::None | Transform::Uppercase | Transform::Reverse => true,
+        }
+    }
+
+    /// Rewrites a binary message in place. Uppercasing only touches ASCII letters, and
+    /// reversing is byte by byte, since there's no telling where characters start.
+    pub(crate) fn apply_bytes(&self, message: &mut [u8]) {
+        match self.transform {
+            Transform::Uppercase => message.make_ascii_uppercase(),
+            Transform::Reverse => message.reverse(),
+            Transform::None | Transform::Delay | Transform::Drop => {}
+        }
+    }
+
+    /// Rewrites a line of text, reversing it by character.
+    pub(crate) fn apply_line(&self, line: String) -> String {
+        match self.transform {
+            Transform::Uppercase => line.to_uppercase(),
+            Transform::Reverse => line.chars().rev().collect(),
+            Transform::None | Transform::Delay | Transform::Drop => line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transformer(transform: Transform) -> Transformer {
+        Transformer::new(&ServerConfig {
+            transform,
+            drop_probability: 1.0,
+            ..ServerConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_rewrites_bytes_and_lines() {
+        let mut bytes = *b"hello 42";
+        transformer(Transform::Uppercase).apply_bytes(&mut bytes);
+        assert_eq!(&bytes, b"HELLO 42");
+        transformer(Transform::Reverse).apply_bytes(&mut bytes);
+        assert_eq!(&bytes, b"24 OLLEH");
+
+        assert_eq!(transformer(Transform::Reverse).apply_line("añb".into()), "bña");
+        assert_eq!(transformer(Transform::Uppercase).apply_line("straße".into()), "STRASSE");
+        assert_eq!(transformer(Transform::Drop).apply_line("as is".into()), "as is");
+    }
+
+    #[tokio::test]
+    async fn test_drop_and_delay_decide_about_the_reply() {
+        assert!(!transformer(Transform::Drop).should_reply().await);
+        assert!(transformer(Transform::Reverse).should_reply().await);
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::{Server, ServerError};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+fn config_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("echo-{name}-{}.toml", std::process::id()))
+}
+
+#[tokio::test]
+async fn test_settings_come_from_the_file() {
+    let path = config_path("startup");
+    std::fs::write(&path, "bind = [\"127.0.0.1:0\"]\n").unwrap();
+
+    // The builder's default port is 3011; the file wins.
+    let server = Server::builder().config_file(&path).build().await.unwrap();
+    assert_ne!(server.local_addr().unwrap().port(), 3011);
+
+    let server = server.start();
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_a_bad_file_stops_the_build() {
+    let path = config_path("bad");
+    std::fs::write(&path, "idle_timeout_secs = \"soon\"\n").unwrap();
+
+    let error = Server::builder().config_file(&path).build().await.err().unwrap();
+    assert!(matches!(&error, ServerError::ConfigFile(e) if e.kind() == std::io::ErrorKind::InvalidData), "{error}");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_open_connections_pick_up_a_new_idle_timeout() {
+    let path = config_path("reload");
+    std::fs::write(&path, "bind = [\"127.0.0.1:0\"]\nidle_timeout_secs = 30\n").unwrap();
+    let server = Server::builder()
+        .config_file(&path)
+        .config_reload_interval(Duration::from_millis(50))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    let mut client = TcpStream::connect(server.local_addr()).await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0_u8; 4];
+    client.read_exact(&mut buf).await.unwrap();
+
+    // Connected under a 30s timeout; after the edit it's gone in well under a second.
+    std::fs::write(&path, "bind = [\"127.0.0.1:0\"]\nidle_timeout_secs = 0.2\n").unwrap();
+    let mut rest = Vec::new();
+    timeout(Duration::from_secs(3), client.read_to_end(&mut rest)).await.unwrap().unwrap();
+    assert_eq!(rest, b"idle timeout, closing connection\n");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,174 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::client::EchoClient;
+use tcp_server_graceful_shutdown::{ChaosConfig, Server, ServerError, ServerHandle, ShutdownMode};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::time::timeout;
+
+async fn start_server(drain_timeout: Duration) -> ServerHandle {
+    Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .control(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .drain_timeout(drain_timeout)
+        .build()
+        .await
+        .expect("bind ephemeral ports")
+        .start()
+}
+
+/// An operator's session on the control socket.
+struct Control {
+    lines: tokio::io::Lines<BufReader<tokio::net::tcp::OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Control {
+    async fn connect(server: &ServerHandle) -> Self {
+        let socket = TcpStream::connect(server.control_addr().unwrap()).await.unwrap();
+        let (reader, writer) = socket.into_split();
+        Self { lines: BufReader::new(reader).lines(), writer }
+    }
+
+    async fn send(&mut self, command: &str) {
+        self.writer.write_all(format!("{command}\n").as_bytes()).await.unwrap();
+    }
+
+    async fn line(&mut self) -> String {
+        timeout(Duration::from_secs(2), self.lines.next_line()).await.unwrap().unwrap().unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_stats_and_conns_describe_the_server() {
+    let server = start_server(Duration::from_secs(5)).await;
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+        assert_eq!(client.echo(b"hello").await.unwrap(), b"hello");
+        clients.push(client);
+    }
+
+    let mut control = Control::connect(&server).await;
+    control.send("STATS").await;
+    let stats = control.line().await;
+    assert!(stats.starts_with("OK {"), "{stats}");
+    assert!(stats.contains(r#""connections_active":2"#), "{stats}");
+
+    control.send("conns").await;
+    assert_eq!(control.line().await, "OK 2 connection(s)");
+    for _ in 0..2 {
+        let conn = control.line().await;
+        assert!(conn.starts_with("conn="), "{conn}");
+        assert!(conn.contains("in=5B out=5B"), "{conn}");
+    }
+
+    control.send("RESTART").await;
+    assert!(control.line().await.starts_with("ERR unknown command"));
+
+    server.shutdown();
+    // Sessions are told about the shutdown like any connection.
+    assert_eq!(control.line().await, "server shutting down");
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_drain_shuts_the_server_down_gracefully() {
+    let server = start_server(Duration::from_secs(5)).await;
+    let mut client = TcpStream::connect(server.local_addr()).await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0_u8; 4];
+    client.read_exact(&mut buf).await.unwrap();
+
+    let mut control = Control::connect(&server).await;
+    control.send("DRAIN 2").await;
+    assert_eq!(control.line().await, "OK draining for up to 2s");
+
+    // The connection gets the usual farewell, not a reset.
+    let mut farewell = Vec::new();
+    timeout(Duration::from_secs(2), client.read_to_end(&mut farewell)).await.unwrap().unwrap();
+    assert_eq!(farewell, b"server shutting down\n");
+    timeout(Duration::from_secs(2), server.await_terminated()).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_abort_skips_the_drain() {
+    // A drain deadline long enough that only ABORT can explain a quick exit.
+    let server = start_server(Duration::from_secs(30)).await;
+    let _idle = TcpStream::connect(server.local_addr()).await.unwrap();
+
+    let mut control = Control::connect(&server).await;
+    control.send("ABORT").await;
+    assert_eq!(control.line().await, "OK aborting");
+    timeout(Duration::from_secs(2), server.await_terminated()).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_finished_sees_a_shutdown_from_the_control_socket() {
+    let mut server = start_server(Duration::from_secs(5)).await;
+    let mut control = Control::connect(&server).await;
+    control.send("DRAIN 1").await;
+    assert_eq!(control.line().await, "OK draining for up to 1s");
+
+    // What `main` waits on besides the signals.
+    timeout(Duration::from_secs(2), server.finished()).await.unwrap();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_control_socket_must_be_local_and_broadcast() {
+    let public = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .control(SocketAddr::from(([0, 0, 0, 0], 0)))
+        .build()
+        .await;
+    let error = public.err().expect("a control socket on 0.0.0.0 is refused");
+    assert!(matches!(error, ServerError::InvalidConfig(_)), "{error}");
+
+    let token = Server::builder()
+        .mode(ShutdownMode::Token)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .control(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await;
+    assert!(matches!(token.err().unwrap(), ServerError::InvalidConfig(_)));
+}
+
+#[tokio::test]
+async fn test_chaos_can_be_switched_on_and_off() {
+    // Every read resets the connection, once chaos is on.
+    let chaos = ChaosConfig {
+        read_stall: 0.0,
+        partial_write: 0.0,
+        reset: 1.0,
+        delayed_accept: 0.0,
+        ..ChaosConfig::default()
+    };
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .control(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .chaos(chaos)
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let mut control = Control::connect(&server).await;
+    control.send("CHAOS").await;
+    assert!(control.line().await.starts_with("OK chaos off "));
+
+    control.send("CHAOS ON").await;
+    assert!(control.line().await.starts_with("OK chaos on "));
+    let mut client = TcpStream::connect(server.local_addr()).await.unwrap();
+    let mut buf = [0_u8; 16];
+    let reset = timeout(Duration::from_secs(2), client.read(&mut buf)).await.unwrap();
+    assert_eq!(reset.unwrap_err().kind(), std::io::ErrorKind::ConnectionReset);
+
+    control.send("chaos off").await;
+    assert!(control.line().await.starts_with("OK chaos off "));
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"calm").await.unwrap(), b"calm");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
@@ -0,0 +1,775 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::client::{EchoClient, ProxyHeaderOptions, connect_with_proxy_header, run_slow_client};
+use tcp_server_graceful_shutdown::listener::Listener;
+use tcp_server_graceful_shutdown::proxy::ProxyVersion;
+use tcp_server_graceful_shutdown::{Framing, OverloadPolicy, RateLimitPolicy, Server, ServerError, ServerHandle, ShutdownMode, Transform};
+use throttled_stream::{Throttle, ThrottledStream};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::time::timeout;
+
+async fn start_server(mode: ShutdownMode) -> ServerHandle {
+    Server::builder()
+        .mode(mode)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .expect("bind ephemeral port")
+        .start()
+}
+
+#[tokio::test]
+async fn test_echoes_a_message() {
+    let server = start_server(ShutdownMode::Broadcast).await;
+
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    let reply = client.echo(b"hello").await.unwrap();
+    assert_eq!(reply, b"hello");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_echoes_many_messages_on_one_connection() {
+    let server = start_server(ShutdownMode::Broadcast).await;
+
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    for i in 0..20 {
+        let msg = format!("message {i}");
+        let reply = client.echo(msg.as_bytes()).await.unwrap();
+        assert_eq!(reply, msg.as_bytes());
+    }
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_serves_concurrent_clients() {
+    let server = start_server(ShutdownMode::Broadcast).await;
+    let addr = server.local_addr();
+
+    let clients: Vec<_> = (0..10)
+        .map(|i| {
+            tokio::spawn(async move {
+                let mut client = EchoClient::connect(addr).await.unwrap();
+                let msg = format!("hello from client {i}");
+                let reply = client.echo(msg.as_bytes()).await.unwrap();
+                assert_eq!(reply, msg.as_bytes());
+            })
+        })
+        .collect();
+    for client in clients {
+        client.await.unwrap();
+    }
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+async fn assert_farewell_on_shutdown(mode: ShutdownMode) {
+    let server = start_server(mode).await;
+
+    // Keep the connection open across the shutdown.
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"ping").await.unwrap(), b"ping");
+
+    server.shutdown();
+
+    let mut socket = client.into_inner();
+    let mut farewell = String::new();
+    timeout(Duration::from_secs(2), socket.read_to_string(&mut farewell))
+        .await
+        .expect("server should close the connection")
+        .unwrap();
+    assert_eq!(farewell, "server shutting down\n");
+
+    timeout(Duration::from_secs(2), server.await_terminated())
+        .await
+        .expect("server should finish draining")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_farewell_delivered_on_shutdown_broadcast() {
+    assert_farewell_on_shutdown(ShutdownMode::Broadcast).await;
+}
+
+#[tokio::test]
+async fn test_farewell_delivered_on_shutdown_token() {
+    assert_farewell_on_shutdown(ShutdownMode::Token).await;
+}
+
+#[tokio::test]
+async fn test_farewell_delivered_on_shutdown_tracker() {
+    assert_farewell_on_shutdown(ShutdownMode::Tracker).await;
+}
+
+#[tokio::test]
+async fn test_farewell_delivered_on_shutdown_sentinel() {
+    assert_farewell_on_shutdown(ShutdownMode::Sentinel).await;
+}
+
+#[tokio::test]
+async fn test_token_modes_refuse_what_they_dont_implement() {
+    let builders = [
+        Server::builder().max_connections(10),
+        Server::builder().idle_timeout(Duration::from_secs(5)),
+        Server::builder().framing(Framing::Lines),
+        Server::builder().proxy_protocol(true),
+    ];
+    for builder in builders {
+        let server = builder
+            .mode(ShutdownMode::Tracker)
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .build()
+            .await;
+        assert!(matches!(server.err().unwrap(), ServerError::InvalidConfig(_)));
+    }
+}
+
+/// A TCP listener whose `accept` fails, every time, once `failing` is set.
+struct FailingListener {
+    inner: TcpListener,
+    failing: watch::Receiver<bool>,
+}
+
+impl Listener for FailingListener {
+    type Stream = TcpStream;
+
+    async fn accept(&mut self) -> io::Result<(TcpStream, SocketAddr)> {
+        tokio::select! {
+            accepted = self.inner.accept() => accepted,
+            _ = self.failing.wait_for(|&failing| failing) => Err(io::Error::other("injected accept failure")),
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+async fn assert_farewell_when_accept_gives_up(mode: ShutdownMode) {
+    let (fail, failing) = watch::channel(false);
+    let listener = FailingListener {
+        inner: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        failing,
+    };
+    let server = Server::builder()
+        .mode(mode)
+        .max_accept_failures(Some(3))
+        .drain_timeout(Duration::from_secs(30))
+        .build_with_listeners(vec![listener])
+        .await
+        .unwrap()
+        .start();
+
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"ping").await.unwrap(), b"ping");
+
+    fail.send_replace(true);
+
+    // Long before the drain deadline, which would cut it off without a word.
+    let mut socket = client.into_inner();
+    let mut farewell = String::new();
+    timeout(Duration::from_secs(2), socket.read_to_string(&mut farewell))
+        .await
+        .expect("the open connection should hear the shutdown")
+        .unwrap();
+    assert_eq!(farewell, "server shutting down\n");
+
+    let result = timeout(Duration::from_secs(2), server.await_terminated())
+        .await
+        .expect("server should finish draining");
+    assert!(matches!(result, Err(ServerError::Accept(_))), "{result:?}");
+}
+
+#[tokio::test]
+async fn test_farewell_when_accept_gives_up_broadcast() {
+    assert_farewell_when_accept_gives_up(ShutdownMode::Broadcast).await;
+}
+
+#[tokio::test]
+async fn test_farewell_when_accept_gives_up_token() {
+    assert_farewell_when_accept_gives_up(ShutdownMode::Token).await;
+}
+
+#[tokio::test]
+async fn test_farewell_when_accept_gives_up_tracker() {
+    assert_farewell_when_accept_gives_up(ShutdownMode::Tracker).await;
+}
+
+#[tokio::test]
+async fn test_farewell_when_accept_gives_up_sentinel() {
+    assert_farewell_when_accept_gives_up(ShutdownMode::Sentinel).await;
+}
+
+#[tokio::test]
+async fn test_stops_accepting_after_shutdown() {
+    let server = start_server(ShutdownMode::Broadcast).await;
+    let addr = server.local_addr();
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+
+    assert!(EchoClient::connect(addr).await.is_err());
+}
+
+async fn start_limited_server(when_full: OverloadPolicy) -> ServerHandle {
+    Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .max_connections(1)
+        .when_full(when_full)
+        .build()
+        .await
+        .unwrap()
+        .start()
+}
+
+#[tokio::test]
+async fn test_rejects_when_full() {
+    let server = start_limited_server(OverloadPolicy::Reject).await;
+
+    let mut first = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(first.echo(b"one").await.unwrap(), b"one");
+
+    let mut second = EchoClient::connect(server.local_addr()).await.unwrap().into_inner();
+    let mut reply = String::new();
+    timeout(Duration::from_secs(2), second.read_to_string(&mut reply))
+        .await
+        .expect("busy server should hang up")
+        .unwrap();
+    assert_eq!(reply, "server busy\n");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_waits_for_a_free_slot_when_full() {
+    let server = start_limited_server(OverloadPolicy::Wait).await;
+    let addr = server.local_addr();
+
+    let mut first = EchoClient::connect(addr).await.unwrap();
+    assert_eq!(first.echo(b"one").await.unwrap(), b"one");
+
+    // The second connection completes at the TCP level (it sits in the backlog),
+    // but isn't served until the first one goes away.
+    let second = tokio::spawn(async move {
+        let mut second = EchoClient::connect(addr).await.unwrap();
+        second.echo(b"two").await.unwrap()
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!second.is_finished());
+
+    drop(first);
+    let reply = timeout(Duration::from_secs(2), second).await.unwrap().unwrap();
+    assert_eq!(reply, b"two");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_idle_connection_is_closed() {
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .idle_timeout(Duration::from_millis(200))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    // Activity pushes the deadline back.
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(client.echo(b"still here").await.unwrap(), b"still here");
+    }
+
+    let mut socket = client.into_inner();
+    let mut reply = String::new();
+    timeout(Duration::from_secs(2), socket.read_to_string(&mut reply))
+        .await
+        .expect("idle connection should be closed")
+        .unwrap();
+    assert_eq!(reply, "idle timeout, closing connection\n");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_split_halves_echo_and_farewell() {
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .split_halves(true)
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    for i in 0..20 {
+        let msg = format!("message {i}");
+        assert_eq!(client.echo(msg.as_bytes()).await.unwrap(), msg.as_bytes());
+    }
+
+    server.shutdown();
+
+    let mut socket = client.into_inner();
+    let mut farewell = String::new();
+    timeout(Duration::from_secs(2), socket.read_to_string(&mut farewell))
+        .await
+        .expect("both halves should finish")
+        .unwrap();
+    assert_eq!(farewell, "server shutting down\n");
+
+    server.await_terminated().await.unwrap();
+}
+
+/// Sends `payload`, half-closes, and reads until the server closes its side too.
+async fn echo_after_half_close(server: &ServerHandle, payload: &[u8]) -> Vec<u8> {
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    socket.write_all(payload).await.unwrap();
+    // FIN: "I'm done sending", but we keep reading.
+    socket.shutdown().await.unwrap();
+
+    let mut reply = Vec::new();
+    timeout(Duration::from_secs(5), socket.read_to_end(&mut reply))
+        .await
+        .expect("server should close its side after echoing everything")
+        .unwrap();
+    reply
+}
+
+#[tokio::test]
+async fn test_half_close_still_gets_every_byte_back() {
+    // Much more than one read buffer, so echoing is still going on when the FIN arrives.
+    let payload: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+
+    for split_halves in [false, true] {
+        let server = Server::builder()
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .split_halves(split_halves)
+            .build()
+            .await
+            .unwrap()
+            .start();
+
+        let reply = echo_after_half_close(&server, &payload).await;
+        assert_eq!(reply.len(), payload.len(), "split_halves: {split_halves}");
+        assert!(reply == payload, "split_halves: {split_halves}");
+
+        server.shutdown();
+        server.await_terminated().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_write_timeout_drops_a_client_that_never_reads() {
+    for split_halves in [false, true] {
+        let server = Server::builder()
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .write_timeout(Duration::from_millis(200))
+            .split_halves(split_halves)
+            .outbound_queue(4)
+            .build()
+            .await
+            .unwrap()
+            .start();
+
+        let sent = timeout(
+            Duration::from_secs(10),
+            run_slow_client("slow", server.local_addr(), b"flood", None),
+        )
+        .await
+        .expect("server should drop a client that never reads")
+        .unwrap();
+        assert!(sent > 0, "split_halves: {split_halves}");
+
+        server.shutdown();
+        server.await_terminated().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_serves_every_listener_and_stops_them_all() {
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .also_bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .also_bind("[::1]:0".parse().unwrap())
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let addrs = server.local_addrs().to_vec();
+    assert_eq!(addrs.len(), 3);
+    assert!(addrs[2].is_ipv6());
+
+    let mut clients = Vec::new();
+    for addr in &addrs {
+        let mut client = EchoClient::connect(*addr).await.unwrap();
+        let msg = format!("hello via {addr}");
+        assert_eq!(client.echo(msg.as_bytes()).await.unwrap(), msg.as_bytes());
+        clients.push(client);
+    }
+
+    server.shutdown();
+    for client in clients {
+        let mut farewell = String::new();
+        timeout(Duration::from_secs(2), client.into_inner().read_to_string(&mut farewell))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(farewell, "server shutting down\n");
+    }
+    server.await_terminated().await.unwrap();
+
+    for addr in addrs {
+        assert!(TcpStream::connect(addr).await.is_err(), "{addr} still accepting");
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_protocol_header_is_consumed_before_echoing() {
+    let server = Server::builder()
+        .proxy_protocol(true)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    for version in [ProxyVersion::V1, ProxyVersion::V2] {
+        let options = ProxyHeaderOptions {
+            version,
+            source: Some(SocketAddr::from(([203, 0, 113, 9], 4242))),
+        };
+        let socket = connect_with_proxy_header(server.local_addr(), options).await.unwrap();
+        let mut client = EchoClient::new(socket);
+        assert_eq!(client.echo(b"behind a balancer").await.unwrap(), b"behind a balancer");
+    }
+
+    // Without a header the connection is dropped before anything is echoed.
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    socket.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+    let mut reply = Vec::new();
+    let read = timeout(Duration::from_secs(2), socket.read_to_end(&mut reply)).await.unwrap();
+    assert!(read.is_err() || reply.is_empty());
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_reuseport_lets_a_new_server_take_over_the_port() {
+    let old = Server::builder()
+        .reuseport(true)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let addr = old.local_addr();
+    // Connected before the new server exists, so it's certainly the old one's.
+    let mut old_client = EchoClient::connect(addr).await.unwrap();
+    assert_eq!(old_client.echo(b"old").await.unwrap(), b"old");
+
+    // Without SO_REUSEPORT the port is taken...
+    let taken = Server::builder().bind(addr).build().await.err().unwrap();
+    assert!(matches!(taken, ServerError::Bind { addr: a, .. } if a == addr), "{taken}");
+    // ...with it, both can listen at once.
+    let new = Server::builder().reuseport(true).bind(addr).build().await.unwrap().start();
+
+    old.shutdown();
+    let mut farewell = String::new();
+    timeout(Duration::from_secs(2), old_client.into_inner().read_to_string(&mut farewell))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(farewell, "server shutting down\n");
+    old.await_terminated().await.unwrap();
+
+    for _ in 0..5 {
+        let mut client = EchoClient::connect(addr).await.unwrap();
+        assert_eq!(client.echo(b"new").await.unwrap(), b"new");
+    }
+    new.shutdown();
+    new.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rate_limit_delay_slows_a_fast_sender() {
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .rate_limit_bytes(20_000)
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    // A second's worth goes through straight away, the rest at 20 kB/s.
+    let mut client = TcpStream::connect(server.local_addr()).await.unwrap();
+    let payload = vec![b'x'; 40_000];
+    let started = tokio::time::Instant::now();
+    let (mut reader, mut writer) = client.split();
+    let mut echoed = vec![0_u8; payload.len()];
+    let (written, read) = tokio::join!(writer.write_all(&payload), reader.read_exact(&mut echoed));
+    written.unwrap();
+    read.unwrap();
+    assert_eq!(echoed, payload);
+    assert!(started.elapsed() >= Duration::from_millis(700), "took {:?}", started.elapsed());
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rate_limit_disconnect_closes_a_fast_sender() {
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .rate_limit_messages(3)
+        .over_rate_limit(RateLimitPolicy::Disconnect)
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    // Three a second, and a tenth of one back every 100ms: the third or fourth message
+    // in quick succession empties the bucket and gets the reason instead of an echo.
+    let mut client = TcpStream::connect(server.local_addr()).await.unwrap();
+    let mut echoes = 0;
+    let mut reply = loop {
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0_u8; 64];
+        let n = timeout(Duration::from_secs(2), client.read(&mut buf)).await.unwrap().unwrap();
+        if &buf[..n] != b"ping" {
+            break buf[..n].to_vec();
+        }
+        echoes += 1;
+    };
+    timeout(Duration::from_secs(2), client.read_to_end(&mut reply)).await.unwrap().unwrap();
+    assert_eq!(reply, b"rate limit exceeded, closing connection\n");
+    assert!((2..=4).contains(&echoes), "{echoes} echoes");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_accept_rate_spaces_out_new_connections() {
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .accept_rate(10)
+        .accept_burst(2)
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    // Two get in straight away, then one every 100ms: the fourth waits about 200ms.
+    let started = tokio::time::Instant::now();
+    let clients: Vec<_> = (0..4)
+        .map(|_| {
+            let addr = server.local_addr();
+            tokio::spawn(async move {
+                let mut client = EchoClient::connect(addr).await.unwrap();
+                assert_eq!(client.echo(b"hi").await.unwrap(), b"hi");
+            })
+        })
+        .collect();
+    for client in clients {
+        client.await.unwrap();
+    }
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(150), "all four served in {elapsed:?}");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+async fn start_transform_server(transform: Transform) -> ServerHandle {
+    Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .transform(transform)
+        .transform_delay(Duration::from_millis(300))
+        .drop_probability(1.0)
+        .build()
+        .await
+        .unwrap()
+        .start()
+}
+
+#[tokio::test]
+async fn test_transform_uppercase_and_delay() {
+    let server = start_transform_server(Transform::Uppercase).await;
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"hello").await.unwrap(), b"HELLO");
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+
+    let server = start_transform_server(Transform::Delay).await;
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    let started = tokio::time::Instant::now();
+    assert_eq!(client.echo(b"slow").await.unwrap(), b"slow");
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_transform_drop_never_answers() {
+    let server = start_transform_server(Transform::Drop).await;
+    let mut client = TcpStream::connect(server.local_addr()).await.unwrap();
+    client.write_all(b"anyone there?").await.unwrap();
+
+    // Nothing comes back until the farewell.
+    let mut buf = [0_u8; 64];
+    assert!(timeout(Duration::from_millis(300), client.read(&mut buf)).await.is_err());
+    server.shutdown();
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).await.unwrap();
+    assert_eq!(rest, b"server shutting down\n");
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_echoes_over_a_throttled_connection() {
+    let server = start_server(ShutdownMode::Broadcast).await;
+    let throttle = Throttle {
+        latency: Duration::from_millis(100),
+        write_bytes_per_sec: Some(2000),
+        ..Throttle::default()
+    };
+    let socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    let mut client = EchoClient::new(ThrottledStream::new(socket, throttle));
+
+    let started = tokio::time::Instant::now();
+    for _ in 0..3 {
+        assert_eq!(client.echo(b"over a slow link").await.unwrap(), b"over a slow link");
+    }
+    // Every round trip pays the latency once.
+    assert!(started.elapsed() >= Duration::from_millis(300), "{:?}", started.elapsed());
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stats_file_gets_the_final_totals_after_the_connections() {
+    let path = std::env::temp_dir().join(format!("echo-stats-{}.json", std::process::id()));
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .stats_file(&path)
+        .stats_flush_interval(Duration::from_millis(50))
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"counted").await.unwrap(), b"counted");
+
+    // The periodic writes see the connection open...
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let live = std::fs::read_to_string(&path).unwrap();
+    assert!(live.contains(r#""connections_active":1"#), "{live}");
+
+    // ...and the last one, written after the drain, sees it closed and counted.
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+    let last = std::fs::read_to_string(&path).unwrap();
+    assert!(last.contains(r#""connections_active":0"#), "{last}");
+    assert!(last.contains(r#""bytes_in":7"#), "{last}");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_server_survives_panicking_connections_until_max_panics() {
+    let path = std::env::temp_dir().join(format!("echo-panics-{}.json", std::process::id()));
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .panic_injection(2)
+        .max_panics(2)
+        .stats_file(&path)
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    // Every second connection panics; the ones in between are served as usual.
+    let mut survivors = Vec::new();
+    for id in 1..=4 {
+        let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+        if id % 2 == 1 {
+            socket.write_all(b"still here").await.unwrap();
+            let mut reply = [0_u8; 10];
+            socket.read_exact(&mut reply).await.unwrap();
+            assert_eq!(&reply, b"still here");
+            survivors.push(socket);
+        } else {
+            let mut rest = Vec::new();
+            let n = timeout(Duration::from_secs(2), socket.read_to_end(&mut rest)).await.unwrap().unwrap_or(0);
+            assert_eq!(n, 0, "a panicked connection is just closed");
+        }
+    }
+
+    // The second panic shuts the server down without anyone asking.
+    timeout(Duration::from_secs(5), server.await_terminated()).await.unwrap().unwrap();
+    for mut socket in survivors {
+        let mut farewell = String::new();
+        socket.read_to_string(&mut farewell).await.unwrap();
+        assert_eq!(farewell, "server shutting down\n");
+    }
+    let last = std::fs::read_to_string(&path).unwrap();
+    assert!(last.contains(r#""connection_panics":2"#), "{last}");
+    assert!(last.contains(r#""connections_active":0"#), "{last}");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_split_halves_drop_what_they_cannot_flush_by_the_deadline() {
+    let path = std::env::temp_dir().join(format!("echo-dropped-{}.json", std::process::id()));
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .split_halves(true)
+        .outbound_queue(2)
+        .shutdown_flush_timeout(Duration::from_millis(100))
+        .stats_file(&path)
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    // Sends far more than the socket buffers hold and never reads a byte of the echo,
+    // so the writer is stuck with a full queue.
+    let socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    let (_read, mut write) = socket.into_split();
+    let flood = tokio::spawn(async move {
+        let _ = write.write_all(&vec![b'x'; 32 * 1024 * 1024]).await;
+        write
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // Well within the drain timeout, though each stuck chunk could take a write timeout.
+    let started = tokio::time::Instant::now();
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+    flood.abort();
+
+    let last = std::fs::read_to_string(&path).unwrap();
+    let dropped: u64 = last
+        .split(r#""bytes_dropped_at_shutdown":"#)
+        .nth(1)
+        .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_else(|| panic!("no dropped bytes in {last}"));
+    assert!(dropped > 0, "{last}");
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,68 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::client::EchoClient;
+use tcp_server_graceful_shutdown::event_log::{Event, EventLog};
+use tcp_server_graceful_shutdown::{Framing, Server};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn test_a_graceful_shutdown_happens_in_order() {
+    let events = EventLog::new();
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .event_log(events.clone())
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let mut client = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"hello").await.unwrap(), b"hello");
+
+    server.shutdown();
+    timeout(Duration::from_secs(2), server.await_terminated()).await.unwrap().unwrap();
+
+    let events: Vec<Event> = events.events().await.into_iter().map(|recorded| recorded.event).collect();
+    assert_eq!(
+        events,
+        [
+            Event::Accepted { conn: 1 },
+            Event::Read { conn: 1, bytes: 5 },
+            Event::Wrote { conn: 1, bytes: 5 },
+            Event::ShutdownSeen { conn: 1 },
+            Event::Closed { conn: 1 },
+            Event::Drained { aborted: 0 },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_a_connection_cut_off_by_the_drain_deadline_never_closes() {
+    let events = EventLog::new();
+    // GOAWAY leaves the connection waiting for a BYE long after the drain deadline.
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .framing(Framing::Lines)
+        .goaway(Duration::from_secs(30))
+        .drain_timeout(Duration::from_millis(200))
+        .event_log(events.clone())
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let client = TcpStream::connect(server.local_addr()).await.unwrap();
+    let accepted = timeout(Duration::from_secs(2), events.wait_for(|event| matches!(event, Event::Accepted { .. }))).await.unwrap();
+
+    server.shutdown();
+    let mut lines = BufReader::new(client).lines();
+    let goaway = timeout(Duration::from_secs(2), lines.next_line()).await.unwrap().unwrap().unwrap();
+    assert!(goaway.starts_with("GOAWAY "), "{goaway}");
+    timeout(Duration::from_secs(2), server.await_terminated()).await.unwrap().unwrap();
+
+    let recorded = events.events().await;
+    let events: Vec<Event> = recorded.iter().map(|recorded| recorded.event).collect();
+    assert_eq!(events, [Event::Accepted { conn: 1 }, Event::ShutdownSeen { conn: 1 }, Event::Drained { aborted: 1 }]);
+    // The drain took the whole deadline.
+    assert!(recorded[2].at - accepted.at >= Duration::from_millis(200));
+}
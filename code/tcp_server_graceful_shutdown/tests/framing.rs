@@ -0,0 +1,276 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::client::{EchoClient, FramedEchoClient, LineEchoClient};
+use tcp_server_graceful_shutdown::{Framing, Server, ServerHandle, Transform};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+async fn start_server(framing: Framing) -> ServerHandle {
+    Server::builder()
+        .framing(framing)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start()
+}
+
+#[tokio::test]
+async fn test_length_framing_echoes_whole_frames() {
+    let server = start_server(Framing::Length).await;
+
+    let mut client = FramedEchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"hello").await.unwrap(), b"hello");
+    let big = vec![b'x'; 100_000];
+    assert_eq!(client.echo(&big).await.unwrap(), big);
+
+    server.shutdown();
+    let farewell = timeout(Duration::from_secs(2), client.next_message())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(farewell, b"server shutting down");
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_length_framing_reassembles_split_writes() {
+    let server = start_server(Framing::Length).await;
+
+    // Dribble one frame out a byte at a time; the server must still echo it whole.
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    let payload = b"split";
+    let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(payload);
+    for byte in frame.iter() {
+        socket.write_all(&[*byte]).await.unwrap();
+        socket.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let mut echoed = vec![0_u8; frame.len()];
+    socket.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(echoed, frame);
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_length_framing_rejects_oversized_messages() {
+    let server = Server::builder()
+        .framing(Framing::Length)
+        .max_message_size(16)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    let mut client = FramedEchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo(b"small enough").await.unwrap(), b"small enough");
+    let reply = client.echo(&[b'x'; 32]).await.unwrap();
+    assert_eq!(reply, b"error: message larger than 16 bytes, closing connection");
+    assert!(client.next_message().await.is_none());
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_lines_framing_prefixes_each_line() {
+    let server = start_server(Framing::Lines).await;
+
+    let mut client = LineEchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo("hello").await.unwrap(), "echo: hello");
+    assert_eq!(client.echo("second line").await.unwrap(), "echo: second line");
+
+    server.shutdown();
+    let farewell = client.next_line().await.unwrap().unwrap();
+    assert_eq!(farewell, "server shutting down");
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_lines_framing_handles_telnet_line_endings_and_batches() {
+    let server = start_server(Framing::Lines).await;
+
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    socket.write_all(b"one\r\ntwo\nthree\n").await.unwrap();
+
+    let expected = b"echo: one\necho: two\necho: three\n";
+    let mut reply = vec![0_u8; expected.len()];
+    socket.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, expected);
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+async fn reply_then_close(server: &ServerHandle, input: &[u8]) -> String {
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    socket.write_all(input).await.unwrap();
+    let mut reply = String::new();
+    timeout(Duration::from_secs(2), socket.read_to_string(&mut reply))
+        .await
+        .expect("server should hang up")
+        .unwrap();
+    reply
+}
+
+#[tokio::test]
+async fn test_lines_framing_rejects_overlong_lines() {
+    let server = Server::builder()
+        .framing(Framing::Lines)
+        .max_line_length(16)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    let reply = reply_then_close(&server, b"ok\nthis line is far too long for the limit\n").await;
+    assert_eq!(reply, "echo: ok\nerror: line longer than 16 bytes, closing connection\n");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_lines_framing_rejects_invalid_utf8() {
+    let server = start_server(Framing::Lines).await;
+
+    let reply = reply_then_close(&server, b"ok\n\xff\xfe\n").await;
+    assert_eq!(reply, "echo: ok\nerror: invalid UTF-8, closing connection\n");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+async fn start_goaway_server(deadline: Duration) -> ServerHandle {
+    Server::builder()
+        .framing(Framing::Lines)
+        .goaway(deadline)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start()
+}
+
+#[tokio::test]
+async fn test_goaway_waits_for_in_flight_work_and_bye() {
+    let server = start_goaway_server(Duration::from_secs(3)).await;
+
+    let mut client = LineEchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo("before").await.unwrap(), "echo: before");
+
+    server.shutdown();
+    // Give the GOAWAY time to be sent, so it's already waiting ahead of the echo.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // Sent after the shutdown signal, but before we've read the notice: still answered.
+    assert_eq!(client.echo("in flight").await.unwrap(), "echo: in flight");
+    assert_eq!(client.goaway(), Some(Duration::from_secs(3)));
+    timeout(Duration::from_secs(1), client.bye()).await.unwrap().unwrap();
+
+    // The BYE ends the drain long before the three-second deadline.
+    timeout(Duration::from_secs(1), server.await_terminated())
+        .await
+        .expect("server should close as soon as the client says BYE")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_goaway_closes_at_the_deadline_without_bye() {
+    let server = start_goaway_server(Duration::from_millis(200)).await;
+
+    let mut client = LineEchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo("hello").await.unwrap(), "echo: hello");
+
+    server.shutdown();
+    assert_eq!(client.next_line().await.unwrap().unwrap(), "GOAWAY 200");
+    let closed = timeout(Duration::from_secs(2), client.next_line()).await.unwrap();
+    assert!(closed.is_none());
+    server.await_terminated().await.unwrap();
+}
+
+async fn start_heartbeat_server(period: Duration, misses: u32) -> ServerHandle {
+    Server::builder()
+        .framing(Framing::Lines)
+        .heartbeat(period)
+        .heartbeat_misses(misses)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start()
+}
+
+#[tokio::test]
+async fn test_heartbeat_keeps_a_client_that_answers() {
+    let server = start_heartbeat_server(Duration::from_millis(50), 2).await;
+
+    let mut client = LineEchoClient::connect(server.local_addr()).await.unwrap();
+    // Long enough for several pings, all answered by `wait`.
+    assert_eq!(client.wait(Duration::from_millis(400)).await.unwrap(), None);
+    assert_eq!(client.echo("still here").await.unwrap(), "echo: still here");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_heartbeat_drops_a_client_that_stops_answering() {
+    let server = start_heartbeat_server(Duration::from_millis(50), 2).await;
+
+    let mut client = LineEchoClient::connect(server.local_addr()).await.unwrap();
+    // Read the pings without answering: two go unanswered, then the third tick hangs up.
+    let mut lines = Vec::new();
+    while let Some(line) = timeout(Duration::from_secs(2), client.next_line()).await.unwrap() {
+        lines.push(line.unwrap());
+    }
+    assert_eq!(lines, ["PING", "PING", "heartbeat timeout, closing connection"]);
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_lines_transform_reverses_by_character() {
+    let server = Server::builder()
+        .framing(Framing::Lines)
+        .transform(Transform::Reverse)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    let mut client = LineEchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(client.echo("héllo").await.unwrap(), "echo: olléh");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_auto_framing_tells_the_protocols_apart() {
+    let server = start_server(Framing::Auto).await;
+
+    let mut lines = LineEchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(lines.echo("hello").await.unwrap(), "echo: hello");
+
+    let mut raw = EchoClient::connect(server.local_addr()).await.unwrap();
+    assert_eq!(raw.echo(b"no newline").await.unwrap(), b"no newline");
+
+    let mut http = TcpStream::connect(server.local_addr()).await.unwrap();
+    http.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    timeout(Duration::from_secs(2), http.read_to_string(&mut response)).await.unwrap().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
@@ -0,0 +1,207 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::{Framing, Server, ServerHandle};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+async fn start_server() -> ServerHandle {
+    Server::builder()
+        .framing(Framing::Http)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start()
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    head: String,
+    body: String,
+}
+
+/// Reads exactly one response off `socket`, leaving anything after it unread.
+async fn read_response(socket: &mut TcpStream) -> Response {
+    let mut raw = Vec::new();
+    let mut byte = [0_u8; 1];
+    while !raw.ends_with(b"\r\n\r\n") {
+        let n = timeout(Duration::from_secs(2), socket.read(&mut byte)).await.unwrap().unwrap();
+        assert_eq!(n, 1, "connection closed mid-response: {:?}", String::from_utf8_lossy(&raw));
+        raw.push(byte[0]);
+    }
+    let head = String::from_utf8(raw).unwrap();
+    let status = head[9..12].parse().unwrap();
+    let length: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut body = vec![0_u8; length];
+    timeout(Duration::from_secs(2), socket.read_exact(&mut body)).await.unwrap().unwrap();
+    Response {
+        status,
+        head,
+        body: String::from_utf8(body).unwrap(),
+    }
+}
+
+async fn assert_closed(socket: &mut TcpStream) {
+    let mut rest = Vec::new();
+    timeout(Duration::from_secs(2), socket.read_to_end(&mut rest)).await.unwrap().unwrap();
+    assert!(rest.is_empty(), "expected EOF, got {:?}", String::from_utf8_lossy(&rest));
+}
+
+#[tokio::test]
+async fn test_health_and_stats_over_one_keep_alive_connection() {
+    let server = start_server().await;
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+
+    socket.write_all(b"GET /health HTTP/1.1\r\nHost: test\r\n\r\n").await.unwrap();
+    let health = read_response(&mut socket).await;
+    assert_eq!((health.status, health.body.as_str()), (200, "ok\n"));
+    assert!(health.head.contains("Connection: keep-alive"));
+
+    // The request head arrives in pieces; nothing is answered until it's complete.
+    for piece in ["GET /stats HT", "TP/1.1\r\nHost: te", "st\r\n", "\r\n"] {
+        socket.write_all(piece.as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let stats = read_response(&mut socket).await;
+    assert_eq!(stats.status, 200);
+    assert!(stats.head.contains("Content-Type: application/json"));
+    assert!(stats.body.contains(r#""connections_accepted":1"#), "{}", stats.body);
+    assert!(stats.body.contains(r#""connections_active":1"#), "{}", stats.body);
+
+    server.shutdown();
+    assert_closed(&mut socket).await;
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_pipelined_requests_and_connection_close() {
+    let server = start_server().await;
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+
+    socket
+        .write_all(
+            b"POST /health HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+              GET /nowhere?x=1 HTTP/1.1\r\n\r\n\
+              GET /health HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let not_allowed = read_response(&mut socket).await;
+    assert_eq!(not_allowed.status, 405);
+    assert!(not_allowed.head.contains("Allow: GET, HEAD"));
+    assert_eq!(read_response(&mut socket).await.status, 404);
+    let last = read_response(&mut socket).await;
+    assert_eq!(last.status, 200);
+    assert!(last.head.contains("Connection: close"));
+    assert_closed(&mut socket).await;
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_bad_request_gets_an_error_and_a_hangup() {
+    let server = start_server().await;
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+
+    socket.write_all(b"HELLO\r\n\r\n").await.unwrap();
+    let response = read_response(&mut socket).await;
+    assert_eq!(response.status, 400);
+    assert!(response.head.contains("Connection: close"));
+    assert_closed(&mut socket).await;
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_post_shutdown_stops_the_server() {
+    let server = start_server().await;
+    let mut idle = TcpStream::connect(server.local_addr()).await.unwrap();
+    idle.write_all(b"GET /health HTTP/1.1\r\n\r\n").await.unwrap();
+    assert_eq!(read_response(&mut idle).await.status, 200);
+
+    let mut admin = TcpStream::connect(server.local_addr()).await.unwrap();
+    admin.write_all(b"GET /shutdown HTTP/1.1\r\n\r\n").await.unwrap();
+    assert_eq!(read_response(&mut admin).await.status, 405);
+    admin.write_all(b"POST /shutdown HTTP/1.1\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+    let accepted = read_response(&mut admin).await;
+    assert_eq!(accepted.status, 202);
+    assert!(accepted.head.contains("Connection: close"));
+    assert_closed(&mut admin).await;
+
+    // The idle keep-alive connection is closed as part of the same shutdown.
+    assert_closed(&mut idle).await;
+    timeout(Duration::from_secs(2), server.await_terminated())
+        .await
+        .expect("POST /shutdown should stop the server")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_metrics_in_the_prometheus_text_format() {
+    let server = start_server().await;
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+
+    socket.write_all(b"GET /metrics HTTP/1.1\r\nHost: test\r\n\r\n").await.unwrap();
+    let metrics = read_response(&mut socket).await;
+    assert_eq!(metrics.status, 200);
+    assert!(metrics.head.contains("Content-Type: text/plain; version=0.0.4"), "{}", metrics.head);
+    // Every server in this test binary shares the recorder, so only the names are
+    // certain, not the numbers.
+    assert!(metrics.body.contains("# TYPE echo_connections_active gauge"), "{}", metrics.body);
+    assert!(metrics.body.contains("# TYPE echo_connections_accepted_total counter"), "{}", metrics.body);
+
+    server.shutdown();
+    assert_closed(&mut socket).await;
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_metrics_listener_next_to_an_echo_server() {
+    let server = Server::builder()
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .metrics(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let metrics_addr = server.metrics_addr().unwrap();
+
+    let mut echo = TcpStream::connect(server.local_addr()).await.unwrap();
+    echo.write_all(b"hello").await.unwrap();
+    let mut reply = [0_u8; 5];
+    timeout(Duration::from_secs(2), echo.read_exact(&mut reply)).await.unwrap().unwrap();
+    drop(echo);
+
+    // Bytes are counted as the connection closes, which the server may not have noticed yet.
+    let mut metrics = String::new();
+    for _ in 0..50 {
+        let mut socket = TcpStream::connect(metrics_addr).await.unwrap();
+        socket.write_all(b"GET /metrics HTTP/1.1\r\nHost: test\r\n\r\n").await.unwrap();
+        let response = read_response(&mut socket).await;
+        assert_eq!(response.status, 200);
+        assert!(response.head.contains("Connection: close"), "{}", response.head);
+        assert_closed(&mut socket).await;
+        metrics = response.body;
+        if metrics.contains("echo_bytes_echoed_total") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(metrics.contains("echo_bytes_echoed_total"), "{metrics}");
+
+    let mut socket = TcpStream::connect(metrics_addr).await.unwrap();
+    socket.write_all(b"GET /stats HTTP/1.1\r\nHost: test\r\n\r\n").await.unwrap();
+    assert_eq!(read_response(&mut socket).await.status, 404);
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
@@ -0,0 +1,76 @@
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::process::Stdio;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::client::EchoClient;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{ChildStdout, Command};
+use tokio::time::timeout;
+
+/// The next event the server logged that `matches`, skipping the others.
+async fn next_event(lines: &mut Lines<BufReader<ChildStdout>>, matches: impl Fn(&Value) -> bool) -> Value {
+    timeout(Duration::from_secs(10), async {
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let event: Value = serde_json::from_str(&line).unwrap_or_else(|e| panic!("not JSON ({e}): {line}"));
+            if matches(&event) {
+                return event;
+            }
+        }
+        panic!("the server exited first");
+    })
+    .await
+    .expect("the event was logged")
+}
+
+#[tokio::test]
+async fn test_json_log_has_one_object_per_event() {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_tcp_server_graceful_shutdown"))
+        .args(["--log-format", "json", "--bind", "127.0.0.1:0"])
+        .env("RUST_LOG", "info")
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(server.stdout.take().unwrap()).lines();
+
+    let listening = next_event(&mut lines, |event| event["message"].as_str().is_some_and(|m| m.starts_with("listening on"))).await;
+    assert!(listening["timestamp"].is_string(), "{listening}");
+    let addr: SocketAddr = listening["message"].as_str().unwrap().split(' ').nth(2).unwrap().parse().unwrap();
+
+    let mut client = EchoClient::connect(addr).await.unwrap();
+    assert_eq!(client.echo(b"hello").await.unwrap(), b"hello");
+    drop(client);
+
+    // The connection's events carry its span, and with it the connection id.
+    let closed = next_event(&mut lines, |event| event["message"] == "closed").await;
+    assert_eq!(closed["span"]["name"], "conn", "{closed}");
+    assert_eq!(closed["span"]["id"], 1, "{closed}");
+    assert_eq!((closed["bytes_in"].as_u64(), closed["bytes_out"].as_u64()), (Some(5), Some(5)), "{closed}");
+}
+
+#[tokio::test]
+async fn test_soak_report_counts_tasks_and_memory() {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_tcp_server_graceful_shutdown"))
+        .args(["--log-format", "json", "--bind", "127.0.0.1:0", "--soak-report", "0.2"])
+        .env("RUST_LOG", "info")
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(server.stdout.take().unwrap()).lines();
+    let listening = next_event(&mut lines, |event| event["message"].as_str().is_some_and(|m| m.starts_with("listening on"))).await;
+    let addr: SocketAddr = listening["message"].as_str().unwrap().split(' ').nth(2).unwrap().parse().unwrap();
+
+    let mut client = EchoClient::connect(addr).await.unwrap();
+    assert_eq!(client.echo(b"hello").await.unwrap(), b"hello");
+    let open = next_event(&mut lines, |event| event["message"] == "soak report" && event["open"] == 1).await;
+    assert_eq!(open["connection_tasks"], 1, "{open}");
+    if cfg!(target_os = "linux") {
+        assert!(open["rss_kib"].as_u64().is_some_and(|rss| rss > 0), "{open}");
+    }
+    drop(client);
+
+    // Once the connection is gone, its task is too.
+    let closed = next_event(&mut lines, |event| event["message"] == "soak report" && event["open"] == 0 && event["accepted"] == 1).await;
+    assert_eq!(closed["connection_tasks"], 0, "{closed}");
+}
@@ -0,0 +1,43 @@
+#![cfg(windows)]
+
+use shutdown_util::ShutdownController;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::ServerConfig;
+use tcp_server_graceful_shutdown::pipe::run_pipe_server;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::windows::named_pipe::ClientOptions;
+use tokio::time::{sleep, timeout};
+
+#[tokio::test]
+async fn test_pipe_echoes_and_says_goodbye() {
+    let name = format!(r"\\.\pipe\graceful-shutdown-test-{}", std::process::id());
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let server = {
+        let name = name.clone();
+        tokio::spawn(async move { run_pipe_server(&name, controller, shutdown_rx, ServerConfig::default()).await })
+    };
+
+    // The pipe only exists once the server task has created its first instance.
+    let mut client = loop {
+        match ClientOptions::new().open(&name) {
+            Ok(client) => break client,
+            Err(_) => sleep(Duration::from_millis(10)).await,
+        }
+    };
+
+    client.write_all(b"ping").await.unwrap();
+    let mut reply = [0_u8; 4];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply, b"ping");
+
+    trigger.trigger();
+    let mut farewell = String::new();
+    timeout(Duration::from_secs(2), client.read_to_string(&mut farewell))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(farewell, "server shutting down\n");
+    server.await.unwrap().unwrap();
+}
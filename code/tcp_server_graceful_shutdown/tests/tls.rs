@@ -0,0 +1,178 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tcp_server_graceful_shutdown::tls::DemoPki;
+use tcp_server_graceful_shutdown::{CertFiles, Server, ServerError, ShutdownMode};
+use tcp_server_graceful_shutdown::client::EchoClient;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn test_tls_echo_and_farewell() {
+    let server = Server::builder()
+        .tls(true)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap();
+    let ca = server.tls_pki().unwrap().ca().cert().clone();
+    let server = server.start();
+
+    let mut client = EchoClient::connect_tls(server.local_addr(), &ca, None).await.unwrap();
+    assert_eq!(client.echo(b"secret").await.unwrap(), b"secret");
+
+    server.shutdown();
+    let mut farewell = String::new();
+    timeout(Duration::from_secs(2), client.into_inner().read_to_string(&mut farewell))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(farewell, "server shutting down\n");
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mtls_accepts_a_client_certificate_from_the_ca() {
+    let server = Server::builder()
+        .mtls(true)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap();
+    let pki = server.tls_pki().unwrap();
+    let (ca, identity) = (pki.ca().cert().clone(), pki.client().clone());
+    let server = server.start();
+
+    let mut client = EchoClient::connect_tls(server.local_addr(), &ca, Some(&identity))
+        .await
+        .unwrap();
+    assert_eq!(client.echo(b"it's me").await.unwrap(), b"it's me");
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mtls_rejects_a_client_without_a_certificate() {
+    let server = Server::builder()
+        .mtls(true)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap();
+    let ca = server.tls_pki().unwrap().ca().cert().clone();
+    let server = server.start();
+
+    // In TLS 1.3 the client finishes its side of the handshake before the server has
+    // looked at the (missing) certificate, so the rejection may only surface as an
+    // alert on the first read. Either way, no echo comes back.
+    let rejected = match EchoClient::connect_tls(server.local_addr(), &ca, None).await {
+        Err(_) => true,
+        Ok(mut client) => match timeout(Duration::from_secs(2), client.echo(b"let me in")).await.unwrap() {
+            Err(_) => true,
+            Ok(reply) => reply.is_empty(),
+        },
+    };
+    assert!(rejected);
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stalled_handshake_times_out() {
+    let server = Server::builder()
+        .tls(true)
+        .handshake_timeout(Duration::from_millis(200))
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    // Connect but never send a ClientHello.
+    let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    let mut buf = Vec::new();
+    let read = timeout(Duration::from_secs(2), socket.read_to_end(&mut buf))
+        .await
+        .expect("server should give up on the handshake");
+    assert!(read.is_ok_and(|n| n == 0));
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_does_not_wait_for_a_stalled_handshake() {
+    let server = Server::builder()
+        .tls(true)
+        .handshake_timeout(Duration::from_secs(60))
+        .drain_timeout(Duration::from_secs(60))
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+
+    let _socket = TcpStream::connect(server.local_addr()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    server.shutdown();
+    timeout(Duration::from_secs(2), server.await_terminated())
+        .await
+        .expect("a connection mid-handshake shouldn't hold up shutdown")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_rotated_certificate_is_picked_up() {
+    let path = std::env::temp_dir().join(format!("echo-rotate-{}.pem", std::process::id()));
+    let (old, new) = (DemoPki::generate().unwrap(), DemoPki::generate().unwrap());
+    std::fs::write(&path, old.server().to_pem_bundle()).unwrap();
+
+    let server = Server::builder()
+        .tls_files(CertFiles {
+            cert: path.clone(),
+            key: path.clone(),
+        })
+        .tls_reload_interval(Duration::from_millis(20))
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await
+        .unwrap()
+        .start();
+    let addr = server.local_addr();
+
+    // A connection made before the rotation keeps working afterwards.
+    let mut before = EchoClient::connect_tls(addr, old.ca().cert(), None).await.unwrap();
+    std::fs::write(&path, new.server().to_pem_bundle()).unwrap();
+
+    let mut after = timeout(Duration::from_secs(2), async {
+        loop {
+            if let Ok(client) = EchoClient::connect_tls(addr, new.ca().cert(), None).await {
+                return client;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the new certificate should be served within a few polls");
+    assert_eq!(after.echo(b"new").await.unwrap(), b"new");
+    assert_eq!(before.echo(b"old").await.unwrap(), b"old");
+    assert!(EchoClient::connect_tls(addr, old.ca().cert(), None).await.is_err());
+
+    server.shutdown();
+    server.await_terminated().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_tls_needs_broadcast_mode() {
+    let server = Server::builder()
+        .mode(ShutdownMode::Token)
+        .tls(true)
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .build()
+        .await;
+    assert!(matches!(server.err().unwrap(), ServerError::InvalidConfig(_)));
+}
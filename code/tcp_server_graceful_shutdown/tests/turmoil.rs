@@ -0,0 +1,221 @@
+//! The server on a simulated network, where turmoil decides when every message
+//! arrives, drops the ones that cross a partition and holds others back on request.
+//! The same seed always gives the same run, so each test tries a handful.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tcp_server_graceful_shutdown::event_log::{Event, EventLog};
+use tcp_server_graceful_shutdown::listener::{Connection, Listener};
+use tcp_server_graceful_shutdown::{Framing, Server, ServerConfig};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::time::{Instant, interval, sleep, timeout};
+use turmoil::net::{TcpListener, TcpStream};
+
+const PORT: u16 = 3011;
+const CLIENTS: usize = 8;
+const SHUTDOWN_AT: Duration = Duration::from_secs(2);
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(3);
+/// Long enough that only the drain deadline can cut a `GOAWAY` short.
+const GOAWAY: Duration = Duration::from_secs(30);
+/// How long a client that never hears from the server keeps trying: a while after the
+/// server has certainly finished.
+const CLIENT_GIVES_UP: Duration = Duration::from_secs(8);
+const SEEDS: u64 = 5;
+
+struct SimListener(TcpListener);
+
+impl Listener for SimListener {
+    type Stream = SimStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Stream, SocketAddr)> {
+        self.0.accept().await.map(|(stream, peer)| (SimStream(stream), peer))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+}
+
+/// A simulated stream has no socket options, so the defaults do.
+struct SimStream(TcpStream);
+
+impl Connection for SimStream {}
+
+impl AsyncRead for SimStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SimStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+fn client_name(n: usize) -> String {
+    format!("client-{n}")
+}
+
+fn simulation(seed: u64) -> turmoil::Sim<'static> {
+    turmoil::Builder::new()
+        .rng_seed(seed)
+        .min_message_latency(Duration::from_millis(1))
+        .max_message_latency(Duration::from_millis(100))
+        .simulation_duration(Duration::from_secs(60))
+        .build()
+}
+
+/// Runs the line server, shuts it down at `SHUTDOWN_AT` and checks the whole shutdown
+/// kept to the drain deadline. Hands back how many connections the drain aborted and
+/// how long it took.
+async fn serve_then_shut_down() -> Result<(usize, Duration), Box<dyn std::error::Error>> {
+    let listener = SimListener(TcpListener::bind(("0.0.0.0", PORT)).await?);
+    let events = EventLog::new();
+    let config = ServerConfig {
+        framing: Framing::Lines,
+        goaway: Some(GOAWAY),
+        drain_timeout: DRAIN_TIMEOUT,
+        ..ServerConfig::default()
+    };
+    let handle = Server::builder()
+        .config(config)
+        .event_log(events.clone())
+        .build_with_listeners(vec![listener])
+        .await?
+        .start();
+
+    sleep(SHUTDOWN_AT).await;
+    handle.shutdown();
+    handle.await_terminated().await?;
+    let drained_in = turmoil::elapsed() - SHUTDOWN_AT;
+    // Joining what the deadline aborted takes a moment more, but not a simulated one.
+    assert!(drained_in <= DRAIN_TIMEOUT + Duration::from_millis(10), "drained in {drained_in:?}");
+    let aborted = events
+        .events()
+        .await
+        .iter()
+        .find_map(|recorded| match recorded.event {
+            Event::Drained { aborted } => Some(aborted),
+            _ => None,
+        })
+        .expect("the drain is always recorded");
+    Ok((aborted, drained_in))
+}
+
+/// Echoes a line every 100ms until the server says `GOAWAY`, answers `BYE` and reads on
+/// to the end. Says whether it got that far.
+async fn chatty_client() -> io::Result<bool> {
+    let stream = TcpStream::connect(("server", PORT)).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut ticks = interval(Duration::from_millis(100));
+    let mut sent = 0;
+    let conversation = async {
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {
+                    sent += 1;
+                    writer.write_all(format!("message {sent}\n").as_bytes()).await?;
+                }
+                line = lines.next_line() => match line? {
+                    Some(line) if line.starts_with("GOAWAY ") => {
+                        writer.write_all(b"BYE\n").await?;
+                        while lines.next_line().await?.is_some() {}
+                        return Ok(true);
+                    }
+                    Some(line) => assert!(line.starts_with("echo: message "), "{line}"),
+                    None => return Ok(false),
+                },
+            }
+        }
+    };
+    timeout(CLIENT_GIVES_UP, conversation).await.unwrap_or(Ok(false))
+}
+
+fn add_clients(sim: &mut turmoil::Sim<'static>) {
+    for n in 0..CLIENTS {
+        sim.client(client_name(n), async move {
+            // Connect in turn rather than all at once.
+            sleep(Duration::from_millis(50) * n as u32).await;
+            chatty_client().await?;
+            Ok(())
+        });
+    }
+}
+
+#[test]
+fn test_every_client_says_bye_before_the_deadline() {
+    for seed in 0..SEEDS {
+        let mut sim = simulation(seed);
+        sim.client("server", async move {
+            let (aborted, _) = serve_then_shut_down().await?;
+            assert_eq!(aborted, 0, "seed {seed}");
+            Ok(())
+        });
+        add_clients(&mut sim);
+        sim.run().unwrap();
+    }
+}
+
+#[test]
+fn test_partitioned_clients_are_cut_off_at_the_deadline() {
+    const PARTITIONED: usize = 3;
+    for seed in 0..SEEDS {
+        let mut sim = simulation(seed);
+        sim.client("server", async move {
+            let (aborted, drained_in) = serve_then_shut_down().await?;
+            // They never hear the GOAWAY, so the others had all finished long before.
+            assert_eq!(aborted, PARTITIONED, "seed {seed}");
+            assert!(drained_in >= DRAIN_TIMEOUT, "seed {seed}: drained in {drained_in:?}");
+            Ok(())
+        });
+        add_clients(&mut sim);
+        sim.client("network", async {
+            // After everyone has connected, before the shutdown.
+            sleep(SHUTDOWN_AT - Duration::from_millis(200)).await;
+            for n in 0..PARTITIONED {
+                turmoil::partition(client_name(n), "server");
+            }
+            Ok(())
+        });
+        sim.run().unwrap();
+    }
+}
+
+#[test]
+fn test_delayed_byes_still_make_the_deadline() {
+    const HELD_FOR: Duration = Duration::from_secs(2);
+    for seed in 0..SEEDS {
+        let mut sim = simulation(seed);
+        sim.client("server", async move {
+            let started = Instant::now();
+            let (aborted, drained_in) = serve_then_shut_down().await?;
+            assert_eq!(aborted, 0, "seed {seed}");
+            // The held client's GOAWAY, and BYE, only get through once released.
+            assert!(drained_in >= HELD_FOR, "seed {seed}: drained in {drained_in:?}");
+            assert!(started.elapsed() >= SHUTDOWN_AT + HELD_FOR);
+            Ok(())
+        });
+        add_clients(&mut sim);
+        sim.client("network", async {
+            sleep(SHUTDOWN_AT).await;
+            turmoil::hold(client_name(0), "server");
+            sleep(HELD_FOR).await;
+            turmoil::release(client_name(0), "server");
+            Ok(())
+        });
+        sim.run().unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep, sleep_until};
+
+/// The most we read ahead of the caller while bytes are "in flight". Past this the
+/// stream stops reading, and the kernel's receive buffer fills up instead, as it would
+/// behind a slow reader.
+const MAX_IN_FLIGHT: usize = 64 * 1024;
+
+/// How a [`ThrottledStream`] slows things down. The default changes nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Throttle {
+    /// How long everything read takes to arrive, on top of however long it really took.
+    ///
+    /// Only incoming bytes are delayed, so on one end of a request and reply this is
+    /// the extra round trip time. Bytes keep arriving behind delayed ones: latency
+    /// doesn't cut throughput, just as a long cable doesn't.
+    pub latency: Duration,
+    /// At most this many bytes a second are read.
+    pub read_bytes_per_sec: Option<u64>,
+    /// At most this many bytes a second are written.
+    pub write_bytes_per_sec: Option<u64>,
+}
+
+/// Wraps any stream (a `TcpStream`, a TLS stream, one end of `tokio::io::duplex`) to
+/// make it behave like a slower network: added latency on reads, and a bandwidth cap
+/// in either direction.
+///
+/// Everything stays poll-based, with no background task: a read first pulls whatever
+/// the inner stream has into a queue, stamping each chunk with when it's allowed to be
+/// seen, then hands over the oldest chunk once it's due. Bandwidth is a token bucket
+/// per direction that refills at the configured rate and holds about 50ms of traffic,
+/// so a capped stream moves data in small, steady bursts instead of all at once and
+/// then nothing.
+pub struct ThrottledStream<S> {
+    inner: S,
+    latency: Duration,
+    read_rate: Option<Bucket>,
+    write_rate: Option<Bucket>,
+    /// What has been read from `inner`, with when the caller may see it. An empty chunk
+    /// is the end of the stream; an error is handed over in order, like data.
+    arrivals: VecDeque<(Instant, io::Result<Vec<u8>>)>,
+    /// How many bytes `arrivals` holds.
+    in_flight: usize,
+    eof: bool,
+    /// Wakes us when the oldest arrival is due.
+    delay: Option<Pin<Box<Sleep>>>,
+    scratch: Vec<u8>,
+}
+
+impl<S> ThrottledStream<S> {
+    /// Wraps `inner`, slowing it down as `throttle` says.
+    pub fn new(inner: S, throttle: Throttle) -> Self {
+        Self {
+            inner,
+            latency: throttle.latency,
+            read_rate: throttle.read_bytes_per_sec.map(Bucket::new),
+            write_rate: throttle.write_bytes_per_sec.map(Bucket::new),
+            arrivals: VecDeque::new(),
+            in_flight: 0,
+            eof: false,
+            delay: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// The wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// The wrapped stream. Reading from it directly skips whatever is still in flight.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Unwraps the stream. Bytes that were read but weren't due yet are lost.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn is_read_throttled(&self) -> bool {
+        !self.latency.is_zero() || self.read_rate.is_some()
+    }
+}
+
+impl<S: AsyncRead + Unpin> ThrottledStream<S> {
+    /// Reads everything `inner` has ready (and the bandwidth allows) into `arrivals`.
+    /// Stops once a read would wait, which leaves a wakeup registered with `cx`.
+    fn pull(&mut self, cx: &mut Context<'_>) {
+        while !self.eof && self.in_flight < MAX_IN_FLIGHT {
+            let want = MAX_IN_FLIGHT - self.in_flight;
+            let want = match &mut self.read_rate {
+                Some(bucket) => match bucket.poll_take(cx, want) {
+                    Poll::Ready(n) => n,
+                    Poll::Pending => return,
+                },
+                None => want,
+            };
+            self.scratch.resize(want, 0);
+            let mut chunk = ReadBuf::new(&mut self.scratch);
+            let arrival = match Pin::new(&mut self.inner).poll_read(cx, &mut chunk) {
+                Poll::Pending => return,
+                Poll::Ready(Ok(())) => {
+                    let data = chunk.filled().to_vec();
+                    if let Some(bucket) = &mut self.read_rate {
+                        bucket.spend(data.len());
+                    }
+                    self.eof = data.is_empty();
+                    self.in_flight += data.len();
+                    Ok(data)
+                }
+                Poll::Ready(Err(e)) => Err(e),
+            };
+            let failed = arrival.is_err();
+            self.arrivals.push_back((Instant::now() + self.latency, arrival));
+            if failed {
+                return;
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ThrottledStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.is_read_throttled() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        this.pull(cx);
+
+        // Nothing in flight means `pull` is waiting on the network or the bandwidth,
+        // and either will wake us.
+        let Some(&(due, _)) = this.arrivals.front() else {
+            return Poll::Pending;
+        };
+        if due > Instant::now() {
+            let delay = this.delay.get_or_insert_with(|| Box::pin(sleep_until(due)));
+            delay.as_mut().reset(due);
+            ready!(delay.as_mut().poll(cx));
+        }
+
+        match this.arrivals.front_mut() {
+            // The end of the stream stays put, so every later read sees it too.
+            Some((_, Ok(data))) if data.is_empty() => Poll::Ready(Ok(())),
+            Some((_, Ok(data))) => {
+                let n = data.len().min(buf.remaining());
+                buf.put_slice(&data[..n]);
+                data.drain(..n);
+                if data.is_empty() {
+                    this.arrivals.pop_front();
+                }
+                this.in_flight -= n;
+                Poll::Ready(Ok(()))
+            }
+            Some((_, Err(_))) => match this.arrivals.pop_front() {
+                Some((_, Err(e))) => Poll::Ready(Err(e)),
+                _ => unreachable!("the front of the queue was an error"),
+            },
+            None => unreachable!("the queue was checked for an arrival above"),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ThrottledStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(bucket) = this.write_rate.as_mut().filter(|_| !buf.is_empty()) else {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        };
+        // Taking less than was offered is a partial write, which every caller of
+        // `poll_write` has to cope with anyway.
+        let n = ready!(bucket.poll_take(cx, buf.len()));
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..n]))?;
+        bucket.spend(written);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// A token bucket counting bytes, refilled by the clock rather than a timer.
+struct Bucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    wait: Option<Pin<Box<Sleep>>>,
+}
+
+impl Bucket {
+    fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1) as f64;
+        let capacity = (rate / 20.0).max(1.0);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled_at: Instant::now(),
+            wait: None,
+        }
+    }
+
+    /// Waits until at least one byte may go, then says how many of `want` may.
+    /// Nothing is taken until [`spend`](Self::spend), since the stream may not manage
+    /// to move them all.
+    fn poll_take(&mut self, cx: &mut Context<'_>, want: usize) -> Poll<usize> {
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+            self.refilled_at = now;
+            if self.tokens >= 1.0 {
+                return Poll::Ready(want.min(self.tokens as usize));
+            }
+            let until = now + Duration::from_secs_f64((1.0 - self.tokens) / self.rate);
+            let wait = self.wait.get_or_insert_with(|| Box::pin(sleep_until(until)));
+            wait.as_mut().reset(until);
+            ready!(wait.as_mut().poll(cx));
+        }
+    }
+
+    fn spend(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+    #[tokio::test]
+    async fn test_default_throttle_passes_everything_through() {
+        let (near, mut far) = duplex(64);
+        let mut stream = ThrottledStream::new(near, Throttle::default());
+        far.write_all(b"hello").await.unwrap();
+        let mut buf = [0_u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        stream.write_all(b"world").await.unwrap();
+        far.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"world");
+    }
+
+    #[tokio::test]
+    async fn test_latency_delays_reads_but_keeps_the_order_and_the_end() {
+        let (near, mut far) = duplex(64);
+        let throttle = Throttle {
+            latency: Duration::from_millis(200),
+            ..Throttle::default()
+        };
+        let mut stream = ThrottledStream::new(near, throttle);
+        let started = Instant::now();
+        far.write_all(b"one ").await.unwrap();
+        far.write_all(b"two").await.unwrap();
+        drop(far);
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"one two");
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(stream.read(&mut [0_u8; 4]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_caps_both_directions() {
+        let (near, mut far) = duplex(4096);
+        let throttle = Throttle {
+            read_bytes_per_sec: Some(1000),
+            write_bytes_per_sec: Some(1000),
+            ..Throttle::default()
+        };
+        let mut stream = ThrottledStream::new(near, throttle);
+
+        // 300 bytes at 1000 a second, less the 50 byte head start: at least 250ms.
+        let started = Instant::now();
+        stream.write_all(&[7; 300]).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(240), "{:?}", started.elapsed());
+        let mut buf = [0_u8; 300];
+        far.read_exact(&mut buf).await.unwrap();
+
+        far.write_all(&buf).await.unwrap();
+        let started = Instant::now();
+        stream.read_exact(&mut buf).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(240), "{:?}", started.elapsed());
+        assert_eq!(buf, [7; 300]);
+    }
+}
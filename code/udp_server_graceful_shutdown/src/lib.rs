@@ -0,0 +1,18 @@
+//! The graceful shutdown echo server again, over UDP.
+//!
+//! The shape is the same as `tcp_server_graceful_shutdown`: a `ShutdownController`
+//! broadcasts the stop signal and a `select!` loop races it against the socket. What
+//! changes is everything the TCP version gets from connections:
+//!
+//! - There's one socket and one task. A datagram is a complete message, and replying is
+//!   a single `send_to`, so there's nothing to gain from a task per peer.
+//! - A peer is just the address the last datagram came from. If we want per-peer state
+//!   we have to keep it ourselves, in a `HashMap`, and decide on our own when a peer
+//!   has gone: nobody sends us a FIN. Here that's "silent for longer than `peer_ttl`".
+//! - There's nothing to drain. Shutdown means: stop receiving, send each peer we still
+//!   consider live a farewell datagram (which may or may not arrive), and exit.
+
+mod peers;
+mod server;
+
+pub use server::{ServerConfig, run_server};
@@ -0,0 +1,55 @@
+use clap::Parser;
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use udp_server_graceful_shutdown::{ServerConfig, run_server};
+
+/// A UDP echo server that shuts down gracefully on Ctrl-C or SIGTERM.
+///
+/// Try it with `nc -u 127.0.0.1 3012`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:3012")]
+    bind: SocketAddr,
+    /// Seconds a peer may stay silent before it's forgotten.
+    #[arg(long, value_name = "SECS", default_value = "30", value_parser = parse_secs)]
+    peer_ttl: Duration,
+    /// Seconds between sweeps for expired peers.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    sweep_interval: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = ServerConfig {
+        peer_ttl: cli.peer_ttl,
+        sweep_interval: cli.sweep_interval,
+    };
+
+    let socket = UdpSocket::bind(cli.bind).await?;
+    println!("[main] listening on udp://{}", socket.local_addr()?);
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let shutdown_rx = controller.subscribe();
+    let server = tokio::spawn(run_server(socket, config, shutdown_rx));
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    controller.trigger();
+
+    match server.await {
+        Ok(Ok(())) => println!("[main] server exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
+        Err(e) => eprintln!("[main] server task failed: {e}"),
+    }
+    Ok(())
+}
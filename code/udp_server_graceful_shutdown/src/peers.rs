@@ -0,0 +1,118 @@
+//! Per-peer bookkeeping for a protocol without connections.
+//!
+//! TCP tells us when a client arrives (`accept`) and when it leaves (EOF). UDP tells us
+//! neither, so the table below stands in for both: an address is "connected" from its
+//! first datagram until it has been quiet for the TTL. Times are passed in rather than
+//! read from the clock, which keeps the table trivial to test.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// What we know about one peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PeerState {
+    pub(crate) first_seen: Instant,
+    pub(crate) last_seen: Instant,
+    pub(crate) datagrams: u64,
+    pub(crate) bytes: u64,
+}
+
+/// Every peer heard from within the last `ttl`.
+#[derive(Debug)]
+pub(crate) struct PeerTable {
+    peers: HashMap<SocketAddr, PeerState>,
+    ttl: Duration,
+}
+
+impl PeerTable {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            peers: HashMap::new(),
+            ttl,
+        }
+    }
+
+    /// Notes a datagram of `len` bytes from `peer`. Returns `true` if the peer is new
+    /// (or had expired and is back).
+    pub(crate) fn record(&mut self, peer: SocketAddr, len: usize, now: Instant) -> bool {
+        let mut is_new = false;
+        let state = self.peers.entry(peer).or_insert_with(|| {
+            is_new = true;
+            PeerState {
+                first_seen: now,
+                last_seen: now,
+                datagrams: 0,
+                bytes: 0,
+            }
+        });
+        state.last_seen = now;
+        state.datagrams += 1;
+        state.bytes += len as u64;
+        is_new
+    }
+
+    /// Removes and returns the peers that have been silent for longer than the TTL.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<(SocketAddr, PeerState)> {
+        let ttl = self.ttl;
+        let expired: Vec<SocketAddr> = self
+            .peers
+            .iter()
+            .filter(|(_, state)| now.saturating_duration_since(state.last_seen) > ttl)
+            .map(|(&addr, _)| addr)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|addr| self.peers.remove(&addr).map(|state| (addr, state)))
+            .collect()
+    }
+
+    /// Empties the table, handing back every peer still in it.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (SocketAddr, PeerState)> + '_ {
+        self.peers.drain()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.peers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_record_counts_datagrams_per_peer() {
+        let start = Instant::now();
+        let mut table = PeerTable::new(Duration::from_secs(10));
+        assert!(table.record(addr(1), 5, start));
+        assert!(!table.record(addr(1), 7, start + Duration::from_secs(1)));
+        assert!(table.record(addr(2), 3, start));
+
+        let state = table.peers[&addr(1)];
+        assert_eq!((state.datagrams, state.bytes), (2, 12));
+        assert_eq!(state.last_seen - state.first_seen, Duration::from_secs(1));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_expire_removes_only_quiet_peers() {
+        let start = Instant::now();
+        let mut table = PeerTable::new(Duration::from_secs(10));
+        table.record(addr(1), 1, start);
+        table.record(addr(2), 1, start + Duration::from_secs(8));
+
+        assert!(table.expire(start + Duration::from_secs(10)).is_empty());
+        let expired = table.expire(start + Duration::from_secs(11));
+        assert_eq!(expired.iter().map(|(a, _)| *a).collect::<Vec<_>>(), [addr(1)]);
+        assert_eq!(table.len(), 1);
+
+        // A peer that comes back after expiring counts as new again.
+        assert!(table.record(addr(1), 1, start + Duration::from_secs(12)));
+    }
+}
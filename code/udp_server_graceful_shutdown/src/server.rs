@@ -0,0 +1,101 @@
+use crate::peers::PeerTable;
+use std::io;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio::time::{Instant, MissedTickBehavior, interval};
+
+/// The largest payload a UDP datagram can carry over IPv4. A smaller buffer would
+/// silently truncate bigger datagrams: `recv_from` drops whatever doesn't fit.
+const MAX_DATAGRAM: usize = 65_507;
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How long a peer may stay silent before we forget it.
+    pub peer_ttl: Duration,
+    /// How often to look for peers past their TTL.
+    pub sweep_interval: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            peer_ttl: Duration::from_secs(30),
+            sweep_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Echoes every datagram back to its sender until shutdown is signalled, then sends a
+/// farewell to each peer that's still live.
+pub async fn run_server(
+    socket: UdpSocket,
+    config: ServerConfig,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let mut peers = PeerTable::new(config.peer_ttl);
+    let mut buf = vec![0_u8; MAX_DATAGRAM];
+    let mut sweep = interval(config.sweep_interval);
+    sweep.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            recv = shutdown_rx.recv() => {
+                match recv {
+                    Ok(()) => println!("[server] shutdown requested"),
+                    Err(e) => println!("[server] shutdown channel: {e}"),
+                }
+                break;
+            }
+            _ = sweep.tick() => {
+                for (peer, state) in peers.expire(Instant::now()) {
+                    println!(
+                        "[server] peer={peer} expired: {} datagram(s), {}B over {:?}",
+                        state.datagrams,
+                        state.bytes,
+                        state.last_seen - state.first_seen
+                    );
+                }
+            }
+            // `recv_from` is cancel safe: if shutdown wins the race, no datagram has
+            // been taken off the socket.
+            received = socket.recv_from(&mut buf) => {
+                let (n, peer) = match received {
+                    Ok(received) => received,
+                    // A previous `send_to` hit a closed port and the ICMP error surfaced
+                    // here (Linux reports it as ConnectionRefused, Windows as
+                    // ConnectionReset). It's about one peer, not our socket, so carry on.
+                    Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset) => {
+                        println!("[server] a peer went away: {e}");
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                if peers.record(peer, n, Instant::now()) {
+                    println!("[server] peer={peer} new ({} live)", peers.len());
+                }
+                // One datagram in, one out. If the send fails the peer just doesn't get
+                // its echo, which over UDP it has to be ready for anyway.
+                if let Err(e) = socket.send_to(&buf[..n], peer).await {
+                    eprintln!("[server] peer={peer} echo failed: {e}");
+                }
+            }
+        }
+    }
+
+    // No connections to wait for: the best we can do is tell each live peer we're
+    // leaving. Nothing guarantees these arrive, and nothing would tell us if they don't.
+    let live = peers.len();
+    for (peer, state) in peers.drain() {
+        if let Err(e) = socket.send_to(b"server shutting down\n", peer).await {
+            eprintln!("[server] peer={peer} farewell failed: {e}");
+        }
+        println!(
+            "[server] peer={peer} said goodbye: {} datagram(s), {}B",
+            state.datagrams, state.bytes
+        );
+    }
+    println!("[server] sent a farewell to {live} live peer(s)");
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use shutdown_util::ShutdownController;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use udp_server_graceful_shutdown::{ServerConfig, run_server};
+
+async fn start_server(config: ServerConfig) -> (SocketAddr, ShutdownController, JoinHandle<std::io::Result<()>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let controller = ShutdownController::new();
+    let task = tokio::spawn(run_server(socket, config, controller.subscribe()));
+    (addr, controller, task)
+}
+
+async fn client(server: SocketAddr) -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    // `connect` on a UDP socket only fixes the default destination (and filters what
+    // we receive to that address); no packets are exchanged.
+    socket.connect(server).await.unwrap();
+    socket
+}
+
+async fn recv(socket: &UdpSocket, wait: Duration) -> Option<Vec<u8>> {
+    let mut buf = [0_u8; 1500];
+    let n = timeout(wait, socket.recv(&mut buf)).await.ok()?.unwrap();
+    Some(buf[..n].to_vec())
+}
+
+#[tokio::test]
+async fn test_echoes_and_says_goodbye_to_live_peers() {
+    let (addr, controller, task) = start_server(ServerConfig::default()).await;
+    let (a, b) = (client(addr).await, client(addr).await);
+
+    a.send(b"from a").await.unwrap();
+    assert_eq!(recv(&a, Duration::from_secs(2)).await.unwrap(), b"from a");
+    b.send(b"from b").await.unwrap();
+    assert_eq!(recv(&b, Duration::from_secs(2)).await.unwrap(), b"from b");
+
+    controller.trigger();
+    task.await.unwrap().unwrap();
+    for socket in [&a, &b] {
+        assert_eq!(recv(socket, Duration::from_secs(2)).await.unwrap(), b"server shutting down\n");
+    }
+}
+
+#[tokio::test]
+async fn test_expired_peers_get_no_farewell() {
+    let config = ServerConfig {
+        peer_ttl: Duration::from_millis(100),
+        sweep_interval: Duration::from_millis(20),
+    };
+    let (addr, controller, task) = start_server(config).await;
+    let socket = client(addr).await;
+
+    socket.send(b"hello").await.unwrap();
+    assert_eq!(recv(&socket, Duration::from_secs(2)).await.unwrap(), b"hello");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    controller.trigger();
+    task.await.unwrap().unwrap();
+    assert_eq!(recv(&socket, Duration::from_millis(200)).await, None);
+}
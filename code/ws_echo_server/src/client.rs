@@ -0,0 +1,47 @@
+//! The demo client: sends a greeting on an interval and prints the echoes, until the
+//! server closes the connection.
+
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::time::{MissedTickBehavior, interval};
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+/// Connects to `url`, sends a text message every `every`, and prints what comes back.
+///
+/// Returns the server's Close frame, once the closing handshake is done, or `None` if
+/// the server hung up without one.
+pub async fn run_demo_client(name: &str, url: &str, every: Duration) -> Result<Option<CloseFrame>, WsError> {
+    let (mut ws, _response) = tokio_tungstenite::connect_async(url).await?;
+    println!("[{name}] connected to {url}");
+    let mut ticks = interval(every);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut sent = 0_u64;
+    let mut close = None;
+
+    loop {
+        tokio::select! {
+            // Once the server has sent Close we mustn't send data, only finish reading.
+            _ = ticks.tick(), if close.is_none() => {
+                sent += 1;
+                ws.send(Message::text(format!("hello #{sent} from {name}"))).await?;
+            }
+            message = ws.next() => match message {
+                Some(Ok(Message::Text(text))) => println!("[{name}] echo: {text}"),
+                Some(Ok(Message::Binary(bytes))) => println!("[{name}] echo: {} byte(s)", bytes.len()),
+                // tungstenite queues our Close reply; the loop keeps reading so it gets
+                // flushed, and the stream ends after that.
+                Some(Ok(Message::Close(frame))) => {
+                    match &frame {
+                        Some(frame) => println!("[{name}] server closed with {} ({})", u16::from(frame.code), frame.reason),
+                        None => println!("[{name}] server closed without a status"),
+                    }
+                    close = Some(frame);
+                }
+                Some(Ok(_)) => {}
+                Some(Err(WsError::ConnectionClosed)) | None => return Ok(close.flatten()),
+                Some(Err(e)) => return Err(e),
+            },
+        }
+    }
+}
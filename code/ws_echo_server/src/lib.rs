@@ -0,0 +1,184 @@
+//! A WebSocket echo server whose shutdown speaks WebSocket.
+//!
+//! The accept loop and the drain are the usual `ShutdownController` pattern. What's new
+//! is the goodbye. A WebSocket connection ends with a closing handshake: one side sends
+//! a Close frame with a status code, the other answers with a Close of its own, and only
+//! then does the TCP connection go away. So on shutdown each connection:
+//!
+//! 1. sends Close with code 1001, "going away", which tells a well-behaved client that
+//!    the server is leaving (as opposed to rejecting it) and that reconnecting later is
+//!    reasonable;
+//! 2. keeps reading until the client's Close comes back, for at most `close_timeout`;
+//! 3. lets the connection drop.
+//!
+//! Pings never reach our code: tungstenite answers them itself, on the next read or
+//! write. It answers a client-initiated Close the same way, which is why the echo loop
+//! only has to log one and keep reading until the stream ends.
+
+pub mod client;
+
+use futures::{SinkExt, StreamExt};
+use shutdown_util::ShutdownController;
+use shutdown_util::backoff::AcceptBackoff;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+/// Settings for [`run_server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// How long a client may take over the HTTP upgrade.
+    pub handshake_timeout: Duration,
+    /// How long to wait for a client's Close after sending ours.
+    pub close_timeout: Duration,
+    /// How long connections get to finish after shutdown. Keep it above `close_timeout`.
+    pub drain_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            handshake_timeout: Duration::from_secs(5),
+            close_timeout: Duration::from_secs(2),
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Serves WebSocket clients accepted on `listener` until shutdown is signalled, then
+/// closes each connection with "going away" and drains.
+pub async fn run_server(
+    listener: TcpListener,
+    config: ServerConfig,
+    mut controller: ShutdownController,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    let mut next_conn_id = 0_u64;
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                println!("[server] shutdown requested, no longer accepting");
+                break;
+            }
+            accepted = listener.accept() => {
+                let (socket, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let delay = backoff.on_error();
+                        eprintln!("[server] accept failed: {e}, retrying in {delay:?}");
+                        sleep(delay).await;
+                        continue;
+                    }
+                };
+                if let Some(failures) = backoff.on_success() {
+                    println!("[server] accepting again after {failures} failure(s)");
+                }
+                next_conn_id += 1;
+                let id = next_conn_id;
+                let config = config.clone();
+                let conn_shutdown = controller.subscribe();
+                controller.spawn(async move {
+                    match serve_client(socket, id, peer, &config, conn_shutdown).await {
+                        Ok(echoed) => println!("[server] conn={id} peer={peer} closed after {echoed} message(s)"),
+                        Err(e) => eprintln!("[server] conn={id} peer={peer} failed: {e}"),
+                    }
+                });
+            }
+            // The controller keeps a finished task until it's joined: reaping here stops
+            // every client that ever left piling up, and leaves the drain only the ones
+            // still open.
+            Some(joined) = controller.join_next(), if controller.active_tasks() > 0 => {
+                if let Err(e) = joined {
+                    eprintln!("[server] connection task join error: {e}");
+                }
+            }
+        }
+    }
+    drop(listener);
+
+    println!(
+        "[server] waiting up to {:?} for {} connection(s) to close",
+        config.drain_timeout,
+        controller.active_tasks()
+    );
+    let report = controller.wait_idle_timeout(config.drain_timeout).await;
+    for e in &report.errors {
+        eprintln!("[server] connection task join error: {e}");
+    }
+    if report.aborted > 0 {
+        eprintln!("[server] drain deadline hit, aborted {} connection(s)", report.aborted);
+    } else {
+        println!("[server] all connections closed");
+    }
+    Ok(())
+}
+
+/// Upgrades one connection and echoes text and binary messages until either side
+/// closes. Returns how many messages it echoed.
+async fn serve_client(
+    socket: TcpStream,
+    id: u64,
+    peer: SocketAddr,
+    config: &ServerConfig,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> Result<u64, WsError> {
+    // Until the upgrade is done there's no way to send a Close, so shutdown during the
+    // handshake just drops the socket.
+    let upgrade = timeout(config.handshake_timeout, tokio_tungstenite::accept_async(socket));
+    let mut ws = tokio::select! {
+        _ = shutdown_rx.recv() => return Ok(0),
+        upgraded = upgrade => match upgraded {
+            Ok(ws) => ws?,
+            Err(_) => return Err(WsError::Io(io::Error::new(io::ErrorKind::TimedOut, "WebSocket handshake timed out"))),
+        },
+    };
+    println!("[server] conn={id} peer={peer} upgraded to WebSocket");
+    let mut echoed = 0;
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                ws.send(Message::Close(Some(CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "server shutting down".into(),
+                })))
+                .await?;
+                // The stream ends once the client's Close arrives. Anything it sends in
+                // between is read and dropped: after our Close we've promised not to
+                // send any more data.
+                let closing = async {
+                    while let Some(message) = ws.next().await {
+                        message?;
+                    }
+                    Ok::<_, WsError>(())
+                };
+                match timeout(config.close_timeout, closing).await {
+                    Ok(result) => result?,
+                    Err(_) => println!("[server] conn={id} no Close reply within {:?}, dropping", config.close_timeout),
+                }
+                return Ok(echoed);
+            }
+            message = ws.next() => match message {
+                None => return Ok(echoed),
+                Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => {
+                    ws.send(message).await?;
+                    echoed += 1;
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    println!("[server] conn={id} client closed: {frame:?}");
+                }
+                // Pings are answered for us; pongs and raw frames need nothing.
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+            },
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use clap::Parser;
+use shutdown_util::ShutdownController;
+use shutdown_util::cli::parse_secs;
+use shutdown_util::signal::shutdown_signal;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use ws_echo_server::client::run_demo_client;
+use ws_echo_server::{ServerConfig, run_server};
+
+/// A WebSocket echo server that closes connections with "going away" on Ctrl-C or
+/// SIGTERM.
+///
+/// Run it with `--demo-clients 2` to watch a couple of clients chat with it and get
+/// closed properly, or connect your own, e.g. `websocat ws://127.0.0.1:8011`.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8011")]
+    bind: SocketAddr,
+    /// How many demo clients to start alongside the server.
+    #[arg(long, default_value_t = 0)]
+    demo_clients: usize,
+    /// Seconds between the demo clients' messages.
+    #[arg(long, value_name = "SECS", default_value = "1", value_parser = parse_secs)]
+    demo_interval: Duration,
+    /// Seconds to wait for a client's Close after sending ours.
+    #[arg(long, value_name = "SECS", default_value = "2", value_parser = parse_secs)]
+    close_timeout: Duration,
+    /// Seconds connections get to finish after shutdown.
+    #[arg(long, value_name = "SECS", default_value = "5", value_parser = parse_secs)]
+    drain_timeout: Duration,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let config = ServerConfig {
+        close_timeout: cli.close_timeout,
+        drain_timeout: cli.drain_timeout,
+        ..ServerConfig::default()
+    };
+
+    let listener = TcpListener::bind(cli.bind).await?;
+    let addr = listener.local_addr()?;
+    println!("[main] listening on ws://{addr}");
+    println!("[main] press Ctrl-C (or send SIGTERM) to shut down");
+
+    let controller = ShutdownController::new();
+    let trigger = controller.trigger_handle();
+    let shutdown_rx = controller.subscribe();
+    let server = tokio::spawn(run_server(listener, config, controller, shutdown_rx));
+
+    // Plain tasks, not tracked by the server's controller: to the server they're just
+    // clients like any other, and they finish when it closes them.
+    let url = format!("ws://{addr}");
+    let demos: Vec<_> = (1..=cli.demo_clients)
+        .map(|i| {
+            let url = url.clone();
+            let every = cli.demo_interval;
+            tokio::spawn(async move {
+                let name = format!("demo{i}");
+                if let Err(e) = run_demo_client(&name, &url, every).await {
+                    eprintln!("[{name}] failed: {e}");
+                }
+            })
+        })
+        .collect();
+
+    match shutdown_signal().await {
+        Ok(name) => println!("[main] received {name}"),
+        Err(e) => eprintln!("[main] failed to listen for shutdown signals: {e}"),
+    }
+    trigger.trigger();
+
+    match server.await {
+        Ok(Ok(())) => println!("[main] server exited cleanly"),
+        Ok(Err(e)) => eprintln!("[main] server returned error: {e}"),
+        Err(e) => eprintln!("[main] server task failed: {e}"),
+    }
+    for demo in demos {
+        let _ = demo.await;
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use futures::{SinkExt, StreamExt};
+use shutdown_util::ShutdownTrigger;
+use shutdown_util::testing::spawn_server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use ws_echo_server::client::run_demo_client;
+use ws_echo_server::{ServerConfig, run_server};
+
+async fn start_server(config: ServerConfig) -> (SocketAddr, ShutdownTrigger, JoinHandle<std::io::Result<()>>) {
+    spawn_server(|listener, controller, shutdown_rx| run_server(listener, config, controller, shutdown_rx)).await
+}
+
+async fn next_message<S>(ws: &mut S) -> Message
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    timeout(Duration::from_secs(2), ws.next())
+        .await
+        .expect("timed out waiting for a message")
+        .expect("stream ended")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_echoes_text_and_binary_and_closes_with_going_away() {
+    let (addr, trigger, task) = start_server(ServerConfig::default()).await;
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}")).await.unwrap();
+
+    ws.send(Message::text("hello")).await.unwrap();
+    assert_eq!(next_message(&mut ws).await, Message::text("hello"));
+    ws.send(Message::binary(vec![0_u8, 1, 2])).await.unwrap();
+    assert_eq!(next_message(&mut ws).await, Message::binary(vec![0_u8, 1, 2]));
+
+    trigger.trigger();
+    let Message::Close(Some(frame)) = next_message(&mut ws).await else {
+        panic!("expected a Close frame");
+    };
+    assert_eq!(frame.code, CloseCode::Away);
+    // Reading on lets tungstenite send our Close reply; then the stream ends.
+    assert!(timeout(Duration::from_secs(2), ws.next()).await.unwrap().is_none());
+    timeout(Duration::from_secs(2), task).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_client_that_never_answers_close_is_dropped_after_close_timeout() {
+    let config = ServerConfig {
+        close_timeout: Duration::from_millis(200),
+        ..ServerConfig::default()
+    };
+    let (addr, trigger, task) = start_server(config).await;
+    // Connected, but never read from again, so our side never replies to the Close.
+    let (_ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    trigger.trigger();
+    timeout(Duration::from_secs(2), task)
+        .await
+        .expect("the server should give up on the Close reply")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_demo_client_sees_going_away() {
+    let (addr, trigger, task) = start_server(ServerConfig::default()).await;
+    let url = format!("ws://{addr}");
+    let demo = tokio::spawn(async move { run_demo_client("demo", &url, Duration::from_millis(20)).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    trigger.trigger();
+    let frame = timeout(Duration::from_secs(2), demo).await.unwrap().unwrap().unwrap();
+    assert_eq!(frame.unwrap().code, CloseCode::Away);
+    task.await.unwrap().unwrap();
+}